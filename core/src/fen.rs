@@ -0,0 +1,200 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::{
+    write_square, Position, BLACK, BLACK_KINGSIDE, BLACK_QUEENSIDE, EMPTY, KING, NO_SQUARE,
+    PIECE_LETTERS, WHITE, WHITE_KINGSIDE, WHITE_QUEENSIDE,
+};
+
+/// What is wrong with a FEN string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FenError {
+    /// The piece placement doesn't describe eight ranks of eight squares.
+    Placement,
+    /// There isn't exactly one king of each color.
+    Kings,
+    /// The side to move isn't `w` or `b`.
+    SideToMove,
+    Castling,
+    EnPassant,
+    /// A move counter isn't a number.
+    Counter,
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FenError::Placement => "The piece placement must have eight ranks of eight squares.",
+            FenError::Kings => "Each side must have exactly one king.",
+            FenError::SideToMove => "The side to move must be w or b.",
+            FenError::Castling => "The castling rights must be - or some of KQkq.",
+            FenError::EnPassant => "The en passant square must be - or a square on rank 3 or 6.",
+            FenError::Counter => "The move counters must be numbers.",
+        })
+    }
+}
+
+impl Position {
+    /// Reads a position written in Forsyth-Edwards Notation. The two move counters may be left
+    /// out.
+    pub fn from_fen(fen: &str) -> Result<Position, FenError> {
+        let mut fields = fen.split_whitespace();
+        let mut position = Position::start();
+        position.squares = [EMPTY; 64];
+
+        let placement = fields.next().ok_or(FenError::Placement)?;
+        let mut ranks = 0;
+        for (i, rank) in placement.split('/').enumerate() {
+            let rank_start = 8 * 7u8.checked_sub(i as u8).ok_or(FenError::Placement)?;
+            let mut file = 0;
+            for c in rank.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    file += empty as u8;
+                    continue;
+                }
+                let piece = PIECE_LETTERS
+                    .iter()
+                    .position(|&letter| letter == c && c != ' ')
+                    .ok_or(FenError::Placement)?;
+                if file >= 8 {
+                    return Err(FenError::Placement);
+                }
+                position.squares[(rank_start + file) as usize] = piece as u8;
+                file += 1;
+            }
+            if file != 8 {
+                return Err(FenError::Placement);
+            }
+            ranks += 1;
+        }
+        if ranks != 8 {
+            return Err(FenError::Placement);
+        }
+        for color in [WHITE, BLACK] {
+            let kings = position.squares.iter().filter(|&&p| p == KING | color);
+            if kings.count() != 1 {
+                return Err(FenError::Kings);
+            }
+        }
+
+        position.side = match fields.next() {
+            Some("w") => WHITE,
+            Some("b") => BLACK,
+            _ => return Err(FenError::SideToMove),
+        };
+        position.castling = 0;
+        match fields.next() {
+            Some("-") => {}
+            Some(rights) => {
+                for c in rights.chars() {
+                    position.castling |= match c {
+                        'K' => WHITE_KINGSIDE,
+                        'Q' => WHITE_QUEENSIDE,
+                        'k' => BLACK_KINGSIDE,
+                        'q' => BLACK_QUEENSIDE,
+                        _ => return Err(FenError::Castling),
+                    };
+                }
+            }
+            None => return Err(FenError::Castling),
+        }
+        position.en_passant = match fields.next().map(str::as_bytes) {
+            Some(b"-") => NO_SQUARE,
+            Some(&[file @ b'a'..=b'h', rank @ (b'3' | b'6')]) => (rank - b'1') * 8 + file - b'a',
+            _ => return Err(FenError::EnPassant),
+        };
+        let mut counter = |default| match fields.next() {
+            Some(number) => number.parse().map_err(|_| FenError::Counter),
+            None => Ok(default),
+        };
+        position.halfmoves = counter(0)?;
+        position.fullmoves = counter(1)?.max(1);
+        Ok(position)
+    }
+}
+
+/// Writes the position in Forsyth-Edwards Notation.
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for square in rank * 8..rank * 8 + 8 {
+                match self.piece(square) {
+                    EMPTY => empty += 1,
+                    piece => {
+                        if empty > 0 {
+                            write!(f, "{}", empty)?;
+                            empty = 0;
+                        }
+                        write!(f, "{}", PIECE_LETTERS[piece as usize])?;
+                    }
+                }
+            }
+            if empty > 0 {
+                write!(f, "{}", empty)?;
+            }
+            if rank > 0 {
+                f.write_str("/")?;
+            }
+        }
+        f.write_str(if self.side == WHITE { " w " } else { " b " })?;
+        if self.castling == 0 {
+            f.write_str("-")?;
+        }
+        for (right, letter) in [
+            (WHITE_KINGSIDE, "K"),
+            (WHITE_QUEENSIDE, "Q"),
+            (BLACK_KINGSIDE, "k"),
+            (BLACK_QUEENSIDE, "q"),
+        ] {
+            if self.castling & right != 0 {
+                f.write_str(letter)?;
+            }
+        }
+        f.write_str(" ")?;
+        match self.en_passant {
+            NO_SQUARE => f.write_str("-")?,
+            square => write_square(f, square)?,
+        }
+        write!(f, " {} {}", self.halfmoves, self.fullmoves)
+    }
+}
+
+#[test]
+fn test_fen() {
+    use crate::{Buffer, Move};
+
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(Position::from_fen(start), Ok(Position::start()));
+    assert_eq!(Buffer::write(Position::start()).as_str(), start);
+
+    let mut position = Position::start();
+    position.make_move(Move::new(12, 28));
+    assert_eq!(
+        Buffer::write(position).as_str(),
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+    );
+    let fen = "8/8/8/8/8/8/8/K6k b - - 12 40";
+    assert_eq!(
+        Buffer::write(Position::from_fen(fen).unwrap()).as_str(),
+        fen
+    );
+    assert!(Position::from_fen("8/8/8/8/8/8/8/K6k w -").is_err());
+    assert_eq!(
+        Position::from_fen("8/8/8/8/8/8/8/K6k w - -").map(|p| p.fullmoves),
+        Ok(1)
+    );
+
+    for (fen, error) in [
+        ("8/8/8/8/8/8/8/K6k", FenError::SideToMove),
+        ("8/8/8/8/8/8/K6k w - -", FenError::Placement),
+        ("8/8/8/8/8/8/8/K7k w - -", FenError::Placement),
+        ("8/8/8/8/8/8/8/K6x w - -", FenError::Placement),
+        ("8/8/8/8/8/8/8/K6K w - -", FenError::Kings),
+        ("8/8/8/8/8/8/8/K6k x - -", FenError::SideToMove),
+        ("8/8/8/8/8/8/8/K6k w KX -", FenError::Castling),
+        ("8/8/8/8/8/8/8/K6k w - e4", FenError::EnPassant),
+        ("8/8/8/8/8/8/8/K6k w - - x", FenError::Counter),
+    ] {
+        assert_eq!(Position::from_fen(fen), Err(error), "{}", fen);
+    }
+}
@@ -0,0 +1,155 @@
+//! The rules of standard chess without the standard library: the pieces, moves, move generation
+//! and make/unmake for any `Board`, a position that is one, and FEN. Nothing here allocates, so it
+//! runs wherever Rust's `core` library does, such as on the microcontroller of a physical board
+//! that reads its pieces with sensors and lights up the legal moves. FreightChess itself builds
+//! its variants, search and tools on the same rules, with its own board.
+
+#![no_std]
+
+use core::fmt::{self, Display, Formatter};
+
+mod fen;
+mod movelist;
+mod position;
+pub mod rules;
+
+pub use fen::FenError;
+pub use movelist::{MoveList, MAX_MOVES};
+pub use position::{Position, Undo};
+pub use rules::Board;
+
+// Pieces are a number from 1 to 6, with `BLACK` set for black's.
+pub const EMPTY: u8 = 0;
+pub const PAWN: u8 = 1;
+pub const KNIGHT: u8 = 2;
+pub const ROOK: u8 = 3;
+pub const BISHOP: u8 = 4;
+pub const QUEEN: u8 = 5;
+pub const KING: u8 = 6;
+
+pub const WHITE: u8 = 0;
+pub const BLACK: u8 = 8;
+
+/// Piece letters as used in FEN, indexed by piece.
+pub const PIECE_LETTERS: [char; 16] = [
+    ' ', 'P', 'N', 'R', 'B', 'Q', 'K', ' ', ' ', 'p', 'n', 'r', 'b', 'q', 'k', ' ',
+];
+
+// Castling rights, as bit flags.
+pub const WHITE_KINGSIDE: u8 = 1;
+pub const WHITE_QUEENSIDE: u8 = 2;
+pub const BLACK_KINGSIDE: u8 = 4;
+pub const BLACK_QUEENSIDE: u8 = 8;
+
+/// Marks that there is no en passant square available.
+pub const NO_SQUARE: u8 = 64;
+
+/// A single move. Squares are numbered from 0 (a1) to 63 (h8), rank by rank, so the file of a
+/// square is `square & 7` and its rank is `square >> 3`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub from: u8,
+    pub to: u8,
+    /// The (colorless) piece a pawn promotes to, or the piece dropped by a drop, or `EMPTY` for
+    /// every other move.
+    pub promotion: u8,
+}
+
+impl Move {
+    pub const fn new(from: u8, to: u8) -> Move {
+        Move {
+            from,
+            to,
+            promotion: EMPTY,
+        }
+    }
+
+    /// Drops `piece` (colorless) from the mover's hand onto `square`, in the variants that have
+    /// hands. Drops are written with the same square as their start and end, which no other
+    /// move has.
+    pub const fn drop(piece: u8, square: u8) -> Move {
+        Move {
+            from: square,
+            to: square,
+            promotion: piece,
+        }
+    }
+
+    /// Returns `true` if the move drops a piece from the mover's hand.
+    pub const fn is_drop(&self) -> bool {
+        self.from == self.to
+    }
+}
+
+/// Writes a square in chessboard notation, e.g. `e4`.
+pub fn write_square(f: &mut Formatter<'_>, square: u8) -> fmt::Result {
+    write!(f, "{}{}", (b'a' + (square & 7)) as char, (square >> 3) + 1)
+}
+
+/// Writes the move as UCI does: the two squares and any promotion piece, as in `e7e8q`, or the
+/// piece and square of a drop, as in `N@f3`.
+impl Display for Move {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_drop() {
+            write!(f, "{}@", PIECE_LETTERS[self.promotion as usize])?;
+            return write_square(f, self.to);
+        }
+        write_square(f, self.from)?;
+        write_square(f, self.to)?;
+        if self.promotion != EMPTY {
+            let letter = PIECE_LETTERS[self.promotion as usize].to_ascii_lowercase();
+            write!(f, "{}", letter)?;
+        }
+        Ok(())
+    }
+}
+
+/// A `fmt::Write` into a fixed buffer, for tests to look at what `Display` writes without a
+/// `String`.
+#[cfg(test)]
+pub(crate) struct Buffer {
+    bytes: [u8; 96],
+    len: usize,
+}
+
+#[cfg(test)]
+impl Buffer {
+    pub(crate) fn write(value: impl Display) -> Buffer {
+        let mut buffer = Buffer {
+            bytes: [0; 96],
+            len: 0,
+        };
+        fmt::Write::write_fmt(&mut buffer, format_args!("{}", value)).unwrap();
+        buffer
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl fmt::Write for Buffer {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        let end = self.len + text.len();
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(text.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_move() {
+    assert_eq!(Buffer::write(Move::new(12, 28)).as_str(), "e2e4");
+    let promotion = Move {
+        from: 52,
+        to: 60,
+        promotion: QUEEN,
+    };
+    assert_eq!(Buffer::write(promotion).as_str(), "e7e8q");
+    assert_eq!(Buffer::write(Move::drop(KNIGHT, 21)).as_str(), "N@f3");
+    assert!(Move::drop(KNIGHT, 21).is_drop());
+}
@@ -0,0 +1,153 @@
+use core::ops::{Deref, DerefMut};
+
+use crate::Move;
+
+/// The most moves a `MoveList` holds. No position of standard chess has more than 218, but in
+/// crazyhouse and bughouse the drops onto every empty square come on top of those, as with the
+/// 299 moves of `k7/8/8/8/8/8/8/K7[QRBNPqrbnp] w - - 0 1`.
+pub const MAX_MOVES: usize = 512;
+
+/// A list of moves kept in a fixed array rather than on the heap.
+#[derive(Clone, Copy)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub const fn new() -> MoveList {
+        MoveList {
+            moves: [Move::new(0, 0); MAX_MOVES],
+            len: 0,
+        }
+    }
+
+    /// Adds `mv` to the end of the list.
+    ///
+    /// Panics if the list is full, which no position's moves fill.
+    pub fn push(&mut self, mv: Move) {
+        self.moves[self.len] = mv;
+        self.len += 1;
+    }
+
+    /// Keeps only the moves `keep` returns `true` for, in the same order.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Move) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(&self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> MoveList {
+        MoveList::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl core::fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl PartialEq for MoveList {
+    fn eq(&self, other: &MoveList) -> bool {
+        **self == **other
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = core::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// The moves of a list, taken out of it in order.
+pub struct IntoIter {
+    list: MoveList,
+    next: usize,
+}
+
+impl Iterator for IntoIter {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        let mv = self.list.get(self.next).copied();
+        self.next += 1;
+        mv
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.list.len().saturating_sub(self.next);
+        (left, Some(left))
+    }
+}
+
+impl ExactSizeIterator for IntoIter {}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            list: self,
+            next: 0,
+        }
+    }
+}
+
+impl Extend<Move> for MoveList {
+    fn extend<I: IntoIterator<Item = Move>>(&mut self, moves: I) {
+        for mv in moves {
+            self.push(mv);
+        }
+    }
+}
+
+impl FromIterator<Move> for MoveList {
+    fn from_iter<I: IntoIterator<Item = Move>>(moves: I) -> MoveList {
+        let mut list = MoveList::new();
+        list.extend(moves);
+        list
+    }
+}
+
+#[test]
+fn test_move_list() {
+    let mut list = (0..10).map(|to| Move::new(0, to)).collect::<MoveList>();
+    assert_eq!(list.len(), 10);
+    assert!(list.contains(&Move::new(0, 3)));
+    list.retain(|mv| mv.to % 2 == 0);
+    assert_eq!(list.len(), 5);
+    assert_eq!(list[1], Move::new(0, 2));
+    assert_eq!(list.into_iter().map(|mv| mv.to).sum::<u8>(), 20);
+    list.clear();
+    assert!(list.is_empty());
+}
@@ -0,0 +1,225 @@
+use crate::rules::{self, Board};
+use crate::{
+    Move, MoveList, BISHOP, BLACK, BLACK_KINGSIDE, BLACK_QUEENSIDE, EMPTY, KING, KNIGHT, NO_SQUARE,
+    PAWN, QUEEN, ROOK, WHITE, WHITE_KINGSIDE, WHITE_QUEENSIDE,
+};
+
+/// A position in standard chess: a piece (or `EMPTY`) on each square, whose move it is, and
+/// what the history of the game still allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub(crate) squares: [u8; 64],
+    pub(crate) side: u8,
+    /// Remaining castling rights, as `WHITE_KINGSIDE | ...` flags.
+    pub(crate) castling: u8,
+    /// The square a pawn may capture onto en passant, or `NO_SQUARE`.
+    pub(crate) en_passant: u8,
+    /// Half-moves since the last capture or pawn move, for the fifty-move rule.
+    pub(crate) halfmoves: u16,
+    /// The number of the move being played, counting from 1 and going up after black's.
+    pub(crate) fullmoves: u16,
+}
+
+/// What `make_move` keeps to take its move back with `unmake_move`: the position from before
+/// the move, which is small enough to copy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Undo(Position);
+
+impl Position {
+    /// The standard start position.
+    pub const fn start() -> Position {
+        let mut squares = [EMPTY; 64];
+        let back = [ROOK, KNIGHT, BISHOP, QUEEN, KING, BISHOP, KNIGHT, ROOK];
+        let mut file = 0;
+        while file < 8 {
+            squares[file] = back[file];
+            squares[8 + file] = PAWN;
+            squares[48 + file] = PAWN | BLACK;
+            squares[56 + file] = back[file] | BLACK;
+            file += 1;
+        }
+        Position {
+            squares,
+            side: WHITE,
+            castling: WHITE_KINGSIDE | WHITE_QUEENSIDE | BLACK_KINGSIDE | BLACK_QUEENSIDE,
+            en_passant: NO_SQUARE,
+            halfmoves: 0,
+            fullmoves: 1,
+        }
+    }
+
+    /// Returns the piece on `square`, numbered from 0 (a1) to 63 (h8).
+    pub fn piece(&self, square: u8) -> u8 {
+        self.squares[square as usize]
+    }
+
+    /// Returns `WHITE` or `BLACK`, depending on whose turn it is.
+    pub fn side_to_move(&self) -> u8 {
+        self.side
+    }
+
+    pub fn castling(&self) -> u8 {
+        self.castling
+    }
+
+    pub fn en_passant(&self) -> u8 {
+        self.en_passant
+    }
+
+    pub fn halfmoves(&self) -> u16 {
+        self.halfmoves
+    }
+
+    pub fn king_square(&self, color: u8) -> Option<u8> {
+        rules::king_square(self, color)
+    }
+
+    /// Returns `true` if any piece of color `by` attacks `square`.
+    pub fn is_attacked(&self, square: u8, by: u8) -> bool {
+        rules::is_attacked(self, square, by)
+    }
+
+    /// Returns `true` if `color`'s king is in check.
+    pub fn is_in_check(&self, color: u8) -> bool {
+        self.king_square(color)
+            .is_some_and(|square| self.is_attacked(square, color ^ BLACK))
+    }
+
+    /// Returns every legal move for the side to move.
+    pub fn legal_moves(&self) -> MoveList {
+        let mut moves = self.pseudo_legal_moves();
+        let side = self.side;
+        let mut next = *self;
+        moves.retain(|&mv| {
+            let undo = next.make_move(mv);
+            let legal = !next.is_in_check(side);
+            next.unmake_move(undo);
+            legal
+        });
+        moves
+    }
+
+    /// Returns `true` if the side to move is checkmated.
+    pub fn is_checkmate(&self) -> bool {
+        self.is_in_check(self.side) && self.legal_moves().is_empty()
+    }
+
+    /// Returns `true` if the side to move has no legal moves but isn't in check.
+    pub fn is_stalemate(&self) -> bool {
+        !self.is_in_check(self.side) && self.legal_moves().is_empty()
+    }
+
+    /// Generates every move that follows the movement rules of the pieces, without checking
+    /// whether it leaves the mover's own king in check.
+    pub fn pseudo_legal_moves(&self) -> MoveList {
+        rules::piece_moves(self)
+    }
+
+    /// Plays `mv`, which must be one of `pseudo_legal_moves`, returning what `unmake_move`
+    /// needs to take it back.
+    pub fn make_move(&mut self, mv: Move) -> Undo {
+        let undo = Undo(*self);
+        rules::make_move(self, mv);
+        undo
+    }
+
+    /// Takes back the move `undo` was made for.
+    pub fn unmake_move(&mut self, undo: Undo) {
+        *self = undo.0;
+    }
+
+    /// Counts the move paths `depth` moves long, to check move generation against the known
+    /// counts.
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        let moves = self.legal_moves();
+        if depth <= 1 {
+            return moves.len() as u64 * depth as u64 + (depth == 0) as u64;
+        }
+        let mut nodes = 0;
+        for mv in moves {
+            let undo = self.make_move(mv);
+            nodes += self.perft(depth - 1);
+            self.unmake_move(undo);
+        }
+        nodes
+    }
+}
+
+impl Board for Position {
+    fn piece(&self, square: u8) -> u8 {
+        self.squares[square as usize]
+    }
+
+    fn set_piece(&mut self, square: u8, piece: u8) {
+        self.squares[square as usize] = piece;
+    }
+
+    fn side_to_move(&self) -> u8 {
+        self.side
+    }
+
+    fn next_turn(&mut self) {
+        if self.side == BLACK {
+            self.fullmoves += 1;
+        }
+        self.side ^= BLACK;
+    }
+
+    fn castling(&self) -> u8 {
+        self.castling
+    }
+
+    fn set_castling(&mut self, castling: u8) {
+        self.castling = castling;
+    }
+
+    fn en_passant(&self) -> u8 {
+        self.en_passant
+    }
+
+    fn set_en_passant(&mut self, square: u8) {
+        self.en_passant = square;
+    }
+
+    fn halfmoves(&self) -> u16 {
+        self.halfmoves
+    }
+
+    fn set_halfmoves(&mut self, halfmoves: u16) {
+        self.halfmoves = halfmoves;
+    }
+}
+
+impl Default for Position {
+    fn default() -> Position {
+        Position::start()
+    }
+}
+
+#[test]
+fn test_position() {
+    let mut position = Position::start();
+    assert_eq!(position.legal_moves().len(), 20);
+    assert_eq!(position.perft(3), 8902);
+    assert_eq!(position, Position::start());
+
+    // Kiwipete, which has castling, en passant and promotions within a few moves.
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let mut kiwipete = Position::from_fen(fen).unwrap();
+    assert_eq!(kiwipete.perft(1), 48);
+    assert_eq!(kiwipete.perft(3), 97862);
+    assert_eq!(kiwipete, Position::from_fen(fen).unwrap());
+
+    let mut endgame = Position::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
+    assert_eq!(endgame.perft(4), 43238);
+
+    let mut promotions =
+        Position::from_fen("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1")
+            .unwrap();
+    assert_eq!(promotions.perft(3), 9467);
+
+    let mate = Position::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    assert!(mate.is_checkmate());
+    let stalemate = Position::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    assert!(stalemate.is_stalemate());
+}
@@ -0,0 +1,373 @@
+use core::ops::Range;
+
+use crate::{
+    Move, MoveList, BISHOP, BLACK, BLACK_KINGSIDE, BLACK_QUEENSIDE, EMPTY, KING, KNIGHT, NO_SQUARE,
+    PAWN, QUEEN, ROOK, WHITE, WHITE_KINGSIDE, WHITE_QUEENSIDE,
+};
+
+/// Every castling right, in the order of `Board::castling_rooks`: right `i` is bit `1 << i`.
+pub const CASTLING_RIGHTS: [u8; 4] = [
+    WHITE_KINGSIDE,
+    WHITE_QUEENSIDE,
+    BLACK_KINGSIDE,
+    BLACK_QUEENSIDE,
+];
+/// The squares the rooks castle from in standard chess, in the order of `CASTLING_RIGHTS`.
+pub const CORNERS: [u8; 4] = [7, 0, 63, 56];
+
+pub const KNIGHT_OFFSETS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+pub const KING_OFFSETS: [(i8, i8); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+
+const PROMOTIONS: [u8; 4] = [QUEEN, ROOK, BISHOP, KNIGHT];
+
+/// A board the pieces move on by the rules here. `Position` is one; FreightChess's own board,
+/// which also keeps what its variants need, is another, so both generate and play their moves
+/// with the same code.
+pub trait Board {
+    /// Returns the piece on `square`, numbered from 0 (a1) to 63 (h8).
+    fn piece(&self, square: u8) -> u8;
+    fn set_piece(&mut self, square: u8, piece: u8);
+    /// Returns `WHITE` or `BLACK`, depending on whose turn it is.
+    fn side_to_move(&self) -> u8;
+    /// Passes the turn to the other side, once a move has been played.
+    fn next_turn(&mut self);
+    /// Remaining castling rights, as `WHITE_KINGSIDE | ...` flags.
+    fn castling(&self) -> u8;
+    fn set_castling(&mut self, castling: u8);
+    /// The square a pawn may capture onto en passant, or `NO_SQUARE`.
+    fn en_passant(&self) -> u8;
+    fn set_en_passant(&mut self, square: u8);
+    /// Half-moves since the last capture or pawn move, for the fifty-move rule.
+    fn halfmoves(&self) -> u16;
+    fn set_halfmoves(&mut self, halfmoves: u16);
+
+    /// The squares the rooks castle from, in the order of `CASTLING_RIGHTS`.
+    fn castling_rooks(&self) -> [u8; 4] {
+        CORNERS
+    }
+
+    /// Whether castling is by the rules of Chess960, written as the king capturing its own rook.
+    fn chess960(&self) -> bool {
+        false
+    }
+
+    /// The files and ranks of the board, which are those of its bottom left corner.
+    fn dimensions(&self) -> (u8, u8) {
+        (8, 8)
+    }
+}
+
+/// Returns the square `(files, ranks)` away from `square`, if it is still on the board.
+pub fn offset(square: u8, (files, ranks): (i8, i8)) -> Option<u8> {
+    let file = (square & 7) as i8 + files;
+    let rank = (square >> 3) as i8 + ranks;
+    if (0..8).contains(&file) && (0..8).contains(&rank) {
+        Some((rank * 8 + file) as u8)
+    } else {
+        None
+    }
+}
+
+/// The indices (into `CASTLING_RIGHTS`) of `color`'s two castling rights.
+pub fn castling_indices(color: u8) -> Range<usize> {
+    if color == WHITE {
+        0..2
+    } else {
+        2..4
+    }
+}
+
+/// Where the king and the rook stand after castling with right `index`: on the g and f files
+/// kingside and the c and d files queenside, wherever they started.
+pub fn castled_squares(index: usize) -> (u8, u8) {
+    let home = if index < 2 { 0 } else { 56 };
+    if index.is_multiple_of(2) {
+        (home + 6, home + 5)
+    } else {
+        (home + 2, home + 3)
+    }
+}
+
+pub fn king_square(board: &impl Board, color: u8) -> Option<u8> {
+    (0..64).find(|&square| board.piece(square) == KING | color)
+}
+
+/// Returns `true` if any piece of color `by` attacks `square`.
+pub fn is_attacked(board: &impl Board, square: u8, by: u8) -> bool {
+    let pawn_rank = if by == WHITE { -1 } else { 1 };
+    let attacked_by = |offsets: &[(i8, i8)], piece: u8| {
+        offsets
+            .iter()
+            .filter_map(|&o| offset(square, o))
+            .any(|s| board.piece(s) == piece | by)
+    };
+    if attacked_by(&[(-1, pawn_rank), (1, pawn_rank)], PAWN)
+        || attacked_by(&KNIGHT_OFFSETS, KNIGHT)
+        || attacked_by(&KING_OFFSETS, KING)
+    {
+        return true;
+    }
+    let slides_to = |directions: &[(i8, i8)], piece: u8| {
+        directions.iter().any(|&direction| {
+            let mut current = square;
+            while let Some(next) = offset(current, direction) {
+                let found = board.piece(next);
+                if found != EMPTY {
+                    return found == piece | by || found == QUEEN | by;
+                }
+                current = next;
+            }
+            false
+        })
+    };
+    slides_to(&ROOK_DIRECTIONS, ROOK) || slides_to(&BISHOP_DIRECTIONS, BISHOP)
+}
+
+/// Generates every move that follows the standard movement rules of the pieces, without
+/// checking whether it leaves the mover's own king in check.
+pub fn piece_moves(board: &impl Board) -> MoveList {
+    let side = board.side_to_move();
+    let (files, ranks) = board.dimensions();
+    let mut moves = MoveList::new();
+    for from in 0..64 {
+        let piece = board.piece(from);
+        if piece == EMPTY || piece & BLACK != side {
+            continue;
+        }
+        match piece & !BLACK {
+            PAWN => pawn_moves(board, from, ranks, &mut moves),
+            KNIGHT => step_moves(board, from, &KNIGHT_OFFSETS, &mut moves),
+            BISHOP => slide_moves(board, from, &BISHOP_DIRECTIONS, &mut moves),
+            ROOK => slide_moves(board, from, &ROOK_DIRECTIONS, &mut moves),
+            QUEEN => {
+                slide_moves(board, from, &BISHOP_DIRECTIONS, &mut moves);
+                slide_moves(board, from, &ROOK_DIRECTIONS, &mut moves);
+            }
+            KING => {
+                step_moves(board, from, &KING_OFFSETS, &mut moves);
+                castling_moves(board, from, &mut moves);
+            }
+            _ => {}
+        }
+    }
+    // On a smaller board, pieces stop at its edge. A line that leaves it never comes back.
+    if (files, ranks) != (8, 8) {
+        moves.retain(|mv| mv.to & 7 < files && mv.to >> 3 < ranks);
+    }
+    moves
+}
+
+/// Returns `true` if a piece of the side to move may land on `square`.
+fn can_land_on(board: &impl Board, square: u8) -> bool {
+    let piece = board.piece(square);
+    piece == EMPTY || piece & BLACK != board.side_to_move()
+}
+
+fn step_moves(board: &impl Board, from: u8, offsets: &[(i8, i8)], moves: &mut MoveList) {
+    for to in offsets.iter().filter_map(|&o| offset(from, o)) {
+        if can_land_on(board, to) {
+            moves.push(Move::new(from, to));
+        }
+    }
+}
+
+fn slide_moves(board: &impl Board, from: u8, directions: &[(i8, i8)], moves: &mut MoveList) {
+    for &direction in directions {
+        let mut current = from;
+        while let Some(to) = offset(current, direction) {
+            if !can_land_on(board, to) {
+                break;
+            }
+            moves.push(Move::new(from, to));
+            if board.piece(to) != EMPTY {
+                break;
+            }
+            current = to;
+        }
+    }
+}
+
+/// Pawn moves on a board with `ranks` ranks: pawns promote on the last of them, and only step
+/// twice from their first rank on the full-size board.
+fn pawn_moves(board: &impl Board, from: u8, ranks: u8, moves: &mut MoveList) {
+    let side = board.side_to_move();
+    let (forward, start_rank, last_rank) = match (side, ranks) {
+        (WHITE, 8) => (1, 1, 7),
+        (WHITE, _) => (1, u8::MAX, ranks - 1),
+        (_, 8) => (-1, 6, 0),
+        _ => (-1, u8::MAX, 0),
+    };
+    let mut push = |to: u8| {
+        if to >> 3 == last_rank {
+            for promotion in PROMOTIONS {
+                moves.push(Move {
+                    from,
+                    to,
+                    promotion,
+                });
+            }
+        } else {
+            moves.push(Move::new(from, to));
+        }
+    };
+
+    if let Some(to) = offset(from, (0, forward)) {
+        if board.piece(to) == EMPTY {
+            push(to);
+            if from >> 3 == start_rank {
+                if let Some(to) = offset(to, (0, forward)) {
+                    if board.piece(to) == EMPTY {
+                        push(to);
+                    }
+                }
+            }
+        }
+    }
+    for to in [(-1, forward), (1, forward)]
+        .iter()
+        .filter_map(|&o| offset(from, o))
+    {
+        let target = board.piece(to);
+        if (target != EMPTY && target & BLACK != side) || to == board.en_passant() {
+            push(to);
+        }
+    }
+}
+
+/// Castling, with the king and rook wherever Chess960 put them (and on the e file and in the
+/// corners otherwise): every square either of them crosses must be empty (but for the two of
+/// them), and the king may not be in check or cross an attacked square.
+fn castling_moves(board: &impl Board, from: u8, moves: &mut MoveList) {
+    let side = board.side_to_move();
+    let chess960 = board.chess960();
+    for index in castling_indices(side) {
+        let rook = board.castling_rooks()[index];
+        if board.castling() & CASTLING_RIGHTS[index] == 0
+            || board.piece(rook) != ROOK | side
+            || from >> 3 != rook >> 3
+            || (!chess960 && from & 7 != 4)
+        {
+            continue;
+        }
+        let (king_to, rook_to) = castled_squares(index);
+        let squares = [from, rook, king_to, rook_to];
+        let (low, high) = (
+            *squares.iter().min().unwrap(),
+            *squares.iter().max().unwrap(),
+        );
+        let clear = (low..=high)
+            .all(|square| square == from || square == rook || board.piece(square) == EMPTY);
+        let safe = (from.min(king_to)..=from.max(king_to))
+            .all(|square| !is_attacked(board, square, side ^ BLACK));
+        if clear && safe {
+            moves.push(Move::new(from, if chess960 { rook } else { king_to }));
+        }
+    }
+}
+
+/// The castling right `mv` would castle with, as an index into `castling_rooks`, if it is a
+/// castling move: the king moving two files in standard chess, or onto its own castling rook in
+/// Chess960. The right itself may already be lost.
+pub fn castling_index(board: &impl Board, mv: Move) -> Option<usize> {
+    let piece = board.piece(mv.from);
+    if piece & !BLACK != KING {
+        return None;
+    }
+    let side = piece & BLACK;
+    castling_indices(side).find(|&index| match board.chess960() {
+        true => mv.to == board.castling_rooks()[index] && board.piece(mv.to) == ROOK | side,
+        false => {
+            (mv.from & 7).abs_diff(mv.to & 7) == 2
+                && mv.from >> 3 == mv.to >> 3
+                && mv.to == castled_squares(index).0
+        }
+    })
+}
+
+/// Plays `mv` by the standard rules, or drops its piece if it is a drop, without checking that
+/// it is legal: moves the pieces and updates the castling rights, en passant square and
+/// half-move clock, and passes the turn.
+pub fn make_move(board: &mut impl Board, mv: Move) {
+    let side = board.side_to_move();
+    if mv.is_drop() {
+        board.set_piece(mv.to, mv.promotion | side);
+        board.set_halfmoves(match mv.promotion {
+            PAWN => 0,
+            _ => board.halfmoves().saturating_add(1),
+        });
+        board.set_en_passant(NO_SQUARE);
+        board.next_turn();
+        return;
+    }
+    let piece = board.piece(mv.from);
+    let kind = piece & !BLACK;
+
+    if let Some(index) = castling_index(board, mv) {
+        let (king_to, rook_to) = castled_squares(index);
+        board.set_piece(mv.from, EMPTY);
+        board.set_piece(board.castling_rooks()[index], EMPTY);
+        board.set_piece(king_to, piece);
+        board.set_piece(rook_to, ROOK | side);
+        board.set_halfmoves(board.halfmoves().saturating_add(1));
+        board.set_en_passant(NO_SQUARE);
+        let mut castling = board.castling();
+        for index in castling_indices(side) {
+            castling &= !CASTLING_RIGHTS[index];
+        }
+        board.set_castling(castling);
+        board.next_turn();
+        return;
+    }
+    if kind == PAWN && mv.to == board.en_passant() {
+        // The captured pawn sits behind the en passant square.
+        let captured = if side == WHITE { mv.to - 8 } else { mv.to + 8 };
+        board.set_piece(captured, EMPTY);
+    }
+    board.set_halfmoves(match kind == PAWN || board.piece(mv.to) != EMPTY {
+        true => 0,
+        false => board.halfmoves().saturating_add(1),
+    });
+    board.set_en_passant(match kind == PAWN && mv.from.abs_diff(mv.to) == 16 {
+        true => (mv.from + mv.to) / 2,
+        false => NO_SQUARE,
+    });
+    // Moving the king loses both rights; moving or capturing a castling rook loses its own.
+    let mut castling = board.castling();
+    for (index, &rook) in board.castling_rooks().iter().enumerate() {
+        let king_moved = kind == KING && castling_indices(side).contains(&index);
+        if king_moved || mv.from == rook || mv.to == rook {
+            castling &= !CASTLING_RIGHTS[index];
+        }
+    }
+    board.set_castling(castling);
+
+    board.set_piece(
+        mv.to,
+        match mv.promotion {
+            EMPTY => piece,
+            promotion => promotion | side,
+        },
+    );
+    board.set_piece(mv.from, EMPTY);
+    board.next_turn();
+}
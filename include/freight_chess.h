@@ -0,0 +1,110 @@
+/*
+ * FreightChess: the rules and engine as a C library.
+ *
+ * Build it with `cargo build --release --features cdylib`, which leaves
+ * libfreight_chess.so (or .dylib, or freight_chess.dll) in target/release.
+ *
+ * Moves are in UCI notation (e2e4, e7e8q, N@f3). Functions that return text
+ * copy it into the caller's buffer as snprintf does: as much as fits, always
+ * NUL-terminated, returning the length of the whole text.
+ */
+
+#ifndef FREIGHT_CHESS_H
+#define FREIGHT_CHESS_H
+
+/* Generated from src/ffi.rs by cbindgen with cbindgen.toml. Don't edit it by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * `fc_make_move` played the move.
+ */
+#define FC_OK 0
+
+/**
+ * An argument was a null pointer or not valid UTF-8.
+ */
+#define FC_INVALID_ARGUMENT -1
+
+/**
+ * The move isn't legal in the position.
+ */
+#define FC_ILLEGAL_MOVE 1
+
+/**
+ * A position, which C sees only through a pointer.
+ */
+typedef struct FcBoard FcBoard;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * A new board in the standard start position, to be freed with `fc_board_free`.
+ */
+FcBoard *fc_board_new(void);
+
+/**
+ * A new board in the position `fen` describes, to be freed with `fc_board_free`, or null if
+ * `fen` isn't a valid position.
+ *
+ * # Safety
+ *
+ * `fen` must be null or point to a NUL-terminated string.
+ */
+FcBoard *fc_board_from_fen(const char *fen);
+
+/**
+ * Frees a board made by `fc_board_new` or `fc_board_from_fen`. Freeing null does nothing.
+ *
+ * # Safety
+ *
+ * `board` must be null or a board not yet freed.
+ */
+void fc_board_free(FcBoard *board);
+
+/**
+ * Writes the position's FEN into `buffer`.
+ *
+ * # Safety
+ *
+ * `board` must be a live board, and `buffer` null or `capacity` writable bytes.
+ */
+size_t fc_board_fen(const FcBoard *board, char *buffer, size_t capacity);
+
+/**
+ * Writes every legal move into `buffer`, separated by spaces.
+ *
+ * # Safety
+ *
+ * `board` must be a live board, and `buffer` null or `capacity` writable bytes.
+ */
+size_t fc_legal_moves(const FcBoard *board, char *buffer, size_t capacity);
+
+/**
+ * Plays `mv`, in UCI notation or SAN. Returns `FC_OK`, or `FC_ILLEGAL_MOVE` without changing
+ * the board if the move can't be played.
+ *
+ * # Safety
+ *
+ * `board` must be a live board, and `mv` null or a NUL-terminated string.
+ */
+int32_t fc_make_move(FcBoard *board, const char *mv);
+
+/**
+ * Searches `depth` plies deep, at most `MAX_DEPTH` (64), and writes the best move into `buffer`.
+ * Returns 0, writing an empty string, if the game is over.
+ *
+ * # Safety
+ *
+ * `board` must be a live board, and `buffer` null or `capacity` writable bytes.
+ */
+size_t fc_search_bestmove(const FcBoard *board, uint32_t depth, char *buffer, size_t capacity);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FREIGHT_CHESS_H */
@@ -0,0 +1,355 @@
+//! The player registry a server uses to decide who may play. It is a JSON file mapping each
+//! player's name to a salted PBKDF2 hash of their password and their Glicko-2 rating, managed
+//! with the `players` subcommand:
+//!
+//! ```json
+//! { "players": { "alice": { "salt": "9f86d081...", "hash": "5e884898...", "rating": 1500.0,
+//!   "deviation": 350.0, "volatility": 0.06, "games": 0 } } }
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{json, Value};
+
+use crate::rating::Rating;
+
+/// How many PBKDF2 rounds protect each password.
+const ITERATIONS: u32 = 100_000;
+
+/// The longest player name accepted.
+pub const MAX_NAME_LEN: usize = 32;
+
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+struct Account {
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+    rating: Rating,
+    /// How many rated games the player has finished.
+    games: u32,
+}
+
+/// Every registered player, as stored in the registry file at `path`.
+pub struct Accounts {
+    path: PathBuf,
+    players: BTreeMap<String, Account>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn iterations() -> NonZeroU32 {
+    NonZeroU32::new(ITERATIONS).unwrap()
+}
+
+/// Whether `name` can be registered: 1 to 32 letters, digits, `-` or `_`, so it can go into PGN
+/// tags and messages without escaping.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl Accounts {
+    /// Loads the registry at `path`. A missing file is an empty registry, which `save` creates.
+    pub fn load(path: &Path) -> Result<Accounts, Error> {
+        let mut accounts = Accounts {
+            path: path.to_path_buf(),
+            players: BTreeMap::new(),
+        };
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(accounts),
+            Err(e) => return Err(e),
+        };
+        let corrupt = || invalid(format!("{} is not a player registry.", path.display()));
+        let registry: Value = serde_json::from_str(&text).map_err(|_| corrupt())?;
+        for (name, account) in registry["players"].as_object().ok_or_else(corrupt)? {
+            let field = |key: &str| account[key].as_str().and_then(from_hex);
+            let (salt, hash) = match (field("salt"), field("hash")) {
+                (Some(salt), Some(hash)) => (salt, hash),
+                _ => return Err(corrupt()),
+            };
+            // Registries written before ratings existed have none yet.
+            let number = |key: &str| {
+                account
+                    .get(key)
+                    .map(|value| value.as_f64().ok_or_else(corrupt))
+            };
+            let default = Rating::default();
+            let rating = Rating {
+                rating: number("rating").unwrap_or(Ok(default.rating))?,
+                deviation: number("deviation").unwrap_or(Ok(default.deviation))?,
+                volatility: number("volatility").unwrap_or(Ok(default.volatility))?,
+            };
+            let games = number("games").unwrap_or(Ok(0.0))? as u32;
+            let account = Account {
+                salt,
+                hash,
+                rating,
+                games,
+            };
+            accounts.players.insert(name.clone(), account);
+        }
+        Ok(accounts)
+    }
+
+    /// Writes the registry back to its file.
+    pub fn save(&self) -> Result<(), Error> {
+        let players = self
+            .players
+            .iter()
+            .map(|(name, account)| {
+                let account = json!({
+                    "salt": to_hex(&account.salt),
+                    "hash": to_hex(&account.hash),
+                    "rating": account.rating.rating,
+                    "deviation": account.rating.deviation,
+                    "volatility": account.rating.volatility,
+                    "games": account.games,
+                });
+                (name.clone(), account)
+            })
+            .collect::<serde_json::Map<String, Value>>();
+        let text = serde_json::to_string_pretty(&json!({ "players": players }))?;
+        // Written next to the registry and renamed over it, so a crash can't leave it half written.
+        let partial = self.path.with_extension("partial");
+        std::fs::write(&partial, text + "\n")?;
+        std::fs::rename(&partial, &self.path)
+    }
+
+    /// Registers `name` with `password`, or changes their password if they are already
+    /// registered. Their rating is kept.
+    pub fn set_password(&mut self, name: &str, password: &str) -> Result<(), Error> {
+        if !is_valid_name(name) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Player names must be 1 to 32 letters, digits, '-' or '_'.",
+            ));
+        }
+        let mut salt = vec![0; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| Error::other("No randomness available for the password salt."))?;
+        let mut hash = vec![0; HASH_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations(),
+            &salt,
+            password.as_bytes(),
+            &mut hash,
+        );
+        match self.players.get_mut(name) {
+            Some(account) => {
+                account.salt = salt;
+                account.hash = hash;
+            }
+            None => {
+                let account = Account {
+                    salt,
+                    hash,
+                    rating: Rating::default(),
+                    games: 0,
+                };
+                self.players.insert(name.to_string(), account);
+            }
+        }
+        Ok(())
+    }
+
+    /// Unregisters `name`, returning whether they were registered.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.players.remove(name).is_some()
+    }
+
+    /// The registered players, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.players.keys().map(String::as_str)
+    }
+
+    /// Updates the ratings of `white` and `black` after a game between them, where `score` is
+    /// what white scored (1, 0.5 or 0). Returns their new ratings, or `None` (changing nothing)
+    /// unless they are two different registered players.
+    pub fn record_game(&mut self, white: &str, black: &str, score: f64) -> Option<[Rating; 2]> {
+        if white == black {
+            return None;
+        }
+        let before = [
+            self.players.get(white)?.rating,
+            self.players.get(black)?.rating,
+        ];
+        let after = [
+            before[0].update(&[(before[1], score)]),
+            before[1].update(&[(before[0], 1.0 - score)]),
+        ];
+        for (name, rating) in [white, black].into_iter().zip(after) {
+            let account = self.players.get_mut(name)?;
+            account.rating = rating;
+            account.games += 1;
+        }
+        Some(after)
+    }
+
+    /// Every registered player with their rating and number of rated games, best first.
+    pub fn leaderboard(&self) -> Vec<(&str, Rating, u32)> {
+        let mut players = self
+            .players
+            .iter()
+            .map(|(name, account)| (name.as_str(), account.rating, account.games))
+            .collect::<Vec<_>>();
+        players.sort_by(|a, b| b.1.rating.total_cmp(&a.1.rating));
+        players
+    }
+
+    /// Whether `name` is registered with `password`.
+    pub fn verify(&self, name: &str, password: &str) -> bool {
+        self.credentials(name)
+            .is_some_and(|credentials| credentials.verify(password))
+    }
+
+    /// What `name`'s password is checked against, if they are registered. Checking is slow on
+    /// purpose, so a server can copy these out and check without holding on to the registry.
+    pub fn credentials(&self, name: &str) -> Option<Credentials> {
+        self.players.get(name).map(|account| Credentials {
+            salt: account.salt.clone(),
+            hash: account.hash.clone(),
+        })
+    }
+}
+
+/// The salt and hash of a registered player's password.
+pub struct Credentials {
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+impl Credentials {
+    /// Whether `password` is the one these were made from.
+    pub fn verify(&self, password: &str) -> bool {
+        pbkdf2::verify(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations(),
+            &self.salt,
+            password.as_bytes(),
+            &self.hash,
+        )
+        .is_ok()
+    }
+}
+
+/// Reads a password from the first line of stdin.
+fn read_password(name: &str) -> Result<String, Error> {
+    eprint!("Password for {}: ", name);
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    let password = line.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "The password can't be empty.",
+        ));
+    }
+    Ok(password.to_string())
+}
+
+/// Runs the `players` subcommand against the registry at `path`: `add` registers a player (or
+/// changes their password), `remove` unregisters them, `list` prints everyone registered and
+/// `leaderboard` prints them by rating.
+pub fn manage(path: &Path, command: &str, name: Option<&str>) -> Result<(), Error> {
+    let mut accounts = Accounts::load(path)?;
+    match (command, name) {
+        ("add", Some(name)) => {
+            let password = read_password(name)?;
+            accounts.set_password(name, &password)?;
+            accounts.save()?;
+            println!("Saved {}.", name);
+        }
+        ("remove", Some(name)) => {
+            if !accounts.remove(name) {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("{} isn't registered.", name),
+                ));
+            }
+            accounts.save()?;
+            println!("Removed {}.", name);
+        }
+        ("leaderboard", _) => {
+            for (rank, (name, rating, games)) in accounts.leaderboard().into_iter().enumerate() {
+                println!(
+                    "{:>3}. {:<32} {:>5.0} ±{:<4.0} {} games",
+                    rank + 1,
+                    name,
+                    rating.rating,
+                    2.0 * rating.deviation,
+                    games
+                );
+            }
+        }
+        _ => {
+            for name in accounts.names() {
+                println!("{}", name);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_accounts() {
+    let path =
+        std::env::temp_dir().join(format!("freight_chess_players_{}.json", std::process::id()));
+    let mut accounts = Accounts::load(&path).unwrap();
+    assert_eq!(accounts.names().count(), 0);
+    accounts.set_password("alice", "hunter2").unwrap();
+    accounts.set_password("bob", "correct horse").unwrap();
+    assert!(accounts.set_password("no spaces", "x").is_err());
+    accounts.save().unwrap();
+
+    let mut accounts = Accounts::load(&path).unwrap();
+    assert_eq!(accounts.names().collect::<Vec<&str>>(), ["alice", "bob"]);
+    assert!(accounts.verify("alice", "hunter2"));
+    assert!(!accounts.verify("alice", "hunter3"));
+    assert!(!accounts.verify("carol", "hunter2"));
+    accounts.set_password("alice", "hunter3").unwrap();
+    assert!(accounts.verify("alice", "hunter3"));
+    let [alice, bob] = accounts.record_game("alice", "bob", 0.0).unwrap();
+    assert!(alice.rating < 1500.0 && bob.rating > 1500.0);
+    assert!(accounts.record_game("alice", "alice", 1.0).is_none());
+    assert!(accounts.record_game("alice", "carol", 1.0).is_none());
+    accounts.save().unwrap();
+    let mut accounts = Accounts::load(&path).unwrap();
+    let leaderboard = accounts.leaderboard();
+    assert_eq!(leaderboard[0].0, "bob");
+    assert_eq!(leaderboard[0].1, bob);
+    assert_eq!(leaderboard[1].2, 1);
+    assert!(accounts.remove("bob"));
+    assert!(!accounts.remove("bob"));
+
+    std::fs::write(&path, "{\"players\": {\"alice\": {\"salt\": \"zz\"}}}").unwrap();
+    assert!(Accounts::load(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,242 @@
+//! Colored arrows and highlighted squares drawn over a position, as written into PGN comments by
+//! Lichess and ChessBase: `[%csl Rd4,Ge5]` highlights squares and `[%cal Ge2e4]` draws arrows,
+//! each prefixed with the letter of its color.
+
+use crate::movegen::square_name;
+use crate::ChessBoard;
+
+/// The colors annotations come in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    Green,
+    Red,
+    Yellow,
+    Blue,
+}
+
+impl Color {
+    const ALL: [Color; 4] = [Color::Green, Color::Red, Color::Yellow, Color::Blue];
+
+    /// The letter the color is written with in `[%csl]` and `[%cal]`.
+    pub fn letter(self) -> char {
+        match self {
+            Color::Green => 'G',
+            Color::Red => 'R',
+            Color::Yellow => 'Y',
+            Color::Blue => 'B',
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Color::Green => "green",
+            Color::Red => "red",
+            Color::Yellow => "yellow",
+            Color::Blue => "blue",
+        }
+    }
+
+    /// Splits the color letter off the front of `text`, which is green if it has none.
+    fn split(text: &str) -> (Color, &str) {
+        let color = Color::ALL
+            .into_iter()
+            .find(|color| text.starts_with(color.letter()));
+        match color {
+            Some(color) => (color, &text[1..]),
+            None => (Color::Green, text),
+        }
+    }
+}
+
+/// The arrows and highlighted squares on a position.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Annotations {
+    pub squares: Vec<(Color, u8)>,
+    pub arrows: Vec<(Color, u8, u8)>,
+}
+
+fn parse_square(text: &str) -> Option<u8> {
+    (text.len() == 2 && ChessBoard::is_valid_piece(text.as_bytes()))
+        .then(|| ChessBoard::square_of(text.as_bytes()))
+}
+
+/// Reads a highlighted square, such as `Rd4`, or `d4` for a green one.
+pub fn parse_highlight(text: &str) -> Option<(Color, u8)> {
+    let (color, square) = Color::split(text);
+    Some((color, parse_square(square)?))
+}
+
+/// Reads an arrow, such as `Ge2e4`, or `e2e4` for a green one.
+pub fn parse_arrow(text: &str) -> Option<(Color, u8, u8)> {
+    let (color, squares) = Color::split(text);
+    let (from, to) = (squares.get(..2)?, squares.get(2..)?);
+    Some((color, parse_square(from)?, parse_square(to)?))
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.squares.is_empty() && self.arrows.is_empty()
+    }
+
+    /// Adds the square highlighted in `color`, or removes it if it is already highlighted in
+    /// that color, as clicking it does on Lichess.
+    pub fn toggle_square(&mut self, highlight: (Color, u8)) {
+        match self
+            .squares
+            .iter()
+            .position(|&(_, square)| square == highlight.1)
+        {
+            Some(index) if self.squares[index] == highlight => {
+                self.squares.remove(index);
+            }
+            Some(index) => self.squares[index] = highlight,
+            None => self.squares.push(highlight),
+        }
+    }
+
+    /// Adds the arrow, or removes it if it is already drawn in that color.
+    pub fn toggle_arrow(&mut self, arrow: (Color, u8, u8)) {
+        let same_squares = |&(_, from, to): &(Color, u8, u8)| (from, to) == (arrow.1, arrow.2);
+        match self.arrows.iter().position(same_squares) {
+            Some(index) if self.arrows[index] == arrow => {
+                self.arrows.remove(index);
+            }
+            Some(index) => self.arrows[index] = arrow,
+            None => self.arrows.push(arrow),
+        }
+    }
+
+    /// The annotations as `[%csl]` and `[%cal]` commands, for a PGN comment.
+    pub fn commands(&self) -> String {
+        let mut commands = Vec::new();
+        if !self.squares.is_empty() {
+            let squares = self
+                .squares
+                .iter()
+                .map(|&(color, square)| format!("{}{}", color.letter(), square_name(square)))
+                .collect::<Vec<String>>();
+            commands.push(format!("[%csl {}]", squares.join(",")));
+        }
+        if !self.arrows.is_empty() {
+            let arrows = self
+                .arrows
+                .iter()
+                .map(|&(color, from, to)| {
+                    format!("{}{}{}", color.letter(), square_name(from), square_name(to))
+                })
+                .collect::<Vec<String>>();
+            commands.push(format!("[%cal {}]", arrows.join(",")));
+        }
+        commands.join(" ")
+    }
+
+    /// The annotations in words, as in `Arrows: green e2-e4. Squares: red d4.`
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.arrows.is_empty() {
+            let arrows = self
+                .arrows
+                .iter()
+                .map(|&(color, from, to)| {
+                    format!("{} {}-{}", color.name(), square_name(from), square_name(to))
+                })
+                .collect::<Vec<String>>();
+            parts.push(format!("Arrows: {}.", arrows.join(", ")));
+        }
+        if !self.squares.is_empty() {
+            let squares = self
+                .squares
+                .iter()
+                .map(|&(color, square)| format!("{} {}", color.name(), square_name(square)))
+                .collect::<Vec<String>>();
+            parts.push(format!("Squares: {}.", squares.join(", ")));
+        }
+        parts.join(" ")
+    }
+}
+
+/// Takes the `[%csl]` and `[%cal]` commands out of a PGN comment, returning what they annotate
+/// and the rest of the comment. Other commands, such as `[%clk]`, and any that can't be read are
+/// left in the comment.
+pub fn extract(comment: &str) -> (Annotations, String) {
+    let mut annotations = Annotations::default();
+    let mut rest = String::new();
+    let mut text = comment;
+    while let Some(start) = text.find("[%") {
+        let Some(length) = text[start..].find(']') else {
+            break;
+        };
+        let command = &text[start + 2..start + length];
+        let (name, arguments) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let items = arguments
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty());
+        let read = match name {
+            "csl" => items
+                .map(parse_highlight)
+                .collect::<Option<Vec<_>>>()
+                .map(|squares| annotations.squares.extend(squares)),
+            "cal" => items
+                .map(parse_arrow)
+                .collect::<Option<Vec<_>>>()
+                .map(|arrows| annotations.arrows.extend(arrows)),
+            _ => None,
+        };
+        rest.push_str(&text[..start]);
+        if read.is_none() {
+            rest.push_str(&text[start..=start + length]);
+        }
+        rest.push(' ');
+        text = &text[start + length + 1..];
+    }
+    rest.push_str(text);
+    (
+        annotations,
+        rest.split_whitespace().collect::<Vec<&str>>().join(" "),
+    )
+}
+
+#[test]
+fn test_annotation() {
+    assert_eq!(parse_highlight("Rd4"), Some((Color::Red, 27)));
+    assert_eq!(parse_highlight("d4"), Some((Color::Green, 27)));
+    assert_eq!(parse_arrow("Be2e4"), Some((Color::Blue, 12, 28)));
+    assert_eq!(parse_arrow("Xe2e4"), None);
+    assert_eq!(parse_arrow("e2e4q"), None);
+
+    let (annotations, rest) =
+        extract("Good move [%csl Rd4,Ge5][%cal Ge2e4, Yd1h5] [%clk 0:01:00] [%cal Qz9]");
+    assert_eq!(
+        annotations.squares,
+        vec![(Color::Red, 27), (Color::Green, 36)]
+    );
+    assert_eq!(
+        annotations.arrows,
+        vec![(Color::Green, 12, 28), (Color::Yellow, 3, 39)]
+    );
+    assert_eq!(rest, "Good move [%clk 0:01:00] [%cal Qz9]");
+    assert_eq!(annotations.commands(), "[%csl Rd4,Ge5] [%cal Ge2e4,Yd1h5]");
+    assert_eq!(
+        extract(&annotations.commands()),
+        (annotations.clone(), String::new())
+    );
+    assert_eq!(
+        annotations.describe(),
+        "Arrows: green e2-e4, yellow d1-h5. Squares: red d4, green e5."
+    );
+
+    // Toggling works as clicking does: the same color again removes, another color replaces.
+    let mut annotations = Annotations::default();
+    annotations.toggle_square((Color::Red, 27));
+    annotations.toggle_square((Color::Blue, 27));
+    assert_eq!(annotations.squares, vec![(Color::Blue, 27)]);
+    annotations.toggle_square((Color::Blue, 27));
+    annotations.toggle_arrow((Color::Green, 12, 28));
+    annotations.toggle_arrow((Color::Red, 12, 28));
+    assert_eq!(annotations.arrows, vec![(Color::Red, 12, 28)]);
+    annotations.toggle_arrow((Color::Red, 12, 28));
+    assert!(annotations.is_empty());
+}
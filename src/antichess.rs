@@ -0,0 +1,121 @@
+//! Antichess (losing chess): whoever loses all their pieces, or has no move, wins. Capturing is
+//! compulsory, the king is an ordinary piece that can be captured and never is in check, pawns
+//! may also promote to a king, and there is no castling.
+
+use crate::movegen::{Move, MoveList, MoveRejection};
+use crate::variant::Variant;
+use crate::{ChessBoard, BLACK, EMPTY, KING, QUEEN, WHITE};
+
+pub struct Antichess;
+
+impl Variant for Antichess {
+    fn name(&self) -> &'static str {
+        "antichess"
+    }
+
+    fn pgn_name(&self) -> &'static str {
+        "Antichess"
+    }
+
+    fn start(&self) -> ChessBoard {
+        let mut board = ChessBoard::new();
+        board.castling = 0;
+        board
+    }
+
+    fn has_royal_king(&self) -> bool {
+        false
+    }
+
+    /// Takes away castling, adds promotions to a king and, when there is a capture, takes away
+    /// every move that isn't one.
+    fn adjust_moves(&self, board: &ChessBoard, moves: &mut MoveList) {
+        moves.retain(|&mv| !board.is_castling(mv));
+        let kings = moves
+            .iter()
+            .filter(|mv| mv.promotion == QUEEN)
+            .map(|&mv| Move {
+                promotion: KING,
+                ..mv
+            })
+            .collect::<Vec<Move>>();
+        moves.extend(kings);
+        if moves.iter().any(|&mv| board.is_capture(mv)) {
+            moves.retain(|&mv| board.is_capture(mv));
+        }
+    }
+
+    fn is_in_check(&self, _board: &ChessBoard, _color: u8) -> bool {
+        false
+    }
+
+    fn is_legal(&self, _before: &ChessBoard, _after: &ChessBoard) -> bool {
+        true
+    }
+
+    fn rejection(&self, board: &ChessBoard, mv: Move) -> Option<MoveRejection> {
+        let can_capture = board
+            .pseudo_legal_moves()
+            .into_iter()
+            .any(|other| board.is_capture(other));
+        match can_capture && !board.is_capture(mv) {
+            true => Some(MoveRejection::MustCapture),
+            false => None,
+        }
+    }
+
+    /// The side to move wins once it has no move, whether or not it has pieces left.
+    fn outcome(&self, board: &ChessBoard, moves: &[Move]) -> Option<(&'static str, &'static str)> {
+        if !moves.is_empty() {
+            return None;
+        }
+        let side = board.side_to_move();
+        let result = if side == WHITE { "1-0" } else { "0-1" };
+        let has_pieces = (0..64).any(|square| {
+            let piece = board.get_piece(square);
+            piece != EMPTY && piece & BLACK == side
+        });
+        Some((result, if has_pieces { "stalemate" } else { "no_pieces" }))
+    }
+
+    /// Having fewer pieces than the other side is good.
+    fn evaluate(&self, board: &ChessBoard) -> i32 {
+        let side = board.side_to_move();
+        (0..64)
+            .map(|square| board.get_piece(square))
+            .filter(|&piece| piece != EMPTY)
+            .map(|piece| if piece & BLACK == side { -100 } else { 100 })
+            .sum()
+    }
+}
+
+#[test]
+fn test_antichess() {
+    use crate::variant::by_name;
+
+    let antichess = by_name("antichess").unwrap();
+    let mut board = ChessBoard::start_of(antichess);
+    assert_eq!(board.perft(3), 8067);
+    assert_eq!(
+        board.fen(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1"
+    );
+
+    // Captures are compulsory, and the king can be captured like any other piece.
+    board.apply_move(board.parse_san("e4").unwrap());
+    board.apply_move(board.parse_san("d5").unwrap());
+    assert_eq!(board.legal_sans(), ["exd5"]);
+    assert_eq!(board.parse_san("Nf3"), Err(MoveRejection::MustCapture));
+    let board = ChessBoard::from_variant_fen("8/8/8/8/8/8/3k4/4K3 w - - 0 1", antichess).unwrap();
+    assert!(!board.is_in_check(WHITE));
+    assert_eq!(board.legal_sans(), ["Kxd2"]);
+
+    // Pawns promote to kings too, and a side with no pieces or no moves wins.
+    let board = ChessBoard::from_variant_fen("8/P7/8/8/8/8/8/7k w - - 0 1", antichess).unwrap();
+    assert_eq!(board.legal_sans(), ["a8=Q", "a8=R", "a8=B", "a8=N", "a8=K"]);
+    let board = ChessBoard::from_variant_fen("8/8/8/8/8/8/8/7K b - - 0 1", antichess).unwrap();
+    assert_eq!(board.outcome(), Some(("0-1", "no_pieces")));
+    let board = ChessBoard::from_variant_fen("8/8/8/8/8/p7/P7/7K b - - 0 1", antichess).unwrap();
+    assert_eq!(board.outcome(), Some(("0-1", "stalemate")));
+    assert!(ChessBoard::from_fen("8/8/8/8/8/8/8/7K b - - 0 1").is_err());
+}
@@ -0,0 +1,54 @@
+//! Armageddon, the tiebreak game that can't end in a draw: white gets more time on the clock, and
+//! black wins if the game is drawn.
+
+use crate::ChessBoard;
+
+/// White's starting time in minutes when none is given, against black's four.
+pub const DEFAULT_MINUTES: u32 = 5;
+
+/// The starting clocks of white and black in milliseconds when white starts with `time_ms`.
+/// Black gets four fifths of it, as in the usual five minutes against four.
+pub fn clocks(time_ms: u32) -> [u32; 2] {
+    [time_ms, (time_ms as u64 * 4 / 5) as u32]
+}
+
+/// Announces a drawn game, adding that black wins it when the game is played as Armageddon.
+pub fn announce_draw(announcement: &str, armageddon: bool) -> String {
+    match armageddon {
+        true => format!("{} Black wins the Armageddon game.", announcement),
+        false => announcement.to_string(),
+    }
+}
+
+/// Says how the game on `board` ended once it is over, adding that black wins a draw when the
+/// game is played as Armageddon.
+pub fn announce_outcome(board: &ChessBoard, armageddon: bool) -> Option<String> {
+    let description = board.describe_outcome()?;
+    Some(match board.outcome() {
+        Some(("1/2-1/2", _)) => announce_draw(&description, armageddon),
+        _ => description,
+    })
+}
+
+#[test]
+fn test_armageddon() {
+    assert_eq!(clocks(DEFAULT_MINUTES * 60_000), [300_000, 240_000]);
+    assert_eq!(clocks(u32::MAX)[1], 3_435_973_836);
+    assert_eq!(announce_draw("Draw agreed.", false), "Draw agreed.");
+    assert_eq!(
+        announce_draw("Stalemate!", true),
+        "Stalemate! Black wins the Armageddon game."
+    );
+
+    let stalemate = ChessBoard::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(
+        announce_outcome(&stalemate, true).unwrap(),
+        "Stalemate. The game is drawn. Black wins the Armageddon game."
+    );
+    let mate = ChessBoard::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(
+        announce_outcome(&mate, true).unwrap(),
+        "Checkmate. White wins."
+    );
+    assert_eq!(announce_outcome(&ChessBoard::new(), false), None);
+}
@@ -0,0 +1,122 @@
+//! Atomic chess: every capture is an explosion that removes the captured piece, the capturing
+//! piece and every piece but a pawn next to them. Kings can't capture, a move may not explode
+//! the mover's own king, and exploding the other king wins. Kings standing next to each other
+//! can't be checked, since neither may be captured without exploding both.
+
+use crate::movegen::{adjacent_squares, Move, MoveList, CASTLING_RIGHTS};
+use crate::variant::{Standard, Variant};
+use crate::{ChessBoard, BLACK, EMPTY, GET_NUM, KING, PAWN, ROOK, WHITE};
+
+pub struct Atomic;
+
+impl Variant for Atomic {
+    fn name(&self) -> &'static str {
+        "atomic"
+    }
+
+    fn pgn_name(&self) -> &'static str {
+        "Atomic"
+    }
+
+    fn start(&self) -> ChessBoard {
+        ChessBoard::new()
+    }
+
+    /// Takes away king captures.
+    fn adjust_moves(&self, board: &ChessBoard, moves: &mut MoveList) {
+        moves.retain(|&mv| {
+            GET_NUM(board.get_piece(mv.from)) != KING
+                || board.get_piece(mv.to) == EMPTY
+                || board.is_castling(mv)
+        });
+    }
+
+    fn is_in_check(&self, board: &ChessBoard, color: u8) -> bool {
+        match (board.king_square(color), board.king_square(color ^ BLACK)) {
+            (Some(king), Some(other)) if adjacent_squares(king).any(|s| s == other) => false,
+            _ => board.is_king_attacked(color),
+        }
+    }
+
+    /// A move must keep the mover's king on the board, and out of check unless it explodes the
+    /// other king.
+    fn is_legal(&self, before: &ChessBoard, after: &ChessBoard) -> bool {
+        let side = before.side_to_move();
+        after.king_square(side).is_some()
+            && (after.king_square(side ^ BLACK).is_none() || !after.is_in_check(side))
+    }
+
+    fn after_move(&self, before: &ChessBoard, mv: Move, after: &mut ChessBoard) {
+        if !before.is_capture(mv) {
+            return;
+        }
+        after.set_piece(mv.to, EMPTY);
+        for square in adjacent_squares(mv.to) {
+            let piece = after.get_piece(square);
+            if piece != EMPTY && GET_NUM(piece) != PAWN {
+                after.set_piece(square, EMPTY);
+            }
+        }
+        // Exploded rooks and kings take their castling rights with them.
+        for (index, &rook) in after.castling_rooks.iter().enumerate() {
+            let color = if index < 2 { WHITE } else { BLACK };
+            if after.get_piece(rook) != ROOK | color || after.king_square(color).is_none() {
+                after.castling &= !CASTLING_RIGHTS[index];
+            }
+        }
+    }
+
+    fn outcome(&self, board: &ChessBoard, moves: &[Move]) -> Option<(&'static str, &'static str)> {
+        match (board.king_square(WHITE), board.king_square(BLACK)) {
+            (None, _) => Some(("0-1", "explosion")),
+            (_, None) => Some(("1-0", "explosion")),
+            _ => Standard.outcome(board, moves),
+        }
+    }
+}
+
+#[test]
+fn test_atomic() {
+    use crate::variant::by_name;
+
+    let atomic = by_name("atomic").unwrap();
+    let board = ChessBoard::start_of(atomic);
+    assert_eq!(board.perft(4), 197326);
+
+    // Capturing on d5 explodes the knights and bishop around it, but not the pawns.
+    let fen = "rnbqkbnr/ppp1pppp/8/3p4/4P3/2N5/PPPP1PPP/R1BQKBNR w KQkq - 0 2";
+    let mut board = ChessBoard::from_fen(fen).unwrap().with_variant(atomic);
+    board.apply_move(board.parse_san("exd5").unwrap());
+    assert_eq!(
+        board.fen(),
+        "rnbqkbnr/ppp1pppp/8/8/8/2N5/PPPP1PPP/R1BQKBNR b KQkq - 0 2"
+    );
+    let mut board = ChessBoard::from_fen(fen).unwrap().with_variant(atomic);
+    board.apply_move(board.parse_san("Nxd5").unwrap());
+    assert_eq!(
+        board.fen(),
+        "rnbqkbnr/ppp1pppp/8/8/4P3/8/PPPP1PPP/R1BQKBNR b KQkq - 0 2"
+    );
+
+    // Exploding the king next to the target wins at once, even with the own king in check.
+    let board = ChessBoard::from_fen("4k3/3p4/8/8/8/8/8/3QK2r w - - 0 1")
+        .unwrap()
+        .with_variant(atomic);
+    assert!(board.is_in_check(WHITE));
+    let mv = board.parse_san("Qxd7#").unwrap();
+    let mut exploded = board;
+    exploded.apply_move(mv);
+    assert_eq!(exploded.outcome(), Some(("1-0", "explosion")));
+    assert_eq!(board.search(1).best_move, Some(mv));
+
+    // Kings can't capture, can't be checked while touching, and can't explode themselves.
+    let board = ChessBoard::from_fen("8/8/8/8/8/3k4/3r4/4K3 w - - 0 1")
+        .unwrap()
+        .with_variant(atomic);
+    assert!(!board.is_in_check(WHITE));
+    assert!(board.parse_san("Kxd2").is_err());
+    let board = ChessBoard::from_fen("4k3/8/8/8/8/8/3p4/2Q1K3 w - - 0 1")
+        .unwrap()
+        .with_variant(atomic);
+    assert!(board.parse_san("Qxd2").is_err());
+}
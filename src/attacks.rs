@@ -0,0 +1,205 @@
+//! The squares rooks and bishops attack, as bitboards (bits `1 << square`), given the squares
+//! occupied. On x86-64 processors with BMI2 they are looked up in tables indexed with `pext`,
+//! which gathers the occupied squares on a piece's lines into a small index; elsewhere, and on
+//! processors without it, the lines are walked square by square. Which is used is decided when
+//! the program runs, so one build is fast on new processors and still runs on old ones.
+//!
+//! Some AMD processors before Zen 3 have `pext` but run it slowly, in microcode, so there the
+//! tables are slower than walking. They are still used, since telling those processors apart
+//! would mean keeping a list of models.
+
+use std::sync::OnceLock;
+
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+
+/// Returns the square `(files, ranks)` away from `square`, if it is still on the board.
+fn offset(square: u8, (files, ranks): (i8, i8)) -> Option<u8> {
+    let file = (square & 7) as i8 + files;
+    let rank = (square >> 3) as i8 + ranks;
+    match (0..8).contains(&file) && (0..8).contains(&rank) {
+        true => Some((rank * 8 + file) as u8),
+        false => None,
+    }
+}
+
+/// The squares a piece on `square` moving along `directions` attacks, walking each line until
+/// it reaches an occupied square or the edge.
+fn walk(square: u8, occupied: u64, directions: &[(i8, i8)]) -> u64 {
+    let mut attacks = 0;
+    for &direction in directions {
+        let mut current = square;
+        while let Some(next) = offset(current, direction) {
+            attacks |= 1 << next;
+            if occupied & 1 << next != 0 {
+                break;
+            }
+            current = next;
+        }
+    }
+    attacks
+}
+
+/// The squares along `directions` whose occupant can change what a piece on `square` attacks:
+/// all of the lines but the square at the edge, which is attacked whatever stands on it.
+fn relevant(square: u8, directions: &[(i8, i8)]) -> u64 {
+    let mut mask = 0;
+    for &direction in directions {
+        let mut current = square;
+        while let Some(next) = offset(current, direction) {
+            if offset(next, direction).is_none() {
+                break;
+            }
+            mask |= 1 << next;
+            current = next;
+        }
+    }
+    mask
+}
+
+/// Gathers the bits of `bits` under `mask` into the low bits, in order, as `pext` does.
+fn pext(bits: u64, mut mask: u64) -> u64 {
+    let mut gathered = 0;
+    let mut bit = 1;
+    while mask != 0 {
+        if bits & mask & mask.wrapping_neg() != 0 {
+            gathered |= bit;
+        }
+        mask &= mask - 1;
+        bit <<= 1;
+    }
+    gathered
+}
+
+/// The attacks of one kind of slider from every square, for every occupancy of its lines.
+struct Table {
+    masks: [u64; 64],
+    /// Where each square's attacks start in `attacks`.
+    offsets: [usize; 64],
+    attacks: Vec<u64>,
+}
+
+impl Table {
+    fn new(directions: &[(i8, i8)]) -> Table {
+        let mut table = Table {
+            masks: [0; 64],
+            offsets: [0; 64],
+            attacks: Vec::new(),
+        };
+        for square in 0..64 {
+            let mask = relevant(square, directions);
+            table.masks[square as usize] = mask;
+            table.offsets[square as usize] = table.attacks.len();
+            let start = table.attacks.len();
+            table.attacks.resize(start + (1 << mask.count_ones()), 0);
+            // Every subset of the mask, from the empty set back around to it.
+            let mut occupied = 0u64;
+            loop {
+                table.attacks[start + pext(occupied, mask) as usize] =
+                    walk(square, occupied, directions);
+                occupied = occupied.wrapping_sub(mask) & mask;
+                if occupied == 0 {
+                    break;
+                }
+            }
+        }
+        table
+    }
+}
+
+/// The rook and bishop tables, built the first time they are needed.
+fn tables() -> &'static (Table, Table) {
+    static TABLES: OnceLock<(Table, Table)> = OnceLock::new();
+    TABLES.get_or_init(|| (Table::new(&ROOK_DIRECTIONS), Table::new(&BISHOP_DIRECTIONS)))
+}
+
+/// Looks up the attacks from `square` in `table` with the processor's `pext`.
+///
+/// # Safety
+///
+/// The processor must support BMI2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+unsafe fn lookup(table: &Table, square: u8, occupied: u64) -> u64 {
+    let index = std::arch::x86_64::_pext_u64(occupied, table.masks[square as usize]);
+    table.attacks[table.offsets[square as usize] + index as usize]
+}
+
+/// Returns `true` if the processor has BMI2, so the tables can be used.
+fn has_bmi2() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    return std::arch::is_x86_feature_detected!("bmi2");
+    #[cfg(not(target_arch = "x86_64"))]
+    return false;
+}
+
+/// The squares a rook on `square` attacks, whoever stands on them.
+pub fn rook_attacks(square: u8, occupied: u64) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    if has_bmi2() {
+        // Safe, as the processor has BMI2.
+        return unsafe { lookup(&tables().0, square, occupied) };
+    }
+    walk(square, occupied, &ROOK_DIRECTIONS)
+}
+
+/// The squares a bishop on `square` attacks, whoever stands on them.
+pub fn bishop_attacks(square: u8, occupied: u64) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    if has_bmi2() {
+        // Safe, as the processor has BMI2.
+        return unsafe { lookup(&tables().1, square, occupied) };
+    }
+    walk(square, occupied, &BISHOP_DIRECTIONS)
+}
+
+/// The name of the way attacks are found on this processor, for `bench` to report.
+pub fn method() -> &'static str {
+    match has_bmi2() {
+        true => "pext",
+        false => "walking",
+    }
+}
+
+#[test]
+fn test_attacks() {
+    assert_eq!(pext(0b1011_0110, 0b0101_0100), 0b011);
+    assert_eq!(relevant(0, &ROOK_DIRECTIONS).count_ones(), 12);
+    assert_eq!(relevant(27, &BISHOP_DIRECTIONS).count_ones(), 9);
+    let (rooks, bishops) = tables();
+    assert_eq!(rooks.attacks.len(), 102_400);
+    assert_eq!(bishops.attacks.len(), 5_248);
+
+    // The tables, the processor's way and walking all agree, on a spread of occupancies.
+    let soft = |table: &Table, square: u8, occupied: u64| {
+        let index = pext(occupied, table.masks[square as usize]) as usize;
+        table.attacks[table.offsets[square as usize] + index]
+    };
+    let mut occupied = 0x9e37_79b9_7f4a_7c15u64;
+    for _ in 0..200 {
+        occupied ^= occupied << 13;
+        occupied ^= occupied >> 7;
+        occupied ^= occupied << 17;
+        for square in 0..64 {
+            let sparse = occupied & occupied.rotate_left(11);
+            for occupied in [occupied, sparse, 0] {
+                let rook = walk(square, occupied, &ROOK_DIRECTIONS);
+                let bishop = walk(square, occupied, &BISHOP_DIRECTIONS);
+                assert_eq!(soft(rooks, square, occupied), rook);
+                assert_eq!(soft(bishops, square, occupied), bishop);
+                assert_eq!(rook_attacks(square, occupied), rook);
+                assert_eq!(bishop_attacks(square, occupied), bishop);
+            }
+        }
+    }
+    // A rook on d4 behind a piece on d6, and a bishop on c1 behind one on e3.
+    assert_eq!(
+        rook_attacks(27, 1 << 43),
+        walk(27, 1 << 43, &ROOK_DIRECTIONS)
+    );
+    assert_eq!(rook_attacks(27, 1 << 43) & 1 << 51, 0);
+    assert_eq!(
+        bishop_attacks(2, 1 << 20),
+        1 << 9 | 1 << 16 | 1 << 11 | 1 << 20
+    );
+}
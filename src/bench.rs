@@ -0,0 +1,236 @@
+//! Bench: searches a fixed set of positions to a fixed depth and counts the positions visited.
+//! The total is a signature of the search: a change that isn't meant to change what the search
+//! does (a refactor, a speedup) should leave it alone, and the time it took gives the speed.
+//!
+//! It can instead time one of the primitives the search is built on, over the same positions:
+//! generating moves, evaluating, or making and unmaking every move. As Criterion does, it warms
+//! up first and then times several samples, giving the median and the spread, so a regression
+//! shows without reaching for other tools.
+
+use std::io::Error;
+use std::time::Instant;
+
+use crate::eval::evaluate;
+use crate::search::SEARCH_HASH_MB;
+use crate::tt::{Strategy, TranspositionTable};
+use crate::{attacks, ChessBoard};
+
+/// The positions searched: openings, middlegames with tactics and every kind of special move,
+/// and endgames.
+const POSITIONS: [&str; 12] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "rnbqkb1r/pp2pppp/3p1n2/8/3NP3/8/PPP2PPP/RNBQKB1R w KQkq - 1 5",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "2r3k1/pp3ppp/2n1b3/q2pP3/3P4/P1PB1N2/5PPP/R2Q1RK1 b - - 0 18",
+    "r1b2rk1/2q1bppp/p2ppn2/1p6/3BPP2/2NB4/PPP1Q1PP/2KR3R w - - 0 13",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "8/8/4k3/3p4/3P4/4K3/8/8 w - - 0 1",
+    "6k1/5pp1/7p/8/8/1r5P/5PP1/3R2K1 w - - 0 40",
+    "8/5pk1/6p1/3Q3p/7P/6P1/2q2PK1/8 b - - 3 50",
+];
+
+/// Searches every position to `depth` with a fresh search on `threads` threads, sharing a table
+/// by `strategy` as big as `search` gives itself, returning the positions visited in each. On
+/// one thread the counts are the same whatever the strategy.
+fn bench(depth: u32, threads: usize, strategy: Strategy) -> Vec<u64> {
+    POSITIONS
+        .iter()
+        .map(|fen| {
+            let table = TranspositionTable::with_strategy(SEARCH_HASH_MB, strategy);
+            let board = ChessBoard::from_fen(fen).unwrap();
+            board
+                .search_threads(depth, u64::MAX, None, threads, &table, &[])
+                .nodes
+        })
+        .collect()
+}
+
+/// How many times `generate` generates the moves of each position, enough to time.
+const GENERATIONS: usize = 20_000;
+
+/// Generates the legal moves of every position `rounds` times, returning how many moves it
+/// generated, to time the move generator apart from the rest of the search.
+fn generate(rounds: usize) -> u64 {
+    let boards = POSITIONS.map(|fen| ChessBoard::from_fen(fen).unwrap());
+    let mut moves = 0;
+    for _ in 0..rounds {
+        for board in &boards {
+            moves += std::hint::black_box(board).legal_moves().len() as u64;
+        }
+    }
+    moves
+}
+
+/// A primitive of the search that `bench` can time alone.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Primitive {
+    /// Generating the legal moves of a position.
+    Movegen,
+    /// Evaluating a position.
+    Eval,
+    /// Making a move and unmaking it.
+    MakeUnmake,
+}
+
+impl Primitive {
+    /// The primitive called `name` on the command line.
+    pub fn by_name(name: &str) -> Option<Primitive> {
+        match name {
+            "movegen" => Some(Primitive::Movegen),
+            "eval" => Some(Primitive::Eval),
+            "makeunmake" => Some(Primitive::MakeUnmake),
+            _ => None,
+        }
+    }
+
+    /// What one operation of the primitive does, to label its speed.
+    fn operation(self) -> &'static str {
+        match self {
+            Primitive::Movegen => "Move generations",
+            Primitive::Eval => "Evaluations",
+            Primitive::MakeUnmake => "Moves made and unmade",
+        }
+    }
+
+    /// Does the primitive `rounds` times over every position, returning how many operations
+    /// that was.
+    fn perform(self, rounds: usize) -> u64 {
+        let boards = POSITIONS.map(|fen| ChessBoard::from_fen(fen).unwrap());
+        match self {
+            Primitive::Movegen => {
+                generate(rounds);
+                (rounds * boards.len()) as u64
+            }
+            Primitive::Eval => {
+                for _ in 0..rounds {
+                    for board in &boards {
+                        std::hint::black_box(evaluate(std::hint::black_box(board)));
+                    }
+                }
+                (rounds * boards.len()) as u64
+            }
+            Primitive::MakeUnmake => {
+                let moves = boards.map(|board| board.legal_moves());
+                let mut count = 0;
+                for _ in 0..rounds {
+                    for (board, moves) in boards.iter().zip(&moves) {
+                        let mut board = *board;
+                        for &mv in moves {
+                            let undo = board.make_move(mv);
+                            std::hint::black_box(&board);
+                            board.unmake_move(undo);
+                        }
+                        count += moves.len() as u64;
+                    }
+                }
+                count
+            }
+        }
+    }
+}
+
+/// How many samples a primitive is timed over.
+const SAMPLES: usize = 10;
+
+/// How long to spend warming up, and roughly how long each sample takes, in seconds.
+const SAMPLE_SECONDS: f64 = 0.2;
+
+/// Times `primitive`, returning the operations per second of each sample, slowest first.
+fn sample(primitive: Primitive, samples: usize, seconds: f64) -> Vec<f64> {
+    // Warm up, doubling the rounds until they take long enough to time well.
+    let mut rounds = 1;
+    loop {
+        let started = Instant::now();
+        primitive.perform(rounds);
+        if started.elapsed().as_secs_f64() >= seconds / 2.0 {
+            break;
+        }
+        rounds *= 2;
+    }
+    let mut speeds = (0..samples)
+        .map(|_| {
+            let started = Instant::now();
+            let operations = primitive.perform(rounds);
+            operations as f64 / started.elapsed().as_secs_f64().max(1e-9)
+        })
+        .collect::<Vec<f64>>();
+    speeds.sort_by(f64::total_cmp);
+    speeds
+}
+
+/// Runs `bench` on `primitive`: prints how many operations per second it managed, the median of
+/// the samples and the slowest and fastest.
+pub fn run_primitive(primitive: Primitive) -> Result<(), Error> {
+    let speeds = sample(primitive, SAMPLES, SAMPLE_SECONDS);
+    println!(
+        "{}: {:.0} per second (from {:.0} to {:.0} over {} samples)",
+        primitive.operation(),
+        speeds[speeds.len() / 2],
+        speeds[0],
+        speeds[speeds.len() - 1],
+        speeds.len()
+    );
+    Ok(())
+}
+
+/// Runs `bench`: prints the positions visited in each search to `depth` on `threads` threads,
+/// their total and how fast they were searched.
+pub fn run(depth: u32, threads: usize, strategy: Strategy) -> Result<(), Error> {
+    let started = Instant::now();
+    let nodes = bench(depth, threads, strategy);
+    let elapsed = started.elapsed().as_secs_f64();
+    for (index, (fen, nodes)) in POSITIONS.iter().zip(&nodes).enumerate() {
+        println!("Position {:>2}: {:>9} nodes  {}", index + 1, nodes, fen);
+    }
+    let total = nodes.iter().sum::<u64>();
+    println!();
+    println!("Nodes: {}", total);
+    println!(
+        "Time: {:.3}s ({:.0} nodes per second)",
+        elapsed,
+        total as f64 / elapsed.max(1e-9)
+    );
+
+    let started = Instant::now();
+    let moves = generate(GENERATIONS);
+    let elapsed = started.elapsed().as_secs_f64();
+    println!("Slider attacks: by {}", attacks::method());
+    println!(
+        "Move generation: {} positions in {:.3}s ({:.0} positions per second)",
+        GENERATIONS * POSITIONS.len(),
+        elapsed,
+        (GENERATIONS * POSITIONS.len()) as f64 / elapsed.max(1e-9)
+    );
+    debug_assert_eq!(moves, generate(1) * GENERATIONS as u64);
+    Ok(())
+}
+
+#[test]
+fn test_bench() {
+    let nodes = bench(2, 1, Strategy::Lockless);
+    assert_eq!(nodes.len(), POSITIONS.len());
+    assert!(nodes.iter().all(|&nodes| nodes > 0));
+    assert_eq!(bench(2, 1, Strategy::Lockless), nodes);
+    assert_eq!(bench(2, 1, Strategy::Locked), nodes);
+    for strategy in [Strategy::Lockless, Strategy::Locked] {
+        assert!(bench(2, 3, strategy).iter().all(|&nodes| nodes > 0));
+    }
+    assert_eq!(generate(2), generate(1) * 2);
+
+    assert_eq!(
+        Primitive::by_name("makeunmake"),
+        Some(Primitive::MakeUnmake)
+    );
+    assert_eq!(Primitive::Eval.perform(3), 3 * POSITIONS.len() as u64);
+    let moves = POSITIONS
+        .iter()
+        .map(|fen| ChessBoard::from_fen(fen).unwrap().legal_moves().len() as u64)
+        .sum::<u64>();
+    assert_eq!(Primitive::MakeUnmake.perform(2), 2 * moves);
+    let speeds = sample(Primitive::Movegen, 3, 0.001);
+    assert_eq!(speeds.len(), 3);
+    assert!(speeds[0] > 0.0 && speeds[0] <= speeds[2]);
+}
@@ -0,0 +1,75 @@
+//! Bughouse: two games of Crazyhouse played side by side by two teams of two. A captured piece
+//! goes into the hand of the capturer's partner, who plays the other color on the other board, so
+//! a single board only knows about the drops. Linking the boards is up to the server hosting
+//! them (see `net::ws`).
+
+use crate::movegen::{Move, MoveList};
+use crate::variant::Variant;
+use crate::{ChessBoard, BLACK, GET_NUM};
+
+pub struct Bughouse;
+
+/// Bughouse isn't among the variants that can be played on a single board, so it is reached
+/// through this instead of `variant::by_name`.
+pub static BUGHOUSE: Bughouse = Bughouse;
+
+impl Variant for Bughouse {
+    fn name(&self) -> &'static str {
+        "bughouse"
+    }
+
+    fn pgn_name(&self) -> &'static str {
+        "Bughouse"
+    }
+
+    fn start(&self) -> ChessBoard {
+        ChessBoard::new()
+    }
+
+    fn has_pockets(&self) -> bool {
+        true
+    }
+
+    fn adjust_moves(&self, board: &ChessBoard, moves: &mut MoveList) {
+        board.add_drops(moves);
+    }
+
+    /// Captured pieces leave this board: they go to the partner, whose board is out of reach.
+    fn after_move(&self, before: &ChessBoard, mv: Move, after: &mut ChessBoard) {
+        after.update_hands(before, mv);
+    }
+}
+
+impl ChessBoard {
+    /// Puts `piece`, captured on the partner board, into the hand of the side of its color.
+    pub fn receive(&mut self, piece: u8) {
+        let count = &mut self.pockets[((piece & BLACK) >> 3) as usize][GET_NUM(piece) as usize];
+        *count = count.saturating_add(1);
+    }
+}
+
+#[test]
+fn test_bughouse() {
+    use crate::{KNIGHT, PAWN, SET_BLACK, WHITE};
+
+    let mut board = ChessBoard::start_of(&BUGHOUSE);
+    assert_eq!(board.perft(2), 400);
+    for san in ["e4", "d5", "exd5"] {
+        let mv = board.parse_san(san).unwrap();
+        assert_eq!(
+            board.captured_piece(mv),
+            if san == "exd5" { SET_BLACK(PAWN) } else { 0 }
+        );
+        board.apply_move(mv);
+    }
+    // The captured pawn went nowhere on this board.
+    assert_eq!(board.pockets_fen(), "");
+
+    board.receive(SET_BLACK(KNIGHT));
+    board.receive(KNIGHT | WHITE);
+    assert_eq!(board.pockets_fen(), "Nn");
+    let drop = board.parse_san("N@e3").unwrap();
+    board.apply_move(drop);
+    assert_eq!(board.pockets_fen(), "N");
+    assert!(board.fen().contains("[N]"));
+}
@@ -0,0 +1,135 @@
+//! Chess960 (Fischer Random Chess) start positions, numbered 0 to 959 as in Scharnagl's scheme:
+//! the standard position is number 518.
+
+#[cfg(feature = "cli")]
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::movegen::CASTLING_RIGHTS;
+use crate::{ChessBoard, BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK, SET_BLACK};
+
+/// How many start positions there are.
+pub const POSITIONS: u16 = 960;
+
+/// The files of the two knights among the five files left after the bishops and queen, for each
+/// remainder after dividing the position number by 96.
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// The white back rank of start position `number`, as pieces by file.
+pub fn back_rank(number: u16) -> Option<[u8; 8]> {
+    if number >= POSITIONS {
+        return None;
+    }
+    let mut rank = [0; 8];
+    let mut number = number as usize;
+    // The light-squared bishop goes on b, d, f or h, the dark-squared one on a, c, e or g.
+    rank[number % 4 * 2 + 1] = BISHOP;
+    number /= 4;
+    rank[number % 4 * 2] = BISHOP;
+    number /= 4;
+    let mut empty = (0..8)
+        .filter(|&file| rank[file] == 0)
+        .collect::<Vec<usize>>();
+    rank[empty.remove(number % 6)] = QUEEN;
+    number /= 6;
+    let (first, second) = KNIGHTS[number];
+    rank[empty[first]] = KNIGHT;
+    rank[empty[second]] = KNIGHT;
+    // The king stands between the rooks on the three files that are left.
+    let empty = (0..8)
+        .filter(|&file| rank[file] == 0)
+        .collect::<Vec<usize>>();
+    for (file, piece) in empty.into_iter().zip([ROOK, KING, ROOK]) {
+        rank[file] = piece;
+    }
+    Some(rank)
+}
+
+/// Picks a start position at random.
+pub fn random_number() -> u16 {
+    let mut bytes = [0; 2];
+    // Without randomness, the standard position is as good as any.
+    match fill_random(&mut bytes) {
+        true => u16::from_le_bytes(bytes) % POSITIONS,
+        false => 518,
+    }
+}
+
+/// Fills `bytes` with random ones, if the system has a source of them.
+#[cfg(feature = "cli")]
+fn fill_random(bytes: &mut [u8]) -> bool {
+    SystemRandom::new().fill(bytes).is_ok()
+}
+
+/// Without `ring`, there is no source of randomness to fill `bytes` from.
+#[cfg(not(feature = "cli"))]
+fn fill_random(_bytes: &mut [u8]) -> bool {
+    false
+}
+
+impl ChessBoard {
+    /// The Chess960 start position `number`, with both sides able to castle either way.
+    pub fn chess960(number: u16) -> Option<ChessBoard> {
+        let rank = back_rank(number)?;
+        let mut board = ChessBoard::new();
+        board.chess960 = true;
+        for (file, &piece) in rank.iter().enumerate() {
+            let file = file as u8;
+            board.set_piece(file, piece);
+            board.set_piece(8 + file, PAWN);
+            board.set_piece(48 + file, SET_BLACK(PAWN));
+            board.set_piece(56 + file, SET_BLACK(piece));
+        }
+        let rooks = (0..8u8)
+            .filter(|&file| rank[file as usize] == ROOK)
+            .collect::<Vec<u8>>();
+        board.castling_rooks = [rooks[1], rooks[0], 56 + rooks[1], 56 + rooks[0]];
+        board.castling = CASTLING_RIGHTS
+            .iter()
+            .fold(0, |rights, right| rights | right);
+        Some(board)
+    }
+}
+
+#[test]
+fn test_chess960() {
+    let standard = ChessBoard::chess960(518).unwrap();
+    assert_eq!(standard.fen(), ChessBoard::new().fen());
+    assert_eq!(
+        ChessBoard::chess960(0).unwrap().fen(),
+        "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+    );
+    assert_eq!(
+        ChessBoard::chess960(959).unwrap().placement(),
+        "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB"
+    );
+    assert!(ChessBoard::chess960(960).is_none());
+
+    // Every position is different, with the bishops on opposite colors and the king between
+    // the rooks.
+    let mut ranks = (0..POSITIONS)
+        .map(|number| back_rank(number).unwrap())
+        .collect::<Vec<[u8; 8]>>();
+    for rank in &ranks {
+        let files = |piece| (0..8).filter(move |&file| rank[file] == piece);
+        let bishops = files(BISHOP).collect::<Vec<usize>>();
+        assert_ne!(bishops[0] % 2, bishops[1] % 2);
+        let rooks = files(ROOK).collect::<Vec<usize>>();
+        let king = files(KING).next().unwrap();
+        assert!(rooks[0] < king && king < rooks[1]);
+    }
+    ranks.sort();
+    ranks.dedup();
+    assert_eq!(ranks.len(), POSITIONS as usize);
+    assert!(random_number() < POSITIONS);
+}
@@ -0,0 +1,166 @@
+//! How the engine behaves as a person's opponent, as a club player would: it resigns once it has
+//! been clearly lost for several moves, rather than dragging the game out to mate, and offers or
+//! accepts a draw once an endgame has been dead equal for a while.
+
+use crate::eval::PIECE_VALUES;
+use crate::{ChessBoard, BLACK, GET_NUM, PAWN};
+
+/// The most material, besides pawns, each side can have in an endgame: a rook and a minor piece.
+const ENDGAME_MATERIAL: i32 = 500 + 330;
+
+/// When the engine resigns and offers or accepts draws.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+    /// The score, in centipawns against the engine, at which it counts as lost.
+    pub resign_score: i32,
+    /// How many of its moves in a row the engine must be lost for before it resigns, or 0 to
+    /// never resign.
+    pub resign_moves: u32,
+    /// The score, either way, within which an endgame counts as dead equal.
+    pub draw_score: i32,
+    /// How many of its moves in a row an endgame must be dead equal for before the engine offers
+    /// a draw, or 0 to never offer or accept one.
+    pub draw_moves: u32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Thresholds {
+        Thresholds {
+            resign_score: 800,
+            resign_moves: 4,
+            draw_score: 15,
+            draw_moves: 8,
+        }
+    }
+}
+
+/// What the engine does besides playing its move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decision {
+    /// Plays on.
+    Play,
+    /// Resigns instead of moving.
+    Resign,
+    /// Offers a draw along with its move.
+    OfferDraw,
+}
+
+/// Returns `true` if neither side has more than a rook and a minor piece besides pawns.
+pub fn is_endgame(board: &ChessBoard) -> bool {
+    let mut material = [0; 2];
+    for square in 0..64 {
+        let piece = board.get_piece(square);
+        if GET_NUM(piece) != PAWN {
+            material[(piece & BLACK != 0) as usize] += PIECE_VALUES[GET_NUM(piece) as usize];
+        }
+    }
+    material
+        .iter()
+        .all(|&material| material <= ENDGAME_MATERIAL)
+}
+
+/// The engine's record over a game: how long it has been lost, or the endgame dead equal.
+#[derive(Clone, Debug)]
+pub struct Conduct {
+    thresholds: Thresholds,
+    /// The engine's moves in a row it has been lost for.
+    lost: u32,
+    /// The engine's moves in a row the endgame has been dead equal for.
+    level: u32,
+}
+
+impl Conduct {
+    pub fn new(thresholds: Thresholds) -> Conduct {
+        Conduct {
+            thresholds,
+            lost: 0,
+            level: 0,
+        }
+    }
+
+    /// Returns `true` if `board`, scored `score` for the engine, is a dead-equal endgame.
+    fn is_level(&self, board: &ChessBoard, score: i32) -> bool {
+        self.thresholds.draw_moves > 0
+            && score.abs() <= self.thresholds.draw_score
+            && is_endgame(board)
+    }
+
+    /// Decides what the engine does in `board`, where it is to move and its search scored
+    /// `score`. A declined draw is offered again only once the endgame has stayed level as long
+    /// again.
+    pub fn decide(&mut self, board: &ChessBoard, score: i32) -> Decision {
+        self.lost = match score <= -self.thresholds.resign_score {
+            true => self.lost + 1,
+            false => 0,
+        };
+        self.level = match self.is_level(board, score) {
+            true => self.level + 1,
+            false => 0,
+        };
+        if self.thresholds.resign_moves > 0 && self.lost >= self.thresholds.resign_moves {
+            Decision::Resign
+        } else if self.thresholds.draw_moves > 0 && self.level >= self.thresholds.draw_moves {
+            self.level = 0;
+            Decision::OfferDraw
+        } else {
+            Decision::Play
+        }
+    }
+
+    /// Returns `true` if the engine accepts a draw offered in `board`, which it scores `score`:
+    /// if the endgame is dead equal, or if it is lost.
+    pub fn accepts_draw(&self, board: &ChessBoard, score: i32) -> bool {
+        self.thresholds.draw_moves > 0
+            && (self.is_level(board, score) || score <= -self.thresholds.resign_score)
+    }
+}
+
+#[test]
+fn test_conduct() {
+    let start = ChessBoard::new();
+    let rooks = ChessBoard::from_fen("4k3/p4r2/8/8/8/8/P4R2/4K3 w - - 0 40").unwrap();
+    assert!(!is_endgame(&start));
+    assert!(is_endgame(&rooks));
+    assert!(is_endgame(
+        &ChessBoard::from_fen("4k3/pb3r2/8/8/8/8/PN3R2/4K3 w - - 0 40").unwrap()
+    ));
+
+    // Lost for four moves in a row, not counting a move that wasn't.
+    let mut conduct = Conduct::new(Thresholds::default());
+    for score in [-900, -900, -900, -100, -900, -900, -900] {
+        assert_eq!(conduct.decide(&start, score), Decision::Play);
+    }
+    assert_eq!(conduct.decide(&start, -1200), Decision::Resign);
+    let mut stubborn = Conduct::new(Thresholds {
+        resign_moves: 0,
+        ..Thresholds::default()
+    });
+    for _ in 0..20 {
+        assert_eq!(stubborn.decide(&start, -2000), Decision::Play);
+    }
+
+    // A level endgame is offered a draw after eight moves in a row, and again eight moves later.
+    let mut conduct = Conduct::new(Thresholds::default());
+    for score in [0, 0, 0, 40, 0, 0, 0, 0, 0, 0, -10] {
+        assert_eq!(conduct.decide(&rooks, score), Decision::Play);
+    }
+    assert_eq!(conduct.decide(&rooks, 15), Decision::OfferDraw);
+    for _ in 0..7 {
+        assert_eq!(conduct.decide(&rooks, 0), Decision::Play);
+    }
+    assert_eq!(conduct.decide(&rooks, 0), Decision::OfferDraw);
+    assert_eq!(conduct.decide(&start, 0), Decision::Play);
+
+    // Draws are accepted when level in an endgame or lost, and never when turned off.
+    assert!(conduct.accepts_draw(&rooks, 10));
+    assert!(!conduct.accepts_draw(&rooks, 40));
+    assert!(!conduct.accepts_draw(&start, 0));
+    assert!(!conduct.accepts_draw(&start, -300));
+    assert!(conduct.accepts_draw(&start, -900));
+    let never = Conduct::new(Thresholds {
+        draw_moves: 0,
+        ..Thresholds::default()
+    });
+    assert!(!never.accepts_draw(&rooks, 0));
+    assert!(!never.accepts_draw(&start, -900));
+}
@@ -0,0 +1,474 @@
+//! The optional configuration file. It is read from `~/.freight_chess.toml` unless `--config`
+//! names another file, and every setting in it has a default, so the file may be missing or
+//! partial.
+//!
+//! ```toml
+//! [tls]
+//! certificate = "server.crt"    # the certificate chain `serve --tls` presents (PEM)
+//! private_key = "server.key"    # its private key (PEM)
+//! ca_certificate = "ca.crt"     # a CA `connect --tls` trusts besides the usual web roots
+//!
+//! [eval]
+//! KnightMobility = 4            # the evaluation's parameters, as `tune` writes them
+//!
+//! [search]
+//! LmrBase = 75                  # the search's parameters, as `spsa` writes them
+//!
+//! [display]
+//! notation = "figurine"         # how moves are written: san, figurine, long or coordinate
+//! locale = "de"                 # whose piece letters san and long use, as in Sf3
+//! screen_reader = true          # describe the board and moves in words, as --screen-reader does
+//! theme = "solarized"           # color the board: classic, blue, high-contrast, solarized or plain
+//! cell_width = 2                # columns each square takes
+//! glyph_width = 2               # columns the terminal draws a chess glyph in, 1 or 2
+//!
+//! [play]
+//! auto_promote = "queen"        # a pawn move that doesn't say promotes to a queen, or "ask"
+//! resign_score = 800            # --opponent engine resigns when this far behind, in centipawns,
+//! resign_moves = 4              # for this many of its moves in a row (0 never resigns)
+//! draw_score = 15               # it offers a draw when an endgame is this close to equal
+//! draw_moves = 8                # for this many of its moves in a row (0 never draws)
+//!
+//! [locales]
+//! eo = "ĈKTDR"                  # the letters of a locale not built in: N, B, R, Q and K
+//!
+//! [themes]
+//! green = { light = "#eeeed2", dark = "#769656" }   # more themes, as in `theme`'s docs
+//! ```
+
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+use crate::conduct::Thresholds;
+use crate::eval::{Params, PARAM_LIMIT};
+use crate::grid::{Layout, MAX_CELL_WIDTH};
+use crate::prompt::AutoPromote;
+use crate::san::{Notation, PieceLetters};
+use crate::search::{SearchParams, MATE};
+use crate::theme::{self, Theme};
+
+/// Where the configuration file lives, relative to the home directory.
+const CONFIG_FILE: &str = ".freight_chess.toml";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    pub tls: TlsConfig,
+    /// The evaluation's parameters, for the engine to start with.
+    pub eval: Params,
+    /// The search's parameters, for the engine to start with.
+    pub search: SearchParams,
+    pub display: DisplayConfig,
+    pub play: PlayConfig,
+}
+
+/// How games are shown.
+#[derive(Debug, Default, PartialEq)]
+pub struct DisplayConfig {
+    /// How moves are written in move lists, analysis and exported PGN.
+    pub notation: Notation,
+    /// Whether the board and moves are described in words rather than drawn.
+    pub screen_reader: bool,
+    /// The colors the board is drawn in, or `None` for the plain grid.
+    pub theme: Option<Theme>,
+    /// The themes the `[themes]` section defines, by name.
+    pub themes: Vec<(String, Theme)>,
+    /// How wide the squares of the board are drawn.
+    pub layout: Layout,
+}
+
+/// How games at the prompt are played.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayConfig {
+    /// What a pawn promotes to when a move doesn't say.
+    pub auto_promote: AutoPromote,
+    /// When the engine, as an opponent, resigns and offers or accepts draws.
+    pub engine: Thresholds,
+}
+
+/// The files used by network play over TLS. Relative paths are resolved against the directory
+/// of the configuration file.
+#[derive(Debug, Default, PartialEq)]
+pub struct TlsConfig {
+    pub certificate: Option<PathBuf>,
+    pub private_key: Option<PathBuf>,
+    pub ca_certificate: Option<PathBuf>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+impl Config {
+    /// Loads the configuration from `path`, or from the default location if `path` is `None`.
+    /// Only a missing default file is not an error.
+    pub fn load(path: Option<&str>) -> Result<Config, Error> {
+        let (path, required) = match path {
+            Some(path) => (PathBuf::from(path), true),
+            None => match std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+                Some(home) => (PathBuf::from(home).join(CONFIG_FILE), false),
+                None => return Ok(Config::default()),
+            },
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound && !required => return Ok(Config::default()),
+            Err(e) => return Err(e),
+        };
+        let base = path.parent().unwrap_or(Path::new("."));
+        Config::parse(&text, base).map_err(|e| invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Parses the text of a configuration file living in the directory `base`.
+    pub fn parse(text: &str, base: &Path) -> Result<Config, Error> {
+        let table = text
+            .parse::<Table>()
+            .map_err(|e| invalid(e.message().to_string()))?;
+        let mut config = Config::default();
+        let themes = match table.get("themes") {
+            Some(Value::Table(themes)) => parse_themes(themes)?,
+            Some(_) => return Err(invalid("[themes] must be a table.".to_string())),
+            None => Vec::new(),
+        };
+        config.display.themes = themes.clone();
+        for (key, value) in &table {
+            match (key.as_str(), value) {
+                ("tls", Value::Table(tls)) => config.tls = TlsConfig::parse(tls, base)?,
+                ("tls", _) => return Err(invalid("[tls] must be a table.".to_string())),
+                ("eval", Value::Table(eval)) => config.eval = parse_params(eval)?,
+                ("eval", _) => return Err(invalid("[eval] must be a table.".to_string())),
+                ("search", Value::Table(search)) => config.search = parse_search_params(search)?,
+                ("search", _) => return Err(invalid("[search] must be a table.".to_string())),
+                ("display", Value::Table(display)) => {
+                    let locales = table.get("locales").and_then(Value::as_table);
+                    config.display = DisplayConfig::parse(display, locales, themes.clone())?
+                }
+                ("display", _) => return Err(invalid("[display] must be a table.".to_string())),
+                ("play", Value::Table(play)) => config.play = PlayConfig::parse(play)?,
+                ("play", _) => return Err(invalid("[play] must be a table.".to_string())),
+                // Read along with [display].
+                ("locales", Value::Table(_)) => {}
+                ("locales", _) => return Err(invalid("[locales] must be a table.".to_string())),
+                // Read before the rest.
+                ("themes", _) => {}
+                _ => return Err(invalid(format!("Unknown setting `{}`.", key))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Reads the value of the parameter `section.key`, which must be a whole number from `low` to
+/// `high`.
+fn whole_number(
+    section: &str,
+    key: &str,
+    value: &Value,
+    (low, high): (i32, i32),
+) -> Result<i32, Error> {
+    value
+        .as_integer()
+        .filter(|value| (low as i64..=high as i64).contains(value))
+        .map(|value| value as i32)
+        .ok_or_else(|| {
+            invalid(format!(
+                "`{}.{}` must be a whole number from {} to {}.",
+                section, key, low, high
+            ))
+        })
+}
+
+/// Reads the `[eval]` section. Parameters it doesn't mention keep their defaults.
+fn parse_params(table: &Table) -> Result<Params, Error> {
+    let mut params = Params::default();
+    for (key, value) in table {
+        let value = whole_number("eval", key, value, (-PARAM_LIMIT, PARAM_LIMIT))?;
+        if !params.set(key, value) {
+            return Err(invalid(format!("Unknown setting `eval.{}`.", key)));
+        }
+    }
+    Ok(params)
+}
+
+/// Reads the `[search]` section. Parameters it doesn't mention keep their defaults.
+fn parse_search_params(table: &Table) -> Result<SearchParams, Error> {
+    let mut params = SearchParams::default();
+    for (key, value) in table {
+        let range = SearchParams::range(key)
+            .ok_or_else(|| invalid(format!("Unknown setting `search.{}`.", key)))?;
+        params.set(key, whole_number("search", key, value, range)?);
+    }
+    Ok(params)
+}
+
+/// Writes the named parameters `params` as the `[name]` section of a configuration file.
+pub fn params_section(name: &str, params: Vec<(&str, i32)>) -> String {
+    let mut section = format!("[{}]\n", name);
+    for (name, value) in params {
+        section.push_str(&format!("{} = {}\n", name, value));
+    }
+    section
+}
+
+/// Reads the `[themes]` section, whose every entry is a table of colors.
+fn parse_themes(table: &Table) -> Result<Vec<(String, Theme)>, Error> {
+    table
+        .iter()
+        .map(|(name, value)| {
+            let colors = value
+                .as_table()
+                .ok_or_else(|| invalid(format!("`themes.{}` must be a table.", name)))?;
+            Theme::parse(colors)
+                .map(|theme| (name.clone(), theme))
+                .map_err(|e| invalid(format!("`themes.{}`: {}", name, e)))
+        })
+        .collect()
+}
+
+impl TlsConfig {
+    fn parse(table: &Table, base: &Path) -> Result<TlsConfig, Error> {
+        let mut tls = TlsConfig::default();
+        for (key, value) in table {
+            let slot = match key.as_str() {
+                "certificate" => &mut tls.certificate,
+                "private_key" => &mut tls.private_key,
+                "ca_certificate" => &mut tls.ca_certificate,
+                _ => return Err(invalid(format!("Unknown setting `tls.{}`.", key))),
+            };
+            match value.as_str() {
+                Some(path) => *slot = Some(base.join(path)),
+                None => return Err(invalid(format!("`tls.{}` must be a path.", key))),
+            }
+        }
+        Ok(tls)
+    }
+}
+
+impl PlayConfig {
+    fn parse(table: &Table) -> Result<PlayConfig, Error> {
+        let mut play = PlayConfig::default();
+        for (key, value) in table {
+            match key.as_str() {
+                "auto_promote" => {
+                    play.auto_promote =
+                        value
+                            .as_str()
+                            .and_then(AutoPromote::by_name)
+                            .ok_or_else(|| {
+                                invalid("`play.auto_promote` must be queen or ask.".to_string())
+                            })?
+                }
+                "resign_score" => {
+                    play.engine.resign_score = whole_number("play", key, value, (1, MATE))?
+                }
+                "resign_moves" => {
+                    play.engine.resign_moves = whole_number("play", key, value, (0, 100))? as u32
+                }
+                "draw_score" => {
+                    play.engine.draw_score = whole_number("play", key, value, (0, 100))?
+                }
+                "draw_moves" => {
+                    play.engine.draw_moves = whole_number("play", key, value, (0, 100))? as u32
+                }
+                _ => return Err(invalid(format!("Unknown setting `play.{}`.", key))),
+            }
+        }
+        Ok(play)
+    }
+}
+
+impl DisplayConfig {
+    /// Reads the `[display]` section, with `locales` holding the `[locales]` section's letters
+    /// and `themes` the `[themes]` section's themes.
+    fn parse(
+        table: &Table,
+        locales: Option<&Table>,
+        themes: Vec<(String, Theme)>,
+    ) -> Result<DisplayConfig, Error> {
+        let mut display = DisplayConfig {
+            themes,
+            ..DisplayConfig::default()
+        };
+        let mut letters = PieceLetters::ENGLISH;
+        let mut layout = Layout::default();
+        for (key, value) in table {
+            match key.as_str() {
+                "locale" => {
+                    let locale = value.as_str().unwrap_or_default();
+                    letters =
+                        match locales.and_then(|locales| locales.get(locale)) {
+                            Some(custom) => custom
+                                .as_str()
+                                .and_then(PieceLetters::parse)
+                                .ok_or_else(|| {
+                                    invalid(format!(
+                                    "`locales.{}` must be five capital letters for the knight, \
+                                     bishop, rook, queen and king, as in \"SLTDK\".",
+                                    locale
+                                ))
+                                })?,
+                            None => PieceLetters::by_locale(locale).ok_or_else(|| {
+                                invalid(format!(
+                                "`display.locale` names {}, which isn't known; add its letters \
+                                 to [locales].",
+                                value
+                            ))
+                            })?,
+                        }
+                }
+                "notation" => {
+                    display.notation =
+                        value.as_str().and_then(Notation::by_name).ok_or_else(|| {
+                            invalid(
+                                "`display.notation` must be san, figurine, long or coordinate."
+                                    .to_string(),
+                            )
+                        })?
+                }
+                "theme" => {
+                    display.theme = value
+                        .as_str()
+                        .and_then(|name| theme::find(name, &display.themes))
+                        .ok_or_else(|| {
+                            invalid(format!(
+                                "`display.theme` names {}, which isn't known; add it to [themes].",
+                                value
+                            ))
+                        })?
+                }
+                "cell_width" => {
+                    let range = (1, MAX_CELL_WIDTH as i32);
+                    layout.cell_width = whole_number("display", key, value, range)? as usize
+                }
+                "glyph_width" => {
+                    layout.glyph_width = whole_number("display", key, value, (1, 2))? as usize
+                }
+                "screen_reader" => {
+                    display.screen_reader = value.as_bool().ok_or_else(|| {
+                        invalid("`display.screen_reader` must be true or false.".to_string())
+                    })?
+                }
+                _ => return Err(invalid(format!("Unknown setting `display.{}`.", key))),
+            }
+        }
+        display.notation = display.notation.with_letters(letters);
+        display.layout = Layout::new(layout.cell_width, layout.glyph_width);
+        Ok(display)
+    }
+}
+
+#[test]
+fn test_config() {
+    let base = Path::new("/etc/chess");
+    assert_eq!(Config::parse("", base).unwrap(), Config::default());
+
+    let config = Config::parse(
+        "[tls]\ncertificate = \"server.crt\"\nprivate_key = \"/keys/server.key\"\n",
+        base,
+    )
+    .unwrap();
+    assert_eq!(
+        config.tls.certificate,
+        Some(PathBuf::from("/etc/chess/server.crt"))
+    );
+    assert_eq!(
+        config.tls.private_key,
+        Some(PathBuf::from("/keys/server.key"))
+    );
+    assert_eq!(config.tls.ca_certificate, None);
+
+    assert!(Config::parse("[tls]\ncertificate = 3\n", base).is_err());
+    assert!(Config::parse("[tls]\ncert = \"a\"\n", base).is_err());
+    assert!(Config::parse("colour = \"blue\"\n", base).is_err());
+    assert!(Config::parse("[tls", base).is_err());
+
+    let mut params = Params::default();
+    params.set("RookSeventh", 35);
+    let config = Config::parse(&params_section("eval", params.named()), base).unwrap();
+    assert_eq!(config.eval, params);
+    assert_eq!(config.eval.rook_seventh, 35);
+    assert!(Config::parse("[eval]\nRookEighth = 1\n", base).is_err());
+    assert!(Config::parse("[eval]\nBishopPair = 501\n", base).is_err());
+    assert!(Config::parse("[eval]\nBishopPair = \"lots\"\n", base).is_err());
+
+    let config = Config::parse("[search]\nlmrbase = 120\n", base).unwrap();
+    assert_eq!(config.search.lmr_base, 120);
+    assert_eq!(
+        config.search.futility_margin,
+        SearchParams::default().futility_margin
+    );
+    assert!(Config::parse("[search]\nLmrDivisor = 0\n", base).is_err());
+    assert!(Config::parse("[search]\nNullMove = 1\n", base).is_err());
+
+    let config = Config::parse("[display]\nnotation = \"long\"\n", base).unwrap();
+    assert_eq!(config.display.notation, Notation::by_name("long").unwrap());
+    assert!(Config::parse("[display]\nnotation = \"fan\"\n", base).is_err());
+    assert!(Config::parse("[display]\ncolour = \"blue\"\n", base).is_err());
+
+    let config = Config::parse("[display]\nlocale = \"fr\"\n", base).unwrap();
+    let french = PieceLetters(['C', 'F', 'T', 'D', 'R']);
+    assert_eq!(config.display.notation, Notation::San(french));
+    let config = Config::parse(
+        "[display]\nnotation = \"long\"\nlocale = \"eo\"\n[locales]\neo = \"ĈKTDR\"\n",
+        base,
+    )
+    .unwrap();
+    assert_eq!(
+        config.display.notation,
+        Notation::Long(PieceLetters(['Ĉ', 'K', 'T', 'D', 'R']))
+    );
+    assert!(Config::parse("[display]\nlocale = \"xx\"\n", base).is_err());
+    assert!(
+        Config::parse("[display]\nscreen_reader = true\n", base)
+            .unwrap()
+            .display
+            .screen_reader
+    );
+    assert!(Config::parse("[display]\nscreen_reader = 1\n", base).is_err());
+
+    let config = Config::parse("[display]\nglyph_width = 2\n", base).unwrap();
+    assert_eq!(config.display.layout, Layout::new(2, 2));
+    let config = Config::parse("[display]\ncell_width = 3\n", base).unwrap();
+    assert_eq!(config.display.layout, Layout::new(3, 1));
+    assert!(Config::parse("[display]\nglyph_width = 3\n", base).is_err());
+    assert!(Config::parse("[display]\ncell_width = 0\n", base).is_err());
+
+    let config = Config::parse("[display]\ntheme = \"solarized\"\n", base).unwrap();
+    assert_eq!(config.display.theme, Theme::by_name("solarized"));
+    let config = Config::parse(
+        "[display]\ntheme = \"green\"\n[themes]\ngreen = { dark = \"#769656\" }\n",
+        base,
+    )
+    .unwrap();
+    assert_eq!(config.display.theme.unwrap().dark, (0x76, 0x96, 0x56));
+    assert_eq!(config.display.themes.len(), 1);
+    let config = Config::parse("[themes]\ngreen = { dark = \"#769656\" }\n", base).unwrap();
+    assert_eq!(config.display.theme, None);
+    assert_eq!(config.display.themes.len(), 1);
+    assert!(Config::parse("[display]\ntheme = \"green\"\n", base).is_err());
+    assert!(Config::parse("[themes]\ngreen = \"#769656\"\n", base).is_err());
+    assert!(Config::parse("[themes]\ngreen = { dark = \"green\" }\n", base).is_err());
+    assert!(Config::parse(
+        "[display]\nlocale = \"xx\"\n[locales]\nxx = \"NNRQK\"\n",
+        base
+    )
+    .is_err());
+
+    let config = Config::parse("[play]\nauto_promote = \"queen\"\n", base).unwrap();
+    assert_eq!(config.play.auto_promote, AutoPromote::Queen);
+    assert_eq!(
+        Config::parse("", base).unwrap().play.auto_promote,
+        AutoPromote::Ask
+    );
+    assert!(Config::parse("[play]\nauto_promote = \"knight\"\n", base).is_err());
+    assert!(Config::parse("[play]\npromote = \"queen\"\n", base).is_err());
+    let config = Config::parse("[play]\nresign_moves = 0\ndraw_score = 5\n", base).unwrap();
+    assert_eq!(config.play.engine.resign_moves, 0);
+    assert_eq!(config.play.engine.draw_score, 5);
+    assert_eq!(
+        config.play.engine.draw_moves,
+        Thresholds::default().draw_moves
+    );
+    assert!(Config::parse("[play]\nresign_score = -100\n", base).is_err());
+}
@@ -0,0 +1,145 @@
+//! Correspondence chess without a server. The game lives in a PGN file that the players pass
+//! back and forth (by email, a shared folder, ...), and every run of `correspond` plays at most
+//! one move in it.
+
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::movegen::MoveRejection;
+use crate::pgn::PgnGame;
+use crate::{ChessBoard, ChessErr, WHITE};
+
+fn chess_error(e: ChessErr) -> Error {
+    Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+fn side_name(color: u8) -> &'static str {
+    if color == WHITE {
+        "White"
+    } else {
+        "Black"
+    }
+}
+
+/// Describes where the game stands, e.g. `Black to move.` or `Game over: 1-0.`
+fn status(game: &PgnGame, board: &ChessBoard) -> String {
+    match game.result.as_str() {
+        "*" => format!("{} to move.", side_name(board.side_to_move())),
+        result => format!("Game over: {}.", result),
+    }
+}
+
+/// The message telling the opponent about `game`'s last move, with the whole game as PGN so it
+/// can be pasted into an email.
+fn snippet(game: &PgnGame, before: &ChessBoard, san: &str, board: &ChessBoard) -> String {
+    let number = before.moves / 2 + 1;
+    let dots = if before.side_to_move() == WHITE {
+        "."
+    } else {
+        "..."
+    };
+    format!(
+        "{} vs {}: {} played {}{} {}. {}\n\n{}\n{}",
+        game.tag("White").unwrap_or("?"),
+        game.tag("Black").unwrap_or("?"),
+        side_name(before.side_to_move()),
+        number,
+        dots,
+        san,
+        status(game, board),
+        board,
+        game.to_pgn()
+    )
+}
+
+/// Runs one turn of the correspondence game in `path`. `new_game` starts the file afresh
+/// (refusing to overwrite an existing game); otherwise the game is loaded from it. If `mv` is
+/// given it is played and the file rewritten, and `snippet` (a file, or `-` for stdout) receives
+/// a message for the opponent.
+pub fn correspond(
+    path: &Path,
+    new_game: Option<PgnGame>,
+    mv: Option<&str>,
+    snippet_path: Option<&str>,
+) -> Result<(), Error> {
+    let mut game = match new_game {
+        Some(_) if path.exists() => {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} already holds a game.", path.display()),
+            ))
+        }
+        Some(game) => {
+            std::fs::write(path, game.to_pgn())?;
+            game
+        }
+        None => PgnGame::parse(&std::fs::read_to_string(path)?).map_err(chess_error)?,
+    };
+    let before = game.board();
+
+    let text = match mv {
+        Some(text) => text,
+        None => {
+            print!("{}", before);
+            println!("{}", status(&game, &before));
+            return Ok(());
+        }
+    };
+    if game.result != "*" {
+        return Err(chess_error(ChessErr::BadMove(MoveRejection::GameOver)));
+    }
+    let mv = before.parse_move(text).map_err(chess_error)?;
+    let san = before.san(mv);
+    let mut board = before;
+    board.apply_move(mv);
+    game.tree.push(mv);
+    if let Some((result, _)) = board.outcome() {
+        game.result = result.to_string();
+    }
+    std::fs::write(path, game.to_pgn())?;
+
+    print!("{}", board);
+    println!("Played {}. {}", san, status(&game, &board));
+    let message = snippet(&game, &before, &san, &board);
+    match snippet_path {
+        Some("-") => print!("\n{}", message),
+        Some(snippet_path) => std::fs::write(snippet_path, message)?,
+        None => {}
+    }
+    Ok(())
+}
+
+#[test]
+fn test_correspond() {
+    let path = std::env::temp_dir().join(format!("freight_chess_game_{}.pgn", std::process::id()));
+    let snippet_path = path.with_extension("txt");
+    let _ = std::fs::remove_file(&path);
+    let mut game = PgnGame::new(ChessBoard::new());
+    game.set_tag("White", "alice");
+    game.set_tag("Black", "bob");
+    correspond(&path, Some(game), None, None).unwrap();
+    let fresh = PgnGame::new(ChessBoard::new());
+    assert!(correspond(&path, Some(fresh), None, None).is_err());
+
+    for mv in ["f3", "e5", "g4"] {
+        correspond(&path, None, Some(mv), None).unwrap();
+    }
+    assert!(correspond(&path, None, Some("Nf4"), None).is_err());
+    let snippet = snippet_path.to_str().unwrap();
+    correspond(&path, None, Some("Qh4#"), Some(snippet)).unwrap();
+    let message = std::fs::read_to_string(&snippet_path).unwrap();
+    assert!(message.starts_with("alice vs bob: Black played 2... Qh4#. Game over: 0-1.\n"));
+    assert!(message.ends_with("\n1. f3 e5 2. g4 Qh4# 0-1\n"));
+
+    let game = crate::pgn::parse_games(
+        &std::fs::read_to_string(&path).unwrap(),
+        crate::san::PieceLetters::ENGLISH,
+    )
+    .unwrap();
+    assert_eq!(game.len(), 1);
+    assert_eq!(game[0].result, "0-1");
+    assert_eq!(game[0].tag("Black"), Some("bob"));
+    assert!(correspond(&path, None, Some("Ke2"), None).is_err());
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&snippet_path).unwrap();
+}
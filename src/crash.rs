@@ -0,0 +1,194 @@
+//! What to do when the program panics: rather than leave only a backtrace, it writes what it
+//! was doing (the game's start position, the moves since and where they led, the command line
+//! and the options set since) to a crash file, and asks for the file to be attached to the bug
+//! report, so the position that crashed it can be looked at.
+//!
+//! The prompt and the UCI engine note the game as it goes along with `note_game`, and the UCI
+//! engine notes each option set with `note_setting`.
+
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ChessBoard;
+
+/// What the program was doing, as last noted.
+#[derive(Default)]
+struct Context {
+    /// The position the game started from, as FEN or as UCI's `startpos`.
+    start: Option<String>,
+    /// The moves played since.
+    moves: Vec<String>,
+    /// The position they led to, as FEN.
+    current: Option<String>,
+    /// The options set, by name, in the order they were first set.
+    settings: Vec<(String, String)>,
+}
+
+impl Context {
+    fn note_game(&mut self, start: &str, moves: &[String], current: &ChessBoard) {
+        self.start = Some(start.to_string());
+        self.moves = moves.to_vec();
+        self.current = Some(current.fen());
+    }
+
+    fn note_setting(&mut self, name: &str, value: &str) {
+        match self.settings.iter_mut().find(|(known, _)| known == name) {
+            Some((_, current)) => *current = value.to_string(),
+            None => self.settings.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// The crash report of a panic with `message` at `location`.
+    fn report(&self, message: &str, location: &str, backtrace: &str) -> String {
+        let mut report = String::new();
+        let none = "(none)".to_string();
+        let args = std::env::args().collect::<Vec<String>>();
+        // Writing to a string can't fail.
+        let _ = writeln!(
+            report,
+            "FreightChess {} crashed.\n\nPanic: {}\nAt: {}\nCommand line: {}\n",
+            env!("CARGO_PKG_VERSION"),
+            message,
+            location,
+            args.join(" ")
+        );
+        let _ = writeln!(
+            report,
+            "Started from: {}",
+            self.start.as_ref().unwrap_or(&none)
+        );
+        let _ = writeln!(report, "Moves: {}", self.moves.join(" "));
+        let _ = writeln!(
+            report,
+            "Position: {}\n",
+            self.current.as_ref().unwrap_or(&none)
+        );
+        let _ = writeln!(report, "Settings:");
+        for (name, value) in &self.settings {
+            let _ = writeln!(report, "  {} = {}", name, value);
+        }
+        let _ = write!(report, "\nBacktrace:\n{}", backtrace);
+        report
+    }
+}
+
+static CONTEXT: Mutex<Option<Context>> = Mutex::new(None);
+
+/// Runs `note` on the context, making it if it's the first note.
+fn with_context(note: impl FnOnce(&mut Context)) {
+    let mut context = CONTEXT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    note(context.get_or_insert_with(Context::default));
+}
+
+/// Notes that the game being played started from `start` (FEN, or UCI's `startpos`) and has had
+/// `moves` played since, reaching `current`.
+pub fn note_game(start: &str, moves: &[String], current: &ChessBoard) {
+    with_context(|context| context.note_game(start, moves, current));
+}
+
+/// Notes that the option `name` has been set to `value`.
+pub fn note_setting(name: &str, value: &str) {
+    with_context(|context| context.note_setting(name, value));
+}
+
+/// How many crash reports this process has written.
+static CRASHES: AtomicU32 = AtomicU32::new(0);
+
+/// Where the `count`th crash report of process `pid`, of a crash at `seconds` since the epoch,
+/// goes. No two crashes share one, however close together.
+fn crash_path(seconds: u64, pid: u32, count: u32) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "freight_chess-crash-{}-{}-{}.txt",
+        seconds, pid, count
+    ))
+}
+
+/// Returns `true` if the panic with `message` is `println!` finding standard output closed, as
+/// when the output is piped into `head`. That's no bug.
+fn is_broken_pipe(message: &str) -> bool {
+    message.starts_with("failed printing to stdout") && message.contains("Broken pipe")
+}
+
+/// Makes panics write a crash report and ask for it in the bug report, instead of printing a
+/// backtrace.
+pub fn install() {
+    std::panic::set_hook(Box::new(|info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("(no message)");
+        if is_broken_pipe(message) {
+            // Nobody is reading any more, so stop quietly, as a program killed by SIGPIPE would.
+            std::process::exit(141);
+        }
+        let location = info
+            .location()
+            .map_or("an unknown place".to_string(), |location| {
+                location.to_string()
+            });
+        let backtrace = Backtrace::force_capture().to_string();
+        // The panic may have struck while the context was being noted; then go without it.
+        let report = match CONTEXT.try_lock() {
+            Ok(context) => context
+                .as_ref()
+                .unwrap_or(&Context::default())
+                .report(message, &location, &backtrace),
+            Err(_) => Context::default().report(message, &location, &backtrace),
+        };
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let count = CRASHES.fetch_add(1, Ordering::Relaxed);
+        let path = crash_path(seconds, std::process::id(), count);
+        eprintln!("FreightChess crashed: {}", message);
+        let written = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(report.as_bytes()));
+        match written {
+            Ok(()) => eprintln!(
+                "What it was doing is saved in {}. Please attach that file to your bug report.",
+                path.display()
+            ),
+            Err(_) => eprintln!("Please include this in your bug report:\n\n{}", report),
+        }
+    }));
+}
+
+#[test]
+fn test_crash() {
+    let mut context = Context::default();
+    let mut board = ChessBoard::new();
+    let e4 = board.parse_san("e4").unwrap();
+    board.apply_move(e4);
+    context.note_game("startpos", &["e2e4".to_string()], &board);
+    context.note_setting("Hash", "64");
+    context.note_setting("Threads", "2");
+    context.note_setting("Hash", "128");
+    let report = context.report("index out of bounds", "src/search.rs:10:5", "0: main");
+    assert!(report.contains("\nPanic: index out of bounds\nAt: src/search.rs:10:5\n"));
+    assert!(report.contains("\nStarted from: startpos\nMoves: e2e4\n"));
+    assert!(report.contains(&format!("\nPosition: {}\n", board.fen())));
+    assert!(report.contains("Settings:\n  Hash = 128\n  Threads = 2\n"));
+    assert!(report.ends_with("Backtrace:\n0: main"));
+    assert!(Context::default()
+        .report("", "", "")
+        .contains("Started from: (none)"));
+    assert!(crash_path(7, 42, 0).ends_with("freight_chess-crash-7-42-0.txt"));
+    assert_ne!(crash_path(7, 42, 0), crash_path(7, 42, 1));
+    assert!(is_broken_pipe(
+        "failed printing to stdout: Broken pipe (os error 32)"
+    ));
+    assert!(!is_broken_pipe("index out of bounds"));
+}
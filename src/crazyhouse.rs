@@ -0,0 +1,206 @@
+//! Crazyhouse: a captured piece goes into the capturer's hand, and instead of moving, a player
+//! may drop a piece from their hand onto any empty square. Pawns can't be dropped on the first
+//! or last rank, and a promoted piece goes back into hand as a pawn.
+
+use crate::movegen::{color_name, Move, MoveList};
+use crate::variant::Variant;
+use crate::{
+    ChessBoard, BISHOP, BLACK, CHESS_PIECES, EMPTY, GET_NUM, KNIGHT, PAWN, PIECE_LETTERS, QUEEN,
+    ROOK, SET_BLACK, WHITE,
+};
+
+/// The pieces that can be held, in the order FEN and the board list them.
+pub const DROPPABLE: [u8; 5] = [QUEEN, ROOK, BISHOP, KNIGHT, PAWN];
+
+pub struct Crazyhouse;
+
+impl Variant for Crazyhouse {
+    fn name(&self) -> &'static str {
+        "crazyhouse"
+    }
+
+    fn pgn_name(&self) -> &'static str {
+        "Crazyhouse"
+    }
+
+    fn start(&self) -> ChessBoard {
+        ChessBoard::new()
+    }
+
+    fn has_pockets(&self) -> bool {
+        true
+    }
+
+    fn adjust_moves(&self, board: &ChessBoard, moves: &mut MoveList) {
+        board.add_drops(moves);
+    }
+
+    /// Puts what was captured into the mover's hand (a pawn, if it had been promoted).
+    fn after_move(&self, before: &ChessBoard, mv: Move, after: &mut ChessBoard) {
+        let captured = GET_NUM(before.captured_piece(mv));
+        if captured != EMPTY {
+            let hand = &mut after.pockets[(before.side_to_move() >> 3) as usize];
+            hand[captured as usize] = hand[captured as usize].saturating_add(1);
+        }
+        after.update_hands(before, mv);
+    }
+}
+
+impl ChessBoard {
+    /// How many of each piece (indexed by piece number) `color` holds in hand.
+    pub fn pocket(&self, color: u8) -> &[u8; 6] {
+        &self.pockets[(color >> 3) as usize]
+    }
+
+    /// Writes both hands as FEN does, white's pieces first, as in `QNPnp`.
+    pub fn pockets_fen(&self) -> String {
+        let mut pockets = String::new();
+        for color in [WHITE, BLACK] {
+            for piece in DROPPABLE {
+                let letter = PIECE_LETTERS[(piece | color) as usize];
+                for _ in 0..self.pocket(color)[piece as usize] {
+                    pockets.push(letter);
+                }
+            }
+        }
+        pockets
+    }
+
+    /// Adds a drop of every piece in hand onto every empty square it may go to.
+    pub fn add_drops(&self, moves: &mut MoveList) {
+        let side = self.side_to_move();
+        for piece in DROPPABLE {
+            if self.pocket(side)[piece as usize] == 0 {
+                continue;
+            }
+            let squares = match piece {
+                PAWN => 8..56,
+                _ => 0..64,
+            };
+            for square in squares {
+                if self.get_piece(square) == EMPTY {
+                    moves.push(Move::drop(piece, square));
+                }
+            }
+        }
+    }
+
+    /// The piece `mv` captures, with its color, as it goes into hand: a pawn if it had been
+    /// promoted. `EMPTY` if the move captures nothing.
+    pub fn captured_piece(&self, mv: Move) -> u8 {
+        let piece = match self.get_piece(mv.to) {
+            _ if mv.is_drop() || self.is_castling(mv) => return EMPTY,
+            EMPTY if GET_NUM(self.get_piece(mv.from)) == PAWN && mv.to == self.en_passant => {
+                return PAWN | (self.side_to_move() ^ BLACK)
+            }
+            EMPTY => return EMPTY,
+            piece => piece,
+        };
+        match self.promoted & (1u64 << mv.to) != 0 {
+            true => PAWN | (piece & BLACK),
+            false => piece,
+        }
+    }
+
+    /// Once `mv` has been played from `before`, takes what was dropped out of the mover's hand
+    /// and keeps track of which pieces were promoted.
+    pub fn update_hands(&mut self, before: &ChessBoard, mv: Move) {
+        if mv.is_drop() {
+            let hand = &mut self.pockets[(before.side_to_move() >> 3) as usize];
+            hand[mv.promotion as usize] = hand[mv.promotion as usize].saturating_sub(1);
+            return;
+        }
+        let (from, to) = (1u64 << mv.from, 1u64 << mv.to);
+        self.promoted &= !(from | to);
+        if before.promoted & from != 0 || mv.promotion != EMPTY {
+            self.promoted |= to;
+        }
+    }
+
+    /// Reads the hands written as in `pockets_fen`.
+    pub fn set_pockets(&mut self, pockets: &str) -> Option<()> {
+        self.pockets = [[0; 6]; 2];
+        for c in pockets.chars() {
+            let piece = DROPPABLE
+                .iter()
+                .flat_map(|&piece| [piece, SET_BLACK(piece)])
+                .find(|&piece| PIECE_LETTERS[piece as usize] == c)?;
+            let count = &mut self.pockets[(piece >> 3) as usize][GET_NUM(piece) as usize];
+            *count = count.checked_add(1)?;
+        }
+        Some(())
+    }
+
+    /// Describes what `color` holds in hand, as in `white holds ♕ ♙ ♙`.
+    pub fn describe_pocket(&self, color: u8) -> String {
+        let pieces = DROPPABLE
+            .iter()
+            .flat_map(|&piece| {
+                let glyph = CHESS_PIECES[(piece | color) as usize].to_string();
+                std::iter::repeat_n(glyph, self.pocket(color)[piece as usize] as usize)
+            })
+            .collect::<Vec<String>>();
+        match pieces.is_empty() {
+            true => format!("{} holds nothing", color_name(color)),
+            false => format!("{} holds {}", color_name(color), pieces.join(" ")),
+        }
+    }
+}
+
+#[test]
+fn test_crazyhouse() {
+    use crate::variant::by_name;
+
+    let play = |board: &mut ChessBoard, sans: &[&str]| {
+        for san in sans {
+            let mv = board.parse_san(san).expect(san);
+            assert_eq!(board.san(mv), *san);
+            board.apply_move(mv);
+        }
+    };
+    let mut board = ChessBoard::start_of(by_name("crazyhouse").unwrap());
+    assert_eq!(board.perft(3), 8902);
+    play(&mut board, &["e4", "d5", "exd5", "Qxd5", "Nc3", "Qxg2"]);
+    assert_eq!(board.pockets_fen(), "Ppp");
+    play(&mut board, &["Bxg2", "P@h3", "Nf3", "hxg2", "P@e6"]);
+    assert_eq!(board.pockets_fen(), "Qbp");
+    assert_eq!(
+        board.fen(),
+        "rnb1kbnr/ppp1pppp/4P3/8/8/2N2N2/PPPP1PpP/R1BQK2R[Qbp] b KQkq - 0 6"
+    );
+    assert_eq!(board.describe_pocket(WHITE), "white holds ♕");
+    assert_eq!(board.describe_pocket(BLACK), "black holds ♝ ♟");
+
+    // Pawns can't be dropped on the back ranks, and may be dropped without the P.
+    assert!(board.parse_san("P@a1").is_err());
+    assert_eq!(
+        board.parse_san("@a3").map(|mv| mv.to_string()),
+        Ok("P@a3".to_string())
+    );
+    play(&mut board, &["gxh1=Q+"]);
+    assert!(board
+        .fen()
+        .starts_with("rnb1kbnr/ppp1pppp/4P3/8/8/2N2N2/PPPP1P1P/R1BQK2q~[Qrbp] "));
+    assert_eq!(
+        ChessBoard::from_fen(&board.fen()).unwrap().fen(),
+        board.fen()
+    );
+
+    // A promoted piece goes back into hand as a pawn.
+    let mut board = ChessBoard::from_fen("4k3/8/8/8/8/8/6K1/7q~[] w - - 0 1").unwrap();
+    assert_eq!(board.variant.name(), "crazyhouse");
+    play(&mut board, &["Kxh1"]);
+    assert_eq!(board.pockets_fen(), "P");
+
+    // Drops count as moves, including ones that block a check.
+    let board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/4K3/Qp w - - 0 1").unwrap();
+    assert_eq!(board.legal_moves().len(), 5 + 62);
+    let board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/r3K3[N] w - - 0 1").unwrap();
+    assert_eq!(board.legal_moves().len(), 3 + 3);
+    let board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/r3K3[P] w - - 0 1").unwrap();
+    assert_eq!(board.legal_sans(), ["Ke2", "Kf2", "Kd2"]);
+
+    // Drops of every piece onto a nearly empty board are more moves than standard chess has.
+    let board = ChessBoard::from_fen("k7/8/8/8/8/8/8/K7[QRBNPqrbnp] w - - 0 1").unwrap();
+    assert_eq!(board.legal_moves().len(), 3 + 4 * 62 + 48);
+}
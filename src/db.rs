@@ -0,0 +1,831 @@
+//! The local game database: completed games stored in an SQLite file (`~/.freight_chess.db`
+//! unless `db --file` names another), with engine evaluations of every move once a game has been
+//! analyzed. Every position of every game is indexed by its Zobrist hash, so games reaching a
+//! position can be found quickly.
+
+use std::io::{Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+
+use crate::engine::{EngineSpec, Limits};
+use crate::explorer;
+use crate::pgn::{self, PgnGame};
+use crate::san::Notation;
+use crate::tree::Cursor;
+use crate::{ChessBoard, WHITE};
+
+/// Where the database lives, relative to the home directory.
+const DATABASE_FILE: &str = ".freight_chess.db";
+/// How many centipawns a move must lose to be annotated as an inaccuracy (`?!`), a mistake
+/// (`?`) or a blunder (`??`).
+const INACCURACY: i32 = 50;
+const MISTAKE: i32 = 100;
+const BLUNDER: i32 = 300;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS games (
+        id INTEGER PRIMARY KEY,
+        white TEXT NOT NULL,
+        black TEXT NOT NULL,
+        date TEXT NOT NULL,
+        result TEXT NOT NULL,
+        -- Every tag of the game, as a JSON array of [name, value] pairs.
+        tags TEXT NOT NULL,
+        start_fen TEXT NOT NULL,
+        -- The moves in SAN, separated by spaces.
+        moves TEXT NOT NULL,
+        final_fen TEXT NOT NULL,
+        -- `moves_hash` of the game, to find duplicates by.
+        moves_hash INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS evaluations (
+        game_id INTEGER NOT NULL REFERENCES games(id) ON DELETE CASCADE,
+        -- The position after this many moves.
+        ply INTEGER NOT NULL,
+        -- In centipawns, from white's point of view.
+        score INTEGER NOT NULL,
+        PRIMARY KEY (game_id, ply)
+    );
+    CREATE TABLE IF NOT EXISTS positions (
+        game_id INTEGER NOT NULL REFERENCES games(id) ON DELETE CASCADE,
+        -- The position after this many moves; 0 is the starting position.
+        ply INTEGER NOT NULL,
+        -- `ChessBoard::zobrist` of the position, stored as a signed integer.
+        hash INTEGER NOT NULL,
+        PRIMARY KEY (game_id, ply)
+    );
+    CREATE INDEX IF NOT EXISTS positions_by_hash ON positions (hash);
+";
+
+/// One line of `db list`.
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub id: i64,
+    pub white: String,
+    pub black: String,
+    pub date: String,
+    pub result: String,
+    pub moves: usize,
+}
+
+/// A stored game that reached a position, from `db find-position`.
+#[derive(Debug, PartialEq)]
+pub struct Occurrence {
+    pub game: Summary,
+    /// How many moves had been played when the position was reached.
+    pub ply: u32,
+    /// The move played from the position, in SAN, unless the game ended there.
+    pub next_move: Option<String>,
+}
+
+/// How often a move was played from a position, and how those games ended.
+#[derive(Debug, PartialEq)]
+pub struct MoveStats {
+    pub san: String,
+    pub games: u32,
+    pub white_wins: u32,
+    pub draws: u32,
+    pub black_wins: u32,
+}
+
+/// What happened to a game given to `Database::store`.
+#[derive(Debug, PartialEq)]
+pub enum Stored {
+    Added(i64),
+    /// The game was already stored, with this id, so it was skipped.
+    Duplicate(i64),
+}
+
+/// What `db` was asked to do.
+pub enum Command {
+    /// Stores the games in a PGN file (or stdin, for `-`).
+    Add(String),
+    List,
+    Show(i64),
+    /// Writes every game as PGN, to a file or stdout.
+    ExportPgn(Option<String>),
+    /// Evaluates every position of a game at the given depth, with the given engine.
+    Analyze(i64, u32, EngineSpec),
+    /// Finds the games that reached the position given as FEN.
+    FindPosition(String),
+    /// Browses the moves played in the stored games, from the starting position or the one
+    /// given as FEN.
+    Explorer(Option<String>),
+}
+
+fn sql_error(e: rusqlite::Error) -> Error {
+    Error::other(format!("Database error: {}", e))
+}
+
+fn not_found(id: i64) -> Error {
+    Error::new(ErrorKind::NotFound, format!("There is no game {}.", id))
+}
+
+/// The database used when `db --file` isn't given.
+pub fn default_path() -> PathBuf {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map_or_else(
+            || PathBuf::from(DATABASE_FILE),
+            |home| PathBuf::from(home).join(DATABASE_FILE),
+        )
+}
+
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: &Path) -> Result<Database, Error> {
+        Database::with_connection(Connection::open(path).map_err(sql_error)?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Database, Error> {
+        connection
+            .execute_batch(&format!("PRAGMA foreign_keys = ON; {}", SCHEMA))
+            .map_err(sql_error)?;
+        let mut database = Database { connection };
+        database.upgrade()?;
+        database.index_positions()?;
+        Ok(database)
+    }
+
+    /// Adds the duplicate detection hash to databases made before it existed.
+    fn upgrade(&mut self) -> Result<(), Error> {
+        if self
+            .connection
+            .prepare("SELECT moves_hash FROM games LIMIT 0")
+            .is_err()
+        {
+            self.connection
+                .execute("ALTER TABLE games ADD COLUMN moves_hash INTEGER", [])
+                .map_err(sql_error)?;
+        }
+        for id in self.ids_where("moves_hash IS NULL")? {
+            let hash = moves_hash(&self.game(id)?);
+            self.connection
+                .execute(
+                    "UPDATE games SET moves_hash = ?1 WHERE id = ?2",
+                    params![hash, id],
+                )
+                .map_err(sql_error)?;
+        }
+        self.connection
+            .execute(
+                "CREATE INDEX IF NOT EXISTS games_by_players ON games (white, black, date)",
+                [],
+            )
+            .map_err(sql_error)?;
+        Ok(())
+    }
+
+    /// The ids of the games matching an SQL condition, oldest first.
+    fn ids_where(&self, condition: &str) -> Result<Vec<i64>, Error> {
+        let mut statement = self
+            .connection
+            .prepare(&format!(
+                "SELECT id FROM games WHERE {} ORDER BY id",
+                condition
+            ))
+            .map_err(sql_error)?;
+        let ids = statement
+            .query_map([], |row| row.get(0))
+            .map_err(sql_error)?
+            .collect::<Result<Vec<i64>, _>>()
+            .map_err(sql_error);
+        ids
+    }
+
+    /// Indexes the positions of games stored before positions were indexed.
+    fn index_positions(&mut self) -> Result<(), Error> {
+        for id in self.ids_where("id NOT IN (SELECT game_id FROM positions)")? {
+            let game = self.game(id)?;
+            self.insert_positions(id, &game)?;
+        }
+        Ok(())
+    }
+
+    fn insert_positions(&self, id: i64, game: &PgnGame) -> Result<(), Error> {
+        let mut statement = self
+            .connection
+            .prepare("INSERT INTO positions (game_id, ply, hash) VALUES (?1, ?2, ?3)")
+            .map_err(sql_error)?;
+        let mut board = game.tree.start;
+        statement
+            .execute(params![id, 0, board.zobrist() as i64])
+            .map_err(sql_error)?;
+        for (ply, mv) in game.tree.moves().into_iter().enumerate() {
+            board.apply_move(mv);
+            statement
+                .execute(params![id, ply as u32 + 1, board.zobrist() as i64])
+                .map_err(sql_error)?;
+        }
+        Ok(())
+    }
+
+    /// Stores `game`, returning its id.
+    pub fn add(&self, game: &PgnGame) -> Result<i64, Error> {
+        let tags = game
+            .tags
+            .iter()
+            .map(|(name, value)| json!([name, value]))
+            .collect::<Vec<Value>>();
+        self.connection
+            .execute(
+                "INSERT INTO games
+                 (white, black, date, result, tags, start_fen, moves, final_fen, moves_hash)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    game.tag("White").unwrap_or("?"),
+                    game.tag("Black").unwrap_or("?"),
+                    game.tag("Date").unwrap_or("????.??.??"),
+                    game.result,
+                    Value::from(tags).to_string(),
+                    game.tree.start.fen(),
+                    game.tree.start.line_sans(&game.tree.moves()).join(" "),
+                    game.board().fen(),
+                    moves_hash(game),
+                ],
+            )
+            .map_err(sql_error)?;
+        let id = self.connection.last_insert_rowid();
+        self.insert_positions(id, game)?;
+        Ok(id)
+    }
+
+    /// The stored game with the same players, date and moves as `game`, if there is one.
+    pub fn find_duplicate(&self, game: &PgnGame) -> Result<Option<i64>, Error> {
+        self.connection
+            .query_row(
+                "SELECT id FROM games
+                 WHERE white = ?1 AND black = ?2 AND date = ?3 AND moves_hash = ?4",
+                params![
+                    game.tag("White").unwrap_or("?"),
+                    game.tag("Black").unwrap_or("?"),
+                    game.tag("Date").unwrap_or("????.??.??"),
+                    moves_hash(game),
+                ],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sql_error)
+    }
+
+    /// Stores `game` unless it is already stored.
+    pub fn store(&self, game: &PgnGame) -> Result<Stored, Error> {
+        match self.find_duplicate(game)? {
+            Some(id) => Ok(Stored::Duplicate(id)),
+            None => self.add(game).map(Stored::Added),
+        }
+    }
+
+    /// Every stored game, oldest first.
+    pub fn list(&self) -> Result<Vec<Summary>, Error> {
+        self.summaries(
+            "SELECT id, white, black, date, result, moves FROM games ORDER BY id",
+            [],
+        )
+    }
+
+    /// Runs `query`, which selects the columns of a `Summary`.
+    fn summaries(
+        &self,
+        query: &str,
+        parameters: impl rusqlite::Params,
+    ) -> Result<Vec<Summary>, Error> {
+        let mut statement = self.connection.prepare(query).map_err(sql_error)?;
+        let rows = statement
+            .query_map(parameters, |row| {
+                let moves: String = row.get(5)?;
+                Ok(Summary {
+                    id: row.get(0)?,
+                    white: row.get(1)?,
+                    black: row.get(2)?,
+                    date: row.get(3)?,
+                    result: row.get(4)?,
+                    moves: moves.split_whitespace().count().div_ceil(2),
+                })
+            })
+            .map_err(sql_error)?;
+        rows.collect::<Result<_, _>>().map_err(sql_error)
+    }
+
+    /// The ids of every stored game, oldest first.
+    pub fn ids(&self) -> Result<Vec<i64>, Error> {
+        Ok(self.list()?.into_iter().map(|summary| summary.id).collect())
+    }
+
+    /// The game stored as `id`.
+    pub fn game(&self, id: i64) -> Result<PgnGame, Error> {
+        let row = self
+            .connection
+            .query_row(
+                "SELECT tags, start_fen, moves, result FROM games WHERE id = ?1",
+                [id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(sql_error)?;
+        let (tags, start, moves, result) = row.ok_or_else(|| not_found(id))?;
+        let corrupt = || Error::new(ErrorKind::InvalidData, format!("Game {} is corrupt.", id));
+        let tags: Value = serde_json::from_str(&tags).map_err(|_| corrupt())?;
+        let mut game = PgnGame::new(ChessBoard::from_fen(&start).map_err(|_| corrupt())?);
+        game.tags = tags
+            .as_array()
+            .ok_or_else(corrupt)?
+            .iter()
+            .filter_map(|pair| Some((pair[0].as_str()?.to_string(), pair[1].as_str()?.to_string())))
+            .collect();
+        game.result = result;
+        let mut board = game.tree.start;
+        for san in moves.split_whitespace() {
+            let mv = board.parse_move(san).map_err(|_| corrupt())?;
+            board.apply_move(mv);
+            game.tree.push(mv);
+        }
+        Ok(game)
+    }
+
+    /// Every game that reached `board`'s position, oldest first, with when it did and what was
+    /// played next. Transpositions are found too, since positions are compared by hash (and
+    /// then checked, so hash collisions don't show up).
+    pub fn find_position(&self, board: &ChessBoard) -> Result<Vec<Occurrence>, Error> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT game_id, MIN(ply) FROM positions WHERE hash = ?1
+                 GROUP BY game_id ORDER BY game_id",
+            )
+            .map_err(sql_error)?;
+        let found = statement
+            .query_map([board.zobrist() as i64], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map_err(sql_error)?
+            .collect::<Result<Vec<(i64, u32)>, _>>()
+            .map_err(sql_error)?;
+        let placement = |board: &ChessBoard| board.fen().rsplitn(3, ' ').last().map(str::to_string);
+        let mut occurrences = Vec::new();
+        for (id, ply) in found {
+            let game = self.game(id)?;
+            let moves = game.tree.moves();
+            let mut reached = game.tree.start;
+            for &mv in &moves[..ply as usize] {
+                reached.apply_move(mv);
+            }
+            if placement(&reached) != placement(board) {
+                continue;
+            }
+            let next_move = moves.get(ply as usize).map(|&mv| reached.san(mv));
+            let summary = self.summaries(
+                "SELECT id, white, black, date, result, moves FROM games WHERE id = ?1",
+                [id],
+            )?;
+            occurrences.extend(summary.into_iter().map(|game| Occurrence {
+                game,
+                ply,
+                next_move: next_move.clone(),
+            }));
+        }
+        Ok(occurrences)
+    }
+
+    /// The moves played from `board`'s position in the stored games, most popular first. Only
+    /// the first time a game reached the position counts.
+    pub fn explore(&self, board: &ChessBoard) -> Result<Vec<MoveStats>, Error> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT games.moves, MIN(positions.ply), games.result
+                 FROM positions JOIN games ON games.id = positions.game_id
+                 WHERE positions.hash = ?1 GROUP BY games.id",
+            )
+            .map_err(sql_error)?;
+        let rows = statement
+            .query_map([board.zobrist() as i64], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .map_err(sql_error)?
+            .collect::<Result<Vec<(String, u32, String)>, _>>()
+            .map_err(sql_error)?;
+        let mut stats: Vec<MoveStats> = Vec::new();
+        for (moves, ply, result) in rows {
+            let san = match moves.split_whitespace().nth(ply as usize) {
+                Some(san) => san,
+                None => continue,
+            };
+            let index = match stats.iter().position(|stats| stats.san == san) {
+                Some(index) => index,
+                None => {
+                    stats.push(MoveStats {
+                        san: san.to_string(),
+                        games: 0,
+                        white_wins: 0,
+                        draws: 0,
+                        black_wins: 0,
+                    });
+                    stats.len() - 1
+                }
+            };
+            let entry = &mut stats[index];
+            entry.games += 1;
+            match result.as_str() {
+                "1-0" => entry.white_wins += 1,
+                "0-1" => entry.black_wins += 1,
+                "1/2-1/2" => entry.draws += 1,
+                _ => {}
+            }
+        }
+        stats.sort_by(|a, b| b.games.cmp(&a.games).then_with(|| a.san.cmp(&b.san)));
+        Ok(stats)
+    }
+
+    /// The evaluation after each move of game `id`, by ply, if it has been analyzed.
+    pub fn evaluations(&self, id: i64) -> Result<Vec<(u32, i32)>, Error> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT ply, score FROM evaluations WHERE game_id = ?1 ORDER BY ply")
+            .map_err(sql_error)?;
+        let rows = statement
+            .query_map([id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(sql_error)?;
+        rows.collect::<Result<_, _>>().map_err(sql_error)
+    }
+
+    /// Has `engine` evaluate the position after every move of game `id`, searching `depth`
+    /// plies, and stores the scores.
+    pub fn analyze(&mut self, id: i64, depth: u32, engine: &EngineSpec) -> Result<(), Error> {
+        let game = self.game(id)?;
+        let mut engine = engine.start()?;
+        engine.new_game()?;
+        let limits = Limits {
+            depth,
+            nodes: u64::MAX,
+            clocks: None,
+        };
+        let moves = game.tree.moves();
+        let mut board = game.tree.start;
+        let mut scores = Vec::new();
+        for (ply, &mv) in moves.iter().enumerate() {
+            board.apply_move(mv);
+            let score = engine
+                .search(&game.tree.start, &moves[..=ply], limits)?
+                .score;
+            let score = if board.side_to_move() == WHITE {
+                score
+            } else {
+                -score
+            };
+            scores.push((ply as u32 + 1, score));
+        }
+        let transaction = self.connection.transaction().map_err(sql_error)?;
+        transaction
+            .execute("DELETE FROM evaluations WHERE game_id = ?1", [id])
+            .map_err(sql_error)?;
+        for (ply, score) in scores {
+            transaction
+                .execute(
+                    "INSERT INTO evaluations (game_id, ply, score) VALUES (?1, ?2, ?3)",
+                    params![id, ply, score],
+                )
+                .map_err(sql_error)?;
+        }
+        transaction.commit().map_err(sql_error)
+    }
+}
+
+/// A fingerprint of the sequence of positions in `game`. Games that transpose into each other
+/// still differ, since every position counts in order.
+fn moves_hash(game: &PgnGame) -> i64 {
+    let mut board = game.tree.start;
+    let mut hash = board.zobrist();
+    for mv in game.tree.moves() {
+        board.apply_move(mv);
+        hash = hash.rotate_left(5) ^ board.zobrist();
+    }
+    hash as i64
+}
+
+/// Formats a score in centipawns as pawns, e.g. `+0.35`.
+fn pawns(score: i32) -> String {
+    format!("{:+.2}", score as f64 / 100.0)
+}
+
+/// Adds the evaluations of an analyzed game's main line to its PGN: each move gets an
+/// `[%eval]` comment, and a NAG marking it as a mistake by how much it lost for the side that
+/// played it.
+pub fn annotate(game: &mut PgnGame, evaluations: &[(u32, i32)]) {
+    let mut board = game.tree.start;
+    let mut cursor = Cursor::default();
+    let mut previous = None;
+    for ply in 1.. {
+        cursor = match game.tree.forward(&cursor) {
+            Some(cursor) => cursor,
+            None => break,
+        };
+        let node = match game.tree.node_mut(&cursor) {
+            Some(node) => node,
+            None => break,
+        };
+        let mover = board.side_to_move();
+        board.apply_move(node.mv);
+        let score = match evaluations.iter().find(|&&(evaluated, _)| evaluated == ply) {
+            Some(&(_, score)) => score,
+            None => {
+                previous = None;
+                continue;
+            }
+        };
+        let eval = format!("[%eval {}]", pawns(score));
+        node.comment = Some(match node.comment.take() {
+            Some(comment) => format!("{} {}", eval, comment),
+            None => eval,
+        });
+        if let Some(before) = previous {
+            let lost = if mover == WHITE {
+                before - score
+            } else {
+                score - before
+            };
+            let nag = match lost {
+                lost if lost >= BLUNDER => Some(4),
+                lost if lost >= MISTAKE => Some(2),
+                lost if lost >= INACCURACY => Some(6),
+                _ => None,
+            };
+            if let Some(nag) = nag.filter(|nag| !node.nags.contains(nag)) {
+                node.nags.push(nag);
+            }
+        }
+        previous = Some(score);
+    }
+}
+
+/// The analysis of game `id` for `db analyze --json`: for each move, in `notation`, the position it
+/// leads to, its legal moves and the evaluation from white's point of view, if there is one.
+fn analysis(database: &Database, id: i64, notation: Notation) -> Result<Vec<Value>, Error> {
+    let game = database.game(id)?;
+    let evaluations = database.evaluations(id)?;
+    let mut board = game.tree.start;
+    let moves = game.tree.moves();
+    let sans = board.line_notation(&moves, notation);
+    let mut analysis = Vec::new();
+    for (ply, (san, &mv)) in sans.iter().zip(&moves).enumerate() {
+        board.apply_move(mv);
+        let score = evaluations
+            .iter()
+            .find(|&&(evaluated, _)| evaluated == ply as u32 + 1)
+            .map(|&(_, score)| score);
+        analysis.push(json!({
+            "ply": ply + 1,
+            "move": san,
+            "fen": board.fen(),
+            "legal_moves": if board.is_done() { Vec::new() } else { board.legal_sans() },
+            "score": score,
+        }));
+    }
+    Ok(analysis)
+}
+
+/// Describes game `id` for `db show`: its tags, every move (in `notation`) with its evaluation
+/// (if analyzed) and the final position.
+fn show(database: &Database, id: i64, notation: Notation) -> Result<String, Error> {
+    let game = database.game(id)?;
+    let evaluations = database.evaluations(id)?;
+    let mut text = String::new();
+    for (name, value) in &game.tags {
+        text.push_str(&format!("{}: {}\n", name, value));
+    }
+    text.push_str(&format!("Result: {}\n\n", game.result));
+    let mut board = game.tree.start;
+    let moves = game.tree.moves();
+    let sans = board.line_notation(&moves, notation);
+    for (ply, (san, &mv)) in sans.iter().zip(&moves).enumerate() {
+        let number = board.moves / 2 + 1;
+        let dots = if board.side_to_move() == WHITE {
+            "."
+        } else {
+            "..."
+        };
+        let evaluation = evaluations
+            .iter()
+            .find(|&&(evaluated, _)| evaluated == ply as u32 + 1)
+            .map(|&(_, score)| format!(" {}", pawns(score)))
+            .unwrap_or_default();
+        text.push_str(&format!("{}{} {}{}\n", number, dots, san, evaluation));
+        board.apply_move(mv);
+    }
+    text.push_str(&format!("\n{}", board));
+    Ok(text)
+}
+
+/// Runs a `db` subcommand against the database at `path`, showing moves and exporting games in
+/// `notation`. With `json`, an analysis is printed as one JSON object per move instead.
+pub fn run(path: &Path, command: Command, notation: Notation, json: bool) -> Result<(), Error> {
+    let mut database = Database::open(path)?;
+    match command {
+        Command::Add(file) => {
+            let text = match file.as_str() {
+                "-" => {
+                    let mut text = String::new();
+                    std::io::stdin().read_to_string(&mut text)?;
+                    text
+                }
+                file => std::fs::read_to_string(file)?,
+            };
+            let (mut added, mut duplicates) = (0, 0);
+            for game in pgn::split_games(&text) {
+                let game = match PgnGame::parse_in(game, notation.letters()) {
+                    Ok(game) => game,
+                    Err(e) => {
+                        eprintln!("Skipping a game that couldn't be read: {}", e);
+                        continue;
+                    }
+                };
+                match database.store(&game)? {
+                    Stored::Added(id) => {
+                        println!("Added game {}.", id);
+                        added += 1;
+                    }
+                    Stored::Duplicate(id) => {
+                        println!("Skipped a duplicate of game {}.", id);
+                        duplicates += 1;
+                    }
+                }
+            }
+            println!("Added {} games, skipped {} duplicates.", added, duplicates);
+        }
+        Command::List => {
+            for game in database.list()? {
+                println!(
+                    "{:>5}  {}  {} - {}  {}  ({} moves)",
+                    game.id, game.date, game.white, game.black, game.result, game.moves
+                );
+            }
+        }
+        Command::Show(id) => print!("{}", show(&database, id, notation)?),
+        Command::ExportPgn(output) => {
+            let games = database
+                .ids()?
+                .into_iter()
+                .map(|id| {
+                    let mut game = database.game(id)?;
+                    annotate(&mut game, &database.evaluations(id)?);
+                    Ok(game.write(notation))
+                })
+                .collect::<Result<Vec<String>, Error>>()?;
+            match output {
+                Some(output) => std::fs::write(output, games.join("\n"))?,
+                None => print!("{}", games.join("\n")),
+            }
+        }
+        Command::Analyze(id, depth, engine) => {
+            database.analyze(id, depth, &engine)?;
+            match json {
+                true => {
+                    for analyzed in analysis(&database, id, notation)? {
+                        println!("{}", analyzed);
+                    }
+                }
+                false => print!("{}", show(&database, id, notation)?),
+            }
+        }
+        Command::FindPosition(fen) => {
+            let board = ChessBoard::from_fen(&fen)
+                .map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
+            let occurrences = database.find_position(&board)?;
+            for found in &occurrences {
+                let game = &found.game;
+                println!(
+                    "{:>5}  {}  {} - {}  {}  at ply {}, then {}",
+                    game.id,
+                    game.date,
+                    game.white,
+                    game.black,
+                    game.result,
+                    found.ply,
+                    found.next_move.as_deref().unwrap_or("the game ended")
+                );
+            }
+            println!("Found in {} games.", occurrences.len());
+        }
+        Command::Explorer(fen) => {
+            let board = match fen {
+                Some(fen) => ChessBoard::from_fen(&fen)
+                    .map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?,
+                None => ChessBoard::new(),
+            };
+            let stdin = std::io::stdin();
+            explorer::explore(&database, board, &mut stdin.lock(), &mut std::io::stdout())?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_database() {
+    let mut database = Database::with_connection(Connection::open_in_memory().unwrap()).unwrap();
+    let text = "[Event \"Casual\"]\n[Date \"2024.01.02\"]\n[White \"Fool\"]\n[Black \"Mate\"]\n\n\
+        1. f3 e5 2. g4 Qh4# 0-1\n";
+    let game = PgnGame::parse(text).unwrap();
+    let id = database.add(&game).unwrap();
+    database.add(&PgnGame::parse("1. e4 *").unwrap()).unwrap();
+
+    let games = database.list().unwrap();
+    assert_eq!(games.len(), 2);
+    assert_eq!(
+        games[0],
+        Summary {
+            id,
+            white: "Fool".to_string(),
+            black: "Mate".to_string(),
+            date: "2024.01.02".to_string(),
+            result: "0-1".to_string(),
+            moves: 2,
+        }
+    );
+    assert_eq!(games[1].white, "?");
+
+    // The same game again is a duplicate, but not the same moves on another day.
+    assert_eq!(database.store(&game).unwrap(), Stored::Duplicate(id));
+    let mut rematch = PgnGame::parse(text).unwrap();
+    rematch.set_tag("Date", "2024.01.03");
+    assert!(database.find_duplicate(&rematch).unwrap().is_none());
+
+    let stored = database.game(id).unwrap();
+    assert_eq!(stored.to_pgn(), game.to_pgn());
+    assert!(database.game(99).is_err());
+
+    assert!(database.evaluations(id).unwrap().is_empty());
+    database
+        .analyze(id, 1, &EngineSpec::Internal(Default::default()))
+        .unwrap();
+    let evaluations = database.evaluations(id).unwrap();
+    assert_eq!(evaluations.len(), 4);
+    // Black has just mated.
+    assert!(evaluations[3].1 <= -crate::search::MATE + 100);
+    let analyzed = analysis(&database, id, Notation::default()).unwrap();
+    assert_eq!(analyzed.len(), 4);
+    assert_eq!(analyzed[0]["move"], "f3");
+    assert_eq!(
+        analyzed[0]["fen"],
+        "rnbqkbnr/pppppppp/8/8/8/5P2/PPPPP1PP/RNBQKBNR b KQkq - 0 1"
+    );
+    assert_eq!(analyzed[0]["legal_moves"].as_array().unwrap().len(), 20);
+    assert_eq!(analyzed[0]["score"], evaluations[0].1);
+    assert_eq!(analyzed[3]["legal_moves"], json!([]));
+    let mut annotated = database.game(id).unwrap();
+    annotate(&mut annotated, &[(1, 20), (2, 30), (3, -250), (4, -30_000)]);
+    let annotated = annotated.to_pgn();
+    assert!(annotated.contains("1. f3 {[%eval +0.20]} 1... e5 {[%eval +0.30]} 2. g4 $2"));
+    assert!(annotated.contains("Qh4# {[%eval -300.00]} 0-1"));
+    // The Italian, reached by two move orders, and a game that never gets there.
+    let italian = PgnGame::parse("1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 1/2-1/2").unwrap();
+    let italian = database.add(&italian).unwrap();
+    let transposed = PgnGame::parse("1. Nf3 Nc6 2. e4 e5 3. Bc4 Nf6 1-0").unwrap();
+    let transposed = database.add(&transposed).unwrap();
+    let board =
+        ChessBoard::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3")
+            .unwrap();
+    let found = database.find_position(&board).unwrap();
+    assert_eq!(found.len(), 2);
+    assert_eq!((found[0].game.id, found[0].ply), (italian, 5));
+    assert_eq!(found[0].next_move.as_deref(), Some("Bc5"));
+    assert_eq!(
+        (found[1].game.id, found[1].next_move.as_deref()),
+        (transposed, Some("Nf6"))
+    );
+    assert_eq!(database.find_position(&ChessBoard::new()).unwrap().len(), 4);
+
+    let explored = database.explore(&ChessBoard::new()).unwrap();
+    assert_eq!(
+        explored
+            .iter()
+            .map(|stats| (stats.san.as_str(), stats.games))
+            .collect::<Vec<_>>(),
+        [("e4", 2), ("Nf3", 1), ("f3", 1)]
+    );
+    assert_eq!(
+        (
+            explored[0].white_wins,
+            explored[0].draws,
+            explored[0].black_wins
+        ),
+        (0, 1, 0)
+    );
+    assert_eq!(database.explore(&board).unwrap().len(), 2);
+
+    let shown = show(&database, id, Notation::default()).unwrap();
+    assert!(shown.contains("White: Fool\n"));
+    assert!(shown.contains("2... Qh4# -"));
+}
@@ -0,0 +1,363 @@
+//! Tools for tracking down bugs in the board, the move generator and the evaluation: `debug
+//! dump`, everything the engine knows about the current position written out, and `validate`,
+//! which checks that the parts of a position agree with each other.
+
+use std::fmt::Write;
+
+use crate::eval::{self, TERM_NAMES};
+use crate::movegen::{color_name, square_name, CORNERS, NO_SQUARE};
+use crate::{ChessBoard, BLACK, EMPTY, GET_NUM, KING, PAWN, PIECE_LETTERS, ROOK, WHITE};
+
+/// The names of the pieces, indexed by piece number.
+const PIECE_NAMES: [&str; 7] = [
+    "", "pawns", "knights", "rooks", "bishops", "queens", "kings",
+];
+
+impl ChessBoard {
+    /// The squares holding `piece` (with its color), as bits `1 << square`.
+    fn bitboard(&self, piece: u8) -> u64 {
+        (0..64)
+            .filter(|&square| self.get_piece(square) == piece)
+            .fold(0, |bits, square| bits | 1 << square)
+    }
+
+    /// Describes the position as the engine sees it: its FEN and Zobrist key, castling rights
+    /// and en passant square, a bitboard for each piece, the terms of the classical evaluation
+    /// and how many legal moves there are.
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        let fen = self.fen();
+        let fields = fen.split(' ').collect::<Vec<&str>>();
+        // Writing to a String can't fail.
+        let _ = writeln!(dump, "FEN: {}", fen);
+        let _ = writeln!(dump, "Variant: {}", self.variant.name());
+        let _ = writeln!(dump, "Zobrist key: {:#018x}", self.zobrist());
+        let _ = writeln!(dump, "Side to move: {}", color_name(self.side_to_move()));
+        let rooks = (0..4)
+            .filter(|&right| self.castling & 1 << right != 0)
+            .map(|right| square_name(self.castling_rooks[right]))
+            .collect::<Vec<String>>();
+        let _ = match rooks.is_empty() {
+            true => writeln!(dump, "Castling rights: none"),
+            false => writeln!(
+                dump,
+                "Castling rights: {} (with the rooks on {})",
+                fields[2],
+                rooks.join(", ")
+            ),
+        };
+        let _ = match self.en_passant {
+            NO_SQUARE => writeln!(dump, "En passant: none"),
+            square => writeln!(dump, "En passant: {}", square_name(square)),
+        };
+        let _ = writeln!(
+            dump,
+            "Halfmove clock: {}, plies played: {}",
+            self.halfmoves, self.moves
+        );
+
+        let _ = writeln!(dump, "\nBitboards:");
+        for color in [WHITE, BLACK] {
+            for piece in 1..=6 {
+                let bits = self.bitboard(piece | color);
+                let squares = (0..64)
+                    .filter(|&square| bits & 1 << square != 0)
+                    .map(square_name)
+                    .collect::<Vec<String>>();
+                let _ = writeln!(
+                    dump,
+                    "  {} {} {:<8} {:#018x} {}",
+                    PIECE_LETTERS[(piece | color) as usize],
+                    color_name(color),
+                    PIECE_NAMES[piece as usize],
+                    bits,
+                    squares.join(" ")
+                );
+            }
+        }
+
+        let _ = writeln!(dump, "\nEvaluation terms, from white's point of view:");
+        let terms = eval::terms(self);
+        for (name, value) in TERM_NAMES.iter().zip(terms) {
+            let _ = writeln!(dump, "  {:<14} {:>6}", name, value);
+        }
+        let _ = writeln!(dump, "  {:<14} {:>6}", "Total", terms.iter().sum::<i32>());
+        let _ = writeln!(
+            dump,
+            "Evaluation for the side to move: {}",
+            eval::evaluate(self)
+        );
+        let _ = writeln!(dump, "Legal moves: {}", self.legal_moves().len());
+        dump
+    }
+
+    /// Checks that the parts of the position agree with each other, describing the first that
+    /// doesn't. Every square of the packed board must hold a piece or be empty (and empty
+    /// squares off the variant's board), there may be no pawns on the first or last rank and no
+    /// more than one king per side where kings are royal, each castling right needs its king and
+    /// rook on their first rank, the en passant square must be one a pawn just skipped, and
+    /// pieces in hand, promoted pieces and checks given only appear in variants that have them.
+    ///
+    /// The bitboards and the Zobrist key are worked out from the packed board whenever they are
+    /// needed, so they agree with it as long as it holds together. Debug builds check every
+    /// position `apply_move` plays into, including those the move generator only tries out.
+    pub fn validate(&self) -> Result<(), String> {
+        let (files, ranks) = self.variant.dimensions();
+        let mut kings = [0; 2];
+        for square in 0..64 {
+            let piece = self.get_piece(square);
+            if GET_NUM(piece) == KING {
+                kings[(piece >> 3) as usize] += 1;
+            }
+            if GET_NUM(piece) > KING || (GET_NUM(piece) == EMPTY && piece != EMPTY) {
+                return Err(format!(
+                    "{} holds {:#x}, which is no piece.",
+                    square_name(square),
+                    piece
+                ));
+            }
+            if piece != EMPTY && !self.is_on_board(square) {
+                return Err(format!(
+                    "{} holds a piece but is off the board.",
+                    square_name(square)
+                ));
+            }
+        }
+        let last_rank = (ranks - 1) * 8;
+        if let Some(square) = (0..files)
+            .chain(last_rank..last_rank + files)
+            .find(|&square| GET_NUM(self.get_piece(square)) == PAWN)
+        {
+            return Err(format!("There is a pawn on {}.", square_name(square)));
+        }
+        for (color, kings) in [WHITE, BLACK].into_iter().zip(kings) {
+            if self.variant.has_royal_king() && kings > 1 {
+                return Err(format!("There are {} {} kings.", kings, color_name(color)));
+            }
+        }
+
+        for (index, &rook) in self.castling_rooks.iter().enumerate() {
+            if self.castling & 1 << index == 0 {
+                continue;
+            }
+            let color = if index < 2 { WHITE } else { BLACK };
+            let home = if color == WHITE { 0 } else { 56 };
+            // A king can only be missing in positions the move generator tries out, where it
+            // was taken, or after an explosion.
+            let Some(king) = self.king_square(color) else {
+                continue;
+            };
+            if king & !7 != home {
+                return Err(format!(
+                    "The castling rights need the {} king on its first rank.",
+                    color_name(color)
+                ));
+            }
+            let beside = match index % 2 {
+                0 => rook > king,
+                _ => rook < king,
+            };
+            if self.get_piece(rook) != ROOK | color || rook & !7 != home || !beside {
+                return Err(format!(
+                    "The castling rights need a {} rook on {}.",
+                    color_name(color),
+                    square_name(rook)
+                ));
+            }
+            if (rook != CORNERS[index] || king != home + 4) && !self.chess960 {
+                return Err(format!(
+                    "Castling with a {} king on {} and rook on {} needs Chess960.",
+                    color_name(color),
+                    square_name(king),
+                    square_name(rook)
+                ));
+            }
+        }
+
+        if self.en_passant != NO_SQUARE {
+            // The pawn that skipped the square stands in front of it, and left from behind it.
+            let (rank, pawn, start) = match self.side_to_move() {
+                WHITE => (5, self.en_passant.wrapping_sub(8), self.en_passant + 8),
+                _ => (2, self.en_passant + 8, self.en_passant.wrapping_sub(8)),
+            };
+            let mover = PAWN | (self.side_to_move() ^ BLACK);
+            if (ranks, self.en_passant >> 3) != (8, rank)
+                || self.get_piece(self.en_passant) != EMPTY
+                || self.get_piece(pawn) != mover
+                || self.get_piece(start) != EMPTY
+            {
+                return Err(format!(
+                    "No pawn can just have skipped the en passant square {}.",
+                    square_name(self.en_passant)
+                ));
+            }
+        }
+
+        if !self.variant.has_pockets() {
+            if self.pockets != [[0; 6]; 2] {
+                return Err("There are pieces in hand outside of a variant with drops.".to_string());
+            }
+        } else if let Some(square) = (0..64).find(|&square| {
+            let piece = GET_NUM(self.get_piece(square));
+            self.promoted & 1 << square != 0 && (piece == EMPTY || piece == PAWN || piece == KING)
+        }) {
+            return Err(format!(
+                "{} is marked as promoted but holds no promoted piece.",
+                square_name(square)
+            ));
+        }
+        match self.variant.check_limit() {
+            None if self.checks != [0; 2] => {
+                Err("Checks are counted outside of a variant that counts them.".to_string())
+            }
+            Some(limit) if self.checks.iter().any(|&checks| checks > limit) => {
+                Err(format!("More than {} checks have been given.", limit))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks every line of legal moves `depth` plies long from the position: each move must
+    /// lead to a position that passes `validate`, and unmaking it must give back the position it
+    /// was made from, Zobrist key and all. Describes the first line that doesn't, for fuzzing
+    /// and property tests.
+    pub fn check_make_unmake(&self, depth: u32) -> Result<(), String> {
+        if depth == 0 {
+            return Ok(());
+        }
+        let mut board = *self;
+        for mv in self.legal_moves() {
+            let undo = board.make_move(mv);
+            let checked = board
+                .validate()
+                .and_then(|()| board.check_make_unmake(depth - 1));
+            board.unmake_move(undo);
+            if let Err(error) = checked {
+                return Err(format!("{} {}", mv, error));
+            }
+            if board != *self || board.zobrist() != self.zobrist() {
+                return Err(format!("{}: unmaking it changed the position.", mv));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_dump() {
+    let board = ChessBoard::from_fen(
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    )
+    .unwrap();
+    let dump = board.dump();
+    assert!(dump.starts_with("FEN: r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w "));
+    assert!(dump.contains(&format!("Zobrist key: {:#018x}\n", board.zobrist())));
+    assert!(dump.contains("Castling rights: KQkq (with the rooks on h1, a1, h8, a8)\n"));
+    assert!(dump.contains("En passant: none\n"));
+    assert!(dump.contains("  K white kings    0x0000000000000010 e1\n"));
+    assert!(dump.contains("  n black knights  0x0000220000000000 b6 f6\n"));
+    assert!(dump.contains("Legal moves: 48\n"));
+    let total = eval::terms(&board).iter().sum::<i32>();
+    assert!(dump.contains(&format!("  Total          {:>6}\n", total)));
+
+    let board = ChessBoard::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 3").unwrap();
+    let dump = board.dump();
+    assert!(dump.contains("Castling rights: none\n"));
+    assert!(dump.contains("En passant: d6\n"));
+}
+
+#[test]
+fn test_validate() {
+    use crate::variant::by_name;
+
+    // Every position reached in a game, including the trial positions of the move generator.
+    for name in [
+        "standard",
+        "chess960",
+        "crazyhouse",
+        "atomic",
+        "threecheck",
+        "gardner",
+    ] {
+        let mut board = ChessBoard::start_of(by_name(name).unwrap());
+        assert_eq!(board.validate(), Ok(()), "{}", name);
+        for _ in 0..12 {
+            let Some(&mv) = board.legal_moves().last() else {
+                break;
+            };
+            board.apply_move(mv);
+            assert_eq!(board.validate(), Ok(()), "{} {}", name, board.fen());
+        }
+    }
+    let board = ChessBoard::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 3").unwrap();
+    assert_eq!(board.validate(), Ok(()));
+
+    let broken = |change: fn(&mut ChessBoard)| {
+        let mut board = ChessBoard::new();
+        change(&mut board);
+        board.validate().unwrap_err()
+    };
+    assert_eq!(
+        broken(|board| board.set_piece(20, BLACK)),
+        "e3 holds 0x8, which is no piece."
+    );
+    assert_eq!(
+        broken(|board| board.set_piece(56, PAWN)),
+        "There is a pawn on a8."
+    );
+    assert_eq!(
+        broken(|board| board.set_piece(20, KING)),
+        "There are 2 white kings."
+    );
+    assert_eq!(
+        broken(|board| board.set_piece(7, EMPTY)),
+        "The castling rights need a white rook on h1."
+    );
+    assert_eq!(
+        broken(|board| {
+            board.set_piece(60, EMPTY);
+            board.set_piece(44, KING | BLACK);
+        }),
+        "The castling rights need the black king on its first rank."
+    );
+    assert_eq!(
+        broken(|board| board.en_passant = 20),
+        "No pawn can just have skipped the en passant square e3."
+    );
+    assert_eq!(
+        broken(|board| board.pockets[0][PAWN as usize] = 1),
+        "There are pieces in hand outside of a variant with drops."
+    );
+    assert_eq!(
+        broken(|board| board.checks[1] = 1),
+        "Checks are counted outside of a variant that counts them."
+    );
+    let mut gardner = ChessBoard::start_of(by_name("gardner").unwrap());
+    gardner.set_piece(63, KING | BLACK);
+    assert_eq!(
+        gardner.validate().unwrap_err(),
+        "h8 holds a piece but is off the board."
+    );
+}
+
+#[test]
+fn test_make_unmake() {
+    use crate::variant::by_name;
+
+    let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let board = ChessBoard::from_fen(kiwipete).unwrap();
+    assert_eq!(board.check_make_unmake(2), Ok(()));
+    for name in ["crazyhouse", "atomic", "threecheck", "antichess"] {
+        let board = ChessBoard::from_fen(kiwipete)
+            .unwrap()
+            .with_variant(by_name(name).unwrap());
+        assert_eq!(board.check_make_unmake(2), Ok(()), "{}", name);
+    }
+
+    let start = ChessBoard::from_fen(kiwipete).unwrap();
+    let mut board = start;
+    let undo = board.make_move(board.parse_san("Qxf6").unwrap());
+    assert!(board != start);
+    board.unmake_move(undo);
+    assert!(board == start);
+}
@@ -0,0 +1,333 @@
+//! Over-the-board play on a DGT electronic board, or one that speaks its protocol, connected by
+//! USB or a serial port. The board reports every piece lifted or put down; whenever what stands
+//! on it is the position after a legal move, that move is played. The engine's replies are
+//! announced here, for the player to make on the board, since it can't move the pieces itself.
+//!
+//! Setting up the starting position starts a new game, with the board either way round.
+
+use std::fs::OpenOptions;
+use std::io::{Error, ErrorKind, Read, Write};
+
+use crate::movegen::Move;
+use crate::search::MAX_DEPTH;
+use crate::spoken::spoken_move;
+use crate::ChessBoard;
+
+/// Commands to the board: reset, send the whole board, then report every change.
+const DGT_SEND_RESET: u8 = 0x40;
+const DGT_SEND_BRD: u8 = 0x42;
+const DGT_SEND_UPDATE_BRD: u8 = 0x44;
+
+/// The messages from the board this reads.
+const DGT_BOARD_DUMP: u8 = 0x86;
+const DGT_FIELD_UPDATE: u8 = 0x8e;
+
+/// The pieces by the board's codes, as FEN writes them.
+const PIECES: [char; 13] = [
+    ' ', 'P', 'R', 'N', 'B', 'K', 'Q', 'p', 'r', 'n', 'b', 'k', 'q',
+];
+
+/// A message from the board.
+#[derive(Debug, PartialEq)]
+enum Message {
+    /// The piece on every square, a8 to h8, then a7 and so on down to h1.
+    Dump([u8; 64]),
+    /// The piece now on one square, 0 if it was lifted.
+    FieldUpdate {
+        field: u8,
+        piece: u8,
+    },
+    Other(u8),
+}
+
+/// Splits what the board sends into messages: an id with its top bit set, the size of the whole
+/// message in two 7-bit bytes, then the data.
+#[derive(Default)]
+struct Decoder {
+    buffer: Vec<u8>,
+}
+
+impl Decoder {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Message> {
+        self.buffer.extend_from_slice(bytes);
+        let mut messages = Vec::new();
+        loop {
+            // Only ids have the top bit set, so anything before one is noise.
+            let start = self.buffer.iter().position(|byte| byte & 0x80 != 0);
+            self.buffer.drain(..start.unwrap_or(self.buffer.len()));
+            if self.buffer.len() < 3 {
+                return messages;
+            }
+            let size = ((self.buffer[1] as usize) << 7) | self.buffer[2] as usize;
+            if size < 3 {
+                self.buffer.remove(0);
+                continue;
+            }
+            if self.buffer.len() < size {
+                return messages;
+            }
+            let message = self.buffer.drain(..size).collect::<Vec<u8>>();
+            messages.push(match (message[0], &message[3..]) {
+                (DGT_BOARD_DUMP, squares) if squares.len() == 64 => {
+                    Message::Dump(squares.try_into().unwrap())
+                }
+                (DGT_FIELD_UPDATE, &[field, piece]) => Message::FieldUpdate { field, piece },
+                (id, _) => Message::Other(id),
+            });
+        }
+    }
+}
+
+/// What stands on the board, written as the first field of a FEN string. `flipped` reads it
+/// with black on the side of the board's a1.
+fn placement(squares: &[u8; 64], flipped: bool) -> String {
+    let mut squares = *squares;
+    if flipped {
+        squares.reverse();
+    }
+    let mut placement = String::new();
+    for (rank, pieces) in squares.chunks(8).enumerate() {
+        let mut empty = 0;
+        for &piece in pieces {
+            match piece {
+                0 => empty += 1,
+                piece => {
+                    if empty > 0 {
+                        placement.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    // Codes beyond the pieces never match a position.
+                    placement.push(*PIECES.get(piece as usize).unwrap_or(&'?'));
+                }
+            }
+        }
+        if empty > 0 {
+            placement.push_str(&empty.to_string());
+        }
+        if rank < 7 {
+            placement.push('/');
+        }
+    }
+    placement
+}
+
+/// A game played on the board.
+struct Session {
+    /// The game so far, once the starting position has been set up.
+    board: Option<ChessBoard>,
+    /// What stands on the board now.
+    squares: [u8; 64],
+    /// Whether the board is turned around.
+    flipped: bool,
+    /// The side the engine plays, if any, and how many positions it searches for a move.
+    engine: Option<u8>,
+    nodes: u64,
+    /// The engine's move, while it waits to be made on the board.
+    reply: Option<Move>,
+}
+
+impl Session {
+    fn new(engine: Option<u8>, nodes: u64) -> Session {
+        Session {
+            board: None,
+            squares: [0; 64],
+            flipped: false,
+            engine,
+            nodes,
+            reply: None,
+        }
+    }
+
+    /// Has the engine choose its move, if it's its turn.
+    fn think(&mut self, board: &ChessBoard) -> Option<String> {
+        if self.engine != Some(board.side_to_move()) || board.outcome().is_some() {
+            return None;
+        }
+        let mv = board.search_nodes(MAX_DEPTH, self.nodes).best_move?;
+        self.reply = Some(mv);
+        Some(format!(
+            "The engine plays {}: {}. Make the move on the board.",
+            board.san(mv),
+            spoken_move(board, mv)
+        ))
+    }
+
+    /// Takes in what stands on the board now, returning what to tell the player.
+    fn update(&mut self) -> Vec<String> {
+        let mut messages = Vec::new();
+        let standing = placement(&self.squares, self.flipped);
+        if let Some(board) = self.board.filter(|board| board.outcome().is_none()) {
+            if standing == board.placement() {
+                return messages;
+            }
+            // Anything else is a move being made, until it lands on a legal one.
+            let played = board.legal_moves().iter().copied().find(|&mv| {
+                let mut after = board;
+                after.apply_move(mv);
+                after.placement() == standing
+            });
+            if let Some(mv) = played {
+                return self.play(board, mv);
+            }
+        }
+
+        // Setting up the pieces, either way round, starts a new game.
+        let start = ChessBoard::new();
+        for flipped in [self.flipped, !self.flipped] {
+            if placement(&self.squares, flipped) == start.placement() {
+                self.flipped = flipped;
+                self.board = Some(start);
+                self.reply = None;
+                messages.push("New game.".to_string());
+                messages.extend(self.think(&start));
+                break;
+            }
+        }
+        messages
+    }
+
+    /// Plays `mv`, made on the board in the position `board`.
+    fn play(&mut self, mut board: ChessBoard, mv: Move) -> Vec<String> {
+        if let Some(reply) = self.reply.filter(|&reply| reply != mv) {
+            return vec![format!(
+                "That isn't the engine's move. It plays {}.",
+                board.san(reply)
+            )];
+        }
+        let mut messages = vec![format!("{}.", board.san(mv))];
+        board.apply_move(mv);
+        self.board = Some(board);
+        self.reply = None;
+        if let Some((result, reason)) = board.outcome() {
+            messages.push(format!(
+                "Game over: {} by {}. Set up the pieces again for a new game.",
+                result,
+                reason.replace('_', " ")
+            ));
+        }
+        messages.extend(self.think(&board));
+        messages
+    }
+}
+
+/// Sets the serial port up the way DGT boards talk: 9600 baud, 8 data bits, no parity, one stop
+/// bit, and no translation of the bytes.
+#[cfg(unix)]
+fn configure(path: &str) -> Result<(), Error> {
+    let status = std::process::Command::new("stty")
+        .args(["9600", "cs8", "-parenb", "-cstopb", "raw", "-echo"])
+        .stdin(std::fs::File::open(path)?)
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(Error::other(format!("stty couldn't set up {}.", path))),
+    }
+}
+
+/// Elsewhere, the port is set up beforehand, as with `mode COM3 BAUD=9600 DATA=8 PARITY=n`.
+#[cfg(not(unix))]
+fn configure(_path: &str) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Plays games on the board connected at `path` until the process is stopped or the board is
+/// disconnected. The engine plays `engine`, if any, searching `nodes` positions a move.
+pub fn run(path: &str, engine: Option<u8>, nodes: u64) -> Result<(), Error> {
+    configure(path)?;
+    let mut port = OpenOptions::new().read(true).write(true).open(path)?;
+    port.write_all(&[DGT_SEND_RESET, DGT_SEND_BRD, DGT_SEND_UPDATE_BRD])?;
+    println!(
+        "Connected to the board on {}. Set up the pieces to start.",
+        path
+    );
+
+    let mut session = Session::new(engine, nodes);
+    let mut decoder = Decoder::default();
+    let mut buffer = [0; 256];
+    loop {
+        let read = port.read(&mut buffer)?;
+        if read == 0 {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "The board was disconnected.",
+            ));
+        }
+        for message in decoder.feed(&buffer[..read]) {
+            match message {
+                Message::Dump(squares) => session.squares = squares,
+                Message::FieldUpdate { field, piece } if field < 64 => {
+                    session.squares[field as usize] = piece
+                }
+                _ => continue,
+            }
+            tracing::debug!(target: "dgt", "{}", placement(&session.squares, session.flipped));
+            for line in session.update() {
+                println!("{}", line);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_dgt() {
+    let mut start = [0; 64];
+    start[..8].copy_from_slice(&[8, 9, 10, 12, 11, 10, 9, 8]);
+    start[8..16].fill(7);
+    start[48..56].fill(1);
+    start[56..].copy_from_slice(&[2, 3, 4, 6, 5, 4, 3, 2]);
+    assert_eq!(placement(&start, false), ChessBoard::new().placement());
+
+    // A dump split across reads, with noise before it, then two field updates at once.
+    let mut bytes = vec![0x12, DGT_BOARD_DUMP, 0, 67];
+    bytes.extend_from_slice(&start);
+    let mut decoder = Decoder::default();
+    assert_eq!(decoder.feed(&bytes[..40]), vec![]);
+    assert_eq!(decoder.feed(&bytes[40..]), vec![Message::Dump(start)]);
+    let e2 = 52;
+    let updates = [DGT_FIELD_UPDATE, 0, 5, e2, 0, DGT_FIELD_UPDATE, 0, 5, 36, 1];
+    assert_eq!(
+        decoder.feed(&updates),
+        vec![
+            Message::FieldUpdate {
+                field: e2,
+                piece: 0
+            },
+            Message::FieldUpdate {
+                field: 36,
+                piece: 1
+            }
+        ]
+    );
+
+    // The engine plays black, on a board turned around.
+    let mut session = Session::new(Some(crate::BLACK), 1);
+    session.squares = start;
+    session.squares.reverse();
+    assert_eq!(session.update(), vec!["New game."]);
+    assert!(session.flipped);
+    // Moves a piece between squares of the board, as numbered unturned.
+    fn play(session: &mut Session, from: usize, to: usize) -> Vec<String> {
+        let (from, to) = (63 - from, 63 - to);
+        let piece = session.squares[from];
+        session.squares[from] = 0;
+        // Lifting the piece plays nothing yet.
+        assert!(session.update().is_empty());
+        session.squares[to] = piece;
+        session.update()
+    }
+    let messages = play(&mut session, e2 as usize, 36);
+    assert_eq!(messages[0], "e4.");
+    assert!(messages[1].starts_with("The engine plays "));
+    let reply = session.reply.unwrap();
+    // Moving something else for the engine is refused.
+    let board = session.board.unwrap();
+    let other = board.legal_moves().iter().copied().find(|&mv| mv != reply);
+    let other = other.unwrap();
+    let field = |square: u8| (7 - square as usize / 8) * 8 + square as usize % 8;
+    let messages = play(&mut session, field(other.from), field(other.to));
+    assert!(messages[0].starts_with("That isn't the engine's move."));
+    assert!(play(&mut session, field(other.to), field(other.from)).is_empty());
+    let messages = play(&mut session, field(reply.from), field(reply.to));
+    assert_eq!(messages, vec![format!("{}.", board.san(reply))]);
+    assert!(session.reply.is_none());
+}
@@ -0,0 +1,458 @@
+//! Engines to play and analyze with: the engine's own search, or another engine run as a
+//! process of its own and spoken to over UCI. An external engine is started with the UCI
+//! handshake and given its options; if it dies, it is started again and the search retried.
+
+use std::io::{BufRead, BufReader, Error, ErrorKind, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::movegen::Move;
+use crate::search::{self, SearchParams, SearchResult, SEARCH_HASH_MB};
+use crate::time_control;
+use crate::tt::TranspositionTable;
+use crate::uci;
+use crate::{ChessBoard, BLACK};
+
+/// How long an external engine has to answer `uci` and `isready`.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long an external engine has to exit after `quit` before it is killed.
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How far a search may go: to `depth` plies, through at most `nodes` positions, and in a game on
+/// the clock, with `clocks` the time white and black have left in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub depth: u32,
+    pub nodes: u64,
+    pub clocks: Option<[u32; 2]>,
+}
+
+/// An engine as chosen on the command line, which can be started as often as needed, such as
+/// once for every thread of a match.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EngineSpec {
+    /// The engine's own search, with these parameters.
+    Internal(SearchParams),
+    /// An external UCI engine, run by `command` (a program and its arguments, separated by
+    /// spaces) and given the `options` by name and value.
+    Uci {
+        command: String,
+        options: Vec<(String, String)>,
+    },
+}
+
+impl EngineSpec {
+    /// Starts the engine. An external one has answered the handshake once this returns.
+    pub fn start(&self) -> Result<Engine, Error> {
+        match self {
+            EngineSpec::Internal(params) => Ok(Engine::Internal(*params)),
+            EngineSpec::Uci { command, options } => {
+                UciEngine::start(command, options).map(Engine::Uci)
+            }
+        }
+    }
+}
+
+/// Reads an engine option as `--option` takes it, `NAME=VALUE`.
+pub fn parse_option(text: &str) -> Option<(String, String)> {
+    let (name, value) = text.split_once('=')?;
+    Some((name.trim().to_string(), value.trim().to_string()))
+}
+
+/// The name this crate's own engine goes by.
+pub fn internal_name() -> String {
+    format!("FreightChess {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// A started engine.
+pub enum Engine {
+    Internal(SearchParams),
+    Uci(UciEngine),
+}
+
+impl Engine {
+    /// The engine's name, as an external engine gives it with `id name`.
+    pub fn name(&self) -> String {
+        match self {
+            Engine::Internal(_) => internal_name(),
+            Engine::Uci(engine) => engine.name.clone(),
+        }
+    }
+
+    /// Tells the engine that a new game starts.
+    pub fn new_game(&mut self) -> Result<(), Error> {
+        match self {
+            Engine::Internal(_) => Ok(()),
+            Engine::Uci(engine) => match engine.new_game() {
+                Err(e) if has_died(&e) => engine.restart(&e),
+                result => result,
+            },
+        }
+    }
+
+    /// Searches the position `moves` lead to from `start` within `limits`. The score is the
+    /// side to move's, as `ChessBoard::search` gives it. An external engine that has died is
+    /// started again, once, and asked again.
+    pub fn search(
+        &mut self,
+        start: &ChessBoard,
+        moves: &[Move],
+        limits: Limits,
+    ) -> Result<SearchResult, Error> {
+        match self.search_once(start, moves, limits) {
+            Err(e) if has_died(&e) => {
+                if let Engine::Uci(engine) = self {
+                    engine.restart(&e)?;
+                }
+                self.search_once(start, moves, limits)
+            }
+            result => result,
+        }
+    }
+
+    /// Searches like `search`, but leaves an external engine that has died as it is, so a match
+    /// can forfeit its game.
+    pub fn search_once(
+        &mut self,
+        start: &ChessBoard,
+        moves: &[Move],
+        limits: Limits,
+    ) -> Result<SearchResult, Error> {
+        match self {
+            Engine::Internal(params) => {
+                let mut board = *start;
+                let mut history = Vec::new();
+                for &mv in moves {
+                    history.push(board.zobrist());
+                    board.apply_move(mv);
+                }
+                let previous = search::params();
+                search::set_params(*params);
+                let time = limits.clocks.map(|clocks| {
+                    time_control::move_time(clocks[(board.side_to_move() == BLACK) as usize])
+                });
+                let table = TranspositionTable::new(SEARCH_HASH_MB);
+                let result =
+                    board.search_threads(limits.depth, limits.nodes, time, 1, &table, &history);
+                search::set_params(previous);
+                Ok(result)
+            }
+            Engine::Uci(engine) => engine.search(start, moves, limits),
+        }
+    }
+}
+
+/// Returns `true` if `e` means the engine's process is gone.
+fn has_died(e: &Error) -> bool {
+    matches!(e.kind(), ErrorKind::BrokenPipe | ErrorKind::UnexpectedEof)
+}
+
+/// An external engine's process, with what it is run and set up with, to start it again.
+pub struct UciEngine {
+    command: String,
+    options: Vec<(String, String)>,
+    /// The name from its `id name`, or its command until it gives one.
+    name: String,
+    child: Child,
+    input: ChildStdin,
+    /// Its output, a line at a time, read on a thread of its own so it can be waited for with
+    /// a timeout.
+    lines: Receiver<String>,
+}
+
+impl UciEngine {
+    /// Runs `command` and does the handshake, setting `options`.
+    fn start(command: &str, options: &[(String, String)]) -> Result<UciEngine, Error> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "The engine command is empty."))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| Error::new(e.kind(), format!("Couldn't start {}: {}", command, e)))?;
+        let (input, output) = (child.stdin.take().unwrap(), child.stdout.take().unwrap());
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(output).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut engine = UciEngine {
+            command: command.to_string(),
+            options: options.to_vec(),
+            name: command.to_string(),
+            child,
+            input,
+            lines,
+        };
+        engine.handshake()?;
+        Ok(engine)
+    }
+
+    /// Sends `uci`, reading the engine's name, then the options, and waits until it is ready.
+    fn handshake(&mut self) -> Result<(), Error> {
+        self.send("uci")?;
+        for line in self.wait_for("uciok", Some(HANDSHAKE_TIMEOUT))? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                self.name = name.trim().to_string();
+            }
+        }
+        for (name, value) in self.options.clone() {
+            self.send(&format!("setoption name {} value {}", name, value))?;
+        }
+        self.send("isready")?;
+        self.wait_for("readyok", Some(HANDSHAKE_TIMEOUT)).map(drop)
+    }
+
+    /// Starts the engine again after it died with `e`.
+    fn restart(&mut self, e: &Error) -> Result<(), Error> {
+        tracing::warn!(target: "uci", "{} died ({}), starting it again", self.name, e);
+        *self = UciEngine::start(&self.command, &self.options)?;
+        Ok(())
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), Error> {
+        tracing::debug!(target: "uci", "{} << {}", self.name, line);
+        writeln!(self.input, "{}", line)?;
+        self.input.flush()
+    }
+
+    /// The engine's next line, waiting at most `timeout` if there is one.
+    fn receive(&mut self, timeout: Option<Duration>) -> Result<String, Error> {
+        let died = || Error::new(ErrorKind::UnexpectedEof, format!("{} exited.", self.name));
+        let line = match timeout {
+            Some(timeout) => self.lines.recv_timeout(timeout).map_err(|e| match e {
+                RecvTimeoutError::Timeout => Error::new(
+                    ErrorKind::TimedOut,
+                    format!("{} didn't answer in time.", self.name),
+                ),
+                RecvTimeoutError::Disconnected => died(),
+            }),
+            None => self.lines.recv().map_err(|_| died()),
+        }?;
+        tracing::debug!(target: "uci", "{} >> {}", self.name, line);
+        Ok(line)
+    }
+
+    /// Reads lines until `answer`, each waited for at most `timeout`, and returns those before
+    /// it.
+    fn wait_for(&mut self, answer: &str, timeout: Option<Duration>) -> Result<Vec<String>, Error> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.receive(timeout)?;
+            if line.trim() == answer {
+                return Ok(lines);
+            }
+            lines.push(line);
+        }
+    }
+
+    fn new_game(&mut self) -> Result<(), Error> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.wait_for("readyok", Some(HANDSHAKE_TIMEOUT)).map(drop)
+    }
+
+    /// Sends the position and `go`, and reads the `info` lines up to the `bestmove`.
+    fn search(
+        &mut self,
+        start: &ChessBoard,
+        moves: &[Move],
+        limits: Limits,
+    ) -> Result<SearchResult, Error> {
+        let mut board = *start;
+        let mut position = format!("position fen {}", start.fen());
+        if !moves.is_empty() {
+            position.push_str(" moves");
+        }
+        for &mv in moves {
+            position.push_str(&format!(" {}", mv));
+            board.apply_move(mv);
+        }
+        self.send(&position)?;
+        let mut go = format!("go depth {}", limits.depth);
+        if limits.nodes < u64::MAX {
+            go.push_str(&format!(" nodes {}", limits.nodes));
+        }
+        // An engine on the clock has until its time runs out to answer.
+        let mut deadline = None;
+        if let Some([white, black]) = limits.clocks {
+            go.push_str(&format!(" wtime {} btime {}", white, black));
+            let clock = [white, black][(board.side_to_move() == BLACK) as usize];
+            deadline = Some(Instant::now() + Duration::from_millis(clock as u64));
+        }
+        self.send(&go)?;
+
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            pv: Vec::new(),
+            nodes: 0,
+            depth: 0,
+        };
+        loop {
+            let timeout =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let line = self.receive(timeout)?;
+            match line.split_whitespace().collect::<Vec<&str>>().as_slice() {
+                ["info", info @ ..] => read_info(&board, info, &mut result),
+                ["bestmove", text, ..] => {
+                    result.best_move = uci::parse_move(&board, text);
+                    if result.best_move.is_none() && !board.legal_moves().is_empty() {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!("{} played {}, which isn't legal.", self.name, text),
+                        ));
+                    }
+                    return Ok(result);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    /// Asks the engine to quit, and kills it if it doesn't.
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + QUIT_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Reads the depth, score, nodes and principal variation of an `info` line into `result`. A
+/// score that is only a bound is skipped.
+fn read_info(board: &ChessBoard, info: &[&str], result: &mut SearchResult) {
+    let mut words = info.iter().enumerate();
+    while let Some((index, &word)) = words.next() {
+        let next = info.get(index + 1).copied().unwrap_or("");
+        match word {
+            "depth" => result.depth = next.parse().unwrap_or(result.depth),
+            "nodes" => result.nodes = next.parse().unwrap_or(result.nodes),
+            "score" => {
+                let bound = matches!(info.get(index + 3), Some(&"lowerbound" | &"upperbound"));
+                if let Some(score) = info
+                    .get(index + 2)
+                    .and_then(|value| uci::read_score(next, value).filter(|_| !bound))
+                {
+                    result.score = score;
+                }
+            }
+            "pv" => {
+                let mut line = *board;
+                result.pv.clear();
+                for text in &info[index + 1..] {
+                    let Some(mv) = uci::parse_move(&line, text) else {
+                        break;
+                    };
+                    line.apply_move(mv);
+                    result.pv.push(mv);
+                }
+                return;
+            }
+            _ => continue,
+        }
+        words.next();
+    }
+}
+
+#[test]
+fn test_engine() {
+    use crate::search::MATE;
+
+    let board = ChessBoard::new();
+    let mut result = SearchResult {
+        best_move: None,
+        score: 0,
+        pv: Vec::new(),
+        nodes: 0,
+        depth: 0,
+    };
+    read_info(
+        &board,
+        &"depth 12 seldepth 20 score cp -35 nodes 123456 nps 1000 pv e2e4 e7e5 g1f3"
+            .split(' ')
+            .collect::<Vec<&str>>(),
+        &mut result,
+    );
+    assert_eq!(
+        (result.depth, result.score, result.nodes),
+        (12, -35, 123456)
+    );
+    assert_eq!(board.line_sans(&result.pv), ["e4", "e5", "Nf3"]);
+    read_info(&board, &["score", "cp", "90", "lowerbound"], &mut result);
+    assert_eq!(result.score, -35);
+    read_info(&board, &["score", "mate", "-2"], &mut result);
+    assert_eq!(result.score, -(MATE - 4));
+    assert_eq!(
+        parse_option("Hash = 64"),
+        Some(("Hash".to_string(), "64".to_string()))
+    );
+    assert_eq!(parse_option("Hash"), None);
+
+    // An engine that doesn't speak UCI, or doesn't exist, can't be started.
+    let command = |command: &str| EngineSpec::Uci {
+        command: command.to_string(),
+        options: Vec::new(),
+    };
+    assert!(command("true").start().is_err());
+    assert!(command("no-such-engine").start().is_err());
+    assert!(command("").start().is_err());
+
+    // The engine itself, run as an external engine, mates in one, and after it has been killed
+    // is started again to search.
+    let binary = match cfg!(target_os = "windows") {
+        true => "target\\debug\\freight_chess.exe uci",
+        false => "./target/debug/freight_chess uci",
+    };
+    let external = EngineSpec::Uci {
+        command: binary.to_string(),
+        options: vec![("Hash".to_string(), "1".to_string())],
+    };
+    let mut engine = external.start().unwrap();
+    assert!(engine.name().starts_with("FreightChess "));
+    engine.new_game().unwrap();
+    let mate = ChessBoard::from_fen("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1").unwrap();
+    let limits = Limits {
+        depth: 3,
+        nodes: u64::MAX,
+        clocks: None,
+    };
+    let result = engine.search(&mate, &[], limits).unwrap();
+    assert_eq!(result.best_move, mate.parse_san("Qb8#").ok());
+    assert_eq!(result.score, MATE - 1);
+    let Engine::Uci(uci) = &mut engine else {
+        panic!("not an external engine");
+    };
+    uci.child.kill().unwrap();
+    uci.child.wait().unwrap();
+    let moves = [mate.parse_san("Qb7").unwrap()];
+    let result = engine.search(&mate, &moves, limits).unwrap();
+    assert_eq!(
+        result.best_move.map(|mv| mv.to_string()).as_deref(),
+        Some("h8g8")
+    );
+
+    // The internal search gives what searching the position does.
+    let mut internal = EngineSpec::Internal(SearchParams::default())
+        .start()
+        .unwrap();
+    let result = internal.search(&mate, &[], limits).unwrap();
+    assert_eq!(result.best_move, mate.search(3).best_move);
+}
@@ -0,0 +1,737 @@
+use std::cell::{Cell, RefCell};
+
+use crate::movegen::adjacent_squares;
+use crate::nnue;
+use crate::{ChessBoard, BISHOP, BLACK, EMPTY, GET_NUM, KING, KNIGHT, PAWN, QUEEN, ROOK, WHITE};
+
+/// Material values in centipawns, indexed by piece number.
+pub const PIECE_VALUES: [i32; 7] = [0, 100, 320, 500, 330, 900, 0];
+
+// Piece-square tables, in centipawns, from white's point of view. They are laid out the way the
+// board is usually drawn (the eighth rank first), so a white piece on `square` uses
+// `table[square ^ 56]` and a black piece uses `table[square]`.
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+// Pawn structure terms, in centipawns per pawn.
+const DOUBLED_PAWN: i32 = -15;
+const ISOLATED_PAWN: i32 = -15;
+const BACKWARD_PAWN: i32 = -10;
+
+/// The bonus for a passed pawn, by how many steps it still has to make to promote.
+const PASSED_PAWN: [i32; 8] = [0, 120, 80, 50, 30, 15, 10, 0];
+
+/// The bonus for a passed pawn per square its own king is closer to the square in front of it
+/// than the enemy king, multiplied by how far the pawn has come.
+const KING_PROXIMITY: i32 = 2;
+
+// King safety terms, in centipawns: for each pawn in front of the king, by how far in front it
+// is, and for each file beside or under the king without pawns of its own side, or without any.
+const SHIELD_PAWN: [i32; 2] = [15, 8];
+const HALF_OPEN_FILE: i32 = -12;
+const OPEN_FILE: i32 = -20;
+
+/// How much an enemy piece adds to the danger to a king for each square around the king it
+/// attacks, indexed by piece number. Pawns and kings don't lead attacks.
+const ATTACK_WEIGHTS: [i32; 7] = [0, 0, 2, 3, 2, 5, 0];
+
+/// The most the danger from attacking pieces can cost.
+const MAX_KING_DANGER: i32 = 500;
+
+/// The knights, bishops, rooks and queen one side starts with, in centipawns. King safety counts
+/// in full while the enemy still has this much to attack with, and less as they trade it off.
+const ATTACKING_MATERIAL: i32 = 2 * 320 + 2 * 330 + 2 * 500 + 900;
+
+/// The weights of the mobility and piece placement terms, in centipawns. Each can be tuned by
+/// name (as a UCI option, say) without rebuilding the engine.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Params {
+    /// Per square a knight can safely move to: one that holds no piece of its own side and
+    /// isn't attacked by an enemy pawn.
+    pub knight_mobility: i32,
+    pub bishop_mobility: i32,
+    pub rook_mobility: i32,
+    pub queen_mobility: i32,
+    /// For a knight on the fourth to sixth rank, defended by a pawn of its own and out of reach
+    /// of the enemy pawns.
+    pub knight_outpost: i32,
+    pub bishop_outpost: i32,
+    /// For a rook on a file without pawns, or without pawns of its own side.
+    pub rook_open_file: i32,
+    pub rook_half_open_file: i32,
+    /// For a rook on the seventh rank.
+    pub rook_seventh: i32,
+    /// For having both bishops.
+    pub bishop_pair: i32,
+}
+
+/// How far from zero a parameter may be set, in centipawns.
+pub const PARAM_LIMIT: i32 = 500;
+
+pub const DEFAULT_PARAMS: Params = Params {
+    knight_mobility: 4,
+    bishop_mobility: 5,
+    rook_mobility: 2,
+    queen_mobility: 1,
+    knight_outpost: 20,
+    bishop_outpost: 10,
+    rook_open_file: 20,
+    rook_half_open_file: 10,
+    rook_seventh: 20,
+    bishop_pair: 30,
+};
+
+impl Default for Params {
+    fn default() -> Params {
+        DEFAULT_PARAMS
+    }
+}
+
+impl Params {
+    /// Every parameter with its name.
+    fn fields(&mut self) -> [(&'static str, &mut i32); 10] {
+        [
+            ("KnightMobility", &mut self.knight_mobility),
+            ("BishopMobility", &mut self.bishop_mobility),
+            ("RookMobility", &mut self.rook_mobility),
+            ("QueenMobility", &mut self.queen_mobility),
+            ("KnightOutpost", &mut self.knight_outpost),
+            ("BishopOutpost", &mut self.bishop_outpost),
+            ("RookOpenFile", &mut self.rook_open_file),
+            ("RookHalfOpenFile", &mut self.rook_half_open_file),
+            ("RookSeventh", &mut self.rook_seventh),
+            ("BishopPair", &mut self.bishop_pair),
+        ]
+    }
+
+    /// The name and value of every parameter.
+    pub fn named(mut self) -> Vec<(&'static str, i32)> {
+        self.fields()
+            .into_iter()
+            .map(|(name, value)| (name, *value))
+            .collect()
+    }
+
+    /// Sets the parameter called `name` (in any case), returning `false` if there is none.
+    pub fn set(&mut self, name: &str, value: i32) -> bool {
+        match self
+            .fields()
+            .into_iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+        {
+            Some((_, field)) => {
+                *field = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+const FILE_H: u64 = FILE_A << 7;
+
+/// How many pawn structures each thread remembers, as a power of two. Pawns move rarely, so
+/// during a search the same few structures come up over and over.
+const PAWN_HASH_BITS: u32 = 13;
+
+/// What a pawn structure is worth, as far as it only depends on where the pawns stand.
+#[derive(Clone, Copy)]
+struct PawnEntry {
+    /// The squares of the white and black pawns, as bits `1 << square`.
+    pawns: [u64; 2],
+    /// The doubled, isolated and backward pawns, in centipawns from white's point of view.
+    score: i32,
+    /// The squares of white's and black's passed pawns.
+    passed: [u64; 2],
+}
+
+thread_local! {
+    /// The pawn structures evaluated so far, indexed by a hash of the pawns' squares.
+    static PAWN_HASH: RefCell<Vec<Option<PawnEntry>>> =
+        RefCell::new(vec![None; 1 << PAWN_HASH_BITS]);
+
+    /// The parameters evaluations on this thread use.
+    static PARAMS: Cell<Params> = const { Cell::new(DEFAULT_PARAMS) };
+}
+
+/// The parameters evaluations on this thread use.
+pub fn params() -> Params {
+    PARAMS.with(Cell::get)
+}
+
+/// Makes evaluations on this thread use `params`.
+pub fn set_params(params: Params) {
+    PARAMS.with(|current| current.set(params));
+}
+
+fn square_bonus(piece: u8, square: u8) -> i32 {
+    let index = if piece & BLACK == WHITE {
+        square ^ 56
+    } else {
+        square
+    } as usize;
+    match GET_NUM(piece) {
+        PAWN => PAWN_TABLE[index],
+        KNIGHT => KNIGHT_TABLE[index],
+        BISHOP => BISHOP_TABLE[index],
+        ROOK => ROOK_TABLE[index],
+        QUEEN => QUEEN_TABLE[index],
+        KING => KING_TABLE[index],
+        _ => 0,
+    }
+}
+
+/// The files either side of `file`.
+fn adjacent_files(file: u8) -> u64 {
+    let left = if file > 0 { FILE_A << (file - 1) } else { 0 };
+    let right = if file < 7 { FILE_A << (file + 1) } else { 0 };
+    left | right
+}
+
+/// The squares on the ranks ahead of `rank`, as seen by `color`.
+fn ranks_ahead(rank: u8, color: u8) -> u64 {
+    match color {
+        WHITE if rank >= 7 => 0,
+        WHITE => !0 << (8 * (rank + 1)),
+        _ => (1 << (8 * rank)) - 1,
+    }
+}
+
+/// Finds the doubled, isolated, backward and passed pawns among the white and black `pawns`.
+fn pawn_structure(pawns: [u64; 2]) -> PawnEntry {
+    let mut entry = PawnEntry {
+        pawns,
+        score: 0,
+        passed: [0; 2],
+    };
+    for (index, color, sign) in [(0, WHITE, 1), (1, BLACK, -1)] {
+        let (own, enemy) = (pawns[index], pawns[index ^ 1]);
+        let mut rest = own;
+        while rest != 0 {
+            let square = rest.trailing_zeros() as u8;
+            rest &= rest - 1;
+            let (file, rank) = (square & 7, square >> 3);
+            let ahead = ranks_ahead(rank, color);
+            let neighbours = own & adjacent_files(file);
+
+            if own & FILE_A << file & ahead != 0 {
+                entry.score += sign * DOUBLED_PAWN;
+            }
+            if neighbours == 0 {
+                entry.score += sign * ISOLATED_PAWN;
+            } else if neighbours & !ahead == 0 {
+                // Every neighbour has gone ahead, so none can come back to support this pawn,
+                // and it can't catch up when an enemy pawn guards the square in front of it.
+                let guard_rank = match color {
+                    WHITE => Some(rank + 2).filter(|&rank| rank < 8),
+                    _ => rank.checked_sub(2),
+                };
+                let guarded = guard_rank.is_some_and(|guard_rank| {
+                    (enemy & adjacent_files(file)) >> (8 * guard_rank) & 0xFF != 0
+                });
+                if guarded {
+                    entry.score += sign * BACKWARD_PAWN;
+                }
+            }
+            // Of doubled passed pawns, only the front one counts.
+            if (enemy & adjacent_files(file) | (enemy | own) & FILE_A << file) & ahead == 0 {
+                entry.passed[index] |= 1 << square;
+            }
+        }
+    }
+    entry
+}
+
+/// Looks `pawns` up in this thread's pawn hash table, working out and storing their structure if
+/// they aren't there.
+fn probe_pawn_hash(pawns: [u64; 2]) -> PawnEntry {
+    let index = ((pawns[0] ^ pawns[1].rotate_left(32)).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        >> (64 - PAWN_HASH_BITS)) as usize;
+    PAWN_HASH.with(|table| {
+        let mut table = table.borrow_mut();
+        match table[index] {
+            Some(entry) if entry.pawns == pawns => entry,
+            _ => {
+                let entry = pawn_structure(pawns);
+                table[index] = Some(entry);
+                entry
+            }
+        }
+    })
+}
+
+/// How many king moves apart two squares are.
+fn distance(from: u8, to: u8) -> i32 {
+    let files = (from & 7).abs_diff(to & 7);
+    let ranks = (from >> 3).abs_diff(to >> 3);
+    files.max(ranks) as i32
+}
+
+/// Scores the pawn structure in centipawns from white's point of view: the weaknesses from the
+/// pawn hash table, plus the passed pawns, which are worth more the further they have come and the
+/// closer their own king is to escorting them.
+fn evaluate_pawns(board: &ChessBoard, pawns: [u64; 2], kings: [Option<u8>; 2]) -> i32 {
+    let entry = probe_pawn_hash(pawns);
+    let last = board.variant.dimensions().1 - 1;
+    let mut score = entry.score;
+    for (index, color, sign) in [(0, WHITE, 1), (1, BLACK, -1)] {
+        let mut rest = entry.passed[index];
+        while rest != 0 {
+            let square = rest.trailing_zeros() as u8;
+            rest &= rest - 1;
+            let (rank, stop) = match color {
+                WHITE => (square >> 3, square + 8),
+                _ => (last - (square >> 3), square - 8),
+            };
+            let mut bonus = PASSED_PAWN[(last - rank) as usize];
+            if let [Some(own), Some(enemy)] = [kings[index], kings[index ^ 1]] {
+                bonus +=
+                    (distance(enemy, stop) - distance(own, stop)) * KING_PROXIMITY * rank as i32;
+            }
+            score += sign * bonus;
+        }
+    }
+    score
+}
+
+/// Scores how safe `color`'s king on `king` is, in centipawns (negative when it is in danger):
+/// the pawns sheltering it, the open files leading to it, and the enemy pieces bearing down on
+/// the squares around it, which grow more dangerous the more of them join in. `occupied` holds
+/// the squares with a piece on them.
+fn king_safety(board: &ChessBoard, color: u8, king: u8, pawns: [u64; 2], occupied: u64) -> i32 {
+    let index = (color == BLACK) as usize;
+    let (own, enemy) = (pawns[index], pawns[index ^ 1]);
+    let (files, ranks) = board.variant.dimensions();
+    let (file, rank) = (king & 7, king >> 3);
+    let forward = if color == WHITE { 1 } else { -1 };
+    // The pawn shield only counts while the king stays at home.
+    let at_home = if color == WHITE {
+        rank
+    } else {
+        ranks - 1 - rank
+    } <= 1;
+
+    let mut score = 0;
+    for shield_file in file.saturating_sub(1)..=(file + 1).min(files - 1) {
+        let on_file = FILE_A << shield_file;
+        if at_home {
+            for (step, bonus) in (1..).zip(SHIELD_PAWN) {
+                let shield_rank = rank as i8 + step * forward;
+                if (0..8).contains(&shield_rank) && own & on_file & 0xFF << (8 * shield_rank) != 0 {
+                    score += bonus;
+                }
+            }
+        }
+        if own & on_file == 0 {
+            score += if enemy & on_file == 0 {
+                OPEN_FILE
+            } else {
+                HALF_OPEN_FILE
+            };
+        }
+    }
+
+    let zone = adjacent_squares(king)
+        .filter(|&square| board.is_on_board(square))
+        .fold(1 << king, |zone, square| zone | 1 << square);
+    let (mut attackers, mut danger, mut material) = (0, 0, 0);
+    for square in 0..64 {
+        let piece = board.get_piece(square);
+        if piece == EMPTY || piece & BLACK == color || ATTACK_WEIGHTS[GET_NUM(piece) as usize] == 0
+        {
+            continue;
+        }
+        material += PIECE_VALUES[GET_NUM(piece) as usize];
+        let hits = (board.attacks_through(square, occupied) & zone).count_ones() as i32;
+        if hits > 0 {
+            attackers += 1;
+            danger += ATTACK_WEIGHTS[GET_NUM(piece) as usize] * hits;
+        }
+    }
+    // A lone piece can't mate, but every piece that joins an attack makes it much worse.
+    if attackers >= 2 {
+        score -= (danger * danger / 2).min(MAX_KING_DANGER);
+    }
+    score * material.min(ATTACKING_MATERIAL) / ATTACKING_MATERIAL
+}
+
+/// The squares attacked by the white and black `pawns`.
+fn pawn_attacks(pawns: [u64; 2]) -> [u64; 2] {
+    [
+        (pawns[0] & !FILE_A) << 7 | (pawns[0] & !FILE_H) << 9,
+        (pawns[1] & !FILE_A) >> 9 | (pawns[1] & !FILE_H) >> 7,
+    ]
+}
+
+/// Scores the knights, bishops and rooks in centipawns from white's point of view: how freely
+/// the pieces move, knights and bishops on outposts, rooks on open files and the seventh rank,
+/// and the bishop pair. `occupied` holds the squares of each side's pieces.
+fn evaluate_pieces(board: &ChessBoard, pawns: [u64; 2], occupied: [u64; 2]) -> i32 {
+    let params = params();
+    let (files, ranks) = board.variant.dimensions();
+    let on_board = (0..ranks).fold(0u64, |mask, rank| mask | ((1 << files) - 1) << (8 * rank));
+    let guarded = pawn_attacks(pawns);
+    let mut score = 0;
+    let mut bishops = [0; 2];
+    for square in 0..64 {
+        let piece = board.get_piece(square);
+        let kind = GET_NUM(piece);
+        if !matches!(kind, KNIGHT | BISHOP | ROOK | QUEEN) {
+            continue;
+        }
+        let (color, index) = (piece & BLACK, (piece & BLACK == BLACK) as usize);
+        let sign = if color == WHITE { 1 } else { -1 };
+        let (file, rank) = (square & 7, square >> 3);
+        let relative = if color == WHITE {
+            rank
+        } else {
+            ranks - 1 - rank
+        };
+
+        let all = occupied[0] | occupied[1];
+        let safe =
+            board.attacks_through(square, all) & on_board & !occupied[index] & !guarded[index ^ 1];
+        let mobility = match kind {
+            KNIGHT => params.knight_mobility,
+            BISHOP => params.bishop_mobility,
+            ROOK => params.rook_mobility,
+            _ => params.queen_mobility,
+        };
+        let mut bonus = mobility * safe.count_ones() as i32;
+
+        match kind {
+            KNIGHT | BISHOP => {
+                let outpost = (3..ranks - 2).contains(&relative)
+                    && guarded[index] & 1 << square != 0
+                    && pawns[index ^ 1] & adjacent_files(file) & ranks_ahead(rank, color) == 0;
+                if outpost {
+                    bonus += match kind {
+                        KNIGHT => params.knight_outpost,
+                        _ => params.bishop_outpost,
+                    };
+                }
+                if kind == BISHOP {
+                    bishops[index] += 1;
+                }
+            }
+            ROOK => {
+                let on_file = FILE_A << file;
+                if pawns[index] & on_file == 0 {
+                    bonus += match pawns[index ^ 1] & on_file {
+                        0 => params.rook_open_file,
+                        _ => params.rook_half_open_file,
+                    };
+                }
+                if relative == ranks - 2 {
+                    bonus += params.rook_seventh;
+                }
+            }
+            _ => {}
+        }
+        score += sign * bonus;
+    }
+    if bishops[0] >= 2 {
+        score += params.bishop_pair;
+    }
+    if bishops[1] >= 2 {
+        score -= params.bishop_pair;
+    }
+    score
+}
+
+/// Statically evaluates the position in centipawns, from the point of view of the side to move
+/// (positive means the side to move is better), by the rules of its variant, or with the NNUE
+/// network if one is switched on.
+pub fn evaluate(board: &ChessBoard) -> i32 {
+    nnue::evaluate(board).unwrap_or_else(|| board.variant.evaluate(board))
+}
+
+/// Evaluates the position by its material, where the pieces stand and the pawn structure, as
+/// suits standard chess.
+pub fn evaluate_material(board: &ChessBoard) -> i32 {
+    let score = terms(board).iter().sum::<i32>();
+    if board.side_to_move() == WHITE {
+        score
+    } else {
+        -score
+    }
+}
+
+/// The names of the terms `evaluate_material` adds up, in the order `terms` returns them.
+pub const TERM_NAMES: [&str; 6] = [
+    "Material",
+    "Piece squares",
+    "Pawns",
+    "Pieces",
+    "King safety",
+    "In hand",
+];
+
+/// The terms of `evaluate_material`, in centipawns from white's point of view, named by
+/// `TERM_NAMES`.
+pub fn terms(board: &ChessBoard) -> [i32; 6] {
+    let mut material = 0;
+    let mut placement = 0;
+    let mut pawns = [0u64; 2];
+    let mut kings = [None; 2];
+    let mut occupied = [0u64; 2];
+    for square in 0..64 {
+        let piece = board.get_piece(square);
+        if piece == EMPTY {
+            continue;
+        }
+        let side = (piece & BLACK == BLACK) as usize;
+        occupied[side] |= 1 << square;
+        match GET_NUM(piece) {
+            PAWN => pawns[side] |= 1 << square,
+            KING => kings[side] = Some(square),
+            _ => {}
+        }
+        let sign = if piece & BLACK == WHITE { 1 } else { -1 };
+        material += sign * PIECE_VALUES[GET_NUM(piece) as usize];
+        placement += sign * square_bonus(piece, square);
+    }
+
+    let mut safety = 0;
+    for (color, sign) in [(WHITE, 1), (BLACK, -1)] {
+        if let Some(king) = kings[(color == BLACK) as usize] {
+            safety += sign * king_safety(board, color, king, pawns, occupied[0] | occupied[1]);
+        }
+    }
+
+    // Pieces in hand are worth as much as on the board.
+    let mut hand = 0;
+    for (color, sign) in [(WHITE, 1), (BLACK, -1)] {
+        for (piece, &count) in board.pocket(color).iter().enumerate() {
+            hand += sign * PIECE_VALUES[piece] * count as i32;
+        }
+    }
+
+    [
+        material,
+        placement,
+        evaluate_pawns(board, pawns, kings),
+        evaluate_pieces(board, pawns, occupied),
+        safety,
+        hand,
+    ]
+}
+
+#[test]
+fn test_evaluate() {
+    // The starting position is symmetric.
+    let board = ChessBoard::new();
+    assert_eq!(evaluate(&board), 0);
+
+    // Being a queen up is good for white and equally bad for black.
+    let white = ChessBoard::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+    let black = ChessBoard::from_fen("4k3/8/8/8/8/8/8/3QK3 b - - 0 1").unwrap();
+    assert!(evaluate(&white) > 800);
+    assert_eq!(evaluate(&white), -evaluate(&black));
+}
+
+#[test]
+fn test_pawn_structure() {
+    let pawns = |squares: &[u8]| {
+        squares
+            .iter()
+            .fold(0u64, |bits, &square| bits | 1 << square)
+    };
+
+    // Doubled and isolated on the a-file; only the front pawn is passed.
+    let entry = pawn_structure([pawns(&[8, 16]), 0]);
+    assert_eq!(entry.score, DOUBLED_PAWN + 2 * ISOLATED_PAWN);
+    assert_eq!(entry.passed, [pawns(&[16]), 0]);
+
+    // White's d3 is backward behind c4 with e5 guarding d4, black's e5 is isolated, and c4 is
+    // passed.
+    let entry = pawn_structure([pawns(&[19, 26]), pawns(&[36])]);
+    assert_eq!(entry.score, BACKWARD_PAWN - ISOLATED_PAWN);
+    assert_eq!(entry.passed, [pawns(&[26]), 0]);
+
+    // The pawn hash table gives back what was worked out.
+    let structure = [pawns(&[8, 13, 21, 27]), pawns(&[48, 51, 46])];
+    let first = probe_pawn_hash(structure);
+    let cached = probe_pawn_hash(structure);
+    assert_eq!(cached.score, pawn_structure(structure).score);
+    assert_eq!((first.score, first.passed), (cached.score, cached.passed));
+
+    // A passed pawn is worth more the further it has come and with its own king beside it.
+    let board = ChessBoard::new();
+    let d6 = [pawns(&[43]), 0];
+    let escorted = evaluate_pawns(&board, d6, [Some(44), Some(0)]);
+    let alone = evaluate_pawns(&board, d6, [Some(4), Some(0)]);
+    assert_eq!(alone, PASSED_PAWN[2] + ISOLATED_PAWN);
+    assert_eq!(escorted - alone, 5 * KING_PROXIMITY * 5);
+    assert!(evaluate_pawns(&board, [pawns(&[19]), 0], [Some(4), Some(0)]) < alone);
+}
+
+#[test]
+fn test_king_safety() {
+    let safety = |fen: &str| {
+        let board = ChessBoard::from_fen(fen).unwrap();
+        let mut pawns = [0u64; 2];
+        for square in 0..64 {
+            match board.get_piece(square) {
+                PAWN => pawns[0] |= 1 << square,
+                piece if piece == PAWN | BLACK => pawns[1] |= 1 << square,
+                _ => {}
+            }
+        }
+        let occupied = board.occupied();
+        king_safety(
+            &board,
+            WHITE,
+            board.king_square(WHITE).unwrap(),
+            pawns,
+            occupied,
+        )
+    };
+
+    // A king behind its unmoved pawns is safest; pushing them, or losing one, opens it up.
+    let sheltered = safety("rnbq1rk1/pppppppp/8/8/8/8/5PPP/6K1 w - - 0 1");
+    let pushed = safety("rnbq1rk1/pppppppp/8/8/8/6P1/5P1P/6K1 w - - 0 1");
+    let half_open = safety("rnbq1rk1/pppppppp/8/8/8/8/5P1P/6K1 w - - 0 1");
+    let open = safety("rnbq1rk1/pppppp1p/8/8/8/8/5P1P/6K1 w - - 0 1");
+    assert!(sheltered > pushed && pushed > half_open && half_open > open);
+
+    // One attacker is no danger; a second one joining in is.
+    let board = ChessBoard::from_fen("6k1/8/8/8/6nq/8/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(board.attacks(30).count_ones(), 6);
+    let alone = safety("6k1/8/8/8/7q/8/5PPP/6K1 w - - 0 1");
+    let together = safety("6k1/8/8/8/6nq/8/5PPP/6K1 w - - 0 1");
+    assert!(alone > 0);
+    assert!(together < 0);
+
+    // With nothing left to attack with, the king's shelter doesn't matter.
+    assert_eq!(safety("6k1/pppppppp/8/8/8/8/8/6K1 w - - 0 1"), 0);
+}
+
+#[test]
+fn test_pieces() {
+    let pieces = |fen: &str| {
+        let board = ChessBoard::from_fen(fen).unwrap();
+        let (mut pawns, mut occupied) = ([0u64; 2], [0u64; 2]);
+        for square in 0..64 {
+            let piece = board.get_piece(square);
+            let side = (piece & BLACK == BLACK) as usize;
+            if piece != EMPTY {
+                occupied[side] |= 1 << square;
+            }
+            if GET_NUM(piece) == PAWN {
+                pawns[side] |= 1 << square;
+            }
+        }
+        evaluate_pieces(&board, pawns, occupied)
+    };
+    // How much a position's score changes when one parameter is switched off.
+    let worth = |fen: &str, name: &str| {
+        let with = pieces(fen);
+        let mut params = DEFAULT_PARAMS;
+        assert!(params.set(name, 0));
+        set_params(params);
+        let without = pieces(fen);
+        set_params(DEFAULT_PARAMS);
+        with - without
+    };
+
+    // Knights are better in the middle of the board.
+    assert!(pieces("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1") > pieces("4k3/8/8/8/8/8/8/N3K3 w - - 0 1"));
+
+    // An outpost needs a pawn behind it, and no enemy pawn that could chase the piece away.
+    let outpost = "4k3/8/8/3N4/4P3/8/8/4K3 w - - 0 1";
+    assert_eq!(
+        worth(outpost, "KnightOutpost"),
+        DEFAULT_PARAMS.knight_outpost
+    );
+    assert_eq!(
+        worth("4k3/2p5/8/3N4/4P3/8/8/4K3 w - - 0 1", "KnightOutpost"),
+        0
+    );
+    assert_eq!(
+        worth("4k3/8/8/4p3/3n4/8/8/4K3 w - - 0 1", "knightoutpost"),
+        -20
+    );
+
+    // Rooks want open files and the seventh rank.
+    assert_eq!(worth("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "RookOpenFile"), 20);
+    assert_eq!(
+        worth("4k3/p7/8/8/8/8/8/R3K3 w - - 0 1", "RookHalfOpenFile"),
+        10
+    );
+    assert_eq!(
+        worth("4k3/p7/8/8/8/8/P7/R3K3 w - - 0 1", "RookHalfOpenFile"),
+        0
+    );
+    assert_eq!(worth("4k3/R7/8/8/8/8/8/4K3 w - - 0 1", "RookSeventh"), 20);
+
+    assert_eq!(worth("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", "BishopPair"), 30);
+    assert_eq!(worth("4k3/8/8/8/8/8/8/2B1KN2 w - - 0 1", "BishopPair"), 0);
+    let mut params = DEFAULT_PARAMS;
+    assert!(!params.set("Tempo", 10));
+}
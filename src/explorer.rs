@@ -0,0 +1,137 @@
+//! The opening explorer: the moves played from a position in the stored games, with how often
+//! each was played and how those games ended. Lines are explored by playing moves at the prompt.
+
+use std::io::{BufRead, Error, Write};
+
+use crate::db::{Database, MoveStats};
+use crate::movegen::Move;
+use crate::pgn;
+use crate::ChessBoard;
+
+const HELP: &str = "Play a move (or the number of one in the list) to explore it, 'back' to take \
+    the last move back, 'reset' to return to the start, or 'quit'.";
+
+/// `part` of `games` as a whole percentage.
+fn percent(part: u32, games: u32) -> u32 {
+    (part * 100 + games / 2) / games.max(1)
+}
+
+/// The explorer's table of moves for a position.
+fn table(stats: &[MoveStats]) -> String {
+    if stats.is_empty() {
+        return "No stored game reached this position.\n".to_string();
+    }
+    let mut text = format!(
+        "{:>3}  {:<8}{:>6}  {:>5}  {:>5}  {:>5}\n",
+        "", "Move", "Games", "White", "Draw", "Black"
+    );
+    for (i, stats) in stats.iter().enumerate() {
+        text.push_str(&format!(
+            "{:>3}  {:<8}{:>6}  {:>4}%  {:>4}%  {:>4}%\n",
+            format!("{}.", i + 1),
+            stats.san,
+            stats.games,
+            percent(stats.white_wins, stats.games),
+            percent(stats.draws, stats.games),
+            percent(stats.black_wins, stats.games)
+        ));
+    }
+    text
+}
+
+/// Runs the explorer from `start`, reading commands from `input` until it ends or the user quits.
+pub fn explore(
+    database: &Database,
+    start: ChessBoard,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<(), Error> {
+    writeln!(output, "{}", HELP)?;
+    let mut line: Vec<Move> = Vec::new();
+    loop {
+        let mut board = start;
+        for &mv in &line {
+            board.apply_move(mv);
+        }
+        let stats = database.explore(&board)?;
+        write!(output, "\n{}", board)?;
+        if !line.is_empty() {
+            writeln!(output, "{}", pgn::numbered_sans(&start, &line).join(" "))?;
+        }
+        write!(output, "{}explorer> ", table(&stats))?;
+        output.flush()?;
+
+        let mut command = String::new();
+        if input.read_line(&mut command)? == 0 {
+            writeln!(output)?;
+            return Ok(());
+        }
+        match command.trim() {
+            "" => {}
+            "quit" | "exit" => return Ok(()),
+            "help" => writeln!(output, "{}", HELP)?,
+            "back" => {
+                if line.pop().is_none() {
+                    writeln!(output, "This is where exploring started.")?;
+                }
+            }
+            "reset" => line.clear(),
+            text => {
+                let san = match text.parse::<usize>() {
+                    Ok(number) => match stats.get(number.wrapping_sub(1)) {
+                        Some(stats) => stats.san.as_str(),
+                        None => {
+                            writeln!(output, "There is no move {} in the list.", number)?;
+                            continue;
+                        }
+                    },
+                    Err(_) => text,
+                };
+                match board.parse_move(san) {
+                    Ok(mv) => line.push(mv),
+                    Err(e) => writeln!(output, "{}", e)?,
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_explorer() {
+    let database = Database::open(std::path::Path::new(":memory:")).unwrap();
+    for text in [
+        "1. e4 e5 2. Nf3 1-0",
+        "1. e4 c5 1/2-1/2",
+        "1. e4 e5 2. Bc4 0-1",
+        "1. d4 d5 1-0",
+    ] {
+        database.add(&pgn::PgnGame::parse(text).unwrap()).unwrap();
+    }
+    let explored = |commands: &str| {
+        let mut output = Vec::new();
+        explore(
+            &database,
+            ChessBoard::new(),
+            &mut commands.as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    let output = explored("quit\n");
+    assert!(output.contains(" 1.  e4           3    33%    33%    33%\n"));
+    assert!(output.contains(" 2.  d4           1   100%     0%     0%\n"));
+
+    let output = explored("1\ne5\n9\nback\nback\nback\nreset\n");
+    assert!(output.contains("1. e4\n"));
+    assert!(output.contains("1. e4 e5\n"));
+    assert!(output.contains(" 1.  Bc4          1     0%     0%   100%\n"));
+    assert!(output.contains("There is no move 9 in the list."));
+    assert!(output.contains("This is where exploring started."));
+
+    // Moves no stored game played can still be explored.
+    let output = explored("a4\n");
+    assert!(output.contains("1. a4\n"));
+    assert!(output.contains("No stored game reached this position."));
+}
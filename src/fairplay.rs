@@ -0,0 +1,286 @@
+//! Fair-play heuristics for the games a server hosts. Once a game is over, each move is searched
+//! to see whether it was the engine's choice, and the time the moves took is checked for the
+//! even pace of someone relaying an engine's moves. The server appends one JSON line per game to
+//! a report, which the `fairplay` subcommand sums up per player:
+//!
+//! ```json
+//! { "room": "main", "result": "1-0", "white": { "name": "alice", "moves": 24,
+//!   "engine_matches": 23, "timed_moves": 24, "total_ms": 96000, "square_ms": 4.1e8,
+//!   "flags": ["engine_match"] }, "black": { ... } }
+//! ```
+//!
+//! The flags only point an administrator at games worth a look: strong players agree with the
+//! engine often, and some play at a steady pace.
+
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Write};
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::movegen::Move;
+use crate::search::MAX_DEPTH;
+use crate::tt::TranspositionTable;
+use crate::{ChessBoard, WHITE};
+
+/// How many positions the engine searches to find its choice for each move.
+pub const ANALYSIS_NODES: u64 = 100_000;
+
+/// The moves of the opening aren't counted, since players know them by heart.
+const OPENING_PLIES: usize = 16;
+
+/// How many counted moves a player needs before any flag is raised.
+const MIN_MOVES: u32 = 15;
+
+/// The share of the engine's choices above which a player is flagged.
+const ENGINE_MATCH_RATE: f64 = 0.9;
+
+/// How much the time a player takes may vary from move to move (the standard deviation over the
+/// mean) before the pace counts as suspiciously even.
+const MIN_SPREAD: f64 = 0.25;
+
+const TABLE_MB: usize = 16;
+
+/// What a game, or every game of a player, says about one player.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlayerStats {
+    /// The moves counted: those past the opening where there was more than one legal move.
+    pub moves: u32,
+    /// How many of them the engine would have played too.
+    pub engine_matches: u32,
+    /// How many of them have a known time, and the sum of their times and of their squares in
+    /// milliseconds.
+    pub timed_moves: u32,
+    pub total_ms: u64,
+    pub square_ms: f64,
+}
+
+impl PlayerStats {
+    /// The share of the counted moves that were the engine's choice.
+    pub fn engine_match_rate(&self) -> f64 {
+        self.engine_matches as f64 / self.moves.max(1) as f64
+    }
+
+    /// The average time of a timed move, in milliseconds.
+    pub fn mean_ms(&self) -> f64 {
+        self.total_ms as f64 / self.timed_moves.max(1) as f64
+    }
+
+    /// The standard deviation of the move times over their mean, or `None` without times.
+    pub fn spread(&self) -> Option<f64> {
+        let mean = self.mean_ms();
+        if self.timed_moves == 0 || mean == 0.0 {
+            return None;
+        }
+        let variance = self.square_ms / self.timed_moves as f64 - mean * mean;
+        Some(variance.max(0.0).sqrt() / mean)
+    }
+
+    /// The patterns that look suspicious: `engine_match` when nearly every move was the engine's
+    /// choice, and `even_pace` when every move took about as long.
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = Vec::new();
+        if self.moves >= MIN_MOVES && self.engine_match_rate() >= ENGINE_MATCH_RATE {
+            flags.push("engine_match");
+        }
+        if self.timed_moves >= MIN_MOVES && self.spread().is_some_and(|spread| spread < MIN_SPREAD)
+        {
+            flags.push("even_pace");
+        }
+        flags
+    }
+
+    fn add(&mut self, other: &PlayerStats) {
+        self.moves += other.moves;
+        self.engine_matches += other.engine_matches;
+        self.timed_moves += other.timed_moves;
+        self.total_ms += other.total_ms;
+        self.square_ms += other.square_ms;
+    }
+
+    fn to_json(&self, name: Option<&str>) -> Value {
+        json!({
+            "name": name,
+            "moves": self.moves,
+            "engine_matches": self.engine_matches,
+            "timed_moves": self.timed_moves,
+            "total_ms": self.total_ms,
+            "square_ms": self.square_ms,
+            "flags": self.flags(),
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<PlayerStats> {
+        Some(PlayerStats {
+            moves: value["moves"].as_u64()? as u32,
+            engine_matches: value["engine_matches"].as_u64()? as u32,
+            timed_moves: value["timed_moves"].as_u64()? as u32,
+            total_ms: value["total_ms"].as_u64()?,
+            square_ms: value["square_ms"].as_f64()?,
+        })
+    }
+}
+
+/// Goes over the game of `moves` from `start`, searching each position `nodes` positions deep.
+/// `move_ms` is how long each move took, or `None` where that isn't known. Returns what it says
+/// about white and black.
+pub fn analyze(
+    start: &ChessBoard,
+    moves: &[Move],
+    move_ms: &[Option<u32>],
+    nodes: u64,
+) -> [PlayerStats; 2] {
+    let mut stats = [PlayerStats::default(), PlayerStats::default()];
+    let table = TranspositionTable::new(TABLE_MB);
+    let mut board = *start;
+    for (ply, &mv) in moves.iter().enumerate() {
+        let player = &mut stats[(board.side_to_move() != WHITE) as usize];
+        if ply >= OPENING_PLIES && board.legal_moves().len() > 1 {
+            player.moves += 1;
+            if board.search_with(MAX_DEPTH, nodes, &table).best_move == Some(mv) {
+                player.engine_matches += 1;
+            }
+            if let Some(ms) = move_ms.get(ply).copied().flatten() {
+                player.timed_moves += 1;
+                player.total_ms += ms as u64;
+                player.square_ms += ms as f64 * ms as f64;
+            }
+        }
+        board.apply_move(mv);
+    }
+    stats
+}
+
+/// Appends the line for a game played in `room` to the report at `path`. `players` holds the
+/// name (if any) and stats of white and black, or `None` for a side that isn't checked, like the
+/// engine in a simul.
+pub fn record(
+    path: &Path,
+    room: &str,
+    result: &str,
+    players: [Option<(Option<&str>, &PlayerStats)>; 2],
+) -> Result<(), Error> {
+    let side = |player: Option<(Option<&str>, &PlayerStats)>| {
+        player.map_or(Value::Null, |(name, stats)| stats.to_json(name))
+    };
+    let line = json!({
+        "room": room,
+        "result": result,
+        "white": side(players[0]),
+        "black": side(players[1]),
+    });
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    // One write per line, so games finishing at once don't interleave.
+    file.write_all(format!("{}\n", line).as_bytes())
+}
+
+/// Sums up the report at `path`: the games that raised flags, then every named player's
+/// totals, with the flags those raise.
+pub fn report(path: &Path) -> Result<String, Error> {
+    let file = std::fs::File::open(path)?;
+    let mut flagged = Vec::new();
+    let mut players = BTreeMap::<String, (u32, PlayerStats)>::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let game: Value = serde_json::from_str(&line?).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Line {} of the report: {}", number + 1, e),
+            )
+        })?;
+        for color in ["white", "black"] {
+            let Some(stats) = PlayerStats::from_json(&game[color]) else {
+                continue;
+            };
+            let name = game[color]["name"].as_str();
+            let flags = stats.flags();
+            if !flags.is_empty() {
+                flagged.push(format!(
+                    "{} ({}): {} as {}, {}",
+                    game["room"].as_str().unwrap_or("?"),
+                    game["result"].as_str().unwrap_or("?"),
+                    name.unwrap_or("anonymous"),
+                    color,
+                    flags.join(", ")
+                ));
+            }
+            if let Some(name) = name {
+                let (games, total) = players.entry(name.to_string()).or_default();
+                *games += 1;
+                total.add(&stats);
+            }
+        }
+    }
+
+    let mut report = format!("Flagged games: {}\n", flagged.len());
+    for game in flagged {
+        report += &format!("  {}\n", game);
+    }
+    report += "Players:\n";
+    for (name, (games, stats)) in players {
+        report += &format!(
+            "  {:<32} {:>4} games {:>5} moves {:>5.1}% engine {:>6.1}s/move {}\n",
+            name,
+            games,
+            stats.moves,
+            100.0 * stats.engine_match_rate(),
+            stats.mean_ms() / 1000.0,
+            stats.flags().join(", ")
+        );
+    }
+    Ok(report)
+}
+
+#[test]
+fn test_fairplay() {
+    let mut board = ChessBoard::new();
+    let mut moves = Vec::new();
+    for _ in 0..48 {
+        let mv = board.legal_moves()[0];
+        moves.push(mv);
+        board.apply_move(mv);
+    }
+    // White takes three seconds a move, black one to seven.
+    let move_ms = (0..moves.len())
+        .map(|ply| {
+            Some(if ply % 2 == 0 {
+                3_000
+            } else {
+                1_000 + 3_000 * (ply as u32 % 3)
+            })
+        })
+        .collect::<Vec<Option<u32>>>();
+    let [white, black] = analyze(&ChessBoard::new(), &moves, &move_ms, 1);
+    assert!(white.moves >= MIN_MOVES && white.moves <= 16);
+    assert_eq!(white.timed_moves, white.moves);
+    assert_eq!((white.mean_ms(), white.spread()), (3_000.0, Some(0.0)));
+    assert!(white.flags().contains(&"even_pace"));
+    assert!(!black.flags().contains(&"even_pace"));
+    assert!(white.engine_match_rate() < ENGINE_MATCH_RATE);
+
+    let path = std::env::temp_dir().join(format!("fairplay-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let cheat = PlayerStats {
+        moves: 20,
+        engine_matches: 19,
+        ..PlayerStats::default()
+    };
+    record(
+        &path,
+        "main",
+        "1-0",
+        [Some((Some("alice"), &cheat)), Some((None, &black))],
+    )
+    .unwrap();
+    record(
+        &path,
+        "simul/1",
+        "0-1",
+        [None, Some((Some("alice"), &black))],
+    )
+    .unwrap();
+    let report = report(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(report.starts_with("Flagged games: 1\n  main (1-0): alice as white, engine_match\n"));
+    assert!(report.contains("alice") && report.contains("   2 games"));
+}
@@ -0,0 +1,362 @@
+use crate::crazyhouse::Crazyhouse;
+use crate::movegen::{square_name, CASTLING_RIGHTS, CORNERS, NO_SQUARE};
+use crate::three_check::{ThreeCheck, CHECKS};
+use crate::variant::{self, Variant};
+use crate::ChessErr::InvalidFen;
+use crate::{ChessBoard, ChessErr, BLACK, EMPTY, GET_NUM, KING, PAWN, PIECE_LETTERS, ROOK, WHITE};
+
+/// Reads Three-check counters as the checks white and black have given: `3+2` counts the checks
+/// each side has left, and `+0+1` the checks each has given.
+fn read_checks(field: &str) -> Result<[u8; 2], ChessErr> {
+    let malformed = || InvalidFen("The check counters must look like 3+3 or +0+0.");
+    let (given, counts) = match field.strip_prefix('+') {
+        Some(counts) => (true, counts),
+        None => (false, field),
+    };
+    let (white, black) = counts.split_once('+').ok_or_else(malformed)?;
+    let count = |count: &str| match count.parse::<u8>() {
+        Ok(count) if count <= CHECKS => Ok(if given { count } else { CHECKS - count }),
+        _ => Err(malformed()),
+    };
+    Ok([count(white)?, count(black)?])
+}
+
+impl ChessBoard {
+    /// Reads a position written in Forsyth-Edwards Notation. The halfmove clock and fullmove
+    /// number may be left out, in which case they default to `0` and `1`.
+    ///
+    /// Chess960 castling rights may be written as in X-FEN (`KQkq` for the outermost rooks, the
+    /// rook's file otherwise) or Shredder-FEN (always the file, as in `HAha`). A position whose
+    /// castling rooks or king aren't where standard chess has them is read as Chess960.
+    ///
+    /// Crazyhouse positions list the pieces in hand after the placement, in brackets or as a
+    /// ninth rank (`.../RNBQKBNR[Qp]` or `.../RNBQKBNR/Qp`), and mark promoted pieces with a `~`.
+    /// A position with pieces in hand is read as Crazyhouse.
+    ///
+    /// Three-check positions give the checks each side has left after the en passant square
+    /// (`3+2`), or the checks each side has given after the move numbers (`+0+1`), and are read
+    /// as Three-check.
+    pub fn from_fen(fen: &str) -> Result<ChessBoard, ChessErr> {
+        ChessBoard::from_variant_fen(fen, &variant::STANDARD)
+    }
+
+    /// Reads a position of `variant` written in FEN, as `from_fen` does. The position is only
+    /// checked against the rules of `variant`, so that e.g. Antichess positions need no kings.
+    pub fn from_variant_fen(
+        fen: &str,
+        variant: &'static dyn Variant,
+    ) -> Result<ChessBoard, ChessErr> {
+        let mut fields = fen.split_whitespace().collect::<Vec<&str>>();
+        let checks = match (4..fields.len()).find(|&i| fields[i].contains('+')) {
+            Some(index) => Some(read_checks(fields.remove(index))?),
+            None => None,
+        };
+        if !(4..=6).contains(&fields.len()) {
+            return Err(InvalidFen("A FEN needs between four and six fields."));
+        }
+
+        let (files, rank_count) = variant.dimensions();
+        let mut board = ChessBoard::new().with_variant(variant);
+        board.board = [[0; 4]; 8];
+        let (placement, pockets) = match fields[0].strip_suffix(']') {
+            Some(placement) => placement
+                .split_once('[')
+                .map(|(placement, pockets)| (placement, Some(pockets)))
+                .ok_or(InvalidFen(
+                    "The pieces in hand are missing their opening bracket.",
+                ))?,
+            None if fields[0].split('/').count() == rank_count as usize + 1 => fields[0]
+                .rsplit_once('/')
+                .map(|(placement, pockets)| (placement, Some(pockets)))
+                .unwrap_or((fields[0], None)),
+            None => (fields[0], None),
+        };
+        let ranks = placement.split('/').collect::<Vec<&str>>();
+        if ranks.len() != rank_count as usize {
+            return Err(InvalidFen(match rank_count {
+                8 => "The piece placement needs exactly eight ranks.",
+                _ => "The piece placement has the wrong number of ranks for the variant.",
+            }));
+        }
+        for (row, rank) in ranks.iter().rev().enumerate() {
+            let mut file = 0u8;
+            for c in rank.chars() {
+                if c == '~' {
+                    if file == 0 {
+                        return Err(InvalidFen("A ~ must follow a promoted piece."));
+                    }
+                    board.promoted |= 1 << (row as u8 * 8 + file - 1);
+                    continue;
+                }
+                if let Some(skip) = c.to_digit(10).filter(|&d| (1..=files as u32).contains(&d)) {
+                    file += skip as u8;
+                } else {
+                    let piece = PIECE_LETTERS
+                        .iter()
+                        .position(|&letter| letter == c && c != ' ')
+                        .ok_or(InvalidFen("The piece placement has an unknown piece."))?;
+                    if file >= files {
+                        return Err(InvalidFen("A rank in the piece placement is too long."));
+                    }
+                    board.set_piece(row as u8 * 8 + file, piece as u8);
+                    file += 1;
+                }
+                if file > files {
+                    return Err(InvalidFen("A rank in the piece placement is too long."));
+                }
+            }
+            if file != files {
+                return Err(InvalidFen("A rank in the piece placement is too short."));
+            }
+        }
+
+        let black_to_move = match fields[1] {
+            "w" => false,
+            "b" => true,
+            _ => return Err(InvalidFen("The side to move must be w or b.")),
+        };
+
+        board.castling = 0;
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let color = if c.is_ascii_uppercase() { WHITE } else { BLACK };
+                let home = if color == WHITE { 0 } else { 56 };
+                let rook = ROOK | color;
+                let king = (home..home + 8)
+                    .find(|&square| board.get_piece(square) == KING | color)
+                    .ok_or(InvalidFen(
+                        "A side that can castle needs its king on its first rank.",
+                    ))?;
+                let rook_square = match c.to_ascii_lowercase() {
+                    'k' => (king + 1..home + 8)
+                        .rev()
+                        .find(|&square| board.get_piece(square) == rook),
+                    'q' => (home..king).find(|&square| board.get_piece(square) == rook),
+                    file @ 'a'..='h' => {
+                        board.chess960 = true;
+                        Some(home + (file as u8 - b'a')).filter(|&s| board.get_piece(s) == rook)
+                    }
+                    _ => return Err(InvalidFen("The castling rights are malformed.")),
+                }
+                .ok_or(InvalidFen("A castling right has no rook to castle with."))?;
+                let index = (color == BLACK) as usize * 2 + (rook_square < king) as usize;
+                board.castling |= CASTLING_RIGHTS[index];
+                board.castling_rooks[index] = rook_square;
+                if rook_square != CORNERS[index] || king & 7 != 4 {
+                    board.chess960 = true;
+                }
+            }
+        }
+
+        board.en_passant = match fields[3] {
+            "-" => NO_SQUARE,
+            square if ChessBoard::is_valid_piece(square.as_bytes()) => {
+                ChessBoard::square_of(square.as_bytes())
+            }
+            _ => return Err(InvalidFen("The en passant square is malformed.")),
+        };
+
+        let number = |i: usize, default: u16| match fields.get(i) {
+            Some(field) => field
+                .parse::<u16>()
+                .map_err(|_| InvalidFen("The move counters must be numbers.")),
+            None => Ok(default),
+        };
+        board.halfmoves = number(4, 0)?;
+        let fullmoves = number(5, 1)?.max(1);
+        board.moves = (fullmoves - 1)
+            .checked_mul(2)
+            .and_then(|moves| moves.checked_add(black_to_move as u16))
+            .ok_or(InvalidFen("The fullmove number is too large."))?;
+
+        if let Some(checks) = checks {
+            board.checks = checks;
+            if variant.check_limit().is_none() {
+                board = board.with_variant(&ThreeCheck);
+            }
+        }
+        if let Some(pockets) = pockets {
+            board.set_pockets(pockets).ok_or(InvalidFen(
+                "The pieces in hand must be letters of pawns to queens.",
+            ))?;
+            if !variant.has_pockets() {
+                board = board.with_variant(&Crazyhouse);
+            }
+        }
+
+        board.validate_setup()?;
+        Ok(board)
+    }
+
+    /// Rejects positions that could never be played from, so the move generator can rely on
+    /// there being exactly one king per side in variants where kings can be checked.
+    fn validate_setup(&self) -> Result<(), ChessErr> {
+        let royal_kings = match self.variant.has_royal_king() {
+            true => [WHITE, BLACK].as_slice(),
+            false => &[],
+        };
+        for &color in royal_kings {
+            let kings = (0..64)
+                .filter(|&square| self.get_piece(square) == KING | color)
+                .count();
+            if kings != 1 {
+                return Err(InvalidFen("Each side needs exactly one king."));
+            }
+        }
+        let (files, ranks) = self.variant.dimensions();
+        let last_rank = (ranks - 1) * 8;
+        let pawn_on_back_rank = (0..files)
+            .chain(last_rank..last_rank + files)
+            .any(|square| {
+                let piece = self.get_piece(square);
+                piece != EMPTY && GET_NUM(piece) == PAWN
+            });
+        if pawn_on_back_rank {
+            return Err(InvalidFen("Pawns cannot stand on the first or last rank."));
+        }
+        if self.is_in_check(self.side_to_move() ^ BLACK) {
+            return Err(InvalidFen("The side that just moved cannot be in check."));
+        }
+        Ok(())
+    }
+
+    /// Writes the position in Forsyth-Edwards Notation, using X-FEN for Chess960 castling
+    /// rights and brackets for the pieces in hand in Crazyhouse.
+    pub fn fen(&self) -> String {
+        let mut castling = String::new();
+        for (index, &flag) in CASTLING_RIGHTS.iter().enumerate() {
+            if self.castling & flag == 0 {
+                continue;
+            }
+            let rook = self.castling_rooks[index];
+            let home = rook & !7;
+            // `K` and `Q` name the outermost rook on their side of the king, so any other rook
+            // is named by its file.
+            let outer = match index % 2 {
+                0 => rook + 1..home + 8,
+                _ => home..rook,
+            };
+            let outermost = outer
+                .into_iter()
+                .all(|square| self.get_piece(square) != self.get_piece(rook));
+            let letter = match outermost {
+                true => ['K', 'Q'][index % 2],
+                false => (b'A' + (rook & 7)) as char,
+            };
+            castling.push(match index < 2 {
+                true => letter,
+                false => letter.to_ascii_lowercase(),
+            });
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let mut placement = self.placement();
+        if self.variant.has_pockets() {
+            placement.push_str(&format!("[{}]", self.pockets_fen()));
+        }
+        let mut en_passant = match self.en_passant {
+            NO_SQUARE => "-".to_string(),
+            square => square_name(square),
+        };
+        if let Some(limit) = self.variant.check_limit() {
+            let left = |checks: u8| limit.saturating_sub(checks);
+            en_passant.push_str(&format!(
+                " {}+{}",
+                left(self.checks[0]),
+                left(self.checks[1])
+            ));
+        }
+        format!(
+            "{} {} {} {} {} {}",
+            placement,
+            if self.side_to_move() == WHITE {
+                'w'
+            } else {
+                'b'
+            },
+            castling,
+            en_passant,
+            self.halfmoves,
+            self.moves / 2 + 1
+        )
+    }
+}
+
+#[test]
+fn test_fen() {
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let mut board = ChessBoard::new();
+    assert_eq!(board.fen(), start);
+    assert_eq!(ChessBoard::from_fen(start).unwrap().fen(), start);
+    for san in ["e4", "Nf6", "Ke2"] {
+        board.apply_move(board.parse_san(san).expect(san));
+    }
+    assert_eq!(
+        board.fen(),
+        "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2"
+    );
+    board.apply_move(board.parse_san("d5").expect("d5"));
+    assert_eq!(
+        board.fen(),
+        "rnbqkb1r/ppp1pppp/5n2/3p4/4P3/8/PPPPKPPP/RNBQ1BNR w kq d6 0 3"
+    );
+}
+
+#[test]
+fn test_from_fen() {
+    for fen in [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "rnbqkb1r/ppp1pppp/5n2/3p4/4P3/8/PPPPKPPP/RNBQ1BNR w kq d6 0 3",
+        "4k3/8/8/8/8/8/8/4K3 b - - 49 120",
+    ] {
+        assert_eq!(ChessBoard::from_fen(fen).expect(fen).fen(), fen);
+    }
+    assert_eq!(
+        ChessBoard::from_fen("4k3/8/8/8/8/8/8/4K3 w -  -")
+            .unwrap()
+            .fen(),
+        "4k3/8/8/8/8/8/8/4K3 w - - 0 1"
+    );
+
+    // Chess960 castling rights, in Shredder-FEN and X-FEN.
+    for (fen, written) in [
+        (
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9",
+        ),
+        (
+            "rk2r3/8/8/8/8/8/8/RK2R3 b EAea - 0 1",
+            "rk2r3/8/8/8/8/8/8/RK2R3 b KQkq - 0 1",
+        ),
+        (
+            "4k3/8/8/8/8/8/8/R3K1RR w Q - 0 1",
+            "4k3/8/8/8/8/8/8/R3K1RR w Q - 0 1",
+        ),
+    ] {
+        let board = ChessBoard::from_fen(fen).expect(fen);
+        assert_eq!(board.fen(), written);
+        assert_eq!(ChessBoard::from_fen(written).unwrap().fen(), written);
+        assert_eq!(board.chess960, !fen.contains("R3K1RR"));
+    }
+
+    for fen in [
+        "",
+        "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+        "4k3/8/8/8/8/8/8/R3K3 w C - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+        "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - a 1",
+        "rnbqqbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "4k3/8/8/8/8/8/8/4K2P w - - 0 1",
+        "4k2R/8/8/8/8/8/8/4K3 w - - 0 1",
+    ] {
+        assert!(ChessBoard::from_fen(fen).is_err(), "{}", fen);
+    }
+}
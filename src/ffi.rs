@@ -0,0 +1,250 @@
+//! A C interface to the rules and the engine, for embedding FreightChess in programs written in
+//! other languages. Build the shared library with `cargo build --release --features cdylib` and
+//! include `include/freight_chess.h`, which cbindgen generates from this file as `cbindgen.toml`
+//! says:
+//!
+//! ```c
+//! FcBoard *board = fc_board_new();
+//! fc_make_move(board, "e2e4");
+//! char move[8];
+//! if (fc_search_bestmove(board, 6, move, sizeof move) > 0)
+//!     printf("%s\n", move);
+//! fc_board_free(board);
+//! ```
+//!
+//! Moves are written as UCI writes them, as in `e2e4`, `e7e8q` or `N@f3`. Functions that return
+//! text copy it into a buffer the caller owns, as `snprintf` does: they write as much as fits,
+//! always ending it with a NUL, and return the length of the whole text, so a caller whose
+//! buffer was too small can call again with a larger one.
+
+use std::ffi::{c_char, CStr};
+
+use crate::movegen::Move;
+use crate::search::MAX_DEPTH;
+use crate::ChessBoard;
+
+/// A position, which C sees only through a pointer.
+pub struct FcBoard {
+    board: ChessBoard,
+}
+
+/// `fc_make_move` played the move.
+pub const FC_OK: i32 = 0;
+/// An argument was a null pointer or not valid UTF-8.
+pub const FC_INVALID_ARGUMENT: i32 = -1;
+/// The move isn't legal in the position.
+pub const FC_ILLEGAL_MOVE: i32 = 1;
+
+fn boxed(board: ChessBoard) -> *mut FcBoard {
+    Box::into_raw(Box::new(FcBoard { board }))
+}
+
+/// The text `text` points to, if it isn't null and is UTF-8.
+///
+/// # Safety
+///
+/// `text` must be null or point to a NUL-terminated string.
+unsafe fn text<'a>(text: *const c_char) -> Option<&'a str> {
+    match text.is_null() {
+        true => None,
+        false => CStr::from_ptr(text).to_str().ok(),
+    }
+}
+
+/// Copies as much of `text` as fits into the `capacity` bytes at `buffer`, ending it with a NUL,
+/// and returns the length of all of `text`.
+///
+/// # Safety
+///
+/// `buffer` must be null or point to `capacity` writable bytes.
+unsafe fn copy_out(text: &str, buffer: *mut c_char, capacity: usize) -> usize {
+    if !buffer.is_null() && capacity > 0 {
+        let length = text.len().min(capacity - 1);
+        std::ptr::copy_nonoverlapping(text.as_ptr(), buffer as *mut u8, length);
+        *buffer.add(length) = 0;
+    }
+    text.len()
+}
+
+/// Reads `text` as a move in UCI notation, or failing that in SAN.
+fn parse(board: &ChessBoard, text: &str) -> Option<Move> {
+    board
+        .legal_moves()
+        .into_iter()
+        .find(|mv| mv.to_string() == text)
+        .or_else(|| board.parse_san(text).ok())
+}
+
+/// A new board in the standard start position, to be freed with `fc_board_free`.
+#[no_mangle]
+pub extern "C" fn fc_board_new() -> *mut FcBoard {
+    boxed(ChessBoard::new())
+}
+
+/// A new board in the position `fen` describes, to be freed with `fc_board_free`, or null if
+/// `fen` isn't a valid position.
+///
+/// # Safety
+///
+/// `fen` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn fc_board_from_fen(fen: *const c_char) -> *mut FcBoard {
+    match text(fen).map(ChessBoard::from_fen) {
+        Some(Ok(board)) => boxed(board),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Frees a board made by `fc_board_new` or `fc_board_from_fen`. Freeing null does nothing.
+///
+/// # Safety
+///
+/// `board` must be null or a board not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn fc_board_free(board: *mut FcBoard) {
+    if !board.is_null() {
+        drop(Box::from_raw(board));
+    }
+}
+
+/// Writes the position's FEN into `buffer`.
+///
+/// # Safety
+///
+/// `board` must be a live board, and `buffer` null or `capacity` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fc_board_fen(
+    board: *const FcBoard,
+    buffer: *mut c_char,
+    capacity: usize,
+) -> usize {
+    copy_out(&(*board).board.fen(), buffer, capacity)
+}
+
+/// Writes every legal move into `buffer`, separated by spaces.
+///
+/// # Safety
+///
+/// `board` must be a live board, and `buffer` null or `capacity` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fc_legal_moves(
+    board: *const FcBoard,
+    buffer: *mut c_char,
+    capacity: usize,
+) -> usize {
+    let moves = (*board)
+        .board
+        .legal_moves()
+        .iter()
+        .map(Move::to_string)
+        .collect::<Vec<String>>();
+    copy_out(&moves.join(" "), buffer, capacity)
+}
+
+/// Plays `mv`, in UCI notation or SAN. Returns `FC_OK`, or `FC_ILLEGAL_MOVE` without changing
+/// the board if the move can't be played.
+///
+/// # Safety
+///
+/// `board` must be a live board, and `mv` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn fc_make_move(board: *mut FcBoard, mv: *const c_char) -> i32 {
+    let board = &mut (*board).board;
+    let Some(mv) = text(mv) else {
+        return FC_INVALID_ARGUMENT;
+    };
+    match parse(board, mv.trim()) {
+        Some(mv) => {
+            board.apply_move(mv);
+            FC_OK
+        }
+        None => FC_ILLEGAL_MOVE,
+    }
+}
+
+/// Searches `depth` plies deep, at most `MAX_DEPTH` (64), and writes the best move into `buffer`.
+/// Returns 0, writing an empty string, if the game is over.
+///
+/// # Safety
+///
+/// `board` must be a live board, and `buffer` null or `capacity` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fc_search_bestmove(
+    board: *const FcBoard,
+    depth: u32,
+    buffer: *mut c_char,
+    capacity: usize,
+) -> usize {
+    let best = (*board).board.search(depth.clamp(1, MAX_DEPTH)).best_move;
+    copy_out(
+        &best.map_or(String::new(), |mv| mv.to_string()),
+        buffer,
+        capacity,
+    )
+}
+
+#[test]
+fn test_ffi() {
+    let out = |write: &dyn Fn(*mut c_char, usize) -> usize| {
+        let mut buffer = [0 as c_char; 512];
+        let length = write(buffer.as_mut_ptr(), buffer.len());
+        let text = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+        assert_eq!(text.to_bytes().len(), length);
+        text.to_str().unwrap().to_string()
+    };
+    unsafe {
+        let board = fc_board_new();
+        assert_eq!(
+            fc_legal_moves(board, std::ptr::null_mut(), 0),
+            "a2a3 ".len() * 20 - 1
+        );
+        assert!(out(&|b, c| fc_legal_moves(board, b, c)).contains("g1f3"));
+        assert_eq!(fc_make_move(board, c"e2e4".as_ptr()), FC_OK);
+        assert_eq!(fc_make_move(board, c"e5".as_ptr()), FC_OK);
+        assert_eq!(fc_make_move(board, c"e2e4".as_ptr()), FC_ILLEGAL_MOVE);
+        assert_eq!(fc_make_move(board, std::ptr::null()), FC_INVALID_ARGUMENT);
+        assert_eq!(
+            out(&|b, c| fc_board_fen(board, b, c)),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+        );
+        // A short buffer holds what fits, ended with a NUL.
+        let mut short = [1 as c_char; 4];
+        assert!(fc_board_fen(board, short.as_mut_ptr(), 4) > 4);
+        assert_eq!(CStr::from_ptr(short.as_ptr()).to_str(), Ok("rnb"));
+        fc_board_free(board);
+
+        assert!(fc_board_from_fen(c"not a position".as_ptr()).is_null());
+        let mate = fc_board_from_fen(c"7k/8/6K1/8/8/8/8/R7 w - - 0 1".as_ptr());
+        assert_eq!(out(&|b, c| fc_search_bestmove(mate, 2, b, c)), "a1a8");
+        assert_eq!(fc_make_move(mate, c"Ra8#".as_ptr()), FC_OK);
+        assert_eq!(out(&|b, c| fc_search_bestmove(mate, 2, b, c)), "");
+        fc_board_free(mate);
+    }
+
+    // However deep it is asked to search, a search stops at `MAX_DEPTH`.
+    unsafe {
+        let board = fc_board_from_fen(c"7k/8/6K1/8/8/8/8/R7 w - - 0 1".as_ptr());
+        assert_eq!(
+            out(&|b, c| fc_search_bestmove(board, u32::MAX, b, c)),
+            "a1a8"
+        );
+        fc_board_free(board);
+    }
+
+    // The header, which cbindgen generates, declares everything the library exports as it is.
+    let header = include_str!("../include/freight_chess.h");
+    for declaration in [
+        "FcBoard *fc_board_new(void);",
+        "FcBoard *fc_board_from_fen(const char *fen);",
+        "void fc_board_free(FcBoard *board);",
+        "size_t fc_board_fen(const FcBoard *board, char *buffer, size_t capacity);",
+        "size_t fc_legal_moves(const FcBoard *board, char *buffer, size_t capacity);",
+        "int32_t fc_make_move(FcBoard *board, const char *mv);",
+        "size_t fc_search_bestmove(const FcBoard *board, uint32_t depth, char *buffer, size_t capacity);",
+        "#define FC_OK 0",
+        "#define FC_INVALID_ARGUMENT -1",
+        "#define FC_ILLEGAL_MOVE 1",
+    ] {
+        assert!(header.contains(declaration), "{}", declaration);
+    }
+}
@@ -0,0 +1,197 @@
+//! Training data: positions from self-play games, each with the search's score and the game's
+//! result, for training NNUE networks or tuning the classical evaluation with `tune`.
+//!
+//! Only quiet positions are kept (the side to move isn't in check and the best move isn't a
+//! capture or promotion), since a static evaluation can't be expected to see an exchange
+//! through, and positions with a forced mate are left out.
+
+use std::io::{BufWriter, Error, Write};
+
+use crate::movegen::Move;
+use crate::net::random_u64;
+use crate::search::{SearchParams, SearchResult, MATE};
+use crate::selfplay::{play_recorded, random_opening, Rng};
+use crate::{ChessBoard, BLACK, EMPTY};
+
+/// How many random plies a game starts with, at the least.
+const OPENING_PLIES: usize = 8;
+
+/// How the positions are written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Stockfish's plain text format, which its tools convert to binpacks: `fen`, `move`,
+    /// `score` and `ply` lines, then `result` (1, 0 or -1 for the side to move) and `e`.
+    Plain,
+    /// One EPD line per position with the result as `c9`, which `tune` reads.
+    Epd,
+}
+
+impl Format {
+    /// The format called `name` on the command line.
+    pub fn by_name(name: &str) -> Option<Format> {
+        match name {
+            "plain" => Some(Format::Plain),
+            "epd" => Some(Format::Epd),
+            _ => None,
+        }
+    }
+}
+
+/// A position worth training on, and what the search made of it.
+struct Position {
+    board: ChessBoard,
+    best_move: Move,
+    score: i32,
+    ply: usize,
+}
+
+/// Writes `position` in `format`, for a game white scored `score` in (1, 0.5 or 0).
+fn write_position(
+    output: &mut impl Write,
+    position: &Position,
+    score: f64,
+    format: Format,
+) -> Result<(), Error> {
+    let board = &position.board;
+    match format {
+        Format::Plain => {
+            let result = match board.side_to_move() {
+                BLACK => 1.0 - score,
+                _ => score,
+            };
+            writeln!(output, "fen {}", board.fen())?;
+            writeln!(output, "move {}", position.best_move)?;
+            writeln!(output, "score {}", position.score)?;
+            writeln!(output, "ply {}", position.ply)?;
+            writeln!(output, "result {}", (result * 2.0) as i32 - 1)?;
+            writeln!(output, "e")
+        }
+        Format::Epd => {
+            let result = match score {
+                1.0 => "1-0",
+                0.0 => "0-1",
+                _ => "1/2-1/2",
+            };
+            writeln!(output, "{} c9 \"{}\";", board.fen(), result)
+        }
+    }
+}
+
+/// Whether the position is quiet enough to train on.
+fn is_quiet(board: &ChessBoard, result: &SearchResult) -> bool {
+    let mv = result.best_move.unwrap();
+    !board.is_in_check(board.side_to_move())
+        && !board.is_capture(mv)
+        && mv.promotion == EMPTY
+        && result.score.abs() < MATE - 1000
+}
+
+/// Plays `games` games of self-play from random openings, searching `depth` plies per move,
+/// and writes their quiet positions to `output` in `format`. Returns how many were written.
+fn generate(
+    games: u32,
+    depth: u32,
+    format: Format,
+    rng: &mut Rng,
+    output: &mut impl Write,
+) -> Result<usize, Error> {
+    let params = [SearchParams::default(); 2];
+    let mut written = 0;
+    for game in 1..=games {
+        let plies = OPENING_PLIES + rng.below(4);
+        let start = random_opening(plies, rng);
+        let mut positions = Vec::new();
+        let mut ply = plies;
+        let score = play_recorded(start, params, depth, u64::MAX, |board, result| {
+            if is_quiet(board, result) {
+                positions.push(Position {
+                    board: *board,
+                    best_move: result.best_move.unwrap(),
+                    score: result.score,
+                    ply,
+                });
+            }
+            ply += 1;
+        });
+        for position in &positions {
+            write_position(output, position, score, format)?;
+        }
+        written += positions.len();
+        eprintln!(
+            "Game {}: {} for white, {} positions.",
+            game,
+            score,
+            positions.len()
+        );
+    }
+    output.flush()?;
+    Ok(written)
+}
+
+/// Runs `gen-data`: writes the positions of `games` self-play games, searched to `depth`, to
+/// `output` (or stdout) in `format`.
+pub fn run(games: u32, depth: u32, format: Format, output: Option<&str>) -> Result<(), Error> {
+    let mut rng = Rng::new(random_u64());
+    let written = match output {
+        Some(path) => generate(
+            games,
+            depth,
+            format,
+            &mut rng,
+            &mut BufWriter::new(std::fs::File::create(path)?),
+        )?,
+        None => generate(
+            games,
+            depth,
+            format,
+            &mut rng,
+            &mut std::io::stdout().lock(),
+        )?,
+    };
+    eprintln!("Wrote {} positions.", written);
+    Ok(())
+}
+
+#[test]
+fn test_gen_data() {
+    let mut output = Vec::new();
+    let written = generate(1, 1, Format::Plain, &mut Rng::new(3), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(written > 0);
+    assert_eq!(output.lines().filter(|&line| line == "e").count(), written);
+    let first = output.lines().take(6).collect::<Vec<&str>>();
+    let board = ChessBoard::from_fen(first[0].strip_prefix("fen ").unwrap()).unwrap();
+    let mv = first[1].strip_prefix("move ").unwrap();
+    assert!(board
+        .legal_moves()
+        .iter()
+        .any(|legal| legal.to_string() == mv));
+    assert!(first[2]
+        .strip_prefix("score ")
+        .unwrap()
+        .parse::<i32>()
+        .is_ok());
+    assert!(
+        first[3]
+            .strip_prefix("ply ")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap()
+            >= OPENING_PLIES
+    );
+    assert!(["result 1", "result 0", "result -1"].contains(&first[4]));
+    assert_eq!(first[5], "e");
+
+    // The same game again, as EPD that `tune` can read.
+    let mut epd = Vec::new();
+    generate(1, 1, Format::Epd, &mut Rng::new(3), &mut epd).unwrap();
+    let epd = String::from_utf8(epd).unwrap();
+    assert_eq!(epd.lines().count(), written);
+    assert!(epd
+        .lines()
+        .next()
+        .unwrap()
+        .starts_with(first[0].strip_prefix("fen ").unwrap()));
+    assert_eq!(Format::by_name("epd"), Some(Format::Epd));
+    assert_eq!(Format::by_name("binpack"), None);
+}
@@ -0,0 +1,176 @@
+//! Lays the board out in the terminal as a grid of cells of a fixed width, so its columns line
+//! up whatever the glyphs in them. Terminals give each character one or two columns: two for
+//! the wide characters of East Asian scripts, none for combining marks, and, for the chess
+//! glyphs, whatever the font draws them as. Unicode calls the chess glyphs narrow, but many
+//! fonts draw them wide, so how wide they are is a setting rather than a guess:
+//!
+//! ```toml
+//! [display]
+//! cell_width = 3                # columns each square takes
+//! glyph_width = 2               # columns the terminal draws a chess glyph in
+//! ```
+
+use crate::{ChessBoard, CHESS_PIECES};
+
+/// The widest a cell can be set to.
+pub const MAX_CELL_WIDTH: usize = 8;
+
+/// How wide the cells of the grid are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    /// The columns each square takes. Content narrower than this is centered in it.
+    pub cell_width: usize,
+    /// The columns the terminal draws a chess glyph in: 1 or 2.
+    pub glyph_width: usize,
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout {
+            cell_width: 1,
+            glyph_width: 1,
+        }
+    }
+}
+
+/// Characters that take no columns of their own: combining marks, zero-width spaces and
+/// joiners, and variation selectors.
+const ZERO_WIDTH: [(u32, u32); 5] = [
+    (0x0300, 0x036f),
+    (0x200b, 0x200f),
+    (0x20d0, 0x20ff),
+    (0xfe00, 0xfe0f),
+    (0xfe20, 0xfe2f),
+];
+
+/// The East Asian wide and fullwidth characters, which take two columns, and the emoji that are
+/// drawn the same way.
+const WIDE: [(u32, u32); 15] = [
+    (0x1100, 0x115f),
+    (0x2e80, 0x303e),
+    (0x3041, 0x33ff),
+    (0x3400, 0x4dbf),
+    (0x4e00, 0x9fff),
+    (0xa000, 0xa4cf),
+    (0xac00, 0xd7a3),
+    (0xf900, 0xfaff),
+    (0xfe30, 0xfe4f),
+    (0xff00, 0xff60),
+    (0xffe0, 0xffe6),
+    (0x1f300, 0x1f64f),
+    (0x1f900, 0x1f9ff),
+    (0x20000, 0x2fffd),
+    (0x30000, 0x3fffd),
+];
+
+/// The chess glyphs, from the white king to the black pawn.
+const CHESS_GLYPHS: (u32, u32) = (0x2654, 0x265f);
+
+impl Layout {
+    /// A layout with cells `cell_width` wide, widened to fit a glyph if need be.
+    pub fn new(cell_width: usize, glyph_width: usize) -> Layout {
+        Layout {
+            cell_width: cell_width.max(glyph_width),
+            glyph_width,
+        }
+    }
+
+    /// The columns the terminal draws `c` in.
+    pub fn char_width(&self, c: char) -> usize {
+        let code = c as u32;
+        let within = |&(low, high): &(u32, u32)| (low..=high).contains(&code);
+        if within(&CHESS_GLYPHS) {
+            self.glyph_width
+        } else if ZERO_WIDTH.iter().any(within) || c.is_control() {
+            0
+        } else if WIDE.iter().any(within) {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// The columns the terminal draws `text` in.
+    pub fn width(&self, text: &str) -> usize {
+        text.chars().map(|c| self.char_width(c)).sum()
+    }
+
+    /// `text` centered in a cell, with any odd column of padding on the right. Text wider than
+    /// a cell is left as it is.
+    pub fn cell(&self, text: &str) -> String {
+        let padding = self.cell_width.saturating_sub(self.width(text));
+        let left = padding / 2;
+        format!("{}{}{}", " ".repeat(left), text, " ".repeat(padding - left))
+    }
+
+    /// The same layout with `extra` more columns in each cell.
+    pub fn widened(&self, extra: usize) -> Layout {
+        Layout {
+            cell_width: self.cell_width + extra,
+            ..*self
+        }
+    }
+}
+
+impl ChessBoard {
+    /// Draws the board as a grid with white at the bottom and a `|` between squares, with the
+    /// checks given and pieces in hand in the variants that have them.
+    pub fn grid(&self, layout: &Layout) -> String {
+        let (files, ranks) = self.variant.dimensions();
+        let mut text = String::new();
+        for rank in (0..ranks).rev() {
+            text.push('|');
+            for square in rank * 8..rank * 8 + files {
+                let glyph = CHESS_PIECES[self.get_piece(square) as usize];
+                text.push_str(&layout.cell(&glyph.to_string()));
+                text.push('|');
+            }
+            text.push('\n');
+        }
+        if let Some(limit) = self.variant.check_limit() {
+            text.push_str(&format!(
+                "Checks given: white {}, black {} ({} win).\n",
+                self.checks[0], self.checks[1], limit
+            ));
+        }
+        if self.variant.has_pockets() {
+            text.push_str(&format!(
+                "In hand: {}; {}.\n",
+                self.describe_pocket(crate::WHITE),
+                self.describe_pocket(crate::BLACK)
+            ));
+        }
+        text
+    }
+}
+
+#[test]
+fn test_grid() {
+    let narrow = Layout::default();
+    let wide = Layout::new(3, 2);
+    assert_eq!(narrow.width("♞"), 1);
+    assert_eq!(wide.width("♞"), 2);
+    assert_eq!(wide.width("象"), 2);
+    assert_eq!(wide.width("ｂ"), 2);
+    assert_eq!(wide.width("e\u{301}"), 1);
+    assert_eq!(wide.width("a1"), 2);
+    assert_eq!(Layout::new(1, 2), Layout::new(2, 2));
+
+    assert_eq!(wide.cell("♞"), "♞ ");
+    assert_eq!(wide.cell(" "), "   ");
+    assert_eq!(Layout::new(4, 1).cell("♞"), " ♞  ");
+    assert_eq!(narrow.cell("ab"), "ab");
+
+    // Every row takes the same columns, with the glyphs as wide as the empty squares.
+    let board = ChessBoard::new();
+    assert_eq!(board.grid(&narrow), board.to_string());
+    let grid = board.grid(&Layout::new(2, 2));
+    let rows = grid.lines().collect::<Vec<&str>>();
+    assert_eq!(rows[0], "|♜|♞|♝|♛|♚|♝|♞|♜|");
+    assert_eq!(rows[3], "|  |  |  |  |  |  |  |  |");
+    let widths = rows
+        .iter()
+        .map(|row| Layout::new(2, 2).width(row))
+        .collect::<Vec<usize>>();
+    assert!(widths.iter().all(|&width| width == 25), "{:?}", widths);
+}
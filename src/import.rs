@@ -0,0 +1,271 @@
+//! Downloads games from Lichess and Chess.com through their public APIs, either a single game
+//! from its URL or a player's most recent games.
+
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::db::{Database, Stored};
+use crate::pgn::{self, PgnGame};
+
+/// Where games can be imported from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Site {
+    Lichess,
+    ChessCom,
+}
+
+/// What has to be downloaded to import what the user asked for.
+#[derive(Debug, PartialEq)]
+enum Download {
+    /// A URL answering with PGN.
+    Pgn(String),
+    /// The list of a Chess.com player's monthly archives, of which the latest are needed.
+    ChessComArchives(String),
+}
+
+impl Site {
+    pub fn from_name(name: &str) -> Option<Site> {
+        match name {
+            "lichess" => Some(Site::Lichess),
+            "chesscom" | "chess.com" => Some(Site::ChessCom),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+fn is_valid_username(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 30
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Works out what to download for `target`: a game or profile URL from either site, or the
+/// name of a player on `site`, of whom the `max` most recent games are wanted.
+fn resolve(target: &str, site: Site, max: usize) -> Result<Download, Error> {
+    let url = target
+        .strip_prefix("https://")
+        .or_else(|| target.strip_prefix("http://"));
+    let (site, name) = match url {
+        None => (site, target),
+        Some(url) => {
+            let (host, path) = url.split_once('/').unwrap_or((url, ""));
+            let segments = path
+                .split(['/', '?', '#'])
+                .filter(|segment| !segment.is_empty())
+                .collect::<Vec<&str>>();
+            match (host.trim_start_matches("www."), segments.as_slice()) {
+                ("lichess.org", ["@", name, ..]) => (Site::Lichess, *name),
+                // Game links are 8 characters, or 12 when they show the game from one side.
+                ("lichess.org", [id, ..]) if id.len() == 8 || id.len() == 12 => {
+                    let url = format!("https://lichess.org/game/export/{}", &id[..8]);
+                    return Ok(Download::Pgn(url));
+                }
+                ("chess.com", ["member", name, ..]) => (Site::ChessCom, *name),
+                ("chess.com", ["game", ..]) => {
+                    return Err(invalid(
+                        "Chess.com has no public API for single games. Import the player's \
+                        games with their username instead."
+                            .to_string(),
+                    ))
+                }
+                _ => return Err(invalid(format!("Don't know how to import {}.", target))),
+            }
+        }
+    };
+    if !is_valid_username(name) {
+        return Err(invalid(format!("{} is not a valid username.", name)));
+    }
+    Ok(match site {
+        Site::Lichess => Download::Pgn(format!(
+            "https://lichess.org/api/games/user/{}?max={}",
+            name, max
+        )),
+        Site::ChessCom => Download::ChessComArchives(format!(
+            "https://api.chess.com/pub/player/{}/games/archives",
+            name.to_lowercase()
+        )),
+    })
+}
+
+fn get(url: &str, accept: &str) -> Result<String, Error> {
+    ureq::get(url)
+        .header("Accept", accept)
+        .call()
+        .and_then(|mut response| response.body_mut().read_to_string())
+        .map_err(|e| Error::other(format!("Couldn't download {}: {}", url, e)))
+}
+
+fn get_json(url: &str) -> Result<Value, Error> {
+    serde_json::from_str(&get(url, "application/json")?)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, format!("{} didn't send JSON.", url)))
+}
+
+/// The PGN of every game in a Chess.com monthly archive, most recent first.
+fn chess_com_games(archive: &Value) -> Vec<String> {
+    let games = archive["games"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or(&[]);
+    games
+        .iter()
+        .rev()
+        .filter_map(|game| game["pgn"].as_str())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Downloads the PGN of everything `download` refers to, up to `max` games.
+fn download(download: Download, max: usize) -> Result<Vec<String>, Error> {
+    match download {
+        Download::Pgn(url) => {
+            let text = get(&url, "application/x-chess-pgn")?;
+            Ok(pgn::split_games(&text)
+                .into_iter()
+                .take(max)
+                .map(str::to_string)
+                .collect())
+        }
+        Download::ChessComArchives(url) => {
+            let archives = get_json(&url)?;
+            let mut games = Vec::new();
+            let months = archives["archives"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or(&[]);
+            for month in months.iter().rev().filter_map(Value::as_str) {
+                if games.len() >= max {
+                    break;
+                }
+                games.extend(chess_com_games(&get_json(month)?));
+            }
+            games.truncate(max);
+            Ok(games)
+        }
+    }
+}
+
+/// Imports the games `target` refers to (see `resolve`), converting them to `PgnGame`s. Games
+/// that can't be read, such as variants, are skipped with a warning.
+pub fn fetch(target: &str, site: Site, max: usize) -> Result<Vec<PgnGame>, Error> {
+    let texts = download(resolve(target, site, max)?, max)?;
+    let mut games = Vec::new();
+    for text in texts {
+        match PgnGame::parse(&text) {
+            Ok(game) => games.push(game),
+            Err(e) => eprintln!("Skipping a game that couldn't be read: {}", e),
+        }
+    }
+    Ok(games)
+}
+
+/// Runs the `import` subcommand: the games are printed as PGN, or appended to `output` with a
+/// one-line summary of each printed instead. With `database`, they are stored there too (unless
+/// already stored), and only printed if `output` is given.
+pub fn import(
+    target: &str,
+    site: Site,
+    max: usize,
+    output: Option<&Path>,
+    database: Option<&Path>,
+) -> Result<(), Error> {
+    let games = fetch(target, site, max)?;
+    if let Some(path) = database {
+        let database = Database::open(path)?;
+        let mut duplicates = 0;
+        for game in &games {
+            if let Stored::Duplicate(_) = database.store(game)? {
+                duplicates += 1;
+            }
+        }
+        println!(
+            "Stored {} games in {}, skipped {} duplicates.",
+            games.len() - duplicates,
+            path.display(),
+            duplicates
+        );
+        if output.is_none() {
+            return Ok(());
+        }
+    }
+    let pgn = games
+        .iter()
+        .map(PgnGame::to_pgn)
+        .collect::<Vec<String>>()
+        .join("\n");
+    let output = match output {
+        Some(output) => output,
+        None => {
+            print!("{}", pgn);
+            return Ok(());
+        }
+    };
+    let mut text = std::fs::read_to_string(output).unwrap_or_default();
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text.push_str(&pgn);
+    std::fs::write(output, text)?;
+    for game in &games {
+        println!(
+            "{} - {} {} ({} moves)",
+            game.tag("White").unwrap_or("?"),
+            game.tag("Black").unwrap_or("?"),
+            game.result,
+            game.tree.mainline.len().div_ceil(2)
+        );
+    }
+    println!("Imported {} games into {}.", games.len(), output.display());
+    Ok(())
+}
+
+#[test]
+fn test_import() {
+    let lichess = |url: &str| Download::Pgn(url.to_string());
+    assert_eq!(
+        resolve("https://lichess.org/q7ZvsdUF", Site::ChessCom, 10).unwrap(),
+        lichess("https://lichess.org/game/export/q7ZvsdUF")
+    );
+    assert_eq!(
+        resolve(
+            "https://lichess.org/q7ZvsdUFb3e1/black#12",
+            Site::Lichess,
+            10
+        )
+        .unwrap(),
+        lichess("https://lichess.org/game/export/q7ZvsdUF")
+    );
+    assert_eq!(
+        resolve("DrNykterstein", Site::Lichess, 5).unwrap(),
+        lichess("https://lichess.org/api/games/user/DrNykterstein?max=5")
+    );
+    assert_eq!(
+        resolve("https://lichess.org/@/DrNykterstein", Site::ChessCom, 5).unwrap(),
+        lichess("https://lichess.org/api/games/user/DrNykterstein?max=5")
+    );
+    assert_eq!(
+        resolve("https://www.chess.com/member/Hikaru", Site::Lichess, 5).unwrap(),
+        Download::ChessComArchives(
+            "https://api.chess.com/pub/player/hikaru/games/archives".to_string()
+        )
+    );
+    assert!(resolve("https://www.chess.com/game/live/123", Site::Lichess, 5).is_err());
+    assert!(resolve("https://example.com/game", Site::Lichess, 5).is_err());
+    assert!(resolve("no spaces", Site::Lichess, 5).is_err());
+
+    let archive = serde_json::json!({ "games": [
+        { "pgn": "[Event \"Live Chess\"]\n\n1. e4 1-0" },
+        { "rules": "chess960" },
+        { "pgn": "[Event \"Live Chess\"]\n\n1. d4 0-1" },
+    ] });
+    let games = chess_com_games(&archive);
+    assert_eq!(games.len(), 2);
+    assert!(games[0].ends_with("1. d4 0-1"));
+}
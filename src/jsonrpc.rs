@@ -0,0 +1,385 @@
+//! JSON-RPC 2.0 on stdin and stdout, for editors and GUIs that would rather call methods than
+//! parse UCI text. Messages are framed as in the Language Server Protocol, each preceded by a
+//! `Content-Length` header and a blank line; a client may instead send one message per line,
+//! and is answered the same way. The session holds one position, which the methods of
+//! `docs/jsonrpc.md` set, play moves in, take moves back from and analyze.
+
+use std::io::{BufRead, Error, ErrorKind, Write};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::movegen::color_name;
+use crate::search::MAX_DEPTH;
+use crate::variant::{self, Variant};
+use crate::{ChessBoard, ChessErr};
+
+/// How deep `engine/analyze` searches when it isn't limited otherwise.
+const DEFAULT_DEPTH: u64 = 4;
+
+/// The error codes of the JSON-RPC spec.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// How a message arrived, and so how its reply is sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Framing {
+    Header,
+    Line,
+}
+
+/// A failed call: its error code, message, and any machine-readable detail.
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Value,
+}
+
+fn invalid_params(message: &str) -> RpcError {
+    RpcError {
+        code: INVALID_PARAMS,
+        message: message.to_string(),
+        data: Value::Null,
+    }
+}
+
+fn chess_error(e: ChessErr) -> RpcError {
+    let reason = match &e {
+        ChessErr::BadMove(reason) => reason.kind(),
+        e => e.kind(),
+    };
+    RpcError {
+        code: INVALID_PARAMS,
+        message: e.to_string(),
+        data: json!({ "reason": reason }),
+    }
+}
+
+/// The session's position, and the way to it.
+struct Session {
+    /// The position after each move so far, the starting one first.
+    boards: Vec<ChessBoard>,
+    /// Every move played since the starting position, in SAN.
+    moves: Vec<String>,
+    /// Set once the client asks to exit.
+    exit: bool,
+}
+
+impl Session {
+    fn board(&self) -> &ChessBoard {
+        self.boards.last().unwrap()
+    }
+
+    /// Describes the position the way every position method reports it.
+    fn state(&self) -> Value {
+        let board = self.board();
+        let (status, result) = match board.outcome() {
+            Some((result, reason)) => (reason, Some(result)),
+            None => ("ongoing", None),
+        };
+        json!({
+            "fen": board.fen(),
+            "variant": board.variant.name(),
+            "start": self.boards[0].fen(),
+            "moves": self.moves,
+            "side_to_move": color_name(board.side_to_move()),
+            "status": status,
+            "result": result,
+        })
+    }
+
+    /// Plays the moves of `params["moves"]`, if any, all or none of them.
+    fn play(&mut self, params: &Value) -> Result<(), RpcError> {
+        let moves = match &params["moves"] {
+            Value::Null => return Ok(()),
+            Value::Array(moves) => moves,
+            _ => return Err(invalid_params("\"moves\" must be a list of moves.")),
+        };
+        let mut board = *self.board();
+        let (mut boards, mut played) = (Vec::new(), Vec::new());
+        for mv in moves {
+            let text = mv
+                .as_str()
+                .ok_or_else(|| invalid_params("\"moves\" must be a list of moves."))?;
+            let mv = board.parse_move(text).map_err(chess_error)?;
+            played.push(board.san(mv));
+            board.apply_move(mv);
+            boards.push(board);
+        }
+        self.boards.extend(boards);
+        self.moves.extend(played);
+        Ok(())
+    }
+
+    /// Runs `method` with `params`, returning its result.
+    fn call(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "position/set" => {
+                let variant: &'static dyn Variant = match &params["variant"] {
+                    Value::Null => self.board().variant,
+                    name => name.as_str().and_then(variant::by_name).ok_or_else(|| {
+                        invalid_params(&format!(
+                            "\"variant\" must be one of {}.",
+                            variant::names().join(", ")
+                        ))
+                    })?,
+                };
+                let start = match &params["fen"] {
+                    Value::Null => ChessBoard::start_of(variant),
+                    fen => {
+                        let fen = fen
+                            .as_str()
+                            .ok_or_else(|| invalid_params("\"fen\" must be a string."))?;
+                        ChessBoard::from_variant_fen(fen, variant).map_err(chess_error)?
+                    }
+                };
+                let previous = std::mem::replace(
+                    self,
+                    Session {
+                        boards: vec![start],
+                        moves: Vec::new(),
+                        exit: false,
+                    },
+                );
+                if let Err(e) = self.play(params) {
+                    *self = previous;
+                    return Err(e);
+                }
+                Ok(self.state())
+            }
+            "position/get" => Ok(self.state()),
+            "position/play" => {
+                let mv = params["move"]
+                    .as_str()
+                    .ok_or_else(|| invalid_params("Send the move as \"move\"."))?;
+                self.play(&json!({ "moves": [mv] }))?;
+                Ok(self.state())
+            }
+            "position/undo" => {
+                let count = params["count"].as_u64().unwrap_or(1) as usize;
+                if count > self.moves.len() {
+                    return Err(invalid_params("There aren't that many moves to take back."));
+                }
+                self.boards.truncate(self.boards.len() - count);
+                self.moves.truncate(self.moves.len() - count);
+                Ok(self.state())
+            }
+            "position/legalMoves" => {
+                let board = self.board();
+                let moves = board
+                    .legal_moves()
+                    .iter()
+                    .map(|&mv| json!({ "san": board.san(mv), "uci": mv.to_string() }))
+                    .collect::<Vec<Value>>();
+                Ok(json!(moves))
+            }
+            "engine/analyze" => {
+                let limit = |name: &str| match &params[name] {
+                    Value::Null => Ok(None),
+                    value => match value.as_u64() {
+                        Some(limit @ 1..) => Ok(Some(limit)),
+                        _ => Err(invalid_params(&format!(
+                            "\"{}\" must be a positive number.",
+                            name
+                        ))),
+                    },
+                };
+                let (depth, nodes, movetime) =
+                    (limit("depth")?, limit("nodes")?, limit("movetime_ms")?);
+                let depth = match (depth, nodes, movetime) {
+                    (Some(depth), _, _) => depth.min(MAX_DEPTH as u64),
+                    (None, None, None) => DEFAULT_DEPTH,
+                    (None, _, _) => MAX_DEPTH as u64,
+                };
+                let board = self.board();
+                let nodes = nodes.unwrap_or(u64::MAX);
+                let search = match movetime {
+                    Some(ms) => board.search_within(depth as u32, nodes, Duration::from_millis(ms)),
+                    None => board.search_nodes(depth as u32, nodes),
+                };
+                let pv = board.line_sans(&search.pv);
+                Ok(json!({
+                    "best_move": pv.first(),
+                    "uci": search.best_move.map(|mv| mv.to_string()),
+                    "score": search.score,
+                    "pv": pv,
+                    "depth": search.depth,
+                    "nodes": search.nodes,
+                }))
+            }
+            "exit" => {
+                self.exit = true;
+                Ok(Value::Null)
+            }
+            _ => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("There is no method {}.", method),
+                data: Value::Null,
+            }),
+        }
+    }
+
+    /// Handles one message, returning the reply to send, if any: notifications, which have no
+    /// `id`, get none.
+    fn handle(&mut self, message: &str) -> Option<Value> {
+        let request = match serde_json::from_str::<Value>(message) {
+            Ok(request) => request,
+            Err(e) => return Some(error_reply(Value::Null, PARSE_ERROR, &e.to_string())),
+        };
+        let id = request.get("id").cloned();
+        let method = match (&request["jsonrpc"], &request["method"]) {
+            (Value::String(version), Value::String(method)) if version == "2.0" => method,
+            _ => {
+                let message = "Requests need \"jsonrpc\": \"2.0\" and a \"method\".";
+                return Some(error_reply(
+                    id.unwrap_or(Value::Null),
+                    INVALID_REQUEST,
+                    message,
+                ));
+            }
+        };
+        let params = match &request["params"] {
+            Value::Null => json!({}),
+            params @ Value::Object(_) => params.clone(),
+            _ => {
+                let message = "\"params\" must be an object.";
+                return id.map(|id| error_reply(id, INVALID_PARAMS, message));
+            }
+        };
+        let _span = tracing::info_span!(target: "jsonrpc", "request", %method).entered();
+        tracing::debug!(target: "jsonrpc", "<< {}", method);
+        let result = self.call(method, &params);
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => {
+                let mut reply = error_reply(id, e.code, &e.message);
+                if !e.data.is_null() {
+                    reply["error"]["data"] = e.data;
+                }
+                reply
+            }
+        })
+    }
+}
+
+fn error_reply(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// Reads the next message, or `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> Result<Option<(Framing, String)>, Error> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.starts_with('{') && length.is_none() {
+            return Ok(Some((Framing::Line, line.to_string())));
+        }
+        if line.is_empty() {
+            if let Some(length) = length {
+                let mut body = vec![0; length];
+                input.read_exact(&mut body)?;
+                let body = String::from_utf8(body)
+                    .map_err(|_| Error::new(ErrorKind::InvalidData, "A message isn't UTF-8."))?;
+                return Ok(Some((Framing::Header, body)));
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+}
+
+fn write_message(output: &mut impl Write, framing: Framing, message: &Value) -> Result<(), Error> {
+    let message = message.to_string();
+    match framing {
+        Framing::Header => write!(
+            output,
+            "Content-Length: {}\r\n\r\n{}",
+            message.len(),
+            message
+        )?,
+        Framing::Line => writeln!(output, "{}", message)?,
+    }
+    output.flush()
+}
+
+/// Serves JSON-RPC on `input` and `output`, starting from `board`, until the input ends or the
+/// client calls `exit`.
+pub fn run(
+    mut input: impl BufRead,
+    output: &mut impl Write,
+    board: ChessBoard,
+) -> Result<(), Error> {
+    let mut session = Session {
+        boards: vec![board],
+        moves: Vec::new(),
+        exit: false,
+    };
+    while let Some((framing, message)) = read_message(&mut input)? {
+        if let Some(reply) = session.handle(&message) {
+            write_message(output, framing, &reply)?;
+        }
+        if session.exit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_jsonrpc() {
+    let requests = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"position/set","params":{"moves":["e4","e5"]}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"position/play","params":{"move":"Ke3"}}"#,
+        r#"{"jsonrpc":"2.0","method":"position/play","params":{"move":"g1->f3"}}"#,
+        r#"{"jsonrpc":"2.0","id":"a","method":"position/legalMoves"}"#,
+        r#"{"jsonrpc":"2.0","id":4,"method":"position/undo","params":{"count":2}}"#,
+        r#"{"jsonrpc":"2.0","id":5,"method":"position/resign"}"#,
+        "{not json",
+    ];
+    let mut input = requests.join("\n") + "\n";
+    let framed = r#"{"jsonrpc":"2.0","id":6,"method":"engine/analyze","params":{"nodes":1}}"#;
+    input += &format!("Content-Length: {}\r\n\r\n{}", framed.len(), framed);
+    input += "{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":\"exit\"}\n{\"jsonrpc\":\"2.0\",\"id\":8}\n";
+
+    let mut output = Vec::new();
+    run(input.as_bytes(), &mut output, ChessBoard::new()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let (lines, framed) = output.split_once("Content-Length: ").unwrap();
+    let replies = lines
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<Value>>();
+    assert_eq!(replies.len(), 6);
+    assert_eq!(replies[0]["id"], 1);
+    assert_eq!(replies[0]["result"]["moves"], json!(["e4", "e5"]));
+    assert_eq!(replies[0]["result"]["side_to_move"], "white");
+    assert_eq!(replies[1]["error"]["code"], INVALID_PARAMS);
+    assert!(replies[1]["error"]["data"]["reason"].is_string());
+    // The notification played Nf3 without a reply.
+    let legal = replies[2]["result"].as_array().unwrap();
+    assert_eq!(replies[2]["id"], "a");
+    assert!(legal.contains(&json!({ "san": "Nc6", "uci": "b8c6" })));
+    assert_eq!(replies[3]["result"]["moves"], json!(["e4"]));
+    assert_eq!(replies[4]["error"]["code"], METHOD_NOT_FOUND);
+
+    let (header, rest) = framed.split_once("\r\n\r\n").unwrap();
+    let length = header.parse::<usize>().unwrap();
+    let analysis = serde_json::from_str::<Value>(&rest[..length]).unwrap();
+    assert_eq!(analysis["id"], 6);
+    assert!(analysis["result"]["best_move"].is_string());
+    assert_eq!(replies[5]["error"]["code"], PARSE_ERROR);
+    // Nothing is read once the client exits.
+    let exit = serde_json::from_str::<Value>(rest[length..].trim()).unwrap();
+    assert_eq!(exit["id"], 7);
+}
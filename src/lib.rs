@@ -0,0 +1,822 @@
+//! FreightChess: the rules of chess and its variants, an engine to play them, and the tools
+//! built around both. The `freight_chess` binary is the command line over this library.
+
+use std::fmt::{Display, Formatter};
+
+use crate::movegen::{Move, MoveRejection};
+use crate::ChessErr::{BadMove, IllegalCommand, InvalidFen, InvalidIndexing, InvalidPgn};
+
+#[cfg(feature = "cli")]
+pub mod accounts;
+pub mod annotation;
+pub mod antichess;
+pub mod armageddon;
+pub mod atomic;
+pub mod attacks;
+pub mod bench;
+pub mod bughouse;
+pub mod chess960;
+pub mod conduct;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "cli")]
+pub mod correspondence;
+pub mod crash;
+pub mod crazyhouse;
+#[cfg(feature = "cli")]
+pub mod db;
+pub mod debug;
+#[cfg(feature = "cli")]
+pub mod dgt;
+#[cfg(feature = "cli")]
+pub mod engine;
+pub mod eval;
+#[cfg(feature = "cli")]
+pub mod explorer;
+#[cfg(feature = "cli")]
+pub mod fairplay;
+pub mod fen;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+#[cfg(feature = "cli")]
+pub mod gendata;
+pub mod grid;
+#[cfg(feature = "cli")]
+pub mod import;
+#[cfg(feature = "cli")]
+pub mod jsonrpc;
+pub mod logging;
+#[cfg(feature = "cli")]
+pub mod matches;
+pub mod mcts;
+pub mod minichess;
+pub mod movegen;
+#[cfg(feature = "cli")]
+pub mod net;
+pub mod nnue;
+pub mod odds;
+pub mod opponent;
+pub mod perft;
+pub mod pgn;
+#[cfg(feature = "cli")]
+pub mod prompt;
+#[cfg(feature = "cli")]
+pub mod puzzle;
+#[cfg(feature = "cli")]
+pub mod query;
+pub mod racing_kings;
+#[cfg(feature = "cli")]
+pub mod random_position;
+pub mod raster;
+pub mod rating;
+pub mod render;
+#[cfg(feature = "cli")]
+pub mod repertoire;
+#[cfg(feature = "cli")]
+pub mod replay;
+pub mod san;
+#[cfg(feature = "cli")]
+pub mod script;
+pub mod search;
+pub mod selfplay;
+pub mod spoken;
+#[cfg(feature = "cli")]
+pub mod spsa;
+pub mod testsuite;
+#[cfg(feature = "cli")]
+pub mod theme;
+pub mod three_check;
+pub mod time_control;
+#[cfg(feature = "cli")]
+pub mod tournament;
+pub mod tree;
+pub mod tt;
+#[cfg(feature = "cli")]
+pub mod tune;
+pub mod uci;
+pub mod variant;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod zobrist;
+
+const CHESS_PIECES: [char; 16] = [
+    ' ', '♙', '♘', '♖', '♗', '♕', '♔', ' ', ' ', '♟', '♞', '♜', '♝', '♛', '♚', ' ',
+];
+
+// The pieces, and their letters in FEN, are those of the `no_std` core the rules start from.
+use freight_chess_core::{BISHOP, EMPTY, KING, KNIGHT, PAWN, PIECE_LETTERS, QUEEN, ROOK};
+pub use freight_chess_core::{BLACK, WHITE};
+
+const LEFT_MASK: u8 = 0xF0u8;
+const RIGHT_MASK: u8 = 0x0Fu8;
+
+// Define a bunch of useful functions to make the bit-manipulation sensible.
+const GET_LEFT: fn(u8) -> u8 = |s: u8| (s & LEFT_MASK) >> 4;
+const GET_RIGHT: fn(u8) -> u8 = |s: u8| s & RIGHT_MASK;
+
+const GET_NUM: fn(u8) -> u8 = |s: u8| s & 0b0111;
+#[allow(dead_code)]
+const GET_COLOR: fn(u8) -> u8 = |s: u8| (s & BLACK) >> 3;
+
+const SET_BLACK: fn(u8) -> u8 = |s: u8| (s | BLACK) * (s != EMPTY) as u8;
+const SET_WHITE: fn(u8) -> u8 = |s: u8| s & !BLACK;
+
+#[allow(dead_code)]
+const GET_CELL_PAIR: fn(u8) -> (u8, u8) = |pair: u8| (GET_LEFT(pair), GET_RIGHT(pair));
+const SET_CELL_PAIR: fn(u8, u8) -> u8 = |left: u8, right: u8| (left << 4) + right;
+#[allow(dead_code)]
+const SWAP_CELL: fn(u8) -> u8 = |pair: u8| SET_CELL_PAIR(GET_RIGHT(pair), GET_LEFT(pair));
+
+const SET_LEFT: fn(u8, u8) -> u8 = |pair: u8, left: u8| SET_CELL_PAIR(left, GET_RIGHT(pair));
+const SET_RIGHT: fn(u8, u8) -> u8 = |pair: u8, right: u8| SET_CELL_PAIR(GET_LEFT(pair), right);
+
+// If the boolean is true, get the right piece, otherwise, get the left piece.
+const GET_CELL_BOOLEAN: fn(u8, bool) -> u8 =
+    |pair: u8, side: bool| GET_RIGHT(pair) * (side as u8) + GET_LEFT(pair) * (!side as u8);
+
+// If the boolean is true, set the right piece, otherwise, set the left piece.
+const SET_CELL_BOOLEAN: fn(u8, bool, u8) -> u8 = |pair: u8, side: bool, piece: u8| {
+    SET_RIGHT(pair, piece) * (side as u8) + SET_LEFT(pair, piece) * (!side as u8)
+};
+
+#[derive(Debug)]
+pub enum ChessErr {
+    InvalidIndexing(&'static str),
+    BadMove(MoveRejection),
+    IllegalCommand(&'static str),
+    InvalidFen(&'static str),
+    InvalidPgn(&'static str),
+}
+
+impl ChessErr {
+    #[cfg(feature = "cli")]
+    /// The process exit code used when this error ends a scripted game.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            IllegalCommand(_) => 2,
+            InvalidIndexing(_) => 3,
+            BadMove(_) => 4,
+            InvalidFen(_) => 5,
+            InvalidPgn(_) => 6,
+        }
+    }
+
+    #[cfg(feature = "cli")]
+    /// A short, stable identifier for the kind of error, for machine-readable output.
+    fn kind(&self) -> &'static str {
+        match self {
+            InvalidIndexing(_) => "invalid_indexing",
+            BadMove(_) => "bad_move",
+            IllegalCommand(_) => "illegal_command",
+            InvalidFen(_) => "invalid_fen",
+            InvalidPgn(_) => "invalid_pgn",
+        }
+    }
+}
+
+impl Display for ChessErr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidIndexing(x) => write!(f, "{}", x),
+            BadMove(reason) => write!(f, "{}", reason),
+            IllegalCommand(x) => write!(f, "{}", x),
+            InvalidFen(x) => write!(f, "{}", x),
+            InvalidPgn(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+/// Represents the state of the board at any given point. Each byte is two cells.
+#[derive(Clone, Copy)]
+pub struct ChessBoard {
+    /// The chess board itself, 8x4 array of bytes (Each byte is a can store 2 pieces)
+    /// Indexing the outer array returns the row. Each row contains 4 bytes representing pairs of two columns.
+    /// In a standard depiction of the chess board, the white starting rows are located at the bottom.
+    /// For ease of indexing (for me at least lol), those rows will be start at the 0th index.
+    /// The columns will follow standard left-to-right convention.
+    /// Each byte is composed of two sets of 4 bits:
+    ///
+    /// _ (Color of the piece) ___ (Type of piece)
+    ///
+    /// The piece values are as follows:
+    /// - Empty: 0
+    /// - Pawn: 1
+    /// - Knight: 2
+    /// - Rook: 3
+    /// - Bishop: 4
+    /// - Queen: 5
+    /// - King: 6
+    ///
+    /// The color values are as follows (White Empty Squares and Black Empty Squares both have color 0):
+    /// - White: 0
+    /// - Black: 1
+    board: [[u8; 4]; 8],
+    moves: u16, // Theoretical maximum move count (with the FIDE limits) is somewhere around 6000, iirc?
+    /// Remaining castling rights, as `movegen::WHITE_KINGSIDE | ...` flags.
+    castling: u8,
+    /// The square of the rook each castling right castles with, indexed like the rights' bits
+    /// (white kingside first). Only Chess960 puts them anywhere but the corners.
+    castling_rooks: [u8; 4],
+    /// Whether the game is Chess960: castling is written as the king capturing its own rook,
+    /// and FEN names castling rooks by file when they aren't the outermost.
+    chess960: bool,
+    /// The square a pawn may capture onto en passant, or `movegen::NO_SQUARE`.
+    en_passant: u8,
+    /// Half-moves since the last capture or pawn move, for the fifty-move rule.
+    halfmoves: u16,
+    /// How many of each piece (indexed by piece number) white and black hold in hand, in
+    /// variants where captured pieces can be dropped back on the board.
+    pockets: [[u8; 6]; 2],
+    /// The squares of pieces that were promoted from pawns, as bits `1 << square`. They go back
+    /// into hand as pawns when captured.
+    promoted: u64,
+    /// How many checks white and black have given, in variants that count them.
+    checks: [u8; 2],
+    /// The rules the game is played by.
+    variant: &'static dyn variant::Variant,
+}
+
+impl ChessBoard {
+    /// Creates new initialized ChessBoard.
+    pub fn new() -> ChessBoard {
+        ChessBoard {
+            board: [
+                [
+                    SET_CELL_PAIR(SET_WHITE(ROOK), SET_WHITE(KNIGHT)),
+                    SET_CELL_PAIR(SET_WHITE(BISHOP), SET_WHITE(QUEEN)),
+                    SET_CELL_PAIR(SET_WHITE(KING), SET_WHITE(BISHOP)),
+                    SET_CELL_PAIR(SET_WHITE(KNIGHT), SET_WHITE(ROOK)),
+                ],
+                [
+                    SET_CELL_PAIR(SET_WHITE(PAWN), SET_WHITE(PAWN)),
+                    SET_CELL_PAIR(SET_WHITE(PAWN), SET_WHITE(PAWN)),
+                    SET_CELL_PAIR(SET_WHITE(PAWN), SET_WHITE(PAWN)),
+                    SET_CELL_PAIR(SET_WHITE(PAWN), SET_WHITE(PAWN)),
+                ],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0],
+                [
+                    SET_CELL_PAIR(SET_BLACK(PAWN), SET_BLACK(PAWN)),
+                    SET_CELL_PAIR(SET_BLACK(PAWN), SET_BLACK(PAWN)),
+                    SET_CELL_PAIR(SET_BLACK(PAWN), SET_BLACK(PAWN)),
+                    SET_CELL_PAIR(SET_BLACK(PAWN), SET_BLACK(PAWN)),
+                ],
+                [
+                    SET_CELL_PAIR(SET_BLACK(ROOK), SET_BLACK(KNIGHT)),
+                    SET_CELL_PAIR(SET_BLACK(BISHOP), SET_BLACK(QUEEN)),
+                    SET_CELL_PAIR(SET_BLACK(KING), SET_BLACK(BISHOP)),
+                    SET_CELL_PAIR(SET_BLACK(KNIGHT), SET_BLACK(ROOK)),
+                ],
+            ],
+            moves: 0,
+            castling: movegen::WHITE_KINGSIDE
+                | movegen::WHITE_QUEENSIDE
+                | movegen::BLACK_KINGSIDE
+                | movegen::BLACK_QUEENSIDE,
+            castling_rooks: movegen::CORNERS,
+            chess960: false,
+            en_passant: movegen::NO_SQUARE,
+            halfmoves: 0,
+            pockets: [[0; 6]; 2],
+            promoted: 0,
+            checks: [0; 2],
+            variant: &variant::STANDARD,
+        }
+    }
+
+    /// Returns the piece on `square`, numbered from 0 (a1) to 63 (h8).
+    pub fn get_piece(&self, square: u8) -> u8 {
+        GET_CELL_BOOLEAN(
+            self.board[(square >> 3) as usize][((square & 7) >> 1) as usize],
+            square & 1 != 0,
+        )
+    }
+
+    /// Puts `piece` on `square`, numbered from 0 (a1) to 63 (h8).
+    pub fn set_piece(&mut self, square: u8, piece: u8) {
+        let pair = &mut self.board[(square >> 3) as usize][((square & 7) >> 1) as usize];
+        *pair = SET_CELL_BOOLEAN(*pair, square & 1 != 0, piece);
+    }
+
+    #[allow(dead_code)]
+    pub fn get_piece_at_bytes(&self, coord: &[u8]) -> Result<u8, ChessErr> {
+        if !ChessBoard::is_valid_piece(coord) {
+            return Err(InvalidIndexing("This is an invalid index"));
+        }
+
+        Ok(GET_CELL_BOOLEAN(
+            self.board[((coord[1] & 0x0F) - 1) as usize][(((coord[0] - 1) & 0b0110) >> 1) as usize],
+            ((coord[0] - 1) & 1) != 0,
+        ))
+    }
+
+    // Remember, piece must be currently the rightmost piece (first four bits should be empty).
+    #[allow(dead_code)]
+    pub fn set_piece_at_bytes(&mut self, coord: &[u8], piece: u8) -> Result<(), ChessErr> {
+        if !ChessBoard::is_valid_piece(coord) {
+            return Err(InvalidIndexing("This is an invalid index"));
+        }
+
+        self.board[((coord[1] & 0x0F) - 1) as usize][(((coord[0] - 1) & 0b0110) >> 1) as usize] =
+            SET_CELL_BOOLEAN(
+                self.board[((coord[1] & 0x0F) - 1) as usize]
+                    [(((coord[0] - 1) & 0b0110) >> 1) as usize],
+                ((coord[0] - 1) & 1) != 0,
+                piece,
+            );
+
+        Ok(())
+    }
+
+    /// Reads a move written either as `e2->e4` or in Standard Algebraic Notation (`Nf3`), and
+    /// checks that it is legal and that the game isn't over.
+    pub fn parse_move(&self, text: &str) -> Result<Move, ChessErr> {
+        if self.is_done() {
+            return Err(BadMove(MoveRejection::GameOver));
+        }
+        match text.split_once("->") {
+            Some((move_from, move_to)) => {
+                self.parse_coordinate_move(move_from.as_bytes(), move_to.as_bytes())
+            }
+            None => self.parse_san(text).map_err(BadMove),
+        }
+    }
+
+    /// Reads the move from `move_from` to `move_to`. A pawn reaching the last rank must name its
+    /// promotion piece after the target square, as in `e8=Q` or `e8q`.
+    fn parse_coordinate_move(&self, move_from: &[u8], move_to: &[u8]) -> Result<Move, ChessErr> {
+        let (move_to, suffix) = move_to.split_at(move_to.len().min(2));
+        if !ChessBoard::is_valid_piece(move_from) || !ChessBoard::is_valid_piece(move_to) {
+            return Err(InvalidIndexing("This is an invalid index"));
+        }
+
+        let promotion = match suffix.strip_prefix(b"=").unwrap_or(suffix) {
+            [] => EMPTY,
+            [letter] => match letter.to_ascii_lowercase() {
+                b'q' => QUEEN,
+                b'r' => ROOK,
+                b'b' => BISHOP,
+                b'n' => KNIGHT,
+                b'k' => KING,
+                _ => return Err(BadMove(MoveRejection::BadPromotionPiece)),
+            },
+            _ => return Err(BadMove(MoveRejection::Unreadable)),
+        };
+        let mv = Move {
+            from: ChessBoard::square_of(move_from),
+            to: ChessBoard::square_of(move_to),
+            promotion,
+        };
+        self.check_move(mv).map_err(BadMove)?;
+        Ok(mv)
+    }
+
+    /// Returns `WHITE` or `BLACK`, depending on whose turn it is.
+    pub fn side_to_move(&self) -> u8 {
+        if self.moves.is_multiple_of(2) {
+            WHITE
+        } else {
+            BLACK
+        }
+    }
+
+    /// Returns the piece placement of the board, written as the first field of a FEN string.
+    pub fn placement(&self) -> String {
+        let (files, ranks) = self.variant.dimensions();
+        let mut placement = String::new();
+        for rank in (0..ranks).rev() {
+            let mut empty = 0;
+            for square in rank * 8..rank * 8 + files {
+                let piece = self.get_piece(square);
+                if GET_NUM(piece) == EMPTY {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    placement.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                placement.push(PIECE_LETTERS[piece as usize]);
+                // Crazyhouse marks promoted pieces, which go back into hand as pawns.
+                if self.promoted & 1 << square != 0 {
+                    placement.push('~');
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            placement.push('/');
+        }
+        placement.pop();
+        placement
+    }
+
+    /// Returns `true` once the game is over: in standard chess, once the side to move has no
+    /// legal moves (checkmate or stalemate).
+    pub fn is_done(&self) -> bool {
+        self.outcome().is_some() // TODO: Draws by repetition, material and the 50-move rule.
+    }
+
+    /// Converts a coordinate that passed `is_valid_piece` into a square number.
+    fn square_of(coord: &[u8]) -> u8 {
+        ((coord[1] & 0x0F) - 1) * 8 + (coord[0] & 0x0F) - 1
+    }
+
+    fn is_valid_piece(coord: &[u8]) -> bool {
+        !((coord.len() != 2)
+            || (coord[0] & 0xF0 != 96)
+            || !(1..=8).contains(&(coord[0] & 0x0F))
+            || !(1..=8).contains(&(coord[1] & 0x0F))
+            || (coord[1] & 0xF0 != 48))
+    }
+}
+
+impl Default for ChessBoard {
+    fn default() -> ChessBoard {
+        ChessBoard::new()
+    }
+}
+
+/// Two positions are equal when everything about them is: the pieces, whose move it is, the
+/// castling rights, en passant square and move counters, and the variant's own state.
+impl PartialEq for ChessBoard {
+    fn eq(&self, other: &ChessBoard) -> bool {
+        self.board == other.board
+            && self.moves == other.moves
+            && self.castling == other.castling
+            && self.castling_rooks == other.castling_rooks
+            && self.chess960 == other.chess960
+            && self.en_passant == other.en_passant
+            && self.halfmoves == other.halfmoves
+            && self.pockets == other.pockets
+            && self.promoted == other.promoted
+            && self.checks == other.checks
+            && self.variant.name() == other.variant.name()
+    }
+}
+
+impl Display for ChessBoard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.grid(&grid::Layout::default()))
+    }
+}
+
+#[cfg(feature = "cli")]
+const GAME_TUTORIAL: &str = "Allowed commands:\n\
+    - quit - Leaves game prompt\n\
+    - exit - Leaves game prompt\n\
+    - board - Prints the current board\n\
+    - peek - Prints the board once, even in a blindfold game.\n\
+    - theme [name] - Colors the board: classic, blue, high-contrast, solarized, one from the\n\
+      configuration file, or plain for the uncolored board.\n\
+    - debug dump - Prints everything the engine knows about the position, for debugging.\n\
+    - export svg [path] - Saves a diagram of the board, with the last move highlighted, as SVG.\n\
+    - export png [path] - Saves the same diagram as PNG.\n\
+    - move [start]->[end] - expects [start] and [end] to be chessboard notation (in lowercase).\n\
+    - move [san] - plays a move written in standard algebraic notation, e.g. Nf3 or O-O, or\n\
+      in Crazyhouse a drop from your hand, e.g. N@f3.\n\
+    - [san]... - plays the moves in turn, e.g. e4 e5 Nf3 Nc6, as does move [san]....\n\
+    - goto [number] - Goes back (or forward) to white's move [number] of the game, or black's\n\
+      with goto [number].... Playing on from there keeps the moves after it as a variation.\n\
+    - draw - Offers a draw, or accepts the opponent's offer. Without an opponent, the game is\n\
+      drawn by agreement.\n\
+    Several commands can be given at once separated by ;, as in e4 e5; board. They run in\n\
+    order, stopping at the first that fails.\n\
+    A FEN or a PGN (its tags, or its moves from 1.) pasted at the prompt sets up its position,\n\
+    and the game goes on from there.";
+
+#[cfg(feature = "cli")]
+/// The names of every command accepted by `execute_command`.
+const COMMANDS: [&str; 11] = [
+    "board", "debug", "draw", "exit", "export", "goto", "help", "move", "peek", "quit", "theme",
+];
+
+#[cfg(feature = "cli")]
+fn print_game_tutorial() {
+    println!("{}", GAME_TUTORIAL);
+}
+
+#[cfg(feature = "cli")]
+/// The result of successfully executing a single game command.
+enum Outcome {
+    Help,
+    Board,
+    /// `peek`, which shows the board even when it is otherwise hidden.
+    Peek,
+    /// `theme`, which draws the board in the named theme's colors from then on.
+    Theme(String),
+    /// `debug dump`, which prints `ChessBoard::dump`.
+    Dump,
+    /// `export svg` or `export png`, which saves a diagram of the board in that format to the
+    /// path given.
+    Export(String, String),
+    Moved(Move),
+    /// `goto`, which goes to the position this many half-moves into the game, counted as
+    /// `ChessBoard::moves` counts them.
+    Goto(u16),
+    /// `draw`, which offers a draw, or accepts one offered.
+    Draw,
+    /// A pasted FEN or PGN, whose position the board is now at the end of.
+    Loaded(tree::GameTree),
+    Quit,
+}
+
+#[cfg(feature = "cli")]
+/// Reads a move number as `goto` takes it: `12` for white's twelfth move, `12...` for black's.
+/// Returns the half-moves played before it, as `ChessBoard::moves` counts them.
+fn parse_move_number(text: &str) -> Option<u16> {
+    let (number, black) = match text.strip_suffix("...") {
+        Some(number) => (number, true),
+        None => (text.strip_suffix('.').unwrap_or(text), false),
+    };
+    number
+        .parse::<u16>()
+        .ok()?
+        .checked_sub(1)?
+        .checked_mul(2)?
+        .checked_add(black as u16)
+}
+
+#[cfg(feature = "cli")]
+/// Writes the move `moves` half-moves into the game as `goto` takes it, as in `12` or `12...`.
+fn move_number(moves: u16) -> String {
+    let dots = if moves % 2 == 1 { "..." } else { "" };
+    format!("{}{}", moves / 2 + 1, dots)
+}
+
+#[cfg(feature = "cli")]
+/// Returns `true` if `text` looks like a pasted FEN (its first word has as many ranks as the board
+/// of some variant) or PGN (it starts with a tag or a move number), rather than commands.
+fn is_pasted(text: &str) -> bool {
+    let text = text.trim_start();
+    let Some(first) = text.split_whitespace().next() else {
+        return false;
+    };
+    let numbered = first.starts_with(|c: char| c.is_ascii_digit())
+        && first
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .starts_with('.');
+    let slashes = first.matches('/').count();
+    let ranks = variant::VARIANTS
+        .iter()
+        .any(|variant| slashes + 1 == variant.dimensions().1 as usize);
+    text.starts_with('[') || ranks || numbered
+}
+
+#[cfg(feature = "cli")]
+/// Sets `board` up as the pasted FEN or PGN `text` has it.
+fn load_pasted(board: &mut ChessBoard, text: &str) -> Result<Outcome, ChessErr> {
+    let text = text.trim();
+    let fen = !text.starts_with('[')
+        && text
+            .split_whitespace()
+            .next()
+            .is_some_and(|first| first.contains('/'));
+    let tree = match fen {
+        true => tree::GameTree::new(ChessBoard::from_variant_fen(text, board.variant)?),
+        false => pgn::PgnGame::parse(text)?.tree,
+    };
+    *board = tree.board();
+    Ok(Outcome::Loaded(tree))
+}
+
+#[cfg(feature = "cli")]
+/// Splits a line of input into the commands it holds, in order: those separated by `;`, with
+/// several moves in a row (after `move`, or on their own) split into one command each. A pasted
+/// FEN or PGN is one command, whatever it holds.
+fn split_commands(line: &str) -> Vec<String> {
+    if is_pasted(line) {
+        return vec![line.trim().to_string()];
+    }
+    let mut commands = Vec::new();
+    for part in line.split(';') {
+        let words = part.split_whitespace().collect::<Vec<&str>>();
+        match words.as_slice() {
+            [] => {}
+            ["move", moves @ ..] if moves.len() > 1 => {
+                commands.extend(moves.iter().map(|mv| format!("move {}", mv)))
+            }
+            [first, _, ..] if !COMMANDS.contains(first) => {
+                commands.extend(words.iter().map(|mv| mv.to_string()))
+            }
+            _ => commands.push(words.join(" ")),
+        }
+    }
+    commands
+}
+
+#[cfg(feature = "cli")]
+/// Parses and executes one line of input against the board. This is shared by the interactive
+/// prompt and the scripted mode, so both accept exactly the same commands.
+fn execute_command(board: &mut ChessBoard, line: &str) -> Result<Outcome, ChessErr> {
+    if is_pasted(line) {
+        return load_pasted(board, line);
+    }
+    match line.trim() {
+        "help" => Ok(Outcome::Help),
+        "board" => Ok(Outcome::Board),
+        "peek" => Ok(Outcome::Peek),
+        "draw" => Ok(Outcome::Draw),
+        "exit" | "quit" => Ok(Outcome::Quit),
+        a => {
+            let commands = a.split_whitespace().collect::<Vec<&str>>();
+            match commands.as_slice() {
+                ["move", text] => {
+                    let mv = board.parse_move(text)?;
+                    board.apply_move(mv);
+                    Ok(Outcome::Moved(mv))
+                }
+                ["goto", number] => {
+                    parse_move_number(number)
+                        .map(Outcome::Goto)
+                        .ok_or(IllegalCommand(
+                            "goto takes a move number, such as 12, or 12... for black's.",
+                        ))
+                }
+                ["debug", "dump"] => Ok(Outcome::Dump),
+                ["theme", name] => Ok(Outcome::Theme(name.to_string())),
+                ["export", format @ ("svg" | "png"), path] => {
+                    Ok(Outcome::Export(format.to_string(), path.to_string()))
+                }
+                // A move on its own, unless it isn't one at all.
+                [text] => match board.parse_move(text) {
+                    Ok(mv) => {
+                        board.apply_move(mv);
+                        Ok(Outcome::Moved(mv))
+                    }
+                    Err(BadMove(MoveRejection::Unreadable)) => Err(IllegalCommand(
+                        "Command does not exist or is not formatted properly.",
+                    )),
+                    Err(e) => Err(e),
+                },
+                _ => Err(IllegalCommand(
+                    "Command does not exist or is not formatted properly.",
+                )), // TODO: Add more functionality
+            }
+        }
+    }
+}
+
+#[test]
+fn test_get_piece_at() {
+    assert!(ChessBoard::new()
+        .get_piece_at_bytes("ab".as_bytes())
+        .is_err());
+    assert!(ChessBoard::new()
+        .get_piece_at_bytes("12".as_bytes())
+        .is_err());
+    assert!(ChessBoard::new()
+        .get_piece_at_bytes("i1".as_bytes())
+        .is_err());
+    assert!(ChessBoard::new()
+        .get_piece_at_bytes("a9".as_bytes())
+        .is_err());
+    assert!(ChessBoard::new()
+        .get_piece_at_bytes("a0".as_bytes())
+        .is_err());
+
+    let board = ChessBoard::new();
+    let a1_piece = board
+        .get_piece_at_bytes("a1".as_bytes())
+        .expect("a1 failed");
+    assert_eq!(GET_COLOR(a1_piece), 0);
+    assert_eq!(GET_NUM(a1_piece), 3);
+    assert_eq!(SET_WHITE(ROOK), a1_piece);
+    let a2_piece = board
+        .get_piece_at_bytes("a2".as_bytes())
+        .expect("a2 failed");
+    assert_eq!(SET_WHITE(PAWN), a2_piece);
+    let a3_piece = board
+        .get_piece_at_bytes("a3".as_bytes())
+        .expect("a3 failed");
+    assert_eq!(SET_WHITE(EMPTY), a3_piece);
+
+    let a8_piece = board
+        .get_piece_at_bytes("a8".as_bytes())
+        .expect("a8 failed");
+    assert_eq!(GET_COLOR(a8_piece), 1);
+    assert_eq!(GET_NUM(a8_piece), 3);
+    assert_eq!(SET_BLACK(ROOK), a8_piece);
+    let a7_piece = board
+        .get_piece_at_bytes("a7".as_bytes())
+        .expect("a7 failed");
+    assert_eq!(SET_BLACK(PAWN), a7_piece);
+    let a6_piece = board
+        .get_piece_at_bytes("a6".as_bytes())
+        .expect("a6 failed");
+    assert_eq!(SET_BLACK(EMPTY), a6_piece);
+
+    for i in 1..=8 {
+        for j in 1..=8 {
+            assert!(ChessBoard::new()
+                .get_piece_at_bytes(&[96 + i as u8, 48 + j as u8][..])
+                .is_ok());
+        }
+    }
+}
+
+#[test]
+fn test_placement() {
+    let mut board = ChessBoard::new();
+    assert_eq!(
+        board.placement(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR"
+    );
+
+    let mv = board.parse_move("e2->e4").expect("e2->e4 failed");
+    board.apply_move(mv);
+    assert_eq!(
+        board.placement(),
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR"
+    );
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn test_split_commands() {
+    assert_eq!(
+        split_commands("e4 e5 Nf3 Nc6; board;; move d4 exd4 ;theme blue"),
+        [
+            "e4",
+            "e5",
+            "Nf3",
+            "Nc6",
+            "board",
+            "move d4",
+            "move exd4",
+            "theme blue"
+        ]
+    );
+    assert_eq!(split_commands("export svg a.svg"), ["export svg a.svg"]);
+
+    // Run in turn, moves on their own play as `move` does, and a word that isn't a move isn't
+    // taken for a bad one.
+    let mut board = ChessBoard::new();
+    for command in split_commands("e4 e5; move Nf3") {
+        assert!(matches!(
+            execute_command(&mut board, &command),
+            Ok(Outcome::Moved(_))
+        ));
+    }
+    assert_eq!(board.moves, 3);
+    assert!(matches!(
+        execute_command(&mut board, "Ke2"),
+        Err(BadMove(_))
+    ));
+    assert!(matches!(
+        execute_command(&mut board, "castle"),
+        Err(IllegalCommand(_))
+    ));
+
+    // A pasted FEN or PGN is taken whole and sets up its position.
+    let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+    assert_eq!(split_commands(fen), [fen]);
+    assert!(matches!(
+        execute_command(&mut board, fen),
+        Ok(Outcome::Loaded(_))
+    ));
+    assert_eq!(board.fen(), fen);
+    let pgn = "[Event \"Casual\"]\n\n1. e4 e5 2. Nf3 Nc6 {Both develop.} 3. Bb5 *";
+    assert_eq!(split_commands(pgn).len(), 1);
+    match execute_command(&mut board, pgn) {
+        Ok(Outcome::Loaded(tree)) => assert_eq!(tree.moves().len(), 5),
+        _ => panic!("the PGN didn't load"),
+    }
+    assert_eq!(board.side_to_move(), BLACK);
+    assert!(matches!(
+        execute_command(&mut board, "1.e4 e5 2. Ke3"),
+        Err(InvalidPgn(_) | BadMove(_))
+    ));
+    assert!(matches!(
+        execute_command(&mut board, "8/8/8/8/8/8/8/9 w - - 0 1"),
+        Err(InvalidFen(_))
+    ));
+    assert!(!is_pasted("e4 e5") && !is_pasted("") && !is_pasted("export svg a/b/c.svg"));
+    // Boards with fewer ranks have FENs with fewer of them.
+    let mut gardner = ChessBoard::start_of(variant::by_name("gardner").unwrap());
+    let fen = "rnbqk/ppppp/5/PPPPP/RNBQK w - - 0 1";
+    assert_eq!(split_commands(fen), [fen]);
+    assert!(matches!(
+        execute_command(&mut gardner, fen),
+        Ok(Outcome::Loaded(_))
+    ));
+    assert_eq!(gardner.fen(), fen);
+    let fen = "rnqknr/pppppp/6/6/PPPPPP/RNQKNR w - - 0 1";
+    assert!(is_pasted(fen));
+
+    assert!(matches!(
+        execute_command(&mut board, "goto 3..."),
+        Ok(Outcome::Goto(5))
+    ));
+    assert_eq!(parse_move_number("1"), Some(0));
+    assert_eq!(parse_move_number("12."), Some(22));
+    assert_eq!(parse_move_number("0"), None);
+    assert_eq!(move_number(5), "3...");
+    assert_eq!(move_number(22), "12");
+    assert!(matches!(
+        execute_command(&mut board, "goto e4"),
+        Err(IllegalCommand(_))
+    ));
+}
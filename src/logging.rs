@@ -0,0 +1,77 @@
+//! Logging to a file, so the engine's decisions, what passed over UCI and the network, and what
+//! was typed at the prompt can be looked into after the fact. Nothing is logged unless a file is
+//! given, and never to standard output, which belongs to UCI.
+//!
+//! Records go through the `tracing` macros with one of `TARGETS` as their target, and `--log`
+//! filters them as an `EnvFilter`: a level for every target, then `target=level` for those that
+//! differ, separated by commas, as in `warn,search=debug`.
+//!
+//! Longer stretches of work, such as a search, a UCI command or a network connection, run in a
+//! span, and every record logged in it names it, so the records of one search or one connection
+//! can be picked out of the rest.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind};
+use std::sync::Mutex;
+
+use tracing::Subscriber;
+use tracing_subscriber::fmt::time::uptime;
+use tracing_subscriber::EnvFilter;
+
+/// The parts of the program that log: the search, the UCI engine, network games and servers, the
+/// interactive prompt, the JSON-RPC mode and electronic boards.
+pub const TARGETS: [&str; 6] = ["search", "uci", "net", "tui", "jsonrpc", "dgt"];
+
+/// The levels logged when `--log` doesn't say.
+pub const DEFAULT_LEVELS: &str = "info";
+
+/// Reads levels written as `info,search=debug`, with or without spaces.
+pub fn filter(text: &str) -> Result<EnvFilter, String> {
+    EnvFilter::builder()
+        .parse(text.replace(char::is_whitespace, ""))
+        .map_err(|e| format!("Can't read the log levels {}: {}", text, e))
+}
+
+/// Writes the records `filter` lets through to `file`, a line each, with the seconds since it
+/// started and the spans they were logged in, outermost first.
+fn subscriber(file: File, filter: EnvFilter) -> impl Subscriber + Send + Sync {
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(Mutex::new(file))
+        .with_timer(uptime())
+        .with_ansi(false)
+        .finish()
+}
+
+/// Logs to the end of the file at `path` from now on, as much as `filter` lets through.
+pub fn init(path: &str, filter: EnvFilter) -> Result<(), Error> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    tracing::subscriber::set_global_default(subscriber(file, filter))
+        .map_err(|_| Error::new(ErrorKind::AlreadyExists, "Logging has already started."))
+}
+
+#[test]
+fn test_logging() {
+    assert!(filter(DEFAULT_LEVELS).is_ok());
+    assert!(filter("off,dgt=debug").is_ok());
+    assert!(filter("search=loud").is_err());
+
+    let path = std::env::temp_dir().join(format!("freight_chess_{}.log", std::process::id()));
+    let file = File::create(&path).unwrap();
+    let levels = filter("warn, search=debug,uci=trace").unwrap();
+    tracing::subscriber::with_default(subscriber(file, levels), || {
+        tracing::debug!(target: "search", "depth 3");
+        tracing::info!(target: "net", "connected");
+        // Records name the spans they were logged in, outermost first.
+        let _command = tracing::info_span!(target: "uci", "uci", command = %"go").entered();
+        let _search = tracing::info_span!(target: "search", "search", depth = 8).entered();
+        tracing::debug!(target: "search", "depth 4");
+    });
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines = text.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with(" DEBUG search: depth 3"));
+    assert!(lines[1].ends_with(" DEBUG uci{command=go}:search{depth=8}: search: depth 4"));
+    std::fs::remove_file(path).unwrap();
+}
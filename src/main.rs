@@ -1,54 +1,253 @@
 use std::cell::RefCell;
-use std::fmt::{Display, Formatter};
-use std::io::Write;
+use std::path::{Path, PathBuf};
 
-use crate::ChessErr::{BadMove, IllegalCommand, InvalidIndexing};
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
+use freight_chess::ChessErr::IllegalCommand;
+use freight_chess::*;
 
-const CHESS_PIECES: [char; 16] = [
-    ' ', '♙', '♘', '♖', '♗', '♔', '♕', ' ', ' ', '♟', '♞', '♜', '♝', '♛', '♚', ' ',
-];
+/// `--variant`, for the commands that can play variants.
+fn variant_arg() -> Arg<'static> {
+    Arg::new("variant")
+        .long("variant")
+        .takes_value(true)
+        .value_name("VARIANT")
+        .possible_values(variant::names())
+        .default_value("standard")
+        .about("The rules to play by.")
+}
+
+/// The variant chosen by `--variant`.
+fn variant_of(matches: &ArgMatches) -> &'static dyn variant::Variant {
+    matches
+        .value_of("variant")
+        .and_then(variant::by_name)
+        .unwrap_or(&variant::STANDARD)
+}
+
+/// `--position`, which picks the Chess960 start position.
+fn position_arg() -> Arg<'static> {
+    Arg::new("position")
+        .long("position")
+        .takes_value(true)
+        .value_name("NUMBER")
+        .validator(|number| match number.parse::<u16>() {
+            Ok(number) if number < chess960::POSITIONS => Ok(()),
+            _ => Err("must be a number from 0 to 959"),
+        })
+        .about("The Chess960 start position, from 0 to 959 (518 is the standard one). Picked at random otherwise.")
+}
 
-// A bunch of constant that are really useful.
-const EMPTY: u8 = 0;
-const PAWN: u8 = 1;
-const KNIGHT: u8 = 2;
-const ROOK: u8 = 3;
-const BISHOP: u8 = 4;
-const QUEEN: u8 = 5;
-const KING: u8 = 6;
+/// `--odds`, which takes some of white's pieces off the start position.
+fn odds_arg() -> Arg<'static> {
+    Arg::new("odds")
+        .long("odds")
+        .takes_value(true)
+        .value_name("ODDS")
+        .possible_values(odds::names())
+        .conflicts_with_all(&["position", "fen"])
+        .about("Starts a game with material odds: white plays without the pieces named.")
+}
 
-const WHITE: u8 = 0;
-const BLACK: u8 = 8;
+/// `--fen`, which starts from any position.
+fn fen_arg() -> Arg<'static> {
+    Arg::new("fen")
+        .long("fen")
+        .takes_value(true)
+        .value_name("FEN")
+        .conflicts_with("position")
+        .about("Starts from this position instead of the start.")
+}
 
-const LEFT_MASK: u8 = 0xF0u8;
-const RIGHT_MASK: u8 = 0x0Fu8;
+/// Checks an argument naming two squares, as `render::parse_squares` reads them.
+fn squares_validator(squares: &str) -> Result<(), &'static str> {
+    render::parse_squares(squares)
+        .map(|_| ())
+        .ok_or("must be two squares, as in e2e4")
+}
 
-// Define a bunch of useful functions to make the bit-manipulation sensible.
-const GET_LEFT: fn(u8) -> u8 = |s: u8| (s & LEFT_MASK) >> 4;
-const GET_RIGHT: fn(u8) -> u8 = |s: u8| s & RIGHT_MASK;
+/// Checks an argument naming an arrow, as `annotation::parse_arrow` reads them.
+fn arrow_validator(arrow: &str) -> Result<(), &'static str> {
+    annotation::parse_arrow(arrow)
+        .map(|_| ())
+        .ok_or("must be two squares after an optional color letter, as in Rg1f3")
+}
 
-const GET_NUM: fn(u8) -> u8 = |s: u8| s & 0b0111;
-const GET_COLOR: fn(u8) -> u8 = |s: u8| (s & BLACK) >> 3;
+/// Checks an argument naming a highlighted square, as `annotation::parse_highlight` reads them.
+fn highlight_validator(square: &str) -> Result<(), &'static str> {
+    annotation::parse_highlight(square)
+        .map(|_| ())
+        .ok_or("must be a square after an optional color letter, as in Rd4")
+}
 
-const SET_BLACK: fn(u8) -> u8 = |s: u8| (s | BLACK) * (s != EMPTY) as u8;
-const SET_WHITE: fn(u8) -> u8 = |s: u8| s & !BLACK;
+/// An external UCI engine's command, `engine`, and its options, `option`, for the commands that
+/// can use one instead of the engine's own search.
+fn engine_args(
+    engine: &'static str,
+    option: &'static str,
+    about: &'static str,
+) -> [Arg<'static>; 2] {
+    [
+        Arg::new(engine)
+            .long(engine)
+            .takes_value(true)
+            .value_name("COMMAND")
+            .about(about),
+        Arg::new(option)
+            .long(option)
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .value_name("NAME=VALUE")
+            .validator(|option| {
+                engine::parse_option(option)
+                    .map(|_| ())
+                    .ok_or("must be NAME=VALUE")
+            })
+            .about("Sets a UCI option of that engine, as in Hash=64. May be given more than once."),
+    ]
+}
 
-const GET_CELL_PAIR: fn(u8) -> (u8, u8) = |pair: u8| (GET_LEFT(pair), GET_RIGHT(pair));
-const SET_CELL_PAIR: fn(u8, u8) -> u8 = |left: u8, right: u8| (left << 4) + right;
-const SWAP_CELL: fn(u8) -> u8 = |pair: u8| SET_CELL_PAIR(GET_RIGHT(pair), GET_LEFT(pair));
+/// The engine `engine_args` chose: the external one, if its command was given, or else the
+/// engine's own search with `params`.
+fn engine_of(
+    matches: &ArgMatches,
+    engine: &str,
+    option: &str,
+    params: search::SearchParams,
+) -> engine::EngineSpec {
+    match matches.value_of(engine) {
+        Some(command) => engine::EngineSpec::Uci {
+            command: command.to_string(),
+            options: matches
+                .values_of(option)
+                .into_iter()
+                .flatten()
+                .filter_map(engine::parse_option)
+                .collect(),
+        },
+        None => engine::EngineSpec::Internal(params),
+    }
+}
 
-const SET_LEFT: fn(u8, u8) -> u8 = |pair: u8, left: u8| SET_CELL_PAIR(left, GET_RIGHT(pair));
-const SET_RIGHT: fn(u8, u8) -> u8 = |pair: u8, right: u8| SET_CELL_PAIR(GET_LEFT(pair), right);
+/// How `match` and `tournament` adjudicate their games.
+fn adjudication_args() -> [Arg<'static>; 7] {
+    [
+        Arg::new("draw-moves")
+            .long("draw-moves")
+            .takes_value(true)
+            .value_name("N")
+            .default_value("0")
+            .about("Draws a game once both sides' scores have stayed within --draw-score of 0 for N moves each. 0 plays draws out."),
+        Arg::new("draw-score")
+            .long("draw-score")
+            .takes_value(true)
+            .value_name("CENTIPAWNS")
+            .default_value("10")
+            .about("How near 0 scores must be for --draw-moves."),
+        Arg::new("draw-after")
+            .long("draw-after")
+            .takes_value(true)
+            .value_name("MOVE")
+            .default_value("30")
+            .about("Adjudicates draws only from move MOVE on."),
+        Arg::new("resign-moves")
+            .long("resign-moves")
+            .takes_value(true)
+            .value_name("N")
+            .default_value("0")
+            .about("Loses a game for the side whose score has been at or below -(--resign-score) for N of its moves in a row. 0 plays lost games out."),
+        Arg::new("resign-score")
+            .long("resign-score")
+            .takes_value(true)
+            .value_name("CENTIPAWNS")
+            .default_value("600")
+            .about("How far behind a side must be for --resign-moves."),
+        Arg::new("adjudicate-trivial")
+            .long("adjudicate-trivial")
+            .about(
+                "Decides a king and queen or rook against a lone king at once. No \
+                tablebase is read, so no other endgame is decided.",
+            ),
+        Arg::new("max-moves")
+            .long("max-moves")
+            .takes_value(true)
+            .value_name("N")
+            .default_value("0")
+            .about("Draws a game after N moves of each side. 0 leaves the usual limit of 125."),
+    ]
+}
 
-// If the boolean is true, get the right piece, otherwise, get the left piece.
-const GET_CELL_BOOLEAN: fn(u8, bool) -> u8 =
-    |pair: u8, side: bool| GET_RIGHT(pair) * (side as u8) + GET_LEFT(pair) * (!side as u8);
+/// The adjudication `adjudication_args` chose.
+fn adjudication_of(matches: &ArgMatches) -> selfplay::Adjudication {
+    let number = |name| -> u64 { matches.value_of_t(name).unwrap_or_else(|e| e.exit()) };
+    selfplay::Adjudication {
+        draw_after: number("draw-after") as u32,
+        draw_moves: number("draw-moves") as u32,
+        draw_score: number("draw-score") as i32,
+        resign_moves: number("resign-moves") as u32,
+        resign_score: number("resign-score") as i32,
+        trivial: matches.is_present("adjudicate-trivial"),
+        max_moves: number("max-moves") as u32,
+    }
+}
 
-// If the boolean is true, set the right piece, otherwise, set the left piece.
-const SET_CELL_BOOLEAN: fn(u8, bool, u8) -> u8 = |pair: u8, side: bool, piece: u8| {
-    SET_RIGHT(pair, piece) * (side as u8) + SET_LEFT(pair, piece) * (!side as u8)
-};
+/// `--armageddon`, for the commands that play a game on the clock.
+fn armageddon_arg() -> Arg<'static> {
+    Arg::new("armageddon").long("armageddon").about(
+        "Plays an Armageddon game: black gets four fifths of white's time (five minutes \
+        against four unless --time is given), or what --time gives it, but wins if the game is \
+        drawn.",
+    )
+}
+
+/// `--time`, for the commands that play a game on the clock.
+fn time_arg() -> Arg<'static> {
+    Arg::new("time")
+        .long("time")
+        .takes_value(true)
+        .value_name("MINUTES")
+        .validator(|time| {
+            time_control::parse(time)
+                .map(|_| ())
+                .ok_or("must be MINUTES or WHITE/BLACK minutes, as in 5 or 5/1")
+        })
+        .about(
+            "Gives each side MINUTES on their clock, or white and black different times, as in \
+            5/1 for five minutes against one. Games are untimed otherwise.",
+        )
+}
+
+/// The starting clocks of white and black in milliseconds, as given by `--time`, or `None` for
+/// an untimed game. Armageddon games are always timed, and black gets four fifths of white's
+/// time unless `--time` gives the two sides their own.
+fn start_clocks(matches: &ArgMatches) -> Option<[u32; 2]> {
+    let armageddon = matches.is_present("armageddon");
+    match matches.value_of("time").and_then(time_control::parse) {
+        Some([white, black]) if armageddon && white == black => Some(armageddon::clocks(white)),
+        Some(clocks) => Some(clocks),
+        None if armageddon => Some(armageddon::clocks(armageddon::DEFAULT_MINUTES * 60_000)),
+        None => None,
+    }
+}
+
+/// The board a game starts from, as chosen by `--variant`, `--position`, `--odds` and `--fen`.
+fn start_board(matches: &ArgMatches) -> Result<ChessBoard, ChessErr> {
+    let variant = variant_of(matches);
+    if let Some(fen) = matches.value_of("fen") {
+        return ChessBoard::from_variant_fen(fen, variant);
+    }
+    let board = match matches.value_of_t::<u16>("position") {
+        Ok(number) if variant.is_chess960() => ChessBoard::chess960(number)
+            .unwrap_or_else(ChessBoard::new)
+            .with_variant(variant),
+        _ => ChessBoard::start_of(variant),
+    };
+    match matches.value_of("odds") {
+        Some(odds) => board.with_odds(odds).ok_or(IllegalCommand(
+            "Odds can only be given from the standard start position.",
+        )),
+        None => Ok(board),
+    }
+}
 
 pub fn get_app() -> App<'static> {
     App::new("Chess Engine")
@@ -65,202 +264,1749 @@ pub fn get_app() -> App<'static> {
                 .short('p')
                 .about("Starts new game."),
         )
+        .arg(variant_arg())
+        .arg(position_arg())
+        .arg(odds_arg())
+        .arg(fen_arg())
+        .arg(time_arg())
+        .arg(armageddon_arg())
+        .arg(
+            Arg::new("opponent")
+                .long("opponent")
+                .takes_value(true)
+                .value_name("OPPONENT")
+                .possible_values(["random", "greedy-capture", "engine"])
+                .about(
+                    "Plays against an opponent with --play: a baseline one that plays random legal \
+                    moves, or one that takes whatever material it can win, or the engine, which \
+                    resigns lost games and offers draws in level endgames as the [play] section \
+                    of the configuration file says. You play the side to move at the start.",
+                ),
+        )
+        .args(engine_args(
+            "engine",
+            "engine-option",
+            "Makes --opponent engine the external UCI engine run by COMMAND, a program and its \
+            arguments separated by spaces, instead of this one.",
+        ))
+        .arg(
+            Arg::new("blindfold")
+                .long("blindfold")
+                .about(
+                    "Hides the board during a --play game, for visualization training: moves are \
+                    still checked and announced, and 'peek' shows the board when you need it.",
+                ),
+        )
+        .arg(
+            Arg::new("screen-reader")
+                .long("screen-reader")
+                .about(
+                    "Describes the board and moves in words instead of drawing the board, for \
+                    playing with a screen reader. The configuration file can turn this on too.",
+                ),
+        )
+        .arg(
+            Arg::new("script")
+                .long("script")
+                .short('s')
+                .takes_value(true)
+                .value_name("FILE")
+                .about(
+                    "Runs the commands in FILE (or stdin, if FILE is '-') without prompting. \
+                Prints one result line per command and exits with a non-zero code on error.",
+                ),
+        )
+        .arg(
+            Arg::new("jsonrpc")
+                .long("jsonrpc")
+                .conflicts_with_all(&["play", "script"])
+                .about(
+                    "Speaks JSON-RPC on stdin and stdout, framed as in the Language Server \
+                    Protocol, for editors and GUIs: position, legal-move and analysis methods \
+                    described in docs/jsonrpc.md. Starts from --fen, --variant or --position.",
+                ),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .about(
+                    "Prints results as JSON (one object per line) instead of plain text, in \
+                    --script mode and from perft and db analyze.",
+                ),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .takes_value(true)
+                .value_name("FILE")
+                .about("Reads settings from FILE instead of ~/.freight_chess.toml."),
+        )
+        .arg(
+            Arg::new("log-file")
+                .long("log-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .about(
+                    "Logs what the search, the UCI engine, network games and the prompt do to the \
+                    end of FILE. Nothing is logged otherwise.",
+                ),
+        )
+        .arg(
+            Arg::new("log")
+                .long("log")
+                .takes_value(true)
+                .value_name("LEVELS")
+                .default_value(logging::DEFAULT_LEVELS)
+                .validator(|levels| logging::filter(levels).map(|_| ()))
+                .about(
+                    "How much to log with --log-file: a level (off, error, warn, info, debug or \
+                    trace), then target=level for any of search, uci, net, tui, jsonrpc and dgt \
+                    that differ, \
+                    as in warn,search=debug.",
+                ),
+        )
+        .subcommand(
+            App::new("serve")
+                .about("Waits for an opponent to connect over TCP, then plays white against them.")
+                .arg(
+                    Arg::new("port")
+                        .long("port")
+                        .takes_value(true)
+                        .value_name("PORT")
+                        .default_value("7878")
+                        .about("The port to listen on."),
+                )
+                .arg(time_arg())
+                .arg(
+                    Arg::new("grace")
+                        .long("grace")
+                        .takes_value(true)
+                        .value_name("SECONDS")
+                        .default_value("60")
+                        .about(
+                            "How long to keep the game open for an opponent who lost their \
+                            connection. 0 ends the game as soon as they disconnect.",
+                        ),
+                )
+                .arg(armageddon_arg())
+                .arg(Arg::new("ws")
+                    .long("ws")
+                    .conflicts_with_all(&["time", "armageddon"])
+                    .about(
+                    "Serves the game to WebSocket clients instead of playing it here. \
+                        See docs/websocket.md for the message format.",
+                ))
+                .arg(
+                    Arg::new("http")
+                        .long("http")
+                        .conflicts_with_all(&["time", "ws", "armageddon"])
+                        .about(
+                            "Serves a REST API for creating, playing and analyzing games. \
+                            See docs/http.md for the endpoints.",
+                        ),
+                )
+                .arg(
+                    Arg::new("grpc")
+                        .long("grpc")
+                        .conflicts_with_all(&["time", "ws", "http", "armageddon"])
+                        .about(
+                            "Serves the engine's analysis and move checking over gRPC (HTTP/2 \
+                            without TLS), as described by proto/freight_chess.proto. Only in \
+                            builds with the grpc feature. See docs/grpc.md.",
+                        ),
+                )
+                .arg(
+                    Arg::new("grpc-web")
+                        .long("grpc-web")
+                        .conflicts_with_all(&["time", "ws", "http", "grpc", "armageddon"])
+                        .about(
+                            "Serves the same services as --grpc to browsers, over gRPC-Web. \
+                            See docs/grpc.md.",
+                        ),
+                )
+                .arg(
+                    Arg::new("players")
+                        .long("players")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .requires("ws")
+                        .about(
+                            "Only lets the players registered in FILE take a seat. Manage it \
+                            with the `players` subcommand.",
+                        ),
+                )
+                .arg(
+                    Arg::new("fairplay")
+                        .long("fairplay")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .requires("ws")
+                        .about(
+                            "Checks every finished game for engine-like accuracy and move times \
+                            and adds it to the report FILE. Sum it up with the `fairplay` \
+                            subcommand.",
+                        ),
+                )
+                .arg(
+                    Arg::new("simul")
+                        .long("simul")
+                        .takes_value(true)
+                        .value_name("BOARDS")
+                        .requires("ws")
+                        .validator(|boards| match boards.parse::<u32>() {
+                            Ok(1..) => Ok(()),
+                            _ => Err("must be at least 1"),
+                        })
+                        .about(
+                            "Gives a simul: the engine plays white on BOARDS boards at once, the \
+                            rooms simul/1, simul/2, ..., against whoever joins the room simul.",
+                        ),
+                )
+                .arg(
+                    Arg::new("simul-nodes")
+                        .long("simul-nodes")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("200000")
+                        .about("Searches at most N positions for each of the simul's moves."),
+                )
+                .args(engine_args(
+                    "simul-engine",
+                    "simul-option",
+                    "Gives the simul with the external UCI engine run by COMMAND instead of \
+                    this one.",
+                ))
+                .arg(
+                    Arg::new("tls")
+                        .long("tls")
+                        .conflicts_with_all(&["http", "grpc", "grpc-web"])
+                        .about(
+                            "Encrypts the game (or with --ws, every WebSocket connection) with \
+                            TLS, using the certificate and key set in the [tls] section of the \
+                            configuration file.",
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("connect")
+                .about("Connects to a game started with `serve` and plays black.")
+                .arg(
+                    Arg::new("address")
+                        .required(true)
+                        .value_name("HOST:PORT")
+                        .about("The address of the serving instance."),
+                )
+                .arg(
+                    Arg::new("token")
+                        .long("token")
+                        .takes_value(true)
+                        .value_name("TOKEN")
+                        .about("Resumes an interrupted game with the session token it printed."),
+                )
+                .arg(Arg::new("tls").long("tls").about(
+                    "Connects over TLS. The server's certificate must be signed by a public CA \
+                    or by the ca_certificate in the [tls] section of the configuration file.",
+                )),
+        )
+        .subcommand(
+            App::new("correspond")
+                .about(
+                    "Plays correspondence chess: shows the game in a PGN file, or plays one \
+                    move in it and saves it for the opponent.",
+                )
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .value_name("FILE")
+                        .about("The game file."),
+                )
+                .arg(
+                    Arg::new("move")
+                        .value_name("MOVE")
+                        .about("The move to play, e.g. Nf3 or g1->f3."),
+                )
+                .arg(
+                    Arg::new("new")
+                        .long("new")
+                        .about("Starts a new game in FILE, which must not exist yet."),
+                )
+                .arg(
+                    Arg::new("white")
+                        .long("white")
+                        .takes_value(true)
+                        .value_name("NAME")
+                        .requires("new")
+                        .about("The white player's name, for the new game's PGN tags."),
+                )
+                .arg(
+                    Arg::new("black")
+                        .long("black")
+                        .takes_value(true)
+                        .value_name("NAME")
+                        .requires("new")
+                        .about("The black player's name, for the new game's PGN tags."),
+                )
+                .arg(variant_arg())
+                .arg(position_arg().requires("new"))
+                .arg(odds_arg().requires("new"))
+                .arg(fen_arg().requires("new"))
+                .arg(
+                    Arg::new("snippet")
+                        .long("snippet")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .requires("move")
+                        .about(
+                            "Also writes a message announcing the move, with the game as PGN, \
+                            to FILE (or stdout, if FILE is '-') for emailing to the opponent.",
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("import")
+                .about("Downloads games from Lichess or Chess.com and converts them to PGN.")
+                .arg(
+                    Arg::new("target")
+                        .required(true)
+                        .value_name("URL-OR-USERNAME")
+                        .about(
+                            "A Lichess game URL, a Lichess or Chess.com profile URL, or a \
+                            username on the site chosen with --site.",
+                        ),
+                )
+                .arg(
+                    Arg::new("site")
+                        .long("site")
+                        .takes_value(true)
+                        .value_name("SITE")
+                        .possible_values(["lichess", "chesscom"])
+                        .default_value("lichess")
+                        .about("Where the username plays."),
+                )
+                .arg(
+                    Arg::new("max")
+                        .long("max")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("10")
+                        .about("How many of a player's most recent games to import."),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about("Appends the games to FILE instead of printing them."),
+                )
+                .arg(
+                    Arg::new("db")
+                        .long("db")
+                        .takes_value(true)
+                        .min_values(0)
+                        .value_name("FILE")
+                        .about(
+                            "Stores the games in the game database (or the one in FILE) \
+                            instead of printing them, skipping games already stored.",
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("puzzle")
+                .about("Solves tactics puzzles.")
+                .subcommand(
+                    App::new("daily")
+                        .about(
+                            "Plays the Lichess puzzle of the day, or a bundled puzzle when \
+                            Lichess can't be reached.",
+                        )
+                        .arg(
+                            Arg::new("offline")
+                                .long("offline")
+                                .about("Plays a bundled puzzle without connecting to Lichess."),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("perft")
+                .about("Counts the positions DEPTH moves away, to test the move generator.")
+                .arg(
+                    Arg::new("depth")
+                        .required(true)
+                        .value_name("DEPTH")
+                        .about("How many plies deep to count."),
+                )
+                .arg(fen_arg().about("Counts from this position instead of the start."))
+                .arg(
+                    Arg::new("divide")
+                        .long("divide")
+                        .about("Also prints the count below each move."),
+                )
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .about("Also checks that every position reached is consistent and that unmaking each move restores the position it was made from."),
+                )
+                .arg(variant_arg())
+                .arg(position_arg())
+                .arg(odds_arg()),
+        )
+        .subcommand(
+            App::new("render")
+                .about("Draws a position as a diagram, for embedding in blogs and reports.")
+                .arg(
+                    Arg::new("svg")
+                        .long("svg")
+                        .required_unless_present_any(["png", "gif"])
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about("Writes the diagram to FILE as SVG."),
+                )
+                .arg(
+                    Arg::new("png")
+                        .long("png")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about("Writes the diagram to FILE as PNG."),
+                )
+                .arg(
+                    Arg::new("gif")
+                        .long("gif")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .requires("pgn")
+                        .about("Writes the game given with --pgn to FILE as an animated GIF, one frame per move."),
+                )
+                .arg(
+                    Arg::new("pgn")
+                        .long("pgn")
+                        .takes_value(true)
+                        .value_name("GAME")
+                        .about("The PGN file holding the game for --gif."),
+                )
+                .arg(
+                    Arg::new("delay")
+                        .long("delay")
+                        .takes_value(true)
+                        .value_name("MILLISECONDS")
+                        .default_value("1000")
+                        .about("How long --gif shows each move."),
+                )
+                .arg(fen_arg().about("Draws this position instead of the start."))
+                .arg(
+                    Arg::new("last-move")
+                        .long("last-move")
+                        .takes_value(true)
+                        .value_name("SQUARES")
+                        .validator(squares_validator)
+                        .about("Highlights the squares of the last move, as in e2e4."),
+                )
+                .arg(
+                    Arg::new("arrow")
+                        .long("arrow")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .value_name("ARROW")
+                        .validator(arrow_validator)
+                        .about("Draws an arrow from one square to another, as in g1f3, in green or in the color whose letter comes first (G, R, Y or B, as in Rg1f3). May be given more than once."),
+                )
+                .arg(
+                    Arg::new("square")
+                        .long("square")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .value_name("SQUARE")
+                        .validator(highlight_validator)
+                        .about("Frames a square, as in d4, in green or in the color whose letter comes first, as in Rd4. May be given more than once."),
+                )
+                .arg(variant_arg())
+                .arg(position_arg())
+                .arg(odds_arg()),
+        )
+        .subcommand(
+            App::new("bench")
+                .about(
+                    "Searches a fixed set of positions and prints the total positions visited, \
+                    which changes only if the search does, and the speed. Or times one primitive \
+                    of the search over the same positions.",
+                )
+                .arg(
+                    Arg::new("primitive")
+                        .value_name("PRIMITIVE")
+                        .possible_values(["movegen", "eval", "makeunmake"])
+                        .about(
+                            "Times generating moves, evaluating, or making and unmaking every \
+                            move, instead of searching.",
+                        ),
+                )
+                .arg(
+                    Arg::new("depth")
+                        .long("depth")
+                        .takes_value(true)
+                        .value_name("PLIES")
+                        .default_value("6")
+                        .about("How deep to search each position."),
+                )
+                .arg(
+                    Arg::new("threads")
+                        .long("threads")
+                        .takes_value(true)
+                        .value_name("THREADS")
+                        .default_value("1")
+                        .validator(|threads| match threads.parse::<usize>() {
+                            Ok(1..) => Ok(()),
+                            _ => Err("must be at least 1"),
+                        })
+                        .about("How many threads to search each position on."),
+                )
+                .arg(
+                    Arg::new("tt-strategy")
+                        .long("tt-strategy")
+                        .takes_value(true)
+                        .value_name("STRATEGY")
+                        .possible_values(["lockless", "locked"])
+                        .default_value("lockless")
+                        .about(
+                            "How the threads share the hash table: checking entries by XOR, or \
+                            locking each bucket.",
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("testsuite")
+                .about(
+                    "Searches the positions of an EPD test suite and reports which have their \
+                    best move (bm) found or the move to avoid (am) avoided.",
+                )
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .value_name("FILE")
+                        .about("The test suite, one EPD record per line."),
+                )
+                .arg(
+                    Arg::new("time")
+                        .long("time")
+                        .takes_value(true)
+                        .value_name("MILLISECONDS")
+                        .default_value("1000")
+                        .about("How long to search each position."),
+                ),
+        )
+        .subcommand(
+            App::new("tune")
+                .about(
+                    "Fits the evaluation's parameters to positions labeled with the results of \
+                    their games (Texel tuning).",
+                )
+                .arg(
+                    Arg::new("dataset")
+                        .required(true)
+                        .value_name("FILE")
+                        .about(
+                            "Quiet positions in FEN or EPD, one per line, each followed by the \
+                            result of its game: [1.0], [0.5] or [0.0], or 1-0, 1/2-1/2 or 0-1.",
+                        ),
+                )
+                .arg(
+                    Arg::new("passes")
+                        .long("passes")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("20")
+                        .about("Stops after N passes over the parameters if they haven't settled."),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about(
+                            "Writes the tuned parameters to FILE instead of stdout, as the [eval] \
+                            section of a configuration file.",
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("spsa")
+                .about(
+                    "Tunes the search's parameters by playing the engine against itself with \
+                    them nudged up and down (SPSA).",
+                )
+                .arg(
+                    Arg::new("iterations")
+                        .long("iterations")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("1000")
+                        .about("Plays N pairs of games."),
+                )
+                .arg(
+                    Arg::new("nodes")
+                        .long("nodes")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("5000")
+                        .about("Searches at most N positions per move."),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about(
+                            "Writes the tuned parameters to FILE instead of stdout, as the \
+                            [search] section of a configuration file.",
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("match")
+                .about(
+                    "Plays the engine against itself with two configurations, each opening \
+                    once with either color, and reports the score and Elo difference.",
+                )
+                .arg(
+                    Arg::new("openings")
+                        .long("openings")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about(
+                            "Plays the openings of the suite FILE in turn: FEN or EPD, one \
+                            position per line, or PGN, the position each game ends in. The \
+                            openings are random otherwise.",
+                        ),
+                )
+                .arg(
+                    Arg::new("first")
+                        .long("first")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about(
+                            "Gives the first player the [search] section of the configuration \
+                            file FILE. It plays with the defaults otherwise.",
+                        ),
+                )
+                .arg(
+                    Arg::new("second")
+                        .long("second")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about("Gives the second player the [search] section of FILE."),
+                )
+                .args(engine_args(
+                    "first-engine",
+                    "first-option",
+                    "Makes the first player the external UCI engine run by COMMAND, a program \
+                    and its arguments separated by spaces, instead of this one.",
+                ))
+                .args(engine_args(
+                    "second-engine",
+                    "second-option",
+                    "Makes the second player the external UCI engine run by COMMAND.",
+                ))
+                .arg(
+                    Arg::new("games")
+                        .long("games")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("10")
+                        .about("Plays N games."),
+                )
+                .arg(
+                    Arg::new("nodes")
+                        .long("nodes")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("20000")
+                        .about("Searches at most N positions per move."),
+                )
+                .arg(
+                    Arg::new("time")
+                        .long("time")
+                        .takes_value(true)
+                        .value_name("MINUTES")
+                        .validator(|time| {
+                            time_control::parse(time)
+                                .map(|_| ())
+                                .ok_or("must be MINUTES or FIRST/SECOND minutes, as in 1 or 1/0.5")
+                        })
+                        .about(
+                            "Plays on the clock, with MINUTES for each player or different times \
+                            for the first and second, as in 1/0.5. A player who runs out of time \
+                            loses. Searches aren't limited by positions then, unless --nodes is \
+                            given too.",
+                        ),
+                )
+                .arg(Arg::new("armageddon").long("armageddon").about(
+                    "Plays every game as Armageddon, as in a tiebreak: on the clock, five \
+                    minutes each unless --time is given, black gets four fifths of its time but \
+                    wins if the game is drawn.",
+                ))
+                .arg(
+                    Arg::new("concurrency")
+                        .long("concurrency")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("1")
+                        .validator(|concurrency| match concurrency.parse::<usize>() {
+                            Ok(1..) => Ok(()),
+                            _ => Err("must be at least 1"),
+                        })
+                        .about("Plays N games at once, each on its own thread."),
+                )
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .takes_value(true)
+                        .value_name("N")
+                        .about("Starts the random numbers from N, to get the same openings again."),
+                )
+                .arg(
+                    Arg::new("pgn")
+                        .long("pgn")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about("Writes the games to FILE as PGN, with how each ended."),
+                )
+                .args(adjudication_args()),
+        )
+        .subcommand(
+            App::new("tournament")
+                .about(
+                    "Plays a tournament between engine configurations and external engines, \
+                    each pairing a short match, and reports the standings after every round.",
+                )
+                .arg(
+                    Arg::new("player")
+                        .long("player")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .required(true)
+                        .value_name("PLAYER")
+                        .about(
+                            "Enters PLAYER: `default` for the engine with its defaults, a \
+                            configuration file whose [search] section it plays with, or `uci:` \
+                            and the command of an external UCI engine. Given once per player, in \
+                            seeding order.",
+                        ),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .takes_value(true)
+                        .value_name("FORMAT")
+                        .possible_values(["round-robin", "swiss", "knockout"])
+                        .default_value("round-robin")
+                        .about(
+                            "Pairs everyone with everyone, pairs players on equal points each \
+                            round, or eliminates the loser of each pairing.",
+                        ),
+                )
+                .arg(
+                    Arg::new("rounds")
+                        .long("rounds")
+                        .takes_value(true)
+                        .value_name("N")
+                        .about("Plays N rounds of a Swiss tournament, enough to find a winner by default."),
+                )
+                .arg(
+                    Arg::new("games")
+                        .long("games")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("2")
+                        .about("Plays N games in each pairing."),
+                )
+                .arg(
+                    Arg::new("openings")
+                        .long("openings")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about("Plays the openings of the suite FILE in turn, as `match` does."),
+                )
+                .arg(
+                    Arg::new("nodes")
+                        .long("nodes")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("20000")
+                        .about("Searches at most N positions per move."),
+                )
+                .arg(
+                    Arg::new("concurrency")
+                        .long("concurrency")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("1")
+                        .validator(|concurrency| match concurrency.parse::<usize>() {
+                            Ok(1..) => Ok(()),
+                            _ => Err("must be at least 1"),
+                        })
+                        .about("Plays N games of a pairing at once, each on its own thread."),
+                )
+                .arg(Arg::new("armageddon").long("armageddon").about(
+                    "Settles a knockout pairing still tied after its tiebreak games with an \
+                    Armageddon game, the higher seed taking white: five minutes against four, \
+                    besides --nodes, and black wins if it is drawn.",
+                ))
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .takes_value(true)
+                        .value_name("N")
+                        .about("Starts the random numbers from N, to get the same openings again."),
+                )
+                .arg(
+                    Arg::new("pgn")
+                        .long("pgn")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about("Writes the games to FILE as PGN, with their rounds."),
+                )
+                .args(adjudication_args()),
+        )
+        .subcommand(
+            App::new("gen-data")
+                .about(
+                    "Plays the engine against itself from random openings and writes the quiet \
+                    positions with their scores and results, as training data.",
+                )
+                .arg(
+                    Arg::new("games")
+                        .long("games")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("100")
+                        .about("Plays N games."),
+                )
+                .arg(
+                    Arg::new("depth")
+                        .long("depth")
+                        .takes_value(true)
+                        .value_name("PLIES")
+                        .default_value("4")
+                        .about("Searches each move this many plies deep."),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .takes_value(true)
+                        .value_name("FORMAT")
+                        .possible_values(["plain", "epd"])
+                        .default_value("plain")
+                        .about(
+                            "Writes Stockfish's plain text format for training networks, or \
+                            EPD with results for tune.",
+                        ),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about("Writes the positions to FILE instead of stdout."),
+                ),
+        )
+        .subcommand(
+            App::new("random-position")
+                .about(
+                    "Prints the FENs of random legal positions, for fuzzing, benchmarks and \
+                    training data.",
+                )
+                .arg(
+                    Arg::new("count")
+                        .long("count")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("1")
+                        .about("Prints N positions."),
+                )
+                .arg(
+                    Arg::new("material")
+                        .long("material")
+                        .takes_value(true)
+                        .value_name("PIECES")
+                        .about(
+                            "Scatters exactly these pieces on the board, white's then black's, \
+                            as in KRPvKR. Otherwise positions are reached by random moves from \
+                            the start.",
+                        ),
+                )
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .takes_value(true)
+                        .value_name("N")
+                        .about("Starts the random numbers from N, to get the same positions again."),
+                ),
+        )
+        .subcommand(
+            App::new("uci")
+                .about("Speaks the Universal Chess Interface on stdin and stdout, for chess GUIs.")
+                .arg(variant_arg())
+                .arg(
+                    Arg::new("search")
+                        .long("search")
+                        .takes_value(true)
+                        .value_name("SEARCH")
+                        .possible_values(["alphabeta", "mcts"])
+                        .default_value("alphabeta")
+                        .about(
+                            "Searches by alpha-beta, or by Monte Carlo Tree Search with as many \
+                            playouts as go nodes asks.",
+                        ),
+                )
+                .arg(
+                    Arg::new("playout")
+                        .long("playout")
+                        .takes_value(true)
+                        .value_name("POLICY")
+                        .possible_values(["eval", "rollout"])
+                        .default_value("eval")
+                        .about(
+                            "How MCTS values positions: by the evaluation, or by playing random \
+                            moves from them.",
+                        ),
+                )
+                .arg(
+                    Arg::new("contempt")
+                        .long("contempt")
+                        .takes_value(true)
+                        .value_name("CENTIPAWNS")
+                        .allow_hyphen_values(true)
+                        .default_value("0")
+                        .validator(|contempt| match contempt.parse::<i32>() {
+                            Ok(contempt) if contempt.abs() <= search::CONTEMPT_LIMIT => Ok(()),
+                            _ => Err("must be a number from -1000 to 1000"),
+                        })
+                        .about(
+                            "How much worse than even a draw is for the engine: above zero it \
+                            avoids draws, below zero it seeks them.",
+                        ),
+                )
+                .arg(
+                    Arg::new("hash-file")
+                        .long("hash-file")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about(
+                            "Loads the hash table from FILE if it exists, and saves it there \
+                            after every search, so analysis can resume later.",
+                        ),
+                )
+                .arg(
+                    Arg::new("record")
+                        .long("record")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about(
+                            "Records every line in and out, with the time, to FILE, for \
+                            uci-replay to play back.",
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("uci-replay")
+                .about(
+                    "Plays the commands of a transcript recorded by uci --record back to the \
+                    engine, printing its answers and saying where they differ from the recorded \
+                    ones.",
+                )
+                .arg(
+                    Arg::new("transcript")
+                        .required(true)
+                        .value_name("FILE")
+                        .about("The transcript."),
+                )
+                .arg(variant_arg()),
+        )
+        .subcommand(
+            App::new("replay")
+                .about(
+                    "Steps through a PGN game and its variations, adding, promoting, deleting \
+                    and commenting moves.",
+                )
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .value_name("PGN")
+                        .about("The game to replay. Changes are saved back to it."),
+                ),
+        )
+        .subcommand(
+            App::new("train")
+                .about(
+                    "Drills an opening repertoire, scheduling each line for review by spaced \
+                    repetition.",
+                )
+                .arg(
+                    Arg::new("repertoire")
+                        .required(true)
+                        .value_name("PGN")
+                        .about("The repertoire: a PGN game whose variations are the lines to learn."),
+                )
+                .arg(
+                    Arg::new("color")
+                        .long("color")
+                        .takes_value(true)
+                        .value_name("COLOR")
+                        .possible_values(["white", "black"])
+                        .default_value("white")
+                        .about("The side the repertoire is for."),
+                )
+                .arg(
+                    Arg::new("schedule")
+                        .long("schedule")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about(
+                            "Where the review schedule is kept. Defaults to the repertoire's \
+                            name with the extension .schedule.json.",
+                        ),
+                )
+                .arg(
+                    Arg::new("lines")
+                        .long("lines")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("10")
+                        .about("The most lines to review in one session."),
+                ),
+        )
+        .subcommand(
+            App::new("db")
+                .about("Stores finished games in a local database.")
+                .arg(
+                    Arg::new("file")
+                        .long("file")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .about("Uses the database in FILE instead of ~/.freight_chess.db."),
+                )
+                .subcommand(
+                    App::new("add")
+                        .about("Stores the games in a PGN file.")
+                        .arg(
+                            Arg::new("pgn")
+                                .required(true)
+                                .value_name("PGN")
+                                .about("The PGN file, or '-' for stdin."),
+                        ),
+                )
+                .subcommand(App::new("list").about("Lists the stored games."))
+                .subcommand(
+                    App::new("show")
+                        .about("Shows a stored game, with evaluations if it has been analyzed.")
+                        .arg(Arg::new("id").required(true).value_name("ID")),
+                )
+                .subcommand(
+                    App::new("export-pgn")
+                        .about("Writes every stored game as PGN.")
+                        .arg(
+                            Arg::new("output")
+                                .long("output")
+                                .short('o')
+                                .takes_value(true)
+                                .value_name("FILE")
+                                .about("Writes to FILE instead of stdout."),
+                        ),
+                )
+                .subcommand(
+                    App::new("find-position")
+                        .about("Lists the stored games that reached a position, and what was played from it.")
+                        .arg(
+                            Arg::new("fen")
+                                .long("fen")
+                                .required(true)
+                                .takes_value(true)
+                                .value_name("FEN")
+                                .about("The position."),
+                        ),
+                )
+                .subcommand(
+                    App::new("explorer")
+                        .about(
+                            "Explores the moves played in the stored games, with how often each \
+                            was played and how those games ended.",
+                        )
+                        .arg(
+                            Arg::new("fen")
+                                .long("fen")
+                                .takes_value(true)
+                                .value_name("FEN")
+                                .about("Starts from this position instead of the initial one."),
+                        ),
+                )
+                .subcommand(
+                    App::new("analyze")
+                        .about("Evaluates every move of a stored game with the engine.")
+                        .arg(Arg::new("id").required(true).value_name("ID"))
+                        .arg(
+                            Arg::new("depth")
+                                .long("depth")
+                                .takes_value(true)
+                                .value_name("PLIES")
+                                .default_value("4")
+                                .about("How deep to search each position."),
+                        )
+                        .args(engine_args(
+                            "engine",
+                            "engine-option",
+                            "Analyzes with the external UCI engine run by COMMAND, a program and \
+                            its arguments separated by spaces, instead of this one.",
+                        )),
+                ),
+        )
+        .subcommand(
+            App::new("pgn")
+                .about("Works with PGN files.")
+                .subcommand(
+                    App::new("query")
+                        .about(
+                            "Copies the games in a PGN file that match every filter given. The \
+                            file is read a game at a time, so it may be larger than memory.",
+                        )
+                        .arg(
+                            Arg::new("input")
+                                .required(true)
+                                .value_name("PGN")
+                                .about("The PGN file, or '-' for stdin."),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .long("output")
+                                .short('o')
+                                .takes_value(true)
+                                .value_name("FILE")
+                                .about("Writes the games to FILE instead of stdout."),
+                        )
+                        .arg(
+                            Arg::new("player")
+                                .long("player")
+                                .takes_value(true)
+                                .value_name("NAME")
+                                .about("Selects games NAME played, with either color. Part of a name is enough, in any case."),
+                        )
+                        .arg(
+                            Arg::new("white")
+                                .long("white")
+                                .takes_value(true)
+                                .value_name("NAME")
+                                .about("Selects games NAME played with white."),
+                        )
+                        .arg(
+                            Arg::new("black")
+                                .long("black")
+                                .takes_value(true)
+                                .value_name("NAME")
+                                .about("Selects games NAME played with black."),
+                        )
+                        .arg(
+                            Arg::new("eco")
+                                .long("eco")
+                                .takes_value(true)
+                                .value_name("CODE")
+                                .about("Selects openings by ECO code: a prefix such as B2, or a range such as B20-B99."),
+                        )
+                        .arg(
+                            Arg::new("result")
+                                .long("result")
+                                .takes_value(true)
+                                .value_name("RESULT")
+                                .possible_values(["1-0", "0-1", "1/2-1/2", "*"])
+                                .about("Selects games with this result."),
+                        )
+                        .arg(
+                            Arg::new("from")
+                                .long("from")
+                                .takes_value(true)
+                                .value_name("DATE")
+                                .about("Selects games played on or after DATE (YYYY.MM.DD, or just a year or month)."),
+                        )
+                        .arg(
+                            Arg::new("to")
+                                .long("to")
+                                .takes_value(true)
+                                .value_name("DATE")
+                                .about("Selects games played on or before DATE."),
+                        )
+                        .arg(
+                            Arg::new("min-elo")
+                                .long("min-elo")
+                                .takes_value(true)
+                                .value_name("RATING")
+                                .about("Selects games in which both players were rated at least RATING."),
+                        )
+                        .arg(
+                            Arg::new("moves")
+                                .long("moves")
+                                .takes_value(true)
+                                .value_name("PATTERN")
+                                .about("Selects games starting with the moves in PATTERN, e.g. \"1. e4 c5\". '*' stands for any one move and '...' for any number of moves, so \"... Qxf7#\" finds Qxf7# anywhere."),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("players")
+                .about("Manages the player registry used by `serve --ws --players`.")
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .value_name("FILE")
+                        .about("The registry file. It is created when the first player is added."),
+                )
+                .subcommand(
+                    App::new("add")
+                        .about("Registers a player, or changes their password. The password is read from stdin.")
+                        .arg(Arg::new("name").required(true).value_name("NAME")),
+                )
+                .subcommand(
+                    App::new("remove")
+                        .about("Unregisters a player.")
+                        .arg(Arg::new("name").required(true).value_name("NAME")),
+                )
+                .subcommand(App::new("list").about("Lists the registered players."))
+                .subcommand(
+                    App::new("leaderboard").about("Lists the registered players by rating."),
+                ),
+        )
+        .subcommand(
+            App::new("fairplay")
+                .about("Sums up the fair-play report written by `serve --ws --fairplay`.")
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .value_name("FILE")
+                        .about("The report file."),
+                ),
+        )
+        .subcommand(
+            App::new("dgt")
+                .about(
+                    "Plays over the board on a DGT electronic board: moves made on it are read \
+                    and checked, and the engine's replies announced for you to make.",
+                )
+                .arg(
+                    Arg::new("device")
+                        .required(true)
+                        .value_name("DEVICE")
+                        .about("The board's serial port, such as /dev/ttyUSB0 or /dev/ttyACM0."),
+                )
+                .arg(
+                    Arg::new("engine-plays")
+                        .long("engine-plays")
+                        .takes_value(true)
+                        .value_name("COLOR")
+                        .possible_values(["white", "black", "none"])
+                        .default_value("black")
+                        .about("The side the engine plays, or none to only record the moves."),
+                )
+                .arg(
+                    Arg::new("nodes")
+                        .long("nodes")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("200000")
+                        .about("Searches at most N positions for each of the engine's moves."),
+                ),
+        )
 }
 
-#[derive(Debug)]
-enum ChessErr {
-    InvalidIndexing(&'static str),
-    BadMove(&'static str),
-    IllegalCommand(&'static str),
-}
-
-/// Represents the state of the board at any given point. Each byte is two cells.
-struct ChessBoard {
-    /// The chess board itself, 8x4 array of bytes (Each byte is a can store 2 pieces)
-    /// Indexing the outer array returns the row. Each row contains 4 bytes representing pairs of two columns.
-    /// In a standard depiction of the chess board, the white starting rows are located at the bottom.
-    /// For ease of indexing (for me at least lol), those rows will be start at the 0th index.
-    /// The columns will follow standard left-to-right convention.
-    /// Each byte is composed of two sets of 4 bits:
-    ///
-    /// _ (Color of the piece) ___ (Type of piece)
-    ///
-    /// The piece values are as follows:
-    /// - Empty: 0
-    /// - Pawn: 1
-    /// - Knight: 2
-    /// - Rook: 3
-    /// - Bishop: 4
-    /// - Queen: 5
-    /// - King: 6
-    ///
-    /// The color values are as follows (White Empty Squares and Black Empty Squares both have color 0):
-    /// - White: 0
-    /// - Black: 1
-    board: [[u8; 4]; 8],
-    moves: u16, // Theoretical maximum move count (with the FIDE limits) is somewhere around 6000, iirc?
-}
-
-impl ChessBoard {
-    /// Creates new initialized ChessBoard.
-    pub fn new() -> ChessBoard {
-        ChessBoard {
-            board: [
-                [
-                    SET_CELL_PAIR(SET_WHITE(ROOK), SET_WHITE(KNIGHT)),
-                    SET_CELL_PAIR(SET_WHITE(BISHOP), SET_WHITE(QUEEN)),
-                    SET_CELL_PAIR(SET_WHITE(KING), SET_WHITE(BISHOP)),
-                    SET_CELL_PAIR(SET_WHITE(KNIGHT), SET_WHITE(ROOK)),
-                ],
-                [
-                    SET_CELL_PAIR(SET_WHITE(PAWN), SET_WHITE(PAWN)),
-                    SET_CELL_PAIR(SET_WHITE(PAWN), SET_WHITE(PAWN)),
-                    SET_CELL_PAIR(SET_WHITE(PAWN), SET_WHITE(PAWN)),
-                    SET_CELL_PAIR(SET_WHITE(PAWN), SET_WHITE(PAWN)),
-                ],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0],
-                [
-                    SET_CELL_PAIR(SET_BLACK(PAWN), SET_BLACK(PAWN)),
-                    SET_CELL_PAIR(SET_BLACK(PAWN), SET_BLACK(PAWN)),
-                    SET_CELL_PAIR(SET_BLACK(PAWN), SET_BLACK(PAWN)),
-                    SET_CELL_PAIR(SET_BLACK(PAWN), SET_BLACK(PAWN)),
-                ],
-                [
-                    SET_CELL_PAIR(SET_BLACK(ROOK), SET_BLACK(KNIGHT)),
-                    SET_CELL_PAIR(SET_BLACK(BISHOP), SET_BLACK(QUEEN)),
-                    SET_CELL_PAIR(SET_BLACK(KING), SET_BLACK(BISHOP)),
-                    SET_CELL_PAIR(SET_BLACK(KNIGHT), SET_BLACK(ROOK)),
-                ],
-            ],
-            moves: 0,
+fn main() {
+    crash::install();
+    // let mut app = Rc::new(RefCell::new(cmd_parse::get_app()));
+    let app = RefCell::new(get_app());
+    let matches = app.borrow_mut().get_matches_mut();
+    let is_play = matches.is_present("play");
+    if let Some(path) = matches.value_of("log-file") {
+        let filter = logging::filter(matches.value_of("log").unwrap()).unwrap();
+        if let Err(e) = logging::init(path, filter) {
+            eprintln!("Couldn't log to {}: {}", path, e);
         }
     }
 
-    pub fn get_piece_at_bytes(&self, coord: &[u8]) -> Result<u8, ChessErr> {
-        if !ChessBoard::is_valid_piece(coord) {
-            return Err(InvalidIndexing("This is an invalid index"));
+    let config = || config::Config::load(matches.value_of("config"));
+    let network_game = match matches.subcommand() {
+        Some(("serve", serve)) => Some(
+            serve
+                .value_of_t::<u16>("port")
+                .and_then(|port| {
+                    let tls = || match serve.is_present("tls") {
+                        true => config()
+                            .and_then(|config| net::tls::server_config(&config.tls))
+                            .map(Some),
+                        false => Ok(None),
+                    };
+                    if serve.is_present("ws") {
+                        let accounts = serve
+                            .value_of("players")
+                            .map(|path| accounts::Accounts::load(Path::new(path)))
+                            .transpose();
+                        let simul = match serve.is_present("simul") {
+                            true => Some(net::ws::Simul {
+                                boards: serve.value_of_t("simul")?,
+                                engine: engine_of(
+                                    serve,
+                                    "simul-engine",
+                                    "simul-option",
+                                    Default::default(),
+                                ),
+                                nodes: serve.value_of_t("simul-nodes")?,
+                            }),
+                            false => None,
+                        };
+                        return Ok(accounts.and_then(|accounts| {
+                            let fairplay = serve.value_of("fairplay").map(PathBuf::from);
+                            net::ws::serve(port, accounts, simul, fairplay, tls()?)
+                        }));
+                    }
+                    if serve.is_present("http") {
+                        return Ok(net::http::serve(port));
+                    }
+                    if serve.is_present("grpc") {
+                        #[cfg(feature = "grpc")]
+                        return Ok(net::grpc::serve_http2(port));
+                        #[cfg(not(feature = "grpc"))]
+                        return Ok(Err(std::io::Error::new(
+                            std::io::ErrorKind::Unsupported,
+                            "This build doesn't serve gRPC. Build it with --features grpc.",
+                        )));
+                    }
+                    if serve.is_present("grpc-web") {
+                        return Ok(net::grpc::serve(port));
+                    }
+                    let clocks = start_clocks(serve);
+                    let grace = serve.value_of_t::<u32>("grace")?;
+                    let tls = tls();
+                    let armageddon = serve.is_present("armageddon");
+                    Ok(tls.and_then(|tls| net::serve(port, clocks, grace * 1000, armageddon, tls)))
+                })
+                .unwrap_or_else(|e| e.exit()),
+        ),
+        Some(("connect", connect)) => Some(
+            match connect.is_present("tls") {
+                true => config()
+                    .and_then(|config| net::tls::client_config(&config.tls))
+                    .map(Some),
+                false => Ok(None),
+            }
+            .and_then(|tls| {
+                net::connect(
+                    connect.value_of("address").unwrap(),
+                    connect.value_of("token"),
+                    tls,
+                )
+            }),
+        ),
+        Some(("correspond", correspond)) => {
+            let new_game = match correspond.is_present("new") {
+                true => start_board(correspond).map(|start| {
+                    let mut game = pgn::PgnGame::new(start);
+                    game.set_tag("Event", "Correspondence game");
+                    for (tag, argument) in [("White", "white"), ("Black", "black")] {
+                        game.set_tag(tag, correspond.value_of(argument).unwrap_or("?"));
+                    }
+                    Some(game)
+                }),
+                false => Ok(None),
+            };
+            Some(
+                new_game
+                    .map_err(|e| {
+                        std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string())
+                    })
+                    .and_then(|new_game| {
+                        correspondence::correspond(
+                            Path::new(correspond.value_of("file").unwrap()),
+                            new_game,
+                            correspond.value_of("move"),
+                            correspond.value_of("snippet"),
+                        )
+                    }),
+            )
         }
-
-        Ok(GET_CELL_BOOLEAN(
-            self.board[((coord[1] & 0x0F) - 1) as usize][(((coord[0] - 1) & 0b0110) >> 1) as usize],
-            ((coord[0] - 1) & 1) != 0,
-        ))
-    }
-
-    // Remember, piece must be currently the rightmost piece (first four bits should be empty).
-    pub fn set_piece_at_bytes(&mut self, coord: &[u8], piece: u8) -> Result<(), ChessErr> {
-        if !ChessBoard::is_valid_piece(coord) {
-            return Err(InvalidIndexing("This is an invalid index"));
+        Some(("import", import)) => {
+            let max = import
+                .value_of_t::<usize>("max")
+                .unwrap_or_else(|e| e.exit());
+            let site = import::Site::from_name(import.value_of("site").unwrap()).unwrap();
+            Some(import::import(
+                import.value_of("target").unwrap(),
+                site,
+                max,
+                import.value_of("output").map(Path::new),
+                import
+                    .is_present("db")
+                    .then(|| {
+                        import
+                            .value_of("db")
+                            .map_or_else(db::default_path, PathBuf::from)
+                    })
+                    .as_deref(),
+            ))
         }
-
-        self.board[((coord[1] & 0x0F) - 1) as usize][(((coord[0] - 1) & 0b0110) >> 1) as usize] =
-            SET_CELL_BOOLEAN(
-                self.board[((coord[1] & 0x0F) - 1) as usize]
-                    [(((coord[0] - 1) & 0b0110) >> 1) as usize],
-                ((coord[0] - 1) & 1) != 0,
-                piece,
-            );
-
-        Ok(())
-    }
-
-    pub fn make_move(&mut self, move_from: &[u8], move_to: &[u8]) -> Result<(), ChessErr> {
-        // Go ahead and perform the move for now.
-        let piece = self.get_piece_at_bytes(move_from)?;
-        self.set_piece_at_bytes(move_to, piece)?;
-        todo!();
-    }
-
-    pub fn is_done(&self) -> bool {
-        return false; // TODO: Actually give the board checkmate/draw testing.
-    }
-
-    fn is_valid_piece(coord: &[u8]) -> bool {
-        !((coord.len() != 2)
-            || (coord[0] & 0xF0 != 96)
-            || !(1..=8).contains(&(coord[0] & 0x0F))
-            || !(1..=8).contains(&(coord[1] & 0x0F))
-            || (coord[1] & 0xF0 != 48))
-    }
-}
-
-impl Display for ChessBoard {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for i in 0..8 {
-            writeln!(
-                f,
-                "|{}|{}|{}|{}|{}|{}|{}|{}|",
-                CHESS_PIECES[GET_LEFT(self.board[7 - i][0]) as usize],
-                CHESS_PIECES[GET_RIGHT(self.board[7 - i][0]) as usize],
-                CHESS_PIECES[GET_LEFT(self.board[7 - i][1]) as usize],
-                CHESS_PIECES[GET_RIGHT(self.board[7 - i][1]) as usize],
-                CHESS_PIECES[GET_LEFT(self.board[7 - i][2]) as usize],
-                CHESS_PIECES[GET_RIGHT(self.board[7 - i][2]) as usize],
-                CHESS_PIECES[GET_LEFT(self.board[7 - i][3]) as usize],
-                CHESS_PIECES[GET_RIGHT(self.board[7 - i][3]) as usize],
-            )?;
+        Some(("puzzle", puzzle)) => match puzzle.subcommand() {
+            Some(("daily", daily)) => Some(puzzle::daily(daily.is_present("offline"))),
+            _ => None,
+        },
+        Some(("perft", perft)) => {
+            let depth = perft.value_of_t("depth").unwrap_or_else(|e| e.exit());
+            Some(
+                start_board(perft)
+                    .map_err(|e| {
+                        std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string())
+                    })
+                    .and_then(|board| {
+                        perft::run(
+                            &board,
+                            depth,
+                            perft.is_present("divide"),
+                            perft.is_present("check"),
+                            matches.is_present("json"),
+                        )
+                    }),
+            )
         }
-        Ok(())
-    }
-}
-
-fn print_game_tutorial() {
-    let help = "Allowed commands:\n\
-    - quit - Leaves game prompt\n\
-    - exit - Leaves game prompt\n\
-    - move [start]->[end] - expects [start] and [end] to be chessboard notation (in lowercase).";
-    println!("{}", help);
-}
-
-/// Starts chess game prompt. (May be deprecated in a future version.)
-fn play_chess() -> Result<(), ChessErr> {
-    let mut board = ChessBoard::new();
-    let stdin = std::io::stdin();
-    let mut buff = String::new();
-    while !board.is_done() {
-        buff.clear();
-        print!("{}\n>>> ", board);
-        std::io::stdout().flush().unwrap();
-
-        stdin
-            .read_line(&mut buff)
-            .expect("Yikes, something broke the prompt...");
-        match buff.trim() {
-            "help" => print_game_tutorial(),
-            "exit" | "quit" => {
-                return Ok(());
+        Some(("render", render)) => {
+            let diagram = render::Diagram {
+                last_move: render.value_of("last-move").and_then(render::parse_squares),
+                annotations: annotation::Annotations {
+                    squares: render
+                        .values_of("square")
+                        .into_iter()
+                        .flatten()
+                        .filter_map(annotation::parse_highlight)
+                        .collect(),
+                    arrows: render
+                        .values_of("arrow")
+                        .into_iter()
+                        .flatten()
+                        .filter_map(annotation::parse_arrow)
+                        .collect(),
+                },
+            };
+            Some(
+                start_board(render)
+                    .map_err(|e| {
+                        std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string())
+                    })
+                    .and_then(|board| {
+                        if let Some(path) = render.value_of("svg") {
+                            render::run(&board, &diagram, path)?;
+                        }
+                        if let Some(path) = render.value_of("png") {
+                            raster::save_png(&board, &diagram, path)?;
+                        }
+                        match render.value_of("gif") {
+                            Some(path) => {
+                                let delay = render.value_of_t("delay").unwrap_or_else(|e| e.exit());
+                                raster::save_gif(render.value_of("pgn").unwrap(), delay, path)
+                            }
+                            None => Ok(()),
+                        }
+                    }),
+            )
+        }
+        Some(("bench", bench)) => {
+            let depth = bench.value_of_t("depth").unwrap_or_else(|e| e.exit());
+            let threads = bench.value_of_t("threads").unwrap_or_else(|e| e.exit());
+            let strategy = tt::Strategy::by_name(bench.value_of("tt-strategy").unwrap()).unwrap();
+            Some(
+                match bench
+                    .value_of("primitive")
+                    .and_then(bench::Primitive::by_name)
+                {
+                    Some(primitive) => bench::run_primitive(primitive),
+                    None => bench::run(depth, threads, strategy),
+                },
+            )
+        }
+        Some(("testsuite", testsuite)) => {
+            let time = testsuite.value_of_t("time").unwrap_or_else(|e| e.exit());
+            Some(testsuite::run(
+                testsuite.value_of("file").unwrap(),
+                std::time::Duration::from_millis(time),
+            ))
+        }
+        Some(("tune", tune)) => {
+            let passes = tune.value_of_t("passes").unwrap_or_else(|e| e.exit());
+            Some(config().and_then(|config| {
+                tune::run(
+                    tune.value_of("dataset").unwrap(),
+                    config.eval,
+                    passes,
+                    tune.value_of("output"),
+                )
+            }))
+        }
+        Some(("gen-data", gen_data)) => {
+            let games = gen_data.value_of_t("games").unwrap_or_else(|e| e.exit());
+            let depth = gen_data.value_of_t("depth").unwrap_or_else(|e| e.exit());
+            let format = gendata::Format::by_name(gen_data.value_of("format").unwrap()).unwrap();
+            Some(gendata::run(
+                games,
+                depth,
+                format,
+                gen_data.value_of("output"),
+            ))
+        }
+        Some(("match", arguments)) => {
+            let number = |name| -> u64 { arguments.value_of_t(name).unwrap_or_else(|e| e.exit()) };
+            let player = |side: &str| {
+                let params = match arguments.value_of(side) {
+                    Some(path) => config::Config::load(Some(path))?.search,
+                    None => search::SearchParams::default(),
+                };
+                let [engine, option] = [format!("{}-engine", side), format!("{}-option", side)];
+                let name = arguments.value_of(&engine).or(arguments.value_of(side));
+                Ok(matches::Player {
+                    name: name.unwrap_or(side).to_string(),
+                    engine: engine_of(arguments, &engine, &option, params),
+                })
+            };
+            let seed = arguments
+                .is_present("seed")
+                .then(|| arguments.value_of_t("seed").unwrap_or_else(|e| e.exit()));
+            let time = arguments.value_of("time").and_then(time_control::parse);
+            let armageddon = arguments.is_present("armageddon");
+            // On the clock, the time left decides how far a move is searched.
+            let on_clock = time.is_some() || armageddon;
+            let nodes = match on_clock && arguments.occurrences_of("nodes") == 0 {
+                true => u64::MAX,
+                false => number("nodes"),
+            };
+            Some(player("first").and_then(|first| {
+                matches::run(
+                    [first, player("second")?],
+                    arguments.value_of("openings"),
+                    number("games") as u32,
+                    nodes,
+                    time,
+                    armageddon,
+                    number("concurrency") as usize,
+                    seed,
+                    adjudication_of(arguments),
+                    arguments.value_of("pgn"),
+                )
+            }))
+        }
+        Some(("tournament", arguments)) => {
+            let number = |name| -> u64 { arguments.value_of_t(name).unwrap_or_else(|e| e.exit()) };
+            let format =
+                tournament::Format::by_name(arguments.value_of("format").unwrap()).unwrap();
+            let rounds = arguments
+                .is_present("rounds")
+                .then(|| number("rounds") as u32);
+            let seed = arguments.is_present("seed").then(|| number("seed"));
+            let conditions = tournament::Conditions {
+                games: number("games") as u32,
+                nodes: number("nodes"),
+                concurrency: number("concurrency") as usize,
+                adjudication: adjudication_of(arguments),
+                armageddon: arguments.is_present("armageddon"),
+            };
+            let players = arguments
+                .values_of("player")
+                .into_iter()
+                .flatten()
+                .map(tournament::parse_player)
+                .collect::<Result<Vec<_>, _>>();
+            Some(players.and_then(|players| {
+                tournament::run(
+                    players,
+                    format,
+                    rounds,
+                    conditions,
+                    arguments.value_of("openings"),
+                    seed,
+                    arguments.value_of("pgn"),
+                )
+            }))
+        }
+        Some(("random-position", random)) => {
+            let count = random.value_of_t("count").unwrap_or_else(|e| e.exit());
+            let seed = random
+                .is_present("seed")
+                .then(|| random.value_of_t("seed").unwrap_or_else(|e| e.exit()));
+            Some(random_position::run(
+                count,
+                random.value_of("material"),
+                seed,
+            ))
+        }
+        Some(("spsa", spsa)) => {
+            let iterations = spsa.value_of_t("iterations").unwrap_or_else(|e| e.exit());
+            let nodes = spsa.value_of_t("nodes").unwrap_or_else(|e| e.exit());
+            Some(config().and_then(|config| {
+                spsa::run(config.search, iterations, nodes, spsa.value_of("output"))
+            }))
+        }
+        Some(("uci", uci)) => {
+            let mcts = match uci.value_of("search") {
+                Some("mcts") => mcts::Policy::by_name(uci.value_of("playout").unwrap()),
+                _ => None,
+            };
+            search::set_contempt(uci.value_of_t("contempt").unwrap_or_else(|e| e.exit()));
+            let stdin = std::io::stdin();
+            Some(config().and_then(|config| {
+                let (input, mut output) =
+                    uci::recorded(stdin.lock(), std::io::stdout(), uci.value_of("record"))?;
+                uci::run(
+                    input,
+                    &mut output,
+                    variant_of(uci),
+                    config.eval,
+                    config.search,
+                    mcts,
+                    uci.value_of("hash-file"),
+                )
+            }))
+        }
+        Some(("uci-replay", replay)) => Some(config().and_then(|config| {
+            let path = replay.value_of("transcript").unwrap();
+            let differs = uci::replay(
+                path,
+                &mut std::io::stdout(),
+                variant_of(replay),
+                config.eval,
+                config.search,
+                None,
+            )?;
+            match differs {
+                Some(answer) => eprintln!(
+                    "The answers differ from the recording at answer {}.",
+                    answer
+                ),
+                None => eprintln!("The answers match the recording."),
             }
-            a => {
-                let commands = a.split_whitespace().collect::<Vec<&str>>();
-                if commands.len() != 2 {
-                    return Err(IllegalCommand(
-                        "Command does not exist or is not formatted properly.",
-                    ));
+            Ok(())
+        })),
+        Some(("replay", replay)) => {
+            let path = Path::new(replay.value_of("file").unwrap());
+            let screen_reader = matches.is_present("screen-reader");
+            Some(config().and_then(|mut config| {
+                config.display.screen_reader |= screen_reader;
+                replay::replay(path, &config.display)
+            }))
+        }
+        Some(("train", train)) => {
+            let path = Path::new(train.value_of("repertoire").unwrap());
+            let color = match train.value_of("color") {
+                Some("black") => BLACK,
+                _ => WHITE,
+            };
+            let schedule = train
+                .value_of("schedule")
+                .map_or_else(|| repertoire::default_schedule(path), PathBuf::from);
+            let lines = train.value_of_t("lines").unwrap_or_else(|e| e.exit());
+            Some(repertoire::train(path, color, &schedule, lines))
+        }
+        Some(("db", database)) => {
+            let command = match database.subcommand() {
+                Some(("add", add)) => db::Command::Add(add.value_of("pgn").unwrap().to_string()),
+                Some(("show", show)) => {
+                    db::Command::Show(show.value_of_t("id").unwrap_or_else(|e| e.exit()))
                 }
-                match commands[0] {
-                    "move" => {
-                        if let Some((move_from, move_to)) = commands[1].split_once("->") {
-                            board.make_move(move_from.as_bytes(), move_to.as_bytes())?;
-                        } else {
-                            return Err(IllegalCommand("Move formatting invalid."));
-                        }
-                    }
-                    _ => {} // TODO: Add more functionality
+                Some(("export-pgn", export)) => {
+                    db::Command::ExportPgn(export.value_of("output").map(str::to_string))
+                }
+                Some(("analyze", analyze)) => db::Command::Analyze(
+                    analyze.value_of_t("id").unwrap_or_else(|e| e.exit()),
+                    analyze.value_of_t("depth").unwrap_or_else(|e| e.exit()),
+                    engine_of(analyze, "engine", "engine-option", Default::default()),
+                ),
+                Some(("find-position", find)) => {
+                    db::Command::FindPosition(find.value_of("fen").unwrap().to_string())
+                }
+                Some(("explorer", explorer)) => {
+                    db::Command::Explorer(explorer.value_of("fen").map(str::to_string))
                 }
+                _ => db::Command::List,
+            };
+            let path = database
+                .value_of("file")
+                .map_or_else(db::default_path, PathBuf::from);
+            let json = matches.is_present("json");
+            Some(config().and_then(|config| db::run(&path, command, config.display.notation, json)))
+        }
+        Some(("pgn", pgn)) => match pgn.subcommand() {
+            Some(("query", query)) => {
+                let text = |name| query.value_of(name).map(str::to_string);
+                let filter = query::Filter {
+                    player: text("player"),
+                    white: text("white"),
+                    black: text("black"),
+                    eco: text("eco"),
+                    result: text("result"),
+                    from: text("from"),
+                    to: text("to"),
+                    min_elo: query
+                        .is_present("min-elo")
+                        .then(|| query.value_of_t("min-elo").unwrap_or_else(|e| e.exit())),
+                    moves: query.value_of("moves").map(query::parse_pattern),
+                };
+                Some(query::query(
+                    query.value_of("input").unwrap(),
+                    query.value_of("output"),
+                    &filter,
+                ))
             }
-        };
+            _ => None,
+        },
+        Some(("players", players)) => {
+            let (command, name) = match players.subcommand() {
+                Some((command, arguments)) => (command, arguments.value_of("name")),
+                None => ("list", None),
+            };
+            let path = Path::new(players.value_of("file").unwrap());
+            Some(accounts::manage(path, command, name))
+        }
+        Some(("dgt", dgt)) => {
+            let engine = match dgt.value_of("engine-plays") {
+                Some("white") => Some(WHITE),
+                Some("black") => Some(BLACK),
+                _ => None,
+            };
+            let nodes = dgt.value_of_t("nodes").unwrap_or_else(|e| e.exit());
+            Some(dgt::run(dgt.value_of("device").unwrap(), engine, nodes))
+        }
+        Some(("fairplay", report)) => Some(
+            fairplay::report(Path::new(report.value_of("file").unwrap()))
+                .map(|report| print!("{}", report)),
+        ),
+        _ => None,
+    };
+    if let Some(result) = network_game {
+        if let Err(e) = result {
+            println!("An error was encountered: {}", e);
+            std::process::exit(1);
+        }
+        return;
     }
-    Ok(())
-}
 
-fn main() {
-    // let mut app = Rc::new(RefCell::new(cmd_parse::get_app()));
-    let app = RefCell::new(get_app());
-    let matches = app.borrow_mut().get_matches_mut();
-    let is_play = matches.is_present("play");
+    if matches.is_present("jsonrpc") {
+        let stdin = std::io::stdin();
+        let result = start_board(&matches)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))
+            .and_then(|board| jsonrpc::run(stdin.lock(), &mut std::io::stdout(), board));
+        if let Err(e) = result {
+            println!("An error was encountered: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(path) = matches.value_of("script") {
+        let board = start_board(&matches).unwrap_or_else(|e| {
+            println!("An error was encountered: {}", e);
+            std::process::exit(e.exit_code());
+        });
+        std::process::exit(script::run_script(path, matches.is_present("json"), board));
+    }
 
     if !is_play {
         app.borrow_mut()
@@ -270,72 +2016,29 @@ fn main() {
         return;
     }
 
-    play_chess().unwrap_or_else(|x| {
-        println!(
-            "An error was encountered: {}",
-            match x {
-                InvalidIndexing(x) => x,
-                BadMove(x) => x,
-                IllegalCommand(x) => x,
-            }
-        )
+    let clocks = start_clocks(&matches);
+    let armageddon = matches.is_present("armageddon");
+    let opponent = match matches.is_present("engine") {
+        true => Some(opponent::Opponent::Engine),
+        false => matches
+            .value_of("opponent")
+            .and_then(opponent::Opponent::by_name),
+    };
+    let engine = engine_of(&matches, "engine", "engine-option", Default::default());
+    let config::Config { display, play, .. } = config().unwrap_or_else(|e| {
+        println!("An error was encountered: {}", e);
+        std::process::exit(1);
     });
-}
-
-#[test]
-fn test_get_piece_at() {
-    assert!(ChessBoard::new()
-        .get_piece_at_bytes("ab".as_bytes())
-        .is_err());
-    assert!(ChessBoard::new()
-        .get_piece_at_bytes("12".as_bytes())
-        .is_err());
-    assert!(ChessBoard::new()
-        .get_piece_at_bytes("i1".as_bytes())
-        .is_err());
-    assert!(ChessBoard::new()
-        .get_piece_at_bytes("a9".as_bytes())
-        .is_err());
-    assert!(ChessBoard::new()
-        .get_piece_at_bytes("a0".as_bytes())
-        .is_err());
-
-    let board = ChessBoard::new();
-    let a1_piece = board
-        .get_piece_at_bytes("a1".as_bytes())
-        .expect("a1 failed");
-    assert_eq!(GET_COLOR(a1_piece), 0);
-    assert_eq!(GET_NUM(a1_piece), 3);
-    assert_eq!(SET_WHITE(ROOK), a1_piece);
-    let a2_piece = board
-        .get_piece_at_bytes("a2".as_bytes())
-        .expect("a2 failed");
-    assert_eq!(SET_WHITE(PAWN), a2_piece);
-    let a3_piece = board
-        .get_piece_at_bytes("a3".as_bytes())
-        .expect("a3 failed");
-    assert_eq!(SET_WHITE(EMPTY), a3_piece);
-
-    let a8_piece = board
-        .get_piece_at_bytes("a8".as_bytes())
-        .expect("a8 failed");
-    assert_eq!(GET_COLOR(a8_piece), 1);
-    assert_eq!(GET_NUM(a8_piece), 3);
-    assert_eq!(SET_BLACK(ROOK), a8_piece);
-    let a7_piece = board
-        .get_piece_at_bytes("a7".as_bytes())
-        .expect("a7 failed");
-    assert_eq!(SET_BLACK(PAWN), a7_piece);
-    let a6_piece = board
-        .get_piece_at_bytes("a6".as_bytes())
-        .expect("a6 failed");
-    assert_eq!(SET_BLACK(EMPTY), a6_piece);
-
-    for i in 1..=8 {
-        for j in 1..=8 {
-            assert!(ChessBoard::new()
-                .get_piece_at_bytes(&[96 + i as u8, 48 + j as u8][..])
-                .is_ok());
-        }
-    }
+    let view = prompt::View {
+        screen_reader: matches.is_present("screen-reader") || display.screen_reader,
+        blindfold: matches.is_present("blindfold"),
+        theme: display.theme,
+        themes: display.themes,
+        layout: display.layout,
+    };
+    start_board(&matches)
+        .and_then(|board| {
+            prompt::play_chess(board, clocks, armageddon, opponent, engine, view, play)
+        })
+        .unwrap_or_else(|x| println!("An error was encountered: {}", x));
 }
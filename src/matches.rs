@@ -0,0 +1,648 @@
+//! Engine matches: two configurations of the engine play a series of games from random openings
+//! or those of an opening suite, each opening once with either color, and the result is reported
+//! with the Elo difference it suggests. Games can be adjudicated rather than played out, and are
+//! written to PGN with how each ended in its `Termination` tag. Several games can be played at
+//! once, each on its own thread, and every game can be played as Armageddon, as in a tiebreak.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::time::Instant;
+
+use crate::engine::{Engine, EngineSpec, Limits};
+use crate::movegen::color_name;
+use crate::movegen::Move;
+use crate::net::random_u64;
+use crate::pgn::{parse_games, PgnGame};
+use crate::san::PieceLetters;
+use crate::selfplay::{
+    play_with, random_opening, Adjudication, Played, Rng, Termination, MAX_DEPTH,
+};
+use crate::time_control;
+use crate::{armageddon, ChessBoard, BLACK};
+
+/// How many random plies each pair of games starts with.
+pub(crate) const OPENING_PLIES: usize = 8;
+
+/// One side of a match: an engine, under a name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Player {
+    pub name: String,
+    pub engine: EngineSpec,
+}
+
+/// The first player's wins, draws and losses.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tally {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Tally {
+    /// Counts a game the first player scored `score` in.
+    fn add(&mut self, score: f64) {
+        match score {
+            1.0 => self.wins += 1,
+            0.0 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The first player's points.
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+
+    /// The Elo difference the first player's score suggests, or `None` if it won or lost every
+    /// game, which suggests no difference in particular.
+    pub fn elo(&self) -> Option<f64> {
+        let score = self.points() / self.games() as f64;
+        (score > 0.0 && score < 1.0).then(|| 400.0 * (score / (1.0 - score)).log10())
+    }
+}
+
+/// Reads an opening suite: one FEN or EPD record per line, of which only the position is used,
+/// with blank lines and lines starting with `#` skipped; or PGN, each game of which gives the
+/// position at the end of its main line.
+pub fn read_openings(text: &str) -> Result<Vec<ChessBoard>, Error> {
+    let invalid = |e: String| Error::new(ErrorKind::InvalidData, e);
+    let lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+    let fens = lines
+        .clone()
+        .next()
+        .and_then(|(_, line)| line.split_whitespace().next())
+        .is_some_and(|first| first.contains('/'));
+    let openings = match fens {
+        true => lines
+            .map(|(number, line)| {
+                let fields = line.split_whitespace().take(4).collect::<Vec<&str>>();
+                ChessBoard::from_fen(line)
+                    .or_else(|_| ChessBoard::from_fen(&fields.join(" ")))
+                    .map_err(|e| invalid(format!("Line {}: {}", number + 1, e)))
+            })
+            .collect::<Result<Vec<_>, Error>>()?,
+        false => parse_games(text, PieceLetters::ENGLISH)
+            .map_err(|e| invalid(e.to_string()))?
+            .iter()
+            .map(PgnGame::board)
+            .collect(),
+    };
+    if let Some(number) = openings.iter().position(|board| board.outcome().is_some()) {
+        return Err(invalid(format!("Opening {} is already over.", number + 1)));
+    }
+    match openings.is_empty() {
+        true => Err(invalid("The opening suite has no openings.".to_string())),
+        false => Ok(openings),
+    }
+}
+
+/// White's score in a game it scored `score` in by the rules, which in Armageddon is a loss if
+/// the game was drawn.
+fn armageddon_score(score: f64, armageddon: bool) -> f64 {
+    match (score, armageddon) {
+        (0.5, true) => 0.0,
+        _ => score,
+    }
+}
+
+/// The PGN of game `round` of a match, which `white` and `black` played from `start`, with
+/// `clocks` the starting time of each on the clock, as Armageddon if `armageddon`.
+fn record(
+    start: ChessBoard,
+    round: u32,
+    [white, black]: [&Player; 2],
+    clocks: Option<[u32; 2]>,
+    armageddon: bool,
+    played: &Played,
+) -> PgnGame {
+    let mut game = PgnGame::new(start);
+    game.set_tag("Event", "FreightChess match");
+    game.set_tag("Round", &round.to_string());
+    game.set_tag("White", &white.name);
+    game.set_tag("Black", &black.name);
+    if let Some(clocks) = clocks {
+        time_control::set_tags(&mut game, clocks);
+    }
+    game.set_tag("Termination", played.termination.tag());
+    if armageddon && played.score == 0.5 {
+        game.set_tag(
+            "TerminationDetails",
+            &armageddon::announce_draw("Drawn.", true),
+        );
+    }
+    for &mv in &played.moves {
+        game.tree.push(mv);
+    }
+    game.result = match armageddon_score(played.score, armageddon) {
+        1.0 => "1-0",
+        0.0 => "0-1",
+        _ => "1/2-1/2",
+    }
+    .to_string();
+    game
+}
+
+/// Plays `games` games between `players`, searching at most `nodes` positions per move and
+/// adjudicating as `adjudication` says, and passes each to `on_game` in the order of the rounds.
+/// With `time`, the games are played on the clock, the first player starting with `time[0]`
+/// milliseconds and the second with `time[1]`, and a player who runs out of time loses. With
+/// `armageddon`, every game is played as Armageddon: on the clock, five minutes each unless
+/// `time` says otherwise, black gets four fifths of its time and wins if the game is drawn.
+/// Every opening is played twice, the first player taking white in the first game of the pair.
+/// The openings are those of `openings` in turn, starting over once all have been played, or
+/// random ones if it is empty. Up to `concurrency` games are played at once, each on its own
+/// thread, with engines of its own; when every search is limited by positions rather than time,
+/// the games are the same however many there are. An engine that fails forfeits its game and is
+/// started again for the next.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn play_match(
+    players: &[Player; 2],
+    openings: &[ChessBoard],
+    games: u32,
+    nodes: u64,
+    time: Option<[u32; 2]>,
+    armageddon: bool,
+    adjudication: &Adjudication,
+    concurrency: usize,
+    rng: &mut Rng,
+    mut on_game: impl FnMut(&PgnGame) -> Result<(), Error>,
+) -> Result<Tally, Error> {
+    // The openings are all chosen first, so that they don't depend on which game ends first.
+    let starts = (0..games.div_ceil(2) as usize)
+        .map(|pair| match openings.is_empty() {
+            true => random_opening(OPENING_PLIES, rng),
+            false => openings[pair % openings.len()],
+        })
+        .collect::<Vec<_>>();
+    let limits = Limits {
+        depth: MAX_DEPTH,
+        nodes,
+        clocks: None,
+    };
+    let time = time.or(armageddon.then_some([armageddon::DEFAULT_MINUTES * 60_000; 2]));
+    let play = |round: u32, engines: &mut [Option<Engine>; 2]| {
+        let swapped = round.is_multiple_of(2);
+        let [white, black] = match swapped {
+            true => [&players[1], &players[0]],
+            false => [&players[0], &players[1]],
+        };
+        let start = starts[(round as usize - 1) / 2];
+        let start_clocks = time.map(|[first, second]| {
+            let [white, black] = match swapped {
+                true => [second, first],
+                false => [first, second],
+            };
+            match armageddon {
+                true => [white, armageddon::clocks(black)[1]],
+                false => [white, black],
+            }
+        });
+        let mut clocks = start_clocks;
+        // The color whose engine failed, and how.
+        let mut failure = None;
+        for (side, player) in [white, black].into_iter().enumerate() {
+            if let Err(e) = ready(&mut engines[side ^ swapped as usize], &player.engine) {
+                failure = Some((side, e));
+                break;
+            }
+        }
+        let played = match failure {
+            Some((side, ref e)) => Played {
+                moves: Vec::new(),
+                score: side as f64,
+                termination: forfeit(e),
+            },
+            None => {
+                let search = |board: &ChessBoard, moves: &[Move]| {
+                    let side = (board.side_to_move() == BLACK) as usize;
+                    let engine = engines[side ^ swapped as usize].as_mut().unwrap();
+                    let started = Instant::now();
+                    let mut result = engine.search_once(&start, moves, Limits { clocks, ..limits });
+                    if let Some(clock) = clocks.as_mut().map(|clocks| &mut clocks[side]) {
+                        let elapsed = started.elapsed().as_millis();
+                        match elapsed < *clock as u128 {
+                            true => *clock -= elapsed as u32,
+                            false => {
+                                let out = Error::new(ErrorKind::TimedOut, "ran out of time");
+                                result = result.and(Err(out));
+                            }
+                        }
+                    }
+                    result.map_err(|e| {
+                        let termination = forfeit(&e);
+                        failure = Some((side, e));
+                        termination
+                    })
+                };
+                play_with(start, adjudication, search, |_, _| {})
+            }
+        };
+        let mut game = record(
+            start,
+            round,
+            [white, black],
+            start_clocks,
+            armageddon,
+            &played,
+        );
+        if let Some((side, e)) = failure {
+            tracing::warn!(target: "uci", "{} forfeits game {}: {}", [white, black][side].name, round, e);
+            game.set_tag(
+                "TerminationDetails",
+                &format!("Forfeited by {}: {}", color_name(side as u8 * BLACK), e),
+            );
+            engines[side ^ swapped as usize] = None;
+        }
+        let score = armageddon_score(played.score, armageddon);
+        let score = match swapped {
+            true => 1.0 - score,
+            false => score,
+        };
+        (score, game)
+    };
+
+    let next = AtomicU32::new(1);
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..concurrency.max(1) {
+            let (sender, next, play) = (sender.clone(), &next, &play);
+            scope.spawn(move || {
+                let mut engines = [None, None];
+                loop {
+                    let round = next.fetch_add(1, Ordering::Relaxed);
+                    // Once the match has stopped, nobody is left to send the game to.
+                    if round > games || sender.send((round, play(round, &mut engines))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        // A game that ends early waits here until those of the rounds before it have.
+        let mut finished = BTreeMap::new();
+        let mut tally = Tally::default();
+        for (round, game) in receiver {
+            finished.insert(round, game);
+            while let Some((score, game)) = finished.remove(&(tally.games() + 1)) {
+                tally.add(score);
+                on_game(&game)?;
+            }
+        }
+        Ok(tally)
+    })
+}
+
+/// Gets `engine` ready for a new game, starting it as `spec` says if it isn't running.
+fn ready(engine: &mut Option<Engine>, spec: &EngineSpec) -> Result<(), Error> {
+    match engine {
+        Some(engine) => engine.new_game(),
+        None => {
+            *engine = Some(spec.start()?);
+            Ok(())
+        }
+    }
+}
+
+/// How a game ends when an engine fails with `e`: it stopped answering in time, or played an
+/// illegal move, or else crashed.
+fn forfeit(e: &Error) -> Termination {
+    match e.kind() {
+        ErrorKind::TimedOut => Termination::Timeout,
+        ErrorKind::InvalidData => Termination::IllegalMove,
+        _ => Termination::Crash,
+    }
+}
+
+/// A game's round, players, result and how it ended, as in `3: first 1-0 second (normal)`.
+pub(crate) fn describe(game: &PgnGame) -> String {
+    format!(
+        "{}: {} {} {} ({})",
+        game.tag("Round").unwrap_or("?"),
+        game.tag("White").unwrap_or("?"),
+        game.result,
+        game.tag("Black").unwrap_or("?"),
+        game.tag("Termination").unwrap_or("?")
+    )
+}
+
+/// Runs `match`: plays `games` games between `players`, searching at most `nodes` positions per
+/// move, on the clock with `time` for each player if it is given, from the openings of the suite
+/// in the file `openings`, or else from random ones chosen by `seed` (or at random), printing
+/// each result and the total, and writing the games to the PGN file `pgn` if given. Up to
+/// `concurrency` games are played at once, as Armageddon if `armageddon`.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    players: [Player; 2],
+    openings: Option<&str>,
+    games: u32,
+    nodes: u64,
+    time: Option<[u32; 2]>,
+    armageddon: bool,
+    concurrency: usize,
+    seed: Option<u64>,
+    adjudication: Adjudication,
+    pgn: Option<&str>,
+) -> Result<(), Error> {
+    let openings = match openings {
+        Some(path) => read_openings(&std::fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+    let mut rng = Rng::new(seed.unwrap_or_else(random_u64));
+    let mut output = pgn.map(File::create).transpose()?.map(BufWriter::new);
+    let tally = play_match(
+        &players,
+        &openings,
+        games,
+        nodes,
+        time,
+        armageddon,
+        &adjudication,
+        concurrency,
+        &mut rng,
+        |game| {
+            println!("Game {}", describe(game));
+            match &mut output {
+                Some(output) => writeln!(output, "{}", game.to_pgn()),
+                None => Ok(()),
+            }
+        },
+    )?;
+    if let Some(output) = &mut output {
+        output.flush()?;
+    }
+    println!();
+    println!(
+        "{} against {}: +{} -{} ={}, {} of {} points",
+        players[0].name,
+        players[1].name,
+        tally.wins,
+        tally.losses,
+        tally.draws,
+        tally.points(),
+        tally.games()
+    );
+    match tally.elo() {
+        Some(elo) => println!("Elo difference: {:+.0}", elo),
+        None => println!("Elo difference: unknown from this score"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_match() {
+    use crate::search::SearchParams;
+
+    let players = [
+        Player {
+            name: "first".to_string(),
+            engine: EngineSpec::Internal(SearchParams::default()),
+        },
+        Player {
+            name: "second".to_string(),
+            engine: EngineSpec::Internal(SearchParams::default()),
+        },
+    ];
+    let adjudication = Adjudication {
+        max_moves: 10,
+        ..Adjudication::default()
+    };
+    let mut games = Vec::new();
+    let tally = play_match(
+        &players,
+        &[],
+        4,
+        200,
+        None,
+        false,
+        &adjudication,
+        1,
+        &mut Rng::new(3),
+        |game| {
+            games.push((game.tree.start, game.to_pgn()));
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(tally.games(), 4);
+    assert_eq!(games[0].0.zobrist(), games[1].0.zobrist());
+    assert_ne!(games[1].0.zobrist(), games[2].0.zobrist());
+    assert!(games[0].1.contains("[White \"first\"]\n[Black \"second\"]"));
+    assert!(games[1].1.contains("[White \"second\"]\n[Black \"first\"]"));
+    assert!(games[3].1.contains("[Round \"4\"]"));
+    assert!(games.iter().all(|(_, pgn)| pgn.contains("[Termination \"")));
+
+    // Played three at a time, the games are the same, and come in the same order.
+    let mut concurrent = Vec::new();
+    let same = play_match(
+        &players,
+        &[],
+        4,
+        200,
+        None,
+        false,
+        &adjudication,
+        3,
+        &mut Rng::new(3),
+        |game| {
+            concurrent.push(game.to_pgn());
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(same, tally);
+    assert!(games.iter().map(|(_, pgn)| pgn).eq(concurrent.iter()));
+
+    // The engine itself can play as an external engine. One that can't be started, crashes or
+    // plays an illegal move forfeits its games, and the match goes on.
+    let binary = match cfg!(target_os = "windows") {
+        true => "target\\debug\\freight_chess.exe uci",
+        false => "./target/debug/freight_chess uci",
+    };
+    let uci = |command: String| EngineSpec::Uci {
+        command,
+        options: Vec::new(),
+    };
+    let mut external = players.clone();
+    external[1].engine = uci(binary.to_string());
+    let tally = play_match(
+        &external,
+        &[],
+        2,
+        200,
+        None,
+        false,
+        &adjudication,
+        1,
+        &mut Rng::new(3),
+        |_| Ok(()),
+    );
+    assert_eq!(tally.unwrap().games(), 2);
+    let mut forfeited = |engine: String, concurrency: usize| {
+        external[1].engine = uci(engine);
+        let mut games = Vec::new();
+        let tally = play_match(
+            &external,
+            &[],
+            2,
+            200,
+            None,
+            false,
+            &adjudication,
+            concurrency,
+            &mut Rng::new(3),
+            |game| {
+                games.push(game.to_pgn());
+                Ok(())
+            },
+        );
+        assert_eq!(tally.unwrap().wins, 2);
+        games
+    };
+    let games = forfeited("true".to_string(), 2);
+    assert!(games[0].contains("[Termination \"abandoned\"]"));
+    assert!(games[1].contains("[TerminationDetails \"Forfeited by white: "));
+    if cfg!(unix) {
+        // An engine that answers the handshake, then exits or plays a1a1 when asked to move.
+        let script = std::env::temp_dir().join("freight_chess_test_engine.sh");
+        std::fs::write(
+            &script,
+            "while read line; do\n\
+            \x20 case $line in\n\
+            \x20   uci) echo uciok;;\n\
+            \x20   isready) echo readyok;;\n\
+            \x20   go*) [ \"$1\" = crash ] && exit 1; echo bestmove a1a1;;\n\
+            \x20   quit) exit;;\n\
+            \x20 esac\n\
+            done\n",
+        )
+        .unwrap();
+        for (how, termination) in [("crash", "abandoned"), ("illegal", "rules infraction")] {
+            let games = forfeited(format!("sh {} {}", script.display(), how), 1);
+            let tag = format!("[Termination \"{}\"]", termination);
+            assert!(games.iter().all(|pgn| pgn.contains(&tag)));
+            assert!(games[0].contains("[TerminationDetails \"Forfeited by black: "));
+        }
+        std::fs::remove_file(script).unwrap();
+    }
+
+    // On the clock, the player with no time left loses every game, whatever their color.
+    let mut games = Vec::new();
+    let tally = play_match(
+        &players,
+        &[],
+        2,
+        200,
+        Some([60_000, 0]),
+        false,
+        &adjudication,
+        1,
+        &mut Rng::new(3),
+        |game| {
+            games.push(game.to_pgn());
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(tally.wins, 2);
+    assert!(games[0].contains("[WhiteTimeControl \"60\"]\n[BlackTimeControl \"0\"]"));
+    assert!(games[1].contains("[WhiteTimeControl \"0\"]\n[BlackTimeControl \"60\"]"));
+    assert!(games
+        .iter()
+        .all(|pgn| pgn.contains("[Termination \"time forfeit\"]")));
+
+    // A suite's openings are played in turn, each by both players with either color.
+    let suite = read_openings(
+        "# Two openings\n\
+        rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1\n\
+        \n\
+        rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - id \"d4\";\n",
+    )
+    .unwrap();
+    assert_eq!(suite.len(), 2);
+    let mut starts = Vec::new();
+    play_match(
+        &players,
+        &suite,
+        6,
+        200,
+        None,
+        false,
+        &adjudication,
+        2,
+        &mut Rng::new(3),
+        |game| {
+            starts.push(game.tree.start.zobrist());
+            Ok(())
+        },
+    )
+    .unwrap();
+    let [e4, d4] = [suite[0].zobrist(), suite[1].zobrist()];
+    assert_eq!(starts, [e4, e4, d4, d4, e4, e4]);
+
+    let pgn = read_openings("1. e4 e5 2. Nf3 *\n\n[Event \"?\"]\n\n1. d4 d5 *\n").unwrap();
+    assert_eq!(pgn.len(), 2);
+    assert_eq!(
+        pgn[0].zobrist(),
+        ChessBoard::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2")
+            .unwrap()
+            .zobrist()
+    );
+    assert!(read_openings("# Nothing\n").is_err());
+    assert!(read_openings("8/8/8/8/8/8/8/9 w - -\n").is_err());
+    assert!(read_openings("7k/5QQ1/8/8/8/8/8/K7 b - - 0 1\n").is_err());
+
+    // In Armageddon, black wins the drawn games, and has less time.
+    let mut games = Vec::new();
+    let tally = play_match(
+        &players,
+        &[],
+        2,
+        200,
+        None,
+        true,
+        &adjudication,
+        1,
+        &mut Rng::new(3),
+        |game| {
+            games.push(game.to_pgn());
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(tally.wins + tally.losses, 2);
+    for pgn in &games {
+        assert!(pgn.contains("[WhiteTimeControl \"300\"]\n[BlackTimeControl \"240\"]"));
+        assert!(pgn.contains("[Result \"0-1\"]"));
+        assert!(pgn.contains("[TerminationDetails \"Drawn. Black wins the Armageddon game.\"]"));
+    }
+    assert_eq!(armageddon_score(0.5, true), 0.0);
+    assert_eq!(armageddon_score(1.0, true), 1.0);
+    assert_eq!(armageddon_score(0.0, true), 0.0);
+    assert_eq!(armageddon_score(0.5, false), 0.5);
+
+    let tally = Tally {
+        wins: 3,
+        draws: 2,
+        losses: 1,
+    };
+    assert_eq!(tally.points(), 4.0);
+    assert!((tally.elo().unwrap() - 120.4).abs() < 0.1);
+    assert_eq!(
+        Tally {
+            wins: 2,
+            ..Tally::default()
+        }
+        .elo(),
+        None
+    );
+}
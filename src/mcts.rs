@@ -0,0 +1,285 @@
+//! Monte Carlo Tree Search, an alternative to the alpha-beta search in the style of AlphaZero.
+//! Each playout walks down the tree picking moves by PUCT (how well they have done so far, plus
+//! a bonus for moves the policy likes that haven't been tried much), expands the position it
+//! reaches, and backs the policy's value of it up the path. The move played is the one visited
+//! most.
+//!
+//! What the tree is told about a position comes from an `Evaluator`, so other policies (a
+//! network, say) can be tried on the same board code.
+
+use crate::eval::{evaluate, PIECE_VALUES};
+use crate::movegen::{Move, MoveList};
+use crate::search::SearchResult;
+use crate::selfplay::Rng;
+use crate::{ChessBoard, BLACK, GET_NUM, WHITE};
+
+/// How many playouts `go` makes when it isn't told.
+pub const DEFAULT_PLAYOUTS: u32 = 800;
+
+/// How much PUCT weighs the policy's priors against the results of playouts.
+const C_PUCT: f32 = 1.5;
+
+/// How many random moves a rollout plays before it settles for the evaluation.
+const ROLLOUT_PLIES: usize = 32;
+
+/// Tells the tree how promising each move of a position is and how good the position is.
+pub trait Evaluator {
+    /// Returns the prior probability of each of the legal `moves` of `board` (adding up to 1),
+    /// and its value for the side to move, from -1 (lost) to 1 (won).
+    fn evaluate(&mut self, board: &ChessBoard, moves: &[Move]) -> (Vec<f32>, f32);
+}
+
+/// Turns a score in centipawns into a value from -1 to 1, on the same curve `tune` fits.
+fn value_of(score: i32) -> f32 {
+    2.0 / (1.0 + 10f32.powf(-score as f32 / 400.0)) - 1.0
+}
+
+/// Turns a value from -1 to 1 back into centipawns.
+fn score_of(value: f32) -> i32 {
+    let value = value.clamp(-0.999, 0.999);
+    (-400.0 * (2.0 / (value + 1.0) - 1.0).log10()).round() as i32
+}
+
+/// Uses the static evaluation for values, and favors captures of valuable pieces and promotions
+/// in the priors.
+pub struct StaticEval;
+
+impl Evaluator for StaticEval {
+    fn evaluate(&mut self, board: &ChessBoard, moves: &[Move]) -> (Vec<f32>, f32) {
+        let weights = moves
+            .iter()
+            .map(|&mv| {
+                let mut gain = PIECE_VALUES[mv.promotion as usize];
+                if board.is_capture(mv) {
+                    gain += PIECE_VALUES[GET_NUM(board.get_piece(mv.to)) as usize].max(100);
+                }
+                (gain as f32 / 300.0).exp()
+            })
+            .collect::<Vec<f32>>();
+        let total = weights.iter().sum::<f32>();
+        let priors = weights.iter().map(|weight| weight / total).collect();
+        (priors, value_of(evaluate(board)))
+    }
+}
+
+/// Gives every move the same prior, and values a position by playing random moves from it, as
+/// in classic MCTS.
+pub struct Rollout(pub Rng);
+
+impl Evaluator for Rollout {
+    fn evaluate(&mut self, board: &ChessBoard, moves: &[Move]) -> (Vec<f32>, f32) {
+        let priors = vec![1.0 / moves.len() as f32; moves.len()];
+        let side = board.side_to_move();
+        let mut board = *board;
+        let mut moves = moves.iter().copied().collect::<MoveList>();
+        for _ in 0..ROLLOUT_PLIES {
+            if let Some(value) = outcome(&board, &moves) {
+                return match board.side_to_move() == side {
+                    true => (priors, value),
+                    false => (priors, -value),
+                };
+            }
+            board.apply_move(moves[self.0.below(moves.len())]);
+            moves = board.legal_moves();
+        }
+        let value = value_of(evaluate(&board));
+        match board.side_to_move() == side {
+            true => (priors, value),
+            false => (priors, -value),
+        }
+    }
+}
+
+/// Which `Evaluator` to search with, as chosen on the command line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    /// `StaticEval`.
+    Eval,
+    /// `Rollout`.
+    Rollout,
+}
+
+impl Policy {
+    /// The policy called `name` on the command line.
+    pub fn by_name(name: &str) -> Option<Policy> {
+        match name {
+            "eval" => Some(Policy::Eval),
+            "rollout" => Some(Policy::Rollout),
+            _ => None,
+        }
+    }
+}
+
+/// The value of the position for the side to move if the game is over, given its legal `moves`.
+fn outcome(board: &ChessBoard, moves: &[Move]) -> Option<f32> {
+    let (result, _) = board.variant.outcome(board, moves)?;
+    let winner = match result {
+        "1-0" => WHITE,
+        "0-1" => BLACK,
+        _ => return Some(0.0),
+    };
+    Some(if winner == board.side_to_move() {
+        1.0
+    } else {
+        -1.0
+    })
+}
+
+/// A position in the tree, reached by `mv`.
+struct Node {
+    mv: Option<Move>,
+    prior: f32,
+    visits: u32,
+    /// The sum of the values backed up through the node, for the side that played `mv`.
+    total: f32,
+    /// Whether the policy has been asked about the position, which gives it its children.
+    expanded: bool,
+    /// Where the node's children are in the tree.
+    children: Vec<usize>,
+}
+
+impl Node {
+    fn new(mv: Option<Move>, prior: f32) -> Node {
+        Node {
+            mv,
+            prior,
+            visits: 0,
+            total: 0.0,
+            expanded: false,
+            children: Vec::new(),
+        }
+    }
+
+    /// The average value of the node for the side that played `mv`.
+    fn mean(&self) -> f32 {
+        match self.visits {
+            0 => 0.0,
+            visits => self.total / visits as f32,
+        }
+    }
+}
+
+/// Picks the child of `parent` to walk down to, by PUCT.
+fn select(tree: &[Node], parent: usize) -> usize {
+    let exploration = C_PUCT * (tree[parent].visits as f32).sqrt();
+    let puct = |&child: &usize| {
+        let node = &tree[child];
+        node.mean() + exploration * node.prior / (1 + node.visits) as f32
+    };
+    *tree[parent]
+        .children
+        .iter()
+        .max_by(|a, b| puct(a).total_cmp(&puct(b)))
+        .unwrap()
+}
+
+impl ChessBoard {
+    /// Searches the position with `playouts` playouts of Monte Carlo Tree Search, asking
+    /// `evaluator` about each position it expands. The score is the best move's average value in
+    /// centipawns, and the line follows the most visited moves.
+    pub fn search_mcts(&self, playouts: u32, evaluator: &mut impl Evaluator) -> SearchResult {
+        let mut tree = vec![Node::new(None, 1.0)];
+        for _ in 0..playouts.max(1) {
+            let mut board = *self;
+            let mut path = vec![0];
+            let mut node = 0;
+            while tree[node].expanded && !tree[node].children.is_empty() {
+                node = select(&tree, node);
+                board.apply_move(tree[node].mv.unwrap());
+                path.push(node);
+            }
+
+            let moves = board.legal_moves();
+            let value = match outcome(&board, &moves) {
+                Some(value) => value,
+                None => {
+                    let (priors, value) = evaluator.evaluate(&board, &moves);
+                    for (&mv, prior) in moves.iter().zip(priors) {
+                        let child = tree.len();
+                        tree[node].children.push(child);
+                        tree.push(Node::new(Some(mv), prior));
+                    }
+                    value
+                }
+            };
+            tree[node].expanded = true;
+
+            // The value is for the side to move at the end of the path, so it counts against the
+            // side that moved there, and alternates on the way up.
+            let mut value = -value;
+            for &step in path.iter().rev() {
+                tree[step].visits += 1;
+                tree[step].total += value;
+                value = -value;
+            }
+        }
+
+        let most_visited = |node: usize| {
+            tree[node]
+                .children
+                .iter()
+                .copied()
+                .filter(|&child| tree[child].visits > 0)
+                .max_by_key(|&child| tree[child].visits)
+        };
+        let mut pv = Vec::new();
+        let mut node = 0;
+        while let Some(child) = most_visited(node) {
+            pv.push(tree[child].mv.unwrap());
+            node = child;
+        }
+        let score = match most_visited(0) {
+            Some(best) => score_of(tree[best].mean()),
+            None => score_of(-tree[0].mean()),
+        };
+        SearchResult {
+            best_move: pv.first().copied(),
+            score,
+            depth: pv.len() as u32,
+            pv,
+            nodes: tree[0].visits as u64,
+        }
+    }
+
+    /// Searches with MCTS and the evaluator `policy` names.
+    pub fn search_policy(&self, playouts: u32, policy: Policy) -> SearchResult {
+        match policy {
+            Policy::Eval => self.search_mcts(playouts, &mut StaticEval),
+            Policy::Rollout => self.search_mcts(playouts, &mut Rollout(Rng::new(self.zobrist()))),
+        }
+    }
+}
+
+#[test]
+fn test_mcts() {
+    assert_eq!(score_of(value_of(150)), 150);
+    assert_eq!(score_of(0.0), 0);
+
+    // Scholar's mate is one move away, whichever policy finds it.
+    let board =
+        ChessBoard::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+            .unwrap();
+    for policy in [Policy::Eval, Policy::Rollout] {
+        let result = board.search_policy(300, policy);
+        assert_eq!(
+            board.san(result.best_move.unwrap()),
+            "Qxf7#",
+            "{:?}",
+            policy
+        );
+        assert!(result.score > 1000);
+        assert_eq!(result.nodes, 300);
+    }
+
+    // A hanging queen gets taken.
+    let board = ChessBoard::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+    let result = board.search_policy(200, Policy::Eval);
+    assert_eq!(board.san(result.best_move.unwrap()), "Rxd5");
+
+    // No moves, no best move.
+    let board = ChessBoard::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    let result = board.search_policy(10, Policy::Eval);
+    assert_eq!(result.best_move, None);
+    assert_eq!(result.score, 0);
+    assert_eq!(Policy::by_name("rollout"), Some(Policy::Rollout));
+}
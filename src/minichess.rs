@@ -0,0 +1,92 @@
+//! Minichess on smaller boards, for teaching and for quick engine experiments. Pawns step one
+//! square at a time, so there is no en passant, and there is no castling.
+
+use crate::movegen::MoveList;
+use crate::variant::Variant;
+use crate::{ChessBoard, BISHOP};
+
+/// Gardner's 5x5 minichess: the standard pieces but one bishop on each side, from
+/// `rnbqk/ppppp/5/PPPPP/RNBQK`.
+pub struct Gardner;
+
+impl Variant for Gardner {
+    fn name(&self) -> &'static str {
+        "gardner"
+    }
+
+    fn pgn_name(&self) -> &'static str {
+        "Gardner"
+    }
+
+    fn start(&self) -> ChessBoard {
+        ChessBoard::from_variant_fen("rnbqk/ppppp/5/PPPPP/RNBQK w - - 0 1", &Gardner)
+            .unwrap_or_else(|_| ChessBoard::new())
+    }
+
+    fn dimensions(&self) -> (u8, u8) {
+        (5, 5)
+    }
+}
+
+/// Los Alamos chess on a 6x6 board, without bishops, from `rnqknr/pppppp/6/6/PPPPPP/RNQKNR`.
+/// Pawns can't promote to bishops either.
+pub struct LosAlamos;
+
+impl Variant for LosAlamos {
+    fn name(&self) -> &'static str {
+        "losalamos"
+    }
+
+    fn pgn_name(&self) -> &'static str {
+        "Los Alamos"
+    }
+
+    fn start(&self) -> ChessBoard {
+        ChessBoard::from_variant_fen("rnqknr/pppppp/6/6/PPPPPP/RNQKNR w - - 0 1", &LosAlamos)
+            .unwrap_or_else(|_| ChessBoard::new())
+    }
+
+    fn dimensions(&self) -> (u8, u8) {
+        (6, 6)
+    }
+
+    fn adjust_moves(&self, _board: &ChessBoard, moves: &mut MoveList) {
+        moves.retain(|mv| mv.promotion != BISHOP);
+    }
+}
+
+#[test]
+fn test_minichess() {
+    use crate::movegen::MoveRejection;
+    use crate::variant::by_name;
+
+    let gardner = by_name("gardner").unwrap();
+    let mut board = ChessBoard::start_of(gardner);
+    assert_eq!(board.fen(), "rnbqk/ppppp/5/PPPPP/RNBQK w - - 0 1");
+    assert_eq!(
+        board.to_string(),
+        "|♜|♞|♝|♛|♚|\n|♟|♟|♟|♟|♟|\n| | | | | |\n|♙|♙|♙|♙|♙|\n|♖|♘|♗|♕|♔|\n"
+    );
+    assert_eq!(board.perft(1), 7);
+    assert_eq!(board.perft(4), 4775);
+    // Pieces stop at the edge of the board, and pawns promote on the fifth rank.
+    for san in ["b3", "c3", "dxc3", "bxc3", "Nxc3"] {
+        board.apply_move(board.parse_san(san).expect(san));
+    }
+    assert!(board.parse_san("Qa6").is_err());
+    let board = ChessBoard::from_variant_fen("4k/P4/5/5/K4 w - - 0 1", gardner).unwrap();
+    assert_eq!(board.parse_san("a5"), Err(MoveRejection::MissingPromotion));
+    assert_eq!(board.legal_sans()[3..], ["a5=Q+", "a5=R+", "a5=B", "a5=N"]);
+    assert!(ChessBoard::from_variant_fen(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        gardner
+    )
+    .is_err());
+
+    let los_alamos = by_name("Los Alamos").unwrap();
+    let board = ChessBoard::start_of(los_alamos);
+    assert_eq!(board.perft(4), 14332);
+    let board = ChessBoard::from_variant_fen("5k/P5/6/6/6/K5 w - - 0 1", los_alamos).unwrap();
+    assert!(!board.legal_sans().iter().any(|san| san.contains("=B")));
+    assert!(board.legal_sans().contains(&"a6=N".to_string()));
+}
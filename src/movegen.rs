@@ -0,0 +1,578 @@
+use std::fmt::{Display, Formatter};
+
+use freight_chess_core::rules::{self, offset, Board, KING_OFFSETS, KNIGHT_OFFSETS};
+
+use crate::{
+    attacks, ChessBoard, BISHOP, BLACK, EMPTY, GET_NUM, KING, KNIGHT, PAWN, QUEEN, ROOK, WHITE,
+};
+
+// Castling rights, stored as bit flags in `ChessBoard::castling`, the en passant square's
+// marker for there being none, and moves are those of the `no_std` core, which also generates
+// and plays the moves by the standard rules for `ChessBoard`.
+pub use freight_chess_core::rules::{CASTLING_RIGHTS, CORNERS};
+pub use freight_chess_core::{
+    Move, MoveList, BLACK_KINGSIDE, BLACK_QUEENSIDE, NO_SQUARE, WHITE_KINGSIDE, WHITE_QUEENSIDE,
+};
+/// What `make_move` keeps to take the move back with `unmake_move`. The board is small enough to
+/// copy, so it is the position from before the move, which leaves nothing a variant changed to
+/// be undone by hand.
+pub struct Undo(ChessBoard);
+
+/// The reason the legality checker turned a move down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveRejection {
+    /// The game has already ended, so no move can be played.
+    GameOver,
+    /// There is no piece on the starting square.
+    NoPiece(u8),
+    /// The piece belongs to the other player; holds the color whose turn it is.
+    NotYourTurn(u8),
+    /// The target square holds a piece of the mover's own color.
+    CaptureOwnPiece(u8),
+    /// The piece (colorless) doesn't move that way, or something is in its path.
+    CannotReach { piece: u8, to: u8 },
+    /// The king may not castle to that side right now.
+    CannotCastle,
+    /// The piece (colorless) isn't in hand, or may not be dropped on that square.
+    CannotDrop { piece: u8, to: u8 },
+    /// The move would leave (or put) the mover's king in check.
+    LeavesKingInCheck,
+    /// The mover is in check and the move does nothing about it.
+    StillInCheck,
+    /// A pawn reached the last rank without saying what it promotes to.
+    MissingPromotion,
+    /// A promotion was given for a move that isn't a pawn reaching the last rank.
+    NotAPromotion,
+    /// The promotion piece isn't a queen, rook, bishop or knight.
+    BadPromotionPiece,
+    /// A capture is possible, and the variant makes capturing compulsory.
+    MustCapture,
+    /// The move would give check, which the variant forbids.
+    GivesCheck,
+    /// The text couldn't be read as a move at all.
+    Unreadable,
+}
+
+impl MoveRejection {
+    /// A short, stable identifier for the reason, for machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MoveRejection::GameOver => "game_over",
+            MoveRejection::NoPiece(_) => "no_piece",
+            MoveRejection::NotYourTurn(_) => "not_your_turn",
+            MoveRejection::CaptureOwnPiece(_) => "capture_own_piece",
+            MoveRejection::CannotReach { .. } => "cannot_reach",
+            MoveRejection::CannotCastle => "cannot_castle",
+            MoveRejection::CannotDrop { .. } => "cannot_drop",
+            MoveRejection::LeavesKingInCheck => "leaves_king_in_check",
+            MoveRejection::StillInCheck => "still_in_check",
+            MoveRejection::MissingPromotion => "missing_promotion",
+            MoveRejection::NotAPromotion => "not_a_promotion",
+            MoveRejection::BadPromotionPiece => "bad_promotion_piece",
+            MoveRejection::MustCapture => "must_capture",
+            MoveRejection::GivesCheck => "gives_check",
+            MoveRejection::Unreadable => "unreadable",
+        }
+    }
+}
+
+impl Display for MoveRejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            MoveRejection::GameOver => write!(f, "The game is already over."),
+            MoveRejection::NoPiece(square) => {
+                write!(f, "There is no piece on {}.", square_name(square))
+            }
+            MoveRejection::NotYourTurn(side) => write!(f, "It's {}'s turn.", color_name(side)),
+            MoveRejection::CaptureOwnPiece(square) => write!(
+                f,
+                "You can't capture your own piece on {}.",
+                square_name(square)
+            ),
+            MoveRejection::CannotReach { piece, to } => write!(
+                f,
+                "That {} can't reach {}.",
+                piece_name(piece),
+                square_name(to)
+            ),
+            MoveRejection::CannotCastle => write!(f, "You can't castle that way right now."),
+            MoveRejection::CannotDrop { piece, to } => write!(
+                f,
+                "You can't drop a {} on {}.",
+                piece_name(piece),
+                square_name(to)
+            ),
+            MoveRejection::LeavesKingInCheck => write!(f, "Your king would be in check."),
+            MoveRejection::StillInCheck => write!(
+                f,
+                "Your king is in check, and that move doesn't get it out of check."
+            ),
+            MoveRejection::MissingPromotion => {
+                write!(f, "Say what the pawn promotes to, as in e7->e8=Q or e8=Q.")
+            }
+            MoveRejection::NotAPromotion => {
+                write!(f, "Only a pawn reaching the last rank can promote.")
+            }
+            MoveRejection::BadPromotionPiece => write!(
+                f,
+                "Pawns can only promote to a queen, rook, bishop or knight."
+            ),
+            MoveRejection::MustCapture => {
+                write!(
+                    f,
+                    "You can capture, and capturing is compulsory in this variant."
+                )
+            }
+            MoveRejection::GivesCheck => write!(f, "Giving check isn't allowed in this variant."),
+            MoveRejection::Unreadable => write!(
+                f,
+                "That isn't a move. Try e2->e4 or algebraic notation like Nf3."
+            ),
+        }
+    }
+}
+
+/// Returns the English name of a piece, ignoring its color.
+pub fn piece_name(piece: u8) -> &'static str {
+    match GET_NUM(piece) {
+        PAWN => "pawn",
+        KNIGHT => "knight",
+        BISHOP => "bishop",
+        ROOK => "rook",
+        QUEEN => "queen",
+        KING => "king",
+        _ => "piece",
+    }
+}
+
+/// Returns `"white"` or `"black"`.
+pub fn color_name(color: u8) -> &'static str {
+    if color == WHITE {
+        "white"
+    } else {
+        "black"
+    }
+}
+
+/// Returns the squares next to `square`, the ones a king on it could step to.
+pub fn adjacent_squares(square: u8) -> impl Iterator<Item = u8> {
+    KING_OFFSETS
+        .iter()
+        .filter_map(move |&direction| offset(square, direction))
+}
+
+/// Returns the name of a square in chessboard notation, e.g. `e4`.
+pub fn square_name(square: u8) -> String {
+    format!("{}{}", (b'a' + (square & 7)) as char, (square >> 3) + 1)
+}
+
+impl ChessBoard {
+    /// Returns every legal move for the side to move.
+    pub fn legal_moves(&self) -> MoveList {
+        let mut moves = self.pseudo_legal_moves();
+        moves.retain(|&mv| {
+            let mut next = *self;
+            next.apply_move(mv);
+            self.variant.is_legal(self, &next)
+        });
+        moves
+    }
+
+    /// Checks whether `mv` may be played, and if not, explains why.
+    pub fn check_move(&self, mv: Move) -> Result<(), MoveRejection> {
+        if mv.is_drop() {
+            if !self.pseudo_legal_moves().contains(&mv) {
+                return Err(MoveRejection::CannotDrop {
+                    piece: mv.promotion,
+                    to: mv.to,
+                });
+            }
+            return self.check_king_safety(mv);
+        }
+        let side = self.side_to_move();
+        let piece = self.get_piece(mv.from);
+        let target = self.get_piece(mv.to);
+
+        if piece == EMPTY {
+            return Err(MoveRejection::NoPiece(mv.from));
+        }
+        if piece & BLACK != side {
+            return Err(MoveRejection::NotYourTurn(side));
+        }
+        let castles = self.castling_index(mv).is_some();
+        // In Chess960, castling is written as the king capturing its own rook.
+        if target != EMPTY && target & BLACK == side && !(castles && self.chess960) {
+            return Err(MoveRejection::CaptureOwnPiece(mv.to));
+        }
+
+        let pseudo_legal = self
+            .pseudo_legal_moves()
+            .into_iter()
+            .filter(|other| other.from == mv.from && other.to == mv.to)
+            .collect::<Vec<Move>>();
+        if pseudo_legal.is_empty() {
+            if let Some(rejection) = self.variant.rejection(self, mv) {
+                return Err(rejection);
+            }
+            if castles {
+                return Err(MoveRejection::CannotCastle);
+            }
+            return Err(MoveRejection::CannotReach {
+                piece: GET_NUM(piece),
+                to: mv.to,
+            });
+        }
+        let promotes = pseudo_legal[0].promotion != EMPTY;
+        if promotes && mv.promotion == EMPTY {
+            return Err(MoveRejection::MissingPromotion);
+        }
+        if !promotes && mv.promotion != EMPTY {
+            return Err(MoveRejection::NotAPromotion);
+        }
+        if !pseudo_legal
+            .iter()
+            .any(|other| other.promotion == mv.promotion)
+        {
+            return Err(MoveRejection::BadPromotionPiece);
+        }
+        self.check_king_safety(mv)
+    }
+
+    /// Checks that `mv`, which the pieces may make, doesn't leave the mover's king in check (or
+    /// break whatever other rule the variant has about the position it leads to).
+    fn check_king_safety(&self, mv: Move) -> Result<(), MoveRejection> {
+        let side = self.side_to_move();
+        let mut next = *self;
+        next.apply_move(mv);
+        if !self.variant.is_legal(self, &next) {
+            if let Some(rejection) = self.variant.rejection(self, mv) {
+                return Err(rejection);
+            }
+            return Err(if self.is_in_check(side) {
+                MoveRejection::StillInCheck
+            } else {
+                MoveRejection::LeavesKingInCheck
+            });
+        }
+        Ok(())
+    }
+
+    /// Once the game is over by the rules of its variant (in standard chess, once the side to
+    /// move has no legal moves), returns the result as written in PGN (`1-0`, `0-1` or
+    /// `1/2-1/2`) and the reason, such as `checkmate` or `stalemate`.
+    pub fn outcome(&self) -> Option<(&'static str, &'static str)> {
+        self.variant.outcome(self, &self.legal_moves())
+    }
+
+    /// Says how the game ended and who won, as in "Checkmate. White wins.", once it is over.
+    pub fn describe_outcome(&self) -> Option<String> {
+        let (result, reason) = self.outcome()?;
+        let how = match reason {
+            "checkmate" => "Checkmate",
+            "stalemate" => "Stalemate",
+            "explosion" => "A king exploded",
+            "three_checks" => "Three checks",
+            "no_pieces" => "No pieces left",
+            "king_arrived" => "A king reached the last rank",
+            "both_kings_arrived" => "Both kings reached the last rank",
+            reason => reason,
+        };
+        Some(match result {
+            "1-0" => format!("{}. White wins.", how),
+            "0-1" => format!("{}. Black wins.", how),
+            _ => format!("{}. The game is drawn.", how),
+        })
+    }
+
+    /// Returns `true` if `color`'s king is in check by the rules of the variant.
+    pub fn is_in_check(&self, color: u8) -> bool {
+        self.variant.is_in_check(self, color)
+    }
+
+    /// Returns `true` if `color`'s king is attacked, which in standard chess means it is in check.
+    pub fn is_king_attacked(&self, color: u8) -> bool {
+        match self.king_square(color) {
+            Some(square) => self.is_attacked(square, color ^ BLACK),
+            None => false,
+        }
+    }
+
+    pub fn king_square(&self, color: u8) -> Option<u8> {
+        rules::king_square(self, color)
+    }
+
+    /// Returns `true` if any piece of color `by` attacks `square`.
+    pub fn is_attacked(&self, square: u8, by: u8) -> bool {
+        rules::is_attacked(self, square, by)
+    }
+
+    /// Returns the squares the piece on `square` attacks, as bits `1 << square`, whoever stands
+    /// on them.
+    pub fn attacks(&self, square: u8) -> u64 {
+        self.attacks_through(square, self.occupied())
+    }
+
+    /// Returns the squares the piece on `square` attacks, as `attacks` does, for a caller that
+    /// already knows the squares `occupied`.
+    pub fn attacks_through(&self, square: u8, occupied: u64) -> u64 {
+        let piece = self.get_piece(square);
+        let steps = |offsets: &[(i8, i8)]| {
+            offsets
+                .iter()
+                .filter_map(|&o| offset(square, o))
+                .fold(0, |bits, to| bits | 1 << to)
+        };
+        let pawn_rank = if piece & BLACK == WHITE { 1 } else { -1 };
+        match GET_NUM(piece) {
+            PAWN => steps(&[(-1, pawn_rank), (1, pawn_rank)]),
+            KNIGHT => steps(&KNIGHT_OFFSETS),
+            BISHOP => attacks::bishop_attacks(square, occupied),
+            ROOK => attacks::rook_attacks(square, occupied),
+            QUEEN => {
+                attacks::bishop_attacks(square, occupied) | attacks::rook_attacks(square, occupied)
+            }
+            KING => steps(&KING_OFFSETS),
+            _ => 0,
+        }
+    }
+
+    /// Returns the squares with a piece on them, as bits `1 << square`.
+    pub fn occupied(&self) -> u64 {
+        (0..64)
+            .filter(|&square| self.get_piece(square) != EMPTY)
+            .fold(0, |bits, square| bits | 1 << square)
+    }
+
+    /// Generates every move that follows the movement rules of the pieces and the variant,
+    /// without checking whether it leaves the mover's own king in check.
+    pub fn pseudo_legal_moves(&self) -> MoveList {
+        let mut moves = self.piece_moves();
+        self.variant.adjust_moves(self, &mut moves);
+        moves
+    }
+
+    /// Generates every move that follows the standard movement rules of the pieces, whatever
+    /// the variant.
+    pub fn piece_moves(&self) -> MoveList {
+        rules::piece_moves(self)
+    }
+
+    /// The castling right `mv` would castle with, as an index into `castling_rooks`, if it is
+    /// a castling move.
+    fn castling_index(&self, mv: Move) -> Option<usize> {
+        rules::castling_index(self, mv)
+    }
+
+    /// Returns `true` if `mv` castles.
+    pub fn is_castling(&self, mv: Move) -> bool {
+        self.castling_index(mv).is_some()
+    }
+
+    /// Returns `true` if `mv` castles kingside (which only makes sense if it castles).
+    pub fn is_kingside_castling(&self, mv: Move) -> bool {
+        self.castling_index(mv)
+            .is_some_and(|index| index.is_multiple_of(2))
+    }
+
+    /// Returns `true` if `mv` captures a piece, en passant or otherwise.
+    pub fn is_capture(&self, mv: Move) -> bool {
+        (self.get_piece(mv.to) != EMPTY && !self.is_castling(mv))
+            || (GET_NUM(self.get_piece(mv.from)) == PAWN && mv.to == self.en_passant)
+    }
+
+    /// Plays `mv` on the board without checking that it is legal, updating the castling rights,
+    /// en passant square and move counter, and whatever else the variant changes.
+    pub fn apply_move(&mut self, mv: Move) {
+        let before = *self;
+        rules::make_move(self, mv);
+        self.variant.after_move(&before, mv, self);
+        debug_assert_eq!(
+            self.validate(),
+            Ok(()),
+            "after {} from {}",
+            mv,
+            before.fen()
+        );
+    }
+
+    /// Plays `mv` as `apply_move` does, returning what `unmake_move` needs to take it back.
+    pub fn make_move(&mut self, mv: Move) -> Undo {
+        let undo = Undo(*self);
+        self.apply_move(mv);
+        undo
+    }
+
+    /// Takes back the move `undo` was made for, restoring the position exactly, Zobrist key and
+    /// all.
+    pub fn unmake_move(&mut self, undo: Undo) {
+        *self = undo.0;
+    }
+}
+
+/// The board the core's rules play on, with its size set by the variant.
+impl Board for ChessBoard {
+    fn piece(&self, square: u8) -> u8 {
+        self.get_piece(square)
+    }
+
+    fn set_piece(&mut self, square: u8, piece: u8) {
+        ChessBoard::set_piece(self, square, piece);
+    }
+
+    fn side_to_move(&self) -> u8 {
+        ChessBoard::side_to_move(self)
+    }
+
+    fn next_turn(&mut self) {
+        self.moves += 1;
+    }
+
+    fn castling(&self) -> u8 {
+        self.castling
+    }
+
+    fn set_castling(&mut self, castling: u8) {
+        self.castling = castling;
+    }
+
+    fn en_passant(&self) -> u8 {
+        self.en_passant
+    }
+
+    fn set_en_passant(&mut self, square: u8) {
+        self.en_passant = square;
+    }
+
+    fn halfmoves(&self) -> u16 {
+        self.halfmoves
+    }
+
+    fn set_halfmoves(&mut self, halfmoves: u16) {
+        self.halfmoves = halfmoves;
+    }
+
+    fn castling_rooks(&self) -> [u8; 4] {
+        self.castling_rooks
+    }
+
+    fn chess960(&self) -> bool {
+        self.chess960
+    }
+
+    fn dimensions(&self) -> (u8, u8) {
+        self.variant.dimensions()
+    }
+}
+
+#[test]
+fn test_legal_moves() {
+    let board = ChessBoard::new();
+    assert_eq!(board.perft(1), 20);
+    assert_eq!(board.perft(2), 400);
+    assert_eq!(board.perft(3), 8902);
+    assert_eq!(board.perft(4), 197281);
+
+    // Fool's mate leaves white without a single legal move.
+    let mut board = ChessBoard::new();
+    for (from, to) in [(13, 21), (52, 36), (14, 30), (59, 31)] {
+        board.apply_move(Move::new(from, to));
+    }
+    assert!(board.is_in_check(WHITE));
+    assert!(board.legal_moves().is_empty());
+}
+
+#[test]
+fn test_describe_outcome() {
+    use crate::variant::by_name;
+
+    let after = |variant: &str, fen: &str, san: &str| {
+        let variant = by_name(variant).unwrap();
+        let mut board = ChessBoard::from_variant_fen(fen, variant).unwrap();
+        assert_eq!(board.describe_outcome(), None);
+        board.apply_move(board.parse_move(san).unwrap());
+        board.describe_outcome().unwrap()
+    };
+    assert_eq!(
+        after("racingkings", "8/K7/8/8/8/8/k7/8 w - - 0 1", "Kb8"),
+        "A king reached the last rank. White wins."
+    );
+    assert_eq!(
+        after("antichess", "8/8/8/8/8/8/1p6/R7 b - - 0 1", "bxa1=Q"),
+        "No pieces left. White wins."
+    );
+    assert_eq!(
+        after(
+            "threecheck",
+            "rnbqkb1r/pppp1ppp/5n2/4p3/2B1P3/8/PPPP1PPP/RNBQK1NR w KQkq - 1+3 0 4",
+            "Bxf7+"
+        ),
+        "Three checks. White wins."
+    );
+    assert_eq!(
+        after("standard", "7k/8/6K1/8/8/8/8/5Q2 w - - 0 1", "Qf7"),
+        "Stalemate. The game is drawn."
+    );
+}
+
+#[test]
+fn test_check_move() {
+    let mut board = ChessBoard::new();
+    let reject = |board: &ChessBoard, from: u8, to: u8| board.check_move(Move::new(from, to));
+
+    assert_eq!(reject(&board, 28, 36), Err(MoveRejection::NoPiece(28)));
+    assert_eq!(
+        reject(&board, 52, 36),
+        Err(MoveRejection::NotYourTurn(WHITE))
+    );
+    assert_eq!(reject(&board, 0, 8), Err(MoveRejection::CaptureOwnPiece(8)));
+    assert_eq!(
+        reject(&board, 6, 36),
+        Err(MoveRejection::CannotReach {
+            piece: KNIGHT,
+            to: 36
+        })
+    );
+    assert_eq!(reject(&board, 4, 6), Err(MoveRejection::CaptureOwnPiece(6)));
+    assert_eq!(
+        MoveRejection::CannotReach {
+            piece: KNIGHT,
+            to: 36
+        }
+        .to_string(),
+        "That knight can't reach e5."
+    );
+
+    // 1. d4 e6 2. Nc3 Bb4 pins the knight to the king.
+    let mut pinned = board;
+    for (from, to) in [(11, 27), (52, 44), (1, 18), (61, 25)] {
+        assert_eq!(pinned.check_move(Move::new(from, to)), Ok(()));
+        pinned.apply_move(Move::new(from, to));
+    }
+    assert_eq!(
+        reject(&pinned, 18, 28),
+        Err(MoveRejection::LeavesKingInCheck)
+    );
+
+    // 1. e4 e5 2. Qh5 Nc6 3. Bc4 d6 4. Qxf7+ leaves black in check.
+    for (from, to) in [
+        (12, 28),
+        (52, 36),
+        (3, 39),
+        (57, 42),
+        (5, 26),
+        (51, 43),
+        (39, 53),
+    ] {
+        assert_eq!(board.check_move(Move::new(from, to)), Ok(()));
+        board.apply_move(Move::new(from, to));
+    }
+    assert_eq!(reject(&board, 48, 40), Err(MoveRejection::StillInCheck));
+    assert_eq!(reject(&board, 60, 53), Err(MoveRejection::StillInCheck));
+    assert_eq!(
+        reject(&board, 60, 44),
+        Err(MoveRejection::CannotReach {
+            piece: KING,
+            to: 44
+        })
+    );
+}
@@ -0,0 +1,692 @@
+//! The engine services of `proto/freight_chess.proto`, over gRPC and gRPC-Web: each call is a
+//! `POST` to `/freight_chess.Engine/{method}` whose body is the request message in a gRPC frame,
+//! answered with the reply message and `grpc-status`. Native gRPC clients call `serve_http2`
+//! over HTTP/2, where the status comes in HTTP trailers; it needs the `grpc` feature and its
+//! async runtime. Browsers call `serve` over plain HTTP/1.1 as gRPC-Web, which carries the status
+//! in a final frame of the body instead. See `docs/grpc.md`.
+//!
+//! The messages are few and small, so they are encoded and decoded here by hand rather than
+//! generated from the `.proto`.
+
+use std::io::Error;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use tiny_http::{Header, Method, Response, Server};
+#[cfg(feature = "grpc")]
+use tracing::Instrument;
+
+use crate::search::MAX_DEPTH;
+use crate::variant::{self, Variant};
+use crate::{ChessBoard, ChessErr};
+
+/// How many calls are handled at the same time.
+const WORKERS: usize = 4;
+
+/// The path prefix of every call.
+const SERVICE: &str = "/freight_chess.Engine/";
+
+/// The depth `Analyze` searches to unless asked otherwise, and the deepest it will go.
+const DEFAULT_DEPTH: u32 = 4;
+const MAX_ANALYZE_DEPTH: u32 = 6;
+
+/// The longest `BestMove` may be asked to think.
+const MAX_MOVETIME_MS: u32 = 60_000;
+
+/// The gRPC status codes the server answers with.
+const OK: u32 = 0;
+const INVALID_ARGUMENT: u32 = 3;
+const UNIMPLEMENTED: u32 = 12;
+
+/// The headers a browser may send and read across origins.
+const ALLOWED_HEADERS: &str = "content-type, x-grpc-web, x-user-agent, grpc-timeout";
+const EXPOSED_HEADERS: &str = "grpc-status, grpc-message";
+
+/// A failed call: its gRPC status code and a message for the caller.
+#[derive(Debug, PartialEq)]
+struct Status {
+    code: u32,
+    message: String,
+}
+
+fn invalid(message: impl Into<String>) -> Status {
+    Status {
+        code: INVALID_ARGUMENT,
+        message: message.into(),
+    }
+}
+
+/// The value of one field of a protobuf message.
+#[derive(Clone, Copy)]
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+/// The fields of a decoded protobuf message, by number, in the order they came. Fields of the
+/// fixed-width types don't appear in these messages and are skipped.
+struct Fields<'a>(Vec<(u32, Value<'a>)>);
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, Status> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes
+            .split_first()
+            .ok_or_else(|| invalid("The message ends in the middle of a number."))?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("The message holds a number longer than 64 bits."))
+}
+
+impl<'a> Fields<'a> {
+    fn decode(mut bytes: &'a [u8]) -> Result<Fields<'a>, Status> {
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            let key = read_varint(&mut bytes)?;
+            let skip = match key & 7 {
+                0 => {
+                    fields.push(((key >> 3) as u32, Value::Varint(read_varint(&mut bytes)?)));
+                    0
+                }
+                1 => 8,
+                2 => {
+                    let length = read_varint(&mut bytes)? as usize;
+                    if length > bytes.len() {
+                        return Err(invalid("The message ends in the middle of a field."));
+                    }
+                    let (value, rest) = bytes.split_at(length);
+                    fields.push(((key >> 3) as u32, Value::Bytes(value)));
+                    bytes = rest;
+                    0
+                }
+                5 => 4,
+                _ => return Err(invalid("The message isn't valid protobuf.")),
+            };
+            if skip > bytes.len() {
+                return Err(invalid("The message ends in the middle of a field."));
+            }
+            bytes = &bytes[skip..];
+        }
+        Ok(Fields(fields))
+    }
+
+    /// The last value of field `number`, as protobuf takes it when a field is repeated.
+    fn get(&self, number: u32) -> Option<Value<'a>> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(field, _)| *field == number)
+            .map(|&(_, value)| value)
+    }
+
+    fn uint(&self, number: u32) -> Result<u64, Status> {
+        match self.get(number) {
+            None => Ok(0),
+            Some(Value::Varint(value)) => Ok(value),
+            Some(Value::Bytes(_)) => Err(invalid(format!("Field {} must be a number.", number))),
+        }
+    }
+
+    fn bytes(&self, number: u32) -> Result<&'a [u8], Status> {
+        match self.get(number) {
+            None => Ok(&[]),
+            Some(Value::Bytes(bytes)) => Ok(bytes),
+            Some(Value::Varint(_)) => Err(invalid(format!("Field {} must be a string.", number))),
+        }
+    }
+
+    fn string(&self, number: u32) -> Result<&'a str, Status> {
+        std::str::from_utf8(self.bytes(number)?)
+            .map_err(|_| invalid(format!("Field {} must be UTF-8.", number)))
+    }
+
+    fn message(&self, number: u32) -> Result<Fields<'a>, Status> {
+        Fields::decode(self.bytes(number)?)
+    }
+}
+
+/// Builds a protobuf message field by field, leaving out fields at their default as proto3 does.
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn uint(mut self, number: u32, value: u64) -> Message {
+        if value != 0 {
+            self.varint((number as u64) << 3);
+            self.varint(value);
+        }
+        self
+    }
+
+    fn sint(self, number: u32, value: i32) -> Message {
+        self.uint(number, ((value << 1) ^ (value >> 31)) as u32 as u64)
+    }
+
+    fn bytes(mut self, number: u32, value: &[u8]) -> Message {
+        if !value.is_empty() {
+            self.varint(((number as u64) << 3) | 2);
+            self.varint(value.len() as u64);
+            self.0.extend_from_slice(value);
+        }
+        self
+    }
+
+    fn string(self, number: u32, value: &str) -> Message {
+        self.bytes(number, value.as_bytes())
+    }
+
+    fn strings(self, number: u32, values: &[String]) -> Message {
+        values
+            .iter()
+            .fold(self, |message, value| message.string(number, value))
+    }
+}
+
+fn chess_error(e: ChessErr) -> Status {
+    invalid(e.to_string())
+}
+
+/// The board of a `Position` message.
+fn position(fields: &Fields) -> Result<ChessBoard, Status> {
+    let variant: &'static dyn Variant = match fields.string(2)? {
+        "" => &variant::STANDARD,
+        name => variant::by_name(name).ok_or_else(|| {
+            invalid(format!(
+                "The variant must be one of {}.",
+                variant::names().join(", ")
+            ))
+        })?,
+    };
+    match fields.string(1)? {
+        "" => Ok(ChessBoard::start_of(variant)),
+        fen => ChessBoard::from_variant_fen(fen, variant).map_err(chess_error),
+    }
+}
+
+/// The depth field `number` asks for, `default` when it is 0.
+fn depth(fields: &Fields, number: u32, default: u32, max: u32) -> Result<u32, Status> {
+    match fields.uint(number)? {
+        0 => Ok(default),
+        depth if depth <= max as u64 => Ok(depth as u32),
+        _ => Err(invalid(format!("The depth must be from 1 to {}.", max))),
+    }
+}
+
+fn analyze(request: &Fields) -> Result<Message, Status> {
+    let board = position(&request.message(1)?)?;
+    let depth = depth(request, 2, DEFAULT_DEPTH, MAX_ANALYZE_DEPTH)?;
+    let search = board.search(depth);
+    let pv = board.line_sans(&search.pv);
+    Ok(Message::default()
+        .string(1, pv.first().map_or("", String::as_str))
+        .sint(2, search.score)
+        .strings(3, &pv)
+        .uint(4, search.depth as u64)
+        .uint(5, search.nodes))
+}
+
+fn best_move(request: &Fields) -> Result<Message, Status> {
+    let board = position(&request.message(1)?)?;
+    let nodes = request.uint(3)?;
+    let movetime = match request.uint(4)? {
+        ms if ms <= MAX_MOVETIME_MS as u64 => ms,
+        _ => return Err(invalid("The move time must be at most 60000 milliseconds.")),
+    };
+    // Without a node or time limit, the depth is the only bound, so it is held to what `Analyze`
+    // allows. Every search stops after a minute besides, so no call holds a worker for longer.
+    let depth = match nodes == 0 && movetime == 0 {
+        true => depth(request, 2, DEFAULT_DEPTH, MAX_ANALYZE_DEPTH)?,
+        false => depth(request, 2, MAX_DEPTH, MAX_DEPTH)?,
+    };
+    let nodes = if nodes == 0 { u64::MAX } else { nodes };
+    let movetime = match movetime {
+        0 => MAX_MOVETIME_MS as u64,
+        ms => ms,
+    };
+    let search = board.search_within(depth, nodes, Duration::from_millis(movetime));
+    let reply = Message::default();
+    Ok(match search.best_move {
+        Some(mv) => reply
+            .string(1, &board.san(mv))
+            .string(2, &mv.to_string())
+            .sint(3, search.score),
+        None => reply.sint(3, search.score),
+    })
+}
+
+fn legal_moves(request: &Fields) -> Result<Message, Status> {
+    let board = position(request)?;
+    Ok(Message::default().strings(1, &board.legal_sans()))
+}
+
+fn validate_move(request: &Fields) -> Result<Message, Status> {
+    let mut board = position(&request.message(1)?)?;
+    let reply = Message::default();
+    Ok(match board.parse_move(request.string(2)?) {
+        Ok(mv) => {
+            let san = board.san(mv);
+            board.apply_move(mv);
+            reply.uint(1, 1).string(2, &san).string(3, &board.fen())
+        }
+        Err(ChessErr::BadMove(reason)) => reply
+            .string(4, reason.kind())
+            .string(5, &reason.to_string()),
+        Err(e) => reply.string(4, e.kind()).string(5, &e.to_string()),
+    })
+}
+
+/// Runs the call to `method` with the encoded request `message`, returning the encoded reply.
+fn call(method: &str, message: &[u8]) -> Result<Vec<u8>, Status> {
+    let call: fn(&Fields) -> Result<Message, Status> = match method {
+        "Analyze" => analyze,
+        "BestMove" => best_move,
+        "LegalMoves" => legal_moves,
+        "ValidateMove" => validate_move,
+        _ => {
+            return Err(Status {
+                code: UNIMPLEMENTED,
+                message: format!("There is no method {}.", method),
+            })
+        }
+    };
+    call(&Fields::decode(message)?).map(|reply| reply.0)
+}
+
+/// Wraps `payload` in a gRPC frame: a flag byte (0x80 for trailers) and its length.
+fn frame(flag: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![flag];
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Percent-encodes a `grpc-message`, as the gRPC spec asks.
+fn percent_encode(message: &str) -> String {
+    message
+        .bytes()
+        .map(|byte| match byte {
+            b' '..=b'~' if byte != b'%' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Answers the call at `path`, whose `body` holds the framed request, with the encoded reply.
+/// Both transports frame requests the same way.
+fn answer(path: &str, body: &[u8]) -> Result<Vec<u8>, Status> {
+    match path.strip_prefix(SERVICE) {
+        None => Err(Status {
+            code: UNIMPLEMENTED,
+            message: format!("There is no service at {}.", path),
+        }),
+        Some(method) => match body {
+            [0, a, b, c, d, message @ ..]
+                if u32::from_be_bytes([*a, *b, *c, *d]) as usize == message.len() =>
+            {
+                call(method, message)
+            }
+            [flag, ..] if flag & 1 == 1 => Err(Status {
+                code: UNIMPLEMENTED,
+                message: "Compressed messages aren't supported.".to_string(),
+            }),
+            _ => Err(invalid("The request must be exactly one message.")),
+        },
+    }
+}
+
+/// Handles one gRPC-Web call: `path` names the method and `body` holds the framed request.
+/// Returns the response body, the reply frame (if the call succeeded) followed by the trailers.
+fn handle(path: &str, body: &[u8]) -> Vec<u8> {
+    let result = answer(path, body);
+    let (mut response, status) = match result {
+        Ok(reply) => (
+            frame(0, &reply),
+            Status {
+                code: OK,
+                message: String::new(),
+            },
+        ),
+        Err(status) => (Vec::new(), status),
+    };
+    let trailers = format!(
+        "grpc-status:{}\r\ngrpc-message:{}\r\n",
+        status.code,
+        percent_encode(&status.message)
+    );
+    response.extend(frame(0x80, trailers.as_bytes()));
+    response
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).unwrap()
+}
+
+/// Serves the engine services on `port` until the process is stopped.
+pub fn serve(port: u16) -> Result<(), Error> {
+    let server = Server::http(("0.0.0.0", port)).map_err(Error::other)?;
+    println!("Serving gRPC-Web on port {}.", port);
+    let server = Arc::new(server);
+
+    let workers = (0..WORKERS)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let path = request.url().split('?').next().unwrap_or_default();
+                    let path = path.to_string();
+                    let content_type = request
+                        .headers()
+                        .iter()
+                        .find(|header| header.field.equiv("Content-Type"))
+                        .map(|header| header.value.as_str().to_string())
+                        .unwrap_or_default();
+                    let method = request.method().clone();
+                    let _span =
+                        tracing::info_span!(target: "net", "grpc", %method, %path).entered();
+                    tracing::debug!(target: "net", "{} {}", method, path);
+                    let response = match request.method() {
+                        // Browsers ask before calling across origins.
+                        Method::Options => Response::from_data(Vec::new())
+                            .with_status_code(204)
+                            .with_header(header("Access-Control-Allow-Methods", "POST"))
+                            .with_header(header("Access-Control-Allow-Headers", ALLOWED_HEADERS))
+                            .with_header(header("Access-Control-Max-Age", "86400")),
+                        Method::Post
+                            if content_type.starts_with("application/grpc-web")
+                                && !content_type.starts_with("application/grpc-web-text") =>
+                        {
+                            let mut body = Vec::new();
+                            let response = match request.as_reader().read_to_end(&mut body) {
+                                Ok(_) => handle(&path, &body),
+                                Err(_) => handle(&path, &[]),
+                            };
+                            Response::from_data(response)
+                                .with_header(header("Content-Type", "application/grpc-web+proto"))
+                                .with_header(header(
+                                    "Access-Control-Expose-Headers",
+                                    EXPOSED_HEADERS,
+                                ))
+                        }
+                        Method::Post => Response::from_string(
+                            "Calls must be binary gRPC-Web (application/grpc-web+proto).",
+                        )
+                        .with_status_code(415),
+                        _ => Response::from_string("Calls must be POST requests.")
+                            .with_status_code(405)
+                            .with_header(header("Allow", "POST, OPTIONS")),
+                    };
+                    let response = response.with_header(header("Access-Control-Allow-Origin", "*"));
+                    // The client may have hung up already; there is nobody left to tell.
+                    let _ = request.respond(response);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+/// The largest request body the HTTP/2 server reads, far more than any of the messages needs.
+#[cfg(feature = "grpc")]
+const MAX_REQUEST: usize = 1 << 20;
+
+/// Serves the engine services as native gRPC, over HTTP/2 without TLS, on `port` until the
+/// process is stopped. Calls are searched on at most `WORKERS` threads at a time, as over
+/// gRPC-Web.
+#[cfg(feature = "grpc")]
+pub fn serve_http2(port: u16) -> Result<(), Error> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+        println!("Serving gRPC on port {}.", port);
+        accept(listener).await
+    })
+}
+
+/// Takes connections from `listener`, each served on a task of its own.
+#[cfg(feature = "grpc")]
+async fn accept(listener: tokio::net::TcpListener) -> Result<(), Error> {
+    let workers = Arc::new(tokio::sync::Semaphore::new(WORKERS));
+    loop {
+        let (socket, address) = listener.accept().await?;
+        let workers = Arc::clone(&workers);
+        let connection = async move {
+            let mut connection = match h2::server::handshake(socket).await {
+                Ok(connection) => connection,
+                Err(e) => return tracing::debug!(target: "net", "{}", e),
+            };
+            while let Some(call) = connection.accept().await {
+                let (request, respond) = match call {
+                    Ok(call) => call,
+                    Err(e) => return tracing::debug!(target: "net", "{}", e),
+                };
+                let workers = Arc::clone(&workers);
+                let call = async move {
+                    if let Err(e) = respond_http2(request, respond, &workers).await {
+                        tracing::debug!(target: "net", "{}", e);
+                    }
+                };
+                tokio::spawn(call.in_current_span());
+            }
+        };
+        tokio::spawn(connection.instrument(tracing::info_span!(target: "net", "grpc", %address)));
+    }
+}
+
+/// Answers one gRPC call, with the reply message and then `grpc-status` in the trailers.
+#[cfg(feature = "grpc")]
+async fn respond_http2(
+    request: http::Request<h2::RecvStream>,
+    mut respond: h2::server::SendResponse<bytes::Bytes>,
+    workers: &tokio::sync::Semaphore,
+) -> Result<(), h2::Error> {
+    let path = request.uri().path().to_string();
+    tracing::debug!(target: "net", "{} {}", request.method(), path);
+    let content_type = request
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    // Whatever isn't a gRPC call gets a plain HTTP error, as the gRPC spec asks.
+    let refuse = match *request.method() {
+        http::Method::POST if content_type.starts_with("application/grpc") => None,
+        http::Method::POST => Some(http::StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        _ => Some(http::StatusCode::METHOD_NOT_ALLOWED),
+    };
+    if let Some(status) = refuse {
+        let response = http::Response::builder().status(status).body(()).unwrap();
+        respond.send_response(response, true)?;
+        return Ok(());
+    }
+
+    let mut body = request.into_body();
+    let mut message = Vec::new();
+    let mut result = Ok(());
+    while let Some(data) = body.data().await {
+        let data = data?;
+        body.flow_control().release_capacity(data.len())?;
+        message.extend_from_slice(&data);
+        if message.len() > MAX_REQUEST {
+            result = Err(invalid("The request is too large."));
+            break;
+        }
+    }
+    let result = match result {
+        Ok(()) => {
+            // Searches run on the blocking threads, so they don't hold up other connections.
+            let _worker = workers
+                .acquire()
+                .await
+                .expect("The semaphore is never closed.");
+            tokio::task::spawn_blocking(move || answer(&path, &message))
+                .await
+                .expect("Calls don't panic.")
+        }
+        Err(status) => Err(status),
+    };
+
+    let response = http::Response::builder()
+        .header(http::header::CONTENT_TYPE, "application/grpc+proto")
+        .body(())
+        .unwrap();
+    let mut stream = respond.send_response(response, false)?;
+    let status = match result {
+        Ok(reply) => {
+            stream.send_data(frame(0, &reply).into(), false)?;
+            Status {
+                code: OK,
+                message: String::new(),
+            }
+        }
+        Err(status) => status,
+    };
+    let mut trailers = http::HeaderMap::new();
+    trailers.insert("grpc-status", status.code.into());
+    if let Ok(message) = percent_encode(&status.message).parse() {
+        trailers.insert("grpc-message", message);
+    }
+    stream.send_trailers(trailers)
+}
+
+#[test]
+fn test_grpc() {
+    let position = |fen: &str| Message::default().string(1, fen);
+    let request = |method: &str, message: Message| {
+        let response = handle(&format!("{}{}", SERVICE, method), &frame(0, &message.0));
+        assert_eq!(response[0], 0, "{:?}", String::from_utf8_lossy(&response));
+        let size = u32::from_be_bytes(response[1..5].try_into().unwrap()) as usize;
+        let trailers = &response[5 + size..];
+        assert_eq!(&trailers[5..], b"grpc-status:0\r\ngrpc-message:\r\n");
+        response[5..5 + size].to_vec()
+    };
+
+    let moves = request("LegalMoves", Message::default());
+    let moves = Fields::decode(&moves).unwrap();
+    assert_eq!(moves.0.len(), 20);
+    assert!(moves.0.iter().all(|&(number, _)| number == 1));
+
+    let mate = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+    let reply = request(
+        "Analyze",
+        Message::default().bytes(1, &position(mate).0).uint(2, 2),
+    );
+    let analysis = Fields::decode(&reply).unwrap();
+    assert_eq!(analysis.string(1).unwrap(), "Ra8#");
+    assert!((1..=2).contains(&analysis.uint(4).unwrap()));
+    let score = analysis.uint(2).unwrap() as i64;
+    assert!((score >> 1) ^ -(score & 1) > 20_000);
+
+    let reply = request(
+        "BestMove",
+        Message::default()
+            .bytes(1, &position(mate).0)
+            .uint(3, 1_000),
+    );
+    let best = Fields::decode(&reply).unwrap();
+    assert_eq!(
+        (best.string(1).unwrap(), best.string(2).unwrap()),
+        ("Ra8#", "a1a8")
+    );
+
+    let validate = |mv: &str| {
+        let message = Message::default().bytes(1, &position("").0).string(2, mv);
+        request("ValidateMove", message)
+    };
+    let reply = validate("Nf3");
+    let valid = Fields::decode(&reply).unwrap();
+    assert_eq!(valid.uint(1).unwrap(), 1);
+    assert_eq!(valid.string(2).unwrap(), "Nf3");
+    assert!(valid
+        .string(3)
+        .unwrap()
+        .starts_with("rnbqkbnr/pppppppp/8/8/8/5N2/"));
+    let reply = validate("Nf6");
+    let invalid = Fields::decode(&reply).unwrap();
+    assert_eq!(invalid.uint(1).unwrap(), 0);
+    assert!(!invalid.string(4).unwrap().is_empty() && !invalid.string(5).unwrap().is_empty());
+
+    // Errors come back as trailers alone.
+    let bad_fen = Message::default().string(1, "not a position");
+    let response = handle("/freight_chess.Engine/LegalMoves", &frame(0, &bad_fen.0));
+    assert_eq!(response[0], 0x80);
+    assert!(response.ends_with(b"\r\n") && response[5..].starts_with(b"grpc-status:3\r\n"));
+    let response = handle("/freight_chess.Engine/Resign", &frame(0, &[]));
+    assert!(
+        response[5..].ends_with(b"grpc-status:12\r\ngrpc-message:There is no method Resign.\r\n")
+    );
+    assert!(handle(SERVICE, &[0, 0, 0])[5..].starts_with(b"grpc-status:3\r\n"));
+    // Without a node or time limit, BestMove may only go as deep as Analyze.
+    let unbounded = Message::default().bytes(1, &position("").0).uint(2, 64);
+    let response = handle("/freight_chess.Engine/BestMove", &frame(0, &unbounded.0));
+    assert!(response[5..].starts_with(b"grpc-status:3\r\n"));
+}
+
+#[cfg(feature = "grpc")]
+#[test]
+fn test_grpc_http2() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(accept(listener));
+        let socket = tokio::net::TcpStream::connect(address).await.unwrap();
+        let (client, connection) = h2::client::handshake(socket).await.unwrap();
+        tokio::spawn(connection);
+
+        let call = |method: &str, message: Message| {
+            let request = http::Request::post(format!("http://{}{}{}", address, SERVICE, method))
+                .header("content-type", "application/grpc")
+                .header("te", "trailers")
+                .body(())
+                .unwrap();
+            let client = client.clone();
+            async move {
+                let mut client = client.ready().await.unwrap();
+                let (response, mut stream) = client.send_request(request, false).unwrap();
+                stream.send_data(frame(0, &message.0).into(), true).unwrap();
+                let response = response.await.unwrap();
+                assert_eq!(response.status(), 200);
+                let mut body = response.into_body();
+                let mut reply = Vec::new();
+                while let Some(data) = body.data().await {
+                    reply.extend_from_slice(&data.unwrap());
+                }
+                let trailers = body.trailers().await.unwrap().unwrap();
+                (reply, trailers["grpc-status"].to_str().unwrap().to_string())
+            }
+        };
+
+        let (reply, status) = call("LegalMoves", Message::default()).await;
+        assert_eq!(status, "0");
+        assert_eq!(reply[..5], [0, 0, 0, 0, reply.len() as u8 - 5]);
+        assert_eq!(Fields::decode(&reply[5..]).unwrap().0.len(), 20);
+
+        let mate = Message::default().string(1, "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let (reply, status) = call("BestMove", Message::default().bytes(1, &mate.0)).await;
+        assert_eq!(status, "0");
+        assert_eq!(
+            Fields::decode(&reply[5..]).unwrap().string(1).unwrap(),
+            "Ra8#"
+        );
+
+        let (reply, status) = call("Resign", Message::default()).await;
+        assert_eq!((reply.len(), status.as_str()), (0, "12"));
+    });
+}
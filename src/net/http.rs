@@ -0,0 +1,632 @@
+//! The HTTP REST API. Each game lives on the server under a numeric id, and clients create
+//! games, play moves and ask for analysis with the JSON requests documented in `docs/http.md`.
+//! `/metrics` reports how busy the server is in the Prometheus text format, for monitoring.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write;
+use std::io::Error;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::movegen::{color_name, MoveRejection};
+use crate::variant::{self, Variant};
+use crate::{ChessBoard, ChessErr};
+
+/// How many requests are handled at the same time.
+const WORKERS: usize = 4;
+
+/// The depth `/analyze` searches to unless asked otherwise, and the deepest it will go.
+const DEFAULT_DEPTH: u64 = 4;
+const MAX_DEPTH: u64 = 6;
+
+/// The window `freight_chess_moves_per_second` averages over.
+const MOVE_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// How long a game is kept after it was last looked at or played in: a finished one, for its
+/// players to see how it ended, and one still going, in case its players come back.
+const FINISHED_TTL: Duration = Duration::from_secs(10 * 60);
+const IDLE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The most games kept at once.
+const MAX_GAMES: usize = 10_000;
+
+/// One game created through the API.
+struct Game {
+    board: ChessBoard,
+    /// The position the game started from.
+    start: String,
+    /// Every move played so far, in SAN.
+    moves: Vec<String>,
+    /// When the game was last looked at or played in.
+    touched: Instant,
+}
+
+/// Every game served, by id.
+#[derive(Default)]
+struct Games {
+    games: HashMap<u64, Game>,
+    next_id: u64,
+    metrics: Metrics,
+}
+
+/// What the server has done since it started, for `/metrics`.
+#[derive(Default)]
+struct Metrics {
+    requests: u64,
+    /// The requests being handled or waiting their turn.
+    in_flight: u64,
+    /// The requests answered with an error, by `reason`.
+    errors: BTreeMap<String, u64>,
+    moves: u64,
+    /// When each move of the last `MOVE_RATE_WINDOW` was played.
+    recent_moves: VecDeque<Instant>,
+    search_nodes: u64,
+    search_seconds: f64,
+    /// The speed of the latest `/analyze` search, in positions per second.
+    last_nps: f64,
+}
+
+type Reply = (u16, Value);
+
+fn error(status: u16, reason: &str, message: &str) -> Reply {
+    (status, json!({ "reason": reason, "message": message }))
+}
+
+fn chess_error(e: ChessErr) -> Reply {
+    match e {
+        ChessErr::BadMove(reason) => error(400, reason.kind(), &reason.to_string()),
+        e => error(400, e.kind(), &e.to_string()),
+    }
+}
+
+fn not_found() -> Reply {
+    error(404, "not_found", "There is nothing here.")
+}
+
+/// Reads a request body, which must be a JSON object if there is one.
+fn request(body: &str) -> Result<Value, Reply> {
+    if body.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    match serde_json::from_str::<Value>(body) {
+        Ok(value) if value.is_object() => Ok(value),
+        _ => Err(error(
+            400,
+            "invalid_json",
+            "Request bodies must be JSON objects.",
+        )),
+    }
+}
+
+/// The variant a request asks for with `variant`, standard chess if it doesn't.
+fn variant(request: &Value) -> Result<&'static dyn Variant, Reply> {
+    match &request["variant"] {
+        Value::Null => Ok(&variant::STANDARD),
+        name => name.as_str().and_then(variant::by_name).ok_or_else(|| {
+            let message = format!(
+                "\"variant\" must be one of {}.",
+                variant::names().join(", ")
+            );
+            error(400, "invalid_request", &message)
+        }),
+    }
+}
+
+/// Describes a position the way every endpoint reports it.
+fn position(board: &ChessBoard) -> Value {
+    let (status, result) = match board.outcome() {
+        Some((result, reason)) => (reason, Some(result)),
+        None => ("ongoing", None),
+    };
+    json!({
+        "fen": board.fen(),
+        "variant": board.variant.name(),
+        "side_to_move": color_name(board.side_to_move()),
+        "legal_moves": if result.is_some() { Vec::new() } else { board.legal_sans() },
+        "status": status,
+        "result": result,
+    })
+}
+
+/// Answers `/analyze`, with the positions searched and the seconds it took, if it searched.
+/// It needs none of the games, so the server searches without holding them.
+fn analyze(body: &str) -> (Reply, Option<(u64, f64)>) {
+    let request = match request(body) {
+        Ok(request) => request,
+        Err(reply) => return (reply, None),
+    };
+    let variant = match variant(&request) {
+        Ok(variant) => variant,
+        Err(reply) => return (reply, None),
+    };
+    let board = match request["fen"].as_str() {
+        Some(fen) => match ChessBoard::from_variant_fen(fen, variant) {
+            Ok(board) => board,
+            Err(e) => return (chess_error(e), None),
+        },
+        None => {
+            let message = "Send the position as \"fen\".";
+            return (error(400, "invalid_request", message), None);
+        }
+    };
+    let depth = match &request["depth"] {
+        Value::Null => DEFAULT_DEPTH,
+        depth => match depth.as_u64() {
+            Some(depth @ 1..=MAX_DEPTH) => depth,
+            _ => {
+                let message = "\"depth\" must be a number from 1 to 6.";
+                return (error(400, "invalid_request", message), None);
+            }
+        },
+    };
+
+    let started = Instant::now();
+    let search = board.search(depth as u32);
+    let seconds = started.elapsed().as_secs_f64();
+    let pv = board.line_sans(&search.pv);
+    let mut analysis = position(&board);
+    analysis["best_move"] = json!(pv.first());
+    analysis["score"] = json!(search.score);
+    analysis["pv"] = json!(pv);
+    analysis["depth"] = json!(search.depth);
+    analysis["nodes"] = json!(search.nodes);
+    ((200, analysis), Some((search.nodes, seconds)))
+}
+
+impl Game {
+    fn state(&self, id: u64) -> Value {
+        let mut state = position(&self.board);
+        state["id"] = json!(id);
+        state["start"] = json!(self.start);
+        state["moves"] = json!(self.moves);
+        state
+    }
+}
+
+impl Games {
+    /// Handles one request about the games. `answer` handles `POST /analyze` itself, since it
+    /// searches without holding the games.
+    fn dispatch(&mut self, method: &Method, path: &str, body: &str) -> Reply {
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<&str>>();
+        match (method, segments.as_slice()) {
+            (Method::Post, ["games"]) => {
+                let request = match request(body) {
+                    Ok(request) => request,
+                    Err(reply) => return reply,
+                };
+                let variant = match variant(&request) {
+                    Ok(variant) => variant,
+                    Err(reply) => return reply,
+                };
+                let board = match request["fen"].as_str() {
+                    Some(fen) => match ChessBoard::from_variant_fen(fen, variant) {
+                        Ok(board) => board,
+                        Err(e) => return chess_error(e),
+                    },
+                    None => ChessBoard::start_of(variant),
+                };
+                self.evict(Instant::now());
+                if self.games.len() >= MAX_GAMES {
+                    let message = "The server hosts as many games as it can. Try again later.";
+                    return error(503, "too_many_games", message);
+                }
+                let id = self.next_id;
+                self.next_id += 1;
+                let game = Game {
+                    board,
+                    start: board.fen(),
+                    moves: Vec::new(),
+                    touched: Instant::now(),
+                };
+                let state = game.state(id);
+                self.games.insert(id, game);
+                (201, state)
+            }
+            (Method::Get, ["games", id]) => match self.game(id) {
+                Some((id, game)) => (200, game.state(id)),
+                None => not_found(),
+            },
+            (Method::Post, ["games", id, "moves"]) => {
+                let request = match request(body) {
+                    Ok(request) => request,
+                    Err(reply) => return reply,
+                };
+                let (id, game) = match self.game(id) {
+                    Some(found) => found,
+                    None => return not_found(),
+                };
+                if game.board.outcome().is_some() {
+                    let over = MoveRejection::GameOver;
+                    return error(409, over.kind(), &over.to_string());
+                }
+                let mv = match request["move"]
+                    .as_str()
+                    .map(|text| game.board.parse_move(text))
+                {
+                    Some(Ok(mv)) => mv,
+                    Some(Err(e)) => return chess_error(e),
+                    None => return error(400, "invalid_request", "Send the move as \"move\"."),
+                };
+                game.moves.push(game.board.san(mv));
+                game.board.apply_move(mv);
+                let state = game.state(id);
+                self.metrics.count_move();
+                (200, state)
+            }
+            (_, ["games"]) | (_, ["games", _]) | (_, ["games", _, "moves"]) | (_, ["analyze"]) => {
+                error(
+                    405,
+                    "method_not_allowed",
+                    "That method isn't supported here.",
+                )
+            }
+            _ => not_found(),
+        }
+    }
+
+    fn game(&mut self, id: &str) -> Option<(u64, &mut Game)> {
+        let id = id.parse::<u64>().ok()?;
+        let game = self.games.get_mut(&id)?;
+        game.touched = Instant::now();
+        Some((id, game))
+    }
+
+    /// Forgets the games that have gone untouched for too long as of `now`.
+    fn evict(&mut self, now: Instant) {
+        self.games.retain(|_, game| {
+            let idle = now.saturating_duration_since(game.touched);
+            match game.board.outcome() {
+                Some(_) => idle <= FINISHED_TTL,
+                None => idle <= IDLE_TTL,
+            }
+        });
+    }
+
+    /// Describes the server for `/metrics`, in the Prometheus text format.
+    fn metrics(&mut self) -> String {
+        self.evict(Instant::now());
+        self.metrics.forget_old_moves();
+        let metrics = &self.metrics;
+        let active = self
+            .games
+            .values()
+            .filter(|game| game.board.outcome().is_none())
+            .count();
+        let errors = metrics
+            .errors
+            .iter()
+            .map(|(reason, &count)| (format!("{{reason=\"{}\"}}", reason), count as f64))
+            .collect::<Vec<(String, f64)>>();
+        let one = |value: f64| vec![(String::new(), value)];
+
+        let mut text = String::new();
+        for (name, kind, help, samples) in [
+            (
+                "freight_chess_games",
+                "gauge",
+                "Games hosted.",
+                one(self.games.len() as f64),
+            ),
+            (
+                "freight_chess_games_active",
+                "gauge",
+                "Games hosted that aren't over.",
+                one(active as f64),
+            ),
+            (
+                "freight_chess_moves_total",
+                "counter",
+                "Moves played.",
+                one(metrics.moves as f64),
+            ),
+            (
+                "freight_chess_moves_per_second",
+                "gauge",
+                "Moves played per second over the last minute.",
+                one(metrics.recent_moves.len() as f64 / MOVE_RATE_WINDOW.as_secs_f64()),
+            ),
+            (
+                "freight_chess_search_nodes_total",
+                "counter",
+                "Positions searched by /analyze.",
+                one(metrics.search_nodes as f64),
+            ),
+            (
+                "freight_chess_search_seconds_total",
+                "counter",
+                "Seconds spent searching for /analyze.",
+                one(metrics.search_seconds),
+            ),
+            (
+                "freight_chess_search_nps",
+                "gauge",
+                "Positions per second of the latest /analyze search.",
+                one(metrics.last_nps),
+            ),
+            (
+                "freight_chess_http_requests_total",
+                "counter",
+                "API requests answered, not counting /metrics.",
+                one(metrics.requests as f64),
+            ),
+            (
+                "freight_chess_http_requests_in_flight",
+                "gauge",
+                "Requests being handled or waiting their turn.",
+                one(metrics.in_flight as f64),
+            ),
+            (
+                "freight_chess_http_errors_total",
+                "counter",
+                "API requests answered with an error, by reason.",
+                errors,
+            ),
+        ] {
+            let _ = writeln!(text, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(text, "{}{} {}", name, labels, value);
+            }
+        }
+        text
+    }
+}
+
+impl Metrics {
+    /// Records the reply to an API request.
+    fn count_reply(&mut self, reply: &Reply) {
+        self.requests += 1;
+        if reply.0 >= 400 {
+            let reason = reply.1["reason"].as_str().unwrap_or("unknown");
+            *self.errors.entry(reason.to_string()).or_default() += 1;
+        }
+    }
+
+    /// Records an `/analyze` search of `nodes` positions that took `seconds`.
+    fn count_search(&mut self, nodes: u64, seconds: f64) {
+        self.search_nodes += nodes;
+        self.search_seconds += seconds;
+        self.last_nps = nodes as f64 / seconds.max(1e-9);
+    }
+
+    /// Records a move played now.
+    fn count_move(&mut self) {
+        self.moves += 1;
+        self.recent_moves.push_back(Instant::now());
+        self.forget_old_moves();
+    }
+
+    /// Drops the moves played longer than `MOVE_RATE_WINDOW` ago.
+    fn forget_old_moves(&mut self) {
+        while let Some(played) = self.recent_moves.front() {
+            if played.elapsed() <= MOVE_RATE_WINDOW {
+                break;
+            }
+            self.recent_moves.pop_front();
+        }
+    }
+}
+
+/// Handles one request, counting it and any error for `/metrics`. `path` excludes the query
+/// string, and `body` is the raw request body. `/analyze` searches with the games unlocked, so
+/// that other requests don't wait for the search.
+fn answer(games: &Mutex<Games>, method: &Method, path: &str, body: &str) -> Reply {
+    let segments = path.split('/').filter(|segment| !segment.is_empty());
+    let (reply, searched) = match *method == Method::Post && segments.eq(["analyze"]) {
+        true => analyze(body),
+        false => (games.lock().unwrap().dispatch(method, path, body), None),
+    };
+    let mut games = games.lock().unwrap();
+    if let Some((nodes, seconds)) = searched {
+        games.metrics.count_search(nodes, seconds);
+    }
+    games.metrics.count_reply(&reply);
+    reply
+}
+
+/// Serves the REST API on `port` until the process is stopped.
+pub fn serve(port: u16) -> Result<(), Error> {
+    let server = Server::http(("0.0.0.0", port)).map_err(Error::other)?;
+    println!("Serving the HTTP API on port {}.", port);
+    let server = Arc::new(server);
+    let games = Arc::new(Mutex::new(Games::default()));
+
+    let workers = (0..WORKERS)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let games = Arc::clone(&games);
+            thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    games.lock().unwrap().metrics.in_flight += 1;
+                    let path = request.url().split('?').next().unwrap_or_default();
+                    let path = path.to_string();
+                    let response = if *request.method() == Method::Get && path == "/metrics" {
+                        let text = games.lock().unwrap().metrics();
+                        Response::from_string(text).with_header(
+                            Header::from_bytes("Content-Type", "text/plain; version=0.0.4")
+                                .unwrap(),
+                        )
+                    } else {
+                        let mut body = String::new();
+                        let (status, value) = match request.as_reader().read_to_string(&mut body) {
+                            Ok(_) => {
+                                let method = request.method().clone();
+                                let _span =
+                                    tracing::info_span!(target: "net", "http", %method, %path)
+                                        .entered();
+                                tracing::debug!(target: "net", "{} {}", method, path);
+                                answer(&games, &method, &path, &body)
+                            }
+                            Err(_) => error(400, "invalid_request", "The body must be UTF-8."),
+                        };
+                        Response::from_string(value.to_string())
+                            .with_status_code(status)
+                            .with_header(
+                                Header::from_bytes("Content-Type", "application/json").unwrap(),
+                            )
+                    };
+                    // The client may have hung up already; there is nobody left to tell.
+                    let _ = request.respond(response);
+                    games.lock().unwrap().metrics.in_flight -= 1;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+#[test]
+fn test_http_api() {
+    let games = Mutex::new(Games::default());
+    let route = |method: &Method, path: &str, body: &str| answer(&games, method, path, body);
+
+    let (status, game) = route(&Method::Post, "/games", "");
+    assert_eq!(status, 201);
+    assert_eq!(game["id"], 0);
+    assert_eq!(game["status"], "ongoing");
+    assert_eq!(game["legal_moves"].as_array().unwrap().len(), 20);
+
+    for san in ["f3", "e5", "g4"] {
+        let body = json!({ "move": san }).to_string();
+        assert_eq!(route(&Method::Post, "/games/0/moves", &body).0, 200);
+    }
+    let (status, reply) = route(&Method::Post, "/games/0/moves", r#"{"move":"Nf6"}"#);
+    assert_eq!(status, 200);
+    assert_eq!(reply["moves"], json!(["f3", "e5", "g4", "Nf6"]));
+    let (status, reply) = route(&Method::Post, "/games/0/moves", r#"{"move":"Ke2"}"#);
+    assert_eq!(status, 400);
+    assert_eq!(reply["reason"], "cannot_reach");
+
+    let (_, game) = route(&Method::Get, "/games/0", "");
+    assert_eq!(
+        game["fen"],
+        "rnbqkb1r/pppp1ppp/5n2/4p3/6P1/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"
+    );
+    assert_eq!(route(&Method::Get, "/games/7", "").0, 404);
+    assert_eq!(route(&Method::Delete, "/games/0", "").0, 405);
+    assert_eq!(route(&Method::Get, "/nowhere", "").0, 404);
+
+    let fen = json!({ "fen": "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2" });
+    let (status, game) = route(&Method::Post, "/games", &fen.to_string());
+    assert_eq!(status, 201);
+    assert_eq!(game["id"], 1);
+    let (_, game) = route(&Method::Post, "/games/1/moves", r#"{"move":"Qh4"}"#);
+    assert_eq!(game["status"], "checkmate");
+    assert_eq!(game["result"], "0-1");
+    assert_eq!(game["moves"], json!(["Qh4#"]));
+    assert_eq!(game["legal_moves"], json!([]));
+
+    // A game won without mate, with moves still legal, takes no more of them.
+    let three_check = json!({
+        "variant": "threecheck",
+        "fen": "rnbqkb1r/pppp1ppp/5n2/4p3/2B1P3/8/PPPP1PPP/RNBQK1NR w KQkq - 1+3 0 4",
+    });
+    let (_, game) = route(&Method::Post, "/games", &three_check.to_string());
+    let id = game["id"].as_u64().unwrap();
+    let moves = format!("/games/{}/moves", id);
+    let (_, game) = route(&Method::Post, &moves, r#"{"move":"Bxf7+"}"#);
+    assert_eq!(
+        (&game["status"], &game["result"]),
+        (&json!("three_checks"), &json!("1-0"))
+    );
+    assert_eq!(game["legal_moves"], json!([]));
+    let (status, reply) = route(&Method::Post, &moves, r#"{"move":"Kxf7"}"#);
+    assert_eq!((status, &reply["reason"]), (409, &json!("game_over")));
+    assert_eq!(route(&Method::Get, &format!("/games/{}", id), "").0, 200);
+    let (status, reply) = route(&Method::Post, "/games", r#"{"fen":"8/8/8 w - -"}"#);
+    assert_eq!(status, 400);
+    assert_eq!(reply["reason"], "invalid_fen");
+
+    let (status, analysis) = route(&Method::Post, "/analyze", &fen.to_string());
+    assert_eq!(status, 200);
+    assert_eq!(analysis["best_move"], "Qh4#");
+    assert_eq!(analysis["pv"], json!(["Qh4#"]));
+    let (status, _) = route(&Method::Post, "/analyze", r#"{"fen":"x","depth":2}"#);
+    assert_eq!(status, 400);
+    let (status, _) = route(&Method::Post, "/analyze", "[1, 2]");
+    assert_eq!(status, 400);
+
+    let (status, game) = route(&Method::Post, "/games", r#"{"variant":"chess960"}"#);
+    assert_eq!(status, 201);
+    assert_eq!(game["variant"], "chess960");
+    let (status, reply) = route(&Method::Post, "/games", r#"{"variant":"shogi"}"#);
+    assert_eq!(status, 400);
+    assert_eq!(
+        reply["message"],
+        "\"variant\" must be one of standard, chess960, crazyhouse, atomic, threecheck, antichess, racingkings, gardner, losalamos."
+    );
+}
+
+#[test]
+fn test_http_eviction() {
+    let games = Mutex::new(Games::default());
+    let route = |method: &Method, path: &str, body: &str| answer(&games, method, path, body);
+    route(&Method::Post, "/games", "");
+    let fen = r#"{"fen":"rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2"}"#;
+    route(&Method::Post, "/games", fen);
+    route(&Method::Post, "/games/1/moves", r#"{"move":"Qh4"}"#);
+
+    // Finished games go first, and the others once they are abandoned.
+    let now = Instant::now();
+    games.lock().unwrap().evict(now + FINISHED_TTL / 2);
+    assert_eq!(route(&Method::Get, "/games/1", "").0, 200);
+    games.lock().unwrap().evict(now + FINISHED_TTL * 2);
+    assert_eq!(route(&Method::Get, "/games/0", "").0, 200);
+    assert_eq!(route(&Method::Get, "/games/1", "").0, 404);
+    games.lock().unwrap().evict(now + IDLE_TTL * 2);
+    assert_eq!(route(&Method::Get, "/games/0", "").0, 404);
+
+    let mut full = games.lock().unwrap();
+    for id in 0..MAX_GAMES as u64 {
+        let board = ChessBoard::new();
+        let game = Game {
+            board,
+            start: String::new(),
+            moves: Vec::new(),
+            touched: Instant::now(),
+        };
+        full.games.insert(id, game);
+    }
+    drop(full);
+    let (status, reply) = route(&Method::Post, "/games", "");
+    assert_eq!((status, &reply["reason"]), (503, &json!("too_many_games")));
+}
+
+#[test]
+fn test_http_metrics() {
+    let games = Mutex::new(Games::default());
+    let route = |method: &Method, path: &str, body: &str| answer(&games, method, path, body);
+    route(&Method::Post, "/games", "");
+    route(&Method::Post, "/games/0/moves", r#"{"move":"e4"}"#);
+    route(&Method::Post, "/games/0/moves", r#"{"move":"e4"}"#);
+    route(&Method::Get, "/games/9", "");
+    // The server searches with the games unlocked, and counts the search all the same.
+    let fen = r#"{"fen":"7k/5Q2/6K1/8/8/8/8/8 w - - 0 1","depth":2}"#;
+    assert_eq!(route(&Method::Post, "/analyze", fen).0, 200);
+    let fen = r#"{"fen":"7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"}"#;
+    route(&Method::Post, "/games", fen);
+
+    let text = games.lock().unwrap().metrics();
+    assert!(text.starts_with(
+        "# HELP freight_chess_games Games hosted.\n# TYPE freight_chess_games gauge\n\
+        freight_chess_games 2\n"
+    ));
+    // The stalemate is over before it starts.
+    assert!(text.contains("\nfreight_chess_games_active 1\n"));
+    assert!(text.contains("\nfreight_chess_moves_total 1\n"));
+    assert!(text.contains("\nfreight_chess_moves_per_second 0.016666666666666666\n"));
+    assert!(text.contains("\nfreight_chess_http_requests_total 6\n"));
+    assert!(text.contains("\nfreight_chess_http_errors_total{reason=\"cannot_reach\"} 1\n"));
+    assert!(text.contains("\nfreight_chess_http_errors_total{reason=\"not_found\"} 1\n"));
+    assert!(!text.contains("freight_chess_search_nodes_total 0\n"));
+    assert!(!text.contains("freight_chess_search_nps 0\n"));
+}
@@ -0,0 +1,1142 @@
+//! Two-player games over TCP. One instance runs `serve` and plays white, the other `connect`s to
+//! it and plays black. Both sides validate every move, and the messages they exchange are
+//! described in `protocol`.
+//!
+//! The server stays in charge of the game: if the client's connection drops, the server keeps the
+//! game (and the clocks) going for a grace period, and the client can pick it up again with the
+//! session token it was given.
+
+pub mod grpc;
+pub mod http;
+pub mod protocol;
+pub mod tls;
+pub mod ws;
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufRead, Error, ErrorKind};
+use std::net::{Shutdown, TcpListener};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::armageddon;
+use crate::movegen::{color_name, Move};
+use crate::time_control;
+use crate::ChessBoard;
+use crate::{BLACK, WHITE};
+use protocol::{
+    read_message, write_message, Message, MessageBuffer, MAX_CHAT_LEN, NO_OFFER, PROTOCOL_VERSION,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use rustls::{ClientConfig, ServerConfig};
+use tls::Stream;
+
+/// How often the clocks are checked for a flag fall while waiting for input.
+const CLOCK_TICK: Duration = Duration::from_millis(100);
+
+/// How long a connection waits for incoming data before sending the messages queued for it.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a client waits between attempts to reconnect.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the server waits for a new connection to say which game it wants to resume.
+const RESUME_TIMEOUT: Duration = Duration::from_secs(5);
+
+const NET_TUTORIAL: &str = "Allowed commands:\n\
+    - move [move] - plays a move, as in move e2->e4 or move Nf3. On your opponent's turn, it\n\
+      queues a premove, played the moment they have moved if it's still legal\n\
+    - cancel - takes back your premove\n\
+    - draw - offers a draw (or accepts your opponent's offer)\n\
+    - takeback - asks to take back your last move (or agrees to your opponent's request)\n\
+    - accept - accepts your opponent's draw offer or takeback request\n\
+    - decline - declines your opponent's draw offer or takeback request\n\
+    - resign - resigns the game\n\
+    - say [message] - sends a chat message to your opponent\n\
+    - mute - hides your opponent's chat messages (unmute shows them again)\n\
+    - board - prints the current board\n\
+    - quit - resigns and leaves";
+
+enum Event {
+    Local(String),
+    /// A message read from the connection with the given number.
+    Remote(u32, Message),
+    /// The connection with the given number was closed.
+    Disconnected(u32),
+    /// The client came back on a new connection (server only).
+    Reconnected(Stream),
+}
+
+/// Returns a random number from the system's secure source of randomness, for tokens that stand
+/// in for a password.
+pub(crate) fn secure_u64() -> u64 {
+    let mut bytes = [0; 8];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("The system has no secure source of randomness.");
+    u64::from_le_bytes(bytes)
+}
+
+/// Returns a random number, good enough to seed games and samples with, but not for secrets.
+pub(crate) fn random_u64() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(nanos);
+    hasher.finish()
+}
+
+/// Waits for one opponent on `port`, then plays white against them. `clocks` is the starting
+/// time of white and black in milliseconds, or `None` for an untimed game, and `grace_ms` is how
+/// long to wait for the opponent to come back if they lose the connection. With `armageddon`,
+/// black wins if the game is drawn. With `tls`, connections are encrypted.
+pub fn serve(
+    port: u16,
+    clocks: Option<[u32; 2]>,
+    grace_ms: u32,
+    armageddon: bool,
+    tls: Option<Arc<ServerConfig>>,
+) -> Result<(), Error> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    println!("Waiting for an opponent on port {}...", port);
+    let (tcp, address) = listener.accept()?;
+    let mut stream = tls::accept(tcp, tls.as_ref())?;
+    let _span = tracing::info_span!(target: "net", "game", %address).entered();
+    tracing::info!(target: "net", "{} connected", address);
+    println!("{} connected. You play white.", address);
+
+    let token = secure_u64();
+    let [white_ms, black_ms] = clocks.unwrap_or([0, 0]);
+    let hello = Message::Hello {
+        version: PROTOCOL_VERSION,
+        color: BLACK,
+        white_ms,
+        black_ms,
+        grace_ms,
+        token,
+        armageddon,
+    };
+    write_message(&mut stream, &hello)?;
+
+    let (sender, events) = channel();
+    let mut session = Session::new(WHITE, clocks, grace_ms, token, sender.clone());
+    session.armageddon = armageddon;
+    // Keep listening, so the opponent can come back if their connection drops.
+    thread::spawn(move || {
+        for tcp in listener.incoming() {
+            let mut stream = match tcp.and_then(|tcp| tls::accept(tcp, tls.as_ref())) {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let _ = stream.tcp().set_read_timeout(Some(RESUME_TIMEOUT));
+            let resumed = matches!(
+                read_message(&mut stream),
+                Ok(Message::Resume { token: resumed }) if resumed == token
+            );
+            if resumed && sender.send(Event::Reconnected(stream)).is_err() {
+                return;
+            }
+        }
+    });
+    session.attach(stream)?;
+    play(session, events)
+}
+
+/// Joins the game served at `address` (`host:port`). With a `token` (in hexadecimal), resumes
+/// the game that token was issued for instead of starting a new one. With `tls`, the connection
+/// is encrypted.
+pub fn connect(
+    address: &str,
+    token: Option<&str>,
+    tls: Option<Arc<ClientConfig>>,
+) -> Result<(), Error> {
+    let token = match token.map(|token| u64::from_str_radix(token, 16)) {
+        Some(Ok(token)) => Some(token),
+        Some(Err(_)) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Session tokens are written in hexadecimal.",
+            ))
+        }
+        None => None,
+    };
+
+    let (sender, events) = channel();
+    let (mut session, stream) = match token {
+        Some(token) => {
+            let (hello, restore, stream) = resume(address, token, tls.as_ref())?;
+            let mut session = session_from_hello(&hello, sender)?;
+            session.restore(restore)?;
+            println!("Resumed the game at {}.", address);
+            (session, stream)
+        }
+        None => {
+            let mut stream = tls::connect(address, tls.as_ref())?;
+            let hello = read_message(&mut stream)?;
+            let session = session_from_hello(&hello, sender)?;
+            println!(
+                "Connected to {}. You play {}.",
+                address,
+                color_name(session.color)
+            );
+            (session, stream)
+        }
+    };
+    if session.grace > Duration::ZERO {
+        println!(
+            "Your session token is {:016x}. If the game is interrupted, run \
+            `connect {} --token {:016x}` to resume it.",
+            session.token, address, session.token
+        );
+    }
+    session.address = Some(address.to_string());
+    session.tls = tls;
+    session.attach(stream)?;
+    play(session, events)
+}
+
+/// Starts a client session from the server's greeting.
+fn session_from_hello(hello: &Message, events: Sender<Event>) -> Result<Session, Error> {
+    match *hello {
+        Message::Hello {
+            version,
+            color,
+            white_ms,
+            black_ms,
+            grace_ms,
+            token,
+            armageddon,
+        } if version == PROTOCOL_VERSION => {
+            let clocks = (white_ms > 0).then_some([white_ms, black_ms]);
+            let mut session = Session::new(color, clocks, grace_ms, token, events);
+            session.armageddon = armageddon;
+            Ok(session)
+        }
+        Message::Hello { .. } => Err(Error::new(
+            ErrorKind::InvalidData,
+            "The server runs an incompatible version of FreightChess.",
+        )),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "The server did not greet us properly.",
+        )),
+    }
+}
+
+/// Asks the server at `address` to resume the game `token` was issued for, returning its
+/// `Hello` and `Restore` along with the new connection.
+fn resume(
+    address: &str,
+    token: u64,
+    tls: Option<&Arc<ClientConfig>>,
+) -> Result<(Message, Message, Stream), Error> {
+    let mut stream = tls::connect(address, tls)?;
+    stream.tcp().set_read_timeout(Some(RESUME_TIMEOUT))?;
+    write_message(&mut stream, &Message::Resume { token })?;
+    let hello = read_message(&mut stream)?;
+    let restore = read_message(&mut stream)?;
+    Ok((hello, restore, stream))
+}
+
+/// The state of one side of a network game.
+struct Session {
+    board: ChessBoard,
+    /// Every move played so far, so that a reconnecting client can be brought up to date.
+    history: Vec<Move>,
+    /// The queue of messages for the opponent, or `None` while they are away.
+    outgoing: Option<Sender<Message>>,
+    /// The thread running the current connection.
+    connection_thread: Option<JoinHandle<()>>,
+    /// Counts connections, so that events from a connection that has since been replaced are
+    /// ignored.
+    connection: u32,
+    /// Where reader threads report to.
+    events: Sender<Event>,
+    /// The color played by this instance.
+    color: u8,
+    /// The starting time of white and black in milliseconds, or `None` for an untimed game.
+    start_clocks: Option<[u32; 2]>,
+    /// The remaining time of white and black in milliseconds, as of `turn_started`. `None` for
+    /// untimed games.
+    clocks: Option<[u32; 2]>,
+    turn_started: Instant,
+    /// Both clocks as they were when each move was played, so a takeback can give the time
+    /// back. A client that resumed a game only knows the clocks as of resuming.
+    clock_history: Vec<[u32; 2]>,
+    /// The side whose draw offer is still waiting for an answer.
+    draw_offer: Option<u8>,
+    /// The side whose takeback request is still waiting for an answer, and the number of moves
+    /// it asks to go back to.
+    takeback_offer: Option<(u8, u32)>,
+    /// The token the client resumes the game with.
+    token: u64,
+    /// How long the server keeps the game open for a client that lost its connection.
+    grace: Duration,
+    /// When the client's connection was lost (server only).
+    disconnected_at: Option<Instant>,
+    /// The address of the server (client only), for reconnecting.
+    address: Option<String>,
+    /// The TLS settings used to reach the server (client only), for reconnecting.
+    tls: Option<Arc<ClientConfig>>,
+    /// Whether the opponent's chat messages are hidden.
+    muted: bool,
+    /// Whether the game is played as Armageddon, where a draw is a win for black.
+    armageddon: bool,
+    /// The move queued during the opponent's turn, as typed. It's only read once the opponent
+    /// has moved, since it may not be legal until then, or no longer be.
+    premove: Option<String>,
+}
+
+fn play(mut session: Session, events: Receiver<Event>) -> Result<(), Error> {
+    let sender = session.events.clone();
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let line = line.unwrap_or_default();
+            if sender.send(Event::Local(line)).is_err() {
+                return;
+            }
+        }
+        // Running out of input is the same as walking away from the board.
+        let _ = sender.send(Event::Local("quit".to_string()));
+    });
+
+    if session.armageddon {
+        println!("This is an Armageddon game: black has less time, but wins if the game is drawn.");
+    }
+    if let Some(clocks) = session.start_clocks.filter(|[white, black]| white != black) {
+        println!("Time odds: {}.", time_control::describe(clocks));
+    }
+    session.show();
+    let result = loop {
+        let outcome = match events.recv_timeout(CLOCK_TICK) {
+            Ok(Event::Local(line)) => session.local_command(&line)?,
+            Ok(Event::Remote(connection, message)) if connection == session.connection => {
+                session.remote_message(message)?
+            }
+            Ok(Event::Disconnected(connection)) if connection == session.connection => {
+                session.disconnected()?
+            }
+            Ok(Event::Remote(..)) | Ok(Event::Disconnected(_)) => None,
+            Ok(Event::Reconnected(stream)) => session.reconnected(stream)?,
+            Err(RecvTimeoutError::Disconnected) => Some("Your opponent disconnected.".to_string()),
+            Err(RecvTimeoutError::Timeout) => match session.check_flag()? {
+                Some(result) => Some(result),
+                None => session.check_grace(),
+            },
+        };
+        if let Some(result) = outcome {
+            break result;
+        }
+    };
+
+    println!("{}", result);
+    // Let the connection deliver whatever is still queued (a resignation, say) and close.
+    session.outgoing = None;
+    if let Some(thread) = session.connection_thread.take() {
+        let _ = thread.join();
+    }
+    Ok(())
+}
+
+impl Session {
+    fn new(
+        color: u8,
+        clocks: Option<[u32; 2]>,
+        grace_ms: u32,
+        token: u64,
+        events: Sender<Event>,
+    ) -> Session {
+        Session {
+            board: ChessBoard::new(),
+            history: Vec::new(),
+            outgoing: None,
+            connection_thread: None,
+            connection: 0,
+            events,
+            color,
+            start_clocks: clocks,
+            clocks,
+            turn_started: Instant::now(),
+            clock_history: Vec::new(),
+            draw_offer: None,
+            takeback_offer: None,
+            token,
+            grace: Duration::from_millis(grace_ms as u64),
+            disconnected_at: None,
+            address: None,
+            tls: None,
+            muted: false,
+            armageddon: false,
+            premove: None,
+        }
+    }
+
+    fn opponent(&self) -> u8 {
+        self.color ^ BLACK
+    }
+
+    /// Starts talking to the opponent over `stream`, replacing any previous connection.
+    ///
+    /// The connection runs on its own thread, which both reads and writes: TLS streams can't be
+    /// split between a reader and a writer. It reports incoming messages as events, and closes
+    /// once `outgoing` is dropped.
+    fn attach(&mut self, mut stream: Stream) -> Result<(), Error> {
+        stream.tcp().set_read_timeout(Some(POLL_INTERVAL))?;
+        let (outgoing, queue) = channel::<Message>();
+        // Dropping the previous queue closes the previous connection.
+        self.outgoing = Some(outgoing);
+        self.connection += 1;
+        self.disconnected_at = None;
+
+        let connection = self.connection;
+        let events = self.events.clone();
+        self.connection_thread = Some(thread::spawn(move || {
+            let mut buffer = MessageBuffer::default();
+            loop {
+                loop {
+                    match queue.try_recv() {
+                        Ok(message) => {
+                            if write_message(&mut stream, &message).is_err() {
+                                let _ = events.send(Event::Disconnected(connection));
+                                return;
+                            }
+                        }
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => {
+                            let _ = stream.tcp().shutdown(Shutdown::Both);
+                            return;
+                        }
+                    }
+                }
+                match buffer.read(&mut stream) {
+                    Ok(Some(message)) => {
+                        if events.send(Event::Remote(connection, message)).is_err() {
+                            return;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(_) => {
+                        let _ = events.send(Event::Disconnected(connection));
+                        return;
+                    }
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    /// Sends `message` to the opponent. While they are away, messages are dropped: the `Restore`
+    /// they get when they come back brings them up to date.
+    fn send(&mut self, message: &Message) -> Result<(), Error> {
+        if let Some(outgoing) = &self.outgoing {
+            // A connection that has gone away reports it as an event of its own.
+            let _ = outgoing.send(message.clone());
+        }
+        Ok(())
+    }
+
+    /// The remaining time of white and black right now, counting the running clock down.
+    fn current_clocks(&self) -> Option<[u32; 2]> {
+        let mut clocks = self.clocks?;
+        let elapsed = self.turn_started.elapsed().as_millis();
+        let running = &mut clocks[(self.board.side_to_move() == BLACK) as usize];
+        *running = (*running as u128).saturating_sub(elapsed) as u32;
+        Some(clocks)
+    }
+
+    fn show(&self) {
+        println!("{}", self.board);
+        if let Some([white, black]) = self.current_clocks() {
+            println!(
+                "White {} | Black {}",
+                format_clock(white),
+                format_clock(black)
+            );
+        }
+        if self.board.side_to_move() == self.color {
+            println!("Your move.");
+        } else if let Some(premove) = &self.premove {
+            println!("Waiting for your opponent... (premove: {})", premove);
+        } else {
+            println!("Waiting for your opponent...");
+        }
+    }
+
+    /// Describes how the game ended once it is over by the rules of its variant.
+    fn game_over(&self) -> Option<String> {
+        armageddon::announce_outcome(&self.board, self.armageddon)
+    }
+
+    /// Whether this instance is the server, which keeps the clocks and decides flag falls.
+    fn is_server(&self) -> bool {
+        self.address.is_none()
+    }
+
+    /// Ends the game if the side to move has run out of time. Only the server decides that; the
+    /// client ends it once the server's clocks say so.
+    fn check_flag(&mut self) -> Result<Option<String>, Error> {
+        let clocks = match self.is_server() {
+            true => self.current_clocks(),
+            false => self.clocks,
+        };
+        let clocks = match clocks {
+            Some(clocks) => clocks,
+            None => return Ok(None),
+        };
+        let side = self.board.side_to_move();
+        if clocks[(side == BLACK) as usize] > 0 {
+            return Ok(None);
+        }
+        if self.is_server() {
+            // The client may already have noticed and left, so a failed send doesn't matter here.
+            let _ = self.send(&Message::Clock {
+                white_ms: clocks[0],
+                black_ms: clocks[1],
+            });
+        }
+        Ok(Some(if side == self.color {
+            "You ran out of time. Your opponent wins.".to_string()
+        } else {
+            "Your opponent ran out of time. You win.".to_string()
+        }))
+    }
+
+    /// Ends the game once a disconnected opponent has been away for longer than the grace
+    /// period.
+    fn check_grace(&self) -> Option<String> {
+        let away = self.disconnected_at?.elapsed();
+        (away >= self.grace).then(|| "Your opponent didn't come back in time. You win.".to_string())
+    }
+
+    /// Handles losing the connection: the server waits for the client to come back, and the
+    /// client tries to get back in.
+    fn disconnected(&mut self) -> Result<Option<String>, Error> {
+        self.outgoing = None;
+        if self.grace == Duration::ZERO {
+            return Ok(Some("Your opponent disconnected.".to_string()));
+        }
+        let address = match self.address.clone() {
+            Some(address) => address,
+            None => {
+                println!(
+                    "Your opponent lost the connection. Waiting {} seconds for them to return...",
+                    self.grace.as_secs()
+                );
+                self.disconnected_at = Some(Instant::now());
+                return Ok(None);
+            }
+        };
+
+        tracing::info!(target: "net", "lost the connection to {}", address);
+        println!("Lost the connection to the server. Reconnecting...");
+        let started = Instant::now();
+        while started.elapsed() < self.grace {
+            if let Ok((_, restore, stream)) = resume(&address, self.token, self.tls.as_ref()) {
+                self.restore(restore)?;
+                self.attach(stream)?;
+                tracing::info!(target: "net", "reconnected to {}", address);
+                println!("Reconnected.");
+                self.show();
+                return Ok(self.game_over());
+            }
+            thread::sleep(RECONNECT_INTERVAL);
+        }
+        Ok(Some("Couldn't reconnect to the server.".to_string()))
+    }
+
+    /// Takes the client back after it reconnected (server only), bringing it up to date.
+    fn reconnected(&mut self, stream: Stream) -> Result<Option<String>, Error> {
+        self.attach(stream)?;
+        let clocks = self.current_clocks().unwrap_or([0, 0]);
+        let [white_ms, black_ms] = self.start_clocks.unwrap_or([0, 0]);
+        self.send(&Message::Hello {
+            version: PROTOCOL_VERSION,
+            color: self.opponent(),
+            white_ms,
+            black_ms,
+            grace_ms: self.grace.as_millis() as u32,
+            token: self.token,
+            armageddon: self.armageddon,
+        })?;
+        self.send(&Message::Restore {
+            moves: self.history.clone(),
+            white_ms: clocks[0],
+            black_ms: clocks[1],
+            draw_offer: self.draw_offer.unwrap_or(NO_OFFER),
+        })?;
+        // The client doesn't get a pending takeback request back, so it lapses.
+        self.takeback_offer = None;
+        tracing::info!(target: "net", "the opponent reconnected");
+        println!("Your opponent reconnected.");
+        Ok(None)
+    }
+
+    /// Replaces the game with the server's copy.
+    fn restore(&mut self, restore: Message) -> Result<(), Error> {
+        let invalid = |reason: &str| Error::new(ErrorKind::InvalidData, reason.to_string());
+        let (moves, white_ms, black_ms, draw_offer) = match restore {
+            Message::Restore {
+                moves,
+                white_ms,
+                black_ms,
+                draw_offer,
+            } => (moves, white_ms, black_ms, draw_offer),
+            _ => return Err(invalid("The server didn't send the game to resume.")),
+        };
+
+        let mut board = ChessBoard::new();
+        for &mv in &moves {
+            if board.check_move(mv).is_err() {
+                return Err(invalid("The server sent an illegal game."));
+            }
+            board.apply_move(mv);
+        }
+        self.board = board;
+        self.clock_history = vec![[white_ms, black_ms]; moves.len()];
+        self.history = moves;
+        if self.clocks.is_some() {
+            self.clocks = Some([white_ms, black_ms]);
+        }
+        self.turn_started = Instant::now();
+        self.draw_offer = (draw_offer != NO_OFFER).then_some(draw_offer);
+        self.takeback_offer = None;
+        Ok(())
+    }
+
+    /// Plays `mv` on the local board and starts the other side's clock.
+    fn play_move(&mut self, mv: Move) {
+        self.clock_history.push(self.clocks.unwrap_or_default());
+        self.clocks = self.current_clocks();
+        self.turn_started = Instant::now();
+        self.board.apply_move(mv);
+        self.history.push(mv);
+    }
+
+    /// Plays our move `mv` and sends it to the opponent.
+    fn send_move(&mut self, mv: Move) -> Result<Option<String>, Error> {
+        self.play_move(mv);
+        self.send(&Message::Move(mv))?;
+        if let Some([white_ms, black_ms]) = self.clocks.filter(|_| self.is_server()) {
+            self.send(&Message::Clock { white_ms, black_ms })?;
+        }
+        if self.draw_offer == Some(self.opponent()) {
+            // Playing on turns the offer down.
+            self.draw_offer = None;
+            self.send(&Message::DeclineDraw)?;
+        }
+        if let Some((side, _)) = self.takeback_offer {
+            if side == self.opponent() {
+                self.takeback_offer = None;
+                self.send(&Message::DeclineTakeback)?;
+            }
+        }
+        self.show();
+        Ok(self.game_over())
+    }
+
+    /// The number of moves to go back to in order to take back the last move of `side`, so that
+    /// it is to move again, or `None` if it hasn't moved yet.
+    fn takeback_target(&self, side: u8) -> Option<u32> {
+        let plies = if self.board.side_to_move() == side {
+            2
+        } else {
+            1
+        };
+        let moves = self.history.len().checked_sub(plies)?;
+        Some(moves as u32)
+    }
+
+    /// Goes back to the position after the first `moves` moves, which must be fewer than have
+    /// been played, with both clocks as they were then.
+    fn take_back(&mut self, moves: u32) {
+        let moves = moves as usize;
+        self.history.truncate(moves);
+        self.board = ChessBoard::new();
+        for &mv in &self.history {
+            self.board.apply_move(mv);
+        }
+        if self.clocks.is_some() {
+            self.clocks = Some(self.clock_history[moves]);
+        }
+        self.clock_history.truncate(moves);
+        self.turn_started = Instant::now();
+        self.takeback_offer = None;
+        self.premove = None;
+    }
+
+    /// Agrees to the opponent's request to go back to the position after `moves` moves.
+    fn accept_takeback(&mut self, moves: u32) -> Result<(), Error> {
+        self.take_back(moves);
+        let [white_ms, black_ms] = self.clocks.unwrap_or_default();
+        self.send(&Message::AcceptTakeback {
+            moves,
+            white_ms,
+            black_ms,
+        })?;
+        println!("You agreed to take back your opponent's last move.");
+        self.show();
+        Ok(())
+    }
+
+    /// Plays the queued premove now that it's our turn, or drops it if it isn't legal.
+    fn play_premove(&mut self) -> Result<Option<String>, Error> {
+        let premove = match self.premove.take() {
+            Some(premove) => premove,
+            None => return Ok(None),
+        };
+        match self.board.parse_move(&premove) {
+            Ok(mv) => {
+                println!("Playing your premove {}.", self.board.san(mv));
+                self.send_move(mv)
+            }
+            Err(e) => {
+                println!("Your premove {} was dropped: {}", premove, e);
+                Ok(None)
+            }
+        }
+    }
+
+    fn local_command(&mut self, line: &str) -> Result<Option<String>, Error> {
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        match words.as_slice() {
+            [] => {}
+            ["say", ..] => {
+                let text = line.trim_start()[3..].trim();
+                if text.is_empty() {
+                    println!("Type a message after say, as in say good luck!");
+                } else if text.len() > MAX_CHAT_LEN {
+                    println!("That message is too long (at most {} bytes).", MAX_CHAT_LEN);
+                } else {
+                    self.send(&Message::Chat(text.to_string()))?;
+                }
+            }
+            ["mute"] => {
+                self.muted = true;
+                println!("Your opponent's messages are hidden. Type 'unmute' to see them.");
+            }
+            ["unmute"] => {
+                self.muted = false;
+                println!("Your opponent's messages are shown again.");
+            }
+            ["help"] => println!("{}", NET_TUTORIAL),
+            ["board"] => self.show(),
+            ["move", text] => {
+                if self.board.side_to_move() != self.color {
+                    self.premove = Some(text.to_string());
+                    println!("Premove {} queued. Type 'cancel' to take it back.", text);
+                    return Ok(None);
+                }
+                if let Some(result) = self.check_flag()? {
+                    return Ok(Some(result));
+                }
+                let mv = match self.board.parse_move(text) {
+                    Ok(mv) => mv,
+                    Err(e) => {
+                        println!("{}", e);
+                        return Ok(None);
+                    }
+                };
+                return self.send_move(mv);
+            }
+            ["cancel"] => match self.premove.take() {
+                Some(premove) => println!("Premove {} cancelled.", premove),
+                None => println!("You have no premove to cancel."),
+            },
+            ["takeback"] | ["accept"] | ["decline"]
+                if self
+                    .takeback_offer
+                    .is_some_and(|(side, _)| side == self.opponent()) =>
+            {
+                let (_, moves) = self.takeback_offer.take().unwrap();
+                if words[0] == "decline" {
+                    self.send(&Message::DeclineTakeback)?;
+                    println!("You declined the takeback request.");
+                } else {
+                    self.accept_takeback(moves)?;
+                }
+            }
+            ["takeback"] if self.takeback_offer.is_some() => {
+                println!("You have already asked to take back your last move.")
+            }
+            ["takeback"] => match self.takeback_target(self.color) {
+                Some(moves) => {
+                    self.takeback_offer = Some((self.color, moves));
+                    self.send(&Message::OfferTakeback { moves })?;
+                    println!("You asked to take back your last move.");
+                }
+                None => println!("You have no move to take back."),
+            },
+            ["draw"] | ["accept"] if self.draw_offer == Some(self.opponent()) => {
+                self.send(&Message::AcceptDraw)?;
+                return Ok(Some(armageddon::announce_draw(
+                    "Draw agreed.",
+                    self.armageddon,
+                )));
+            }
+            ["draw"] if self.draw_offer.is_none() => {
+                self.draw_offer = Some(self.color);
+                self.send(&Message::OfferDraw)?;
+                println!("You offered a draw.");
+            }
+            ["draw"] => println!("You have already offered a draw."),
+            ["accept"] | ["decline"] if self.draw_offer != Some(self.opponent()) => {
+                println!("Your opponent hasn't offered a draw.")
+            }
+            ["decline"] => {
+                self.draw_offer = None;
+                self.send(&Message::DeclineDraw)?;
+                println!("You declined the draw offer.");
+            }
+            ["resign"] | ["quit"] | ["exit"] => {
+                self.send(&Message::Resign)?;
+                return Ok(Some("You resigned.".to_string()));
+            }
+            _ => println!("Command does not exist or is not formatted properly."),
+        }
+        Ok(None)
+    }
+
+    fn remote_message(&mut self, message: Message) -> Result<Option<String>, Error> {
+        let protocol_error = |reason: &str| Err(Error::new(ErrorKind::InvalidData, reason));
+        match message {
+            Message::Move(mv) => {
+                if self.board.side_to_move() == self.color {
+                    return protocol_error("Your opponent moved out of turn.");
+                }
+                if self.board.check_move(mv).is_err() {
+                    return protocol_error("Your opponent sent an illegal move.");
+                }
+                println!("Your opponent played {}.", self.board.san(mv));
+                self.play_move(mv);
+                if self.draw_offer == Some(self.color) {
+                    self.draw_offer = None;
+                }
+                self.show();
+                if let Some(result) = self.game_over() {
+                    return Ok(Some(result));
+                }
+                return self.play_premove();
+            }
+            // The server keeps its own clocks, so only the client takes the other side's.
+            Message::Clock { white_ms, black_ms } => {
+                if self.clocks.is_some() && !self.is_server() {
+                    self.clocks = Some([white_ms, black_ms]);
+                    self.turn_started = Instant::now();
+                    if white_ms == 0 || black_ms == 0 {
+                        return self.check_flag();
+                    }
+                }
+            }
+            Message::OfferDraw => {
+                self.draw_offer = Some(self.opponent());
+                println!("Your opponent offers a draw. Type 'accept' or 'decline'.");
+            }
+            Message::AcceptDraw if self.draw_offer == Some(self.color) => {
+                return Ok(Some(armageddon::announce_draw(
+                    "Your opponent accepted the draw.",
+                    self.armageddon,
+                )));
+            }
+            Message::DeclineDraw if self.draw_offer == Some(self.color) => {
+                self.draw_offer = None;
+                println!("Your opponent declined the draw.");
+            }
+            Message::AcceptDraw | Message::DeclineDraw => {}
+            Message::Resign => return Ok(Some("Your opponent resigned. You win.".to_string())),
+            Message::Chat(text) => {
+                if !self.muted {
+                    // Control characters could mess with the terminal.
+                    let text = text.replace(|c: char| c.is_control(), " ");
+                    println!("Opponent: {}", text);
+                }
+            }
+            Message::OfferTakeback { moves } => {
+                if self.takeback_target(self.opponent()) != Some(moves) {
+                    return protocol_error("Your opponent asked to take back a move not theirs.");
+                }
+                self.takeback_offer = Some((self.opponent(), moves));
+                println!(
+                    "Your opponent asks to take back their last move. Type 'accept' or 'decline'."
+                );
+            }
+            Message::AcceptTakeback {
+                moves,
+                white_ms,
+                black_ms,
+            } => {
+                // Followed only if someone asked. The opponent may be answering our request even
+                // after theirs overtook it, so both sides stay on the same position.
+                let asked = match self.takeback_offer {
+                    Some((_, offered)) => {
+                        offered == moves || self.takeback_target(self.color) == Some(moves)
+                    }
+                    None => false,
+                };
+                if !asked {
+                    return protocol_error("Your opponent took back moves nobody asked to.");
+                }
+                self.take_back(moves);
+                if self.clocks.is_some() {
+                    self.clocks = Some([white_ms, black_ms]);
+                }
+                println!("Your opponent agreed to take back your last move.");
+                self.show();
+            }
+            Message::DeclineTakeback => {
+                if self
+                    .takeback_offer
+                    .is_some_and(|(side, _)| side == self.color)
+                {
+                    self.takeback_offer = None;
+                    println!("Your opponent declined the takeback request.");
+                }
+            }
+            Message::Hello { .. } => return protocol_error("Unexpected greeting mid-game."),
+            Message::Resume { .. } | Message::Restore { .. } => {
+                return protocol_error("Unexpected resumption mid-game.")
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Formats milliseconds as `m:ss`.
+pub(crate) fn format_clock(ms: u32) -> String {
+    let seconds = ms.div_ceil(1000);
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[test]
+fn test_session_restore() {
+    let (events, _) = channel();
+    let mut server = Session::new(WHITE, Some([60_000, 60_000]), 30_000, 7, events.clone());
+    for san in ["e4", "e5", "Nf3"] {
+        let mv = server.board.parse_move(san).unwrap();
+        server.play_move(mv);
+    }
+    server.draw_offer = Some(WHITE);
+
+    let mut client = Session::new(BLACK, Some([60_000, 60_000]), 30_000, 7, events);
+    client
+        .restore(Message::Restore {
+            moves: server.history.clone(),
+            white_ms: 55_000,
+            black_ms: 41_000,
+            draw_offer: WHITE,
+        })
+        .unwrap();
+    assert_eq!(client.board.fen(), server.board.fen());
+    assert_eq!(client.history.len(), 3);
+    assert_eq!(client.clocks, Some([55_000, 41_000]));
+    assert_eq!(client.draw_offer, Some(WHITE));
+
+    let illegal = Message::Restore {
+        moves: vec![Move::new(12, 36)],
+        white_ms: 0,
+        black_ms: 0,
+        draw_offer: NO_OFFER,
+    };
+    assert!(client.restore(illegal).is_err());
+    assert!(client.restore(Message::Resign).is_err());
+
+    client.disconnected_at = Some(Instant::now() - Duration::from_secs(31));
+    assert!(client.check_grace().is_some());
+}
+
+#[test]
+fn test_clocks_kept_by_server() {
+    let (events, _) = channel();
+    let mut server = Session::new(WHITE, Some([60_000, 60_000]), 0, 7, events.clone());
+    let flag = Message::Clock {
+        white_ms: 0,
+        black_ms: 1_000_000,
+    };
+    // A client can neither flag the server nor give itself more time.
+    assert_eq!(server.remote_message(flag.clone()).unwrap(), None);
+    assert_eq!(server.clocks, Some([60_000, 60_000]));
+
+    // The client takes the server's clocks, and loses on time when the server says so.
+    let mut client = Session::new(BLACK, Some([60_000, 60_000]), 0, 7, events);
+    client.address = Some("localhost:7878".to_string());
+    client.turn_started = Instant::now() - Duration::from_secs(120);
+    assert_eq!(client.check_flag().unwrap(), None);
+    let result = client.remote_message(flag).unwrap();
+    assert_eq!(
+        result.as_deref(),
+        Some("Your opponent ran out of time. You win.")
+    );
+}
+
+#[test]
+fn test_armageddon_session() {
+    let (events, _) = channel();
+    let hello = Message::Hello {
+        version: PROTOCOL_VERSION,
+        color: WHITE,
+        white_ms: 300_000,
+        black_ms: 240_000,
+        grace_ms: 0,
+        token: 7,
+        armageddon: true,
+    };
+    let mut session = session_from_hello(&hello, events.clone()).unwrap();
+    assert_eq!(session.clocks, Some([300_000, 240_000]));
+
+    session.board = ChessBoard::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(
+        session.game_over().unwrap(),
+        "Stalemate. The game is drawn. Black wins the Armageddon game."
+    );
+
+    let untimed = Message::Hello {
+        version: PROTOCOL_VERSION,
+        color: BLACK,
+        white_ms: 0,
+        black_ms: 0,
+        grace_ms: 0,
+        token: 7,
+        armageddon: true,
+    };
+    assert_eq!(session_from_hello(&untimed, events).unwrap().clocks, None);
+}
+
+#[test]
+fn test_premove() {
+    let (events, _) = channel();
+    let mut session = Session::new(BLACK, None, 0, 7, events);
+    let opponent_move = |session: &mut Session, san: &str| {
+        let mv = session.board.parse_move(san).unwrap();
+        session.remote_message(Message::Move(mv)).unwrap()
+    };
+
+    session.local_command("move e5").unwrap();
+    assert_eq!(session.premove.as_deref(), Some("e5"));
+    opponent_move(&mut session, "e4");
+    assert_eq!(session.history.len(), 2);
+    assert_eq!(session.board.side_to_move(), WHITE);
+    assert!(session.premove.is_none());
+
+    // A premove that isn't legal once the opponent has moved is dropped.
+    session.local_command("move Bb5").unwrap();
+    opponent_move(&mut session, "Nf3");
+    assert_eq!(session.history.len(), 3);
+    assert_eq!(session.board.side_to_move(), BLACK);
+    assert!(session.premove.is_none());
+
+    session.local_command("move Nc6").unwrap();
+    session.local_command("move Nf6").unwrap();
+    session.local_command("cancel").unwrap();
+    assert!(session.premove.is_none());
+    opponent_move(&mut session, "Bc4");
+    assert_eq!(session.history.len(), 5);
+    assert_eq!(session.board.side_to_move(), BLACK);
+}
+
+#[test]
+fn test_takeback() {
+    let (events, _) = channel();
+    let mut white = Session::new(WHITE, Some([60_000, 60_000]), 0, 7, events.clone());
+    let mut black = Session::new(BLACK, Some([60_000, 60_000]), 0, 7, events);
+    let (to_black, white_sent) = channel();
+    let (to_white, black_sent) = channel();
+    white.outgoing = Some(to_black);
+    black.outgoing = Some(to_white);
+    let relay = |from: &Receiver<Message>, to: &mut Session| {
+        for message in from.try_iter() {
+            to.remote_message(message).unwrap();
+        }
+    };
+
+    white.local_command("takeback").unwrap();
+    assert!(white.takeback_offer.is_none());
+    for (i, san) in ["e4", "e5", "Nf3"].into_iter().enumerate() {
+        if i % 2 == 0 {
+            white.local_command(&format!("move {}", san)).unwrap();
+            relay(&white_sent, &mut black);
+        } else {
+            black.local_command(&format!("move {}", san)).unwrap();
+            relay(&black_sent, &mut white);
+        }
+    }
+    white.clocks = Some([50_000, 40_000]);
+
+    // Black, to move, asks to go back to before e5, and white declines.
+    black.local_command("takeback").unwrap();
+    relay(&black_sent, &mut white);
+    assert_eq!(white.takeback_offer, Some((BLACK, 1)));
+    white.local_command("decline").unwrap();
+    relay(&white_sent, &mut black);
+    assert!(black.takeback_offer.is_none());
+    assert_eq!(black.history.len(), 3);
+
+    // Asked again, white agrees, and both boards and clocks go back to after e4.
+    black.local_command("takeback").unwrap();
+    relay(&black_sent, &mut white);
+    white.local_command("accept").unwrap();
+    relay(&white_sent, &mut black);
+    for session in [&white, &black] {
+        assert_eq!(session.history.len(), 1);
+        assert_eq!(session.board.side_to_move(), BLACK);
+        assert!(session.takeback_offer.is_none());
+    }
+    assert_eq!(white.board.fen(), black.board.fen());
+    assert_eq!(white.clocks, black.clocks);
+    assert!(white.clocks.unwrap()[0] >= 59_000);
+
+    // Moving on instead of answering turns the request down.
+    black.local_command("move c5").unwrap();
+    relay(&black_sent, &mut white);
+    black.local_command("takeback").unwrap();
+    relay(&black_sent, &mut white);
+    white.local_command("move Nf3").unwrap();
+    relay(&white_sent, &mut black);
+    assert!(black.takeback_offer.is_none());
+    assert_eq!(black.history.len(), 3);
+
+    let bad = Message::OfferTakeback { moves: 3 };
+    assert!(white.remote_message(bad).is_err());
+}
+
+#[test]
+fn test_takeback_rejected() {
+    let (events, _) = channel();
+    let mut white = Session::new(WHITE, Some([60_000, 60_000]), 0, 7, events);
+    for san in ["e4", "e5", "Nf3"] {
+        let mv = white.board.parse_move(san).unwrap();
+        white.play_move(mv);
+    }
+
+    // Black, to move, may only ask to go back to before e5, not to wipe the game.
+    for moves in [0, 2, 3] {
+        let offer = Message::OfferTakeback { moves };
+        assert!(white.remote_message(offer).is_err());
+        assert!(white.takeback_offer.is_none());
+    }
+
+    // Nobody asked, so an acceptance doesn't rewind the game or touch the clocks.
+    let clocks = white.clocks;
+    let accept = Message::AcceptTakeback {
+        moves: 0,
+        white_ms: 1,
+        black_ms: 1,
+    };
+    assert!(white.remote_message(accept).is_err());
+    assert_eq!(white.history.len(), 3);
+    assert_eq!(white.clocks, clocks);
+
+    // Nor does one for other moves than were asked for.
+    white
+        .remote_message(Message::OfferTakeback { moves: 1 })
+        .unwrap();
+    let accept = Message::AcceptTakeback {
+        moves: 0,
+        white_ms: 1,
+        black_ms: 1,
+    };
+    assert!(white.remote_message(accept).is_err());
+    assert_eq!(white.history.len(), 3);
+}
@@ -0,0 +1,401 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+use crate::movegen::Move;
+
+/// Bumped whenever the wire format changes, so mismatched builds refuse to play each other.
+pub const PROTOCOL_VERSION: u8 = 6;
+
+/// No message comes close to this; anything longer means the stream is corrupt.
+const MAX_MESSAGE_LEN: u32 = 1 << 16;
+
+const HELLO: u8 = 0;
+const MOVE: u8 = 1;
+const OFFER_DRAW: u8 = 2;
+const ACCEPT_DRAW: u8 = 3;
+const DECLINE_DRAW: u8 = 4;
+const RESIGN: u8 = 5;
+const CLOCK: u8 = 6;
+const RESUME: u8 = 7;
+const RESTORE: u8 = 8;
+const CHAT: u8 = 9;
+const OFFER_TAKEBACK: u8 = 10;
+const ACCEPT_TAKEBACK: u8 = 11;
+const DECLINE_TAKEBACK: u8 = 12;
+
+/// The longest chat message accepted, in bytes.
+pub const MAX_CHAT_LEN: usize = 500;
+
+/// A message exchanged between two FreightChess instances.
+///
+/// On the wire, every message is a 4-byte big-endian length followed by that many bytes: a tag
+/// byte identifying the message, then its fields (integers are big-endian).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// Sent by the server as soon as a client connects: the protocol version, the color the
+    /// client plays, the starting time of white and black in milliseconds (0 for an untimed
+    /// game), how long the server waits for a dropped client to come back, the session token the
+    /// client resumes the game with, and whether the game is played as Armageddon, where a draw
+    /// is a win for black.
+    Hello {
+        version: u8,
+        color: u8,
+        white_ms: u32,
+        black_ms: u32,
+        grace_ms: u32,
+        token: u64,
+        armageddon: bool,
+    },
+    /// A move played by the sender. Sent as three bytes: from, to and promotion.
+    Move(Move),
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    Resign,
+    /// Both clocks as the server keeps them, sent by the server after each of its moves and when
+    /// a side runs out of time. A clock at zero means that side has lost on time. The server
+    /// ignores any a client sends.
+    Clock {
+        white_ms: u32,
+        black_ms: u32,
+    },
+    /// Sent by a client instead of waiting for a `Hello`, to take its seat back after losing the
+    /// connection. The server answers with a `Hello` and a `Restore`.
+    Resume {
+        token: u64,
+    },
+    /// The whole game so far, sent to a client that resumed it: every move from the starting
+    /// position, both clocks as of now, and the side whose draw offer is pending (`NO_OFFER` if
+    /// there is none).
+    Restore {
+        moves: Vec<Move>,
+        white_ms: u32,
+        black_ms: u32,
+        draw_offer: u8,
+    },
+    /// A chat message from the sender, as UTF-8 of at most `MAX_CHAT_LEN` bytes.
+    Chat(String),
+    /// Asks to take back the sender's last move: to go back to the position after the first
+    /// `moves` moves of the game, where the sender is to move.
+    OfferTakeback {
+        moves: u32,
+    },
+    /// Agrees to an `OfferTakeback`: the sender has gone back to the position after `moves`
+    /// moves, with both clocks as they were then.
+    AcceptTakeback {
+        moves: u32,
+        white_ms: u32,
+        black_ms: u32,
+    },
+    DeclineTakeback,
+}
+
+/// The `draw_offer` of a `Restore` when no draw has been offered.
+pub const NO_OFFER: u8 = 0xFF;
+
+impl Message {
+    /// Encodes the message body (everything after the length prefix).
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Message::Hello {
+                version,
+                color,
+                white_ms,
+                black_ms,
+                grace_ms,
+                token,
+                armageddon,
+            } => {
+                let mut bytes = vec![HELLO, *version, *color];
+                bytes.extend_from_slice(&white_ms.to_be_bytes());
+                bytes.extend_from_slice(&black_ms.to_be_bytes());
+                bytes.extend_from_slice(&grace_ms.to_be_bytes());
+                bytes.extend_from_slice(&token.to_be_bytes());
+                bytes.push(*armageddon as u8);
+                bytes
+            }
+            Message::Move(mv) => vec![MOVE, mv.from, mv.to, mv.promotion],
+            Message::OfferDraw => vec![OFFER_DRAW],
+            Message::AcceptDraw => vec![ACCEPT_DRAW],
+            Message::DeclineDraw => vec![DECLINE_DRAW],
+            Message::Resign => vec![RESIGN],
+            Message::Clock { white_ms, black_ms } => {
+                let mut bytes = vec![CLOCK];
+                bytes.extend_from_slice(&white_ms.to_be_bytes());
+                bytes.extend_from_slice(&black_ms.to_be_bytes());
+                bytes
+            }
+            Message::Resume { token } => {
+                let mut bytes = vec![RESUME];
+                bytes.extend_from_slice(&token.to_be_bytes());
+                bytes
+            }
+            Message::Restore {
+                moves,
+                white_ms,
+                black_ms,
+                draw_offer,
+            } => {
+                let mut bytes = vec![RESTORE];
+                bytes.extend_from_slice(&white_ms.to_be_bytes());
+                bytes.extend_from_slice(&black_ms.to_be_bytes());
+                bytes.push(*draw_offer);
+                for mv in moves {
+                    bytes.extend_from_slice(&[mv.from, mv.to, mv.promotion]);
+                }
+                bytes
+            }
+            Message::Chat(text) => {
+                let mut bytes = vec![CHAT];
+                bytes.extend_from_slice(text.as_bytes());
+                bytes
+            }
+            Message::OfferTakeback { moves } => {
+                let mut bytes = vec![OFFER_TAKEBACK];
+                bytes.extend_from_slice(&moves.to_be_bytes());
+                bytes
+            }
+            Message::AcceptTakeback {
+                moves,
+                white_ms,
+                black_ms,
+            } => {
+                let mut bytes = vec![ACCEPT_TAKEBACK];
+                bytes.extend_from_slice(&moves.to_be_bytes());
+                bytes.extend_from_slice(&white_ms.to_be_bytes());
+                bytes.extend_from_slice(&black_ms.to_be_bytes());
+                bytes
+            }
+            Message::DeclineTakeback => vec![DECLINE_TAKEBACK],
+        }
+    }
+
+    /// Decodes a message body produced by `encode`.
+    pub fn decode(bytes: &[u8]) -> Result<Message, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "Malformed message.");
+        let u32_at = |i: usize| -> Result<u32, Error> {
+            let field = bytes.get(i..i + 4).ok_or_else(invalid)?;
+            Ok(u32::from_be_bytes(field.try_into().unwrap()))
+        };
+        let u64_at = |i: usize| -> Result<u64, Error> {
+            let field = bytes.get(i..i + 8).ok_or_else(invalid)?;
+            Ok(u64::from_be_bytes(field.try_into().unwrap()))
+        };
+        let move_at = |i: usize| -> Result<Move, Error> {
+            if bytes[i] >= 64 || bytes[i + 1] >= 64 {
+                return Err(invalid());
+            }
+            Ok(Move {
+                from: bytes[i],
+                to: bytes[i + 1],
+                promotion: bytes[i + 2],
+            })
+        };
+
+        let message = match bytes.first() {
+            Some(&HELLO) if bytes.len() == 24 && bytes[23] <= 1 => Message::Hello {
+                version: bytes[1],
+                color: bytes[2],
+                white_ms: u32_at(3)?,
+                black_ms: u32_at(7)?,
+                grace_ms: u32_at(11)?,
+                token: u64_at(15)?,
+                armageddon: bytes[23] == 1,
+            },
+            Some(&MOVE) if bytes.len() == 4 => Message::Move(move_at(1)?),
+            Some(&OFFER_DRAW) if bytes.len() == 1 => Message::OfferDraw,
+            Some(&ACCEPT_DRAW) if bytes.len() == 1 => Message::AcceptDraw,
+            Some(&DECLINE_DRAW) if bytes.len() == 1 => Message::DeclineDraw,
+            Some(&RESIGN) if bytes.len() == 1 => Message::Resign,
+            Some(&CLOCK) if bytes.len() == 9 => Message::Clock {
+                white_ms: u32_at(1)?,
+                black_ms: u32_at(5)?,
+            },
+            Some(&RESUME) if bytes.len() == 9 => Message::Resume { token: u64_at(1)? },
+            Some(&RESTORE) if bytes.len() >= 10 && (bytes.len() - 10).is_multiple_of(3) => {
+                Message::Restore {
+                    moves: (10..bytes.len())
+                        .step_by(3)
+                        .map(move_at)
+                        .collect::<Result<Vec<Move>, Error>>()?,
+                    white_ms: u32_at(1)?,
+                    black_ms: u32_at(5)?,
+                    draw_offer: bytes[9],
+                }
+            }
+            Some(&CHAT) if bytes.len() <= MAX_CHAT_LEN + 1 => {
+                let text = std::str::from_utf8(&bytes[1..]).map_err(|_| invalid())?;
+                Message::Chat(text.to_string())
+            }
+            Some(&OFFER_TAKEBACK) if bytes.len() == 5 => {
+                Message::OfferTakeback { moves: u32_at(1)? }
+            }
+            Some(&ACCEPT_TAKEBACK) if bytes.len() == 13 => Message::AcceptTakeback {
+                moves: u32_at(1)?,
+                white_ms: u32_at(5)?,
+                black_ms: u32_at(9)?,
+            },
+            Some(&DECLINE_TAKEBACK) if bytes.len() == 1 => Message::DeclineTakeback,
+            _ => return Err(invalid()),
+        };
+        Ok(message)
+    }
+}
+
+/// Writes one length-prefixed message.
+pub fn write_message(writer: &mut impl Write, message: &Message) -> Result<(), Error> {
+    tracing::trace!(target: "net", "sending {:?}", message);
+    let body = message.encode();
+    writer.write_all(&(body.len() as u32).to_be_bytes())?;
+    writer.write_all(&body)?;
+    writer.flush()
+}
+
+/// Reads one length-prefixed message, blocking until it has fully arrived.
+pub fn read_message(reader: &mut impl Read) -> Result<Message, Error> {
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length);
+    if length > MAX_MESSAGE_LEN {
+        return Err(Error::new(ErrorKind::InvalidData, "Message is too long."));
+    }
+    let mut body = vec![0u8; length as usize];
+    reader.read_exact(&mut body)?;
+    let message = Message::decode(&body)?;
+    tracing::trace!(target: "net", "received {:?}", message);
+    Ok(message)
+}
+
+/// Reassembles messages from a stream that hands out whatever has arrived so far, such as one
+/// with a read timeout, where `read_message` would lose the part of a message read before the
+/// timeout.
+#[derive(Default)]
+pub struct MessageBuffer {
+    bytes: Vec<u8>,
+}
+
+impl MessageBuffer {
+    /// Returns the next message, reading from `reader` if no complete message is buffered yet.
+    /// Returns `None` if the read timed out before a whole message arrived.
+    pub fn read(&mut self, reader: &mut impl Read) -> Result<Option<Message>, Error> {
+        if let Some(message) = self.take()? {
+            return Ok(Some(message));
+        }
+        let mut chunk = [0u8; 4096];
+        match reader.read(&mut chunk) {
+            Ok(0) => Err(Error::from(ErrorKind::UnexpectedEof)),
+            Ok(read) => {
+                self.bytes.extend_from_slice(&chunk[..read]);
+                self.take()
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Removes the first message from the buffer, if it has fully arrived.
+    fn take(&mut self) -> Result<Option<Message>, Error> {
+        let length = match self.bytes.get(..4) {
+            Some(length) => u32::from_be_bytes(length.try_into().unwrap()),
+            None => return Ok(None),
+        };
+        if length > MAX_MESSAGE_LEN {
+            return Err(Error::new(ErrorKind::InvalidData, "Message is too long."));
+        }
+        let end = 4 + length as usize;
+        if self.bytes.len() < end {
+            return Ok(None);
+        }
+        let message = Message::decode(&self.bytes[4..end]);
+        self.bytes.drain(..end);
+        message.map(Some)
+    }
+}
+
+#[test]
+fn test_message_round_trip() {
+    let messages = [
+        Message::Hello {
+            version: PROTOCOL_VERSION,
+            color: crate::BLACK,
+            white_ms: 300_000,
+            black_ms: 60_000,
+            grace_ms: 60_000,
+            token: 0x0123_4567_89AB_CDEF,
+            armageddon: true,
+        },
+        Message::Move(Move {
+            from: 52,
+            to: 60,
+            promotion: crate::QUEEN,
+        }),
+        Message::OfferDraw,
+        Message::AcceptDraw,
+        Message::DeclineDraw,
+        Message::Resign,
+        Message::Clock {
+            white_ms: 1,
+            black_ms: u32::MAX,
+        },
+        Message::Resume { token: u64::MAX },
+        Message::Restore {
+            moves: vec![Move::new(12, 28), Move::new(52, 36)],
+            white_ms: 0,
+            black_ms: 1000,
+            draw_offer: NO_OFFER,
+        },
+        Message::Chat("good luck! ♞".to_string()),
+        Message::OfferTakeback { moves: 3 },
+        Message::AcceptTakeback {
+            moves: 3,
+            white_ms: 58_000,
+            black_ms: 0,
+        },
+        Message::DeclineTakeback,
+    ];
+
+    let mut stream = Vec::new();
+    for message in &messages {
+        write_message(&mut stream, message).expect("write failed");
+    }
+    let mut reader = stream.as_slice();
+    for message in &messages {
+        assert_eq!(&read_message(&mut reader).expect("read failed"), message);
+    }
+    assert!(read_message(&mut reader).is_err());
+
+    assert!(Message::decode(&[MOVE, 64, 0, 0]).is_err());
+    assert!(Message::decode(&[RESIGN, 0]).is_err());
+    assert!(Message::decode(&[RESTORE, 0, 0, 0, 0, 0, 0, 0, 0, NO_OFFER, 64, 0, 0]).is_err());
+    assert!(read_message(&mut [0xFF, 0xFF, 0xFF, 0xFF].as_slice()).is_err());
+    assert!(Message::decode(&[CHAT, 0xFF]).is_err());
+
+    // A stream that delivers a couple of bytes at a time, timing out in between.
+    struct Trickle(Vec<u8>, bool);
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            self.1 = !self.1;
+            if self.1 {
+                return Err(Error::from(ErrorKind::WouldBlock));
+            }
+            let read = self.0.len().min(buf.len()).min(3);
+            buf[..read].copy_from_slice(&self.0[..read]);
+            self.0.drain(..read);
+            Ok(read)
+        }
+    }
+    let mut trickle = Trickle(stream, false);
+    let mut buffer = MessageBuffer::default();
+    let mut received = Vec::new();
+    loop {
+        match buffer.read(&mut trickle) {
+            Ok(Some(message)) => received.push(message),
+            Ok(None) => {}
+            Err(e) => {
+                assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+                break;
+            }
+        }
+    }
+    assert_eq!(received, messages);
+    assert!(Message::decode(&[CHAT; MAX_CHAT_LEN + 2]).is_err());
+}
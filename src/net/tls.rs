@@ -0,0 +1,126 @@
+//! The connections network games run over: plain TCP, or TLS (using rustls) when both sides pass
+//! `--tls`. The certificates come from the `[tls]` section of the configuration file.
+
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection};
+use rustls::{StreamOwned, DEFAULT_VERSIONS};
+
+use crate::config::TlsConfig;
+
+/// A connection to the other instance.
+pub trait Transport: Read + Write + Send {
+    /// The TCP connection underneath, for setting timeouts and shutting it down.
+    fn tcp(&self) -> &TcpStream;
+}
+
+impl Transport for TcpStream {
+    fn tcp(&self) -> &TcpStream {
+        self
+    }
+}
+
+impl Transport for StreamOwned<ServerConnection, TcpStream> {
+    fn tcp(&self) -> &TcpStream {
+        &self.sock
+    }
+}
+
+impl Transport for StreamOwned<ClientConnection, TcpStream> {
+    fn tcp(&self) -> &TcpStream {
+        &self.sock
+    }
+}
+
+pub type Stream = Box<dyn Transport>;
+
+fn tls_error(e: rustls::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, e)
+}
+
+fn pem_error(path: &Path, e: rustls::pki_types::pem::Error) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Couldn't read {}: {}", path.display(), e),
+    )
+}
+
+fn certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| pem_error(path, e))
+}
+
+/// Builds the server side of TLS from the configured certificate and private key.
+pub fn server_config(config: &TlsConfig) -> Result<Arc<ServerConfig>, Error> {
+    let (certificate, private_key) = match (&config.certificate, &config.private_key) {
+        (Some(certificate), Some(private_key)) => (certificate, private_key),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Serving over TLS needs `certificate` and `private_key` in the [tls] section \
+                of the configuration file.",
+            ))
+        }
+    };
+    let chain = certificates(certificate)?;
+    let key = PrivateKeyDer::from_pem_file(private_key).map_err(|e| pem_error(private_key, e))?;
+    let server = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_protocol_versions(DEFAULT_VERSIONS)
+        .map_err(tls_error)?
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(tls_error)?;
+    Ok(Arc::new(server))
+}
+
+/// Builds the client side of TLS, trusting the usual web roots and the configured CA, if any.
+pub fn client_config(config: &TlsConfig) -> Result<Arc<ClientConfig>, Error> {
+    let mut roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(path) = &config.ca_certificate {
+        for certificate in certificates(path)? {
+            roots.add(certificate).map_err(tls_error)?;
+        }
+    }
+    let client = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_protocol_versions(DEFAULT_VERSIONS)
+        .map_err(tls_error)?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(client))
+}
+
+/// Wraps a connection the server accepted, in TLS if `tls` is given.
+pub fn accept(tcp: TcpStream, tls: Option<&Arc<ServerConfig>>) -> Result<Stream, Error> {
+    match tls {
+        Some(tls) => {
+            let connection = ServerConnection::new(Arc::clone(tls)).map_err(tls_error)?;
+            Ok(Box::new(StreamOwned::new(connection, tcp)))
+        }
+        None => Ok(Box::new(tcp)),
+    }
+}
+
+/// Connects to the server at `address` (`host:port`), over TLS if `tls` is given. The server's
+/// certificate must be valid for `host`.
+pub fn connect(address: &str, tls: Option<&Arc<ClientConfig>>) -> Result<Stream, Error> {
+    let tcp = TcpStream::connect(address)?;
+    let tls = match tls {
+        Some(tls) => tls,
+        None => return Ok(Box::new(tcp)),
+    };
+    let host = match address.rsplit_once(':') {
+        Some((host, _)) => host.trim_start_matches('[').trim_end_matches(']'),
+        None => address,
+    };
+    let name = ServerName::try_from(host.to_string())
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid server name."))?;
+    let connection = ClientConnection::new(Arc::clone(tls), name).map_err(tls_error)?;
+    Ok(Box::new(StreamOwned::new(connection, tcp)))
+}
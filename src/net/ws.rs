@@ -0,0 +1,2274 @@
+//! The WebSocket game server. Clients (browsers, mobile apps, ...) exchange the JSON messages
+//! documented in `docs/websocket.md`, and the server checks every move against the rules, so it
+//! is the single source of truth for the game. Any number of games are hosted at once, each in
+//! its own named room. A server given a player registry only seats players who have logged in.
+//!
+//! Bughouse is played in a pair of linked rooms, one per board: pieces captured on one board are
+//! handed to the partner on the other, the clocks of both boards start together, and when one
+//! game ends, so does the other.
+//!
+//! A player may also play the server's engine, which takes the other seat of a room of their own.
+//! The server can also give a simul: its engine plays white on a number of boards at once, each
+//! against its own opponent, and moves on whichever board has waited longest for it.
+//!
+//! Whoever runs the server operates it from its console: commands typed on stdin list the games,
+//! kick players, abort games, make announcements and shut the server down once its games are
+//! over.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Error, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rustls::ServerConfig;
+use serde_json::{json, Value};
+use tungstenite::{accept, Message as WsMessage};
+
+use super::secure_u64;
+use super::tls;
+use crate::accounts::Accounts;
+use crate::bughouse::BUGHOUSE;
+use crate::engine::{self, Engine, EngineSpec, Limits};
+use crate::fairplay;
+use crate::movegen::{color_name, Move, MoveRejection};
+use crate::pgn::PgnGame;
+use crate::rating::Rating;
+use crate::search::MAX_DEPTH;
+use crate::time_control;
+use crate::variant::{self, Variant};
+use crate::{ChessBoard, ChessErr, BLACK, EMPTY, WHITE};
+
+/// How long a connection waits for client input before flushing messages queued for it.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The room players end up in when they don't name one.
+const DEFAULT_ROOM: &str = "main";
+
+/// The longest room name accepted.
+const MAX_ROOM_NAME: usize = 32;
+
+/// How deep the engine searches positions for spectators who asked for an evaluation.
+const EVALUATION_DEPTH: u32 = 3;
+
+/// How often the clocks are checked for a flag fall.
+const CLOCK_TICK: Duration = Duration::from_millis(100);
+
+/// The longest time control accepted, in minutes per side.
+const MAX_MINUTES: u64 = 180;
+
+/// How many positions the engine searches per move against a player who doesn't say, and the
+/// most a player may ask for.
+const ENGINE_NODES: u64 = 200_000;
+const MAX_ENGINE_NODES: u64 = 2_000_000;
+
+/// The room whose boards, `simul/1`, `simul/2`, ..., the engine plays its simul on.
+const SIMUL_ROOM: &str = "simul";
+
+const ADMIN_HELP: &str = "Admin commands:\n\
+    - games - lists every room and its game\n\
+    - kick [room] [white|black] - removes a player from their seat, losing a game in progress\n\
+    - abort [room] - ends a game without a result\n\
+    - say [message] - sends an announcement to every connection\n\
+    - drain - takes no new games, and shuts down once the games in progress are over\n\
+    - shutdown - aborts every game and shuts down";
+
+/// A connected client, as far as the rooms are concerned.
+#[derive(Clone)]
+struct Client {
+    /// Tells connections apart, since senders can't be compared.
+    id: u64,
+    /// The queue of messages waiting to be sent to the client.
+    queue: Sender<String>,
+    /// The player the client logged in as, if any.
+    name: Option<String>,
+    /// The player whose password came with the `login` being handled, once it has been checked.
+    password_checked: Option<String>,
+    /// Set to close the connection, as when the client is kicked.
+    closed: Arc<AtomicBool>,
+}
+
+impl Client {
+    fn new(queue: Sender<String>) -> Client {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Client {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            queue,
+            name: None,
+            password_checked: None,
+            closed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    fn send(&self, value: &Value) {
+        // A client that has gone away will be cleaned up by its own connection.
+        let _ = self.queue.send(value.to_string());
+    }
+}
+
+/// Someone watching a game.
+struct Spectator {
+    client: Client,
+    /// Whether they get an engine `evaluation` after every move.
+    evaluation: bool,
+}
+
+/// The position waiting to be evaluated for a room's spectators, with the spectators to send
+/// the `evaluation` to, and whether the room's evaluation worker is running.
+#[derive(Default)]
+struct Evaluations {
+    next: Option<(ChessBoard, Vec<Client>)>,
+    running: bool,
+}
+
+/// The seat of the engine in a game a player chose to play against it.
+struct EngineSeat {
+    color: u8,
+    /// How deep and how many positions it searches for each move.
+    depth: u32,
+    nodes: u64,
+    /// Whether it is searching for a move right now.
+    thinking: bool,
+}
+
+/// The game played in one room.
+struct Game {
+    /// The name of the room, which doubles as the PGN `Event`.
+    room: String,
+    /// The rules the game is played by.
+    variant: &'static dyn Variant,
+    /// The position the game started from, for its PGN.
+    start: ChessBoard,
+    board: ChessBoard,
+    /// The room of the other board, in Bughouse.
+    partner: Option<String>,
+    /// The pieces captured since the partner board was last brought up to date, with their
+    /// colors, for its hands.
+    passed: Vec<u8>,
+    /// The starting time of white and black in milliseconds, for the PGN of the game. `None` for
+    /// untimed games.
+    time_control: Option<[u32; 2]>,
+    /// The remaining time of white and black in milliseconds, as of `turn_started`. `None` for
+    /// untimed games.
+    clocks: Option<[u32; 2]>,
+    /// When the running clock was started, or `None` while the clocks haven't started yet.
+    turn_started: Option<Instant>,
+    /// Every move played, for the PGN of the game.
+    moves: Vec<Move>,
+    /// How long each move took in milliseconds, for the fair-play report: `None` for moves
+    /// made before the clocks started, and for premoves, which take no thought.
+    move_ms: Vec<Option<u32>>,
+    /// The white and black players, while they are connected.
+    players: [Option<Client>; 2],
+    /// The rejoin tokens of the white and black seats, once they have been taken. A seat stays
+    /// reserved for its token holder even while they are disconnected.
+    tokens: [Option<String>; 2],
+    /// The names of the logged-in players who took the white and black seats.
+    names: [Option<String>; 2],
+    /// Everyone watching.
+    spectators: Vec<Spectator>,
+    /// The work of the room's evaluation worker, shared with it while it runs.
+    evaluations: Arc<Mutex<Evaluations>>,
+    /// The last move played, in SAN.
+    last_move: Option<String>,
+    /// The final `result` message, once the game is over.
+    result: Option<Value>,
+    /// Whether this is a board of the simul, where the server's engine plays white.
+    simul: bool,
+    /// The engine's seat, when a player is playing it.
+    engine: Option<EngineSeat>,
+    /// The moves white and black queued during their opponent's turn, as sent. Each is played
+    /// the moment its side is to move, if it's legal by then.
+    premoves: [Option<String>; 2],
+}
+
+/// Where a connection sits: the room it joined and the color it plays there, or `None` for
+/// spectators.
+#[derive(Clone, Debug, PartialEq)]
+struct Seat {
+    room: String,
+    color: Option<u8>,
+}
+
+/// Every room hosted by the server, by name.
+#[derive(Default)]
+struct Lobby {
+    rooms: HashMap<String, Game>,
+    /// The registered players, when only they may take a seat.
+    accounts: Option<Accounts>,
+    /// The name each session token handed out at login stands for.
+    sessions: HashMap<String, String>,
+    /// The simul the server is giving, if any.
+    simul: Option<SimulBoards>,
+    /// The fair-play report finished games are added to, if the server keeps one.
+    fairplay: Option<PathBuf>,
+    /// Every open connection, by client id, for announcements and shutting down.
+    clients: HashMap<u64, Client>,
+    /// Whether the server takes no new games and shuts down once the games in progress are over.
+    draining: bool,
+}
+
+/// How the server gives a simul: on `boards` boards, with `engine` searching at most `nodes`
+/// positions per move.
+pub struct Simul {
+    pub boards: u32,
+    pub engine: EngineSpec,
+    pub nodes: u64,
+}
+
+/// The boards of the simul being given.
+struct SimulBoards {
+    /// The engine's name, which sits at white on every board.
+    engine: String,
+    /// The rooms of the boards, in order.
+    rooms: Vec<String>,
+    /// The room of the board the engine is thinking about, if any.
+    thinking: Option<String>,
+}
+
+fn seat_index(color: u8) -> usize {
+    (color == BLACK) as usize
+}
+
+fn error(reason: &str, message: &str) -> Value {
+    json!({ "type": "error", "reason": reason, "message": message })
+}
+
+fn chess_error(e: ChessErr) -> Value {
+    match e {
+        ChessErr::BadMove(reason) => error(reason.kind(), &reason.to_string()),
+        e => error(e.kind(), &e.to_string()),
+    }
+}
+
+/// Makes a hard-to-guess token for reclaiming a seat or logging in again.
+fn new_token() -> String {
+    format!("{:016x}{:016x}", secure_u64(), secure_u64())
+}
+
+impl Game {
+    /// A game of `variant` in `room`, with `clocks` the starting time of white and black in
+    /// milliseconds (`None` for an untimed game).
+    fn new(room: &str, variant: &'static dyn Variant, clocks: Option<[u32; 2]>) -> Game {
+        let start = ChessBoard::start_of(variant);
+        Game {
+            room: room.to_string(),
+            variant,
+            start,
+            board: start,
+            partner: None,
+            passed: Vec::new(),
+            time_control: clocks,
+            clocks,
+            turn_started: None,
+            moves: Vec::new(),
+            move_ms: Vec::new(),
+            players: [None, None],
+            tokens: [None, None],
+            names: [None, None],
+            spectators: Vec::new(),
+            evaluations: Arc::default(),
+            last_move: None,
+            result: None,
+            simul: false,
+            engine: None,
+            premoves: [None, None],
+        }
+    }
+
+    fn state(&self) -> Value {
+        let clocks = self
+            .current_clocks()
+            .map(|[white, black]| json!({ "white_ms": white, "black_ms": black }));
+        json!({
+            "type": "state",
+            "variant": self.variant.name(),
+            "fen": self.board.fen(),
+            "side_to_move": color_name(self.board.side_to_move()),
+            "legal_moves": if self.result.is_some() { Vec::new() } else { self.board.legal_sans() },
+            "last_move": self.last_move,
+            "clocks": clocks,
+        })
+    }
+
+    /// The remaining time of white and black right now, counting the running clock down.
+    fn current_clocks(&self) -> Option<[u32; 2]> {
+        let mut clocks = self.clocks?;
+        if let (Some(started), None) = (self.turn_started, &self.result) {
+            let running = &mut clocks[seat_index(self.board.side_to_move())];
+            *running = (*running as u128).saturating_sub(started.elapsed().as_millis()) as u32;
+        }
+        Some(clocks)
+    }
+
+    /// Ends the game if the side to move has run out of time, returning whether it did.
+    fn check_flag(&mut self) -> bool {
+        let side = self.board.side_to_move();
+        match self.current_clocks() {
+            Some(clocks) if self.result.is_none() && clocks[seat_index(side)] == 0 => {
+                self.finish(if side == WHITE { "0-1" } else { "1-0" }, "timeout");
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn broadcast(&self, value: &Value) {
+        let spectators = self.spectators.iter().map(|spectator| &spectator.client);
+        for client in self.players.iter().flatten().chain(spectators) {
+            client.send(value);
+        }
+    }
+
+    /// The game so far as PGN, with the players' names when they logged in and the time control
+    /// of a timed game.
+    fn pgn(&self, result: &str) -> String {
+        let mut pgn = PgnGame::new(self.start);
+        pgn.set_tag("Event", &self.room);
+        pgn.set_tag("Site", "FreightChess");
+        for (tag, name) in ["White", "Black"].into_iter().zip(&self.names) {
+            pgn.set_tag(tag, name.as_deref().unwrap_or("?"));
+        }
+        if let Some(clocks) = self.time_control {
+            time_control::set_tags(&mut pgn, clocks);
+        }
+        for &mv in &self.moves {
+            pgn.tree.push(mv);
+        }
+        pgn.result = result.to_string();
+        pgn.to_pgn()
+    }
+
+    /// Ends the game and tells everyone how. `result` is written as in PGN (`1-0`, `0-1` or
+    /// `1/2-1/2`).
+    fn finish(&mut self, result: &str, reason: &str) {
+        // The clocks stop where they are.
+        self.clocks = self.current_clocks();
+        let value = json!({
+            "type": "result",
+            "result": result,
+            "reason": reason,
+            "pgn": self.pgn(result),
+        });
+        self.broadcast(&value);
+        self.result = Some(value);
+    }
+
+    /// Updates the players' ratings once the game is over, if both seats were taken by
+    /// different registered players, and tells everyone in the room the new ratings. Bughouse
+    /// games are team games, and simul games and others against the engine aren't between
+    /// players, so they aren't rated.
+    fn rate(&self, accounts: &mut Accounts) {
+        let (result, [Some(white), Some(black)]) = (&self.result, &self.names) else {
+            return;
+        };
+        if self.partner.is_some() || self.engine_color().is_some() {
+            return;
+        }
+        let score = match result.as_ref().and_then(|result| result["result"].as_str()) {
+            Some("1-0") => 1.0,
+            Some("0-1") => 0.0,
+            Some("*") | None => return,
+            Some(_) => 0.5,
+        };
+        let ratings = match accounts.record_game(white, black, score) {
+            Some(ratings) => ratings,
+            None => return,
+        };
+        if let Err(e) = accounts.save() {
+            eprintln!("Couldn't save the ratings: {}", e);
+        }
+        let player = |name: &str, rating: Rating| {
+            json!({
+                "name": name,
+                "rating": rating.rating.round(),
+                "deviation": rating.deviation.round(),
+            })
+        };
+        self.broadcast(&json!({
+            "type": "ratings",
+            "white": player(white, ratings[0]),
+            "black": player(black, ratings[1]),
+        }));
+    }
+
+    /// Checks the game, which is over, for fair play in the background, and adds it to the report
+    /// at `path`. The engine's side isn't checked, and neither are Bughouse games, whose moves
+    /// depend on the other board.
+    fn check_fair_play(&self, path: &Path) {
+        let Some(result) = self
+            .result
+            .as_ref()
+            .and_then(|result| result["result"].as_str())
+        else {
+            return;
+        };
+        if self.partner.is_some() {
+            return;
+        }
+        let (path, room, result) = (path.to_path_buf(), self.room.clone(), result.to_string());
+        let (start, moves, move_ms) = (self.start, self.moves.clone(), self.move_ms.clone());
+        let (names, engine) = (self.names.clone(), self.engine_color());
+        thread::spawn(move || {
+            let stats = fairplay::analyze(&start, &moves, &move_ms, fairplay::ANALYSIS_NODES);
+            let white = (engine != Some(WHITE)).then(|| (names[0].as_deref(), &stats[0]));
+            let black = (engine != Some(BLACK)).then(|| (names[1].as_deref(), &stats[1]));
+            if let Err(e) = fairplay::record(&path, &room, &result, [white, black]) {
+                eprintln!("Couldn't write the fair-play report: {}", e);
+            }
+        });
+    }
+
+    /// Has the room's evaluation worker search the position in the background and send the
+    /// `evaluation` to every spectator who asked for one, so the game isn't held up while the
+    /// engine thinks. The worker searches one position at a time, and a position still waiting
+    /// for it when the next one comes is dropped, since nobody wants its evaluation any more.
+    fn evaluate_for_spectators(&self) {
+        let clients = self
+            .spectators
+            .iter()
+            .filter(|spectator| spectator.evaluation)
+            .map(|spectator| spectator.client.clone())
+            .collect::<Vec<Client>>();
+        if clients.is_empty() || self.result.is_some() {
+            return;
+        }
+        let mut evaluations = self.evaluations.lock().unwrap();
+        evaluations.next = Some((self.board, clients));
+        if evaluations.running {
+            return;
+        }
+        evaluations.running = true;
+        let evaluations = Arc::clone(&self.evaluations);
+        thread::spawn(move || loop {
+            let (board, clients) = {
+                let mut evaluations = evaluations.lock().unwrap();
+                match evaluations.next.take() {
+                    Some(next) => next,
+                    None => {
+                        evaluations.running = false;
+                        return;
+                    }
+                }
+            };
+            let evaluation = evaluation(&board);
+            for client in clients {
+                client.send(&evaluation);
+            }
+        });
+    }
+
+    /// The color the server's engine plays, on a simul board or against a player who chose it.
+    fn engine_color(&self) -> Option<u8> {
+        match &self.engine {
+            Some(engine) => Some(engine.color),
+            None => self.simul.then_some(WHITE),
+        }
+    }
+
+    /// The seats nobody has taken yet.
+    fn open_seats(&self) -> Vec<&'static str> {
+        [WHITE, BLACK]
+            .into_iter()
+            .filter(|&color| self.tokens[seat_index(color)].is_none())
+            .map(color_name)
+            .collect()
+    }
+
+    /// Whether anyone is still connected to the room.
+    fn is_empty(&self) -> bool {
+        self.players.iter().all(Option::is_none) && self.spectators.is_empty()
+    }
+
+    /// Whether nobody is in the room and no seat has been taken, so it can go.
+    fn is_unused(&self) -> bool {
+        self.is_empty() && self.tokens.iter().all(Option::is_none)
+    }
+
+    /// Plays `mv`, which must be legal, and tells the room.
+    fn play(&mut self, mv: Move, premove: bool) {
+        let took = self
+            .turn_started
+            .map(|started| started.elapsed().as_millis() as u32);
+        self.move_ms.push(took.filter(|_| !premove));
+        let captured = self.board.captured_piece(mv);
+        if self.partner.is_some() && captured != EMPTY {
+            self.passed.push(captured);
+        }
+        self.clocks = self.current_clocks();
+        self.turn_started = Some(Instant::now());
+        self.last_move = Some(self.board.san(mv));
+        self.board.apply_move(mv);
+        self.moves.push(mv);
+        let state = self.state();
+        self.broadcast(&state);
+        if let Some((result, reason)) = self.board.outcome() {
+            self.finish(result, reason);
+        }
+        self.evaluate_for_spectators();
+    }
+
+    /// Plays the premove of the side to move, taking no time off its clock, or drops it and
+    /// tells its player why if it isn't legal.
+    fn play_premove(&mut self) {
+        let index = seat_index(self.board.side_to_move());
+        let Some(premove) = self.premoves[index].take() else {
+            return;
+        };
+        if self.result.is_some() {
+            return;
+        }
+        match self.board.parse_move(&premove) {
+            Ok(mv) => self.play(mv, true),
+            Err(e) => {
+                let mut dropped = chess_error(e);
+                dropped["type"] = json!("premove_dropped");
+                dropped["move"] = json!(premove);
+                if let Some(player) = &self.players[index] {
+                    player.send(&dropped);
+                }
+            }
+        }
+    }
+
+    /// Handles a message about the game itself from a client seated in this room. `color` is
+    /// the color the client plays, or `None` for spectators.
+    fn handle(&mut self, color: Option<u8>, request: &Value) -> Value {
+        match request["type"].as_str() {
+            Some("state") => self.state(),
+            Some("move") => {
+                let color = match color {
+                    Some(color) => color,
+                    None => return error("spectator", "Spectators can't move."),
+                };
+                if self.check_flag() || self.result.is_some() {
+                    return chess_error(ChessErr::BadMove(MoveRejection::GameOver));
+                }
+                if self.board.side_to_move() != color {
+                    let turn = MoveRejection::NotYourTurn(self.board.side_to_move());
+                    return chess_error(ChessErr::BadMove(turn));
+                }
+                let mv = match request["move"]
+                    .as_str()
+                    .map(|text| self.board.parse_move(text))
+                {
+                    Some(Ok(mv)) => mv,
+                    Some(Err(e)) => return chess_error(e),
+                    None => return error("invalid_message", "A move message needs a \"move\"."),
+                };
+
+                self.play(mv, false);
+                self.play_premove();
+                json!({ "type": "ok" })
+            }
+            Some("premove") => {
+                let color = match color {
+                    Some(color) => color,
+                    None => return error("spectator", "Spectators can't move."),
+                };
+                if self.result.is_some() {
+                    return chess_error(ChessErr::BadMove(MoveRejection::GameOver));
+                }
+                match request["move"].as_str() {
+                    // The opponent may have moved while the premove was on its way.
+                    Some(_) if self.board.side_to_move() == color => {
+                        let request = json!({ "type": "move", "move": request["move"] });
+                        return self.handle(Some(color), &request);
+                    }
+                    Some(text) => self.premoves[seat_index(color)] = Some(text.to_string()),
+                    None => self.premoves[seat_index(color)] = None,
+                }
+                json!({ "type": "ok" })
+            }
+            Some("resign") => match (color, &self.result) {
+                (None, _) => error("spectator", "Spectators can't resign."),
+                (Some(_), Some(_)) => chess_error(ChessErr::BadMove(MoveRejection::GameOver)),
+                (Some(color), None) => {
+                    self.finish(if color == WHITE { "0-1" } else { "1-0" }, "resignation");
+                    json!({ "type": "ok" })
+                }
+            },
+            _ => error("unknown_type", "Unknown message type."),
+        }
+    }
+}
+
+impl Lobby {
+    /// Lists every room, so players can find an open challenge or a game to watch.
+    fn listing(&self) -> Value {
+        let mut names = self.rooms.keys().collect::<Vec<&String>>();
+        names.sort();
+        let rooms = names
+            .into_iter()
+            .map(|name| {
+                let game = &self.rooms[name];
+                json!({
+                    "room": name,
+                    "variant": game.variant.name(),
+                    "partner": game.partner,
+                    "white": game.names[seat_index(WHITE)],
+                    "black": game.names[seat_index(BLACK)],
+                    "open_seats": game.open_seats(),
+                    "spectators": game.spectators.len(),
+                    "moves": game.board.moves,
+                    "over": game.result.is_some(),
+                })
+            })
+            .collect::<Vec<Value>>();
+        json!({ "type": "lobby", "rooms": rooms })
+    }
+
+    /// Creates a lobby where only the players registered in `accounts` may take a seat.
+    fn with_accounts(accounts: Accounts) -> Lobby {
+        Lobby {
+            accounts: Some(accounts),
+            ..Lobby::default()
+        }
+    }
+
+    /// Logs `client` in, either with a name and password, which `handle_message` has checked
+    /// by then, or with the session token of an earlier login.
+    fn login(&mut self, client: &mut Client, request: &Value) -> Value {
+        if self.accounts.is_none() {
+            return error("no_accounts", "This server has no player accounts.");
+        }
+        let checked = client.password_checked.take();
+        let name = match (
+            request["name"].as_str(),
+            request["password"].as_str(),
+            request["token"].as_str(),
+        ) {
+            (Some(name), Some(_), _) if checked.as_deref() == Some(name) => name.to_string(),
+            (_, _, Some(token)) if self.sessions.contains_key(token) => {
+                self.sessions[token].clone()
+            }
+            _ => return error("login_failed", "Wrong name, password or token."),
+        };
+        let token = new_token();
+        self.sessions.insert(token.clone(), name.clone());
+        client.name = Some(name.clone());
+        json!({ "type": "logged_in", "name": name, "token": token })
+    }
+
+    /// Handles one message from a client, returning the reply meant for that client only.
+    /// `seat` is where the client sits, once it has joined a room.
+    fn handle(&mut self, seat: &mut Option<Seat>, client: &mut Client, text: &str) -> Value {
+        let request: Value = match serde_json::from_str(text) {
+            Ok(request) => request,
+            Err(_) => return error("invalid_json", "Messages must be JSON objects."),
+        };
+
+        match request["type"].as_str() {
+            Some("lobby") => self.listing(),
+            Some("join" | "rejoin" | "login") if seat.is_some() => {
+                error("already_joined", "You have already joined a game.")
+            }
+            Some("login") => self.login(client, &request),
+            Some("leaderboard") => match &self.accounts {
+                Some(accounts) => leaderboard(accounts),
+                None => error("no_accounts", "This server has no player accounts."),
+            },
+            Some("simul") => self.simul_status(),
+            Some("join") if self.draining && request["color"] != "spectator" => error(
+                "draining",
+                "The server is shutting down and takes no new games.",
+            ),
+            Some("join") => {
+                let room = request["room"].as_str().unwrap_or(DEFAULT_ROOM);
+                if room.is_empty() || room.len() > MAX_ROOM_NAME {
+                    return error(
+                        "invalid_room",
+                        "Room names must be 1 to 32 characters long.",
+                    );
+                }
+                let wanted = match request["color"].as_str() {
+                    Some("white") => vec![WHITE],
+                    Some("black") => vec![BLACK],
+                    Some("spectator") => Vec::new(),
+                    _ => vec![WHITE, BLACK],
+                };
+                if !wanted.is_empty() && self.accounts.is_some() && client.name.is_none() {
+                    return error("login_required", "Log in to play on this server.");
+                }
+                let variant: &'static dyn Variant = match request["variant"].as_str() {
+                    None => &variant::STANDARD,
+                    Some(name) if name.eq_ignore_ascii_case("bughouse") => &BUGHOUSE,
+                    Some(name) => match variant::by_name(name) {
+                        Some(variant) => variant,
+                        None => {
+                            let message = format!(
+                                "\"variant\" must be one of {}, bughouse.",
+                                variant::names().join(", ")
+                            );
+                            return error("invalid_variant", &message);
+                        }
+                    },
+                };
+                let minutes = |field: &str| match &request[field] {
+                    Value::Null => Ok(None),
+                    minutes => match minutes.as_u64().filter(|m| (1..=MAX_MINUTES).contains(m)) {
+                        Some(minutes) => Ok(Some(minutes as u32 * 60_000)),
+                        None => Err(error(
+                            "invalid_message",
+                            &format!("\"{}\" must be a whole number from 1 to 180.", field),
+                        )),
+                    },
+                };
+                // Black plays with white's time unless it is given its own.
+                let clocks = match (minutes("minutes"), minutes("black_minutes")) {
+                    (Err(e), _) | (_, Err(e)) => return e,
+                    (Ok(Some(white)), Ok(black)) => Some([white, black.unwrap_or(white)]),
+                    (Ok(None), Ok(None)) => None,
+                    (Ok(None), Ok(Some(_))) => {
+                        return error(
+                            "invalid_message",
+                            "\"black_minutes\" needs \"minutes\" for white.",
+                        )
+                    }
+                };
+
+                let engine = match request["opponent"].as_str() {
+                    None => None,
+                    Some("engine") => match engine_seat(&request, &wanted, variant, clocks) {
+                        Ok(engine) => Some(engine),
+                        Err(e) => return e,
+                    },
+                    Some(_) => {
+                        return error("invalid_message", "\"opponent\" can only be \"engine\".")
+                    }
+                };
+
+                // A Bughouse room is a pair of boards, `room/1` and `room/2`.
+                let names = match (variant.name(), request["board"].as_u64()) {
+                    ("bughouse", Some(board @ 1..=2)) => vec![format!("{}/{}", room, board)],
+                    ("bughouse", _) => vec![format!("{}/1", room), format!("{}/2", room)],
+                    // The simul's opponents take the first free board.
+                    _ => match &self.simul {
+                        Some(simul) if room == SIMUL_ROOM => simul.rooms.clone(),
+                        _ => vec![room.to_string()],
+                    },
+                };
+                // Spectators watch games that players have started, and never open a room.
+                let names = match wanted.is_empty() {
+                    true => names
+                        .into_iter()
+                        .filter(|name| self.rooms.contains_key(name))
+                        .collect(),
+                    false => names,
+                };
+                if names.is_empty() {
+                    return error("no_game", "There is no game in that room to watch.");
+                }
+                if let Some(engine) = engine {
+                    if names != [room] || self.rooms.contains_key(room) {
+                        return error("room_taken", "Play the engine in a room nobody uses yet.");
+                    }
+                    let mut game = Game::new(room, variant, None);
+                    // Nobody is given the token, so the seat stays the engine's.
+                    game.tokens[seat_index(engine.color)] = Some(new_token());
+                    game.names[seat_index(engine.color)] = Some(engine::internal_name());
+                    game.engine = Some(engine);
+                    self.rooms.insert(room.to_string(), game);
+                }
+                if variant.name() == "bughouse" && !self.rooms.contains_key(&names[0]) {
+                    let boards = [format!("{}/1", room), format!("{}/2", room)];
+                    for (board, partner) in boards.iter().zip(boards.iter().rev()) {
+                        let mut game = Game::new(board, variant, clocks);
+                        game.partner = Some(partner.clone());
+                        self.rooms.insert(board.clone(), game);
+                    }
+                }
+                let mut found = None;
+                for name in &names {
+                    let game = self
+                        .rooms
+                        .entry(name.clone())
+                        .or_insert_with(|| Game::new(name, variant, clocks));
+                    found = match wanted
+                        .iter()
+                        .copied()
+                        .find(|&color| game.tokens[seat_index(color)].is_none())
+                    {
+                        Some(color) => Some((name, Some(color))),
+                        None if wanted.is_empty() => Some((name, None)),
+                        None => continue,
+                    };
+                    break;
+                }
+                let (room, color) = match found {
+                    Some(found) => found,
+                    None => {
+                        for name in &names {
+                            self.remove_if_unused(name);
+                        }
+                        return error("seat_taken", "That seat is already taken.");
+                    }
+                };
+                let game = self.rooms.get_mut(room.as_str()).unwrap();
+
+                let mut joined = json!({ "type": "joined", "room": room });
+                match color {
+                    Some(color) => {
+                        let token = new_token();
+                        game.players[seat_index(color)] = Some(client.clone());
+                        game.tokens[seat_index(color)] = Some(token.clone());
+                        game.names[seat_index(color)] = client.name.clone();
+                        // A simul board waits for the engine from the moment its opponent sits
+                        // down. Its games are untimed, so no clock starts running.
+                        if game.simul {
+                            game.turn_started = Some(Instant::now());
+                        }
+                        joined["color"] = json!(color_name(color));
+                        joined["token"] = json!(token);
+                    }
+                    None => {
+                        let evaluation = request["evaluation"].as_bool().unwrap_or(false);
+                        game.spectators.push(Spectator {
+                            client: client.clone(),
+                            evaluation,
+                        });
+                        joined["color"] = json!("spectator");
+                    }
+                }
+                client.send(&joined);
+                game.evaluate_for_spectators();
+                *seat = Some(Seat {
+                    room: room.clone(),
+                    color,
+                });
+                game.state()
+            }
+            Some("rejoin") => {
+                let room = request["room"].as_str().unwrap_or(DEFAULT_ROOM);
+                let token = request["token"].as_str();
+                let found = self.rooms.get_mut(room).and_then(|game| {
+                    let color = [WHITE, BLACK]
+                        .into_iter()
+                        .find(|&color| game.tokens[seat_index(color)].as_deref() == token)?;
+                    Some((game, color))
+                });
+                let (game, color) = match (token, found) {
+                    (Some(_), Some(found)) => found,
+                    _ => return error("invalid_token", "That token doesn't match any seat."),
+                };
+                // Whoever held the seat before is replaced, so a player can take over from a
+                // connection that hasn't noticed it dropped.
+                game.players[seat_index(color)] = Some(client.clone());
+                let joined = json!({ "type": "joined", "room": room, "color": color_name(color) });
+                client.send(&joined);
+                *seat = Some(Seat {
+                    room: room.to_string(),
+                    color: Some(color),
+                });
+                game.state()
+            }
+            Some(_) => match seat.as_ref().and_then(|seat| {
+                let game = self.rooms.get_mut(&seat.room)?;
+                Some((game, seat.color, &seat.room))
+            }) {
+                Some((game, color, room)) => {
+                    let was_over = game.result.is_some();
+                    let reply = game.handle(color, &request);
+                    let room = room.clone();
+                    if !was_over && self.rooms[&room].result.is_some() {
+                        self.finished(&room);
+                    }
+                    self.sync_partner(&room);
+                    reply
+                }
+                None if matches!(
+                    request["type"].as_str(),
+                    Some("state" | "move" | "premove" | "resign")
+                ) =>
+                {
+                    error("not_joined", "Join a game first.")
+                }
+                None => error("unknown_type", "Unknown message type."),
+            },
+            None => error("unknown_type", "Unknown message type."),
+        }
+    }
+
+    /// Brings the partner board of `room` up to date in Bughouse: hands it the pieces captured
+    /// in `room`, starts its clocks along with `room`'s, and ends its game along with `room`'s.
+    /// The team that won one board wins the other.
+    fn sync_partner(&mut self, room: &str) {
+        let Some(game) = self.rooms.get_mut(room) else {
+            return;
+        };
+        let Some(partner) = game.partner.clone() else {
+            return;
+        };
+        let passed = std::mem::take(&mut game.passed);
+        let (started, result) = (game.turn_started, game.result.clone());
+        let Some(partner) = self.rooms.get_mut(&partner) else {
+            return;
+        };
+        for &piece in &passed {
+            partner.board.receive(piece);
+        }
+        if partner.turn_started.is_none() {
+            partner.turn_started = started;
+        }
+        match result {
+            Some(result) if partner.result.is_none() => {
+                let score = match result["result"].as_str() {
+                    Some("1-0") => "0-1",
+                    Some("0-1") => "1-0",
+                    Some("*") => "*",
+                    _ => "1/2-1/2",
+                };
+                let reason = format!("partner_{}", result["reason"].as_str().unwrap_or_default());
+                partner.finish(score, &reason);
+            }
+            _ if !passed.is_empty() => partner.broadcast(&partner.state()),
+            _ => {}
+        }
+    }
+
+    /// Sets up the boards of a simul with `engine`, named as it gives its name, at white on each.
+    fn start_simul(&mut self, engine: &str, boards: u32) {
+        let rooms = (1..=boards)
+            .map(|board| format!("{}/{}", SIMUL_ROOM, board))
+            .collect::<Vec<String>>();
+        for room in &rooms {
+            let mut game = Game::new(room, &variant::STANDARD, None);
+            // Nobody is given the token, so the seat stays the engine's.
+            game.tokens[seat_index(WHITE)] = Some(new_token());
+            game.names[seat_index(WHITE)] = Some(engine.to_string());
+            game.simul = true;
+            self.rooms.insert(room.clone(), game);
+        }
+        self.simul = Some(SimulBoards {
+            engine: engine.to_string(),
+            rooms,
+            thinking: None,
+        });
+    }
+
+    /// Picks the simul board the engine should move on next: of the boards with an opponent
+    /// where it is the engine's turn, the one that has waited longest. Returns its room, the
+    /// position the game started from and the moves played since, and marks the engine as
+    /// thinking about it.
+    fn next_simul_move(&mut self) -> Option<(String, ChessBoard, Vec<Move>)> {
+        let simul = self.simul.as_mut()?;
+        let room = simul
+            .rooms
+            .iter()
+            .filter_map(|room| Some((room, self.rooms.get(room)?)))
+            .filter(|(_, game)| {
+                game.result.is_none()
+                    && game.tokens[seat_index(BLACK)].is_some()
+                    && game.board.side_to_move() == WHITE
+            })
+            .min_by_key(|(_, game)| game.turn_started)?
+            .0
+            .clone();
+        let game = &self.rooms[&room];
+        let found = (room.clone(), game.start, game.moves.clone());
+        simul.thinking = Some(room);
+        Some(found)
+    }
+
+    /// Plays the engine's `mv` on the simul board `room`, unless the game has moved on from the
+    /// `moves` it was searched after, as when the opponent resigned meanwhile. `None` means the
+    /// engine couldn't move, which loses the game.
+    fn play_simul_move(&mut self, room: &str, moves: usize, mv: Option<Move>) {
+        if let Some(simul) = &mut self.simul {
+            simul.thinking = None;
+        }
+        self.play_engine_move(room, moves, mv);
+    }
+
+    /// Picks the games against a player where it is the engine's turn and it isn't thinking yet,
+    /// and marks it as thinking about them. Returns the room of each, its position, the number
+    /// of moves played, and how deep and how many positions to search.
+    fn engine_turns(&mut self) -> Vec<(String, ChessBoard, usize, u32, u64)> {
+        let mut turns = Vec::new();
+        for (room, game) in &mut self.rooms {
+            let Some(engine) = &mut game.engine else {
+                continue;
+            };
+            if game.result.is_none()
+                && !engine.thinking
+                && game.board.side_to_move() == engine.color
+            {
+                engine.thinking = true;
+                let moves = game.moves.len();
+                turns.push((room.clone(), game.board, moves, engine.depth, engine.nodes));
+            }
+        }
+        turns
+    }
+
+    /// Plays the engine's `mv` in `room`, on a simul board or against a player, unless the game
+    /// has moved on from the `moves` it was searched after, as when the opponent resigned
+    /// meanwhile. `None` means the engine couldn't move, which loses the game.
+    fn play_engine_move(&mut self, room: &str, moves: usize, mv: Option<Move>) {
+        let Some(game) = self.rooms.get_mut(room) else {
+            return;
+        };
+        if let Some(engine) = &mut game.engine {
+            engine.thinking = false;
+        }
+        let Some(color) = game.engine_color() else {
+            return;
+        };
+        if game.result.is_some() || game.moves.len() != moves {
+            return;
+        }
+        match mv {
+            Some(mv) => {
+                let request = json!({ "type": "move", "move": game.board.san(mv) });
+                game.handle(Some(color), &request);
+            }
+            None => game.finish(if color == WHITE { "0-1" } else { "1-0" }, "engine_failure"),
+        }
+        if game.result.is_some() {
+            self.finished(room);
+        }
+    }
+
+    /// Describes the simul: the engine and, for each board, its opponent, the number of moves
+    /// played and where the game stands.
+    fn simul_status(&self) -> Value {
+        let Some(simul) = &self.simul else {
+            return error("no_simul", "This server isn't giving a simul.");
+        };
+        let boards = simul
+            .rooms
+            .iter()
+            .map(|room| {
+                let Some(game) = self.rooms.get(room) else {
+                    return json!({ "room": room, "status": "closed" });
+                };
+                let status = match &game.result {
+                    Some(result) => result["result"].as_str().unwrap_or_default(),
+                    None if game.tokens[seat_index(BLACK)].is_none() => "open",
+                    None if simul.thinking.as_ref() == Some(room) => "thinking",
+                    None if game.board.side_to_move() == WHITE => "engine_to_move",
+                    None => "opponent_to_move",
+                };
+                json!({
+                    "room": room,
+                    "opponent": game.names[seat_index(BLACK)],
+                    "moves": game.board.moves,
+                    "status": status,
+                })
+            })
+            .collect::<Vec<Value>>();
+        json!({ "type": "simul", "engine": simul.engine, "boards": boards })
+    }
+
+    /// Ends the games whose side to move has run out of time, along with their partner games.
+    fn check_flags(&mut self) {
+        let flagged = self
+            .rooms
+            .iter_mut()
+            .filter_map(|(name, game)| game.check_flag().then(|| name.clone()))
+            .collect::<Vec<String>>();
+        for room in flagged {
+            self.finished(&room);
+            self.sync_partner(&room);
+        }
+    }
+
+    /// Rates the game in `room`, which has just ended, and checks it for fair play.
+    fn finished(&mut self, room: &str) {
+        let Some(game) = self.rooms.get(room) else {
+            return;
+        };
+        if let Some(accounts) = &mut self.accounts {
+            game.rate(accounts);
+        }
+        if let Some(path) = &self.fairplay {
+            game.check_fair_play(path);
+        }
+    }
+
+    /// Runs a command from the server console, returning what to print.
+    fn admin(&mut self, line: &str) -> String {
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        match words.as_slice() {
+            [] => String::new(),
+            ["help"] => ADMIN_HELP.to_string(),
+            ["games"] => self.games(),
+            ["kick", room, color @ ("white" | "black")] => {
+                self.kick(room, if *color == "white" { WHITE } else { BLACK })
+            }
+            ["abort", room] => match self.abort(room) {
+                true => format!("Aborted the game in {}.", room),
+                false => format!("There is no game in progress in {}.", room),
+            },
+            ["say", ..] => {
+                let text = line.trim_start()[3..].trim();
+                if text.is_empty() {
+                    return "Type a message after say.".to_string();
+                }
+                self.announce(text);
+                format!("Sent to {} connections.", self.clients.len())
+            }
+            ["drain"] => {
+                self.drain();
+                "Taking no new games. The server shuts down once the games in progress are over."
+                    .to_string()
+            }
+            ["shutdown"] => {
+                let rooms = self.rooms.keys().cloned().collect::<Vec<String>>();
+                let aborted = rooms.iter().filter(|room| self.abort(room)).count();
+                self.drain();
+                format!("Aborted {} games. Shutting down.", aborted)
+            }
+            _ => "Unknown command. Type 'help' for the list.".to_string(),
+        }
+    }
+
+    /// Lists every room for the console: its players, how far its game has got and who watches.
+    fn games(&self) -> String {
+        let mut names = self.rooms.keys().collect::<Vec<&String>>();
+        names.sort();
+        let mut listing = format!(
+            "{} connections, {} rooms{}",
+            self.clients.len(),
+            names.len(),
+            if self.draining { ", draining" } else { "" }
+        );
+        for name in names {
+            let game = &self.rooms[name];
+            let player = |color: u8| match (
+                &game.tokens[seat_index(color)],
+                &game.names[seat_index(color)],
+            ) {
+                (None, _) => "(open)".to_string(),
+                (Some(_), Some(name)) => name.clone(),
+                (Some(_), None) => "anonymous".to_string(),
+            };
+            let status = match &game.result {
+                Some(result) => format!("over, {}", result["result"].as_str().unwrap_or("?")),
+                None if game.moves.is_empty() => "not started".to_string(),
+                None => "in progress".to_string(),
+            };
+            listing += &format!(
+                "\n  {}: {}, {} against {}, {} moves, {} watching, {}",
+                name,
+                game.variant.name(),
+                player(WHITE),
+                player(BLACK),
+                game.moves.len(),
+                game.spectators.len(),
+                status
+            );
+        }
+        listing
+    }
+
+    /// Takes `color`'s seat in `room` away from its player and closes their connection. They
+    /// lose a game in progress; the seat of a game that hasn't started is opened to others.
+    fn kick(&mut self, room: &str, color: u8) -> String {
+        let Some(game) = self.rooms.get_mut(room) else {
+            return format!("There is no room {}.", room);
+        };
+        let index = seat_index(color);
+        if game.tokens[index].is_none() {
+            return format!("Nobody plays {} in {}.", color_name(color), room);
+        }
+        if game.engine_color() == Some(color) {
+            return "The engine can't be kicked.".to_string();
+        }
+        if let Some(player) = game.players[index].take() {
+            player.send(&json!({
+                "type": "kicked",
+                "message": "An administrator removed you from the game.",
+            }));
+            player.close();
+        }
+        // Without its token, the seat can't be taken back with a rejoin.
+        game.tokens[index] = None;
+        if game.result.is_some() || game.moves.is_empty() {
+            game.names[index] = None;
+        } else {
+            game.finish(if color == WHITE { "0-1" } else { "1-0" }, "kicked");
+            self.finished(room);
+            self.sync_partner(room);
+        }
+        self.remove_if_unused(room);
+        format!("Kicked {} from {}.", color_name(color), room)
+    }
+
+    /// Ends the game in `room` without a result, if it's still going. Returns whether it was.
+    fn abort(&mut self, room: &str) -> bool {
+        let Some(game) = self.rooms.get_mut(room) else {
+            return false;
+        };
+        if game.result.is_some() {
+            return false;
+        }
+        game.finish("*", "aborted");
+        self.finished(room);
+        self.sync_partner(room);
+        true
+    }
+
+    /// Sends `text` to every connection as an `announcement`.
+    fn announce(&self, text: &str) {
+        let announcement = json!({ "type": "announcement", "message": text });
+        for client in self.clients.values() {
+            client.send(&announcement);
+        }
+    }
+
+    /// Stops taking new games and aborts the ones that haven't started, so the server can shut
+    /// down once the rest are over.
+    fn drain(&mut self) {
+        if !self.draining {
+            self.announce("The server is shutting down once the games in progress are over.");
+        }
+        self.draining = true;
+        let unstarted = self
+            .rooms
+            .iter()
+            .filter(|(_, game)| game.result.is_none() && game.moves.is_empty())
+            .map(|(room, _)| room.clone())
+            .collect::<Vec<String>>();
+        for room in unstarted {
+            self.abort(&room);
+        }
+    }
+
+    /// Once the server is draining and no game is left in progress, closes every connection and
+    /// returns `true`, so the server can stop.
+    fn finish_draining(&mut self) -> bool {
+        let done = self.draining && self.rooms.values().all(|game| game.result.is_some());
+        if done {
+            for client in self.clients.values() {
+                client.close();
+            }
+        }
+        done
+    }
+
+    /// Closes `room` if nobody uses it (or its partner board), as when a join was turned down.
+    fn remove_if_unused(&mut self, room: &str) {
+        let Some(game) = self.rooms.get(room) else {
+            return;
+        };
+        let partner = game.partner.clone();
+        let unused = |partner: &String| self.rooms.get(partner).is_none_or(Game::is_unused);
+        if game.is_unused() && partner.iter().all(unused) {
+            self.rooms.remove(room);
+            if let Some(partner) = partner {
+                self.rooms.remove(&partner);
+            }
+        }
+    }
+
+    /// Forgets the connection holding `seat`, and the room too once nobody is left to finish the
+    /// game in it (or on its partner board), or once its last spectator leaves a room whose seats
+    /// nobody holds.
+    fn leave(&mut self, seat: &Seat, client: &Client) {
+        let game = match self.rooms.get_mut(&seat.room) {
+            Some(game) => game,
+            None => return,
+        };
+        match seat.color {
+            Some(color) => {
+                let player = &mut game.players[seat_index(color)];
+                // The seat may already have been taken over by a rejoin.
+                if player.as_ref().is_some_and(|player| player.id == client.id) {
+                    *player = None;
+                }
+            }
+            None => game
+                .spectators
+                .retain(|spectator| spectator.client.id != client.id),
+        }
+        let partner = game.partner.clone();
+        let done = |game: &Game| game.is_unused() || game.is_empty() && game.result.is_some();
+        if done(game)
+            && partner
+                .iter()
+                .all(|partner| self.rooms.get(partner).is_none_or(done))
+        {
+            self.rooms.remove(&seat.room);
+            if let Some(partner) = partner {
+                self.rooms.remove(&partner);
+            }
+        }
+    }
+}
+
+/// Checks the options of a `join` asking to play the engine, where `wanted` are the colors
+/// the player would take, and returns the engine's seat: the other color, searching to the
+/// `depth` and `nodes` asked for.
+fn engine_seat(
+    request: &Value,
+    wanted: &[u8],
+    variant: &'static dyn Variant,
+    clocks: Option<[u32; 2]>,
+) -> Result<EngineSeat, Value> {
+    let Some(&color) = wanted.first() else {
+        return Err(error(
+            "invalid_message",
+            "Spectators can't play the engine.",
+        ));
+    };
+    if variant.name() == "bughouse" {
+        return Err(error(
+            "invalid_variant",
+            "The engine doesn't play Bughouse.",
+        ));
+    }
+    if clocks.is_some() {
+        return Err(error(
+            "invalid_message",
+            "Games against the engine are untimed.",
+        ));
+    }
+    let limit = |field: &str, default: u64, max: u64| match &request[field] {
+        Value::Null => Ok(default),
+        limit => limit
+            .as_u64()
+            .filter(|n| (1..=max).contains(n))
+            .ok_or_else(|| {
+                error(
+                    "invalid_message",
+                    &format!("\"{}\" must be a whole number from 1 to {}.", field, max),
+                )
+            }),
+    };
+    Ok(EngineSeat {
+        color: if color == WHITE { BLACK } else { WHITE },
+        depth: limit("depth", MAX_DEPTH as u64, MAX_DEPTH as u64)? as u32,
+        nodes: limit("nodes", ENGINE_NODES, MAX_ENGINE_NODES)?,
+        thinking: false,
+    })
+}
+
+/// Ranks every registered player by rating.
+fn leaderboard(accounts: &Accounts) -> Value {
+    let players = accounts
+        .leaderboard()
+        .into_iter()
+        .map(|(name, rating, games)| {
+            json!({
+                "name": name,
+                "rating": rating.rating.round(),
+                "deviation": rating.deviation.round(),
+                "games": games,
+            })
+        })
+        .collect::<Vec<Value>>();
+    json!({ "type": "leaderboard", "players": players })
+}
+
+/// Describes what the engine thinks of `board`. Scores are in centipawns from white's point of
+/// view, so spectators don't have to flip them depending on whose turn it is.
+fn evaluation(board: &ChessBoard) -> Value {
+    let search = board.search(EVALUATION_DEPTH);
+    let score = if board.side_to_move() == WHITE {
+        search.score
+    } else {
+        -search.score
+    };
+    let pv = board.line_sans(&search.pv);
+    json!({
+        "type": "evaluation",
+        "fen": board.fen(),
+        "score": score,
+        "best_move": pv.first(),
+        "pv": pv,
+        "depth": search.depth,
+    })
+}
+
+/// Serves games over WebSocket on `port` until the process is stopped, or until the console
+/// shuts the server down once its games are over. When `accounts` is given,
+/// only the players registered in it may play. With `simul`, the engine also gives a simul. With
+/// `fairplay`, every finished game is checked for fair play and added to that report. With `tls`,
+/// connections are encrypted (`wss://`), which logging in with a password needs unless a proxy
+/// in front of the server encrypts them.
+pub fn serve(
+    port: u16,
+    accounts: Option<Accounts>,
+    simul: Option<Simul>,
+    fairplay: Option<PathBuf>,
+    tls: Option<Arc<ServerConfig>>,
+) -> Result<(), Error> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    match tls {
+        Some(_) => println!("Serving WebSocket games over TLS on port {}.", port),
+        None => println!("Serving WebSocket games on port {}.", port),
+    }
+    let mut lobby = match accounts {
+        Some(accounts) => Lobby::with_accounts(accounts),
+        None => Lobby::default(),
+    };
+    lobby.fairplay = fairplay;
+    let lobby = Arc::new(Mutex::new(lobby));
+    if let Some(simul) = simul {
+        let engine = simul.engine.start()?;
+        lobby
+            .lock()
+            .unwrap()
+            .start_simul(&engine.name(), simul.boards);
+        println!(
+            "Giving a simul on {} boards, in the rooms {}/1 to {}/{}.",
+            simul.boards, SIMUL_ROOM, SIMUL_ROOM, simul.boards
+        );
+        let lobby = Arc::clone(&lobby);
+        thread::spawn(move || give_simul(engine, simul.nodes, lobby));
+    }
+    let clocks = Arc::clone(&lobby);
+    thread::spawn(move || loop {
+        thread::sleep(CLOCK_TICK);
+        clocks.lock().unwrap().check_flags();
+    });
+    let engine = Arc::clone(&lobby);
+    thread::spawn(move || play_engine(engine));
+    let console = Arc::clone(&lobby);
+    thread::spawn(move || {
+        println!("Type 'help' for the admin commands.");
+        // Without a console, as when run as a service, this just stops.
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            let reply = console.lock().unwrap().admin(&line);
+            if !reply.is_empty() {
+                println!("{}", reply);
+            }
+        }
+    });
+
+    // Accepting without blocking, so the server notices when it's time to stop.
+    listener.set_nonblocking(true)?;
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                let lobby = Arc::clone(&lobby);
+                let tls = tls.clone();
+                thread::spawn(move || handle_connection(stream, tls, lobby));
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if lobby.lock().unwrap().finish_draining() {
+                    break;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    // Give the connections a moment to deliver their last messages.
+    thread::sleep(POLL_INTERVAL * 4);
+    println!("Every game is over. The server has shut down.");
+    Ok(())
+}
+
+/// Plays the engine's moves against the players who chose it, for as long as the server runs.
+/// Each move is searched on a thread of its own, without holding the lobby.
+fn play_engine(lobby: Arc<Mutex<Lobby>>) {
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let turns = lobby.lock().unwrap().engine_turns();
+        for (room, board, moves, depth, nodes) in turns {
+            let lobby = Arc::clone(&lobby);
+            thread::spawn(move || {
+                let mv = board.search_nodes(depth, nodes).best_move;
+                lobby.lock().unwrap().play_engine_move(&room, moves, mv);
+            });
+        }
+    }
+}
+
+/// Plays the engine's moves in the simul, one board at a time, for as long as the server runs.
+/// Each move is searched to at most `nodes` positions, so no board gets more of the engine's
+/// time than another, and the board that has waited longest goes first. After every move, the
+/// status of each board is printed.
+fn give_simul(mut engine: Engine, nodes: u64, lobby: Arc<Mutex<Lobby>>) {
+    let limits = Limits {
+        depth: MAX_DEPTH,
+        nodes,
+        clocks: None,
+    };
+    loop {
+        let next = lobby.lock().unwrap().next_simul_move();
+        let Some((room, start, moves)) = next else {
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        };
+        let mv = match engine.search(&start, &moves, limits) {
+            Ok(result) => result.best_move,
+            Err(e) => {
+                eprintln!("The engine failed on {}: {}", room, e);
+                None
+            }
+        };
+        let mut lobby = lobby.lock().unwrap();
+        lobby.play_simul_move(&room, moves.len(), mv);
+        for board in lobby.simul_status()["boards"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            let opponent = board["opponent"].as_str().unwrap_or("?");
+            println!(
+                "{}: {} against {} after {} moves",
+                board["room"].as_str().unwrap_or_default(),
+                board["status"].as_str().unwrap_or_default(),
+                opponent,
+                board["moves"]
+            );
+        }
+    }
+}
+
+/// Handles one message from `client` as `Lobby::handle` does, but checks the password of a
+/// `login` first, without holding the lobby: checking is slow on purpose, and every game would
+/// wait for it.
+fn handle_message(
+    lobby: &Mutex<Lobby>,
+    seat: &mut Option<Seat>,
+    client: &mut Client,
+    text: &str,
+) -> Value {
+    let request = serde_json::from_str::<Value>(text).unwrap_or_default();
+    client.password_checked = None;
+    if let ("login", Some(name), Some(password)) = (
+        request["type"].as_str().unwrap_or_default(),
+        request["name"].as_str(),
+        request["password"].as_str(),
+    ) {
+        let credentials = lobby
+            .lock()
+            .unwrap()
+            .accounts
+            .as_ref()
+            .and_then(|accounts| accounts.credentials(name));
+        if credentials.is_some_and(|credentials| credentials.verify(password)) {
+            client.password_checked = Some(name.to_string());
+        }
+    }
+    lobby.lock().unwrap().handle(seat, client, text)
+}
+
+fn handle_connection(stream: TcpStream, tls: Option<Arc<ServerConfig>>, lobby: Arc<Mutex<Lobby>>) {
+    let address = stream
+        .peer_addr()
+        .map_or("an unknown address".to_string(), |address| {
+            address.to_string()
+        });
+    let _span = tracing::info_span!(target: "net", "ws", %address).entered();
+    tracing::info!(target: "net", "{} connected", address);
+    let mut socket = match tls::accept(stream, tls.as_ref()).map(accept) {
+        Ok(Ok(socket)) => socket,
+        _ => return,
+    };
+    // Reads time out regularly so that messages from other players get delivered promptly.
+    if socket
+        .get_mut()
+        .tcp()
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .is_err()
+    {
+        return;
+    }
+
+    let (sender, outgoing) = channel::<String>();
+    let mut client = Client::new(sender);
+    lobby
+        .lock()
+        .unwrap()
+        .clients
+        .insert(client.id, client.clone());
+    let mut seat = None;
+    'connection: loop {
+        match socket.read() {
+            Ok(WsMessage::Text(text)) => {
+                tracing::debug!(target: "net", "{} asked {}", address, text.as_str());
+                let reply = handle_message(&lobby, &mut seat, &mut client, text.as_str());
+                client.send(&reply);
+            }
+            Ok(WsMessage::Close(_)) => break,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => break,
+        }
+        while let Ok(text) = outgoing.try_recv() {
+            if socket.send(WsMessage::Text(text.into())).is_err() {
+                break 'connection;
+            }
+        }
+        if client.is_closed() {
+            let _ = socket.close(None);
+            let _ = socket.flush();
+            break;
+        }
+    }
+
+    let mut lobby = lobby.lock().unwrap();
+    if let Some(seat) = seat {
+        lobby.leave(&seat, &client);
+    }
+    lobby.clients.remove(&client.id);
+    tracing::info!(target: "net", "{} left", address);
+}
+
+#[test]
+fn test_ws_game() {
+    let mut lobby = Lobby::default();
+    let report = std::env::temp_dir().join(format!("ws-fairplay-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&report);
+    lobby.fairplay = Some(report.clone());
+    let (white, white_inbox) = channel();
+    let mut white = Client::new(white);
+    let (black, black_inbox) = channel();
+    let mut black = Client::new(black);
+    let (mut white_seat, mut black_seat) = (None, None);
+
+    let odds = r#"{"type":"join","black_minutes":1}"#;
+    let reply = lobby.handle(&mut white_seat, &mut white, odds);
+    assert_eq!(reply["reason"], "invalid_message");
+    let odds = r#"{"type":"join","minutes":5,"black_minutes":1}"#;
+    let reply = lobby.handle(&mut white_seat, &mut white, odds);
+    assert_eq!(reply["type"], "state");
+    assert_eq!(
+        reply["clocks"],
+        json!({ "white_ms": 300_000, "black_ms": 60_000 })
+    );
+    assert_eq!(white_seat.as_ref().unwrap().color, Some(WHITE));
+    let reply = lobby.handle(
+        &mut black_seat,
+        &mut black,
+        r#"{"type":"join","color":"white"}"#,
+    );
+    assert_eq!(reply["reason"], "seat_taken");
+    lobby.handle(
+        &mut black_seat,
+        &mut black,
+        r#"{"type":"join","color":"black"}"#,
+    );
+    assert_eq!(black_seat.as_ref().unwrap().color, Some(BLACK));
+
+    let reply = lobby.handle(
+        &mut black_seat,
+        &mut black,
+        r#"{"type":"move","move":"e5"}"#,
+    );
+    assert_eq!(reply["reason"], "not_your_turn");
+    let reply = lobby.handle(
+        &mut white_seat,
+        &mut white,
+        r#"{"type":"move","move":"Nf4"}"#,
+    );
+    assert_eq!(reply["reason"], "cannot_reach");
+    assert_eq!(reply["message"], "That knight can't reach f4.");
+
+    // Black's premoves: e5 is played the moment white has moved, Nd5 is dropped, and a
+    // premove sent on black's own turn is played as a move.
+    let premove = |lobby: &mut Lobby, seat: &mut Option<Seat>, client: &mut Client, san| {
+        let request = json!({ "type": "premove", "move": san }).to_string();
+        assert_eq!(lobby.handle(seat, client, &request)["type"], "ok");
+    };
+    premove(&mut lobby, &mut black_seat, &mut black, "Nc6");
+    let cancel = lobby.handle(&mut black_seat, &mut black, r#"{"type":"premove"}"#);
+    assert_eq!(cancel["type"], "ok");
+    premove(&mut lobby, &mut black_seat, &mut black, "e5");
+    let f3 = r#"{"type":"move","move":"f3"}"#;
+    assert_eq!(lobby.handle(&mut white_seat, &mut white, f3)["type"], "ok");
+    assert_eq!(lobby.rooms["main"].moves.len(), 2);
+    premove(&mut lobby, &mut black_seat, &mut black, "Nd5");
+    let g4 = r#"{"type":"move","move":"g4"}"#;
+    assert_eq!(lobby.handle(&mut white_seat, &mut white, g4)["type"], "ok");
+    assert_eq!(lobby.rooms["main"].moves.len(), 3);
+    premove(&mut lobby, &mut black_seat, &mut black, "Qh4#");
+
+    let received = black_inbox.try_iter().collect::<Vec<String>>();
+    let dropped = received
+        .iter()
+        .map(|message| serde_json::from_str::<Value>(message).unwrap())
+        .find(|message| message["type"] == "premove_dropped")
+        .unwrap();
+    assert_eq!(
+        (&dropped["move"], &dropped["reason"]),
+        (&json!("Nd5"), &json!("cannot_reach"))
+    );
+    assert!(received[0].contains(r#""type":"joined""#));
+    assert!(received.last().unwrap().contains(r#""result":"0-1""#));
+    let result: Value = serde_json::from_str(received.last().unwrap()).unwrap();
+    let pgn = result["pgn"].as_str().unwrap();
+    assert!(pgn.contains("[Event \"main\"]\n"));
+    assert!(pgn.contains("[WhiteTimeControl \"300\"]\n[BlackTimeControl \"60\"]\n"));
+    assert!(pgn.ends_with("\n1. f3 e5 2. g4 Qh4# 0-1\n"));
+    let state = white_inbox
+        .try_iter()
+        .filter(|message| message.contains(r#""type":"state""#))
+        .last()
+        .unwrap();
+    assert!(state.contains(r#""last_move":"Qh4#""#));
+    assert!(state.contains(r#""legal_moves":[]"#));
+
+    let reply = lobby.handle(&mut white_seat, &mut white, r#"{"type":"resign"}"#);
+    assert_eq!(reply["reason"], "game_over");
+
+    // The game is checked for fair play in the background.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !std::fs::read_to_string(&report).is_ok_and(|text| text.ends_with('\n')) {
+        assert!(Instant::now() < deadline, "no fair-play report");
+        thread::sleep(Duration::from_millis(10));
+    }
+    let line: Value = serde_json::from_str(&std::fs::read_to_string(&report).unwrap()).unwrap();
+    std::fs::remove_file(&report).unwrap();
+    assert_eq!(
+        (&line["room"], &line["result"]),
+        (&json!("main"), &json!("0-1"))
+    );
+    // Four moves are all opening, so none are counted.
+    assert_eq!(line["black"]["moves"], 0);
+    // The clocks start with f3, and e5 was a premove.
+    let move_ms = &lobby.rooms["main"].move_ms;
+    assert_eq!(move_ms[..2], [None, None]);
+    assert!(move_ms[2..].iter().all(Option::is_some));
+}
+
+#[test]
+fn test_ws_lobby() {
+    let mut lobby = Lobby::default();
+    let (white, white_inbox) = channel();
+    let mut white = Client::new(white);
+    let (watcher, watcher_inbox) = channel();
+    let mut watcher = Client::new(watcher);
+    let (mut white_seat, mut watcher_seat) = (None, None);
+
+    let join = r#"{"type":"join","room":"blitz","color":"white"}"#;
+    lobby.handle(&mut white_seat, &mut white, join);
+    let joined: Value = serde_json::from_str(&white_inbox.try_recv().unwrap()).unwrap();
+    assert_eq!(joined["room"], "blitz");
+    let token = joined["token"].as_str().unwrap().to_string();
+    assert_eq!(token.len(), 32);
+
+    let listing = lobby.handle(&mut None, &mut watcher, r#"{"type":"lobby"}"#);
+    assert_eq!(listing["rooms"][0]["room"], "blitz");
+    assert_eq!(listing["rooms"][0]["open_seats"], json!(["black"]));
+
+    let spectate = r#"{"type":"join","room":"blitz","color":"spectator","evaluation":true}"#;
+    lobby.handle(&mut watcher_seat, &mut watcher, spectate);
+    assert_eq!(watcher_seat.as_ref().unwrap().color, None);
+    let reply = lobby.handle(
+        &mut watcher_seat,
+        &mut watcher,
+        r#"{"type":"move","move":"e4"}"#,
+    );
+    assert_eq!(reply["reason"], "spectator");
+    lobby.handle(
+        &mut white_seat,
+        &mut white,
+        r#"{"type":"move","move":"e4"}"#,
+    );
+    // The evaluation of the position from before e4 may be dropped, but not the one after it.
+    let mut seen_move = false;
+    loop {
+        let message = watcher_inbox.recv_timeout(Duration::from_secs(10)).unwrap();
+        seen_move |= message.contains(r#""last_move":"e4""#);
+        if message.contains(r#""type":"evaluation""#) && message.contains("/4P3/") {
+            break;
+        }
+    }
+    assert!(seen_move);
+
+    // Disconnecting keeps the seat for the token holder.
+    lobby.leave(white_seat.as_ref().unwrap(), &white);
+    let (other, _other_inbox) = channel();
+    let mut other = Client::new(other);
+    let reply = lobby.handle(&mut None, &mut other, join);
+    assert_eq!(reply["reason"], "seat_taken");
+    let rejoin = json!({ "type": "rejoin", "room": "blitz", "token": "nope" }).to_string();
+    assert_eq!(
+        lobby.handle(&mut None, &mut other, &rejoin)["reason"],
+        "invalid_token"
+    );
+    let (back, _back_inbox) = channel();
+    let mut back = Client::new(back);
+    let mut back_seat = None;
+    let rejoin = json!({ "type": "rejoin", "room": "blitz", "token": token }).to_string();
+    let reply = lobby.handle(&mut back_seat, &mut back, &rejoin);
+    assert_eq!(reply["side_to_move"], "black");
+    assert_eq!(back_seat, white_seat);
+
+    // A second room is independent of the first.
+    let mut other_seat = None;
+    lobby.handle(
+        &mut other_seat,
+        &mut other,
+        r#"{"type":"join","room":"casual"}"#,
+    );
+    assert_eq!(other_seat.as_ref().unwrap().color, Some(WHITE));
+    assert_eq!(lobby.listing()["rooms"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_ws_simul() {
+    let mut lobby = Lobby::default();
+    assert_eq!(lobby.simul_status()["reason"], "no_simul");
+    lobby.start_simul("Engine", 2);
+    assert!(lobby.next_simul_move().is_none());
+
+    let mut opponents = Vec::new();
+    for _ in 0..3 {
+        let (sender, inbox) = channel();
+        let mut client = Client::new(sender);
+        let mut seat = None;
+        let reply = lobby.handle(&mut seat, &mut client, r#"{"type":"join","room":"simul"}"#);
+        opponents.push((client, seat, inbox, reply));
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(opponents[0].1.as_ref().unwrap().room, "simul/1");
+    assert_eq!(opponents[1].1.as_ref().unwrap().room, "simul/2");
+    assert_eq!(opponents[1].1.as_ref().unwrap().color, Some(BLACK));
+    assert_eq!(opponents[2].3["reason"], "seat_taken");
+
+    // The board that has waited longest goes first, and is marked as being thought about.
+    let (room, start, moves) = lobby.next_simul_move().unwrap();
+    assert_eq!((room.as_str(), moves.len()), ("simul/1", 0));
+    let status = lobby.simul_status();
+    assert_eq!(status["engine"], "Engine");
+    assert_eq!(status["boards"][0]["status"], "thinking");
+    assert_eq!(status["boards"][1]["status"], "engine_to_move");
+    lobby.play_simul_move(&room, 0, start.parse_move("e4").ok());
+    let (client, seat, inbox, _) = &mut opponents[0];
+    assert!(inbox
+        .try_iter()
+        .any(|message| message.contains(r#""last_move":"e4""#)));
+    assert_eq!(
+        lobby.simul_status()["boards"][0]["status"],
+        "opponent_to_move"
+    );
+    lobby.handle(seat, client, r#"{"type":"move","move":"e5"}"#);
+
+    // Board 2 has waited longer than board 1 since. A move found after the opponent resigned
+    // isn't played.
+    let (room, _, moves) = lobby.next_simul_move().unwrap();
+    assert_eq!(room, "simul/2");
+    let (client, seat, _, _) = &mut opponents[1];
+    lobby.handle(seat, client, r#"{"type":"resign"}"#);
+    lobby.play_simul_move(&room, moves.len(), start.parse_move("d4").ok());
+    assert_eq!(lobby.rooms["simul/2"].moves.len(), 0);
+    assert_eq!(lobby.simul_status()["boards"][1]["status"], "1-0");
+    assert_eq!(lobby.next_simul_move().unwrap().0, "simul/1");
+    lobby.play_simul_move("simul/1", 2, None);
+    assert_eq!(
+        lobby.rooms["simul/1"].result.as_ref().unwrap()["reason"],
+        "engine_failure"
+    );
+}
+
+#[test]
+fn test_ws_engine() {
+    let mut lobby = Lobby::default();
+    let (sender, inbox) = channel();
+    let mut client = Client::new(sender);
+    let mut seat = None;
+    let join = r#"{"type":"join","room":"solo","color":"black","opponent":"engine","nodes":2000}"#;
+    assert_eq!(lobby.handle(&mut seat, &mut client, join)["type"], "state");
+    assert_eq!(seat.as_ref().unwrap().color, Some(BLACK));
+    let listing = lobby.listing();
+    assert!(listing["rooms"][0]["white"]
+        .as_str()
+        .unwrap()
+        .starts_with("FreightChess"));
+    assert_eq!(listing["rooms"][0]["open_seats"], json!([]));
+
+    // The engine plays white, one search at a time.
+    let turns = lobby.engine_turns();
+    assert_eq!(turns.len(), 1);
+    let (room, board, moves, depth, nodes) = turns[0].clone();
+    assert_eq!(
+        (room.as_str(), moves, depth, nodes),
+        ("solo", 0, MAX_DEPTH, 2000)
+    );
+    assert!(lobby.engine_turns().is_empty());
+    let reply = lobby.handle(&mut seat, &mut client, r#"{"type":"move","move":"e5"}"#);
+    assert_eq!(reply["reason"], "not_your_turn");
+    lobby.play_engine_move(&room, moves, board.search_nodes(depth, nodes).best_move);
+    assert!(inbox
+        .try_iter()
+        .any(|message| message.contains(r#""side_to_move":"black""#)));
+    assert!(lobby.engine_turns().is_empty());
+    let reply = lobby.handle(&mut seat, &mut client, r#"{"type":"resign"}"#);
+    assert_eq!(reply["type"], "ok");
+    assert_eq!(
+        lobby.rooms["solo"].result.as_ref().unwrap()["result"],
+        "1-0"
+    );
+    assert_eq!(
+        lobby.admin("kick solo white"),
+        "The engine can't be kicked."
+    );
+
+    let refused = |lobby: &mut Lobby, join: &str| {
+        let (sender, _) = channel();
+        lobby.handle(&mut None, &mut Client::new(sender), join)["reason"].clone()
+    };
+    let join = r#"{"type":"join","room":"solo","opponent":"engine"}"#;
+    assert_eq!(refused(&mut lobby, join), "room_taken");
+    let join = r#"{"type":"join","room":"x","opponent":"engine","color":"spectator"}"#;
+    assert_eq!(refused(&mut lobby, join), "invalid_message");
+    let join = r#"{"type":"join","room":"x","opponent":"engine","minutes":5}"#;
+    assert_eq!(refused(&mut lobby, join), "invalid_message");
+    let join = r#"{"type":"join","room":"x","opponent":"engine","nodes":0}"#;
+    assert_eq!(refused(&mut lobby, join), "invalid_message");
+    let join = r#"{"type":"join","room":"x","opponent":"human"}"#;
+    assert_eq!(refused(&mut lobby, join), "invalid_message");
+    assert!(!lobby.rooms.contains_key("x"));
+
+    // Without a color, the player takes white and the engine waits for them.
+    let (sender, _) = channel();
+    let mut seat = None;
+    let join = r#"{"type":"join","room":"y","opponent":"engine","depth":1}"#;
+    lobby.handle(&mut seat, &mut Client::new(sender), join);
+    assert_eq!(seat.unwrap().color, Some(WHITE));
+    assert_eq!(lobby.rooms["y"].engine.as_ref().unwrap().depth, 1);
+    assert!(lobby.engine_turns().is_empty());
+}
+
+#[test]
+fn test_ws_admin() {
+    let mut lobby = Lobby::default();
+    let connect = |lobby: &mut Lobby, join: &str| {
+        let (sender, inbox) = channel();
+        let mut client = Client::new(sender);
+        lobby.clients.insert(client.id, client.clone());
+        let mut seat = None;
+        lobby.handle(&mut seat, &mut client, join);
+        (client, seat, inbox)
+    };
+    let (mut white, mut white_seat, _) = connect(&mut lobby, r#"{"type":"join","room":"a"}"#);
+    let (black, _, black_inbox) = connect(&mut lobby, r#"{"type":"join","room":"a"}"#);
+    let (waiting, _, _) = connect(&mut lobby, r#"{"type":"join","room":"b"}"#);
+    connect(&mut lobby, r#"{"type":"join","room":"c","color":"white"}"#);
+    let (_, _, watcher_inbox) = connect(
+        &mut lobby,
+        r#"{"type":"join","room":"a","color":"spectator"}"#,
+    );
+    lobby.handle(
+        &mut white_seat,
+        &mut white,
+        r#"{"type":"move","move":"e4"}"#,
+    );
+
+    assert_eq!(
+        lobby.admin("games"),
+        "5 connections, 3 rooms\n  \
+        a: standard, anonymous against anonymous, 1 moves, 1 watching, in progress\n  \
+        b: standard, anonymous against (open), 0 moves, 0 watching, not started\n  \
+        c: standard, anonymous against (open), 0 moves, 0 watching, not started"
+    );
+
+    // Kicking from a game that hasn't started opens the seat; from one in progress, loses it.
+    assert_eq!(lobby.admin("kick b white"), "Kicked white from b.");
+    assert!(waiting.is_closed());
+    // Nobody is left in the room, so it goes.
+    assert!(!lobby.rooms.contains_key("b"));
+    assert_eq!(lobby.admin("kick c black"), "Nobody plays black in c.");
+    lobby.admin("kick a black");
+    assert!(black.is_closed());
+    let result = lobby.rooms["a"].result.clone().unwrap();
+    assert_eq!(
+        (&result["result"], &result["reason"]),
+        (&json!("1-0"), &json!("kicked"))
+    );
+    assert!(black_inbox
+        .try_iter()
+        .any(|message| message.contains(r#""type":"kicked""#)));
+    assert_eq!(lobby.admin("abort a"), "There is no game in progress in a.");
+
+    assert_eq!(lobby.admin("say back in five"), "Sent to 5 connections.");
+    assert!(watcher_inbox
+        .try_iter()
+        .any(|message| message.contains(r#""message":"back in five""#)));
+
+    // Draining aborts the games that haven't started and turns new players away.
+    let (mut late, mut late_seat, _) = connect(&mut lobby, r#"{"type":"join","room":"d"}"#);
+    let (other, _, _) = connect(&mut lobby, r#"{"type":"join","room":"d"}"#);
+    lobby.handle(&mut late_seat, &mut late, r#"{"type":"move","move":"d4"}"#);
+    lobby.admin("drain");
+    assert_eq!(
+        lobby.rooms["c"].result.as_ref().unwrap()["reason"],
+        "aborted"
+    );
+    assert!(lobby.rooms["d"].result.is_none());
+    let reply = lobby.handle(&mut None, &mut late, r#"{"type":"join","room":"e"}"#);
+    assert_eq!(reply["reason"], "draining");
+    // Spectators may still watch the games left, but can't open a room that would keep the
+    // server from shutting down.
+    let (mut watcher, _, _) = connect(&mut lobby, r#"{"type":"lobby"}"#);
+    let watch = |room: &str| format!(r#"{{"type":"join","room":"{}","color":"spectator"}}"#, room);
+    let reply = lobby.handle(&mut None, &mut watcher, &watch("e"));
+    assert_eq!(reply["reason"], "no_game");
+    assert!(!lobby.rooms.contains_key("e"));
+    let mut watcher_seat = None;
+    let reply = lobby.handle(&mut watcher_seat, &mut watcher, &watch("d"));
+    assert_eq!(reply["type"], "state");
+    assert!(!lobby.finish_draining());
+    assert_eq!(lobby.admin("abort d"), "Aborted the game in d.");
+    assert_eq!(lobby.rooms["d"].result.as_ref().unwrap()["result"], "*");
+    assert!(lobby.finish_draining());
+    assert!(late.is_closed() && other.is_closed() && watcher.is_closed());
+
+    // A room whose seats were all given up goes with its last spectator.
+    let mut lobby = Lobby::default();
+    let (mut player, mut player_seat, _) = connect(&mut lobby, r#"{"type":"join","room":"f"}"#);
+    let (watcher, watcher_seat, _) = connect(&mut lobby, &watch("f"));
+    lobby.admin("kick f white");
+    assert!(lobby.rooms.contains_key("f"));
+    lobby.leave(&watcher_seat.unwrap(), &watcher);
+    assert!(!lobby.rooms.contains_key("f"));
+    let reply = lobby.handle(&mut player_seat, &mut player, r#"{"type":"state"}"#);
+    assert_eq!(reply["reason"], "not_joined");
+}
+
+#[test]
+fn test_ws_accounts() {
+    let path = std::env::temp_dir().join(format!(
+        "freight_chess_ws_players_{}.json",
+        std::process::id()
+    ));
+    let mut accounts = Accounts::load(&path).unwrap();
+    accounts.set_password("alice", "hunter2").unwrap();
+    accounts.set_password("bob", "swordfish").unwrap();
+    let lobby = Mutex::new(Lobby::with_accounts(accounts));
+    let (alice, alice_inbox) = channel();
+    let mut alice = Client::new(alice);
+    let mut seat = None;
+
+    let join = r#"{"type":"join","color":"black"}"#;
+    assert_eq!(
+        handle_message(&lobby, &mut seat, &mut alice, join)["reason"],
+        "login_required"
+    );
+    let wrong = r#"{"type":"login","name":"alice","password":"hunter3"}"#;
+    assert_eq!(
+        handle_message(&lobby, &mut seat, &mut alice, wrong)["reason"],
+        "login_failed"
+    );
+    let login = r#"{"type":"login","name":"alice","password":"hunter2"}"#;
+    // The password is checked before the lobby sees the login, never while it is locked.
+    let reply = lobby.lock().unwrap().handle(&mut seat, &mut alice, login);
+    assert_eq!(reply["reason"], "login_failed");
+    let reply = handle_message(&lobby, &mut seat, &mut alice, login);
+    assert_eq!(reply["type"], "logged_in");
+    let token = reply["token"].as_str().unwrap().to_string();
+
+    // A later connection can log in again with the session token instead of the password.
+    let (again, _again_inbox) = channel();
+    let mut again = Client::new(again);
+    let login = json!({ "type": "login", "token": token }).to_string();
+    assert_eq!(
+        handle_message(&lobby, &mut None, &mut again, &login)["name"],
+        "alice"
+    );
+
+    handle_message(&lobby, &mut seat, &mut alice, join);
+    assert_eq!(seat.as_ref().unwrap().color, Some(BLACK));
+    assert_eq!(
+        lobby.lock().unwrap().listing()["rooms"][0]["black"],
+        "alice"
+    );
+    let (white, _white_inbox) = channel();
+    let mut white = Client::new(white);
+    let mut white_seat = None;
+    let reply = handle_message(
+        &lobby,
+        &mut white_seat,
+        &mut white,
+        r#"{"type":"join","color":"spectator"}"#,
+    );
+    assert_eq!(reply["type"], "state");
+    handle_message(&lobby, &mut seat, &mut alice, r#"{"type":"resign"}"#);
+    let result = alice_inbox
+        .try_iter()
+        .find(|message| message.contains(r#""type":"result""#))
+        .unwrap();
+    assert!(result.contains(r#"[White \"?\"]\n[Black \"alice\"]\n[Result \"1-0\"]"#));
+
+    // The game was not rated, since white was anonymous.
+    assert_eq!(
+        handle_message(&lobby, &mut None, &mut white, r#"{"type":"leaderboard"}"#)["players"][0]
+            ["games"],
+        0
+    );
+
+    // Games between two registered players are.
+    let (bob, bob_inbox) = channel();
+    let mut bob = Client::new(bob);
+    let mut bob_seat = None;
+    let login = r#"{"type":"login","name":"bob","password":"swordfish"}"#;
+    handle_message(&lobby, &mut bob_seat, &mut bob, login);
+    let (mut alice_seat, room) = (None, r#"{"type":"join","room":"rated","color":"white"}"#);
+    handle_message(&lobby, &mut alice_seat, &mut again, room);
+    handle_message(
+        &lobby,
+        &mut bob_seat,
+        &mut bob,
+        r#"{"type":"join","room":"rated"}"#,
+    );
+    handle_message(&lobby, &mut alice_seat, &mut again, r#"{"type":"resign"}"#);
+    let ratings = bob_inbox
+        .try_iter()
+        .find(|message| message.contains(r#""type":"ratings""#))
+        .unwrap();
+    let ratings: Value = serde_json::from_str(&ratings).unwrap();
+    assert_eq!(ratings["black"]["name"], "bob");
+    assert_eq!(ratings["black"]["rating"], 1662.0);
+    let leaderboard = handle_message(&lobby, &mut None, &mut white, r#"{"type":"leaderboard"}"#);
+    assert_eq!(leaderboard["players"][0]["name"], "bob");
+    assert_eq!(leaderboard["players"][1]["games"], 1);
+    assert!(Accounts::load(&path).unwrap().leaderboard()[0].0 == "bob");
+    std::fs::remove_file(&path).unwrap();
+
+    let reply = Lobby::default().handle(&mut None, &mut white, r#"{"type":"leaderboard"}"#);
+    assert_eq!(reply["reason"], "no_accounts");
+}
+
+#[test]
+fn test_ws_bughouse() {
+    let mut lobby = Lobby::default();
+    let mut players = Vec::new();
+    for _ in 0..4 {
+        let (sender, inbox) = channel();
+        players.push((Client::new(sender), inbox, None));
+    }
+    let join = r#"{"type":"join","room":"team","variant":"bughouse","minutes":5}"#;
+    for (client, _, seat) in &mut players {
+        let reply = lobby.handle(seat, client, join);
+        assert_eq!(reply["variant"], "bughouse");
+    }
+    let seats = players
+        .iter()
+        .map(|(_, _, seat)| seat.clone().unwrap())
+        .collect::<Vec<Seat>>();
+    assert_eq!(seats[0].room, "team/1");
+    assert_eq!(seats[1].color, Some(BLACK));
+    assert_eq!(seats[3].room, "team/2");
+    assert_eq!(lobby.listing()["rooms"][0]["partner"], "team/2");
+    assert_eq!(
+        seats[2],
+        Seat {
+            room: "team/2".to_string(),
+            color: Some(WHITE)
+        }
+    );
+    let (late, _late_inbox) = channel();
+    let mut late = Client::new(late);
+    assert_eq!(
+        lobby.handle(&mut None, &mut late, join)["reason"],
+        "seat_taken"
+    );
+
+    // The clocks of board 2 start with board 1's first move.
+    assert!(lobby.rooms["team/2"].turn_started.is_none());
+    let (client, _, seat) = &mut players[0];
+    let reply = lobby.handle(seat, client, r#"{"type":"move","move":"e4"}"#);
+    assert_eq!(reply["type"], "ok");
+    let started = lobby.rooms["team/1"].turn_started;
+    assert!(started.is_some());
+    assert_eq!(lobby.rooms["team/2"].turn_started, started);
+
+    let mut play = |player: usize, san: &str| {
+        let (client, _, seat) = &mut players[player];
+        let request = json!({ "type": "move", "move": san }).to_string();
+        lobby.handle(seat, client, &request)
+    };
+    for (player, san) in [(1, "d5"), (0, "exd5")] {
+        assert_eq!(play(player, san)["type"], "ok");
+    }
+    // The pawn white took on board 1 goes to its partner, who plays black on board 2.
+    assert_eq!(play(3, "P@e5")["reason"], "not_your_turn");
+    assert_eq!(play(2, "N@e4")["reason"], "cannot_drop");
+    assert_eq!(play(2, "d4")["type"], "ok");
+    assert_eq!(play(3, "P@e5")["type"], "ok");
+    assert_eq!(play(2, "dxe5")["type"], "ok");
+    assert_eq!(lobby.rooms["team/1"].board.pockets_fen(), "p");
+    let state = lobby.rooms["team/2"].state();
+    assert!(state["fen"].as_str().unwrap().contains("[]"));
+
+    // Losing one board loses the other.
+    let (client, _, seat) = &mut players[1];
+    lobby.handle(seat, client, r#"{"type":"resign"}"#);
+    let result = players[3]
+        .1
+        .try_iter()
+        .find(|message| message.contains(r#""type":"result""#))
+        .unwrap();
+    let result: Value = serde_json::from_str(&result).unwrap();
+    assert_eq!(result["result"], "0-1");
+    assert_eq!(result["reason"], "partner_resignation");
+    assert!(result["pgn"]
+        .as_str()
+        .unwrap()
+        .contains("[Variant \"Bughouse\"]"));
+
+    // So does running out of time on one board.
+    let mut seat = None;
+    lobby.handle(
+        &mut seat,
+        &mut players[0].0,
+        r#"{"type":"join","room":"blitz","variant":"bughouse","board":2,"minutes":1}"#,
+    );
+    assert_eq!(seat.unwrap().room, "blitz/2");
+    lobby.rooms.get_mut("blitz/2").unwrap().turn_started = Some(Instant::now());
+    lobby.rooms.get_mut("blitz/2").unwrap().clocks = Some([0, 60_000]);
+    lobby.check_flags();
+    assert_eq!(
+        lobby.rooms["blitz/2"].result.as_ref().unwrap()["reason"],
+        "timeout"
+    );
+    let partner = lobby.rooms["blitz/1"].result.as_ref().unwrap();
+    assert_eq!(partner["result"], "1-0");
+    assert_eq!(partner["reason"], "partner_timeout");
+}
+
+#[test]
+fn test_ws_evaluations() {
+    let mut game = Game::new("blitz", &variant::STANDARD, None);
+    let (watcher, watcher_inbox) = channel();
+    game.spectators.push(Spectator {
+        client: Client::new(watcher),
+        evaluation: true,
+    });
+
+    // While the worker is busy, each new position replaces the one waiting for it.
+    game.evaluations.lock().unwrap().running = true;
+    for mv in ["e4", "e5", "Nf3"] {
+        game.board.apply_move(game.board.parse_move(mv).unwrap());
+        game.evaluate_for_spectators();
+    }
+    let waiting = game.evaluations.lock().unwrap().next.as_ref().unwrap().0;
+    assert_eq!(waiting.fen(), game.board.fen());
+
+    // Once it's idle, it evaluates the latest position alone, then stops.
+    game.evaluations.lock().unwrap().running = false;
+    game.evaluate_for_spectators();
+    let message = watcher_inbox.recv_timeout(Duration::from_secs(10)).unwrap();
+    let evaluation: Value = serde_json::from_str(&message).unwrap();
+    assert_eq!(evaluation["fen"], game.board.fen());
+    let started = Instant::now();
+    while game.evaluations.lock().unwrap().running {
+        assert!(started.elapsed() < Duration::from_secs(10));
+        thread::sleep(POLL_INTERVAL);
+    }
+    assert!(watcher_inbox.try_recv().is_err());
+}
@@ -0,0 +1,433 @@
+//! NNUE evaluation: a neural network, in the HalfKP format of Stockfish 12's `.nnue` files, that
+//! evaluates standard chess and Chess960 in place of the classical evaluation once it is loaded
+//! and switched on.
+//!
+//! The network's first layer has a weight for every pair of a king square and a piece (other than
+//! a king) on a square, seen from each side. Its sums, the accumulator, only change by a few
+//! weights with each move, so the search keeps a stack of them: playing a move pushes one updated
+//! from its parent's, and going back pops it. Only king moves need a full recount.
+
+use std::cell::RefCell;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+use crate::{ChessBoard, BISHOP, BLACK, GET_NUM, KNIGHT, PAWN, QUEEN, ROOK, WHITE};
+
+/// The version the file starts with.
+const VERSION: u32 = 0x7AF3_2F16;
+
+/// How many pieces on squares there are for each king square: one unused, then 64 squares for
+/// each of the five kinds of piece of each side.
+const PIECE_FEATURES: usize = 641;
+const FEATURES: usize = 64 * PIECE_FEATURES;
+/// The size of the accumulator for each side.
+const HALF: usize = 256;
+/// The size of each hidden layer.
+const HIDDEN: usize = 32;
+
+/// How much the network's output is scaled down by, and what a pawn is worth after that.
+const OUTPUT_SCALE: i32 = 16;
+const PAWN_VALUE: i32 = 208;
+
+/// A network read from a `.nnue` file.
+pub struct Network {
+    description: String,
+    feature_biases: Vec<i16>,
+    feature_weights: Vec<i16>,
+    hidden1_biases: Vec<i32>,
+    hidden1_weights: Vec<i8>,
+    hidden2_biases: Vec<i32>,
+    hidden2_weights: Vec<i8>,
+    output_bias: i32,
+    output_weights: Vec<i8>,
+}
+
+/// Reads the little-endian numbers a network is stored as.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes(&mut self, count: usize) -> Result<&[u8], Error> {
+        if self.0.len() < count {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "The network file is cut short.",
+            ));
+        }
+        let (bytes, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i16s(&mut self, count: usize) -> Result<Vec<i16>, Error> {
+        let bytes = self.bytes(count * 2)?;
+        Ok(bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect())
+    }
+
+    fn i32s(&mut self, count: usize) -> Result<Vec<i32>, Error> {
+        let bytes = self.bytes(count * 4)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|word| i32::from_le_bytes(word.try_into().unwrap()))
+            .collect())
+    }
+
+    fn i8s(&mut self, count: usize) -> Result<Vec<i8>, Error> {
+        Ok(self.bytes(count)?.iter().map(|&byte| byte as i8).collect())
+    }
+}
+
+/// The sums of the first layer, for white's view of the board and black's.
+#[derive(Clone)]
+pub struct Accumulator([[i16; HALF]; 2]);
+
+/// Turns a square around for black's view of the board.
+fn orient(perspective: u8, square: u8) -> usize {
+    match perspective {
+        WHITE => square as usize,
+        _ => (square ^ 63) as usize,
+    }
+}
+
+/// The input seen from `perspective`, with its king on `king`, for `piece` on `square`, or `None`
+/// for a king, which is only an input through where it stands.
+fn feature(perspective: u8, king: u8, piece: u8, square: u8) -> Option<usize> {
+    let kind = match GET_NUM(piece) {
+        PAWN => 0,
+        KNIGHT => 1,
+        BISHOP => 2,
+        ROOK => 3,
+        QUEEN => 4,
+        _ => return None,
+    };
+    let theirs = (piece & BLACK != perspective) as usize;
+    Some(
+        1 + (kind * 2 + theirs) * 64
+            + orient(perspective, square)
+            + PIECE_FEATURES * orient(perspective, king),
+    )
+}
+
+/// Adds `bias` to `inputs` times `weights`, each output's weights in a row, and clips the results
+/// to the next layer's inputs.
+fn layer(inputs: &[u8], biases: &[i32], weights: &[i8]) -> Vec<u8> {
+    biases
+        .iter()
+        .zip(weights.chunks_exact(inputs.len()))
+        .map(|(&bias, row)| {
+            let sum = inputs
+                .iter()
+                .zip(row)
+                .map(|(&input, &weight)| input as i32 * weight as i32)
+                .sum::<i32>();
+            ((bias + sum) >> 6).clamp(0, 127) as u8
+        })
+        .collect()
+}
+
+impl Network {
+    /// Reads a network from the contents of a `.nnue` file.
+    pub fn read(data: &[u8]) -> Result<Network, Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+        let mut reader = Reader(data);
+        if reader.u32()? != VERSION {
+            return Err(invalid("This isn't a HalfKP network file."));
+        }
+        reader.u32()?;
+        let length = reader.u32()? as usize;
+        let description = String::from_utf8_lossy(reader.bytes(length)?).into_owned();
+        reader.u32()?;
+        let feature_biases = reader.i16s(HALF)?;
+        let feature_weights = reader.i16s(FEATURES * HALF)?;
+        reader.u32()?;
+        let network = Network {
+            description,
+            feature_biases,
+            feature_weights,
+            hidden1_biases: reader.i32s(HIDDEN)?,
+            hidden1_weights: reader.i8s(HIDDEN * 2 * HALF)?,
+            hidden2_biases: reader.i32s(HIDDEN)?,
+            hidden2_weights: reader.i8s(HIDDEN * HIDDEN)?,
+            output_bias: reader.i32s(1)?[0],
+            output_weights: reader.i8s(HIDDEN)?,
+        };
+        if !reader.0.is_empty() {
+            return Err(invalid("The network file is longer than a HalfKP network."));
+        }
+        Ok(network)
+    }
+
+    /// Loads a network from the `.nnue` file at `path`.
+    pub fn load(path: &str) -> Result<Network, Error> {
+        Network::read(&std::fs::read(path)?)
+    }
+
+    /// What the network's trainer wrote about it.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Adds (`sign` 1) or takes away (`sign` -1) the weights of an input.
+    fn apply(&self, values: &mut [i16; HALF], feature: usize, sign: i16) {
+        let weights = &self.feature_weights[feature * HALF..(feature + 1) * HALF];
+        for (value, &weight) in values.iter_mut().zip(weights) {
+            *value = value.wrapping_add(sign.wrapping_mul(weight));
+        }
+    }
+
+    /// Counts up the accumulator of `board` from `perspective` from scratch.
+    fn refresh(&self, board: &ChessBoard, perspective: u8) -> [i16; HALF] {
+        let mut values = [0; HALF];
+        values.copy_from_slice(&self.feature_biases);
+        let king = board.king_square(perspective).unwrap_or(0);
+        for square in 0..64 {
+            let piece = board.get_piece(square);
+            if let Some(feature) = feature(perspective, king, piece, square) {
+                self.apply(&mut values, feature, 1);
+            }
+        }
+        values
+    }
+
+    /// Counts up the accumulator of `board` from scratch.
+    pub fn accumulator(&self, board: &ChessBoard) -> Accumulator {
+        Accumulator([self.refresh(board, WHITE), self.refresh(board, BLACK)])
+    }
+
+    /// The accumulator of `after`, a move on from `before`, whose accumulator is `accumulator`.
+    /// Only the pieces that moved change it, unless a king moved.
+    pub fn update(
+        &self,
+        accumulator: &Accumulator,
+        before: &ChessBoard,
+        after: &ChessBoard,
+    ) -> Accumulator {
+        let mut next = accumulator.clone();
+        for (side, perspective) in [WHITE, BLACK].into_iter().enumerate() {
+            let king = after.king_square(perspective).unwrap_or(0);
+            if before.king_square(perspective).unwrap_or(0) != king {
+                next.0[side] = self.refresh(after, perspective);
+                continue;
+            }
+            for square in 0..64 {
+                let (old, new) = (before.get_piece(square), after.get_piece(square));
+                if old == new {
+                    continue;
+                }
+                if let Some(feature) = feature(perspective, king, old, square) {
+                    self.apply(&mut next.0[side], feature, -1);
+                }
+                if let Some(feature) = feature(perspective, king, new, square) {
+                    self.apply(&mut next.0[side], feature, 1);
+                }
+            }
+        }
+        next
+    }
+
+    /// Evaluates the position with `accumulator` in centipawns for `side`, the side to move.
+    pub fn evaluate(&self, accumulator: &Accumulator, side: u8) -> i32 {
+        let ours = (side == BLACK) as usize;
+        let inputs = [ours, ours ^ 1]
+            .iter()
+            .flat_map(|&half| accumulator.0[half].iter())
+            .map(|&value| value.clamp(0, 127) as u8)
+            .collect::<Vec<u8>>();
+        let hidden = layer(&inputs, &self.hidden1_biases, &self.hidden1_weights);
+        let hidden = layer(&hidden, &self.hidden2_biases, &self.hidden2_weights);
+        let output = self.output_bias
+            + hidden
+                .iter()
+                .zip(&self.output_weights)
+                .map(|(&input, &weight)| input as i32 * weight as i32)
+                .sum::<i32>();
+        output / OUTPUT_SCALE * 100 / PAWN_VALUE
+    }
+}
+
+thread_local! {
+    /// The network evaluations on this thread use, if it is switched on.
+    static NETWORK: RefCell<Option<Arc<Network>>> = const { RefCell::new(None) };
+    /// The accumulators of the positions the search on this thread is looking at, from the root
+    /// to the current one.
+    static ACCUMULATORS: RefCell<Vec<Accumulator>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Makes evaluations on this thread use `network`, or the classical evaluation for `None`.
+pub fn set_network(network: Option<Arc<Network>>) {
+    NETWORK.with(|current| *current.borrow_mut() = network);
+    ACCUMULATORS.with(|stack| stack.borrow_mut().clear());
+}
+
+/// The network evaluations on this thread use, if it is switched on.
+pub fn network() -> Option<Arc<Network>> {
+    NETWORK.with(|network| network.borrow().clone())
+}
+
+/// The network evaluations of `board` use, if there is one and it knows the variant.
+fn network_for(board: &ChessBoard) -> Option<Arc<Network>> {
+    match board.variant.name() {
+        "standard" | "chess960" => network(),
+        _ => None,
+    }
+}
+
+/// Takes the accumulator it was made with off the stack when it goes out of scope.
+#[must_use]
+pub struct Made(bool);
+
+impl Drop for Made {
+    fn drop(&mut self) {
+        if self.0 {
+            ACCUMULATORS.with(|stack| stack.borrow_mut().pop());
+        }
+    }
+}
+
+/// Starts the accumulator stack at `board`, the root of a search. Until the returned guard is
+/// dropped, evaluations take the accumulator on top of the stack to be that of the position they
+/// are given.
+pub fn begin(board: &ChessBoard) -> Made {
+    let Some(network) = network_for(board) else {
+        return Made(false);
+    };
+    let accumulator = network.accumulator(board);
+    ACCUMULATORS.with(|stack| stack.borrow_mut().push(accumulator));
+    Made(true)
+}
+
+/// Puts the accumulator of `after`, a move on from `before`, on the stack until the returned
+/// guard is dropped.
+pub fn make(before: &ChessBoard, after: &ChessBoard) -> Made {
+    let Some(network) = network_for(after) else {
+        return Made(false);
+    };
+    ACCUMULATORS.with(|stack| {
+        let mut stack = stack.borrow_mut();
+        let accumulator = match stack.last() {
+            Some(parent) => network.update(parent, before, after),
+            None => network.accumulator(after),
+        };
+        stack.push(accumulator);
+    });
+    Made(true)
+}
+
+/// Evaluates `board` with the network, from the accumulator on top of the stack if there is one,
+/// or `None` if no network is switched on for its variant.
+pub fn evaluate(board: &ChessBoard) -> Option<i32> {
+    let network = network_for(board)?;
+    ACCUMULATORS.with(|stack| match stack.borrow().last() {
+        Some(accumulator) => Some(network.evaluate(accumulator, board.side_to_move())),
+        None => Some(network.evaluate(&network.accumulator(board), board.side_to_move())),
+    })
+}
+
+/// Writes a network of zeros but for `patch`, which sets the given weights, as a `.nnue` file.
+#[cfg(test)]
+pub(crate) fn test_network(patch: impl FnOnce(&mut Network)) -> Vec<u8> {
+    let mut network = Network {
+        description: "test".to_string(),
+        feature_biases: vec![0; HALF],
+        feature_weights: vec![0; FEATURES * HALF],
+        hidden1_biases: vec![0; HIDDEN],
+        hidden1_weights: vec![0; HIDDEN * 2 * HALF],
+        hidden2_biases: vec![0; HIDDEN],
+        hidden2_weights: vec![0; HIDDEN * HIDDEN],
+        output_bias: 0,
+        output_weights: vec![0; HIDDEN],
+    };
+    patch(&mut network);
+    let mut data = Vec::new();
+    for word in [VERSION, 0, network.description.len() as u32] {
+        data.extend(word.to_le_bytes());
+    }
+    data.extend(network.description.as_bytes());
+    data.extend(0u32.to_le_bytes());
+    data.extend(network.feature_biases.iter().flat_map(|v| v.to_le_bytes()));
+    data.extend(network.feature_weights.iter().flat_map(|v| v.to_le_bytes()));
+    data.extend(0u32.to_le_bytes());
+    for (biases, weights) in [
+        (&network.hidden1_biases, &network.hidden1_weights),
+        (&network.hidden2_biases, &network.hidden2_weights),
+        (&vec![network.output_bias], &network.output_weights),
+    ] {
+        data.extend(biases.iter().flat_map(|v| v.to_le_bytes()));
+        data.extend(weights.iter().map(|&v| v as u8));
+    }
+    data
+}
+
+#[test]
+fn test_nnue() {
+    // Each input's first accumulator value is its index, so it is easy to tell them apart.
+    let data = test_network(|network| {
+        for feature in 0..FEATURES {
+            network.feature_weights[feature * HALF] = (feature % 7919) as i16;
+            network.feature_weights[feature * HALF + 1] = (feature % 13) as i16;
+        }
+        // The first hidden value passes on the first of the side to move's accumulator values.
+        network.hidden1_weights[0] = 64;
+        network.hidden2_weights[0] = 64;
+        network.output_weights[0] = 1;
+        network.output_bias = 20 * OUTPUT_SCALE;
+    });
+    assert!(Network::read(&data[..data.len() - 1]).is_err());
+    assert!(Network::read(&[data.as_slice(), &[0]].concat()).is_err());
+    let network = Network::read(&data).unwrap();
+    assert_eq!(network.description(), "test");
+
+    // The accumulator kept up move by move matches one counted from scratch, through captures,
+    // castling, en passant, promotion and king moves.
+    let mut board = ChessBoard::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+    let mut accumulator = network.accumulator(&board);
+    for text in ["exd6", "Kd8", "O-O", "Rxa1", "b8=Q+", "Kd7", "Rxa1"] {
+        let mv = board.parse_san(text).unwrap();
+        let mut next = board;
+        next.apply_move(mv);
+        accumulator = network.update(&accumulator, &board, &next);
+        board = next;
+        let fresh = network.accumulator(&board);
+        assert_eq!(accumulator.0, fresh.0, "after {}", text);
+    }
+
+    // A white pawn on e2 with the white king on e1 is the same input to white as a black pawn on
+    // d7 with the black king on d8 is to black, who sees the board turned around.
+    let white = feature(WHITE, 4, PAWN, 12).unwrap();
+    assert_eq!(feature(BLACK, 59, PAWN | BLACK, 51), Some(white));
+    assert_eq!(feature(WHITE, 4, crate::KING, 12), None);
+    assert_ne!(feature(WHITE, 4, PAWN | BLACK, 12), Some(white));
+
+    // Switched on, the network evaluates standard chess, but not other variants.
+    let board = ChessBoard::new();
+    let expected = {
+        let accumulator = network.accumulator(&board);
+        let first = accumulator.0[0][0].clamp(0, 127) as i32;
+        (20 * OUTPUT_SCALE + first) / OUTPUT_SCALE * 100 / PAWN_VALUE
+    };
+    assert_eq!(evaluate(&board), None);
+    set_network(Some(Arc::new(network)));
+    assert_eq!(evaluate(&board), Some(expected));
+    assert_eq!(crate::eval::evaluate(&board), expected);
+    let mut crazyhouse = board;
+    crazyhouse.variant = crate::variant::by_name("crazyhouse").unwrap();
+    assert_eq!(evaluate(&crazyhouse), None);
+    {
+        let _root = begin(&board);
+        assert_eq!(ACCUMULATORS.with(|stack| stack.borrow().len()), 1);
+        let mut next = board;
+        next.apply_move(board.parse_san("e4").unwrap());
+        let _made = make(&board, &next);
+        assert_eq!(ACCUMULATORS.with(|stack| stack.borrow().len()), 2);
+    }
+    assert_eq!(ACCUMULATORS.with(|stack| stack.borrow().len()), 0);
+    assert!(board.search(2).best_move.is_some());
+    set_network(None);
+    assert_eq!(evaluate(&board), None);
+}
@@ -0,0 +1,70 @@
+//! Odds games, where the stronger player gives the other a head start by playing without some of
+//! their pieces. The player giving odds plays white, as is traditional.
+
+use crate::movegen::CASTLING_RIGHTS;
+use crate::{ChessBoard, EMPTY, KNIGHT, PAWN, QUEEN, ROOK};
+
+/// The odds that can be given, by name, with the squares of the white pieces they take off.
+pub const PRESETS: [(&str, &[(u8, u8)]); 5] = [
+    ("pawn", &[(13, PAWN)]),
+    ("knight", &[(1, KNIGHT)]),
+    ("rook", &[(0, ROOK)]),
+    ("two-knights", &[(1, KNIGHT), (6, KNIGHT)]),
+    ("queen", &[(3, QUEEN)]),
+];
+
+/// The names of the odds, for `--odds`.
+pub fn names() -> Vec<&'static str> {
+    PRESETS.iter().map(|(name, _)| *name).collect()
+}
+
+impl ChessBoard {
+    /// This position with white giving `odds` (one of `PRESETS`), or `None` if the pieces the
+    /// odds take off aren't on their usual squares. A rook taken off takes its castling right
+    /// with it.
+    pub fn with_odds(mut self, odds: &str) -> Option<ChessBoard> {
+        let (_, pieces) = PRESETS.iter().find(|(name, _)| *name == odds)?;
+        for &(square, piece) in *pieces {
+            if self.get_piece(square) != piece {
+                return None;
+            }
+            self.set_piece(square, EMPTY);
+            for (index, &rook) in self.castling_rooks.iter().enumerate() {
+                if rook == square {
+                    self.castling &= !CASTLING_RIGHTS[index];
+                }
+            }
+        }
+        Some(self)
+    }
+}
+
+#[test]
+fn test_odds() {
+    let board = ChessBoard::new().with_odds("rook").unwrap();
+    assert_eq!(
+        board.fen(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/1NBQKBNR w Kkq - 0 1"
+    );
+    let board = ChessBoard::new().with_odds("two-knights").unwrap();
+    assert_eq!(
+        board.fen(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKB1R w KQkq - 0 1"
+    );
+    assert!(board.with_odds("knight").is_none());
+    assert!(ChessBoard::new().with_odds("king").is_none());
+
+    // The engine knows who is ahead.
+    let board = ChessBoard::new().with_odds("queen").unwrap();
+    assert!(board.search(2).score < -500);
+
+    // PGN sets the position up, and reads it back.
+    let mut game = crate::pgn::PgnGame::new(board);
+    game.tree.push(board.parse_san("e4").unwrap());
+    let pgn = game.to_pgn();
+    assert!(pgn.contains(
+        "[SetUp \"1\"]\n[FEN \"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1\"]\n"
+    ));
+    let read = crate::pgn::PgnGame::parse(&pgn).unwrap();
+    assert_eq!(read.tree.start.fen(), board.fen());
+}
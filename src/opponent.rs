@@ -0,0 +1,167 @@
+//! Baseline opponents that don't search: one that plays any legal move at random, and one that
+//! grabs the capture that wins the most material. They give a search something to be measured
+//! against (anything worth playing should beat both every time), and give beginners someone
+//! they can beat. The engine itself can be played as well.
+
+use crate::eval::PIECE_VALUES;
+use crate::movegen::Move;
+use crate::search::SearchResult;
+use crate::selfplay::Rng;
+use crate::{ChessBoard, EMPTY, GET_NUM, KING, PAWN};
+
+/// A baseline opponent, as chosen with `--opponent`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Opponent {
+    /// Plays a legal move picked at random.
+    Random,
+    /// Plays the capture that wins the most material by static exchange evaluation, or a random
+    /// move that doesn't give material away if no capture wins anything.
+    GreedyCapture,
+    /// The engine, searching to `ENGINE_DEPTH` within `ENGINE_NODES` positions.
+    Engine,
+}
+
+/// How deep the engine searches as an opponent.
+pub const ENGINE_DEPTH: u32 = 8;
+
+/// The most positions the engine visits for one move as an opponent, so it answers in about a
+/// second at most.
+pub const ENGINE_NODES: u64 = 300_000;
+
+/// The engine's search of `board`, as it searches when playing an opponent.
+pub fn engine_search(board: &ChessBoard) -> SearchResult {
+    board.search_nodes(ENGINE_DEPTH, ENGINE_NODES)
+}
+
+impl Opponent {
+    /// The opponent called `name` on the command line.
+    pub fn by_name(name: &str) -> Option<Opponent> {
+        match name {
+            "random" => Some(Opponent::Random),
+            "greedy-capture" => Some(Opponent::GreedyCapture),
+            "engine" => Some(Opponent::Engine),
+            _ => None,
+        }
+    }
+
+    /// The move the opponent plays in `board` and, if it searched, the score it found for its
+    /// side, or `None` if the game is over.
+    pub fn choose_scored(&self, board: &ChessBoard, rng: &mut Rng) -> Option<(Move, Option<i32>)> {
+        match self {
+            Opponent::Engine => {
+                let result = engine_search(board);
+                result.best_move.map(|mv| (mv, Some(result.score)))
+            }
+            _ => self.choose(board, rng).map(|mv| (mv, None)),
+        }
+    }
+
+    /// The move the opponent plays in `board`, or `None` if the game is over.
+    pub fn choose(&self, board: &ChessBoard, rng: &mut Rng) -> Option<Move> {
+        if *self == Opponent::Engine {
+            return engine_search(board).best_move;
+        }
+        let moves = board.legal_moves();
+        if moves.is_empty() {
+            return None;
+        }
+        if *self == Opponent::Random {
+            return Some(moves[rng.below(moves.len())]);
+        }
+        let gains = moves
+            .iter()
+            .map(|&mv| match board.is_capture(mv) {
+                true => (see(board, mv), mv),
+                false => (0, mv),
+            })
+            .collect::<Vec<(i32, Move)>>();
+        // Of the moves that win the most (or, if none wins anything, that don't lose anything),
+        // any will do.
+        let best = gains.iter().map(|&(gain, _)| gain).max().unwrap().max(0);
+        let best = gains
+            .into_iter()
+            .filter(|&(gain, _)| gain == best)
+            .map(|(_, mv)| mv)
+            .collect::<Vec<Move>>();
+        match best.is_empty() {
+            true => Some(moves[rng.below(moves.len())]),
+            false => Some(best[rng.below(best.len())]),
+        }
+    }
+}
+
+/// How much the piece on `square` is worth when it is captured, or captures: kings are only put
+/// at risk last.
+fn value(board: &ChessBoard, square: u8) -> i32 {
+    match GET_NUM(board.get_piece(square)) {
+        KING => 10_000,
+        piece => PIECE_VALUES[piece as usize],
+    }
+}
+
+/// Static exchange evaluation: the material the side to move wins, in centipawns, by playing
+/// the capture `mv` if both sides then keep recapturing on its square with their least valuable
+/// piece, each stopping once recapturing would lose material.
+pub fn see(board: &ChessBoard, mv: Move) -> i32 {
+    let captured = match board.get_piece(mv.to) {
+        // Only an en passant capture lands on an empty square.
+        EMPTY => PIECE_VALUES[PAWN as usize],
+        _ => value(board, mv.to),
+    };
+    let promoted = match mv.promotion {
+        EMPTY => 0,
+        piece => PIECE_VALUES[piece as usize] - PIECE_VALUES[PAWN as usize],
+    };
+    let mut next = *board;
+    next.apply_move(mv);
+    let recapture = next
+        .legal_moves()
+        .into_iter()
+        .filter(|&reply| reply.to == mv.to && !next.is_castling(reply))
+        .min_by_key(|&reply| (value(&next, reply.from), reply.promotion));
+    captured + promoted - recapture.map_or(0, |reply| see(&next, reply).max(0))
+}
+
+#[test]
+fn test_opponent() {
+    assert_eq!(
+        Opponent::by_name("greedy-capture"),
+        Some(Opponent::GreedyCapture)
+    );
+    assert_eq!(Opponent::by_name("stockfish"), None);
+
+    // A free queen is taken; a queen for a defended pawn isn't.
+    let board = ChessBoard::from_fen("4k3/8/2p5/3p4/8/3q4/3R4/4K3 w - - 0 1").unwrap();
+    let take = board.parse_san("Rxd3").unwrap();
+    assert_eq!(see(&board, take), 900);
+    let mut rng = Rng::new(1);
+    assert_eq!(Opponent::GreedyCapture.choose(&board, &mut rng), Some(take));
+    let board = ChessBoard::from_fen("4k3/8/2p5/3p4/8/8/3Q4/4K3 w - - 0 1").unwrap();
+    let bad = board.parse_san("Qxd5").unwrap();
+    assert_eq!(see(&board, bad), 100 - 900);
+    for _ in 0..10 {
+        let mv = Opponent::GreedyCapture.choose(&board, &mut rng).unwrap();
+        assert_ne!(mv, bad);
+    }
+
+    // The random opponent plays every legal move sooner or later.
+    let board = ChessBoard::new();
+    let mut played = (0..400)
+        .map(|_| Opponent::Random.choose(&board, &mut rng).unwrap())
+        .collect::<Vec<Move>>();
+    played.sort_by_key(Move::to_string);
+    played.dedup();
+    assert_eq!(played.len(), 20);
+
+    let mate = ChessBoard::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(Opponent::Random.choose(&mate, &mut rng), None);
+    assert_eq!(Opponent::Engine.choose_scored(&mate, &mut rng), None);
+
+    // The engine mates in one, and knows it's winning.
+    let board = ChessBoard::from_fen("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1").unwrap();
+    let (mv, score) = Opponent::Engine.choose_scored(&board, &mut rng).unwrap();
+    let mut after = board;
+    after.apply_move(mv);
+    assert!(after.is_done() && after.is_in_check(after.side_to_move()));
+    assert!(score.unwrap() > 20_000);
+}
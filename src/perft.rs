@@ -0,0 +1,157 @@
+//! Perft: counts the positions a number of moves away, to check the move generator against
+//! published figures.
+
+use std::io::{Error, ErrorKind};
+use std::time::Instant;
+
+use serde_json::{json, Map, Value};
+
+use crate::movegen::Move;
+use crate::ChessBoard;
+
+impl ChessBoard {
+    /// Counts the move sequences `depth` plies long from this position.
+    pub fn perft(&self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut board = *self;
+        moves
+            .into_iter()
+            .map(|mv| {
+                let undo = board.make_move(mv);
+                let count = board.perft(depth - 1);
+                board.unmake_move(undo);
+                debug_assert!(board == *self, "unmaking {} changed the position", mv);
+                count
+            })
+            .sum()
+    }
+
+    /// The perft count below each legal move, in the order of their UCI names, to narrow down
+    /// where two move generators differ.
+    pub fn divide(&self, depth: u32) -> Vec<(Move, u64)> {
+        let mut moves = self
+            .legal_moves()
+            .into_iter()
+            .map(|mv| {
+                let mut next = *self;
+                next.apply_move(mv);
+                (mv, next.perft(depth.saturating_sub(1)))
+            })
+            .collect::<Vec<(Move, u64)>>();
+        moves.sort_by_key(|(mv, _)| mv.to_string());
+        moves
+    }
+}
+
+/// Runs `perft`: prints the count at `depth` from `board` (by move, with `divide`) and how fast
+/// it was found. With `check`, first checks every line of moves as `check_make_unmake` does.
+/// With `json`, prints all of it as one JSON object instead of text.
+pub fn run(
+    board: &ChessBoard,
+    depth: u32,
+    divide: bool,
+    check: bool,
+    json: bool,
+) -> Result<(), Error> {
+    if check {
+        board
+            .check_make_unmake(depth)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        if !json {
+            println!("Every move made and unmade cleanly.");
+        }
+    }
+    let started = Instant::now();
+    let divided = divide.then(|| board.divide(depth));
+    let nodes = match &divided {
+        Some(moves) => moves.iter().map(|(_, nodes)| nodes).sum(),
+        None => board.perft(depth),
+    };
+    let elapsed = started.elapsed().as_secs_f64();
+    let nps = nodes as f64 / elapsed.max(1e-9);
+
+    if json {
+        let mut value = json!({
+            "fen": board.fen(),
+            "depth": depth,
+            "nodes": nodes,
+            "seconds": elapsed,
+            "nps": nps.round() as u64,
+        });
+        if let Some(moves) = &divided {
+            let moves = moves
+                .iter()
+                .map(|(mv, nodes)| (mv.to_string(), json!(nodes)))
+                .collect::<Map<String, Value>>();
+            value["moves"] = Value::Object(moves);
+        }
+        if check {
+            value["checked"] = json!(true);
+        }
+        println!("{}", value);
+        return Ok(());
+    }
+    for (mv, nodes) in divided.iter().flatten() {
+        println!("{}: {}", mv, nodes);
+    }
+    if divided.is_some() {
+        println!();
+    }
+    println!("Nodes: {}", nodes);
+    println!("Time: {:.3}s ({:.0} nodes per second)", elapsed, nps);
+    Ok(())
+}
+
+#[test]
+fn test_perft() {
+    // "Kiwipete", which has every kind of special move.
+    let board = ChessBoard::from_fen(
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    )
+    .unwrap();
+    assert_eq!(board.perft(1), 48);
+    assert_eq!(board.perft(2), 2039);
+    assert_eq!(board.perft(3), 97862);
+
+    // Chess960 positions from the published perft suite.
+    for (fen, counts) in [
+        (
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            [21, 528, 12189],
+        ),
+        (
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            [21, 807, 18002],
+        ),
+        (
+            "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+            [20, 479, 10471],
+        ),
+    ] {
+        let board = ChessBoard::from_fen(fen).unwrap();
+        assert!(board.chess960);
+        for (depth, &count) in counts.iter().enumerate() {
+            assert_eq!(board.perft(depth as u32 + 1), count, "{}", fen);
+        }
+    }
+    // The core's own `Position` plays the rules it shares with the board the same way.
+    for fen in [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    ] {
+        let board = ChessBoard::from_fen(fen).unwrap();
+        let mut position = freight_chess_core::Position::from_fen(fen).unwrap();
+        assert_eq!(position.perft(3), board.perft(3), "{}", fen);
+        assert_eq!(position.to_string(), board.fen());
+    }
+    let divided = ChessBoard::new().divide(2);
+    assert_eq!(divided.len(), 20);
+    assert!(divided.iter().all(|&(_, nodes)| nodes == 20));
+}
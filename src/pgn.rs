@@ -0,0 +1,824 @@
+use std::io::BufRead;
+use std::time::SystemTime;
+
+use crate::annotation;
+use crate::movegen::Move;
+use crate::san::{Notation, PieceLetters};
+use crate::tree::{GameTree, Node};
+use crate::ChessErr::{self, InvalidPgn};
+use crate::{variant, ChessBoard, WHITE};
+
+/// The longest line of movetext written, as recommended by the PGN standard.
+const LINE_WIDTH: usize = 79;
+
+/// The tokens that end a game's movetext.
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// A game as recorded in PGN.
+#[derive(Clone)]
+pub struct PgnGame {
+    /// Tags other than `Result`, `SetUp` and `FEN`, which are filled in from the game itself.
+    pub tags: Vec<(String, String)>,
+    /// The moves, starting from the position the game started from.
+    pub tree: GameTree,
+    /// `1-0`, `0-1`, `1/2-1/2`, or `*` for a game still in progress.
+    pub result: String,
+}
+
+/// Today's date (UTC) in the PGN format, `YYYY.MM.DD`.
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / 86_400)
+        .unwrap_or_default() as i64;
+    // Converts days since 1970-01-01 to a civil date (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+impl PgnGame {
+    /// Starts a game record with the Seven Tag Roster filled with placeholders.
+    pub fn new(start: ChessBoard) -> PgnGame {
+        let tags = [
+            ("Event", "?".to_string()),
+            ("Site", "?".to_string()),
+            ("Date", today()),
+            ("Round", "-".to_string()),
+            ("White", "?".to_string()),
+            ("Black", "?".to_string()),
+        ];
+        PgnGame {
+            tags: tags
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            tree: GameTree::new(start),
+            result: "*".to_string(),
+        }
+    }
+
+    /// Sets the tag `name`, replacing its current value if it has one.
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, current)) => *current = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// The value of the tag `name`, if the game has it.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The position after every move of the main line.
+    pub fn board(&self) -> ChessBoard {
+        self.tree.board()
+    }
+
+    /// Reads the first game in `text`.
+    pub fn parse(text: &str) -> Result<PgnGame, ChessErr> {
+        PgnGame::parse_in(text, PieceLetters::ENGLISH)
+    }
+
+    /// Reads the first game in `text`, whose moves may be written with `letters` for the pieces
+    /// (see `parse_games`).
+    pub fn parse_in(text: &str, letters: PieceLetters) -> Result<PgnGame, ChessErr> {
+        parse_games(text, letters)?
+            .into_iter()
+            .next()
+            .ok_or(InvalidPgn("There is no game in the PGN."))
+    }
+
+    /// Writes the game as PGN.
+    pub fn to_pgn(&self) -> String {
+        self.write(Notation::default())
+    }
+
+    /// Writes the game as PGN with its moves in `notation`. Only SAN is standard, but this
+    /// program reads any of them back.
+    pub fn write(&self, notation: Notation) -> String {
+        let mut pgn = String::new();
+        let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut tags = self.tags.clone();
+        // The Seven Tag Roster puts Result last.
+        let roster_end = tags
+            .iter()
+            .position(|(name, _)| name == "Black")
+            .map_or(tags.len(), |black| black + 1);
+        tags.insert(roster_end, ("Result".to_string(), self.result.clone()));
+        let start = self.tree.start.fen();
+        let variant = match self.tree.start.variant.name() {
+            "standard" if self.tree.start.chess960 => Some("Chess960"),
+            "standard" => None,
+            _ => Some(self.tree.start.variant.pgn_name()),
+        };
+        if let Some(variant) = variant {
+            if !tags.iter().any(|(name, _)| name == "Variant") {
+                tags.push(("Variant".to_string(), variant.to_string()));
+            }
+        }
+        let standard = ChessBoard::usual_start_of(self.tree.start.variant);
+        if start != standard.fen() || self.tree.start.chess960 {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), start));
+        }
+        for (name, value) in &tags {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape(value)));
+        }
+        pgn.push('\n');
+
+        let mut movetext = Movetext {
+            notation,
+            ..Movetext::default()
+        };
+        movetext.line(self.tree.start, &self.tree.mainline);
+        let mut tokens = movetext.tokens;
+        tokens.push(self.result.clone());
+
+        let mut line = String::new();
+        for token in tokens {
+            let width = |text: &str| text.chars().count();
+            if !line.is_empty() && width(&line) + 1 + width(&token) > LINE_WIDTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+/// Movetext being written, as words to be wrapped into lines.
+#[derive(Default)]
+struct Movetext {
+    tokens: Vec<String>,
+    /// Whether a variation has just been opened, so the next word starts with its parenthesis.
+    open: bool,
+    notation: Notation,
+}
+
+impl Movetext {
+    fn push(&mut self, token: String) {
+        match std::mem::take(&mut self.open) {
+            true => self.tokens.push(format!("({}", token)),
+            false => self.tokens.push(token),
+        }
+    }
+
+    /// Adds a comment, a word at a time so that long comments are wrapped too.
+    fn comment(&mut self, comment: &str) {
+        let words = comment.split_whitespace().collect::<Vec<&str>>();
+        match words.as_slice() {
+            [] => self.push("{}".to_string()),
+            [word] => self.push(format!("{{{}}}", word)),
+            [first, middle @ .., last] => {
+                self.push(format!("{{{}", first));
+                for word in middle {
+                    self.push(word.to_string());
+                }
+                self.push(format!("{}}}", last));
+            }
+        }
+    }
+
+    /// Adds `line` as played from `board`, with its annotations and variations. Black's moves
+    /// get a move number when they start the line, or when something came between them and
+    /// white's move.
+    fn line(&mut self, mut board: ChessBoard, line: &[Node]) {
+        let mut numbered = true;
+        for node in line {
+            if let Some(comment) = &node.comment_before {
+                self.comment(comment);
+                numbered = true;
+            }
+            let number = board.moves as usize / 2 + 1;
+            if board.side_to_move() == WHITE {
+                self.push(format!("{}.", number));
+            } else if numbered {
+                self.push(format!("{}...", number));
+            }
+            self.push(board.notate(node.mv, self.notation));
+            for nag in &node.nags {
+                self.push(format!("${}", nag));
+            }
+            numbered = false;
+            let commands = node.annotations.commands();
+            let comment = match (commands.as_str(), &node.comment) {
+                ("", comment) => comment.clone(),
+                (commands, None) => Some(commands.to_string()),
+                (commands, Some(comment)) => Some(format!("{} {}", commands, comment)),
+            };
+            if let Some(comment) = comment {
+                self.comment(&comment);
+                numbered = true;
+            }
+            for variation in &node.variations {
+                self.open = true;
+                self.line(board, variation);
+                if let Some(last) = self.tokens.last_mut() {
+                    last.push(')');
+                }
+                numbered = true;
+            }
+            board.apply_move(node.mv);
+        }
+    }
+}
+
+/// The SAN of `moves` played from `start`, with move numbers as written in movetext (`1.`
+/// before white's moves, and `N...` if the line starts with black's).
+pub fn numbered_sans(start: &ChessBoard, moves: &[Move]) -> Vec<String> {
+    numbered_moves(start, moves, Notation::default())
+}
+
+/// `numbered_sans`, with the moves in `notation`.
+pub fn numbered_moves(start: &ChessBoard, moves: &[Move], notation: Notation) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut board = *start;
+    for (i, san) in board.line_notation(moves, notation).into_iter().enumerate() {
+        let number = board.moves as usize / 2 + 1;
+        if board.side_to_move() == WHITE {
+            tokens.push(format!("{}.", number));
+        } else if i == 0 {
+            tokens.push(format!("{}...", number));
+        }
+        tokens.push(san);
+        board.apply_move(moves[i]);
+    }
+    tokens
+}
+
+/// Reads a tag pair such as `[White "Morphy, Paul"]`, without its brackets.
+fn parse_tag(pair: &str) -> Result<(String, String), ChessErr> {
+    let malformed = || InvalidPgn("Tags must look like [Name \"value\"].");
+    let (name, value) = pair
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(malformed)?;
+    let quoted = value.trim();
+    let value = quoted
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .ok_or_else(malformed)?;
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            '\\' => chars.next().ok_or_else(malformed)?,
+            c => c,
+        });
+    }
+    Ok((name.to_string(), unescaped))
+}
+
+enum Token {
+    Tag(String, String),
+    /// A move, move number or result.
+    Word(String),
+    /// The start of a variation, an alternative to the move before it.
+    VariationStart,
+    VariationEnd,
+    Comment(String),
+    /// A numeric annotation glyph, such as `$1`.
+    Nag(u8),
+}
+
+/// Splits PGN into tags, movetext words, comments, numeric annotation glyphs and variations.
+fn tokenize_annotated(text: &str) -> Result<Vec<Token>, ChessErr> {
+    let mut tokens = Vec::new();
+    let mut depth = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '[' => {
+                let mut pair = String::new();
+                let mut in_string = false;
+                loop {
+                    let c = chars
+                        .next()
+                        .ok_or(InvalidPgn("A tag is missing its closing bracket."))?;
+                    if c == ']' && !in_string {
+                        break;
+                    }
+                    in_string ^= c == '"';
+                    pair.push(c);
+                    if c == '\\' && in_string {
+                        pair.extend(chars.next());
+                    }
+                }
+                let (name, value) = parse_tag(&pair)?;
+                tokens.push(Token::Tag(name, value));
+            }
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err(InvalidPgn("A comment is missing its closing brace.")),
+                    }
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            ';' => {
+                let comment = chars
+                    .by_ref()
+                    .take_while(|&c| c != '\n')
+                    .collect::<String>();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '%' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '(' => {
+                depth += 1;
+                tokens.push(Token::VariationStart);
+            }
+            ')' => {
+                if depth == 0 {
+                    return Err(InvalidPgn("A variation is closed without being opened."));
+                }
+                depth -= 1;
+                tokens.push(Token::VariationEnd);
+            }
+            '$' => {
+                let mut digits = String::new();
+                while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(c);
+                }
+                let nag = digits
+                    .parse()
+                    .map_err(|_| InvalidPgn("Annotation glyphs must be $0 to $255."))?;
+                tokens.push(Token::Nag(nag));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|&c| !c.is_whitespace() && !"[]{}();$".contains(c))
+                {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    if depth > 0 {
+        return Err(InvalidPgn(
+            "A variation is missing its closing parenthesis.",
+        ));
+    }
+    Ok(tokens)
+}
+
+/// Splits PGN into tags and the words of the main line, leaving out comments, variations and
+/// numeric annotation glyphs.
+fn tokenize(text: &str) -> Result<Vec<Token>, ChessErr> {
+    let mut depth = 0;
+    let mut tokens = tokenize_annotated(text)?;
+    tokens.retain(|token| {
+        match token {
+            Token::VariationStart => depth += 1,
+            Token::VariationEnd => depth -= 1,
+            Token::Comment(_) | Token::Nag(_) => {}
+            _ => return depth == 0,
+        }
+        false
+    });
+    Ok(tokens)
+}
+
+/// Splits a file of several games into the text of each, so a game that can't be read doesn't
+/// stop the others from being read.
+pub fn split_games(text: &str) -> Vec<&str> {
+    let mut games = Vec::new();
+    let (mut start, mut offset) = (0, 0);
+    let mut in_movetext = false;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && in_movetext {
+            games.push(&text[start..offset]);
+            start = offset;
+            in_movetext = false;
+        } else if !trimmed.is_empty() && !trimmed.starts_with('[') {
+            in_movetext = true;
+        }
+        offset += line.len();
+    }
+    if !text[start..].trim().is_empty() {
+        games.push(&text[start..]);
+    }
+    games
+}
+
+/// Reads the games of a PGN file one at a time, so files too large to hold in memory can be
+/// searched. Games are split as by `split_games`.
+pub struct GameReader<R> {
+    lines: std::io::Lines<R>,
+    /// The first line of the next game, read while looking for the end of the last one.
+    pending: Option<String>,
+}
+
+impl<R: BufRead> GameReader<R> {
+    pub fn new(input: R) -> GameReader<R> {
+        GameReader {
+            lines: input.lines(),
+            pending: None,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for GameReader<R> {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut game = self.pending.take().unwrap_or_default();
+        let mut in_movetext = false;
+        for line in self.lines.by_ref() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') && in_movetext {
+                self.pending = Some(line + "\n");
+                return Some(Ok(game));
+            } else if !trimmed.is_empty() && !trimmed.starts_with('[') {
+                in_movetext = true;
+            }
+            game.push_str(&line);
+            game.push('\n');
+        }
+        (!game.trim().is_empty()).then_some(Ok(game))
+    }
+}
+
+/// Tag names and values, in the order they are written.
+pub type Tags = Vec<(String, String)>;
+
+/// The tags and moves of the first game in `text` as they are written, without playing the
+/// moves. Much faster than `PgnGame::parse` when only a few games are wanted.
+pub fn scan(text: &str) -> Result<(Tags, Vec<String>), ChessErr> {
+    let (mut tags, mut moves) = (Vec::new(), Vec::new());
+    for token in tokenize(text)? {
+        match token {
+            Token::Tag(..) if !moves.is_empty() => break,
+            Token::Tag(name, value) => tags.push((name, value)),
+            Token::Word(word) if RESULTS.contains(&word.as_str()) => break,
+            Token::Word(word) => {
+                let san = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                if !san.is_empty() {
+                    moves.push(san.to_string());
+                }
+            }
+            // `tokenize` leaves these out.
+            Token::VariationStart | Token::VariationEnd | Token::Comment(_) | Token::Nag(_) => {}
+        }
+    }
+    Ok((tags, moves))
+}
+
+/// The glyphs that can be written as a suffix of a move, by their numbers.
+const SUFFIX_NAGS: [(&str, u8); 6] = [
+    ("!", 1),
+    ("?", 2),
+    ("!!", 3),
+    ("??", 4),
+    ("!?", 5),
+    ("?!", 6),
+];
+
+/// Reads a line of moves played from `board`, with its annotations and variations, up to the
+/// end of the variation or game it is in.
+fn parse_line(
+    tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token>>,
+    mut board: ChessBoard,
+    letters: PieceLetters,
+) -> Result<Vec<Node>, ChessErr> {
+    let mut line: Vec<Node> = Vec::new();
+    // The position before the last move, from which its variations start.
+    let mut before = board;
+    let mut comment_before: Option<String> = None;
+    let append = |comment: &mut Option<String>, text: String| match comment {
+        Some(comment) => {
+            comment.push(' ');
+            comment.push_str(&text);
+        }
+        None => *comment = Some(text),
+    };
+    while let Some(token) = tokens.peek() {
+        match token {
+            Token::Tag(..) | Token::VariationEnd => break,
+            Token::Word(word) if RESULTS.contains(&word.as_str()) => break,
+            _ => {}
+        }
+        match tokens.next() {
+            Some(Token::VariationStart) => {
+                let variation = parse_line(tokens, before, letters)?;
+                match tokens.next() {
+                    Some(Token::VariationEnd) => {}
+                    _ => {
+                        return Err(InvalidPgn(
+                            "A variation is missing its closing parenthesis.",
+                        ))
+                    }
+                }
+                let node = line
+                    .last_mut()
+                    .ok_or(InvalidPgn("A variation has no move to replace."))?;
+                if !variation.is_empty() {
+                    node.variations.push(variation);
+                }
+            }
+            Some(Token::Comment(text)) => match line.last_mut() {
+                Some(node) => {
+                    // A comment holding nothing but annotations leaves no comment behind.
+                    let (annotations, text) = annotation::extract(&text);
+                    if !text.is_empty() || annotations.is_empty() {
+                        append(&mut node.comment, text);
+                    }
+                    node.annotations.squares.extend(annotations.squares);
+                    node.annotations.arrows.extend(annotations.arrows);
+                }
+                None => append(&mut comment_before, text),
+            },
+            Some(Token::Nag(nag)) => {
+                if let Some(node) = line.last_mut() {
+                    node.nags.push(nag);
+                }
+            }
+            Some(Token::Word(word)) => {
+                // Move numbers may be written separately (`1. e4`) or not (`1.e4`).
+                let san = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                if san.is_empty() {
+                    continue;
+                }
+                let (san, suffix) = san.split_at(san.find(['!', '?']).unwrap_or(san.len()));
+                // A move that doesn't read with the local letters may still be in English.
+                let mv = board
+                    .parse_move(&letters.to_english(san))
+                    .or_else(|e| board.parse_move(san).map_err(|_| e))?;
+                let mut node = Node::new(mv);
+                node.nags.extend(
+                    SUFFIX_NAGS
+                        .iter()
+                        .find(|&&(glyph, _)| glyph == suffix)
+                        .map(|&(_, nag)| nag),
+                );
+                if line.is_empty() {
+                    node.comment_before = comment_before.take();
+                }
+                before = board;
+                board.apply_move(mv);
+                line.push(node);
+            }
+            _ => {}
+        }
+    }
+    Ok(line)
+}
+
+/// Reads every game in `text`, with its comments, annotation glyphs and variations. The pieces'
+/// letters in its moves are taken to be `letters` (as in a German `Sf3`), or failing that
+/// English ones.
+pub fn parse_games(text: &str, letters: PieceLetters) -> Result<Vec<PgnGame>, ChessErr> {
+    let mut games = Vec::new();
+    let mut tokens = tokenize_annotated(text)?.into_iter().peekable();
+    while tokens.peek().is_some() {
+        let mut game = PgnGame {
+            tags: Vec::new(),
+            tree: GameTree::new(ChessBoard::new()),
+            result: "*".to_string(),
+        };
+        let mut fen = None;
+        while let Some(Token::Tag(..)) = tokens.peek() {
+            if let Some(Token::Tag(name, value)) = tokens.next() {
+                match name.as_str() {
+                    "Result" => game.result = value,
+                    "FEN" => fen = Some(value),
+                    "SetUp" => {}
+                    _ => game.tags.push((name, value)),
+                }
+            }
+        }
+        let variant = match game.tags.iter().find(|(name, _)| name == "Variant") {
+            Some((_, name)) => variant::by_name(name).ok_or(InvalidPgn(
+                "The game is played in a variant that isn't supported.",
+            ))?,
+            None => &variant::STANDARD,
+        };
+        game.tree.start = match fen {
+            Some(fen) => ChessBoard::from_variant_fen(&fen, variant)?,
+            None => ChessBoard::usual_start_of(variant),
+        };
+        game.tree.mainline = parse_line(&mut tokens, game.tree.start, letters)?;
+        let ended = match tokens.peek() {
+            Some(Token::Word(result)) => {
+                game.result = result.clone();
+                tokens.next();
+                true
+            }
+            Some(Token::VariationEnd) => {
+                return Err(InvalidPgn("A variation is closed without being opened."))
+            }
+            _ => false,
+        };
+        // Text with no tags, moves or result (just a comment, say) isn't a game.
+        if ended || !game.tags.is_empty() || !game.tree.mainline.is_empty() {
+            games.push(game);
+        }
+    }
+    Ok(games)
+}
+
+#[test]
+fn test_pgn() {
+    let mut board = ChessBoard::new();
+    let mut game = PgnGame::new(board);
+    for san in ["f3", "e5", "g4", "Qh4#"] {
+        let mv = board.parse_move(san).unwrap();
+        game.tree.push(mv);
+        board.apply_move(mv);
+    }
+    game.set_tag("White", "Fool \"The\" Player");
+    game.set_tag("Event", "Casual game");
+    game.result = "0-1".to_string();
+    let pgn = game.to_pgn();
+    assert!(pgn.starts_with("[Event \"Casual game\"]\n[Site \"?\"]\n[Date \""));
+    assert!(pgn.contains(
+        "[White \"Fool \\\"The\\\" Player\"]\n[Black \"?\"]\n[Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n"
+    ));
+    assert!(!pgn.contains("SetUp"));
+
+    // Games from a set-up position record it, and may start with black.
+    let start =
+        ChessBoard::from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2")
+            .unwrap();
+    let mut game = PgnGame::new(start);
+    game.tree.push(start.parse_move("Qh4").unwrap());
+    let pgn = game.to_pgn();
+    assert!(pgn.contains(
+        "[SetUp \"1\"]\n[FEN \"rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2\"]\n"
+    ));
+    assert!(pgn.ends_with("\n2... Qh4# *\n"));
+
+    // Chess960 games say so, and castle by their own rules.
+    let chess960 = ChessBoard::chess960(518).unwrap();
+    let mut game = PgnGame::new(chess960);
+    game.tree.push(chess960.parse_move("Nf3").unwrap());
+    let pgn = game.to_pgn();
+    assert!(pgn.contains("[Variant \"Chess960\"]\n[SetUp \"1\"]\n"));
+    assert!(PgnGame::parse(&pgn).unwrap().tree.start.chess960);
+    let castled = PgnGame::parse(
+        "[Variant \"Chess960\"]\n[FEN \"4k3/8/8/8/8/8/8/1R2K1R1 w GB - 0 1\"]\n\n1. O-O-O Kf7 *",
+    )
+    .unwrap();
+    assert_eq!(castled.tree.board().placement(), "8/5k2/8/8/8/8/8/2KR2R1");
+    assert!(castled
+        .to_pgn()
+        .contains("[FEN \"4k3/8/8/8/8/8/8/1R2K1R1 w KQ - 0 1\"]"));
+    let standard = PgnGame::parse("[Variant \"Standard\"]\n\n1. e4 *").unwrap();
+    assert_eq!(standard.tree.start.variant.name(), "standard");
+    assert!(!standard.tree.start.chess960);
+    assert!(PgnGame::parse("[Variant \"Shogi\"]\n\n1. e4 *").is_err());
+    let crazyhouse =
+        PgnGame::parse("[Variant \"Crazyhouse\"]\n\n1. e4 d5 2. exd5 Qxd5 3. P@e4 *").unwrap();
+    assert!(crazyhouse.tree.board().fen().contains("[p]"));
+    let pgn = crazyhouse.to_pgn();
+    assert!(pgn.contains("[Variant \"Crazyhouse\"]\n"));
+    assert!(!pgn.contains("[FEN"));
+    assert!(pgn.ends_with("\n1. e4 d5 2. exd5 Qxd5 3. P@e4 *\n"));
+
+    let date = today();
+    assert_eq!(date.len(), 10);
+    assert!(date.starts_with("20"));
+
+    // Reading keeps comments, variations and annotations, and tells games apart.
+    let text = "[Event \"Casual \\\"game\\\"\"]\n[White \"A\"]\n[Result \"1-0\"]\n\n\
+        1.e4 {King's pawn} e5 $1 2. Nf3 (2. f4 exf4 {gambit}) Nc6!? ; Italian next\n\
+        3. Bc4 1-0\n\n[Event \"Second\"]\n[SetUp \"1\"]\n\
+        [FEN \"rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2\"]\n\n2... Qh4# 0-1\n";
+    let games = parse_games(text, PieceLetters::ENGLISH).unwrap();
+    assert_eq!(games.len(), 2);
+    assert_eq!(games[0].tag("Event"), Some("Casual \"game\""));
+    assert_eq!(games[0].tag("Result"), None);
+    assert_eq!(games[0].result, "1-0");
+    assert_eq!(
+        ChessBoard::new().line_sans(&games[0].tree.moves()),
+        ["e4", "e5", "Nf3", "Nc6", "Bc4"]
+    );
+    let mainline = &games[0].tree.mainline;
+    assert_eq!(mainline[0].comment.as_deref(), Some("King's pawn"));
+    assert_eq!(mainline[1].nags, [1]);
+    assert_eq!(mainline[2].variations.len(), 1);
+    assert_eq!(
+        mainline[2].variations[0][1].comment.as_deref(),
+        Some("gambit")
+    );
+    assert_eq!(mainline[3].nags, [5]);
+    assert_eq!(mainline[3].comment.as_deref(), Some("Italian next"));
+    let pgn = games[0].to_pgn();
+    assert!(pgn.replace('\n', " ").contains(
+        "1. e4 {King's pawn} 1... e5 $1 2. Nf3 (2. f4 exf4 {gambit}) 2... Nc6 $5 {Italian next} \
+        3. Bc4 1-0"
+    ));
+    assert!(pgn.lines().all(|line| line.len() <= LINE_WIDTH));
+    assert_eq!(PgnGame::parse(&pgn).unwrap().to_pgn(), pgn);
+    assert_eq!(games[1].tree.start.fen(), start.fen());
+    assert_eq!(games[1].result, "0-1");
+    assert!(games[1].board().outcome().is_some());
+    let texts = split_games(text);
+    assert_eq!(texts.len(), 2);
+    assert!(texts[0].ends_with("3. Bc4 1-0\n\n"));
+    assert!(texts[1].starts_with("[Event \"Second\"]"));
+    let read = GameReader::new(text.as_bytes())
+        .collect::<Result<Vec<String>, _>>()
+        .unwrap();
+    assert_eq!(read, texts);
+    let (tags, moves) = scan(texts[0]).unwrap();
+    assert_eq!(tags[2], ("Result".to_string(), "1-0".to_string()));
+    assert_eq!(moves, ["e4", "e5", "Nf3", "Nc6!?", "Bc4"]);
+    assert!(PgnGame::parse("(1. e4) *").is_err());
+    assert!(PgnGame::parse("1. e4 (1. d4").is_err());
+    // Nested variations, and comments before a variation's first move, survive a round trip.
+    let nested = "1. e4 ({Or} 1. d4 d5 (1... Nf6 $2)) 1... e5 *";
+    let game = PgnGame::parse(nested).unwrap();
+    let variation = &game.tree.mainline[0].variations[0];
+    assert_eq!(variation[0].comment_before.as_deref(), Some("Or"));
+    assert_eq!(variation[1].variations[0][0].nags, [2]);
+    assert!(game.to_pgn().ends_with(&format!("\n\n{}\n", nested)));
+
+    // Arrows and squares are read out of comments, and written back into them.
+    let annotated = "1. e4 {[%csl Rd5] [%cal Gg1f3,Bd2d4] Center} 1... e5 {[%cal Rd1h5]} *";
+    let game = PgnGame::parse(annotated).unwrap();
+    let e4 = &game.tree.mainline[0];
+    assert_eq!(e4.comment.as_deref(), Some("Center"));
+    assert_eq!(e4.annotations.squares, [(annotation::Color::Red, 35)]);
+    assert_eq!(e4.annotations.arrows.len(), 2);
+    assert_eq!(game.tree.mainline[1].comment, None);
+    assert!(game.to_pgn().ends_with(&format!("\n\n{}\n", annotated)));
+
+    // Games written in other notations are read back the same.
+    let game = PgnGame::parse("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. O-O *").unwrap();
+    for (notation, movetext) in [
+        (Notation::Figurine, "1. e4 e5 2. ♘f3 ♞c6 3. ♗b5 a6 4. O-O *"),
+        (
+            Notation::by_name("long").unwrap(),
+            "1. e2-e4 e7-e5 2. Ng1-f3 Nb8-c6 3. Bf1-b5 a7-a6 4. O-O *",
+        ),
+        (
+            Notation::Coordinate,
+            "1. e2e4 e7e5 2. g1f3 b8c6 3. f1b5 a7a6 4. e1g1 *",
+        ),
+    ] {
+        let written = game.write(notation);
+        assert!(
+            written.ends_with(&format!("\n\n{}\n", movetext)),
+            "{}",
+            written
+        );
+        assert_eq!(
+            PgnGame::parse(&written).unwrap().tree.moves(),
+            game.tree.moves()
+        );
+    }
+    // German letters are written and read, and English moves are still read.
+    let german = PieceLetters::by_locale("de").unwrap();
+    let written = game.write(Notation::San(german));
+    assert!(written.ends_with("\n\n1. e4 e5 2. Sf3 Sc6 3. Lb5 a6 4. O-O *\n"));
+    let read = PgnGame::parse_in(&written, german).unwrap();
+    assert_eq!(read.tree.moves(), game.tree.moves());
+    let mixed = PgnGame::parse_in("1. e4 e5 2. Nf3 Sc6 *", german).unwrap();
+    assert_eq!(mixed.tree.moves(), game.tree.moves()[..4]);
+    // In French the king is R, so Rd2 is the king's move rather than the rook's.
+    let french = PieceLetters::by_locale("fr").unwrap();
+    let position = "[FEN \"4k3/8/8/8/8/8/8/3RK3 w - - 0 1\"]\n\n1. Rd2 *";
+    let king = PgnGame::parse_in(position, french).unwrap().tree.moves()[0];
+    assert_eq!((king.from, king.to), (4, 11));
+    assert!(PgnGame::parse("1. e4 $256 *").is_err());
+    assert!(PgnGame::parse("1. e4 e4").is_err());
+    assert!(PgnGame::parse("[Event \"x\"").is_err());
+    assert!(PgnGame::parse("").is_err());
+}
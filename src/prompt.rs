@@ -0,0 +1,605 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+
+use crate::annotation::Annotations;
+use crate::conduct::{Conduct, Decision, Thresholds};
+use crate::config::PlayConfig;
+use crate::engine::{Engine, EngineSpec, Limits};
+use crate::grid::Layout;
+use crate::movegen::{color_name, Move, MoveRejection};
+use crate::net::{format_clock, random_u64};
+use crate::opponent::{Opponent, ENGINE_DEPTH, ENGINE_NODES};
+use crate::search::SearchResult;
+use crate::selfplay::Rng;
+use crate::spoken::{spoken_board, spoken_move, spoken_turn};
+use crate::theme::{self, Theme, THEMES};
+use crate::tree::{Cursor, GameTree};
+use crate::ChessErr::BadMove;
+use crate::{armageddon, render, time_control, BLACK};
+use crate::{
+    crash, execute_command, move_number, print_game_tutorial, split_commands, ChessBoard, ChessErr,
+    Outcome, COMMANDS,
+};
+
+/// Name of the file (in the user's home directory) that keeps the prompt history between runs.
+const HISTORY_FILE: &str = ".freight_chess_history";
+
+type PromptEditor = Editor<PromptHelper, DefaultHistory>;
+
+/// Tab-completes command names and, after `move `, the SAN of every legal move.
+struct PromptHelper {
+    /// The legal moves of the current position, refreshed after every command.
+    legal_moves: Vec<String>,
+}
+
+impl Completer for PromptHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(completions(&line[..pos], &self.legal_moves))
+    }
+}
+
+impl Hinter for PromptHelper {
+    type Hint = String;
+}
+
+impl Highlighter for PromptHelper {}
+
+impl Validator for PromptHelper {}
+
+impl Helper for PromptHelper {}
+
+/// Returns where the word under the cursor starts and the candidates that could complete it.
+fn completions(line: &str, legal_moves: &[String]) -> (usize, Vec<String>) {
+    let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let word = &line[start..];
+    let previous = line[..start].split_whitespace().collect::<Vec<&str>>();
+
+    let mut candidates: Vec<String> = match previous.as_slice() {
+        [] => COMMANDS
+            .iter()
+            .filter(|command| command.starts_with(word))
+            .map(|command| command.to_string())
+            .collect(),
+        ["move"] => legal_moves
+            .iter()
+            .filter(|san| san.starts_with(word))
+            .cloned()
+            .collect(),
+        ["debug"] if "dump".starts_with(word) => vec!["dump".to_string()],
+        ["export"] => ["png", "svg"]
+            .iter()
+            .filter(|format| format.starts_with(word))
+            .map(|format| format.to_string())
+            .collect(),
+        ["theme"] => THEMES
+            .iter()
+            .chain(&["plain"])
+            .filter(|theme| theme.starts_with(word))
+            .map(|theme| theme.to_string())
+            .collect(),
+        _ => Vec::new(),
+    };
+    candidates.sort();
+    (start, candidates)
+}
+
+/// Returns where the prompt history is stored, if a home directory can be found.
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Asks whether the player really wants to abandon the current game. Anything other than an
+/// explicit "yes" keeps the game going.
+fn confirm_quit(editor: &mut PromptEditor) -> bool {
+    match editor.readline("Quit the current game? [y/N] ") {
+        Ok(answer) => matches!(answer.trim(), "y" | "Y" | "yes"),
+        Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => true,
+        Err(_) => false,
+    }
+}
+
+/// What a pawn reaching the last rank becomes when the move doesn't say.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AutoPromote {
+    /// A queen, as it nearly always should.
+    Queen,
+    /// Whatever the player answers when asked.
+    #[default]
+    Ask,
+}
+
+impl AutoPromote {
+    /// The choice called `name` in the configuration file.
+    pub fn by_name(name: &str) -> Option<AutoPromote> {
+        match name {
+            "queen" => Some(AutoPromote::Queen),
+            "ask" => Some(AutoPromote::Ask),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the promotion to `piece` into a move `command` that left it out, keeping any check
+/// mark or annotation at the end, as in `exd8+` to `exd8=Q+`.
+fn with_promotion(command: &str, piece: char) -> String {
+    let mv = command.trim_end_matches(['+', '#', '!', '?']);
+    format!("{}={}{}", mv, piece, &command[mv.len()..])
+}
+
+/// Asks what the pawn promotes to, a queen unless the player says otherwise. Returns `None` if
+/// the answer isn't a piece a pawn can promote to.
+fn ask_promotion(editor: &mut PromptEditor) -> Option<char> {
+    let answer = editor
+        .readline("Promote to a queen, rook, bishop or knight? [Q/r/b/n] ")
+        .ok()?;
+    match answer.trim().to_ascii_uppercase().as_str() {
+        "" => Some('Q'),
+        piece @ ("Q" | "R" | "B" | "N") => piece.chars().next(),
+        _ => None,
+    }
+}
+
+/// How the game is shown.
+#[derive(Clone, Debug)]
+pub struct View {
+    /// Describes the board and moves in words rather than drawing the board.
+    pub screen_reader: bool,
+    /// Hides the board, except when the player peeks at it, and announces every move instead.
+    pub blindfold: bool,
+    /// The colors the board is drawn in, or `None` for the plain grid.
+    pub theme: Option<Theme>,
+    /// The themes the configuration file defines, which `theme` can switch to as well as the
+    /// built-in ones.
+    pub themes: Vec<(String, Theme)>,
+    /// How wide the squares are drawn.
+    pub layout: Layout,
+}
+
+/// Starts chess game prompt from `board`, with `clocks` the starting time of white and black in
+/// milliseconds (`None` for an untimed game). With `armageddon`, black wins if the game is
+/// drawn. With
+/// an `opponent`, it answers every move, playing the side that doesn't move first; the engine
+/// opponent is `engine`, which may be an external one. `view` says
+/// how the board and moves are shown, and `play` what a pawn promotes to when a move doesn't say
+/// and when the engine resigns or draws. (May be deprecated in a future version.)
+pub fn play_chess(
+    mut board: ChessBoard,
+    clocks: Option<[u32; 2]>,
+    armageddon: bool,
+    opponent: Option<Opponent>,
+    engine: EngineSpec,
+    view: View,
+    play: PlayConfig,
+) -> Result<(), ChessErr> {
+    let engine = match opponent {
+        Some(Opponent::Engine) => match engine.start() {
+            Ok(engine) => Some(engine),
+            Err(e) => {
+                println!("The engine couldn't be started: {}", e);
+                return Ok(());
+            }
+        },
+        _ => None,
+    };
+    if board.chess960 {
+        println!("Chess960, starting from {}", board.fen());
+    } else if board.fen() != ChessBoard::usual_start_of(board.variant).fen() {
+        println!("Starting from {}", board.fen());
+    }
+    if armageddon {
+        println!("This is an Armageddon game: black has less time, but wins if the game is drawn.");
+    }
+    if let Some(clocks) = clocks.filter(|[white, black]| white != black) {
+        println!("Time odds: {}.", time_control::describe(clocks));
+    }
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .build();
+    let mut editor =
+        PromptEditor::with_config(config).expect("Yikes, something broke the prompt...");
+    editor.set_helper(Some(PromptHelper {
+        legal_moves: Vec::new(),
+    }));
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing history file just means this is the first session.
+        let _ = editor.load_history(path);
+    }
+
+    let opponent = opponent.map(|opponent| (opponent, board.side_to_move() ^ BLACK));
+    let result = run_prompt(
+        &mut board,
+        &mut editor,
+        clocks,
+        armageddon,
+        opponent,
+        engine,
+        view,
+        play,
+    );
+
+    if let Some(path) = &history {
+        if let Err(e) = editor.save_history(path) {
+            println!("Could not save prompt history: {}", e);
+        }
+    }
+    result
+}
+
+/// The engine opponent's search of the position `cursor` is at, as it searches when playing.
+fn engine_search(
+    engine: &mut Engine,
+    tree: &GameTree,
+    cursor: &Cursor,
+) -> Result<SearchResult, std::io::Error> {
+    let limits = Limits {
+        depth: ENGINE_DEPTH,
+        nodes: ENGINE_NODES,
+        clocks: None,
+    };
+    let moves = tree.moves_to(cursor).unwrap_or_default();
+    engine.search(&tree.start, &moves, limits)
+}
+
+/// Runs the game. `clocks` holds the starting time of white and black in milliseconds; both
+/// players share the terminal, so the side to move is on the clock from the moment the previous
+/// move was played, and a flag fall is noticed when they next enter a command. `opponent` is the
+/// opponent and the color it plays, whose moves take no time, and `engine` the engine it searches
+/// with if it is the engine. The engine resigns, and offers
+/// and accepts draws, as `play` says, except that it never draws in Armageddon, where a draw
+/// isn't one. With a screen reader,
+/// every move is announced in words and the board is only described at the start and when
+/// asked for, since reading it out every turn would take too long. Blindfolded, every move is
+/// announced and the board is only shown when peeked at, until the game ends. The game is kept
+/// as a tree, so that going back with `goto` and playing on keeps the old line as a variation.
+/// A pawn move that leaves out the promotion is asked about, or promotes to a queen, as `play`
+/// says.
+#[allow(clippy::too_many_arguments)]
+fn run_prompt(
+    board: &mut ChessBoard,
+    editor: &mut PromptEditor,
+    mut clocks: Option<[u32; 2]>,
+    armageddon: bool,
+    opponent: Option<(Opponent, u8)>,
+    mut engine: Option<Engine>,
+    mut view: View,
+    play: PlayConfig,
+) -> Result<(), ChessErr> {
+    let mut conduct = Conduct::new(match armageddon {
+        true => Thresholds {
+            draw_moves: 0,
+            ..play.engine
+        },
+        false => play.engine,
+    });
+    // Whether the opponent's draw offer stands, until the player moves.
+    let mut draw_offered = false;
+    let mut rng = Rng::new(random_u64());
+    let mut last_move = None;
+    let mut turn_started = Instant::now();
+    let mut show_board = !view.blindfold;
+    let mut peeks = 0;
+    let mut tree = GameTree::new(*board);
+    let mut cursor = Cursor::default();
+    while !board.is_done() {
+        let moves = tree
+            .start
+            .line_sans(&tree.moves_to(&cursor).unwrap_or_default());
+        crash::note_game(&tree.start.fen(), &moves, board);
+        if let Some((opponent, color)) =
+            opponent.filter(|&(_, color)| color == board.side_to_move())
+        {
+            let chosen = match engine.as_mut() {
+                Some(engine) => match engine_search(engine, &tree, &cursor) {
+                    Ok(result) => result.best_move.map(|mv| (mv, Some(result.score))),
+                    Err(e) => {
+                        println!("The engine stopped working, so the game ends: {}", e);
+                        return Ok(());
+                    }
+                },
+                None => opponent.choose_scored(board, &mut rng),
+            };
+            if let Some((mv, score)) = chosen {
+                match score.map_or(Decision::Play, |score| conduct.decide(board, score)) {
+                    Decision::Resign => {
+                        tracing::info!(target: "tui", "the opponent resigned");
+                        println!(
+                            "The opponent resigns, so {} wins the game.",
+                            color_name(color ^ BLACK)
+                        );
+                        return Ok(());
+                    }
+                    Decision::OfferDraw => {
+                        tracing::info!(target: "tui", "the opponent offered a draw");
+                        println!(
+                            "The opponent offers a draw: 'draw' accepts it, a move declines it."
+                        );
+                        draw_offered = true;
+                    }
+                    Decision::Play => {}
+                }
+                match view.screen_reader {
+                    true => println!("The opponent plays: {}.", spoken_move(board, mv)),
+                    false => println!(
+                        "The opponent ({}) plays {}.",
+                        color_name(color),
+                        board.san(mv)
+                    ),
+                }
+                tracing::info!(target: "tui", "the opponent played {}", board.san(mv));
+                cursor = tree.play(&cursor, mv).unwrap_or_default();
+                board.apply_move(mv);
+                last_move = Some(mv);
+                turn_started = Instant::now();
+            }
+            continue;
+        }
+        match show_board {
+            true => show(board, &view, last_move),
+            false => print!("{}", spoken_turn(board)),
+        }
+        show_board = !view.screen_reader && !view.blindfold;
+        if let Some([white, black]) = clocks {
+            println!(
+                "White {} | Black {}",
+                format_clock(white),
+                format_clock(black)
+            );
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.legal_moves = board.legal_sans();
+        }
+        let line = match editor.readline(">>> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                // Don't throw away a game in progress on a stray Ctrl-C.
+                if board.moves == 0 || confirm_quit(editor) {
+                    return Ok(());
+                }
+                continue;
+            }
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(e) => panic!("Yikes, something broke the prompt: {}", e),
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.trim());
+        }
+
+        let side = board.side_to_move();
+        let elapsed = turn_started.elapsed().as_millis();
+        let mut clock = clocks
+            .as_mut()
+            .map(|clocks| &mut clocks[(side == BLACK) as usize]);
+        if let Some(clock) = &clock {
+            if elapsed >= **clock as u128 {
+                println!(
+                    "Time is up for {}, so {} wins the game.",
+                    color_name(side),
+                    color_name(side ^ BLACK)
+                );
+                return Ok(());
+            }
+        }
+        // The commands of the line run in turn until one fails.
+        let commands = split_commands(&line);
+        for (index, command) in commands.iter().enumerate() {
+            let before = *board;
+            tracing::debug!(target: "tui", "entered {:?}", command);
+            let mut result = execute_command(board, command);
+            if let Err(BadMove(MoveRejection::MissingPromotion)) = result {
+                let piece = match play.auto_promote {
+                    AutoPromote::Queen => Some('Q'),
+                    AutoPromote::Ask => ask_promotion(editor),
+                };
+                if let Some(piece) = piece {
+                    result = execute_command(board, &with_promotion(command, piece));
+                }
+            }
+            match result {
+                Ok(Outcome::Help) => print_game_tutorial(),
+                Ok(Outcome::Quit) => return Ok(()),
+                Ok(Outcome::Board) if view.blindfold => {
+                    println!("The board is hidden; 'peek' shows it.")
+                }
+                Ok(Outcome::Board) => show_board = true,
+                Ok(Outcome::Peek) => {
+                    show_board = true;
+                    peeks += 1;
+                }
+                Ok(Outcome::Dump) => print!("{}", board.dump()),
+                Ok(Outcome::Theme(name)) => match theme::find(&name, &view.themes) {
+                    Some(theme) => {
+                        view.theme = theme;
+                        show_board = !view.blindfold;
+                    }
+                    None => println!(
+                        "There is no theme {}. Try {} or plain, or add it to the configuration file.",
+                        name,
+                        THEMES.join(", ")
+                    ),
+                },
+                Ok(Outcome::Export(format, path)) => {
+                    match render::export(board, last_move, &Annotations::default(), &format, &path) {
+                        Ok(()) => println!("Saved the board to {}.", path),
+                        Err(e) => println!("Could not save the board to {}: {}", path, e),
+                    }
+                }
+                Ok(Outcome::Loaded(loaded)) => {
+                    tree = loaded;
+                    cursor = tree.end_of_line(&Cursor::default()).unwrap_or_default();
+                    last_move = tree.moves().last().copied();
+                    match tree.mainline.len() {
+                        0 => println!("Set up the position."),
+                        count => println!("Played the {} moves of the game.", count),
+                    }
+                    show_board = !view.blindfold;
+                    turn_started = Instant::now();
+                }
+                Ok(Outcome::Goto(moves)) => {
+                    let Some(found) = tree.find_on_mainline(moves) else {
+                        println!("The game has no move {}.", move_number(moves));
+                        break;
+                    };
+                    cursor = found;
+                    *board = tree.board_at(&cursor).unwrap_or(tree.start);
+                    last_move = tree.node(&cursor).map(|node| node.mv);
+                    match cursor.moves < tree.mainline.len() {
+                        true => println!(
+                            "At move {}. Playing on from here keeps the moves after it as a variation.",
+                            move_number(moves)
+                        ),
+                        false => println!("At move {}.", move_number(moves)),
+                    }
+                    show_board = !view.blindfold;
+                    turn_started = Instant::now();
+                }
+                Ok(Outcome::Draw) => {
+                    let accepted = match opponent {
+                        _ if draw_offered => true,
+                        // Two players at one terminal have both agreed to it.
+                        None => true,
+                        Some(_) => match engine.as_mut() {
+                            Some(engine) => engine_search(engine, &tree, &cursor)
+                                .is_ok_and(|result| conduct.accepts_draw(board, -result.score)),
+                            None => false,
+                        },
+                    };
+                    match accepted {
+                        true => {
+                            tracing::info!(target: "tui", "the game was drawn by agreement");
+                            println!(
+                                "{}",
+                                armageddon::announce_draw("The game is drawn by agreement.", armageddon)
+                            );
+                            return Ok(());
+                        }
+                        false => {
+                            println!("The opponent declines the draw.");
+                            break;
+                        }
+                    }
+                }
+                Ok(Outcome::Moved(mv)) => {
+                    draw_offered = false;
+                    cursor = tree.play(&cursor, mv).unwrap_or_default();
+                    if view.screen_reader {
+                        println!("You play: {}.", spoken_move(&before, mv));
+                    } else if view.blindfold {
+                        println!("You play {}.", before.san(mv));
+                    }
+                    last_move = Some(mv);
+                    // Moves after the first in a line take no time.
+                    if let Some(clock) = clock.take() {
+                        *clock -= elapsed as u32;
+                    }
+                    turn_started = Instant::now();
+                    let rest = &commands[index + 1..];
+                    if opponent.is_some_and(|(_, color)| color == board.side_to_move())
+                        && !rest.is_empty()
+                    {
+                        println!("It's the opponent's move, so {} waits.", rest.join("; "));
+                        break;
+                    }
+                }
+                // A rejected move shouldn't end the game, nor should the rest of the line run;
+                // explain it and ask again.
+                Err(e) => {
+                    tracing::info!(target: "tui", "rejected {:?}: {}", command, e);
+                    println!("{}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    show(board, &view, last_move);
+    if view.blindfold {
+        match peeks {
+            1 => println!("You peeked at the board once."),
+            peeks => println!("You peeked at the board {} times.", peeks),
+        }
+    }
+    if let Some(announcement) = armageddon::announce_outcome(board, armageddon) {
+        println!("{}", announcement);
+    }
+    Ok(())
+}
+
+/// Shows the board, drawn (in the colors of the theme, if there is one) or in words.
+fn show(board: &ChessBoard, view: &View, last_move: Option<Move>) {
+    match (view.screen_reader, view.theme) {
+        (true, _) => print!("{}", spoken_board(board)),
+        (false, Some(theme)) => {
+            let last_move = last_move.map(|mv| (mv.from, mv.to));
+            print!("{}", theme::colored(board, &theme, last_move, &view.layout))
+        }
+        (false, None) => println!("{}", board.grid(&view.layout)),
+    }
+}
+
+#[test]
+fn test_completions() {
+    let legal_moves = ChessBoard::new().legal_sans();
+    assert_eq!(completions("", &legal_moves).1, COMMANDS.to_vec());
+    assert_eq!(
+        completions("mo", &legal_moves),
+        (0, vec!["move".to_string()])
+    );
+    assert_eq!(
+        completions("q", &legal_moves),
+        (0, vec!["quit".to_string()])
+    );
+
+    let (start, knights) = completions("move N", &legal_moves);
+    assert_eq!(start, 5);
+    assert_eq!(knights, vec!["Na3", "Nc3", "Nf3", "Nh3"]);
+    assert_eq!(completions("move e", &legal_moves).1, vec!["e3", "e4"]);
+    assert!(completions("board N", &legal_moves).1.is_empty());
+    assert_eq!(completions("pe", &legal_moves).1, vec!["peek"]);
+    assert_eq!(completions("debug d", &legal_moves).1, vec!["dump"]);
+    assert_eq!(completions("export ", &legal_moves).1, vec!["png", "svg"]);
+    assert_eq!(completions("export s", &legal_moves).1, vec!["svg"]);
+    assert_eq!(
+        completions("theme ", &legal_moves).1,
+        vec!["blue", "classic", "high-contrast", "plain", "solarized"]
+    );
+}
+
+#[test]
+fn test_promotion() {
+    assert_eq!(with_promotion("e8", 'Q'), "e8=Q");
+    assert_eq!(with_promotion("exd8+", 'N'), "exd8=N+");
+    assert_eq!(with_promotion("move e7->e8#", 'R'), "move e7->e8=R#");
+    assert_eq!(AutoPromote::by_name("queen"), Some(AutoPromote::Queen));
+    assert_eq!(AutoPromote::by_name("knight"), None);
+
+    let mut board = ChessBoard::from_fen("3r3k/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+    assert!(matches!(
+        execute_command(&mut board, "exd8"),
+        Err(BadMove(MoveRejection::MissingPromotion))
+    ));
+    for command in ["exd8+", "move e7->e8"] {
+        let mut promoted = board;
+        assert!(matches!(
+            execute_command(&mut promoted, &with_promotion(command, 'Q')),
+            Ok(Outcome::Moved(_))
+        ));
+    }
+}
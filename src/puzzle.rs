@@ -0,0 +1,306 @@
+//! Tactics puzzles: a position and the line that wins from it. The player finds their moves one
+//! at a time and the opponent's replies are played for them, as on Lichess.
+
+use std::io::{BufRead, Error, ErrorKind, Write};
+use std::time::SystemTime;
+
+use serde_json::Value;
+
+use crate::movegen::{square_name, Move};
+use crate::{ChessBoard, WHITE};
+
+/// Where Lichess publishes the puzzle of the day.
+const DAILY_URL: &str = "https://lichess.org/api/puzzle/daily";
+
+/// The puzzles used when Lichess can't be reached: an id, the position, the solution in UCI
+/// notation (alternating between the player and their opponent), a rating and themes.
+const OFFLINE_PUZZLES: [(&str, &str, &str, u32, &str); 6] = [
+    (
+        "offline-1",
+        "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+        "d1d8",
+        600,
+        "mateIn1 backRankMate",
+    ),
+    (
+        "offline-2",
+        "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+        "h5f7",
+        700,
+        "mateIn1 opening",
+    ),
+    (
+        "offline-3",
+        "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2",
+        "d8h4",
+        500,
+        "mateIn1 opening",
+    ),
+    (
+        "offline-4",
+        "6rk/6pp/8/6N1/8/8/8/6K1 w - - 0 1",
+        "g5f7",
+        900,
+        "mateIn1 smotheredMate",
+    ),
+    (
+        "offline-5",
+        "r3k3/8/8/1N6/8/8/8/4K3 w - - 0 1",
+        "b5c7 e8e7 c7a8",
+        1000,
+        "fork short",
+    ),
+    (
+        "offline-6",
+        "2r2r1k/1p2Nppp/p7/7Q/8/3R4/PPP2PPP/2K4R w - - 0 1",
+        "h5h7 h8h7 d3h3",
+        1400,
+        "mateIn2 sacrifice anastasiaMate",
+    ),
+];
+
+/// A position to solve and the line that solves it.
+pub struct Puzzle {
+    pub id: String,
+    pub board: ChessBoard,
+    /// The winning line, starting with the player's move and alternating with the opponent's
+    /// replies.
+    pub solution: Vec<Move>,
+    pub rating: u32,
+    pub themes: Vec<String>,
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads a move in UCI notation, such as `e2e4` or `e7e8q`.
+fn parse_uci(board: &ChessBoard, uci: &str) -> Option<Move> {
+    let (from, to) = (uci.get(..2)?, uci.get(2..)?);
+    board.parse_move(&format!("{}->{}", from, to)).ok()
+}
+
+/// Plays out `solution` from `board`, checking every move is legal.
+fn solution_moves<'a>(
+    board: &ChessBoard,
+    solution: impl IntoIterator<Item = &'a str>,
+) -> Option<Vec<Move>> {
+    let mut board = *board;
+    let mut moves = Vec::new();
+    for uci in solution {
+        let mv = parse_uci(&board, uci)?;
+        board.apply_move(mv);
+        moves.push(mv);
+    }
+    (!moves.is_empty()).then_some(moves)
+}
+
+impl Puzzle {
+    /// Reads a puzzle from Lichess's puzzle API, which gives the game leading up to the puzzle
+    /// in SAN and the solution in UCI.
+    pub fn from_lichess(json: &Value) -> Result<Puzzle, Error> {
+        let malformed = || invalid("Lichess sent a puzzle that couldn't be read.");
+        let mut board = ChessBoard::new();
+        for san in json["game"]["pgn"]
+            .as_str()
+            .ok_or_else(malformed)?
+            .split_whitespace()
+        {
+            let mv = board.parse_move(san).map_err(|_| malformed())?;
+            board.apply_move(mv);
+        }
+        let puzzle = &json["puzzle"];
+        let solution = puzzle["solution"].as_array().ok_or_else(malformed)?;
+        let solution = solution_moves(&board, solution.iter().filter_map(Value::as_str))
+            .ok_or_else(malformed)?;
+        Ok(Puzzle {
+            id: puzzle["id"].as_str().ok_or_else(malformed)?.to_string(),
+            board,
+            solution,
+            rating: puzzle["rating"].as_u64().unwrap_or_default() as u32,
+            themes: puzzle["themes"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or(&[])
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+
+    /// The `index`th bundled puzzle (wrapping around), for playing without a connection.
+    pub fn offline(index: usize) -> Puzzle {
+        let (id, fen, solution, rating, themes) = OFFLINE_PUZZLES[index % OFFLINE_PUZZLES.len()];
+        let board = ChessBoard::from_fen(fen).unwrap();
+        Puzzle {
+            id: id.to_string(),
+            board,
+            solution: solution_moves(&board, solution.split_whitespace()).unwrap(),
+            rating,
+            themes: themes.split_whitespace().map(str::to_string).collect(),
+        }
+    }
+
+    /// Fetches the Lichess puzzle of the day.
+    pub fn daily() -> Result<Puzzle, Error> {
+        let text = ureq::get(DAILY_URL)
+            .header("Accept", "application/json")
+            .call()
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .map_err(|e| Error::other(format!("Couldn't download the daily puzzle: {}", e)))?;
+        let json = serde_json::from_str(&text).map_err(|_| invalid("Lichess didn't send JSON."))?;
+        Puzzle::from_lichess(&json)
+    }
+
+    /// Lets the player solve the puzzle, reading their moves from `input`. Besides moves, they
+    /// may ask for a `hint` or give up and see the `solution`. Returns whether they solved it
+    /// without a wrong move.
+    pub fn solve(&self, input: &mut impl BufRead, output: &mut impl Write) -> Result<bool, Error> {
+        let mut board = self.board;
+        let side = if board.side_to_move() == WHITE {
+            "White"
+        } else {
+            "Black"
+        };
+        writeln!(
+            output,
+            "Puzzle {} (rated {}). {} to play and win.",
+            self.id, self.rating, side
+        )?;
+        let mut clean = true;
+        let mut step = 0;
+        while step < self.solution.len() {
+            write!(output, "{}> ", board)?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(false);
+            }
+            let expected = self.solution[step];
+            match line.trim() {
+                "" => continue,
+                "hint" => {
+                    let square = square_name(expected.from);
+                    writeln!(output, "Move the piece on {}.", square)?;
+                    clean = false;
+                    continue;
+                }
+                "solution" | "quit" | "exit" => {
+                    let line = board.line_sans(&self.solution[step..]).join(" ");
+                    writeln!(output, "The solution was {}.", line)?;
+                    return Ok(false);
+                }
+                text => {
+                    let mv = match board.parse_move(text.strip_prefix("move ").unwrap_or(text)) {
+                        Ok(mv) => mv,
+                        Err(e) => {
+                            writeln!(output, "{}", e)?;
+                            continue;
+                        }
+                    };
+                    let mut next = board;
+                    next.apply_move(mv);
+                    // Any mate finishes a mating puzzle, even one other than the intended move.
+                    let mates = next
+                        .outcome()
+                        .is_some_and(|(_, reason)| reason == "checkmate");
+                    if mv != expected && !mates {
+                        writeln!(output, "That's not it. Try again.")?;
+                        clean = false;
+                        continue;
+                    }
+                    if mates || step + 1 == self.solution.len() {
+                        writeln!(output, "{}Solved!", next)?;
+                        if !self.themes.is_empty() {
+                            writeln!(output, "Themes: {}", self.themes.join(", "))?;
+                        }
+                        return Ok(clean);
+                    }
+                    let reply = self.solution[step + 1];
+                    writeln!(output, "Correct! The reply is {}.", next.san(reply))?;
+                    next.apply_move(reply);
+                    board = next;
+                    step += 2;
+                }
+            }
+        }
+        Ok(clean)
+    }
+}
+
+/// Runs `puzzle daily`: the Lichess puzzle of the day, or a bundled puzzle (changing daily)
+/// when `offline` is set or Lichess can't be reached.
+pub fn daily(offline: bool) -> Result<(), Error> {
+    let puzzle = match offline {
+        true => Err(invalid("Playing offline.")),
+        false => Puzzle::daily(),
+    };
+    let puzzle = puzzle.unwrap_or_else(|e| {
+        if !offline {
+            eprintln!("{} Using an offline puzzle instead.", e);
+        }
+        let days = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() / 86_400)
+            .unwrap_or_default();
+        Puzzle::offline(days as usize)
+    });
+    let stdin = std::io::stdin();
+    puzzle.solve(&mut stdin.lock(), &mut std::io::stdout())?;
+    Ok(())
+}
+
+#[test]
+fn test_offline_puzzles() {
+    for index in 0..OFFLINE_PUZZLES.len() {
+        let puzzle = Puzzle::offline(index);
+        // The engine agrees with every move the player has to find.
+        let mut board = puzzle.board;
+        for (step, &mv) in puzzle.solution.iter().enumerate() {
+            if step % 2 == 0 {
+                assert!(board.search(4).best_move == Some(mv), "{}", puzzle.id);
+            }
+            board.apply_move(mv);
+        }
+    }
+}
+
+#[test]
+fn test_puzzle() {
+    let json = serde_json::json!({
+        "game": { "pgn": "e4 e5 Bc4 Nc6 Qh5 Nf6" },
+        "puzzle": { "id": "Scholar", "rating": 700, "solution": ["h5f7"], "themes": ["mateIn1"] },
+    });
+    let puzzle = Puzzle::from_lichess(&json).unwrap();
+    assert_eq!(puzzle.board.side_to_move(), WHITE);
+    assert_eq!(puzzle.themes, ["mateIn1"]);
+    let mut output = Vec::new();
+    assert!(puzzle
+        .solve(&mut "Qf3\nQxf7#\n".as_bytes(), &mut output)
+        .is_ok_and(|clean| !clean));
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("That's not it. Try again."));
+    assert!(output.ends_with("Solved!\nThemes: mateIn1\n"));
+
+    // The opponent's replies are played automatically.
+    let fork = Puzzle::offline(4);
+    let mut output = Vec::new();
+    assert!(fork
+        .solve(&mut "hint\nNc7+\nNxa8\n".as_bytes(), &mut output)
+        .is_ok_and(|clean| !clean));
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("Move the piece on b5."));
+    assert!(output.contains("The reply is Ke7."));
+
+    let mut output = Vec::new();
+    assert!(fork
+        .solve(&mut "solution\n".as_bytes(), &mut output)
+        .is_ok_and(|clean| !clean));
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .contains("The solution was Nc7+ Ke7 Nxa8."));
+
+    let json = serde_json::json!({ "game": { "pgn": "e4 e4" }, "puzzle": {} });
+    assert!(Puzzle::from_lichess(&json).is_err());
+}
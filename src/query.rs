@@ -0,0 +1,366 @@
+//! Searches PGN files for games by their tags and moves. Games are read one at a time and their
+//! moves aren't played, so files of millions of games can be searched quickly in little memory.
+
+use std::io::{BufRead, Error, ErrorKind, Write};
+
+use crate::pgn::{self, GameReader};
+
+/// What a game must match to be selected. Unset fields match every game.
+#[derive(Default)]
+pub struct Filter {
+    /// Part of either player's name, ignoring case.
+    pub player: Option<String>,
+    pub white: Option<String>,
+    pub black: Option<String>,
+    /// An ECO code prefix (`B2`) or an inclusive range (`B20-B99`).
+    pub eco: Option<String>,
+    pub result: Option<String>,
+    /// The earliest and latest dates, in PGN's `YYYY.MM.DD` format. A year or month alone covers
+    /// all of it.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// The lowest rating both players must have.
+    pub min_elo: Option<u32>,
+    /// Moves the game starts with, in SAN. `*` stands for any one move and `...` for any number
+    /// of moves.
+    pub moves: Option<Vec<String>>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+/// A move as compared by move patterns: without its move number, check marks or annotations.
+fn normalize(san: &str) -> &str {
+    san.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.')
+        .trim_end_matches(['+', '#', '!', '?'])
+}
+
+/// Reads a move pattern such as `1. e4 c5 ... Nd5`. The pattern is matched from a game's first
+/// move, and anything may follow it.
+pub fn parse_pattern(pattern: &str) -> Vec<String> {
+    let mut words = pattern
+        .split_whitespace()
+        .map(|word| match word {
+            "..." => word,
+            word => normalize(word),
+        })
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<String>>();
+    if words.last().is_none_or(|word| word != "...") {
+        words.push("...".to_string());
+    }
+    words
+}
+
+fn matches_pattern(pattern: &[String], moves: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => moves.is_empty(),
+        Some((first, rest)) if first == "..." => {
+            (0..=moves.len()).any(|skip| matches_pattern(rest, &moves[skip..]))
+        }
+        Some((first, rest)) => moves.split_first().is_some_and(|(mv, moves)| {
+            (first == "*" || first == mv) && matches_pattern(rest, moves)
+        }),
+    }
+}
+
+fn contains_ignoring_case(name: &str, part: &str) -> bool {
+    name.to_lowercase().contains(&part.to_lowercase())
+}
+
+impl Filter {
+    /// Checks that the options can be used, before any games are read.
+    pub fn validate(&self) -> Result<(), Error> {
+        for date in self.from.iter().chain(&self.to) {
+            let digits = date.chars().filter(char::is_ascii_digit).count();
+            if !date.chars().all(|c| c.is_ascii_digit() || c == '.') || digits < 4 {
+                return Err(invalid(format!("{} is not a date like 2024.03.15.", date)));
+            }
+        }
+        if let Some(eco) = &self.eco {
+            let valid = |code: &str| {
+                code.len() <= 3
+                    && code.starts_with(|c: char| ('A'..='E').contains(&c))
+                    && code[1..].chars().all(|c| c.is_ascii_digit())
+            };
+            let (low, high) = eco.split_once('-').unwrap_or((eco, eco));
+            if !valid(low) || !valid(high) {
+                return Err(invalid(format!("{} is not an ECO code like B20.", eco)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a game with these tags and moves (as returned by `pgn::scan`) is wanted.
+    pub fn matches(&self, tags: &[(String, String)], moves: &[String]) -> bool {
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(tag, _)| tag == name)
+                .map_or("", |(_, value)| value.as_str())
+        };
+        let (white, black) = (tag("White"), tag("Black"));
+        if let Some(player) = &self.player {
+            if !contains_ignoring_case(white, player) && !contains_ignoring_case(black, player) {
+                return false;
+            }
+        }
+        if self
+            .white
+            .as_ref()
+            .is_some_and(|name| !contains_ignoring_case(white, name))
+            || self
+                .black
+                .as_ref()
+                .is_some_and(|name| !contains_ignoring_case(black, name))
+        {
+            return false;
+        }
+        if let Some(eco) = &self.eco {
+            let code = tag("ECO");
+            let matches = match eco.split_once('-') {
+                Some((low, high)) => code.len() == 3 && low <= code && code <= high,
+                None => code.starts_with(eco.as_str()),
+            };
+            if !matches {
+                return false;
+            }
+        }
+        if self
+            .result
+            .as_ref()
+            .is_some_and(|result| tag("Result") != result)
+        {
+            return false;
+        }
+        let date = tag("Date");
+        let known = date
+            .get(..4)
+            .is_some_and(|year| year.bytes().all(|b| b.is_ascii_digit()));
+        if self
+            .from
+            .as_ref()
+            .is_some_and(|from| !known || date < from.as_str())
+        {
+            return false;
+        }
+        // A date only as precise as `to` (`2024.03` for `2024.03.15`) is in range.
+        if self
+            .to
+            .as_ref()
+            .is_some_and(|to| !known || date.get(..to.len()).unwrap_or(date) > to.as_str())
+        {
+            return false;
+        }
+        if let Some(min_elo) = self.min_elo {
+            let rated = |name| tag(name).parse::<u32>().is_ok_and(|elo| elo >= min_elo);
+            if !rated("WhiteElo") || !rated("BlackElo") {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.moves {
+            let moves = moves
+                .iter()
+                .map(|san| normalize(san))
+                .collect::<Vec<&str>>();
+            if !matches_pattern(pattern, &moves) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Copies the games in `input` that match to `output` unchanged, returning how many games
+    /// were read and how many matched. Games that can't be read are skipped with a warning.
+    pub fn run(
+        &self,
+        input: impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<(usize, usize), Error> {
+        let (mut read, mut matched) = (0, 0);
+        for text in GameReader::new(input) {
+            let text = text?;
+            read += 1;
+            let (tags, moves) = match pgn::scan(&text) {
+                Ok(game) => game,
+                Err(e) => {
+                    eprintln!("Skipping game {}, which couldn't be read: {}", read, e);
+                    continue;
+                }
+            };
+            if self.matches(&tags, &moves) {
+                matched += 1;
+                writeln!(output, "{}\n", text.trim_end())?;
+            }
+        }
+        output.flush()?;
+        Ok((read, matched))
+    }
+}
+
+/// Runs `pgn query`: the games in `input` (a file, or `-` for stdin) that match `filter` are
+/// written to `output`, or stdout.
+pub fn query(input: &str, output: Option<&str>, filter: &Filter) -> Result<(), Error> {
+    filter.validate()?;
+    let stdin = std::io::stdin();
+    let input: Box<dyn BufRead> = match input {
+        "-" => Box::new(stdin.lock()),
+        path => Box::new(std::io::BufReader::new(std::fs::File::open(path)?)),
+    };
+    let (read, matched) = match output {
+        Some(path) => {
+            let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+            filter.run(input, &mut file)?
+        }
+        None => filter.run(input, &mut std::io::stdout().lock())?,
+    };
+    eprintln!("{} of {} games matched.", matched, read);
+    Ok(())
+}
+
+#[test]
+fn test_query() {
+    let text = "[Event \"One\"]\n[Date \"2023.05.01\"]\n[White \"Carlsen, Magnus\"]\n\
+        [Black \"Caruana, Fabiano\"]\n[Result \"1-0\"]\n[WhiteElo \"2850\"]\n[BlackElo \"2800\"]\n\
+        [ECO \"B33\"]\n\n1. e4 c5 2. Nf3 {Open Sicilian} Nc6 3. d4 cxd4 4. Nxd4 Nf6 1-0\n\n\
+        [Event \"Two\"]\n[Date \"2024.01.??\"]\n[White \"Fool\"]\n[Black \"Carlsen, Magnus\"]\n\
+        [Result \"0-1\"]\n[ECO \"A00\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n\
+        [Event \"Broken\"]\n\n1. e4 {unclosed\n";
+    let run = |filter: Filter| {
+        let mut output = Vec::new();
+        let counts = filter.run(text.as_bytes(), &mut output).unwrap();
+        (counts, String::from_utf8(output).unwrap())
+    };
+
+    let (counts, output) = run(Filter::default());
+    assert_eq!(counts, (3, 2));
+    assert!(output.starts_with("[Event \"One\"]"));
+    assert!(output.ends_with("Qh4# 0-1\n\n"));
+
+    let events = |filter: Filter| {
+        let (_, output) = run(filter);
+        crate::pgn::split_games(&output)
+            .iter()
+            .map(|game| crate::pgn::scan(game).unwrap().0[0].1.clone())
+            .collect::<Vec<String>>()
+    };
+    let player = |name: &str| Some(name.to_string());
+    assert_eq!(
+        events(Filter {
+            player: player("carlsen"),
+            ..Filter::default()
+        }),
+        ["One", "Two"]
+    );
+    assert_eq!(
+        events(Filter {
+            white: player("carlsen"),
+            ..Filter::default()
+        }),
+        ["One"]
+    );
+    assert_eq!(
+        events(Filter {
+            black: player("Fool"),
+            ..Filter::default()
+        })
+        .len(),
+        0
+    );
+    assert_eq!(
+        events(Filter {
+            eco: player("B"),
+            ..Filter::default()
+        }),
+        ["One"]
+    );
+    assert_eq!(
+        events(Filter {
+            eco: player("A00-A99"),
+            ..Filter::default()
+        }),
+        ["Two"]
+    );
+    assert_eq!(
+        events(Filter {
+            result: player("0-1"),
+            ..Filter::default()
+        }),
+        ["Two"]
+    );
+    assert_eq!(
+        events(Filter {
+            from: player("2024"),
+            ..Filter::default()
+        }),
+        ["Two"]
+    );
+    assert_eq!(
+        events(Filter {
+            to: player("2024.01"),
+            ..Filter::default()
+        }),
+        ["One", "Two"]
+    );
+    assert_eq!(
+        events(Filter {
+            to: player("2023.12.31"),
+            ..Filter::default()
+        }),
+        ["One"]
+    );
+    assert_eq!(
+        events(Filter {
+            min_elo: Some(2800),
+            ..Filter::default()
+        }),
+        ["One"]
+    );
+
+    // A date that isn't one, even with a character cut in two at its fourth byte, has no year.
+    let odd = "[Event \"Odd\"]\n[Date \"20\u{20ac}\"]\n\n1. e4 *\n";
+    let odd = |filter: Filter| {
+        let mut output = Vec::new();
+        filter.run(odd.as_bytes(), &mut output).unwrap().1
+    };
+    assert_eq!(odd(Filter::default()), 1);
+    assert_eq!(
+        odd(Filter {
+            from: player("2020"),
+            ..Filter::default()
+        }),
+        0
+    );
+
+    let moves = |pattern: &str| {
+        events(Filter {
+            moves: Some(parse_pattern(pattern)),
+            ..Filter::default()
+        })
+    };
+    assert_eq!(moves("1. e4 c5"), ["One"]);
+    assert_eq!(moves("e4 * Nf3"), ["One"]);
+    assert_eq!(moves("... Qh4#"), ["Two"]);
+    assert_eq!(moves("... d4 ... Nf6"), ["One"]);
+    assert_eq!(moves("d4").len(), 0);
+
+    assert!(Filter {
+        from: player("May"),
+        ..Filter::default()
+    }
+    .validate()
+    .is_err());
+    assert!(Filter {
+        eco: player("F00"),
+        ..Filter::default()
+    }
+    .validate()
+    .is_err());
+    assert!(Filter {
+        eco: player("B20-B99"),
+        ..Filter::default()
+    }
+    .validate()
+    .is_ok());
+}
@@ -0,0 +1,111 @@
+//! Racing Kings: both kings race to the eighth rank. There are no pawns, and no move may give
+//! check or leave a king in check. Whoever gets their king to the eighth rank first wins, but
+//! white's lead of a move is made up for: if black's king can reach it on the very next move,
+//! the game is drawn once it does.
+
+use crate::eval::PIECE_VALUES;
+use crate::movegen::{Move, MoveRejection};
+use crate::variant::{Standard, Variant};
+use crate::{ChessBoard, BLACK, EMPTY, GET_NUM, KING, WHITE};
+
+pub struct RacingKings;
+
+/// Whether `color`'s king stands on the eighth rank.
+fn has_arrived(board: &ChessBoard, color: u8) -> bool {
+    board.king_square(color).is_some_and(|square| square >= 56)
+}
+
+impl Variant for RacingKings {
+    fn name(&self) -> &'static str {
+        "racingkings"
+    }
+
+    fn pgn_name(&self) -> &'static str {
+        "Racing Kings"
+    }
+
+    fn start(&self) -> ChessBoard {
+        ChessBoard::from_variant_fen("8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1", &RacingKings)
+            .unwrap_or_else(|_| ChessBoard::new())
+    }
+
+    /// Neither king may be left in check, so giving check is against the rules too.
+    fn is_legal(&self, _before: &ChessBoard, after: &ChessBoard) -> bool {
+        !after.is_in_check(WHITE) && !after.is_in_check(BLACK)
+    }
+
+    fn rejection(&self, board: &ChessBoard, mv: Move) -> Option<MoveRejection> {
+        let mut next = *board;
+        next.apply_move(mv);
+        match next.is_in_check(board.side_to_move() ^ BLACK) {
+            true => Some(MoveRejection::GivesCheck),
+            false => None,
+        }
+    }
+
+    fn outcome(&self, board: &ChessBoard, moves: &[Move]) -> Option<(&'static str, &'static str)> {
+        let black_can_arrive = || {
+            board.side_to_move() == BLACK
+                && moves
+                    .iter()
+                    .any(|&mv| GET_NUM(board.get_piece(mv.from)) == KING && mv.to >= 56)
+        };
+        match (has_arrived(board, WHITE), has_arrived(board, BLACK)) {
+            (true, true) => Some(("1/2-1/2", "both_kings_arrived")),
+            (false, true) => Some(("0-1", "king_arrived")),
+            (true, false) if !black_can_arrive() => Some(("1-0", "king_arrived")),
+            (true, false) => None,
+            (false, false) => Standard.outcome(board, moves),
+        }
+    }
+
+    /// Counts material, and how far each king has come.
+    fn evaluate(&self, board: &ChessBoard) -> i32 {
+        let side = board.side_to_move();
+        (0..64)
+            .filter(|&square| board.get_piece(square) != EMPTY)
+            .map(|square| {
+                let piece = board.get_piece(square);
+                let value = match GET_NUM(piece) {
+                    KING => (square >> 3) as i32 * 100,
+                    number => PIECE_VALUES[number as usize],
+                };
+                if piece & BLACK == side {
+                    value
+                } else {
+                    -value
+                }
+            })
+            .sum()
+    }
+}
+
+#[test]
+fn test_racing_kings() {
+    use crate::variant::by_name;
+
+    let racing_kings = by_name("Racing Kings").unwrap();
+    let board = ChessBoard::start_of(racing_kings);
+    assert_eq!(board.fen(), "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1");
+    assert_eq!(board.perft(3), 11264);
+
+    // Checks may not be given.
+    assert_eq!(board.parse_san("Nc3"), Err(MoveRejection::GivesCheck));
+    assert!(!board.legal_sans().contains(&"Nc3".to_string()));
+
+    // White arriving first wins only if black can't arrive right after.
+    let fen = "8/6K1/8/8/8/8/1k6/8 w - - 0 1";
+    let mut board = ChessBoard::from_variant_fen(fen, racing_kings).unwrap();
+    board.apply_move(board.parse_san("Kg8").unwrap());
+    assert_eq!(board.outcome(), Some(("1-0", "king_arrived")));
+    let fen = "8/1k4K1/8/8/8/8/8/8 w - - 0 1";
+    let mut board = ChessBoard::from_variant_fen(fen, racing_kings).unwrap();
+    board.apply_move(board.parse_san("Kg8").unwrap());
+    assert_eq!(board.outcome(), None);
+    board.apply_move(board.parse_san("Ka6").unwrap());
+    assert_eq!(board.outcome(), Some(("1-0", "king_arrived")));
+    let mut board = ChessBoard::from_variant_fen(fen, racing_kings).unwrap();
+    board.apply_move(board.parse_san("Kg8").unwrap());
+    board.apply_move(board.parse_san("Kb8").unwrap());
+    assert_eq!(board.outcome(), Some(("1/2-1/2", "both_kings_arrived")));
+}
@@ -0,0 +1,146 @@
+//! Random legal positions, for fuzzing the move generator, benchmarking the evaluation and
+//! seeding training data. Without a material constraint a position is reached by random moves
+//! from the start position, so it could come up in a game (if not a good one). With one, the
+//! pieces are scattered over the board, keeping only placements that are legal: pawns off the
+//! first and last ranks, and the side that just moved not in check.
+
+use std::io::{Error, ErrorKind};
+
+use crate::net::random_u64;
+use crate::selfplay::Rng;
+use crate::{ChessBoard, BLACK, EMPTY, KING, PAWN, PIECE_LETTERS, WHITE};
+
+/// The most random moves played to reach a position.
+const MAX_PLIES: usize = 80;
+
+/// Reads the pieces of a material constraint, white's then black's with a `v` between them, as
+/// in `KRPvKR`. Each side needs exactly one king, at most eight pawns and at most sixteen pieces.
+pub fn parse_material(material: &str) -> Result<Vec<u8>, String> {
+    let Some((white, black)) = material.split_once(['v', 'V']) else {
+        return Err(
+            "The material needs a v between white's pieces and black's, as in KQvK.".to_string(),
+        );
+    };
+    let mut pieces = Vec::new();
+    for (side, color) in [(white, WHITE), (black, BLACK)] {
+        let side_pieces = side
+            .chars()
+            .map(|c| {
+                PIECE_LETTERS
+                    .iter()
+                    .position(|&letter| letter == c.to_ascii_uppercase() && c != ' ')
+                    .map(|piece| piece as u8 | color)
+                    .ok_or_else(|| format!("{} is no piece.", c))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        let count = |piece: u8| side_pieces.iter().filter(|&&p| p == piece | color).count();
+        if count(KING) != 1 {
+            return Err("Each side needs exactly one king.".to_string());
+        }
+        if count(PAWN) > 8 || side_pieces.len() > 16 {
+            return Err("A side can't have more than eight pawns or sixteen pieces.".to_string());
+        }
+        pieces.extend(side_pieces);
+    }
+    Ok(pieces)
+}
+
+/// A random legal position that isn't over. With `material`, it has exactly those pieces (as
+/// read by `parse_material`), placed anywhere legal, with either side to move; otherwise it is
+/// reached by up to `MAX_PLIES` random moves from the start.
+pub fn random_position(material: Option<&[u8]>, rng: &mut Rng) -> ChessBoard {
+    loop {
+        let board = match material {
+            Some(pieces) => scatter(pieces, rng),
+            None => play_randomly(rng.below(MAX_PLIES + 1), rng),
+        };
+        if let Some(board) = board.filter(|board| board.outcome().is_none()) {
+            return board;
+        }
+    }
+}
+
+/// Puts `pieces` on random squares, or returns `None` if the placement isn't legal.
+fn scatter(pieces: &[u8], rng: &mut Rng) -> Option<ChessBoard> {
+    let mut board = ChessBoard::new();
+    board.board = [[0; 4]; 8];
+    board.castling = 0;
+    board.moves = rng.below(2) as u16;
+    for &piece in pieces {
+        // Pawns stay off the first and last ranks.
+        let squares = match piece & !BLACK {
+            PAWN => 8..56,
+            _ => 0..64,
+        };
+        let empty = squares
+            .filter(|&square| board.get_piece(square) == EMPTY)
+            .collect::<Vec<u8>>();
+        board.set_piece(empty[rng.below(empty.len())], piece);
+    }
+    let legal = board.validate().is_ok() && !board.is_in_check(board.side_to_move() ^ BLACK);
+    legal.then_some(board)
+}
+
+/// Plays `plies` random legal moves from the start position, or returns `None` if the game
+/// ends first.
+fn play_randomly(plies: usize, rng: &mut Rng) -> Option<ChessBoard> {
+    let mut board = ChessBoard::new();
+    for _ in 0..plies {
+        let moves = board.legal_moves();
+        if moves.is_empty() {
+            return None;
+        }
+        board.apply_move(moves[rng.below(moves.len())]);
+    }
+    Some(board)
+}
+
+/// Runs `random-position`: prints the FENs of `count` random positions, with `material` if
+/// given, from `seed` (or a random seed).
+pub fn run(count: u32, material: Option<&str>, seed: Option<u64>) -> Result<(), Error> {
+    let material = material
+        .map(parse_material)
+        .transpose()
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let mut rng = Rng::new(seed.unwrap_or_else(random_u64));
+    for _ in 0..count {
+        println!("{}", random_position(material.as_deref(), &mut rng).fen());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_random_position() {
+    let mut rng = Rng::new(7);
+    for _ in 0..20 {
+        let board = random_position(None, &mut rng);
+        assert_eq!(board.validate(), Ok(()));
+        assert!(ChessBoard::from_fen(&board.fen()).is_ok());
+        assert!(board.outcome().is_none());
+    }
+
+    let material = parse_material("KRPPvkr").unwrap();
+    assert_eq!(material.len(), 6);
+    for _ in 0..20 {
+        let board = random_position(Some(&material), &mut rng);
+        let fen = board.fen();
+        assert!(ChessBoard::from_fen(&fen).is_ok(), "{}", fen);
+        let placement = fen.split(' ').next().unwrap();
+        let mut letters = placement
+            .chars()
+            .filter(char::is_ascii_alphabetic)
+            .collect::<Vec<char>>();
+        letters.sort_unstable();
+        assert_eq!(letters, ['K', 'P', 'P', 'R', 'k', 'r']);
+        assert!(fen.contains(" - - "));
+    }
+
+    // The same seed gives the same positions.
+    let first = random_position(None, &mut Rng::new(11)).fen();
+    assert_eq!(random_position(None, &mut Rng::new(11)).fen(), first);
+
+    assert!(parse_material("KQK").is_err());
+    assert!(parse_material("KQvQ").is_err());
+    assert!(parse_material("KXvK").is_err());
+    assert!(parse_material("KPPPPPPPPPvK").is_err());
+}
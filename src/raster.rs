@@ -0,0 +1,542 @@
+//! Raster images of positions and games, for sharing outside the terminal: a PNG of a position
+//! and an animated GIF of a game, one frame per move. They have the layout and colors of
+//! `render`'s SVG diagrams, with pixel-art pieces and coordinates in place of its font glyphs,
+//! and are encoded here, so all they need is zlib.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Write};
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::annotation::Color;
+use crate::pgn::PgnGame;
+use crate::render::{
+    center, Diagram, ARROW, BLUE, BORDER, DARK, FRAME, HIGHLIGHT, LABEL, LIGHT, MARGIN, RED,
+    SQUARE, YELLOW,
+};
+use crate::tree::Node;
+use crate::{ChessBoard, BLACK, EMPTY, GET_NUM};
+
+/// The colors images are drawn in. Pixels are indices into it, and it is the palette of both
+/// formats, padded out to the sixteen colors a GIF palette can have.
+const PALETTE: [&str; 16] = [
+    BORDER, LABEL, LIGHT, DARK, HIGHLIGHT, ARROW, "#ffffff", "#000000", RED, YELLOW, BLUE,
+    "#000000", "#000000", "#000000", "#000000", "#000000",
+];
+
+const BORDER_INDEX: u8 = 0;
+const LABEL_INDEX: u8 = 1;
+const LIGHT_INDEX: u8 = 2;
+const DARK_INDEX: u8 = 3;
+const HIGHLIGHT_INDEX: u8 = 4;
+const WHITE_INDEX: u8 = 6;
+const BLACK_INDEX: u8 = 7;
+
+/// Where an annotation color is in `PALETTE`.
+fn index(color: Color) -> u8 {
+    match color {
+        Color::Green => 5,
+        Color::Red => 8,
+        Color::Yellow => 9,
+        Color::Blue => 10,
+    }
+}
+
+/// The pieces, indexed by piece number less one: `#` is the outline and `o` the piece's color.
+#[rustfmt::skip]
+const SPRITES: [[&str; 16]; 6] = [
+    [
+        "................", "................", "................", ".......##.......",
+        "......#oo#......", "......#oo#......", ".......##.......", "......#oo#......",
+        "......#oo#......", ".....#oooo#.....", "....#oooooo#....", "...#oooooooo#...",
+        "...##########...", "................", "................", "................",
+    ],
+    [
+        "................", "................", "......##.#......", ".....#oo#o#.....",
+        "....#oooooo#....", "...#oo#ooooo#...", "..#oooooooooo#..", "..#ooo##ooooo#..",
+        "...##..#ooooo#..", "......#ooooo#...", ".....#ooooo#....", "....#oooooo#....",
+        "...#oooooooo#...", "...##########...", "................", "................",
+    ],
+    [
+        "................", "................", "...##..##..##...", "...#o##oo##o#...",
+        "...#oooooooo#...", "...##########...", "....#oooooo#....", "....#oooooo#....",
+        "....#oooooo#....", "....#oooooo#....", "....#oooooo#....", "...##########...",
+        "..#oooooooooo#..", "..############..", "................", "................",
+    ],
+    [
+        "................", ".......##.......", "......#oo#......", ".......##.......",
+        "......#oo#......", ".....#o#oo#.....", "....#oo#ooo#....", "....#oooooo#....",
+        ".....#oooo#.....", "......#oo#......", ".....######.....", "....#oooooo#....",
+        "...#oooooooo#...", "...##########...", "................", "................",
+    ],
+    [
+        "................", ".##....##....##.", ".#o#..#oo#..#o#.", "..##..#oo#..##..",
+        "..#o#.#oo#.#o#..", "..#oo#oooo#oo#..", "..#oooooooooo#..", "...#oooooooo#...",
+        "...#oooooooo#...", "....#oooooo#....", "....#oooooo#....", "...##########...",
+        "..#oooooooooo#..", "..############..", "................", "................",
+    ],
+    [
+        "......####......", "......#oo#......", "....###oo###....", "....#oooooo#....",
+        "....###oo###....", "......#oo#......", "..############..", ".#oooooooooooo#.",
+        ".#oooooooooooo#.", "..#oooooooooo#..", "...#oooooooo#...", "...#oooooooo#...",
+        "..############..", "..#oooooooooo#..", "..############..", "................",
+    ],
+];
+
+/// How many pixels each pixel of a sprite or glyph takes up.
+const SCALE: u32 = 2;
+
+/// The coordinates, 3 by 5: the files `a` to `h`, then the ranks `1` to `8`.
+#[rustfmt::skip]
+const GLYPHS: [[&str; 5]; 16] = [
+    ["...", ".##", "#.#", "#.#", ".##"], ["#..", "##.", "#.#", "#.#", "##."],
+    ["...", ".##", "#..", "#..", ".##"], ["..#", ".##", "#.#", "#.#", ".##"],
+    ["...", ".#.", "###", "#..", ".##"], [".##", "#..", "##.", "#..", "#.."],
+    [".##", "#.#", ".##", "..#", "##."], ["#..", "##.", "#.#", "#.#", "#.#"],
+    [".#.", "##.", ".#.", ".#.", "###"], ["##.", "..#", ".#.", "#..", "###"],
+    ["##.", "..#", ".#.", "..#", "##."], ["#.#", "#.#", "###", "..#", "..#"],
+    ["###", "#..", "##.", "..#", "##."], [".##", "#..", "##.", "#.#", ".#."],
+    ["###", "..#", ".#.", ".#.", ".#."], [".#.", "#.#", ".#.", "#.#", ".#."],
+];
+
+/// An image whose pixels are indices into `PALETTE`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    fn new(width: u32, height: u32) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![BORDER_INDEX; (width * height) as usize],
+        }
+    }
+
+    fn set(&mut self, x: u32, y: u32, color: u8) {
+        if x < self.width && y < self.height {
+            self.pixels[(y * self.width + x) as usize] = color;
+        }
+    }
+
+    fn fill(&mut self, x: u32, y: u32, width: u32, height: u32, color: u8) {
+        for dy in 0..height {
+            for dx in 0..width {
+                self.set(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    /// Draws the `#`s of `rows` (and the `o`s, if `fill` is given) scaled up, with their top
+    /// left corner at (`x`, `y`).
+    fn blit(&mut self, rows: &[&str], x: u32, y: u32, outline: u8, fill: Option<u8>) {
+        for (row, line) in rows.iter().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let color = match c {
+                    '#' => outline,
+                    'o' => match fill {
+                        Some(fill) => fill,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                let (px, py) = (x + column as u32 * SCALE, y + row as u32 * SCALE);
+                self.fill(px, py, SCALE, SCALE, color);
+            }
+        }
+    }
+}
+
+/// Whether (`x`, `y`) is within `width / 2` of the segment from `from` to `to`.
+fn near_segment((x, y): (f64, f64), from: (f64, f64), to: (f64, f64), width: f64) -> bool {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = (dx * dx + dy * dy).max(1e-9);
+    let t = (((x - from.0) * dx + (y - from.1) * dy) / length).clamp(0.0, 1.0);
+    let (nx, ny) = (from.0 + t * dx - x, from.1 + t * dy - y);
+    nx * nx + ny * ny <= width * width / 4.0
+}
+
+/// Whether `point` is inside the triangle with corners `corners`.
+fn in_triangle(point: (f64, f64), corners: [(f64, f64); 3]) -> bool {
+    let side = |a: (f64, f64), b: (f64, f64)| {
+        (b.0 - a.0) * (point.1 - a.1) - (b.1 - a.1) * (point.0 - a.0)
+    };
+    let sides = [
+        side(corners[0], corners[1]),
+        side(corners[1], corners[2]),
+        side(corners[2], corners[0]),
+    ];
+    sides.iter().all(|&s| s >= 0.0) || sides.iter().all(|&s| s <= 0.0)
+}
+
+/// Draws `board` with what `diagram` adds, as `render::svg` does.
+pub fn draw(board: &ChessBoard, diagram: &Diagram) -> Image {
+    let (files, ranks) = board.variant.dimensions();
+    let mut image = Image::new(
+        files as u32 * SQUARE + 2 * MARGIN,
+        ranks as u32 * SQUARE + 2 * MARGIN,
+    );
+    let sprite_size = 16 * SCALE;
+    for rank in 0..ranks {
+        for file in 0..files {
+            let square = rank * 8 + file;
+            let (x, y) = center(square, ranks);
+            let highlighted = diagram
+                .last_move
+                .is_some_and(|(from, to)| square == from || square == to);
+            let color = match (highlighted, (file + rank) % 2 == 1) {
+                (true, _) => HIGHLIGHT_INDEX,
+                (false, true) => LIGHT_INDEX,
+                (false, false) => DARK_INDEX,
+            };
+            let (left, top) = (x - SQUARE / 2, y - SQUARE / 2);
+            image.fill(left, top, SQUARE, SQUARE, color);
+            let frames = diagram.annotations.squares.iter();
+            for &(color, _) in frames.filter(|&&(_, framed)| framed == square) {
+                let color = index(color);
+                image.fill(left, top, SQUARE, FRAME, color);
+                image.fill(left, top + SQUARE - FRAME, SQUARE, FRAME, color);
+                image.fill(left, top, FRAME, SQUARE, color);
+                image.fill(left + SQUARE - FRAME, top, FRAME, SQUARE, color);
+            }
+            let piece = board.get_piece(square);
+            if piece != EMPTY {
+                let fill = match piece & BLACK {
+                    BLACK => BLACK_INDEX,
+                    _ => WHITE_INDEX,
+                };
+                let sprite = &SPRITES[GET_NUM(piece) as usize - 1];
+                let (left, top) = (x - sprite_size / 2, y - sprite_size / 2);
+                image.blit(sprite, left, top, BLACK_INDEX, Some(fill));
+            }
+        }
+    }
+
+    let (glyph_width, glyph_height) = (3 * SCALE, 5 * SCALE);
+    let height = image.height;
+    let width = image.width;
+    for file in 0..files {
+        let (x, _) = center(file, ranks);
+        for y in [MARGIN / 2, height - MARGIN / 2] {
+            let (left, top) = (x - glyph_width / 2, y - glyph_height / 2);
+            image.blit(&GLYPHS[file as usize], left, top, LABEL_INDEX, None);
+        }
+    }
+    for rank in 0..ranks {
+        let (_, y) = center(rank * 8, ranks);
+        for x in [MARGIN / 2, width - MARGIN / 2] {
+            let (left, top) = (x - glyph_width / 2, y - glyph_height / 2);
+            image.blit(&GLYPHS[8 + rank as usize], left, top, LABEL_INDEX, None);
+        }
+    }
+
+    let shaft = (SQUARE / 5) as f64;
+    for &(color, from, to) in &diagram.annotations.arrows {
+        let (x1, y1) = center(from, ranks);
+        let (x2, y2) = center(to, ranks);
+        let start = (x1 as f64, y1 as f64);
+        let (dx, dy) = (x2 as f64 - start.0, y2 as f64 - start.1);
+        let length = dx.hypot(dy).max(1.0);
+        let (ux, uy) = (dx / length, dy / length);
+        // The head ends short of the center, as in the SVG, and the shaft where the head starts.
+        let tip = (
+            x2 as f64 - ux * SQUARE as f64 / 6.0,
+            y2 as f64 - uy * SQUARE as f64 / 6.0,
+        );
+        let base = (tip.0 - ux * shaft * 2.2, tip.1 - uy * shaft * 2.2);
+        let wing = (-uy * shaft * 1.2, ux * shaft * 1.2);
+        let head = [
+            tip,
+            (base.0 + wing.0, base.1 + wing.1),
+            (base.0 - wing.0, base.1 - wing.1),
+        ];
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let point = (x as f64 + 0.5, y as f64 + 0.5);
+                if near_segment(point, start, base, shaft) || in_triangle(point, head) {
+                    image.set(x, y, index(color));
+                }
+            }
+        }
+    }
+    image
+}
+
+/// `PALETTE` as RGB bytes.
+fn palette() -> Vec<u8> {
+    PALETTE
+        .iter()
+        .flat_map(|color| {
+            (0..3).map(|i| u8::from_str_radix(&color[1 + 2 * i..3 + 2 * i], 16).unwrap())
+        })
+        .collect()
+}
+
+/// Encodes `image` as a PNG with `PALETTE` as its palette.
+pub fn png(image: &Image) -> Vec<u8> {
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut chunk = |kind: &[u8; 4], data: &[u8]| {
+        png.extend((data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend(kind);
+        png.extend(data);
+        let crc = crc32fast::hash(&png[start..]);
+        png.extend(crc.to_be_bytes());
+    };
+    let mut header = Vec::new();
+    header.extend(image.width.to_be_bytes());
+    header.extend(image.height.to_be_bytes());
+    // Eight bits per pixel, indexed color, and the only compression, filter and interlace
+    // methods.
+    header.extend([8, 3, 0, 0, 0]);
+    chunk(b"IHDR", &header);
+    chunk(b"PLTE", &palette());
+    // Each row starts with its filter type, none.
+    let mut rows = Vec::with_capacity(image.pixels.len() + image.height as usize);
+    for row in image.pixels.chunks(image.width as usize) {
+        rows.push(0);
+        rows.extend(row);
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing to a Vec can't fail.
+    let _ = encoder.write_all(&rows);
+    chunk(b"IDAT", &encoder.finish().unwrap_or_default());
+    chunk(b"IEND", &[]);
+    png
+}
+
+/// How many bits GIF's LZW codes start with, for a palette of sixteen colors.
+const MIN_CODE_SIZE: u8 = 4;
+
+/// Compresses `pixels` with GIF's variant of LZW, whose codes grow from `MIN_CODE_SIZE + 1` bits
+/// to 12 as the table fills, written least significant bit first.
+fn lzw(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut table = HashMap::new();
+    let mut next = end + 1;
+    let mut width = MIN_CODE_SIZE as u32 + 1;
+    let mut bytes = Vec::new();
+    let (mut bits, mut count) = (0u32, 0u32);
+    let mut emit = |code: u16, width: u32| {
+        bits |= (code as u32) << count;
+        count += width;
+        while count >= 8 {
+            bytes.push(bits as u8);
+            bits >>= 8;
+            count -= 8;
+        }
+    };
+
+    emit(clear, width);
+    let mut prefix = None;
+    for &pixel in pixels {
+        let Some(current) = prefix else {
+            prefix = Some(pixel as u16);
+            continue;
+        };
+        if let Some(&code) = table.get(&(current, pixel)) {
+            prefix = Some(code);
+            continue;
+        }
+        emit(current, width);
+        if next < 4096 {
+            table.insert((current, pixel), next);
+            next += 1;
+            if next > 1 << width && width < 12 {
+                width += 1;
+            }
+        } else {
+            emit(clear, width);
+            table.clear();
+            next = end + 1;
+            width = MIN_CODE_SIZE as u32 + 1;
+        }
+        prefix = Some(pixel as u16);
+    }
+    if let Some(code) = prefix {
+        emit(code, width);
+    }
+    emit(end, width);
+    // Pads out the last byte, if it is only partly filled.
+    emit(0, 7);
+    bytes
+}
+
+/// Encodes `frames`, all the same size, as an animated GIF that shows each for `delay_ms`
+/// milliseconds and loops forever.
+pub fn gif(frames: &[Image], delay_ms: u32) -> Vec<u8> {
+    let (width, height) = frames
+        .first()
+        .map_or((1, 1), |frame| (frame.width as u16, frame.height as u16));
+    let mut gif = b"GIF89a".to_vec();
+    gif.extend(width.to_le_bytes());
+    gif.extend(height.to_le_bytes());
+    // A global palette of 2^(3 + 1) colors, with eight bits per channel.
+    gif.extend([0xf3, BORDER_INDEX, 0]);
+    gif.extend(palette());
+    gif.extend(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+    let delay = (delay_ms / 10).min(u16::MAX as u32) as u16;
+    for frame in frames {
+        gif.extend([0x21, 0xf9, 4, 0]);
+        gif.extend(delay.to_le_bytes());
+        gif.extend([0, 0]);
+        gif.push(0x2c);
+        gif.extend([0; 4]);
+        gif.extend(width.to_le_bytes());
+        gif.extend(height.to_le_bytes());
+        gif.push(0);
+        gif.push(MIN_CODE_SIZE);
+        for block in lzw(&frame.pixels).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend(block);
+        }
+        gif.push(0);
+    }
+    gif.push(0x3b);
+    gif
+}
+
+/// Runs `render --png`: writes `board`, with what `diagram` adds, to the PNG file at `path`.
+pub fn save_png(board: &ChessBoard, diagram: &Diagram, path: &str) -> Result<(), Error> {
+    std::fs::write(path, png(&draw(board, diagram)))
+}
+
+/// The frames of the game from `start` through `line`: the start, then each position reached
+/// with the move that led to it highlighted and its annotations drawn.
+pub fn frames(start: &ChessBoard, line: &[Node]) -> Vec<Image> {
+    let mut board = *start;
+    let mut frames = vec![draw(&board, &Diagram::default())];
+    for node in line {
+        board.apply_move(node.mv);
+        let diagram = Diagram {
+            last_move: Some((node.mv.from, node.mv.to)),
+            annotations: node.annotations.clone(),
+        };
+        frames.push(draw(&board, &diagram));
+    }
+    frames
+}
+
+/// Runs `render --gif`: writes the first game in the PGN file at `pgn` to the GIF file at
+/// `path`, one frame per move, `delay_ms` apart.
+pub fn save_gif(pgn: &str, delay_ms: u32, path: &str) -> Result<(), Error> {
+    let game = PgnGame::parse(&std::fs::read_to_string(pgn)?)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    let frames = frames(&game.tree.start, &game.tree.mainline);
+    std::fs::write(path, gif(&frames, delay_ms))
+}
+
+#[test]
+fn test_raster() {
+    let board = ChessBoard::new();
+    let diagram = Diagram {
+        last_move: Some((12, 28)),
+        annotations: crate::annotation::Annotations {
+            squares: vec![(Color::Red, 27)],
+            arrows: vec![(Color::Green, 6, 21)],
+        },
+    };
+    let image = draw(&board, &diagram);
+    assert_eq!((image.width, image.height), (400, 400));
+    let pixel = |x: u32, y: u32| image.pixels[(y * image.width + x) as usize];
+    // a1 is dark, b1 light, e4 highlighted, and the arrow's shaft runs through g1 to f3.
+    assert_eq!(pixel(MARGIN + 1, image.height - MARGIN - 2), DARK_INDEX);
+    assert_eq!(
+        pixel(MARGIN + SQUARE + 1, image.height - MARGIN - 2),
+        LIGHT_INDEX
+    );
+    let (x, y) = center(28, 8);
+    assert_eq!(
+        pixel(x - SQUARE / 2 + 1, y - SQUARE / 2 + 1),
+        HIGHLIGHT_INDEX
+    );
+    let (x, y) = center(6, 8);
+    assert_eq!(pixel(x - 5, y - 10), index(Color::Green));
+    let (x, y) = center(27, 8);
+    assert_eq!(pixel(x - SQUARE / 2, y), index(Color::Red));
+    assert_eq!(pixel(x - SQUARE / 2 + FRAME, y), DARK_INDEX);
+    // The black king's sprite is on e8.
+    let (x, y) = center(60, 8);
+    assert_eq!(pixel(x, y), BLACK_INDEX);
+    let (x, y) = center(4, 8);
+    assert_eq!(pixel(x, y), WHITE_INDEX);
+
+    let png = png(&image);
+    assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\x01\x90\0\0\x01\x90\x08\x03"));
+    assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+    // The pixels come back out of the compressed data.
+    let start = png.windows(4).position(|w| w == b"IDAT").unwrap();
+    let length = u32::from_be_bytes(png[start - 4..start].try_into().unwrap()) as usize;
+    let mut rows = Vec::new();
+    let mut decoder = flate2::read::ZlibDecoder::new(&png[start + 4..start + 4 + length]);
+    std::io::Read::read_to_end(&mut decoder, &mut rows).unwrap();
+    assert_eq!(
+        rows.len(),
+        (image.width * image.height + image.height) as usize
+    );
+    assert_eq!(&rows[1..401], &image.pixels[..400]);
+
+    // Decodes GIF's LZW, to check the encoder against.
+    let unlzw = |bytes: &[u8]| {
+        let clear = 1usize << MIN_CODE_SIZE;
+        let mut pixels: Vec<u8> = Vec::new();
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut width = MIN_CODE_SIZE as usize + 1;
+        let (mut position, mut previous): (usize, Option<Vec<u8>>) = (0, None);
+        loop {
+            let code = (0..width)
+                .map(|bit| {
+                    (bytes[(position + bit) / 8] as usize >> ((position + bit) % 8) & 1) << bit
+                })
+                .sum::<usize>();
+            position += width;
+            if code == clear {
+                table = (0..clear as u8).map(|i| vec![i]).collect();
+                table.extend([Vec::new(), Vec::new()]);
+                width = MIN_CODE_SIZE as usize + 1;
+                previous = None;
+                continue;
+            }
+            if code == clear + 1 {
+                return pixels;
+            }
+            let entry = match (table.get(code), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => [previous.clone(), vec![previous[0]]].concat(),
+                (None, None) => panic!("bad code"),
+            };
+            if let Some(previous) = previous {
+                if table.len() < 4096 {
+                    table.push([previous, vec![entry[0]]].concat());
+                }
+            }
+            if table.len() == 1 << width && width < 12 {
+                width += 1;
+            }
+            pixels.extend(&entry);
+            previous = Some(entry);
+        }
+    };
+    let many = (0..100_000)
+        .map(|i| (i * 7 / 13 % 16) as u8)
+        .collect::<Vec<u8>>();
+    assert_eq!(unlzw(&lzw(&many)), many);
+    assert_eq!(unlzw(&lzw(&image.pixels)), image.pixels);
+
+    let e4 = board.parse_move("e4").unwrap();
+    let frames = frames(&board, &[Node::new(e4)]);
+    assert_eq!(frames.len(), 2);
+    let gif = gif(&frames, 500);
+    assert!(gif.starts_with(b"GIF89a\x90\x01\x90\x01\xf3"));
+    assert_eq!(gif.last(), Some(&0x3b));
+    assert_eq!(
+        gif.windows(4).filter(|w| w == b"\x21\xf9\x04\x00").count(),
+        2
+    );
+    assert!(gif.windows(6).any(|w| w == b"\x21\xf9\x04\x00\x32\x00"));
+}
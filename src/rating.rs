@@ -0,0 +1,145 @@
+//! Glicko-2 ratings (<http://www.glicko.net/glicko/glicko2.pdf>). Every rated game is its own
+//! rating period, so ratings move as soon as a game ends.
+
+use std::f64::consts::PI;
+
+/// Glicko-2's scale factor between the displayed ratings and its internal scale.
+const SCALE: f64 = 173.7178;
+
+/// How much the volatility may change between periods. Glickman suggests 0.3 to 1.2.
+const TAU: f64 = 0.5;
+
+/// When the volatility iteration stops.
+const TOLERANCE: f64 = 0.000_001;
+
+/// A player's rating. `deviation` is how uncertain the rating is, and `volatility` how erratic
+/// the player's results have been.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Rating {
+    /// The rating of a player who hasn't played yet.
+    fn default() -> Rating {
+        Rating {
+            rating: 1500.0,
+            deviation: 350.0,
+            volatility: 0.06,
+        }
+    }
+}
+
+/// Glicko-2's `g` function, which discounts games against opponents with uncertain ratings.
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+impl Rating {
+    fn mu(&self) -> f64 {
+        (self.rating - 1500.0) / SCALE
+    }
+
+    fn phi(&self) -> f64 {
+        self.deviation / SCALE
+    }
+
+    /// The rating after one period of `games`, each against an opponent's rating (as it was
+    /// before the period) with a score of 1 for a win, 0.5 for a draw and 0 for a loss.
+    pub fn update(&self, games: &[(Rating, f64)]) -> Rating {
+        let (mu, phi, sigma) = (self.mu(), self.phi(), self.volatility);
+        if games.is_empty() {
+            let deviation = (phi * phi + sigma * sigma).sqrt() * SCALE;
+            return Rating { deviation, ..*self };
+        }
+
+        let mut inverse_variance = 0.0;
+        let mut improvement = 0.0;
+        for (opponent, score) in games {
+            let g = g(opponent.phi());
+            let expected = 1.0 / (1.0 + (-g * (mu - opponent.mu())).exp());
+            inverse_variance += g * g * expected * (1.0 - expected);
+            improvement += g * (score - expected);
+        }
+        let variance = 1.0 / inverse_variance;
+        let delta = variance * improvement;
+
+        // The new volatility, found with the Illinois algorithm as in step 5 of the paper.
+        let a = (sigma * sigma).ln();
+        let f = |x: f64| {
+            let ex = x.exp();
+            let d = phi * phi + variance + ex;
+            ex * (delta * delta - d) / (2.0 * d * d) - (x - a) / (TAU * TAU)
+        };
+        let mut a_x = a;
+        let mut b_x = if delta * delta > phi * phi + variance {
+            (delta * delta - phi * phi - variance).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * TAU) < 0.0 {
+                k += 1.0;
+            }
+            a - k * TAU
+        };
+        let (mut f_a, mut f_b) = (f(a_x), f(b_x));
+        while (b_x - a_x).abs() > TOLERANCE {
+            let c_x = a_x + (a_x - b_x) * f_a / (f_b - f_a);
+            let f_c = f(c_x);
+            if f_c * f_b <= 0.0 {
+                a_x = b_x;
+                f_a = f_b;
+            } else {
+                f_a /= 2.0;
+            }
+            b_x = c_x;
+            f_b = f_c;
+        }
+        let volatility = (a_x / 2.0).exp();
+
+        let pre_period = (phi * phi + volatility * volatility).sqrt();
+        let new_phi = 1.0 / (1.0 / (pre_period * pre_period) + inverse_variance).sqrt();
+        let new_mu = mu + new_phi * new_phi * improvement;
+        Rating {
+            rating: new_mu * SCALE + 1500.0,
+            deviation: new_phi * SCALE,
+            volatility,
+        }
+    }
+}
+
+#[test]
+fn test_rating() {
+    // The worked example from Glickman's paper.
+    let player = Rating {
+        rating: 1500.0,
+        deviation: 200.0,
+        volatility: 0.06,
+    };
+    let opponent = |rating, deviation| Rating {
+        rating,
+        deviation,
+        volatility: 0.06,
+    };
+    let updated = player.update(&[
+        (opponent(1400.0, 30.0), 1.0),
+        (opponent(1550.0, 100.0), 0.0),
+        (opponent(1700.0, 300.0), 0.0),
+    ]);
+    assert!((updated.rating - 1464.06).abs() < 0.01);
+    assert!((updated.deviation - 151.52).abs() < 0.01);
+    assert!((updated.volatility - 0.05999).abs() < 0.00001);
+
+    // A period without games only makes the rating less certain.
+    let idle = player.update(&[]);
+    assert_eq!(idle.rating, 1500.0);
+    assert!(idle.deviation > 200.0);
+
+    // Beating an equal opponent gains exactly what they lose.
+    let new = Rating::default();
+    let winner = new.update(&[(new, 1.0)]);
+    let loser = new.update(&[(new, 0.0)]);
+    assert!(winner.rating > 1500.0);
+    assert!((winner.rating - 1500.0 + loser.rating - 1500.0).abs() < 0.001);
+}
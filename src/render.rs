@@ -0,0 +1,262 @@
+//! Board diagrams as SVG, for embedding in blogs and reports: the position with its coordinates,
+//! the last move highlighted, and colored arrows and squares drawn over the board.
+
+use std::fmt::Write;
+use std::io::Error;
+
+use crate::annotation::{Annotations, Color};
+use crate::movegen::Move;
+use crate::{ChessBoard, BLACK, CHESS_PIECES, EMPTY, GET_NUM};
+
+/// The width of a square, in SVG units (and pixels, in `raster`'s images).
+pub const SQUARE: u32 = 45;
+
+/// The width of the border holding the coordinates.
+pub const MARGIN: u32 = 20;
+
+pub const BORDER: &str = "#404040";
+pub const LABEL: &str = "#e0e0e0";
+pub const LIGHT: &str = "#f0d9b5";
+pub const DARK: &str = "#b58863";
+pub const HIGHLIGHT: &str = "#cdd26a";
+pub const ARROW: &str = "#15781b";
+pub const RED: &str = "#882020";
+pub const YELLOW: &str = "#e68f00";
+pub const BLUE: &str = "#003088";
+
+/// The width of the frame drawn inside a highlighted square.
+pub const FRAME: u32 = 4;
+
+/// How an annotation color is drawn.
+pub fn hex(color: Color) -> &'static str {
+    match color {
+        Color::Green => ARROW,
+        Color::Red => RED,
+        Color::Yellow => YELLOW,
+        Color::Blue => BLUE,
+    }
+}
+
+/// What is drawn over the position.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diagram {
+    /// The squares the last move went from and to, which are highlighted.
+    pub last_move: Option<(u8, u8)>,
+    /// Colored arrows and squares.
+    pub annotations: Annotations,
+}
+
+/// Reads two squares written together, as in `e2e4` (or with a promotion piece after them,
+/// which is ignored), for a last move or an arrow.
+pub fn parse_squares(text: &str) -> Option<(u8, u8)> {
+    let (from, to) = (text.get(..2)?, text.get(2..4)?);
+    let valid = |square: &str| ChessBoard::is_valid_piece(square.as_bytes());
+    (valid(from) && valid(to) && text.len() <= 5).then(|| {
+        (
+            ChessBoard::square_of(from.as_bytes()),
+            ChessBoard::square_of(to.as_bytes()),
+        )
+    })
+}
+
+/// The center of `square`, with white at the bottom.
+pub fn center(square: u8, ranks: u8) -> (u32, u32) {
+    let file = (square & 7) as u32;
+    let rank = (square >> 3) as u32;
+    (
+        MARGIN + file * SQUARE + SQUARE / 2,
+        MARGIN + (ranks as u32 - 1 - rank) * SQUARE + SQUARE / 2,
+    )
+}
+
+/// Draws `board` as an SVG document, with what `diagram` adds.
+pub fn svg(board: &ChessBoard, diagram: &Diagram) -> String {
+    let (files, ranks) = board.variant.dimensions();
+    let (width, height) = (
+        files as u32 * SQUARE + 2 * MARGIN,
+        ranks as u32 * SQUARE + 2 * MARGIN,
+    );
+    let mut svg = String::new();
+    // Writing to a String can't fail.
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\">",
+        w = width,
+        h = height
+    );
+    svg.push_str("<defs>");
+    for color in [Color::Green, Color::Red, Color::Yellow, Color::Blue] {
+        let _ = write!(
+            svg,
+            "<marker id=\"arrowhead-{}\" markerWidth=\"4\" markerHeight=\"4\" refX=\"2\" \
+             refY=\"2\" orient=\"auto\"><path d=\"M0,0 L4,2 L0,4 z\" fill=\"{}\"/></marker>",
+            color.name(),
+            hex(color)
+        );
+    }
+    svg.push_str("</defs>\n");
+    let _ = writeln!(
+        svg,
+        "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+        width, height, BORDER
+    );
+
+    for rank in 0..ranks {
+        for file in 0..files {
+            let square = rank * 8 + file;
+            let (x, y) = center(square, ranks);
+            let light = (file + rank) % 2 == 1;
+            let highlighted = diagram
+                .last_move
+                .is_some_and(|(from, to)| square == from || square == to);
+            let fill = match (highlighted, light) {
+                (true, _) => HIGHLIGHT,
+                (false, true) => LIGHT,
+                (false, false) => DARK,
+            };
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{s}\" height=\"{s}\" fill=\"{}\"/>",
+                x - SQUARE / 2,
+                y - SQUARE / 2,
+                fill,
+                s = SQUARE
+            );
+            let frames = diagram.annotations.squares.iter();
+            for &(color, _) in frames.filter(|&&(_, framed)| framed == square) {
+                let _ = writeln!(
+                    svg,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{s}\" height=\"{s}\" fill=\"none\" \
+                     stroke=\"{}\" stroke-width=\"{}\"/>",
+                    x - SQUARE / 2 + FRAME / 2,
+                    y - SQUARE / 2 + FRAME / 2,
+                    hex(color),
+                    FRAME,
+                    s = SQUARE - FRAME
+                );
+            }
+            let piece = board.get_piece(square);
+            if piece != EMPTY {
+                // The solid glyphs, filled white or black, read better than the outlined ones.
+                let (fill, stroke) = match piece & BLACK {
+                    BLACK => ("#000", "#000"),
+                    _ => ("#fff", "#000"),
+                };
+                let _ = writeln!(
+                    svg,
+                    "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" \
+                     dominant-baseline=\"central\" fill=\"{}\" stroke=\"{}\">{}</text>",
+                    x,
+                    y,
+                    SQUARE * 5 / 6,
+                    fill,
+                    stroke,
+                    CHESS_PIECES[(GET_NUM(piece) | BLACK) as usize]
+                );
+            }
+        }
+    }
+
+    for file in 0..files {
+        let (x, _) = center(file, ranks);
+        let letter = (b'a' + file) as char;
+        for y in [MARGIN / 2, height - MARGIN / 2] {
+            let _ = writeln!(svg, "{}", label(x, y, letter));
+        }
+    }
+    for rank in 0..ranks {
+        let (_, y) = center(rank * 8, ranks);
+        let digit = (b'1' + rank) as char;
+        for x in [MARGIN / 2, width - MARGIN / 2] {
+            let _ = writeln!(svg, "{}", label(x, y, digit));
+        }
+    }
+
+    for &(color, from, to) in &diagram.annotations.arrows {
+        let ((x1, y1), (x2, y2)) = (center(from, ranks), center(to, ranks));
+        // Stop short of the center, so the head doesn't cover the piece.
+        let (dx, dy) = (x2 as f64 - x1 as f64, y2 as f64 - y1 as f64);
+        let length = dx.hypot(dy).max(1.0);
+        let shorten = SQUARE as f64 / 3.0;
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"{}\" \
+             stroke-opacity=\"0.8\" marker-end=\"url(#arrowhead-{})\"/>",
+            x1,
+            y1,
+            x2 as f64 - dx / length * shorten,
+            y2 as f64 - dy / length * shorten,
+            hex(color),
+            SQUARE / 5,
+            color.name()
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A coordinate letter or digit centered on (`x`, `y`).
+fn label(x: u32, y: u32, text: char) -> String {
+    format!(
+        "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" \
+         dominant-baseline=\"central\" fill=\"{}\">{}</text>",
+        x,
+        y,
+        MARGIN * 3 / 5,
+        LABEL,
+        text
+    )
+}
+
+/// Runs `render`: writes `board`, with what `diagram` adds, to the SVG file at `path`.
+pub fn run(board: &ChessBoard, diagram: &Diagram, path: &str) -> Result<(), Error> {
+    std::fs::write(path, svg(board, diagram))
+}
+
+/// Runs `export svg` and `export png`: writes `board` to the file at `path` in `format`,
+/// highlighting `last_move` and drawing `annotations`.
+pub fn export(
+    board: &ChessBoard,
+    last_move: Option<Move>,
+    annotations: &Annotations,
+    format: &str,
+    path: &str,
+) -> Result<(), Error> {
+    let diagram = Diagram {
+        last_move: last_move.map(|mv| (mv.from, mv.to)),
+        annotations: annotations.clone(),
+    };
+    match format {
+        "png" => crate::raster::save_png(board, &diagram, path),
+        _ => run(board, &diagram, path),
+    }
+}
+
+#[test]
+fn test_svg() {
+    assert_eq!(parse_squares("e2e4"), Some((12, 28)));
+    assert_eq!(parse_squares("e7e8q"), Some((52, 60)));
+    assert_eq!(parse_squares("e2"), None);
+    assert_eq!(parse_squares("e2i4"), None);
+
+    let mut board = ChessBoard::new();
+    board.apply_move(board.parse_move("e4").unwrap());
+    let diagram = Diagram {
+        last_move: parse_squares("e2e4"),
+        annotations: Annotations {
+            squares: vec![(Color::Red, 27)],
+            arrows: vec![(Color::Green, 6, 21), (Color::Blue, 5, 33)],
+        },
+    };
+    let svg = svg(&board, &diagram);
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"400\""));
+    assert!(svg.ends_with("</svg>\n"));
+    assert_eq!(svg.matches("<text").count(), 32 + 4 * 8);
+    assert_eq!(svg.matches(HIGHLIGHT).count(), 2);
+    assert_eq!(svg.matches("<line").count(), 2);
+    assert!(svg.contains("stroke=\"#003088\" stroke-width=\"9\" stroke-opacity=\"0.8\" marker-end=\"url(#arrowhead-blue)\"/>"));
+    assert!(svg.contains("<rect x=\"157\" y=\"202\" width=\"41\" height=\"41\" fill=\"none\" stroke=\"#882020\" stroke-width=\"4\"/>"));
+    // The white king on e1, drawn as a white solid king.
+    assert!(svg.contains("<text x=\"222\" y=\"357\" font-size=\"37\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"#fff\" stroke=\"#000\">♚</text>"));
+}
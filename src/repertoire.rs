@@ -0,0 +1,360 @@
+//! The repertoire trainer. A repertoire is a PGN game whose variations are the lines the player
+//! wants to know; the trainer plays the opponent's moves of a line and asks for the player's.
+//! Each line is scheduled by spaced repetition: lines played correctly come back after longer
+//! and longer intervals, and lines with mistakes come back the next day.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{json, Value};
+
+use crate::movegen::{square_name, Move};
+use crate::pgn::{self, PgnGame};
+use crate::{ChessBoard, WHITE};
+
+/// How much longer the interval gets after each correct review, to start with.
+const INITIAL_EASE: f64 = 2.5;
+/// The least the interval can grow by, however often a line is missed.
+const MIN_EASE: f64 = 1.3;
+
+/// When a line is next due, and how quickly its interval grows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Review {
+    /// Days between the last review and the next.
+    pub interval: u32,
+    pub ease: f64,
+    /// The day (counted from the Unix epoch) the line is next due.
+    pub due: u64,
+}
+
+impl Default for Review {
+    fn default() -> Review {
+        Review {
+            interval: 0,
+            ease: INITIAL_EASE,
+            due: 0,
+        }
+    }
+}
+
+impl Review {
+    /// The schedule after reviewing the line on `today`, without a mistake or not.
+    pub fn after(self, correct: bool, today: u64) -> Review {
+        if !correct {
+            // A missed line starts over, and comes back the next day.
+            return Review {
+                interval: 0,
+                ease: (self.ease - 0.2).max(MIN_EASE),
+                due: today + 1,
+            };
+        }
+        let interval = match self.interval {
+            0 => 1,
+            interval => (interval as f64 * self.ease).round() as u32,
+        };
+        Review {
+            interval,
+            ease: self.ease,
+            due: today + interval as u64,
+        }
+    }
+}
+
+/// The review schedule of every line, kept in a JSON file next to the repertoire.
+pub struct Schedule {
+    path: PathBuf,
+    /// By the line's moves in SAN, separated by spaces.
+    lines: BTreeMap<String, Review>,
+}
+
+/// Today, as days since the Unix epoch.
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / 86_400)
+        .unwrap_or_default()
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+impl Schedule {
+    /// Loads the schedule at `path`. A missing file is an empty schedule, in which every line
+    /// is due.
+    pub fn load(path: &Path) -> Result<Schedule, Error> {
+        let mut schedule = Schedule {
+            path: path.to_path_buf(),
+            lines: BTreeMap::new(),
+        };
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(schedule),
+            Err(e) => return Err(e),
+        };
+        let corrupt = || invalid(format!("{} is not a review schedule.", path.display()));
+        let json: Value = serde_json::from_str(&text).map_err(|_| corrupt())?;
+        for (line, review) in json["lines"].as_object().ok_or_else(corrupt)? {
+            let review = Review {
+                interval: review["interval"].as_u64().ok_or_else(corrupt)? as u32,
+                ease: review["ease"].as_f64().ok_or_else(corrupt)?,
+                due: review["due"].as_u64().ok_or_else(corrupt)?,
+            };
+            schedule.lines.insert(line.clone(), review);
+        }
+        Ok(schedule)
+    }
+
+    /// Writes the schedule back to its file.
+    pub fn save(&self) -> Result<(), Error> {
+        let lines = self
+            .lines
+            .iter()
+            .map(|(line, review)| {
+                let review = json!({
+                    "interval": review.interval,
+                    "ease": review.ease,
+                    "due": review.due,
+                });
+                (line.clone(), review)
+            })
+            .collect::<serde_json::Map<String, Value>>();
+        let text = serde_json::to_string_pretty(&json!({ "lines": lines }))?;
+        let partial = self.path.with_extension("partial");
+        std::fs::write(&partial, text + "\n")?;
+        std::fs::rename(&partial, &self.path)
+    }
+
+    pub fn review(&self, line: &str) -> Review {
+        self.lines.get(line).copied().unwrap_or_default()
+    }
+
+    pub fn is_due(&self, line: &str, today: u64) -> bool {
+        self.review(line).due <= today
+    }
+
+    /// Records a review of `line` on `today`.
+    pub fn record(&mut self, line: &str, correct: bool, today: u64) {
+        let review = self.review(line).after(correct, today);
+        self.lines.insert(line.to_string(), review);
+    }
+}
+
+/// Quizzes the player on `line`, played from `start` with the player as `color`. The opponent's
+/// moves are played for them; a wrong move is corrected and the line goes on. Returns whether
+/// they knew every move, or `None` if they stopped.
+pub fn quiz(
+    start: &ChessBoard,
+    line: &[Move],
+    color: u8,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<Option<bool>, Error> {
+    let mut board = *start;
+    let mut clean = true;
+    for &expected in line {
+        let numbered = pgn::numbered_sans(&board, &[expected]).join(" ");
+        if board.side_to_move() != color {
+            writeln!(output, "{}", numbered)?;
+            board.apply_move(expected);
+            continue;
+        }
+        loop {
+            write!(output, "{}Your move> ", board)?;
+            output.flush()?;
+            let mut text = String::new();
+            if input.read_line(&mut text)? == 0 {
+                return Ok(None);
+            }
+            match text.trim() {
+                "" => continue,
+                "quit" | "exit" => return Ok(None),
+                "hint" => {
+                    writeln!(output, "Move the piece on {}.", square_name(expected.from))?;
+                    clean = false;
+                    continue;
+                }
+                "show" => {
+                    writeln!(output, "The repertoire plays {}.", numbered)?;
+                    clean = false;
+                }
+                text => match board.parse_move(text) {
+                    Err(e) => {
+                        writeln!(output, "{}", e)?;
+                        continue;
+                    }
+                    Ok(mv) if mv == expected => writeln!(output, "Correct: {}.", numbered)?,
+                    Ok(_) => {
+                        writeln!(output, "No, the repertoire plays {}.", numbered)?;
+                        clean = false;
+                    }
+                },
+            }
+            board.apply_move(expected);
+            break;
+        }
+    }
+    writeln!(
+        output,
+        "{}",
+        match clean {
+            true => "Line complete!",
+            false => "Line complete, with mistakes. It will come back tomorrow.",
+        }
+    )?;
+    Ok(Some(clean))
+}
+
+/// Puts `items` in a random order.
+fn shuffle<T>(items: &mut [T]) -> Result<(), Error> {
+    let random = SystemRandom::new();
+    for i in (1..items.len()).rev() {
+        let mut bytes = [0; 4];
+        random
+            .fill(&mut bytes)
+            .map_err(|_| Error::other("No randomness available to pick lines."))?;
+        items.swap(i, u32::from_le_bytes(bytes) as usize % (i + 1));
+    }
+    Ok(())
+}
+
+/// Where the schedule of the repertoire at `path` is kept unless `train --schedule` says.
+pub fn default_schedule(path: &Path) -> PathBuf {
+    path.with_extension("schedule.json")
+}
+
+/// Runs `train`: quizzes the player, as `color`, on up to `limit` of the due lines of the
+/// repertoire in `path`, in a random order, and updates the schedule in `schedule_path`.
+pub fn train(path: &Path, color: u8, schedule_path: &Path, limit: usize) -> Result<(), Error> {
+    let text = std::fs::read_to_string(path)?;
+    let tree = PgnGame::parse(&text)
+        .map_err(|e| invalid(e.to_string()))?
+        .tree;
+    let (start, lines) = (tree.start, tree.lines());
+    let mut schedule = Schedule::load(schedule_path)?;
+    let today = today();
+    let mut due = lines
+        .iter()
+        .filter(|line| {
+            // Lines that end before the player has a move have nothing to ask.
+            let mut board = start;
+            line.iter().any(|&mv| {
+                let theirs = board.side_to_move() == color;
+                board.apply_move(mv);
+                theirs
+            })
+        })
+        .map(|line| (start.line_sans(line).join(" "), line))
+        .filter(|(key, _)| schedule.is_due(key, today))
+        .collect::<Vec<(String, &Vec<Move>)>>();
+    shuffle(&mut due)?;
+    due.truncate(limit);
+    println!(
+        "{} of the repertoire's {} lines are due.",
+        due.len(),
+        lines.len()
+    );
+
+    let stdin = std::io::stdin();
+    let (mut reviewed, mut correct) = (0, 0);
+    for (key, line) in &due {
+        let side = if color == WHITE { "White" } else { "Black" };
+        println!(
+            "\nLine {} of {}. You play {}.",
+            reviewed + 1,
+            due.len(),
+            side
+        );
+        let clean = match quiz(
+            &start,
+            line,
+            color,
+            &mut stdin.lock(),
+            &mut std::io::stdout(),
+        )? {
+            Some(clean) => clean,
+            None => break,
+        };
+        schedule.record(key, clean, today);
+        schedule.save()?;
+        reviewed += 1;
+        correct += clean as usize;
+    }
+    println!(
+        "Reviewed {} lines, {} without a mistake.",
+        reviewed, correct
+    );
+    Ok(())
+}
+
+#[test]
+fn test_repertoire() {
+    let mut review = Review::default();
+    review = review.after(true, 100);
+    assert_eq!((review.interval, review.due), (1, 101));
+    review = review.after(true, 101);
+    assert_eq!((review.interval, review.due), (3, 104));
+    review = review.after(true, 104);
+    assert_eq!(review.interval, 8);
+    review = review.after(false, 112);
+    assert_eq!((review.interval, review.due), (0, 113));
+    assert!(review.ease < INITIAL_EASE);
+    assert_eq!(review.after(true, 113).interval, 1);
+
+    let path = std::env::temp_dir().join(format!("freight_chess_{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut schedule = Schedule::load(&path).unwrap();
+    assert!(schedule.is_due("e4 e5", 100));
+    schedule.record("e4 e5", true, 100);
+    schedule.record("e4 c5", false, 100);
+    schedule.save().unwrap();
+    let schedule = Schedule::load(&path).unwrap();
+    assert!(!schedule.is_due("e4 e5", 100));
+    assert!(schedule.is_due("e4 e5", 101));
+    assert_eq!(schedule.review("e4 c5").ease, INITIAL_EASE - 0.2);
+    std::fs::remove_file(&path).unwrap();
+
+    // Playing black, white's moves are played for the player.
+    let tree = PgnGame::parse("1. e4 e5 (1... c5 2. Nf3 d6) 2. Nf3 Nc6 *")
+        .unwrap()
+        .tree;
+    let (start, lines) = (tree.start, tree.lines());
+    let mut output = Vec::new();
+    let result = quiz(
+        &start,
+        &lines[0],
+        crate::BLACK,
+        &mut "c5\nhint\nd6\n".as_bytes(),
+        &mut output,
+    );
+    assert_eq!(result.unwrap(), Some(false));
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("1. e4\n"));
+    assert!(output.contains("Correct: 1... c5.\n2. Nf3\n"));
+    assert!(output.contains("Move the piece on d7."));
+    assert!(output.ends_with("Line complete, with mistakes. It will come back tomorrow.\n"));
+
+    let mut output = Vec::new();
+    let result = quiz(
+        &start,
+        &lines[1],
+        WHITE,
+        &mut "e4\nd4\n".as_bytes(),
+        &mut output,
+    );
+    assert_eq!(result.unwrap(), Some(false));
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .contains("No, the repertoire plays 2. Nf3."));
+    let result = quiz(
+        &start,
+        &lines[1],
+        WHITE,
+        &mut "e4\n".as_bytes(),
+        &mut Vec::new(),
+    );
+    assert_eq!(result.unwrap(), None);
+}
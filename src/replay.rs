@@ -0,0 +1,330 @@
+//! Replays a PGN game move by move, following its variations and editing them: moves played
+//! that aren't in the game become new variations, and variations can be promoted or deleted.
+
+use std::io::{BufRead, Error, ErrorKind, Write};
+use std::path::Path;
+
+use crate::annotation::{parse_arrow, parse_highlight};
+use crate::config::DisplayConfig;
+use crate::pgn::{self, PgnGame};
+use crate::render;
+use crate::spoken::{spoken_board, spoken_move};
+use crate::tree::Cursor;
+
+const HELP: &str = "Press enter (or type 'next') to step forward, 'back' to step back, and \
+    'start' or 'end' to jump to the start or end of the line. A number plays that continuation, \
+    and a move that isn't in the game adds it as a variation. 'promote' makes the current \
+    variation the main continuation, 'delete' deletes it and 'cut' deletes the rest of the line. \
+    'comment TEXT' comments the last move, and 'arrow g1f3' and 'square d4' draw an arrow or \
+    frame a square on the position (in green, or with R, Y or B in front, as in 'arrow Rg1f3', \
+    in red, yellow or blue); the same again takes it off. 'export svg FILE' or 'export png \
+    FILE' saves a diagram of the position. 'save' writes the game back to its file (or 'save \
+    FILE' to another) and 'quit' stops.";
+
+/// Describes the position at `cursor`: the board, the moves leading to it with the last move's
+/// annotations, and the moves that can be played next, shown as `display` says. With a screen
+/// reader, the board and the last move are described in words.
+fn describe(game: &PgnGame, cursor: &Cursor, display: &DisplayConfig) -> String {
+    let tree = &game.tree;
+    let notation = display.notation;
+    let board = tree.board_at(cursor).unwrap_or(tree.start);
+    let mut text = match display.screen_reader {
+        true => spoken_board(&board),
+        false => board.grid(&display.layout),
+    };
+    let moves = tree.moves_to(cursor).unwrap_or_default();
+    if let Some((&last, before)) = moves.split_last().filter(|_| display.screen_reader) {
+        let mut previous = tree.start;
+        for &mv in before {
+            previous.apply_move(mv);
+        }
+        text.push_str(&format!("Last move: {}.\n", spoken_move(&previous, last)));
+    }
+    if !moves.is_empty() {
+        text.push_str(&pgn::numbered_moves(&tree.start, &moves, notation).join(" "));
+        if let Some(node) = tree.node(cursor) {
+            for nag in &node.nags {
+                text.push_str(&format!(" ${}", nag));
+            }
+            if let Some(comment) = &node.comment {
+                text.push_str(&format!(" {{{}}}", comment));
+            }
+        }
+        text.push('\n');
+        if let Some(node) = tree
+            .node(cursor)
+            .filter(|node| !node.annotations.is_empty())
+        {
+            text.push_str(&node.annotations.describe());
+            text.push('\n');
+        }
+    }
+    let continuations = tree.continuations(cursor);
+    if continuations.is_empty() {
+        text.push_str(&format!("End of the line. Result: {}\n", game.result));
+    } else {
+        let next = continuations
+            .iter()
+            .enumerate()
+            .map(|(i, &(mv, _))| format!("{}) {}", i + 1, board.notate(mv, notation)))
+            .collect::<Vec<String>>();
+        text.push_str(&format!("Next: {}\n", next.join("  ")));
+    }
+    text
+}
+
+/// Runs the replay of `game`, read from `path`, taking commands from `input` until it ends or
+/// the user quits. The game is shown as `display` says, and saved in its notation.
+pub fn run(
+    game: &mut PgnGame,
+    path: &Path,
+    display: &DisplayConfig,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<(), Error> {
+    writeln!(output, "{}", HELP)?;
+    let mut cursor = Cursor::default();
+    let mut unsaved = false;
+    loop {
+        write!(output, "\n{}replay> ", describe(game, &cursor, display))?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            break;
+        }
+        let (command, argument) = line
+            .trim()
+            .split_once(' ')
+            .map_or((line.trim(), ""), |(command, argument)| {
+                (command, argument.trim())
+            });
+        let tree = &mut game.tree;
+        let moved = match command {
+            "" | "next" => tree.forward(&cursor),
+            "back" => tree.back(&cursor),
+            "start" => Some(Cursor::default()),
+            "end" => tree.end_of_line(&cursor),
+            "help" => {
+                writeln!(output, "{}", HELP)?;
+                continue;
+            }
+            "quit" | "exit" => break,
+            "promote" => tree.promote(&cursor),
+            "delete" => tree.delete_variation(&cursor),
+            "cut" => {
+                tree.truncate(&cursor);
+                unsaved = true;
+                continue;
+            }
+            "comment" => {
+                if let Some(node) = tree.node_mut(&cursor) {
+                    node.comment = (!argument.is_empty()).then(|| argument.to_string());
+                    unsaved = true;
+                }
+                continue;
+            }
+            "arrow" | "square" => {
+                let Some(node) = tree.node_mut(&cursor) else {
+                    writeln!(output, "Play a move first.")?;
+                    continue;
+                };
+                let toggled = match command {
+                    "arrow" => {
+                        parse_arrow(argument).map(|arrow| node.annotations.toggle_arrow(arrow))
+                    }
+                    _ => parse_highlight(argument)
+                        .map(|square| node.annotations.toggle_square(square)),
+                };
+                match toggled {
+                    Some(()) => unsaved = true,
+                    None => writeln!(
+                        output,
+                        "{} is not {}.",
+                        argument,
+                        match command {
+                            "arrow" => "an arrow, such as g1f3 or Rg1f3",
+                            _ => "a square, such as d4 or Rd4",
+                        }
+                    )?,
+                }
+                continue;
+            }
+            "export" => {
+                let (format, file) = argument.split_once(' ').unwrap_or((argument, ""));
+                let file = file.trim();
+                if !matches!(format, "svg" | "png") || file.is_empty() {
+                    writeln!(
+                        output,
+                        "Export to a file as svg or png, as in 'export svg game.svg'."
+                    )?;
+                    continue;
+                }
+                let board = tree.board_at(&cursor).unwrap_or(tree.start);
+                let node = tree.node(&cursor);
+                let last_move = node.map(|node| node.mv);
+                let annotations = node
+                    .map(|node| node.annotations.clone())
+                    .unwrap_or_default();
+                match render::export(&board, last_move, &annotations, format, file) {
+                    Ok(()) => writeln!(output, "Saved the board to {}.", file)?,
+                    Err(e) => writeln!(output, "Could not save the board to {}: {}", file, e)?,
+                }
+                continue;
+            }
+            "save" => {
+                let target = match argument {
+                    "" => path,
+                    argument => Path::new(argument),
+                };
+                std::fs::write(target, game.write(display.notation))?;
+                writeln!(output, "Saved to {}.", target.display())?;
+                unsaved = false;
+                continue;
+            }
+            text => {
+                let board = tree.board_at(&cursor).unwrap_or(tree.start);
+                let continuations = tree.continuations(&cursor);
+                let chosen = text
+                    .parse::<usize>()
+                    .ok()
+                    .map(|number| continuations.get(number.wrapping_sub(1)).map(|(mv, _)| *mv));
+                let mv = match chosen {
+                    Some(Some(mv)) => mv,
+                    Some(None) => {
+                        writeln!(output, "There is no continuation {}.", text)?;
+                        continue;
+                    }
+                    None => match board.parse_move(text) {
+                        Ok(mv) => mv,
+                        Err(e) => {
+                            writeln!(output, "{}", e)?;
+                            continue;
+                        }
+                    },
+                };
+                unsaved |= continuations.iter().all(|&(next, _)| next != mv);
+                tree.add_move(&cursor, mv)
+            }
+        };
+        match moved {
+            Some(moved) => {
+                unsaved |= matches!(command, "promote" | "delete");
+                cursor = moved;
+            }
+            None => writeln!(
+                output,
+                "{}",
+                match command {
+                    "promote" | "delete" => "That can only be done in a variation.",
+                    "back" => "This is the start of the game.",
+                    _ => "This is the end of the line.",
+                }
+            )?,
+        }
+    }
+    if unsaved {
+        writeln!(output, "The changes were not saved.")?;
+    }
+    Ok(())
+}
+
+/// Runs `replay`: steps through the first game in the PGN file at `path`, showing it as
+/// `display` says.
+pub fn replay(path: &Path, display: &DisplayConfig) -> Result<(), Error> {
+    let text = std::fs::read_to_string(path)?;
+    let mut game = PgnGame::parse_in(&text, display.notation.letters())
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    let stdin = std::io::stdin();
+    run(
+        &mut game,
+        path,
+        display,
+        &mut stdin.lock(),
+        &mut std::io::stdout(),
+    )
+}
+
+#[test]
+fn test_replay() {
+    let path =
+        std::env::temp_dir().join(format!("freight_chess_replay_{}.pgn", std::process::id()));
+    let replayed = |game: &mut PgnGame, commands: &str| {
+        let mut output = Vec::new();
+        run(
+            game,
+            &path,
+            &DisplayConfig::default(),
+            &mut commands.as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    let mut game = PgnGame::parse("1. e4 e5 (1... c5 {Sicilian}) 2. Nf3 *").unwrap();
+    let output = replayed(
+        &mut game,
+        "\n\nback\n2\nback\nback\nback\nend\nnext\n9\nquit\n",
+    );
+    assert!(output.contains("Next: 1) e5  2) c5\n"));
+    assert!(output.contains("1. e4 c5 {Sicilian}\nEnd of the line. Result: *\n"));
+    assert!(output.contains("This is the start of the game."));
+    assert!(output.contains("1. e4 e5 2. Nf3\nEnd of the line."));
+    assert!(output.contains("This is the end of the line."));
+    assert!(output.contains("There is no continuation 9."));
+    assert!(!output.contains("not saved"));
+
+    // New moves become variations, which can be promoted, commented and saved.
+    let output = replayed(
+        &mut game,
+        "\nd5\nexd5\npromote\ncomment Scandinavian\nsave\nstart\n\n3\ndelete\ndelete\nquit\n",
+    );
+    assert!(output.contains("Next: 1) d5  2) e5  3) c5\n"));
+    assert!(output.contains(&format!("Saved to {}.", path.display())));
+    assert!(output.contains("That can only be done in a variation."));
+    assert!(output.ends_with("The changes were not saved.\n"));
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(
+        saved.contains("1. e4 d5 (1... e5 2. Nf3) (1... c5 {Sicilian}) 2. exd5 {Scandinavian} *")
+    );
+
+    // Arrows and squares are shown, toggled and saved with the comment.
+    let svg = path.with_extension("svg");
+    let output = replayed(
+        &mut game,
+        &format!(
+            "arrow e2e4\n\narrow Rg1f3\nsquare Bd5\nsquare e9\nexport svg {}\nsave\nquit\n",
+            svg.display()
+        ),
+    );
+    assert!(output.contains("Play a move first."));
+    assert!(output.contains("Arrows: red g1-f3. Squares: blue d5.\n"));
+    assert!(output.contains("e9 is not a square, such as d4 or Rd4."));
+    assert!(std::fs::read_to_string(&svg)
+        .unwrap()
+        .contains("url(#arrowhead-red)"));
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("1. e4 {[%csl Bd5] [%cal Rg1f3]} 1... d5"));
+    std::fs::remove_file(&svg).unwrap();
+
+    // With a screen reader, the board and the last move are read out in words.
+    let mut output = Vec::new();
+    let mut game = PgnGame::parse("1. e4 e5 *").unwrap();
+    run(
+        &mut game,
+        &path,
+        &DisplayConfig {
+            screen_reader: true,
+            ..DisplayConfig::default()
+        },
+        &mut "\nquit\n".as_bytes(),
+        &mut output,
+    )
+    .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("pawns a2, b2, c2, d2, f2, g2, h2 and e4.\n"));
+    assert!(output.contains("Black to move.\nLast move: white pawn e2 to e4.\n1. e4\n"));
+    assert!(!output.contains('|'));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,474 @@
+use crate::movegen::{square_name, Move, MoveRejection};
+use crate::{
+    ChessBoard, BISHOP, CHESS_PIECES, GET_NUM, KING, KNIGHT, PAWN, PIECE_LETTERS, QUEEN, ROOK,
+};
+
+/// The letters of the knight, bishop, rook, queen and king in some language, as SAN is written
+/// there: `SLTDK` in German, say, or `CFTDR` in French.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PieceLetters(pub [char; 5]);
+
+impl PieceLetters {
+    pub const ENGLISH: PieceLetters = PieceLetters(['N', 'B', 'R', 'Q', 'K']);
+
+    /// The letters used in the language with the ISO 639-1 code `locale`, for the languages
+    /// this program knows.
+    pub fn by_locale(locale: &str) -> Option<PieceLetters> {
+        let letters = match locale {
+            "en" => "NBRQK",
+            "de" | "sv" => "SLTDK",
+            "fr" => "CFTDR",
+            "es" | "it" => "CATDR",
+            "nl" => "PLTDK",
+            "pt" => "CBTDR",
+            "pl" => "SGWHK",
+            _ => return None,
+        };
+        PieceLetters::parse(letters)
+    }
+
+    /// Reads letters written as in `SLTDK`: five different capital letters, none of them the `O`
+    /// of castling.
+    pub fn parse(letters: &str) -> Option<PieceLetters> {
+        let letters = letters.chars().collect::<Vec<char>>();
+        let valid = letters
+            .iter()
+            .enumerate()
+            .all(|(i, &c)| c.is_uppercase() && c != 'O' && !letters[..i].contains(&c));
+        match letters.try_into() {
+            Ok(letters) if valid => Some(PieceLetters(letters)),
+            _ => None,
+        }
+    }
+
+    /// Swaps each letter in `text` in the alphabet `from` for the same piece's letter in `to`,
+    /// all at once, since the same letter may stand for different pieces in the two.
+    fn translate(text: &str, from: PieceLetters, to: PieceLetters) -> String {
+        text.chars()
+            .map(|c| match from.0.iter().position(|&letter| letter == c) {
+                Some(piece) => to.0[piece],
+                None => c,
+            })
+            .collect()
+    }
+
+    /// Writes the SAN `san` with these letters.
+    pub fn localize(self, san: &str) -> String {
+        PieceLetters::translate(san, PieceLetters::ENGLISH, self)
+    }
+
+    /// Writes `san`, written with these letters, with the English ones.
+    pub fn to_english(self, san: &str) -> String {
+        PieceLetters::translate(san, self, PieceLetters::ENGLISH)
+    }
+}
+
+/// How moves are written for people to read: in the move list, analysis and exported PGN.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Notation {
+    /// Standard Algebraic Notation, as in `Nf3` and `exd5`, with the pieces' letters.
+    San(PieceLetters),
+    /// SAN with the pieces drawn as figurines, as in `♘f3`.
+    Figurine,
+    /// Long algebraic notation, which names both squares, as in `Ng1-f3` and `e4xd5`.
+    Long(PieceLetters),
+    /// The squares alone, as UCI writes moves, as in `g1f3` and `e7e8q`.
+    Coordinate,
+}
+
+impl Default for Notation {
+    fn default() -> Notation {
+        Notation::San(PieceLetters::ENGLISH)
+    }
+}
+
+impl Notation {
+    /// The notation called `name` in the configuration file, with English letters.
+    pub fn by_name(name: &str) -> Option<Notation> {
+        match name {
+            "san" => Some(Notation::San(PieceLetters::ENGLISH)),
+            "figurine" => Some(Notation::Figurine),
+            "long" => Some(Notation::Long(PieceLetters::ENGLISH)),
+            "coordinate" => Some(Notation::Coordinate),
+            _ => None,
+        }
+    }
+
+    /// The notation with `letters` for the pieces, if it writes them as letters.
+    pub fn with_letters(self, letters: PieceLetters) -> Notation {
+        match self {
+            Notation::San(_) => Notation::San(letters),
+            Notation::Long(_) => Notation::Long(letters),
+            other => other,
+        }
+    }
+
+    /// The letters the notation writes pieces with, which are English if it doesn't use letters.
+    pub fn letters(self) -> PieceLetters {
+        match self {
+            Notation::San(letters) | Notation::Long(letters) => letters,
+            _ => PieceLetters::ENGLISH,
+        }
+    }
+}
+
+/// Strips the decorations that don't identify a move (check and mate markers, annotations and
+/// the `=` before a promotion piece), so that e.g. `e8=Q+` and `e8Q` compare equal. A pawn drop
+/// may leave out the `P`, and figurines stand for their letters.
+fn normalize(san: &str) -> String {
+    let san = san
+        .trim()
+        .trim_end_matches(['+', '#', '!', '?'])
+        .replace('=', "")
+        .replace('0', "O")
+        .chars()
+        .map(|c| {
+            match CHESS_PIECES
+                .iter()
+                .position(|&piece| piece == c && c != ' ')
+            {
+                Some(piece) => PIECE_LETTERS[piece & 7],
+                None => c,
+            }
+        })
+        .collect::<String>();
+    match san.starts_with('@') {
+        true => format!("P{}", san),
+        false => san,
+    }
+}
+
+impl ChessBoard {
+    /// Writes `mv` (which must be legal) in Standard Algebraic Notation, e.g. `Nbd7`, `exd5`,
+    /// `e8=Q+`, `O-O` or the drop `N@f3`.
+    pub fn san(&self, mv: Move) -> String {
+        let piece = GET_NUM(self.get_piece(mv.from));
+        let capture = (self.get_piece(mv.to) != crate::EMPTY && !self.is_castling(mv))
+            || (piece == PAWN && mv.to == self.en_passant);
+
+        let mut san = String::new();
+        if mv.is_drop() {
+            san.push(PIECE_LETTERS[mv.promotion as usize]);
+            san.push('@');
+            san.push_str(&square_name(mv.to));
+        } else if self.is_castling(mv) {
+            san.push_str(if self.is_kingside_castling(mv) {
+                "O-O"
+            } else {
+                "O-O-O"
+            });
+        } else if piece == PAWN {
+            if capture {
+                san.push((b'a' + (mv.from & 7)) as char);
+                san.push('x');
+            }
+            san.push_str(&square_name(mv.to));
+            if mv.promotion != crate::EMPTY {
+                san.push('=');
+                san.push(PIECE_LETTERS[mv.promotion as usize]);
+            }
+        } else {
+            san.push(PIECE_LETTERS[piece as usize]);
+
+            // Only name the starting file and/or rank when another piece of the same kind could
+            // also reach the target square.
+            let rivals = self
+                .legal_moves()
+                .into_iter()
+                .filter(|other| {
+                    other.to == mv.to
+                        && other.from != mv.from
+                        && GET_NUM(self.get_piece(other.from)) == piece
+                })
+                .collect::<Vec<Move>>();
+            if !rivals.is_empty() {
+                let from = square_name(mv.from);
+                if rivals.iter().all(|other| other.from & 7 != mv.from & 7) {
+                    san.push_str(&from[..1]);
+                } else if rivals.iter().all(|other| other.from >> 3 != mv.from >> 3) {
+                    san.push_str(&from[1..]);
+                } else {
+                    san.push_str(&from);
+                }
+            }
+
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&square_name(mv.to));
+        }
+
+        let mut next = *self;
+        next.apply_move(mv);
+        if next.is_in_check(next.side_to_move()) {
+            san.push(if next.legal_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+        san
+    }
+
+    /// Writes `mv` (which must be legal) in long algebraic notation, without a check marker:
+    /// the piece letter (none for pawns), where it comes from, `-` or `x` and where it goes, as
+    /// in `Ng1-f3`, `e4xd5` or `e7-e8=Q`. Castling and drops are written as in SAN.
+    fn long_algebraic(&self, mv: Move) -> String {
+        if mv.is_drop() || self.is_castling(mv) {
+            return self.san(mv).trim_end_matches(['+', '#']).to_string();
+        }
+        let piece = GET_NUM(self.get_piece(mv.from));
+        let capture =
+            self.get_piece(mv.to) != crate::EMPTY || (piece == PAWN && mv.to == self.en_passant);
+        let mut long = String::new();
+        if piece != PAWN {
+            long.push(PIECE_LETTERS[piece as usize]);
+        }
+        long.push_str(&square_name(mv.from));
+        long.push(if capture { 'x' } else { '-' });
+        long.push_str(&square_name(mv.to));
+        if mv.promotion != crate::EMPTY {
+            long.push('=');
+            long.push(PIECE_LETTERS[mv.promotion as usize]);
+        }
+        long
+    }
+
+    /// Writes `mv` (which must be legal) in `notation`.
+    pub fn notate(&self, mv: Move, notation: Notation) -> String {
+        match notation {
+            Notation::San(letters) => letters.localize(&self.san(mv)),
+            Notation::Figurine => {
+                let color = self.side_to_move();
+                self.san(mv)
+                    .chars()
+                    .map(
+                        |c| match PIECE_LETTERS.iter().position(|&letter| letter == c) {
+                            Some(piece) if c.is_ascii_uppercase() && c != 'O' => {
+                                CHESS_PIECES[piece | color as usize]
+                            }
+                            _ => c,
+                        },
+                    )
+                    .collect()
+            }
+            Notation::Long(letters) => {
+                let san = self.san(mv);
+                let check = san.trim_start_matches(|c| c != '+' && c != '#');
+                letters.localize(&(self.long_algebraic(mv) + check))
+            }
+            Notation::Coordinate => mv.to_string(),
+        }
+    }
+
+    /// Writes a line of moves played one after the other from this position in `notation`.
+    pub fn line_notation(&self, line: &[Move], notation: Notation) -> Vec<String> {
+        let mut board = *self;
+        line.iter()
+            .map(|&mv| {
+                let text = board.notate(mv, notation);
+                board.apply_move(mv);
+                text
+            })
+            .collect()
+    }
+
+    /// Returns the SAN of every legal move for the side to move.
+    pub fn legal_sans(&self) -> Vec<String> {
+        self.legal_moves()
+            .into_iter()
+            .map(|mv| self.san(mv))
+            .collect()
+    }
+
+    /// Writes a line of moves played one after the other from this position in SAN.
+    pub fn line_sans(&self, line: &[Move]) -> Vec<String> {
+        self.line_notation(line, Notation::default())
+    }
+
+    /// Finds the legal move written as `san`. Check markers and the `=` of promotions are
+    /// optional, and castling may be written with zeros. The move may also be written in any
+    /// other `Notation`.
+    pub fn parse_san(&self, san: &str) -> Result<Move, MoveRejection> {
+        let wanted = normalize(san);
+        let matches = |board: &ChessBoard, mv: Move| {
+            normalize(&board.san(mv)) == wanted
+                || normalize(&board.long_algebraic(mv)) == wanted
+                || mv.to_string() == wanted
+        };
+        if let Some(mv) = self.legal_moves().into_iter().find(|&mv| matches(self, mv)) {
+            return Ok(mv);
+        }
+        if self.legal_moves().is_empty() {
+            return Err(MoveRejection::GameOver);
+        }
+
+        // The move would be fine if it weren't for the king (or the variant), so let the checker
+        // say why.
+        if let Some(mv) = self
+            .pseudo_legal_moves()
+            .into_iter()
+            .chain(self.piece_moves())
+            .find(|&mv| matches(self, mv))
+        {
+            return self.check_move(mv).map(|_| mv);
+        }
+
+        let mut other_side = *self;
+        other_side.moves += 1;
+        other_side.en_passant = crate::movegen::NO_SQUARE;
+        if other_side
+            .legal_moves()
+            .into_iter()
+            .any(|mv| matches(&other_side, mv))
+        {
+            return Err(MoveRejection::NotYourTurn(self.side_to_move()));
+        }
+
+        if wanted.starts_with("O-O") {
+            return Err(MoveRejection::CannotCastle);
+        }
+        if let Some((piece, to)) = wanted.split_once('@') {
+            let piece = match piece {
+                "P" => PAWN,
+                "N" => KNIGHT,
+                "B" => BISHOP,
+                "R" => ROOK,
+                "Q" => QUEEN,
+                _ => return Err(MoveRejection::Unreadable),
+            };
+            if !ChessBoard::is_valid_piece(to.as_bytes()) {
+                return Err(MoveRejection::Unreadable);
+            }
+            let to = ChessBoard::square_of(to.as_bytes());
+            return Err(MoveRejection::CannotDrop { piece, to });
+        }
+        // Work out which piece was meant to go where, to name them in the explanation.
+        let letters = wanted.trim_end_matches(['Q', 'R', 'B', 'N']).as_bytes();
+        if letters.len() < 2 {
+            return Err(MoveRejection::Unreadable);
+        }
+        let to = &letters[letters.len() - 2..];
+        if !ChessBoard::is_valid_piece(to) {
+            return Err(MoveRejection::Unreadable);
+        }
+        let piece = match letters[0] {
+            b'N' => KNIGHT,
+            b'B' => BISHOP,
+            b'R' => ROOK,
+            b'Q' => QUEEN,
+            b'K' => KING,
+            b'a'..=b'h' => PAWN,
+            _ => return Err(MoveRejection::Unreadable),
+        };
+        let promotes = wanted.len() > letters.len();
+        let last_rank = if self.side_to_move() == crate::WHITE {
+            b'0' + self.variant.dimensions().1
+        } else {
+            b'1'
+        };
+        if piece == PAWN && to[1] == last_rank && !promotes {
+            return Err(MoveRejection::MissingPromotion);
+        }
+        if promotes && (piece != PAWN || to[1] != last_rank) {
+            return Err(MoveRejection::NotAPromotion);
+        }
+        Err(MoveRejection::CannotReach {
+            piece,
+            to: ChessBoard::square_of(to),
+        })
+    }
+}
+
+#[test]
+fn test_san() {
+    let mut board = ChessBoard::new();
+    let sans = board.legal_sans();
+    assert_eq!(sans.len(), 20);
+    assert!(sans.contains(&"Nf3".to_string()));
+    assert!(sans.contains(&"e4".to_string()));
+
+    for san in [
+        "e4", "d5", "exd5", "Nf6", "Nc3", "c6", "dxc6", "Qd6", "Nb5", "Qe5+",
+    ] {
+        let mv = board.parse_san(san).expect(san);
+        assert_eq!(board.san(mv), san);
+        board.apply_move(mv);
+    }
+    assert!(board.parse_san("Nb5").is_err());
+    assert_eq!(board.parse_san("Ke2"), Err(MoveRejection::StillInCheck));
+    assert_eq!(
+        board.parse_san("Na6"),
+        Err(MoveRejection::NotYourTurn(crate::WHITE))
+    );
+    assert_eq!(
+        board.parse_san("Bh7"),
+        Err(MoveRejection::CannotReach {
+            piece: BISHOP,
+            to: 55
+        })
+    );
+    assert_eq!(board.parse_san("hello"), Err(MoveRejection::Unreadable));
+
+    // Both knights can reach d2.
+    let mut board = ChessBoard::new();
+    for san in ["d4", "a6", "Nf3", "a5"] {
+        board.apply_move(board.parse_san(san).expect(san));
+    }
+    assert!(board.parse_san("Nd2").is_err());
+    assert!(board.legal_sans().contains(&"Nbd2".to_string()));
+    assert!(board.legal_sans().contains(&"Nfd2".to_string()));
+
+    // Castling, en passant and promotion.
+    let mut board = ChessBoard::new();
+    for san in [
+        "e4", "Nf6", "e5", "d5", "exd6", "Bf5", "dxc7", "Nc6", "cxd8=Q+",
+    ] {
+        let mv = board.parse_san(san).expect(san);
+        assert_eq!(board.san(mv), san);
+        board.apply_move(mv);
+    }
+    assert!(board.parse_san("O-O-O").is_err());
+    board.apply_move(board.parse_san("Rxd8").expect("Rxd8"));
+    for san in ["Nf3", "e6", "Bc4", "Be7"] {
+        board.apply_move(board.parse_san(san).expect(san));
+    }
+    assert_eq!(board.parse_san("0-0").ok(), board.parse_san("O-O").ok());
+    assert!(board.parse_san("O-O").is_ok());
+
+    // Every notation writes moves that are read back.
+    let mut board = ChessBoard::from_fen("r3k3/1P6/8/3pP3/8/8/8/R3K2R w KQq d6 0 1").unwrap();
+    for (san, figurine, long, coordinate) in [
+        ("exd6", "exd6", "e5xd6", "e5d6"),
+        ("Kd8", "♚d8", "Ke8-d8", "e8d8"),
+        ("bxa8=Q+", "bxa8=♕+", "b7xa8=Q+", "b7a8q"),
+        ("Kd7", "♚d7", "Kd8-d7", "d8d7"),
+        ("O-O-O", "O-O-O", "O-O-O", "e1c1"),
+    ] {
+        let mv = board.parse_san(san).expect(san);
+        assert_eq!(board.notate(mv, Notation::Figurine), figurine);
+        assert_eq!(board.notate(mv, Notation::by_name("long").unwrap()), long);
+        assert_eq!(board.notate(mv, Notation::Coordinate), coordinate);
+        for text in [figurine, long, coordinate] {
+            assert_eq!(board.parse_san(text), Ok(mv), "{}", text);
+        }
+        board.apply_move(mv);
+    }
+    assert_eq!(Notation::by_name("figurine"), Some(Notation::Figurine));
+    assert_eq!(Notation::by_name("fan"), None);
+
+    assert_eq!(PieceLetters::parse("SLTDK"), PieceLetters::by_locale("de"));
+    assert_eq!(PieceLetters::parse("SLTD"), None);
+    assert_eq!(PieceLetters::parse("SLTDO"), None);
+    assert_eq!(PieceLetters::parse("sltdk"), None);
+    let french = PieceLetters::by_locale("fr").unwrap();
+    assert_eq!(french.localize("Rxe1+"), "Txe1+");
+    assert_eq!(french.localize("Kf1"), "Rf1");
+    assert_eq!(french.to_english("Rf1"), "Kf1");
+    let board = ChessBoard::new();
+    let mv = board.parse_san("Nf3").unwrap();
+    assert_eq!(
+        board.notate(mv, Notation::San(PieceLetters::by_locale("de").unwrap())),
+        "Sf3"
+    );
+}
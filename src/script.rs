@@ -0,0 +1,193 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use serde_json::json;
+
+use crate::annotation::Annotations;
+use crate::eval;
+use crate::movegen::color_name;
+use crate::tree::{Cursor, GameTree};
+use crate::ChessErr::IllegalCommand;
+use crate::{
+    execute_command, print_game_tutorial, render, split_commands, ChessBoard, ChessErr, Outcome,
+    GAME_TUTORIAL,
+};
+
+/// Exit code used when the script itself could not be opened or read.
+const EXIT_IO_ERROR: i32 = 1;
+
+/// Runs a game from `start` non-interactively, reading commands a line at a time from `path` (or
+/// from stdin when `path` is `-`), as the prompt does. Blank lines and lines starting with `#` are skipped.
+///
+/// Each move prints `ok <command>` and a failing command prints `error <line>: <reason>`, so the
+/// output can be diffed or parsed by other programs. Execution stops at the first error.
+/// With `json` set, every command instead prints a single JSON object on its own line.
+/// Returns the process exit code: `0` on success, otherwise the code of the error encountered.
+pub fn run_script(path: &str, json: bool, start: ChessBoard) -> i32 {
+    let reader: Box<dyn BufRead> = if path == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        match File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                report_io_error(json, 0, &format!("Could not open script '{}': {}", path, e));
+                return EXIT_IO_ERROR;
+            }
+        }
+    };
+
+    let mut board = start;
+    let mut last_move = None;
+    // The game so far, so that `goto` can go back and keep the old line as a variation.
+    let mut tree = GameTree::new(board);
+    let mut cursor = Cursor::default();
+    for (number, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                report_io_error(json, number + 1, &format!("Could not read script: {}", e));
+                return EXIT_IO_ERROR;
+            }
+        };
+        let text = line.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        // A line may hold several commands, which run in turn as separate lines would.
+        for command in split_commands(text) {
+            let mut result = execute_command(&mut board, &command);
+            match &result {
+                Ok(Outcome::Moved(mv)) => cursor = tree.play(&cursor, *mv).unwrap_or_default(),
+                Ok(Outcome::Loaded(loaded)) => {
+                    tree = loaded.clone();
+                    cursor = tree.end_of_line(&Cursor::default()).unwrap_or_default();
+                    last_move = tree.moves().last().copied();
+                }
+                Ok(Outcome::Goto(moves)) => match tree.find_on_mainline(*moves) {
+                    Some(found) => {
+                        board = tree.board_at(&found).unwrap_or(tree.start);
+                        last_move = tree.node(&found).map(|node| node.mv);
+                        cursor = found;
+                    }
+                    None => result = Err(IllegalCommand("The game never reached that move.")),
+                },
+                Ok(Outcome::Draw) => {
+                    result = Err(IllegalCommand("There is no opponent to offer a draw to."))
+                }
+                _ => {}
+            }
+            if let Ok(Outcome::Export(format, path)) = &result {
+                if let Err(e) =
+                    render::export(&board, last_move, &Annotations::default(), format, path)
+                {
+                    let message = format!("Could not save the board to {}: {}", path, e);
+                    report_io_error(json, number + 1, &message);
+                    return EXIT_IO_ERROR;
+                }
+            }
+            if let Ok(Outcome::Moved(mv)) = result {
+                last_move = Some(mv);
+            }
+            if json {
+                println!("{}", json_result(&board, number + 1, &command, &result));
+            }
+            match result {
+                Ok(Outcome::Quit) => return 0,
+                Ok(_) if json => {}
+                Ok(Outcome::Help) => print_game_tutorial(),
+                Ok(Outcome::Board | Outcome::Peek) => print!("{}", board),
+                Ok(Outcome::Dump) => print!("{}", board.dump()),
+                Ok(
+                    Outcome::Moved(_)
+                    | Outcome::Loaded(_)
+                    | Outcome::Goto(_)
+                    | Outcome::Draw
+                    | Outcome::Export(..)
+                    | Outcome::Theme(_),
+                ) => {
+                    println!("ok {}", command)
+                }
+                Err(e) => {
+                    if !json {
+                        println!("error {}: {}", number + 1, e);
+                    }
+                    return e.exit_code();
+                }
+            }
+        }
+    }
+    0
+}
+
+/// Describes the position a command left, with its legal moves in SAN and, once the game is over,
+/// its result and why.
+fn position(board: &ChessBoard) -> serde_json::Value {
+    let (status, result) = match board.outcome() {
+        Some((result, reason)) => (reason, Some(result)),
+        None => ("ongoing", None),
+    };
+    json!({
+        "fen": board.fen(),
+        "variant": board.variant.name(),
+        "placement": board.placement(),
+        "side_to_move": color_name(board.side_to_move()),
+        "moves": board.moves,
+        "legal_moves": if result.is_some() { Vec::new() } else { board.legal_sans() },
+        "status": status,
+        "result": result,
+    })
+}
+
+/// Builds the JSON object describing the result of one scripted command.
+fn json_result(
+    board: &ChessBoard,
+    line: usize,
+    command: &str,
+    result: &Result<Outcome, ChessErr>,
+) -> serde_json::Value {
+    match result {
+        Ok(outcome) => {
+            let mut value = json!({
+                "ok": true,
+                "line": line,
+                "command": command,
+                "position": position(board),
+            });
+            match outcome {
+                Outcome::Help => value["help"] = json!(GAME_TUTORIAL),
+                Outcome::Dump => {
+                    value["dump"] = json!(board.dump());
+                    value["score"] = json!(eval::evaluate(board));
+                }
+                _ => {}
+            }
+            value
+        }
+        Err(e) => {
+            let mut value = json!({
+                "ok": false,
+                "line": line,
+                "command": command,
+                "error": { "kind": e.kind(), "message": e.to_string() },
+            });
+            if let ChessErr::BadMove(reason) = e {
+                value["error"]["reason"] = json!(reason.kind());
+            }
+            value
+        }
+    }
+}
+
+fn report_io_error(json: bool, line: usize, message: &str) {
+    if json {
+        let error = json!({
+            "ok": false,
+            "line": line,
+            "error": { "kind": "io", "message": message },
+        });
+        println!("{}", error);
+    } else {
+        println!("error {}: {}", line, message);
+    }
+}
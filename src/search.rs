@@ -0,0 +1,1121 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::eval::{self, evaluate, PIECE_VALUES};
+use crate::movegen::{Move, MoveList};
+use crate::nnue;
+use crate::tt::{Bound, TranspositionTable};
+use crate::{ChessBoard, BLACK, EMPTY, GET_NUM, WHITE};
+
+/// The score of a position where the side to move has been checkmated. Mates found further from
+/// the root score closer to zero, so the search prefers the quickest mate.
+pub const MATE: i32 = 30_000;
+
+/// Checks are only extended this close to the root, so endless checks still end.
+const MAX_EXTENDED_PLY: u32 = 32;
+
+/// How deep a node must be searched for its expected move to be checked for being singular, and
+/// how far below that move's score, in centipawns per ply, every other move must stay.
+const SINGULAR_DEPTH: u32 = 4;
+const SINGULAR_MARGIN: i32 = 10;
+
+/// How deep a node must be searched, and how far from the root it must be, for ProbCut, and how
+/// much shallower its searches of captures are.
+const PROBCUT_DEPTH: u32 = 5;
+const PROBCUT_PLY: u32 = 2;
+const PROBCUT_REDUCTION: u32 = 4;
+
+/// How many plies from the leaves futility pruning and razoring start.
+const FUTILITY_DEPTH: u32 = 2;
+const RAZOR_DEPTH: u32 = 1;
+
+/// The parameters that decide how aggressively the search prunes. Each can be tuned by name (as
+/// a UCI option, say) without rebuilding the engine.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchParams {
+    /// Late move reductions reduce the depth of the `n`th quiet move at depth `d` by
+    /// `lmr_base / 100 + ln(d) * ln(n) / (lmr_divisor / 100)` plies.
+    pub lmr_base: i32,
+    pub lmr_divisor: i32,
+    /// How far below alpha, in centipawns per ply of depth left, the static evaluation must be
+    /// for quiet moves near the leaves to be skipped.
+    pub futility_margin: i32,
+    /// How wide the window around the previous iteration's score is, in centipawns.
+    pub aspiration_delta: i32,
+    /// How far below alpha, in centipawns per ply of depth left, the static evaluation must be
+    /// for a node near the leaves to be razored: searched for captures alone.
+    pub razor_margin: i32,
+    /// How far above beta, in centipawns, a shallow search of a capture must score for ProbCut
+    /// to take the node as won.
+    pub probcut_margin: i32,
+}
+
+pub const DEFAULT_SEARCH_PARAMS: SearchParams = SearchParams {
+    lmr_base: 75,
+    lmr_divisor: 225,
+    futility_margin: 150,
+    aspiration_delta: 50,
+    razor_margin: 300,
+    probcut_margin: 200,
+};
+
+/// The lowest and highest value of each search parameter, in the order of `SearchParams::named`.
+const SEARCH_PARAM_RANGES: [(i32, i32); 6] = [
+    (0, 300),
+    (100, 600),
+    (0, 1000),
+    (5, 500),
+    (0, 1000),
+    (0, 1000),
+];
+
+impl Default for SearchParams {
+    fn default() -> SearchParams {
+        DEFAULT_SEARCH_PARAMS
+    }
+}
+
+impl SearchParams {
+    /// Every parameter with its name.
+    fn fields(&mut self) -> [(&'static str, &mut i32); 6] {
+        [
+            ("LmrBase", &mut self.lmr_base),
+            ("LmrDivisor", &mut self.lmr_divisor),
+            ("FutilityMargin", &mut self.futility_margin),
+            ("AspirationDelta", &mut self.aspiration_delta),
+            ("RazorMargin", &mut self.razor_margin),
+            ("ProbCutMargin", &mut self.probcut_margin),
+        ]
+    }
+
+    /// The name and value of every parameter.
+    pub fn named(mut self) -> Vec<(&'static str, i32)> {
+        self.fields()
+            .into_iter()
+            .map(|(name, value)| (name, *value))
+            .collect()
+    }
+
+    /// The lowest and highest value of the parameter called `name` (in any case).
+    pub fn range(name: &str) -> Option<(i32, i32)> {
+        let index = DEFAULT_SEARCH_PARAMS
+            .named()
+            .iter()
+            .position(|(field, _)| field.eq_ignore_ascii_case(name))?;
+        Some(SEARCH_PARAM_RANGES[index])
+    }
+
+    /// Sets the parameter called `name` (in any case), returning `false` if there is none or
+    /// `value` is out of its range.
+    pub fn set(&mut self, name: &str, value: i32) -> bool {
+        match SearchParams::range(name) {
+            Some((low, high)) if (low..=high).contains(&value) => {}
+            _ => return false,
+        }
+        match self
+            .fields()
+            .into_iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+        {
+            Some((_, field)) => {
+                *field = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// How many plies to reduce the `index`th move (counting from 0) by at `depth`.
+    fn reduction(&self, depth: u32, index: usize) -> u32 {
+        let reduction = self.lmr_base as f64 / 100.0
+            + (depth as f64).ln() * (index as f64).ln() / (self.lmr_divisor as f64 / 100.0);
+        reduction as u32
+    }
+}
+
+thread_local! {
+    /// The parameters searches on this thread use.
+    static PARAMS: Cell<SearchParams> = const { Cell::new(DEFAULT_SEARCH_PARAMS) };
+    /// The contempt searches on this thread use.
+    static CONTEMPT: Cell<i32> = const { Cell::new(0) };
+}
+
+/// The parameters searches on this thread use.
+pub fn params() -> SearchParams {
+    PARAMS.with(Cell::get)
+}
+
+/// Makes searches on this thread use `params`.
+pub fn set_params(params: SearchParams) {
+    PARAMS.with(|current| current.set(params));
+}
+
+/// The deepest a search goes, as when it is only limited by positions.
+pub const MAX_DEPTH: u32 = 64;
+
+/// How far the contempt may go either way, in centipawns.
+pub const CONTEMPT_LIMIT: i32 = 1000;
+
+/// How much worse than even searches on this thread take a draw to be for the side to move at
+/// the root, in centipawns. Above zero they avoid draws, as against a weaker opponent, and below
+/// zero they seek them.
+pub fn contempt() -> i32 {
+    CONTEMPT.with(Cell::get)
+}
+
+/// Makes searches on this thread use `contempt`.
+pub fn set_contempt(contempt: i32) {
+    CONTEMPT.with(|current| current.set(contempt));
+}
+
+/// The score of a draw `ply` plies from the root, for the side to move there: the contempt less
+/// than nothing for the side to move at the root, and as much more for the other.
+fn draw_score(ply: u32) -> i32 {
+    match ply.is_multiple_of(2) {
+        true => -contempt(),
+        false => contempt(),
+    }
+}
+
+/// How big the hash table of a search that isn't given one is, in megabytes: small, since it is
+/// made afresh for every search.
+pub const SEARCH_HASH_MB: usize = 1;
+
+/// How far the history scores go either way.
+const HISTORY_MAX: i32 = 16_384;
+
+/// What a search keeps track of as it goes: how many positions it has visited, which it stops
+/// at once there have been `limit` or it is past its `deadline`, and what it has learned about
+/// quiet moves, to try the ones likely to cause a cutoff first, keeping what it finds about
+/// positions in `table`.
+struct Search<'a> {
+    count: u64,
+    limit: u64,
+    deadline: Option<Instant>,
+    /// Set by another thread when this search, a helper, should stop.
+    stop: Option<&'a AtomicBool>,
+    /// Whether the search has run out of positions or time, after which its scores mean nothing.
+    stopped: bool,
+    table: &'a TranspositionTable,
+    /// How well each quiet move, by side and its two squares, has done at causing cutoffs.
+    history: Vec<i32>,
+    /// The quiet move that last refuted each move, by the piece moved and where it went.
+    countermoves: Vec<Option<Move>>,
+    /// How well each quiet move has done after each move one or two plies before it, both by
+    /// the piece moved and where it went.
+    continuation: Vec<i32>,
+    /// The piece moved and where it went at each ply of the line being searched.
+    played: Vec<usize>,
+    /// The keys of the positions of the game before the root, oldest first, and then of each
+    /// position on the line being searched.
+    keys: Vec<u64>,
+    /// How many of `keys` come from the game before the root.
+    root: usize,
+}
+
+/// Adds `bonus` to a history score, less the more the score already leans that way, so scores
+/// stay within `HISTORY_MAX` and recent results count most.
+fn nudge(score: &mut i32, bonus: i32) {
+    *score += bonus - *score * bonus.abs() / HISTORY_MAX;
+}
+
+impl Search<'_> {
+    fn new(limit: u64, table: &TranspositionTable) -> Search<'_> {
+        Search {
+            count: 0,
+            limit,
+            deadline: None,
+            stop: None,
+            stopped: false,
+            table,
+            history: vec![0; 2 * 64 * 64],
+            countermoves: vec![None; 16 * 64],
+            continuation: vec![0; 16 * 64 * 16 * 64],
+            played: Vec::new(),
+            keys: Vec::new(),
+            root: 0,
+        }
+    }
+
+    /// Notes that the position at `ply` has `key`, and returns whether the same position came up
+    /// before it, in the game or on the line being searched, since the last of the `halfmoves`
+    /// that can be undone.
+    fn repeats(&mut self, ply: u32, key: u64, halfmoves: u16) -> bool {
+        let index = self.root + ply as usize;
+        // A search started below the root, as in the tests, didn't see the positions above.
+        self.keys.resize(index, 0);
+        self.keys.push(key);
+        // The same side is to move every other ply.
+        self.keys[..index]
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .take(halfmoves as usize / 2)
+            .any(|&earlier| earlier == key)
+    }
+
+    /// Counts one more position, or stops the search and returns `false` if it has already
+    /// visited `limit`, is out of time or has been told to stop. The clock and the flag are only
+    /// looked at every so often, and never at the root, so there is always a move.
+    fn visit(&mut self) -> bool {
+        let late = || {
+            self.deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+                || self.stop.is_some_and(|stop| stop.load(Ordering::Relaxed))
+        };
+        if self.count >= self.limit || (self.count % 1024 == 1023 && late()) {
+            self.stopped = true;
+            return false;
+        }
+        self.count += 1;
+        true
+    }
+
+    /// Stores what was found about a position in the table, unless the search has stopped.
+    fn store(
+        &mut self,
+        key: u64,
+        ply: u32,
+        depth: u32,
+        score: i32,
+        bound: Bound,
+        best_move: Option<Move>,
+    ) {
+        if !self.stopped {
+            self.table.store(key, ply, depth, score, bound, best_move);
+        }
+    }
+
+    /// Notes that the move at `ply` moved a piece as `piece_to` says.
+    fn play(&mut self, ply: u32, piece_to: usize) {
+        self.played
+            .resize(self.played.len().max(ply as usize + 1), 0);
+        self.played[ply as usize] = piece_to;
+    }
+
+    /// The moves one and two plies before `ply`, where there are any.
+    fn previous(&self, ply: u32) -> [Option<usize>; 2] {
+        let back = |plies: u32| ply.checked_sub(plies).map(|ply| self.played[ply as usize]);
+        [back(1), back(2)]
+    }
+
+    /// How promising the quiet move `mv` of `board` at `ply` is, by its history.
+    fn quiet_score(&self, board: &ChessBoard, mv: Move, ply: u32) -> i32 {
+        let side = (board.side_to_move() == BLACK) as usize;
+        let piece_to = board.piece_to(mv);
+        self.history[side * 4096 + mv.from as usize * 64 + mv.to as usize]
+            + self
+                .previous(ply)
+                .iter()
+                .flatten()
+                .map(|&previous| self.continuation[previous * 1024 + piece_to])
+                .sum::<i32>()
+    }
+
+    /// Rewards the quiet move `best` of `board` for causing a cutoff at `ply` with `depth` left,
+    /// and marks down the quiet moves `tried` before it.
+    fn reward(&mut self, board: &ChessBoard, best: Move, tried: &[Move], depth: u32, ply: u32) {
+        let bonus = (depth * depth).min(400) as i32 * 4;
+        let side = (board.side_to_move() == BLACK) as usize;
+        let previous = self.previous(ply);
+        for (&mv, bonus) in tried.iter().map(|mv| (mv, -bonus)).chain([(&best, bonus)]) {
+            nudge(
+                &mut self.history[side * 4096 + mv.from as usize * 64 + mv.to as usize],
+                bonus,
+            );
+            let piece_to = board.piece_to(mv);
+            for &previous in previous.iter().flatten() {
+                nudge(&mut self.continuation[previous * 1024 + piece_to], bonus);
+            }
+        }
+        if let Some(previous) = previous[0] {
+            self.countermoves[previous] = Some(best);
+        }
+    }
+}
+
+/// What a search found.
+#[derive(Debug)]
+pub struct SearchResult {
+    /// The best move found, or `None` if the side to move has no legal moves.
+    pub best_move: Option<Move>,
+    /// The score of the position in centipawns, from the point of view of the side to move.
+    pub score: i32,
+    /// The line the search expects to be played, starting with `best_move`.
+    pub pv: Vec<Move>,
+    /// The number of positions visited.
+    pub nodes: u64,
+    /// The depth of the last completed iteration, in plies.
+    pub depth: u32,
+}
+
+impl ChessBoard {
+    /// Orders moves so that the most promising come first: the previous best move, then
+    /// captures of valuable pieces by cheap ones (MVV-LVA), then promotions.
+    fn order_moves(&self, moves: &mut [Move], best: Option<Move>) {
+        moves.sort_by_cached_key(|&mv| match Some(mv) == best {
+            true => i32::MIN,
+            false => self.capture_key(mv),
+        });
+    }
+
+    /// Orders moves as `order_moves` does, and then the quiet ones: first the move that last
+    /// refuted the previous one (the countermove), then the rest by how well they have done in
+    /// `search` so far.
+    fn order_by_history(&self, moves: &mut [Move], best: Option<Move>, search: &Search, ply: u32) {
+        let countermove =
+            search.previous(ply)[0].and_then(|previous| search.countermoves[previous]);
+        moves.sort_by_cached_key(|&mv| {
+            if Some(mv) == best {
+                i32::MIN
+            } else if self.is_capture(mv) || mv.promotion != EMPTY {
+                i32::MIN / 2 + self.capture_key(mv)
+            } else if Some(mv) == countermove {
+                i32::MIN / 4
+            } else {
+                -search.quiet_score(self, mv, ply)
+            }
+        });
+    }
+
+    /// Sorts captures of valuable pieces by cheap ones (MVV-LVA) and promotions first.
+    fn capture_key(&self, mv: Move) -> i32 {
+        let mut key = 0;
+        if self.is_capture(mv) {
+            let victim = PIECE_VALUES[GET_NUM(self.get_piece(mv.to)) as usize].max(100);
+            let attacker = PIECE_VALUES[GET_NUM(self.get_piece(mv.from)) as usize];
+            key -= 10 * victim - attacker / 10;
+        }
+        key - PIECE_VALUES[mv.promotion as usize]
+    }
+
+    /// The piece `mv` moves (with its color) and the square it goes to, as one number below
+    /// 1024 for the history tables.
+    fn piece_to(&self, mv: Move) -> usize {
+        let piece = match mv.is_drop() {
+            true => mv.promotion | self.side_to_move(),
+            false => self.get_piece(mv.from),
+        };
+        (piece as usize & 15) * 64 + mv.to as usize
+    }
+
+    /// Scores the position `ply` plies from the root if the game is over by the rules of its
+    /// variant, given the legal `moves`: a win is worth a mate, and a draw `draw_score`.
+    fn terminal_score(&self, moves: &[Move], ply: u32) -> Option<i32> {
+        let (result, _) = self.variant.outcome(self, moves)?;
+        let winner = match result {
+            "1-0" => WHITE,
+            "0-1" => BLACK,
+            _ => return Some(draw_score(ply)),
+        };
+        Some(if winner == self.side_to_move() {
+            MATE - ply as i32
+        } else {
+            -MATE + ply as i32
+        })
+    }
+
+    /// Searches the position to `depth` plies with iterative deepening, as if the game had
+    /// started from it.
+    pub fn search(&self, depth: u32) -> SearchResult {
+        self.search_nodes(depth, u64::MAX)
+    }
+
+    /// Searches the position with iterative deepening, to `depth` plies or until it has visited
+    /// `limit` positions, never more. An iteration that runs out of positions is thrown away,
+    /// unless it is the first, whose best move so far is still better than none. Searches with the
+    /// same limit and parameters always find the same move, on any machine.
+    pub fn search_nodes(&self, depth: u32, limit: u64) -> SearchResult {
+        self.search_with(depth, limit, &TranspositionTable::new(SEARCH_HASH_MB))
+    }
+
+    /// Searches with iterative deepening for `time`, or until a mate is found, throwing away the
+    /// iteration that runs out of time as `search_nodes` does one that runs out of positions.
+    pub fn search_for(&self, time: Duration) -> SearchResult {
+        self.search_within(MAX_DEPTH, u64::MAX, time)
+    }
+
+    /// Searches like `search_nodes`, but for at most `time` too, as an engine on the clock does.
+    pub fn search_within(&self, depth: u32, limit: u64, time: Duration) -> SearchResult {
+        let table = TranspositionTable::new(SEARCH_HASH_MB);
+        self.search_until(depth, limit, Some(Instant::now() + time), None, &table, &[])
+    }
+
+    /// Searches like `search_nodes`, starting from what `table` knows and adding to it what it
+    /// finds, so a later search can reuse it.
+    pub fn search_with(&self, depth: u32, limit: u64, table: &TranspositionTable) -> SearchResult {
+        self.search_until(depth, limit, None, None, table, &[])
+    }
+
+    /// Searches like `search_with` on `threads` threads sharing `table` (Lazy SMP), for at most
+    /// `time` if it is given. The others help by searching the same position with no limit,
+    /// filling the table with what this thread will look for, until this thread finishes. With
+    /// more than one thread the result isn't the same from one search to the next, and its
+    /// `nodes` counts every thread's. A search limited to `limit` positions runs on this thread
+    /// alone, so it keeps to the limit and finds the same every time. `history` has the keys of
+    /// the positions of the game before this one, oldest first, so that repeating them is
+    /// scored as a draw.
+    pub fn search_threads(
+        &self,
+        depth: u32,
+        limit: u64,
+        time: Option<Duration>,
+        threads: usize,
+        table: &TranspositionTable,
+        history: &[u64],
+    ) -> SearchResult {
+        let deadline = time.map(|time| Instant::now() + time);
+        let threads = match limit {
+            u64::MAX => threads,
+            _ => 1,
+        };
+        let _span = tracing::info_span!(target: "search", "search", depth, threads).entered();
+        if threads > 1 {
+            tracing::debug!(target: "search", "searching on {} threads", threads);
+        }
+        let stop = AtomicBool::new(false);
+        // Helpers search with this thread's settings, which are kept by thread.
+        let (search_params, contempt, eval_params) = (params(), contempt(), eval::params());
+        let network = nnue::network();
+        std::thread::scope(|scope| {
+            let helpers = (1..threads)
+                .map(|_| {
+                    let network = network.clone();
+                    scope.spawn(|| {
+                        set_params(search_params);
+                        set_contempt(contempt);
+                        eval::set_params(eval_params);
+                        nnue::set_network(network);
+                        self.search_until(depth, u64::MAX, None, Some(&stop), table, history)
+                            .nodes
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut result = self.search_until(depth, limit, deadline, None, table, history);
+            stop.store(true, Ordering::Relaxed);
+            for helper in helpers {
+                result.nodes += helper.join().unwrap();
+            }
+            result
+        })
+    }
+
+    /// Searches like `search_with`, stopping at `deadline` if there is one or once `stop` is
+    /// set, after the game's positions `history`.
+    fn search_until(
+        &self,
+        depth: u32,
+        limit: u64,
+        deadline: Option<Instant>,
+        stop: Option<&AtomicBool>,
+        table: &TranspositionTable,
+        history: &[u64],
+    ) -> SearchResult {
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            pv: Vec::new(),
+            nodes: 0,
+            depth: 0,
+        };
+        if let Some(score) = self.terminal_score(&self.legal_moves(), 0) {
+            result.score = score;
+            return result;
+        }
+
+        let _root = nnue::begin(self);
+        let delta = params().aspiration_delta;
+        // The root counts as a position too, so a limit of none still gets a move.
+        let mut search = Search::new(limit.max(1), table);
+        search.deadline = deadline;
+        search.stop = stop;
+        search.keys = history.to_vec();
+        search.root = history.len();
+        for iteration in 1..=depth.max(1) {
+            // Expect the score to stay close to the last one, which lets the search cut off more,
+            // and only look wider if it doesn't.
+            let (mut alpha, mut beta) = match iteration {
+                1 => (-MATE - 1, MATE + 1),
+                _ if result.score.abs() > MATE - 1000 => (-MATE - 1, MATE + 1),
+                _ => (result.score - delta, result.score + delta),
+            };
+            let mut pv = Vec::new();
+            let score = loop {
+                pv.clear();
+                let score =
+                    self.negamax(iteration, 0, alpha, beta, &result.pv, &mut pv, &mut search);
+                if score <= alpha && alpha > -MATE - 1 {
+                    alpha = -MATE - 1;
+                } else if score >= beta && beta < MATE + 1 {
+                    beta = MATE + 1;
+                } else {
+                    break score;
+                }
+            };
+            result.nodes = search.count;
+            if search.stopped {
+                if result.best_move.is_none() {
+                    result.score = score.max(-MATE);
+                    result.best_move = pv.first().copied();
+                    result.pv = pv;
+                }
+                break;
+            }
+            result.score = score;
+            result.best_move = pv.first().copied();
+            result.pv = pv;
+            result.depth = iteration;
+            tracing::debug!(
+                target: "search",
+                "depth {} score {} nodes {} pv {}",
+                iteration,
+                score,
+                search.count,
+                result.pv.iter().map(Move::to_string).collect::<Vec<_>>().join(" ")
+            );
+            // Nothing deeper can beat a forced mate.
+            if score.abs() > MATE - 1000 {
+                break;
+            }
+        }
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &self,
+        depth: u32,
+        ply: u32,
+        mut alpha: i32,
+        beta: i32,
+        previous_pv: &[Move],
+        pv: &mut Vec<Move>,
+        search: &mut Search,
+    ) -> i32 {
+        if !search.visit() {
+            return 0;
+        }
+        // Above the leaves the position's table entry is needed, so it is fetched from memory
+        // while the moves are generated.
+        let key = self.zobrist();
+        if depth > 0 {
+            search.table.prefetch(key);
+        }
+        let mut moves = self.legal_moves();
+        if let Some(score) = self.terminal_score(&moves, ply) {
+            return score;
+        }
+        // A repetition or fifty moves without a capture or pawn move can be claimed as a draw.
+        let repeats = search.repeats(ply, key, self.halfmoves);
+        if ply > 0 && (repeats || self.halfmoves >= 100) {
+            return draw_score(ply);
+        }
+        if depth == 0 {
+            return self.quiesce_visited(alpha, beta, moves, search);
+        }
+        // A position already searched deep enough needn't be searched again, if its score is
+        // enough to settle this one. The root is always searched, so there is a move to play.
+        let entry = search.table.probe(key);
+        if let Some(entry) = entry.filter(|entry| ply > 0 && entry.depth >= depth) {
+            let score = entry.score(ply);
+            match entry.bound {
+                Bound::Exact => {
+                    pv.extend(self.table_line(search.table, depth));
+                    return score.clamp(alpha, beta);
+                }
+                Bound::Lower if score >= beta => return beta,
+                Bound::Upper if score <= alpha => return alpha,
+                _ => {}
+            }
+        }
+        let original_alpha = alpha;
+        let params = params();
+        let in_check = self.is_in_check(self.side_to_move());
+        let static_eval = match in_check || alpha.abs() >= MATE - 1000 {
+            true => None,
+            false => Some(evaluate(self)),
+        };
+        // So far below alpha this close to the leaves, only winning material could help, so see
+        // if captures alone get back above it.
+        if let Some(eval) = static_eval.filter(|_| ply > 0 && depth <= RAZOR_DEPTH) {
+            if eval + params.razor_margin * depth as i32 <= alpha {
+                let score = self.quiesce_visited(alpha, beta, moves, search);
+                if score <= alpha {
+                    return score;
+                }
+            }
+        }
+        if !in_check {
+            if let Some(score) = self.probcut(depth, ply, beta, &moves, search) {
+                return score;
+            }
+        }
+        // So close to the leaves, a quiet move won't make up for being this far below alpha.
+        let futile = depth <= FUTILITY_DEPTH
+            && static_eval
+                .is_some_and(|eval| eval + params.futility_margin * depth as i32 <= alpha);
+        // The move the last iteration expected here, or else the best move the table knows of.
+        // Only the first is worth the work of checking whether it is singular.
+        let expected = previous_pv
+            .first()
+            .copied()
+            .or_else(|| entry.and_then(|entry| entry.best_move))
+            .filter(|mv| moves.contains(mv));
+        let singular = match previous_pv.first().copied() {
+            Some(mv) if ply > 0 && depth >= SINGULAR_DEPTH && static_eval.is_some() => {
+                self.is_singular(mv, &moves, depth, ply, search)
+            }
+            _ => false,
+        };
+        self.order_by_history(&mut moves, expected, search, ply);
+        let first = moves[0];
+        let mut tried = Vec::new();
+
+        for (index, mv) in moves.into_iter().enumerate() {
+            let quiet = !self.is_capture(mv) && mv.promotion == EMPTY;
+            let mut next = *self;
+            next.apply_move(mv);
+            let gives_check = next.is_in_check(next.side_to_move());
+            let reducible = quiet && !in_check && !gives_check;
+            if futile && reducible {
+                continue;
+            }
+            let _made = nnue::make(self, &next);
+            search.play(ply, self.piece_to(mv));
+            let mut line = Vec::new();
+            let rest = match previous_pv.split_first() {
+                Some((&expected, rest)) if expected == mv => rest,
+                _ => &[],
+            };
+            // Moves this late in the order rarely turn out best, so they get a shallower search
+            // first, and a full one only if they beat alpha after all.
+            let reduction = match reducible && depth >= 3 && index >= 3 {
+                true => params.reduction(depth, index).min(depth - 2),
+                false => 0,
+            };
+            // Forcing moves, and a move that is the only good one, are searched a ply deeper.
+            let extension =
+                match (gives_check && ply < MAX_EXTENDED_PLY) || (singular && index == 0) {
+                    true => 1,
+                    false => 0,
+                };
+            // The extension only deepens the search below the move; what is stored and learned
+            // here is still for this node's own depth.
+            let search_depth = depth + extension;
+            let mut score = alpha + 1;
+            if reduction > 0 {
+                score = -next.negamax(
+                    search_depth - 1 - reduction,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    rest,
+                    &mut line,
+                    search,
+                );
+            }
+            if score > alpha {
+                line.clear();
+                score = -next.negamax(
+                    search_depth - 1,
+                    ply + 1,
+                    -beta,
+                    -alpha,
+                    rest,
+                    &mut line,
+                    search,
+                );
+            }
+            if search.stopped {
+                break;
+            }
+            if score >= beta {
+                if quiet {
+                    search.reward(self, mv, &tried, depth, ply);
+                }
+                search.store(key, ply, depth, beta, Bound::Lower, Some(mv));
+                return beta;
+            }
+            if quiet {
+                tried.push(mv);
+            }
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(mv);
+                pv.extend(line);
+            }
+        }
+        let bound = match alpha > original_alpha {
+            true => Bound::Exact,
+            false => Bound::Upper,
+        };
+        search.store(key, ply, depth, alpha, bound, pv.first().copied());
+        // Every move failed low; still report one so the caller always has a move to play.
+        if pv.is_empty() {
+            pv.push(first);
+        }
+        alpha
+    }
+
+    /// The line the table expects from the position, following its best moves for up to `plies`
+    /// plies.
+    fn table_line(&self, table: &TranspositionTable, plies: u32) -> Vec<Move> {
+        let mut line = Vec::new();
+        let mut board = *self;
+        while let Some(mv) = table
+            .probe(board.zobrist())
+            .and_then(|entry| entry.best_move)
+        {
+            if line.len() as u32 >= plies || !board.legal_moves().contains(&mv) {
+                break;
+            }
+            board.apply_move(mv);
+            line.push(mv);
+        }
+        line
+    }
+
+    /// ProbCut: if a capture among the legal `moves`, searched `PROBCUT_REDUCTION` plies
+    /// shallower, beats beta by the margin, a full search would very likely beat beta too, so
+    /// the node is cut off with the score returned.
+    fn probcut(
+        &self,
+        depth: u32,
+        ply: u32,
+        beta: i32,
+        moves: &[Move],
+        search: &mut Search,
+    ) -> Option<i32> {
+        if depth < PROBCUT_DEPTH || ply < PROBCUT_PLY || beta.abs() >= MATE - 1000 {
+            return None;
+        }
+        let raised = beta + params().probcut_margin;
+        let mut captures = moves
+            .iter()
+            .copied()
+            .filter(|&mv| self.is_capture(mv) || mv.promotion != EMPTY)
+            .collect::<Vec<Move>>();
+        self.order_moves(&mut captures, None);
+        for mv in captures {
+            let mut next = *self;
+            next.apply_move(mv);
+            let _made = nnue::make(self, &next);
+            search.play(ply, self.piece_to(mv));
+            let score = -next.negamax(
+                depth - PROBCUT_REDUCTION,
+                ply + 1,
+                -raised,
+                -raised + 1,
+                &[],
+                &mut Vec::new(),
+                search,
+            );
+            if score >= raised {
+                return Some(beta);
+            }
+        }
+        None
+    }
+
+    /// Whether `candidate` is singular among the legal `moves`: searched to half of `depth`, every
+    /// other move falls clearly short of it, so the position hinges on it.
+    fn is_singular(
+        &self,
+        candidate: Move,
+        moves: &[Move],
+        depth: u32,
+        ply: u32,
+        search: &mut Search,
+    ) -> bool {
+        if !moves.contains(&candidate) || moves.len() < 2 {
+            return false;
+        }
+        let probe = |mv: Move, alpha: i32, beta: i32, search: &mut Search| {
+            let mut next = *self;
+            next.apply_move(mv);
+            let _made = nnue::make(self, &next);
+            search.play(ply, self.piece_to(mv));
+            -next.negamax(
+                depth / 2,
+                ply + 1,
+                -beta,
+                -alpha,
+                &[],
+                &mut Vec::new(),
+                search,
+            )
+        };
+        let score = probe(candidate, -MATE - 1, MATE + 1, search);
+        if score.abs() >= MATE - 1000 {
+            return false;
+        }
+        let bound = score - SINGULAR_MARGIN * depth as i32;
+        moves
+            .iter()
+            .filter(|&&mv| mv != candidate)
+            .all(|&mv| probe(mv, bound - 1, bound, search) < bound)
+    }
+
+    /// Only searches captures and promotions among the legal `moves`, so the evaluation isn't
+    /// taken in the middle of an exchange.
+    fn quiesce(&self, alpha: i32, beta: i32, moves: MoveList, search: &mut Search) -> i32 {
+        if !search.visit() {
+            return 0;
+        }
+        self.quiesce_visited(alpha, beta, moves, search)
+    }
+
+    /// Searches as `quiesce` does, a position `negamax` has already counted as visited.
+    fn quiesce_visited(
+        &self,
+        mut alpha: i32,
+        beta: i32,
+        mut moves: MoveList,
+        search: &mut Search,
+    ) -> i32 {
+        let stand_pat = evaluate(self);
+        if stand_pat >= beta {
+            return beta;
+        }
+        alpha = alpha.max(stand_pat);
+
+        moves.retain(|&mv| self.is_capture(mv) || mv.promotion != EMPTY);
+        self.order_moves(&mut moves, None);
+        for mv in moves {
+            let mut next = *self;
+            next.apply_move(mv);
+            let _made = nnue::make(self, &next);
+            let score = -next.quiesce(-beta, -alpha, next.legal_moves(), search);
+            if score >= beta {
+                return beta;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+}
+
+#[test]
+fn test_search() {
+    // Scholar's mate is one move away.
+    let board =
+        ChessBoard::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+            .unwrap();
+    let result = board.search(3);
+    assert_eq!(board.san(result.best_move.unwrap()), "Qxf7#");
+    assert_eq!(result.score, MATE - 1);
+
+    // A hanging queen gets taken.
+    let board = ChessBoard::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+    let result = board.search(2);
+    assert_eq!(board.san(result.best_move.unwrap()), "Rxd5");
+    assert_eq!(result.pv.len(), 2);
+
+    // No moves, no best move.
+    let board = ChessBoard::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    let result = board.search(3);
+    assert_eq!(result.best_move, None);
+    assert_eq!(result.score, 0);
+}
+
+#[test]
+fn test_node_limit() {
+    // However few positions it may visit, the search never goes over them, and finds a move.
+    let board = ChessBoard::new();
+    for limit in [0, 1, 30, 5000] {
+        let result = board.search_nodes(MAX_DEPTH, limit);
+        assert!(result.nodes <= limit.max(1));
+        assert!(result.best_move.is_some());
+        // The same limit always finds the same.
+        let again = board.search_nodes(MAX_DEPTH, limit);
+        assert_eq!(
+            (again.best_move, again.score),
+            (result.best_move, result.score)
+        );
+        assert_eq!((again.pv, again.nodes), (result.pv, result.nodes));
+    }
+    assert_eq!(board.search_nodes(MAX_DEPTH, 5000).nodes, 5000);
+    assert_eq!(board.search_nodes(3, 5000).depth, 3);
+
+    // Helper threads don't add to a limited search.
+    let alone = board.search_with(MAX_DEPTH, 1000, &TranspositionTable::new(1));
+    let threaded = board.search_threads(MAX_DEPTH, 1000, None, 4, &TranspositionTable::new(1), &[]);
+    assert_eq!(threaded.nodes, 1000);
+    assert_eq!(
+        (threaded.best_move, threaded.pv),
+        (alone.best_move, alone.pv)
+    );
+
+    // Each position counts once: the root and the king's three moves, which have no captures
+    // for the quiescence search to look at.
+    let kings = ChessBoard::from_fen("7k/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+    assert_eq!(kings.search(1).nodes, 4);
+}
+
+#[test]
+fn test_time_limit() {
+    // A search with no time to speak of still finds a move, and stops soon after its time is up.
+    let board = ChessBoard::new();
+    let result = board.search_for(Duration::ZERO);
+    assert!(result.best_move.is_some());
+    assert!(result.nodes <= 1024);
+    let started = Instant::now();
+    let result = board.search_for(Duration::from_millis(200));
+    assert!(result.depth >= 1);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn test_pruning() {
+    // Near the leaves, futility pruning and razoring skip moves that can't make up for being
+    // well behind, which the many ways to hang a piece here lead to.
+    let board =
+        ChessBoard::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4")
+            .unwrap();
+    let pruned = board.search(4);
+    set_params(SearchParams {
+        futility_margin: 1000,
+        razor_margin: 1000,
+        ..DEFAULT_SEARCH_PARAMS
+    });
+    let unpruned = board.search(4);
+    set_params(DEFAULT_SEARCH_PARAMS);
+    assert!(pruned.nodes < unpruned.nodes);
+}
+
+#[test]
+fn test_extensions() {
+    // Qxh7+ Kxh7 Rh3# is a ply deeper than two, but the checks are searched deeper. (Razoring,
+    // switched off here, would see only the queen given up a ply from the leaves.)
+    set_params(SearchParams {
+        razor_margin: MATE,
+        ..DEFAULT_SEARCH_PARAMS
+    });
+    let board = ChessBoard::from_fen("2r2r1k/1p2Nppp/p7/7Q/8/3R4/PPP2PPP/2K4R w - - 0 1").unwrap();
+    let result = board.search(2);
+    set_params(DEFAULT_SEARCH_PARAMS);
+    assert_eq!(board.san(result.best_move.unwrap()), "Qxh7+");
+    assert_eq!(result.score, MATE - 3);
+
+    // Only taking the queen back keeps the material even, so it is singular; in the opening,
+    // plenty of moves are about as good as 1. e4.
+    let board = ChessBoard::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+    let table = TranspositionTable::new(1);
+    let mut search = Search::new(u64::MAX, &table);
+    let capture = board.parse_san("Rxd5").unwrap();
+    assert!(board.is_singular(capture, &board.legal_moves(), 4, 1, &mut search));
+    let board = ChessBoard::new();
+    let e4 = board.parse_san("e4").unwrap();
+    assert!(!board.is_singular(e4, &board.legal_moves(), 4, 1, &mut search));
+}
+
+#[test]
+fn test_probcut() {
+    let table = TranspositionTable::new(1);
+    let mut search = Search::new(u64::MAX, &table);
+    // Taking the hanging queen is far above a beta of zero, even searched shallowly, but not near
+    // the root or when the node isn't searched deep enough.
+    let board = ChessBoard::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+    let moves = board.legal_moves();
+    assert_eq!(board.probcut(5, 2, 0, &moves, &mut search), Some(0));
+    assert_eq!(board.probcut(5, 1, 0, &moves, &mut search), None);
+    assert_eq!(board.probcut(4, 2, 0, &moves, &mut search), None);
+    // Nor when there is no capture that wins enough.
+    assert_eq!(board.probcut(5, 2, 900, &moves, &mut search), None);
+    let board = ChessBoard::new();
+    assert_eq!(
+        board.probcut(5, 2, 0, &board.legal_moves(), &mut search),
+        None
+    );
+}
+
+#[test]
+fn test_history() {
+    let start = ChessBoard::new();
+    let e4 = start.parse_san("e4").unwrap();
+    let mut board = start;
+    board.apply_move(e4);
+    let knight = board.parse_san("Nc6").unwrap();
+    let rook_pawn = board.parse_san("a6").unwrap();
+
+    // Nc6 refuted 1. e4 after a6 failed to, so it becomes the countermove to 1. e4 and gains
+    // history, while a6 loses some.
+    let table = TranspositionTable::new(1);
+    let mut search = Search::new(u64::MAX, &table);
+    search.play(0, start.piece_to(e4));
+    search.reward(&board, knight, &[rook_pawn], 4, 1);
+    assert!(search.quiet_score(&board, knight, 1) > 0);
+    assert!(search.quiet_score(&board, rook_pawn, 1) < 0);
+    let mut moves = board.legal_moves();
+    board.order_by_history(&mut moves, None, &search, 1);
+    assert_eq!(moves[0], knight);
+    assert_eq!(moves.last(), Some(&rook_pawn));
+
+    // After 1. d4 it isn't the countermove, but its history still puts it first.
+    let d4 = start.parse_san("d4").unwrap();
+    let mut board = start;
+    board.apply_move(d4);
+    search.play(0, start.piece_to(d4));
+    assert_eq!(search.countermoves[start.piece_to(d4)], None);
+    let mut moves = board.legal_moves();
+    board.order_by_history(&mut moves, None, &search, 1);
+    assert_eq!(moves[0], knight);
+
+    // Scores stay within bounds however often a move does well.
+    for _ in 0..1000 {
+        search.reward(&board, knight, &[], 20, 1);
+    }
+    assert!(search.quiet_score(&board, knight, 1) <= 2 * HISTORY_MAX);
+}
+
+#[test]
+fn test_contempt() {
+    // Taking the queen stalemates black, which saves a lost game, unless a draw is taken to be
+    // worse than losing the queen.
+    let board = ChessBoard::from_fen("k7/p1K5/P7/R6q/8/8/8/8 w - - 0 1").unwrap();
+    let capture = board.parse_san("Rxh5").unwrap();
+    assert_eq!(board.search(2).best_move, Some(capture));
+    set_contempt(-50);
+    assert_eq!(board.search(2).score, 50);
+    set_contempt(CONTEMPT_LIMIT);
+    assert_ne!(board.search(2).best_move, Some(capture));
+
+    // Black, to move at the root, takes a stalemate to be worth the contempt less than nothing.
+    let stalemate = ChessBoard::from_fen("k7/p1K5/P7/7R/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(stalemate.search(1).score, -CONTEMPT_LIMIT);
+    set_contempt(0);
+    assert_eq!(stalemate.search(1).score, 0);
+}
+
+#[test]
+fn test_repetition() {
+    // After 1. Nf3 Nf6 2. Ng1, Ng8 goes back to the start, which black takes for a draw only if
+    // it seeks one.
+    let mut board = ChessBoard::new();
+    let mut history = Vec::new();
+    for san in ["Nf3", "Nf6", "Ng1"] {
+        history.push(board.zobrist());
+        board.apply_move(board.parse_san(san).unwrap());
+    }
+    let back = board.parse_san("Ng8").unwrap();
+    let search = |contempt| {
+        set_contempt(contempt);
+        let table = TranspositionTable::new(1);
+        board.search_threads(3, u64::MAX, None, 1, &table, &history)
+    };
+    let seeking = search(-500);
+    assert_eq!(seeking.best_move, Some(back));
+    assert_eq!(seeking.score, 500);
+    assert_ne!(search(500).best_move, Some(back));
+    // Without the game before it, the same position has nothing to repeat.
+    set_contempt(-500);
+    assert_ne!(board.search(3).best_move, Some(back));
+    set_contempt(0);
+
+    // Fifty moves without a capture or pawn move are a draw too.
+    let fifty = ChessBoard::from_fen("k7/8/8/8/8/8/8/KQ6 w - - 99 80").unwrap();
+    assert_eq!(fifty.search(1).score, 0);
+}
@@ -0,0 +1,378 @@
+//! Self-play matches, where the engine plays itself with different search parameters for each
+//! side. Every search is limited to a number of positions rather than a time, so games are
+//! quick, and the result only depends on the parameters and the opening. Games can be
+//! adjudicated, decided once their result is clear rather than played out.
+
+use std::collections::HashMap;
+
+use crate::movegen::Move;
+use crate::search::{self, SearchParams, SearchResult};
+use crate::{ChessBoard, BLACK, GET_NUM, KING, QUEEN, ROOK, WHITE};
+
+/// How many plies a game may last before it is called a draw.
+pub const MAX_PLIES: usize = 250;
+
+/// How deep a search may go if it doesn't run out of positions first.
+pub const MAX_DEPTH: u32 = 32;
+
+/// How a game ended, as its PGN `Termination` tag says.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Termination {
+    /// By the rules: checkmate, stalemate, repetition, the fifty-move rule or bare material.
+    Normal,
+    /// Drawn because both sides' scores stayed near 0.
+    Draw,
+    /// Lost by the side whose score stayed hopeless.
+    Resignation,
+    /// Decided as a king and queen or rook against a lone king.
+    KnownEndgame,
+    /// Drawn on reaching the most moves a game may last.
+    MoveLimit,
+    /// Lost by the side whose engine crashed or couldn't be started.
+    Crash,
+    /// Lost by the side whose engine didn't answer in time.
+    Timeout,
+    /// Lost by the side whose engine played an illegal move.
+    IllegalMove,
+}
+
+impl Termination {
+    /// The value of the PGN `Termination` tag.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Termination::Normal => "normal",
+            Termination::Draw => "adjudication: draw",
+            Termination::Resignation => "adjudication: resignation",
+            Termination::KnownEndgame => "adjudication: known endgame",
+            Termination::MoveLimit => "adjudication: move limit",
+            Termination::Crash => "abandoned",
+            Termination::Timeout => "time forfeit",
+            Termination::IllegalMove => "rules infraction",
+        }
+    }
+}
+
+/// When a game is decided without playing it out. Every rule is off by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Adjudication {
+    /// The move from which draws are adjudicated.
+    pub draw_after: u32,
+    /// How many moves each side's score must stay within `draw_score` of 0 for the game to be
+    /// drawn, or 0 never to adjudicate a draw.
+    pub draw_moves: u32,
+    pub draw_score: i32,
+    /// How many of its moves in a row a side's score must be at or below `-resign_score` for it
+    /// to lose, or 0 never to adjudicate a loss.
+    pub resign_moves: u32,
+    pub resign_score: i32,
+    /// Whether a king and queen or rook against a lone king is decided at once. No tablebase is
+    /// read, so no other endgame is.
+    pub trivial: bool,
+    /// How many moves a game may last before it is drawn, or 0 for `MAX_PLIES` plies.
+    pub max_moves: u32,
+}
+
+/// A game that has been played.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Played {
+    pub moves: Vec<Move>,
+    /// White's score: 1 for a win, 0.5 for a draw and 0 for a loss.
+    pub score: f64,
+    pub termination: Termination,
+}
+
+/// The result of an elementary endgame, as a tablebase gives it: a king and queen or a king and
+/// rook beat a lone king, unless it can take the piece at once. Returns white's score, or
+/// `None` if the position isn't one of those. Only tells standard chess positions apart.
+pub fn known_result(board: &ChessBoard) -> Option<f64> {
+    if board.variant.name() != "standard" || board.outcome().is_some() {
+        return None;
+    }
+    let pieces = (0..64)
+        .filter(|&square| board.is_on_board(square))
+        .map(|square| (square, board.get_piece(square)))
+        .filter(|&(_, piece)| !matches!(GET_NUM(piece), crate::EMPTY | KING))
+        .collect::<Vec<(u8, u8)>>();
+    let [(square, piece)] = pieces[..] else {
+        return None;
+    };
+    if !matches!(GET_NUM(piece), QUEEN | ROOK) {
+        return None;
+    }
+    let strong = piece & BLACK;
+    let capture =
+        board.side_to_move() != strong && board.legal_moves().iter().any(|mv| mv.to == square);
+    Some(match (capture, strong) {
+        (true, _) => 0.5,
+        (false, WHITE) => 1.0,
+        (false, _) => 0.0,
+    })
+}
+
+/// A small random number generator (xorshift), so a match can be replayed from its seed.
+pub struct Rng(u64);
+
+impl Rng {
+    /// Starts the generator from `seed`, which may be anything.
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed | 1)
+    }
+
+    /// The next number, any 64 bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number from 0 up to (not including) `bound`.
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+/// Plays `plies` random moves from the start position, so games between the same parameters
+/// don't all repeat. Tries again if the moves end the game.
+pub fn random_opening(plies: usize, rng: &mut Rng) -> ChessBoard {
+    'opening: loop {
+        let mut board = ChessBoard::new();
+        for _ in 0..plies {
+            let moves = board.legal_moves();
+            if moves.is_empty() {
+                continue 'opening;
+            }
+            board.apply_move(moves[rng.below(moves.len())]);
+        }
+        if board.outcome().is_none() {
+            return board;
+        }
+    }
+}
+
+/// Plays a game from `start` with white searching with `white` and black with `black`, each
+/// searching at most `nodes` positions per move. Returns white's score: 1 for a win, 0.5 for a
+/// draw and 0 for a loss. Searches on this thread are left using the parameters they had.
+pub fn play_game(start: ChessBoard, white: SearchParams, black: SearchParams, nodes: u64) -> f64 {
+    play_recorded(start, [white, black], MAX_DEPTH, nodes, |_, _| {})
+}
+
+/// Plays a game like `play_game`, with each side's parameters in `params` and each search going
+/// to at most `depth` plies, and passes every position played from to `record` with what the
+/// search found there.
+pub fn play_recorded(
+    start: ChessBoard,
+    params: [SearchParams; 2],
+    depth: u32,
+    nodes: u64,
+    record: impl FnMut(&ChessBoard, &SearchResult),
+) -> f64 {
+    play_adjudicated(
+        start,
+        params,
+        depth,
+        nodes,
+        &Adjudication::default(),
+        record,
+    )
+    .score
+}
+
+/// Plays a game like `play_recorded`, deciding it early as `adjudication` says, and returns the
+/// moves played, the result and how the game ended.
+pub fn play_adjudicated(
+    start: ChessBoard,
+    params: [SearchParams; 2],
+    depth: u32,
+    nodes: u64,
+    adjudication: &Adjudication,
+    record: impl FnMut(&ChessBoard, &SearchResult),
+) -> Played {
+    let previous = search::params();
+    let search = |board: &ChessBoard, _: &[Move]| {
+        search::set_params(params[(board.side_to_move() == BLACK) as usize]);
+        Ok(board.search_nodes(depth, nodes))
+    };
+    let played = play_with(start, adjudication, search, record);
+    search::set_params(previous);
+    played
+}
+
+/// Plays a game like `play_adjudicated`, with each move found by `search`, which is given the
+/// position and the moves played to it from `start`. If `search` fails, the side to move
+/// forfeits the game, which ends as the `Termination` it gives.
+pub fn play_with(
+    start: ChessBoard,
+    adjudication: &Adjudication,
+    mut search: impl FnMut(&ChessBoard, &[Move]) -> Result<SearchResult, Termination>,
+    mut record: impl FnMut(&ChessBoard, &SearchResult),
+) -> Played {
+    let mut board = start;
+    let mut seen = HashMap::new();
+    let mut played = Played {
+        moves: Vec::new(),
+        score: 0.5,
+        termination: Termination::MoveLimit,
+    };
+    let plies = match adjudication.max_moves {
+        0 => MAX_PLIES,
+        moves => 2 * moves as usize,
+    };
+    // The moves in a row each side has been lost for, and the plies both have been level for.
+    let mut lost = [0; 2];
+    let mut level = 0;
+    for _ in 0..plies {
+        if let Some((result, _)) = board.outcome() {
+            played.score = match result {
+                "1-0" => 1.0,
+                "0-1" => 0.0,
+                _ => 0.5,
+            };
+            played.termination = Termination::Normal;
+            break;
+        }
+        let repetitions = seen.entry(board.zobrist()).or_insert(0);
+        *repetitions += 1;
+        if *repetitions == 3 || board.halfmoves >= 100 {
+            played.termination = Termination::Normal;
+            break;
+        }
+        if let Some(score) = adjudication.trivial.then(|| known_result(&board)).flatten() {
+            played.score = score;
+            played.termination = Termination::KnownEndgame;
+            break;
+        }
+        let side = (board.side_to_move() == BLACK) as usize;
+        let result = match search(&board, &played.moves) {
+            Ok(result) => result,
+            Err(termination) => {
+                played.score = side as f64;
+                played.termination = termination;
+                break;
+            }
+        };
+        let Some(mv) = result.best_move else {
+            played.termination = Termination::Normal;
+            break;
+        };
+        record(&board, &result);
+        lost[side] = match result.score <= -adjudication.resign_score {
+            true => lost[side] + 1,
+            false => 0,
+        };
+        if adjudication.resign_moves > 0 && lost[side] >= adjudication.resign_moves {
+            played.score = side as f64;
+            played.termination = Termination::Resignation;
+            break;
+        }
+        let number = board.moves as u32 / 2 + 1;
+        level = match number >= adjudication.draw_after
+            && result.score.abs() <= adjudication.draw_score
+        {
+            true => level + 1,
+            false => 0,
+        };
+        if adjudication.draw_moves > 0 && level >= 2 * adjudication.draw_moves {
+            played.termination = Termination::Draw;
+            break;
+        }
+        board.apply_move(mv);
+        played.moves.push(mv);
+    }
+    played
+}
+
+/// Plays two games from `start` between `first` and `second`, one with each color, and returns
+/// the points `first` scored, from 0 to 2.
+pub fn play_pair(start: ChessBoard, first: SearchParams, second: SearchParams, nodes: u64) -> f64 {
+    play_game(start, first, second, nodes) + 1.0 - play_game(start, second, first, nodes)
+}
+
+#[test]
+fn test_selfplay() {
+    let mut rng = Rng::new(7);
+    let opening = random_opening(4, &mut rng);
+    assert_eq!(opening.side_to_move(), crate::WHITE);
+    assert_ne!(opening.zobrist(), ChessBoard::new().zobrist());
+    assert_eq!(
+        random_opening(4, &mut Rng::new(7)).zobrist(),
+        opening.zobrist()
+    );
+
+    // White mates at once, whatever the parameters.
+    let params = SearchParams::default();
+    let mate = ChessBoard::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+    assert_eq!(play_game(mate, params, params, 500), 1.0);
+    assert_eq!(play_pair(mate, params, params, 500), 1.0);
+
+    // Bare kings can only draw.
+    let kings = ChessBoard::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 90 1").unwrap();
+    assert_eq!(play_game(kings, params, params, 100), 0.5);
+    assert_eq!(search::params(), params);
+
+    // Without adjudication, games are played out.
+    let params = [params; 2];
+    let queen = ChessBoard::from_fen("8/8/8/3k4/8/8/8/Q3K3 b - - 0 1").unwrap();
+    let cornered = ChessBoard::from_fen("7k/8/6K1/8/8/8/8/1Q6 b - - 0 1").unwrap();
+    let none = Adjudication::default();
+    let played = play_adjudicated(cornered, params, MAX_DEPTH, 2000, &none, |_, _| {});
+    assert_eq!(
+        (played.score, played.termination),
+        (1.0, Termination::Normal)
+    );
+    assert_eq!(played.moves.len(), 2);
+
+    // A known endgame is decided at once, and a lost one resigned.
+    let trivial = Adjudication {
+        trivial: true,
+        ..none
+    };
+    let played = play_adjudicated(queen, params, MAX_DEPTH, 2000, &trivial, |_, _| {});
+    assert_eq!(played.termination, Termination::KnownEndgame);
+    assert_eq!((played.score, played.moves.len()), (1.0, 0));
+    let resign = Adjudication {
+        resign_moves: 2,
+        resign_score: 500,
+        ..none
+    };
+    let played = play_adjudicated(queen, params, MAX_DEPTH, 2000, &resign, |_, _| {});
+    assert_eq!((played.score, played.moves.len()), (1.0, 2));
+    assert_eq!(played.termination, Termination::Resignation);
+
+    // A level game is drawn once both sides have been level long enough, or runs out of moves.
+    let pawns = ChessBoard::from_fen("4k3/p7/8/8/8/8/P7/4K3 w - - 0 1").unwrap();
+    let draw = Adjudication {
+        draw_moves: 2,
+        draw_score: 200,
+        ..none
+    };
+    let played = play_adjudicated(pawns, params, 3, 2000, &draw, |_, _| {});
+    assert_eq!((played.score, played.moves.len()), (0.5, 3));
+    assert_eq!(played.termination, Termination::Draw);
+    let draw_later = Adjudication {
+        draw_after: 3,
+        ..draw
+    };
+    let played = play_adjudicated(pawns, params, 3, 2000, &draw_later, |_, _| {});
+    assert_eq!(played.moves.len(), 7);
+    let short = Adjudication {
+        max_moves: 3,
+        ..none
+    };
+    let played = play_adjudicated(pawns, params, 3, 2000, &short, |_, _| {});
+    assert_eq!((played.score, played.moves.len()), (0.5, 6));
+    assert_eq!(played.termination, Termination::MoveLimit);
+    assert_eq!(Termination::MoveLimit.tag(), "adjudication: move limit");
+}
+
+#[test]
+fn test_known_result() {
+    let result = |fen| known_result(&ChessBoard::from_fen(fen).unwrap());
+    assert_eq!(result("8/8/8/3k4/8/8/8/3QK3 b - - 0 1"), Some(1.0));
+    assert_eq!(result("8/8/8/3k4/8/8/8/Q3K3 w - - 0 1"), Some(1.0));
+    assert_eq!(result("8/8/8/3k4/3Q4/8/8/4K3 b - - 0 1"), Some(0.5));
+    assert_eq!(result("8/8/8/3k4/3r4/8/8/4K3 b - - 0 1"), Some(0.0));
+    assert_eq!(result("8/8/8/3k4/8/8/8/3BK3 w - - 0 1"), None);
+    assert_eq!(result("8/8/8/3k4/8/8/3P4/3QK3 w - - 0 1"), None);
+    assert_eq!(result("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"), None);
+}
@@ -0,0 +1,193 @@
+//! Positions and moves in words rather than as a grid, for players using a screen reader: a
+//! move reads as "white knight g1 to f3" or "black pawn e6 captures knight on d5", and the board
+//! as the squares each side's pieces stand on.
+
+use crate::movegen::{color_name, piece_name, square_name, Move};
+use crate::{ChessBoard, BISHOP, BLACK, EMPTY, GET_NUM, KING, KNIGHT, PAWN, QUEEN, ROOK, WHITE};
+
+/// The order pieces are listed in, most valuable first.
+const LISTED: [u8; 6] = [KING, QUEEN, ROOK, BISHOP, KNIGHT, PAWN];
+
+/// Joins `items` as they are said: "a", "a and b", "a, b and c".
+fn list(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [only] => only.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+/// `mv`, played in `board`, in words, as in "white knight g1 to f3", "black castles kingside" or
+/// "white pawn e7 captures rook on d8, promoting to a queen, check".
+pub fn spoken_move(board: &ChessBoard, mv: Move) -> String {
+    let color = color_name(board.side_to_move());
+    let mut text = if mv.is_drop() {
+        format!(
+            "{} drops a {} on {}",
+            color,
+            piece_name(mv.promotion),
+            square_name(mv.to)
+        )
+    } else if board.is_castling(mv) {
+        let side = match board.is_kingside_castling(mv) {
+            true => "kingside",
+            false => "queenside",
+        };
+        format!("{} castles {}", color, side)
+    } else {
+        let piece = board.get_piece(mv.from);
+        let mover = format!("{} {} {}", color, piece_name(piece), square_name(mv.from));
+        match board.get_piece(mv.to) {
+            _ if GET_NUM(piece) == PAWN && mv.to == board.en_passant => {
+                format!(
+                    "{} captures pawn on {} en passant",
+                    mover,
+                    square_name(mv.to)
+                )
+            }
+            EMPTY => format!("{} to {}", mover, square_name(mv.to)),
+            captured => format!(
+                "{} captures {} on {}",
+                mover,
+                piece_name(captured),
+                square_name(mv.to)
+            ),
+        }
+    };
+    if mv.promotion != EMPTY && !mv.is_drop() {
+        text.push_str(&format!(", promoting to a {}", piece_name(mv.promotion)));
+    }
+    let mut next = *board;
+    next.apply_move(mv);
+    if next
+        .outcome()
+        .is_some_and(|(_, reason)| reason == "checkmate")
+    {
+        text.push_str(", checkmate");
+    } else if next.is_in_check(next.side_to_move()) {
+        text.push_str(", check");
+    }
+    text
+}
+
+/// Where `color`'s pieces stand, as in "White: king e1, rooks a1 and h1, pawns f2 and g2."
+fn spoken_pieces(board: &ChessBoard, color: u8) -> String {
+    let (files, ranks) = board.variant.dimensions();
+    let squares = (0..ranks)
+        .flat_map(|rank| rank * 8..rank * 8 + files)
+        .collect::<Vec<u8>>();
+    let groups = LISTED
+        .iter()
+        .filter_map(|&piece| {
+            let on = squares
+                .iter()
+                .filter(|&&square| board.get_piece(square) == piece | color)
+                .map(|&square| square_name(square))
+                .collect::<Vec<String>>();
+            let name = match on.len() {
+                0 => return None,
+                1 => piece_name(piece).to_string(),
+                _ => format!("{}s", piece_name(piece)),
+            };
+            Some(format!("{} {}", name, list(&on)))
+        })
+        .collect::<Vec<String>>();
+    let mut text = match color {
+        WHITE => "White".to_string(),
+        _ => "Black".to_string(),
+    };
+    match groups.is_empty() {
+        true => text.push_str(": no pieces."),
+        false => text.push_str(&format!(": {}.", groups.join(", "))),
+    }
+    if board.variant.has_pockets() {
+        let held = [QUEEN, ROOK, BISHOP, KNIGHT, PAWN]
+            .iter()
+            .filter_map(|&piece| match board.pocket(color)[piece as usize] {
+                0 => None,
+                1 => Some(format!("1 {}", piece_name(piece))),
+                count => Some(format!("{} {}s", count, piece_name(piece))),
+            })
+            .collect::<Vec<String>>();
+        match held.is_empty() {
+            true => text.push_str(" Nothing in hand."),
+            false => text.push_str(&format!(" In hand: {}.", list(&held))),
+        }
+    }
+    text
+}
+
+/// Whose move it is in `board`, and whether they are in check.
+pub fn spoken_turn(board: &ChessBoard) -> String {
+    let side = board.side_to_move();
+    let to_move = match side {
+        WHITE => "White",
+        _ => "Black",
+    };
+    match board.is_in_check(side) {
+        true => format!("{} to move, in check.\n", to_move),
+        false => format!("{} to move.\n", to_move),
+    }
+}
+
+/// `board` in words: each side's pieces, and whose move it is.
+pub fn spoken_board(board: &ChessBoard) -> String {
+    let mut text = format!(
+        "{}\n{}\n",
+        spoken_pieces(board, WHITE),
+        spoken_pieces(board, BLACK)
+    );
+    if let Some(limit) = board.variant.check_limit() {
+        text.push_str(&format!(
+            "White has given {} checks and black {}, of {} to win.\n",
+            board.checks[0], board.checks[1], limit
+        ));
+    }
+    text.push_str(&spoken_turn(board));
+    text
+}
+
+#[test]
+fn test_spoken() {
+    assert_eq!(list(&[]), "");
+    assert_eq!(list(&["a".to_string(), "b".to_string()]), "a and b");
+
+    let mut board = ChessBoard::new();
+    for (san, spoken) in [
+        ("Nf3", "white knight g1 to f3"),
+        ("d5", "black pawn d7 to d5"),
+        ("e4", "white pawn e2 to e4"),
+        ("dxe4", "black pawn d5 captures pawn on e4"),
+        ("Bc4", "white bishop f1 to c4"),
+        ("exf3", "black pawn e4 captures knight on f3"),
+        ("O-O", "white castles kingside"),
+    ] {
+        let mv = board.parse_san(san).unwrap();
+        assert_eq!(spoken_move(&board, mv), spoken);
+        board.apply_move(mv);
+    }
+
+    let board = ChessBoard::from_fen("4k3/2P5/8/4pP2/8/8/5Q2/4K2R w K e6 0 1").unwrap();
+    let en_passant = board.parse_san("fxe6").unwrap();
+    assert_eq!(
+        spoken_move(&board, en_passant),
+        "white pawn f5 captures pawn on e6 en passant"
+    );
+    let promotion = board.parse_san("c8=Q+").unwrap();
+    assert_eq!(
+        spoken_move(&board, promotion),
+        "white pawn c7 to c8, promoting to a queen, check"
+    );
+    let mate = ChessBoard::from_fen("7k/8/6K1/8/8/8/8/R7 w - - 0 1").unwrap();
+    assert_eq!(
+        spoken_move(&mate, mate.parse_san("Ra8#").unwrap()),
+        "white rook a1 to a8, checkmate"
+    );
+
+    assert_eq!(
+        spoken_board(&board),
+        "White: king e1, queen f2, rook h1, pawns f5 and c7.\n\
+         Black: king e8, pawn e5.\n\
+         White to move.\n"
+    );
+}
@@ -0,0 +1,118 @@
+//! SPSA (simultaneous perturbation stochastic approximation) tuning of the search's parameters.
+//! Each iteration nudges every parameter at once, up or down at random, plays a pair of
+//! self-play games between the nudged-up and nudged-down parameters, and moves the parameters
+//! towards whichever side scored better. The steps shrink as the tuning goes on, so the
+//! parameters settle even though single games are noisy.
+
+use std::io::{Error, Write};
+
+use crate::config::params_section;
+use crate::net::random_u64;
+use crate::search::SearchParams;
+use crate::selfplay::{play_pair, random_opening, Rng};
+
+/// How many random plies each pair of games starts with.
+const OPENING_PLIES: usize = 6;
+
+/// How quickly the step size (`ALPHA`) and the perturbation size (`GAMMA`) shrink over the
+/// iterations, the exponents usually recommended for SPSA.
+const ALPHA: f64 = 0.602;
+const GAMMA: f64 = 0.101;
+
+/// Tunes `params` with `iterations` iterations of SPSA, searching at most `nodes` positions per
+/// move in every game, and returns the tuned parameters.
+fn tune(params: SearchParams, iterations: u32, nodes: u64, rng: &mut Rng) -> SearchParams {
+    let named = params.named();
+    let ranges = named
+        .iter()
+        .map(|(name, _)| SearchParams::range(name).unwrap())
+        .collect::<Vec<(i32, i32)>>();
+    let mut theta = named
+        .iter()
+        .map(|&(_, value)| value as f64)
+        .collect::<Vec<f64>>();
+    // Perturb each parameter by a twentieth of its range at first, and let a pair won outright
+    // move it by a fifth of that.
+    let perturbations = ranges
+        .iter()
+        .map(|&(low, high)| (high - low) as f64 / 20.0)
+        .collect::<Vec<f64>>();
+    let stability = iterations as f64 / 10.0;
+
+    let build = |theta: &[f64]| {
+        let mut params = params;
+        for ((&(name, _), &(low, high)), &value) in named.iter().zip(&ranges).zip(theta) {
+            params.set(name, (value.round() as i32).clamp(low, high));
+        }
+        params
+    };
+    for k in 0..iterations {
+        let a_k = ((1.0 + stability) / (k as f64 + 1.0 + stability)).powf(ALPHA) / 5.0;
+        let c_k = 1.0 / (k as f64 + 1.0).powf(GAMMA);
+        let signs = theta
+            .iter()
+            .map(|_| if rng.next_u64() & 1 == 0 { 1.0 } else { -1.0 })
+            .collect::<Vec<f64>>();
+        let shifted = |direction: f64| {
+            let shifted = theta
+                .iter()
+                .zip(&signs)
+                .zip(&perturbations)
+                .map(|((value, sign), c)| value + direction * c_k * c * sign)
+                .collect::<Vec<f64>>();
+            build(&shifted)
+        };
+        let (plus, minus) = (shifted(1.0), shifted(-1.0));
+        let start = random_opening(OPENING_PLIES, rng);
+        // From -1 if the nudged-down parameters won both games to 1 if the nudged-up ones did.
+        let result = play_pair(start, plus, minus, nodes) - 1.0;
+        for ((value, sign), (c, &(low, high))) in theta
+            .iter_mut()
+            .zip(&signs)
+            .zip(perturbations.iter().zip(&ranges))
+        {
+            *value = (*value + a_k * c / c_k * result * sign).clamp(low as f64, high as f64);
+        }
+        eprintln!(
+            "Iteration {}: {}",
+            k + 1,
+            build(&theta)
+                .named()
+                .iter()
+                .map(|(name, value)| format!("{} {}", name, value))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+    build(&theta)
+}
+
+/// Runs `spsa`: tunes `params` over `iterations` pairs of self-play games and writes the result
+/// to `output` (or stdout) as the `[search]` section of a configuration file.
+pub fn run(
+    params: SearchParams,
+    iterations: u32,
+    nodes: u64,
+    output: Option<&str>,
+) -> Result<(), Error> {
+    let tuned = tune(params, iterations, nodes, &mut Rng::new(random_u64()));
+    let section = params_section("search", tuned.named());
+    match output {
+        Some(path) => std::fs::write(path, section),
+        None => std::io::stdout().write_all(section.as_bytes()),
+    }
+}
+
+#[test]
+fn test_spsa() {
+    let params = SearchParams::default();
+    assert_eq!(tune(params, 0, 100, &mut Rng::new(1)), params);
+
+    let tuned = tune(params, 2, 100, &mut Rng::new(1));
+    for (name, value) in tuned.named() {
+        let (low, high) = SearchParams::range(name).unwrap();
+        assert!((low..=high).contains(&value));
+    }
+    assert_eq!(tune(params, 2, 100, &mut Rng::new(1)), tuned);
+    assert_eq!(crate::search::params(), params);
+}
@@ -0,0 +1,164 @@
+//! Test suites: EPD files of positions with the move that solves them (`bm`) or a move that
+//! looks good but isn't (`am`), as in Win at Chess or the Strategic Test Suite. Each position is
+//! searched for a fixed time, and passes if the search finds one of the best moves and none of
+//! the moves to avoid.
+
+use std::io::{BufRead, BufReader, Error, ErrorKind, Write};
+use std::time::Duration;
+
+use crate::movegen::Move;
+use crate::ChessBoard;
+
+/// A position of a test suite.
+struct Record {
+    board: ChessBoard,
+    /// The name given by the `id` opcode, or the line number.
+    id: String,
+    /// The moves of the `bm` opcode, any of which passes.
+    best: Vec<Move>,
+    /// The moves of the `am` opcode, none of which may be played.
+    avoid: Vec<Move>,
+}
+
+/// Reads one EPD record: the four position fields of a FEN, then opcodes with their operands,
+/// each ending with a semicolon, as in `... w - - bm Qd1+; id "WAC.001";`.
+fn parse_record(line: &str, number: usize) -> Result<Record, String> {
+    let fields = line.split_whitespace().take(4).collect::<Vec<&str>>();
+    let board = ChessBoard::from_fen(&fields.join(" ")).map_err(|e| e.to_string())?;
+    let mut operations = line.trim_start();
+    for _ in 0..4 {
+        let (_, rest) = operations
+            .split_once(char::is_whitespace)
+            .unwrap_or(("", ""));
+        operations = rest.trim_start();
+    }
+
+    let mut record = Record {
+        board,
+        id: number.to_string(),
+        best: Vec::new(),
+        avoid: Vec::new(),
+    };
+    for operation in operations.split(';').map(str::trim) {
+        let (opcode, operands) = operation.split_once(' ').unwrap_or((operation, ""));
+        let moves = || {
+            operands
+                .split_whitespace()
+                .map(|san| {
+                    board
+                        .parse_san(san)
+                        .map_err(|e| format!("Bad move {} ({:?}).", san, e))
+                })
+                .collect::<Result<Vec<Move>, String>>()
+        };
+        match opcode {
+            "bm" => record.best = moves()?,
+            "am" => record.avoid = moves()?,
+            "id" => record.id = operands.trim().trim_matches('"').to_string(),
+            _ => {}
+        }
+    }
+    if record.best.is_empty() && record.avoid.is_empty() {
+        return Err("The record has neither a bm nor an am opcode.".to_string());
+    }
+    Ok(record)
+}
+
+/// Reads a test suite with one EPD record per line. Blank lines and lines starting with `#` are
+/// skipped.
+fn read_suite(input: impl BufRead) -> Result<Vec<Record>, Error> {
+    let mut records = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let record = parse_record(&line, number + 1).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Line {}: {}", number + 1, e),
+            )
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Searches every record for `time` and writes whether it passed to `output`, then the total.
+/// Returns how many passed.
+fn run_suite(records: &[Record], time: Duration, output: &mut impl Write) -> Result<usize, Error> {
+    let mut passed = 0;
+    for record in records {
+        let board = &record.board;
+        let result = board.search_for(time);
+        let Some(mv) = result.best_move else {
+            writeln!(output, "{}: fail, no move", record.id)?;
+            continue;
+        };
+        let pass =
+            (record.best.is_empty() || record.best.contains(&mv)) && !record.avoid.contains(&mv);
+        passed += pass as usize;
+        let expected = [("bm", &record.best), ("am", &record.avoid)]
+            .iter()
+            .filter(|(_, moves)| !moves.is_empty())
+            .map(|(opcode, moves)| {
+                let moves = moves.iter().map(|&mv| board.san(mv)).collect::<Vec<_>>();
+                format!("{} {}", opcode, moves.join(" "))
+            })
+            .collect::<Vec<String>>();
+        writeln!(
+            output,
+            "{}: {}, played {} at depth {} ({})",
+            record.id,
+            if pass { "pass" } else { "fail" },
+            board.san(mv),
+            result.depth,
+            expected.join(", ")
+        )?;
+    }
+    writeln!(
+        output,
+        "\nSolved {} of {} ({:.1}%).",
+        passed,
+        records.len(),
+        100.0 * passed as f64 / records.len().max(1) as f64
+    )?;
+    Ok(passed)
+}
+
+/// Runs `testsuite`: searches each position of the EPD file at `path` for `time` and prints
+/// which were solved.
+pub fn run(path: &str, time: Duration) -> Result<(), Error> {
+    let records = read_suite(BufReader::new(std::fs::File::open(path)?))?;
+    run_suite(&records, time, &mut std::io::stdout().lock())?;
+    Ok(())
+}
+
+#[test]
+fn test_testsuite() {
+    let suite = "\
+        # A free queen, a mate, and a move to avoid that is really the best.\n\
+        4k3/8/8/3q4/8/8/3R4/4K3 w - - bm Rxd5; id \"queen\";\n\
+        \n\
+        r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - bm Qxf7# Bxf7+; id mate;\n\
+        4k3/8/8/3q4/8/8/3R4/4K3 w - - am Rxd5; c0 \"Taking the queen is fine really.\";\n";
+    let records = read_suite(suite.as_bytes()).unwrap();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].id, "queen");
+    assert_eq!(records[1].best.len(), 2);
+    assert_eq!(records[2].id, "5");
+    assert_eq!(records[2].avoid.len(), 1);
+
+    let mut output = Vec::new();
+    let passed = run_suite(&records, Duration::from_millis(300), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(passed, 2, "{}", output);
+    assert!(output.starts_with("queen: pass, played Rxd5 at depth "));
+    assert!(output.contains("5: fail, played Rxd5 at depth "));
+    assert!(output.ends_with("(am Rxd5)\n\nSolved 2 of 3 (66.7%).\n"));
+
+    assert!(read_suite("8/8/8/8/8/8/8/8 w - - bm e4;\n".as_bytes()).is_err());
+    let no_opcode = "4k3/8/8/8/8/8/8/4K3 w - - id \"x\";\n";
+    assert!(read_suite(no_opcode.as_bytes()).is_err());
+    assert!(read_suite("4k3/8/8/8/8/8/8/4K3 w - - bm Qh5;\n".as_bytes()).is_err());
+}
@@ -0,0 +1,212 @@
+//! Color themes for drawing the board in the terminal with 24-bit ANSI colors, as an alternative
+//! to the plain grid of `ChessBoard`'s `Display`. Besides the built-in themes, the configuration
+//! file can define its own:
+//!
+//! ```toml
+//! [display]
+//! theme = "green"             # or classic, blue, high-contrast, solarized, or plain
+//!
+//! [themes]
+//! green = { light = "#eeeed2", dark = "#769656" }
+//! ```
+
+use crate::grid::Layout;
+use crate::{ChessBoard, BLACK, CHESS_PIECES, EMPTY, GET_NUM};
+
+/// A color, as red, green and blue.
+pub type Rgb = (u8, u8, u8);
+
+/// The colors the board is drawn in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+    pub light: Rgb,
+    pub dark: Rgb,
+    /// The squares the last move went from and to.
+    pub highlight: Rgb,
+    /// The pieces of each side.
+    pub white: Rgb,
+    pub black: Rgb,
+}
+
+/// The names of the built-in themes, as the `theme` command and the configuration file take
+/// them.
+pub const THEMES: [&str; 4] = ["classic", "blue", "high-contrast", "solarized"];
+
+impl Theme {
+    /// The brown board of most chess sets and sites.
+    pub const CLASSIC: Theme = Theme {
+        light: (0xf0, 0xd9, 0xb5),
+        dark: (0xb5, 0x88, 0x63),
+        highlight: (0xcd, 0xd2, 0x6a),
+        white: (0xff, 0xff, 0xff),
+        black: (0x00, 0x00, 0x00),
+    };
+
+    /// The built-in theme called `name`.
+    pub fn by_name(name: &str) -> Option<Theme> {
+        match name {
+            "classic" => Some(Theme::CLASSIC),
+            "blue" => Some(Theme {
+                light: (0xde, 0xe3, 0xe6),
+                dark: (0x8c, 0xa2, 0xad),
+                highlight: (0xc3, 0xd8, 0x87),
+                ..Theme::CLASSIC
+            }),
+            // Red and black pieces on white and grey squares, which stay apart even for those
+            // who can't tell the colors of the other themes apart.
+            "high-contrast" => Some(Theme {
+                light: (0xff, 0xff, 0xff),
+                dark: (0x8a, 0x8a, 0x8a),
+                highlight: (0xff, 0xd7, 0x00),
+                white: (0xd7, 0x00, 0x00),
+                black: (0x00, 0x00, 0x00),
+            }),
+            // Ethan Schoonover's Solarized palette.
+            "solarized" => Some(Theme {
+                light: (0xee, 0xe8, 0xd5),
+                dark: (0x83, 0x94, 0x96),
+                highlight: (0xb5, 0x89, 0x00),
+                white: (0x26, 0x8b, 0xd2),
+                black: (0x07, 0x36, 0x42),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Reads a theme defined in the configuration file, as a table of colors written as in
+    /// `"#eeeed2"`. Colors it leaves out are the classic theme's.
+    pub fn parse(table: &toml::Table) -> Result<Theme, String> {
+        let mut theme = Theme::CLASSIC;
+        for (key, value) in table {
+            let slot = match key.as_str() {
+                "light" => &mut theme.light,
+                "dark" => &mut theme.dark,
+                "highlight" => &mut theme.highlight,
+                "white" => &mut theme.white,
+                "black" => &mut theme.black,
+                _ => return Err(format!("Themes have no color `{}`.", key)),
+            };
+            *slot = value
+                .as_str()
+                .and_then(parse_hex)
+                .ok_or_else(|| format!("`{}` must be a color such as \"#eeeed2\".", key))?;
+        }
+        Ok(theme)
+    }
+}
+
+/// The theme called `name`, looking first at the themes the configuration file defines in
+/// `custom`. `plain` is no theme, for the plain grid.
+pub fn find(name: &str, custom: &[(String, Theme)]) -> Option<Option<Theme>> {
+    match custom.iter().find(|(custom, _)| custom == name) {
+        Some(&(_, theme)) => Some(Some(theme)),
+        None if name == "plain" => Some(None),
+        None => Theme::by_name(name).map(Some),
+    }
+}
+
+/// Reads a color written as in `#eeeed2`.
+pub fn parse_hex(text: &str) -> Option<Rgb> {
+    let digits = text.strip_prefix('#').filter(|digits| digits.len() == 6)?;
+    let channel = |i: usize| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// The escape sequence that draws the background in `color`.
+fn background((r, g, b): Rgb) -> String {
+    format!("\x1b[48;2;{};{};{}m", r, g, b)
+}
+
+/// The escape sequence that draws text in `color`.
+fn foreground((r, g, b): Rgb) -> String {
+    format!("\x1b[38;2;{};{};{}m", r, g, b)
+}
+
+const RESET: &str = "\x1b[0m";
+
+/// Draws `board` in the colors of `theme`, with white at the bottom, the ranks and files
+/// labelled and the squares of `last_move` highlighted. Squares are a column wider than `layout`
+/// says on either side, since with no `|` between them the pieces would touch.
+pub fn colored(
+    board: &ChessBoard,
+    theme: &Theme,
+    last_move: Option<(u8, u8)>,
+    layout: &Layout,
+) -> String {
+    let layout = layout.widened(2);
+    let (files, ranks) = board.variant.dimensions();
+    let mut text = String::new();
+    for rank in (0..ranks).rev() {
+        text.push_str(&format!("{} ", rank + 1));
+        for square in rank * 8..rank * 8 + files {
+            let highlighted = last_move.is_some_and(|(from, to)| square == from || square == to);
+            let color = match (highlighted, (square + rank) % 2 == 1) {
+                (true, _) => theme.highlight,
+                (false, true) => theme.light,
+                (false, false) => theme.dark,
+            };
+            text.push_str(&background(color));
+            let piece = board.get_piece(square);
+            // The solid glyphs, drawn in each side's color, read better than the outlined ones.
+            match piece {
+                EMPTY => text.push_str(&layout.cell(" ")),
+                _ => {
+                    let side = match piece & BLACK {
+                        BLACK => theme.black,
+                        _ => theme.white,
+                    };
+                    let glyph = CHESS_PIECES[(GET_NUM(piece) | BLACK) as usize];
+                    text.push_str(&foreground(side));
+                    text.push_str(&layout.cell(&glyph.to_string()));
+                }
+            }
+        }
+        text.push_str(RESET);
+        text.push('\n');
+    }
+    text.push_str("  ");
+    for file in 0..files {
+        text.push_str(&layout.cell(&((b'a' + file) as char).to_string()));
+    }
+    text.push('\n');
+    text
+}
+
+#[test]
+fn test_theme() {
+    assert_eq!(parse_hex("#eeeed2"), Some((0xee, 0xee, 0xd2)));
+    assert_eq!(parse_hex("eeeed2"), None);
+    assert_eq!(parse_hex("#eeed2"), None);
+    assert_eq!(parse_hex("#eeeedg"), None);
+    for name in THEMES {
+        assert!(Theme::by_name(name).is_some(), "{}", name);
+    }
+    assert_eq!(Theme::by_name("plain"), None);
+    assert_eq!(find("plain", &[]), Some(None));
+    assert_eq!(find("blue", &[]), Theme::by_name("blue").map(Some));
+    assert_eq!(find("mine", &[]), None);
+
+    let table = "light = \"#eeeed2\"\ndark = \"#769656\"\n".parse().unwrap();
+    let green = Theme::parse(&table).unwrap();
+    assert_eq!(green.dark, (0x76, 0x96, 0x56));
+    assert_eq!(
+        find("blue", &[("blue".to_string(), green)]),
+        Some(Some(green))
+    );
+    assert_eq!(green.white, Theme::CLASSIC.white);
+    assert!(Theme::parse(&"light = \"green\"\n".parse().unwrap()).is_err());
+    assert!(Theme::parse(&"border = \"#000000\"\n".parse().unwrap()).is_err());
+
+    let mut board = ChessBoard::new();
+    board.apply_move(board.parse_san("e4").unwrap());
+    let text = colored(&board, &Theme::CLASSIC, Some((12, 28)), &Layout::default());
+    let lines = text.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 9);
+    assert_eq!(lines[8], "   a  b  c  d  e  f  g  h ");
+    // a8 is light, with a black rook on it, and the rank ends by resetting the colors.
+    assert!(lines[0].starts_with("8 \x1b[48;2;240;217;181m\x1b[38;2;0;0;0m ♜ "));
+    assert!(lines[0].ends_with(RESET));
+    // e4, where the pawn went, is highlighted.
+    assert!(lines[4].contains("\x1b[48;2;205;210;106m\x1b[38;2;255;255;255m ♟ "));
+    assert_eq!(text.matches("48;2;205;210;106m").count(), 2);
+}
@@ -0,0 +1,96 @@
+//! Three-check: the standard rules, except that giving a third check also wins.
+
+use crate::movegen::Move;
+use crate::variant::{Standard, Variant};
+use crate::{ChessBoard, BLACK};
+
+/// How many checks win.
+pub const CHECKS: u8 = 3;
+
+pub struct ThreeCheck;
+
+impl Variant for ThreeCheck {
+    fn name(&self) -> &'static str {
+        "threecheck"
+    }
+
+    fn pgn_name(&self) -> &'static str {
+        "Three-check"
+    }
+
+    fn start(&self) -> ChessBoard {
+        ChessBoard::new()
+    }
+
+    fn check_limit(&self) -> Option<u8> {
+        Some(CHECKS)
+    }
+
+    /// Counts the check the move gives, if it gives one.
+    fn after_move(&self, before: &ChessBoard, _mv: Move, after: &mut ChessBoard) {
+        let side = before.side_to_move();
+        if after.is_in_check(side ^ BLACK) {
+            let checks = &mut after.checks[(side >> 3) as usize];
+            *checks = checks.saturating_add(1);
+        }
+    }
+
+    fn outcome(&self, board: &ChessBoard, moves: &[Move]) -> Option<(&'static str, &'static str)> {
+        match board.checks {
+            [white, _] if white >= CHECKS => Some(("1-0", "three_checks")),
+            [_, black] if black >= CHECKS => Some(("0-1", "three_checks")),
+            _ => Standard.outcome(board, moves),
+        }
+    }
+}
+
+#[test]
+fn test_three_check() {
+    use crate::variant::by_name;
+
+    let three_check = by_name("Three-check").unwrap();
+    let mut board = ChessBoard::start_of(three_check);
+    assert_eq!(
+        board.fen(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1"
+    );
+    for san in [
+        "e4", "e5", "Bc4", "Nf6", "Bxf7+", "Kxf7", "Qh5+", "Ke7", "Qxe5+",
+    ] {
+        assert_eq!(board.outcome(), None);
+        board.apply_move(board.parse_san(san).expect(san));
+    }
+    assert_eq!(board.checks, [3, 0]);
+    assert_eq!(board.outcome(), Some(("1-0", "three_checks")));
+    // Moves are still legal, but the game is over, in either notation.
+    let mv = board.legal_moves()[0];
+    for text in [
+        board.san(mv),
+        format!("{}->{}", &mv.to_string()[..2], &mv.to_string()[2..4]),
+    ] {
+        assert!(matches!(
+            board.parse_move(&text),
+            Err(crate::ChessErr::BadMove(
+                crate::movegen::MoveRejection::GameOver
+            ))
+        ));
+    }
+    assert!(board
+        .to_string()
+        .contains("Checks given: white 3, black 0 (3 win)."));
+
+    // The counters are read back, as checks left or (after the move numbers) checks given.
+    let fen = "rnbqkb1r/pppp1ppp/5n2/4p3/2B1P3/8/PPPP1PPP/RNBQK1NR w KQkq - 2+3 0 4";
+    let board = ChessBoard::from_fen(fen).unwrap();
+    assert_eq!(board.variant.name(), "threecheck");
+    assert_eq!(board.checks, [1, 0]);
+    assert_eq!(board.fen(), fen);
+    let given = "rnbqkb1r/pppp1ppp/5n2/4p3/2B1P3/8/PPPP1PPP/RNBQK1NR w KQkq - 0 4 +1+0";
+    assert_eq!(ChessBoard::from_fen(given).unwrap().fen(), fen);
+    assert!(ChessBoard::from_fen(&fen.replace("2+3", "4+3")).is_err());
+
+    // The search goes for the third check.
+    let board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 1+3 0 1").unwrap();
+    let best = board.search(2).best_move.unwrap();
+    assert!(board.san(best).ends_with('+'));
+}
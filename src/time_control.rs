@@ -0,0 +1,86 @@
+//! Time controls, which may give the two sides different times: five minutes against one, say.
+//! A game's time control is the starting time of white and black in milliseconds.
+
+use std::time::Duration;
+
+use crate::pgn::PgnGame;
+
+/// How many moves an engine on the clock expects to still have to play, so it spends that
+/// fraction of its remaining time on each.
+const MOVES_TO_GO: u32 = 30;
+
+/// Reads a time control as `--time` takes it: the minutes each side gets, as in `5`, or the
+/// minutes of white (or the first player) and black (or the second), as in `5/1`. Fractions of
+/// a minute, as in `0.5`, are allowed. Returns `None` unless every time is above zero.
+pub fn parse(text: &str) -> Option<[u32; 2]> {
+    let minutes = |text: &str| -> Option<u32> {
+        let minutes = text.trim().parse::<f64>().ok()?;
+        let ms = (minutes * 60_000.0).round();
+        (ms >= 1.0 && ms <= u32::MAX as f64).then_some(ms as u32)
+    };
+    match text.split_once('/') {
+        Some((white, black)) => Some([minutes(white)?, minutes(black)?]),
+        None => minutes(text).map(|ms| [ms, ms]),
+    }
+}
+
+/// Describes the time control `clocks` for the players, as in `5 minutes each` or `5 minutes
+/// for white against 1 for black`.
+pub fn describe(clocks: [u32; 2]) -> String {
+    let minutes = |ms: u32| format!("{}", ms as f64 / 60_000.0);
+    match clocks {
+        [white, black] if white == black => format!("{} minutes each", minutes(white)),
+        [white, black] => format!(
+            "{} minutes for white against {} for black",
+            minutes(white),
+            minutes(black)
+        ),
+    }
+}
+
+/// Records the time control `clocks` in the PGN tags of `game`: `TimeControl` when both sides
+/// have the same time, or else `WhiteTimeControl` and `BlackTimeControl`, as cutechess writes
+/// them. The times are in seconds, as in `300`.
+pub fn set_tags(game: &mut PgnGame, clocks: [u32; 2]) {
+    let seconds = |ms: u32| match ms % 1000 {
+        0 => format!("{}", ms / 1000),
+        _ => format!("{}", ms as f64 / 1000.0),
+    };
+    match clocks {
+        [white, black] if white == black => game.set_tag("TimeControl", &seconds(white)),
+        [white, black] => {
+            game.set_tag("WhiteTimeControl", &seconds(white));
+            game.set_tag("BlackTimeControl", &seconds(black));
+        }
+    }
+}
+
+/// How long an engine with `remaining_ms` on its clock spends on its next move.
+pub fn move_time(remaining_ms: u32) -> Duration {
+    Duration::from_millis((remaining_ms / MOVES_TO_GO) as u64)
+}
+
+#[test]
+fn test_time_control() {
+    assert_eq!(parse("5"), Some([300_000, 300_000]));
+    assert_eq!(parse("5/1"), Some([300_000, 60_000]));
+    assert_eq!(parse("0.5 / 0.25"), Some([30_000, 15_000]));
+    assert_eq!(parse("0"), None);
+    assert_eq!(parse("5/"), None);
+    assert_eq!(parse("-1/5"), None);
+    assert_eq!(describe([300_000, 300_000]), "5 minutes each");
+    assert_eq!(
+        describe([300_000, 30_000]),
+        "5 minutes for white against 0.5 for black"
+    );
+
+    let mut game = PgnGame::new(crate::ChessBoard::new());
+    set_tags(&mut game, [300_000, 300_000]);
+    assert!(game.to_pgn().contains("[TimeControl \"300\"]"));
+    let mut game = PgnGame::new(crate::ChessBoard::new());
+    set_tags(&mut game, [300_000, 1_500]);
+    let pgn = game.to_pgn();
+    assert!(pgn.contains("[WhiteTimeControl \"300\"]\n[BlackTimeControl \"1.5\"]"));
+    assert!(!pgn.contains("[TimeControl"));
+    assert_eq!(move_time(60_000), Duration::from_secs(2));
+}
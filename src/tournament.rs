@@ -0,0 +1,581 @@
+//! Tournaments between several engines: a round-robin, where everyone plays everyone; a Swiss,
+//! where each round pairs players on the same score who haven't met yet, ranked with Buchholz
+//! tiebreaks; or a knockout, where whoever loses a pairing is out. Every pairing is a short
+//! match like `match` plays, each opening once with either color, and the standings are printed
+//! after every round. A knockout pairing still tied after its tiebreaks can be settled with an
+//! Armageddon game.
+
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+
+use crate::config::Config;
+use crate::engine::EngineSpec;
+use crate::matches::{describe, play_match, read_openings, Player, Tally, OPENING_PLIES};
+use crate::net::random_u64;
+use crate::pgn::PgnGame;
+use crate::search::SearchParams;
+use crate::selfplay::{random_opening, Adjudication, Rng};
+use crate::ChessBoard;
+
+/// How many more pairs of games a tied knockout pairing plays before the higher seed goes
+/// through.
+const TIEBREAK_PAIRS: u32 = 2;
+
+/// How players are paired, as chosen with `--format`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    RoundRobin,
+    Swiss,
+    Knockout,
+}
+
+impl Format {
+    pub fn by_name(name: &str) -> Option<Format> {
+        match name {
+            "round-robin" => Some(Format::RoundRobin),
+            "swiss" => Some(Format::Swiss),
+            "knockout" => Some(Format::Knockout),
+            _ => None,
+        }
+    }
+}
+
+/// How every pairing is played: `games` games, searching at most `nodes` positions per move,
+/// adjudicated as `adjudication` says, up to `concurrency` at once, and as Armageddon if
+/// `armageddon`.
+#[derive(Clone, Copy, Debug)]
+pub struct Conditions {
+    pub games: u32,
+    pub nodes: u64,
+    pub concurrency: usize,
+    pub adjudication: Adjudication,
+    pub armageddon: bool,
+}
+
+/// Reads a player as `--player` takes it: `default` for the engine with its defaults, `uci:` and
+/// the command of an external UCI engine, or a configuration file whose `[search]` section the
+/// engine plays with.
+pub fn parse_player(text: &str) -> Result<Player, Error> {
+    let engine = match text.strip_prefix("uci:") {
+        Some(command) => EngineSpec::Uci {
+            command: command.trim().to_string(),
+            options: Vec::new(),
+        },
+        None if text == "default" => EngineSpec::Internal(SearchParams::default()),
+        None => EngineSpec::Internal(Config::load(Some(text))?.search),
+    };
+    Ok(Player {
+        name: text.strip_prefix("uci:").unwrap_or(text).trim().to_string(),
+        engine,
+    })
+}
+
+/// A player's record so far.
+#[derive(Clone, Debug, Default)]
+struct Standing {
+    points: f64,
+    /// Everyone played, in order.
+    opponents: Vec<usize>,
+    /// Whether the player has had a bye, which counts as a win.
+    bye: bool,
+    /// The round the player was knocked out in, if they have been.
+    out: Option<u32>,
+}
+
+/// The pairings of a round, by seed, the first of each taking white in the first game, and the
+/// player with a bye if there is one.
+type Pairings = (Vec<(usize, usize)>, Option<usize>);
+
+/// The rounds of a round-robin between `players` players, by the circle method: the first stays
+/// put while the others move round one place a round, and those opposite each other meet. With
+/// an odd number of players, whoever is opposite the empty place has a bye.
+fn round_robin(players: usize) -> Vec<Pairings> {
+    let places = players + players % 2;
+    (0..places.saturating_sub(1))
+        .map(|round| {
+            let mut circle = vec![0];
+            circle.extend((0..places - 1).map(|place| 1 + (place + round) % (places - 1)));
+            let mut pairings = (Vec::new(), None);
+            for place in 0..places / 2 {
+                let (first, second) = (circle[place], circle[places - 1 - place]);
+                match (first < players, second < players) {
+                    (true, true) if round % 2 == 1 => pairings.0.push((second, first)),
+                    (true, true) => pairings.0.push((first, second)),
+                    (true, false) => pairings.1 = Some(first),
+                    _ => pairings.1 = Some(second),
+                }
+            }
+            pairings
+        })
+        .collect()
+}
+
+/// The sum of the points of everyone `player` has played.
+fn buchholz(standings: &[Standing], player: usize) -> f64 {
+    standings[player]
+        .opponents
+        .iter()
+        .fold(0.0, |sum, &opponent| sum + standings[opponent].points)
+}
+
+/// The players from first to last: in a knockout, by how long they lasted; then by points, then
+/// by Buchholz in a Swiss, then by seed.
+fn ranking(standings: &[Standing], format: Format) -> Vec<usize> {
+    let mut order = (0..standings.len()).collect::<Vec<usize>>();
+    let key = |&player: &usize| {
+        let standing = &standings[player];
+        let lasted = match format {
+            Format::Knockout => standing.out.unwrap_or(u32::MAX) as f64,
+            _ => 0.0,
+        };
+        let tiebreak = match format {
+            Format::Swiss => buchholz(standings, player),
+            _ => 0.0,
+        };
+        (lasted, standing.points, tiebreak)
+    };
+    order.sort_by(|a, b| key(b).partial_cmp(&key(a)).unwrap().then(a.cmp(b)));
+    order
+}
+
+/// The next round of a Swiss. The lowest-ranked player who hasn't had a bye gets one if the
+/// number of players is odd; then, from the top down, each player meets the highest-ranked of
+/// those left whom they haven't met, or the highest-ranked left if they have met all of them.
+fn swiss(standings: &[Standing]) -> Pairings {
+    let mut left = ranking(standings, Format::RoundRobin);
+    let bye = match left.len() % 2 {
+        1 => {
+            let index = (0..left.len())
+                .rev()
+                .find(|&index| !standings[left[index]].bye)
+                .unwrap_or(left.len() - 1);
+            Some(left.remove(index))
+        }
+        _ => None,
+    };
+    let mut pairings = Vec::new();
+    while !left.is_empty() {
+        let player = left.remove(0);
+        let index = left
+            .iter()
+            .position(|opponent| !standings[player].opponents.contains(opponent))
+            .unwrap_or(0);
+        pairings.push((player, left.remove(index)));
+    }
+    (pairings, bye)
+}
+
+/// The order seeds are placed in a knockout bracket of `size` places, a power of two, so that
+/// the top seeds only meet in the last rounds: 1 against 8, 4 against 5, 3 against 6 and 2
+/// against 7 for eight.
+fn bracket(size: usize) -> Vec<usize> {
+    match size {
+        0 | 1 => vec![0],
+        _ => bracket(size / 2)
+            .into_iter()
+            .flat_map(|seed| [seed, size - 1 - seed])
+            .collect(),
+    }
+}
+
+/// Where the openings of each pairing come from: a suite, played in turn, or random ones.
+struct Openings {
+    suite: Vec<ChessBoard>,
+    next: usize,
+    rng: Rng,
+}
+
+impl Openings {
+    /// The openings of `pairs` pairs of games.
+    fn take(&mut self, pairs: u32) -> Vec<ChessBoard> {
+        (0..pairs)
+            .map(|_| match self.suite.is_empty() {
+                true => random_opening(OPENING_PLIES, &mut self.rng),
+                false => {
+                    self.next += 1;
+                    self.suite[(self.next - 1) % self.suite.len()]
+                }
+            })
+            .collect()
+    }
+}
+
+/// Plays a tournament between `players` in `format`, as `conditions` say, over `rounds` rounds
+/// if it is a Swiss, from `openings` (random ones if empty, chosen by `rng`). Every pairing of a
+/// round plays the same openings. Each game is passed to `on_game`, and the standings after
+/// each round to `on_round`. Returns the players from first to last.
+#[allow(clippy::too_many_arguments)]
+fn play_tournament(
+    players: &[Player],
+    format: Format,
+    rounds: u32,
+    conditions: &Conditions,
+    openings: Vec<ChessBoard>,
+    rng: Rng,
+    mut on_game: impl FnMut(&PgnGame) -> Result<(), Error>,
+    mut on_round: impl FnMut(u32, &str) -> Result<(), Error>,
+) -> Result<Vec<usize>, Error> {
+    let mut openings = Openings {
+        suite: openings,
+        next: 0,
+        rng,
+    };
+    let mut standings = vec![Standing::default(); players.len()];
+    let schedule = round_robin(players.len());
+    let size = players.len().next_power_of_two();
+    let mut alive = bracket(size)
+        .into_iter()
+        .map(|seed| (seed < players.len()).then_some(seed))
+        .collect::<Vec<Option<usize>>>();
+    let rounds = match format {
+        Format::RoundRobin => schedule.len() as u32,
+        Format::Swiss => rounds,
+        Format::Knockout => size.trailing_zeros(),
+    };
+    for round in 1..=rounds {
+        let (pairings, byes) = match format {
+            Format::RoundRobin => {
+                let (pairings, bye) = schedule[round as usize - 1].clone();
+                (pairings, Vec::from_iter(bye))
+            }
+            Format::Swiss => {
+                let (pairings, bye) = swiss(&standings);
+                (pairings, Vec::from_iter(bye))
+            }
+            Format::Knockout => {
+                let (mut pairings, mut byes) = (Vec::new(), Vec::new());
+                for places in alive.chunks(2) {
+                    match *places {
+                        [Some(first), Some(second)] => pairings.push((first, second)),
+                        [Some(player), None] | [None, Some(player)] => byes.push(player),
+                        _ => {}
+                    }
+                }
+                (pairings, byes)
+            }
+        };
+        let round_openings = openings.take(conditions.games.div_ceil(2));
+        let mut report = String::new();
+        for (first, second) in pairings {
+            let pair = [players[first].clone(), players[second].clone()];
+            let mut on_game = |game: &PgnGame| {
+                let mut game = game.clone();
+                game.set_tag("Event", "FreightChess tournament");
+                game.set_tag("Round", &round.to_string());
+                on_game(&game)
+            };
+            let mut tally = play_pairing(&pair, &round_openings, conditions, &mut on_game)?;
+            if format == Format::Knockout {
+                for _ in 0..TIEBREAK_PAIRS {
+                    if tally.wins != tally.losses {
+                        break;
+                    }
+                    let tiebreak = Conditions {
+                        games: 2,
+                        armageddon: false,
+                        ..*conditions
+                    };
+                    let more = play_pairing(&pair, &openings.take(1), &tiebreak, &mut on_game)?;
+                    tally.wins += more.wins;
+                    tally.draws += more.draws;
+                    tally.losses += more.losses;
+                }
+                // Then one Armageddon game, which can't be drawn, with the higher seed as white.
+                if tally.wins == tally.losses && conditions.armageddon {
+                    let armageddon = Conditions {
+                        games: 1,
+                        ..*conditions
+                    };
+                    let (seeded, swapped) = match first < second {
+                        true => (pair.clone(), false),
+                        false => ([pair[1].clone(), pair[0].clone()], true),
+                    };
+                    let more = play_pairing(&seeded, &openings.take(1), &armageddon, &mut on_game)?;
+                    let (wins, losses) = match swapped {
+                        true => (more.losses, more.wins),
+                        false => (more.wins, more.losses),
+                    };
+                    tally.wins += wins;
+                    tally.losses += losses;
+                }
+            }
+            standings[first].points += tally.points();
+            standings[second].points += tally.games() as f64 - tally.points();
+            standings[first].opponents.push(second);
+            standings[second].opponents.push(first);
+            report.push_str(&format!(
+                "{} {}-{} {}\n",
+                players[first].name,
+                tally.points(),
+                tally.games() as f64 - tally.points(),
+                players[second].name
+            ));
+            if format == Format::Knockout {
+                // A tie that the tiebreaks didn't settle goes to the higher seed.
+                let loser = match tally.wins > tally.losses
+                    || (tally.wins == tally.losses && first < second)
+                {
+                    true => second,
+                    false => first,
+                };
+                standings[loser].out = Some(round);
+                report.push_str(&format!("{} is out.\n", players[loser].name));
+            }
+        }
+        for &player in &byes {
+            standings[player].bye = true;
+            if format != Format::Knockout {
+                standings[player].points += conditions.games as f64;
+            }
+            report.push_str(&format!("{} has a bye.\n", players[player].name));
+        }
+        alive = alive
+            .chunks(2)
+            .map(|places| {
+                places
+                    .iter()
+                    .flatten()
+                    .copied()
+                    .find(|&player| standings[player].out.is_none())
+            })
+            .collect();
+        report.push_str(&table(players, &standings, format));
+        on_round(round, &report)?;
+    }
+    Ok(ranking(&standings, format))
+}
+
+/// Plays `conditions.games` games between the two `players` from `openings`.
+fn play_pairing(
+    players: &[Player; 2],
+    openings: &[ChessBoard],
+    conditions: &Conditions,
+    on_game: impl FnMut(&PgnGame) -> Result<(), Error>,
+) -> Result<Tally, Error> {
+    play_match(
+        players,
+        openings,
+        conditions.games,
+        conditions.nodes,
+        None,
+        conditions.armageddon,
+        &conditions.adjudication,
+        conditions.concurrency,
+        &mut Rng::new(1),
+        on_game,
+    )
+}
+
+/// The standings as a table: each player's place, name and points, with their Buchholz score in
+/// a Swiss, and whether they are out of a knockout.
+fn table(players: &[Player], standings: &[Standing], format: Format) -> String {
+    let width = players
+        .iter()
+        .map(|player| player.name.len())
+        .max()
+        .unwrap_or(0);
+    let mut table = String::new();
+    for (place, player) in ranking(standings, format).into_iter().enumerate() {
+        let standing = &standings[player];
+        table.push_str(&format!(
+            "{:>3}. {:<width$} {:>5.1}",
+            place + 1,
+            players[player].name,
+            standing.points,
+            width = width
+        ));
+        match format {
+            Format::Swiss => {
+                table.push_str(&format!("  Buchholz {:.1}", buchholz(standings, player)))
+            }
+            Format::Knockout if standing.out.is_some() => table.push_str("  out"),
+            _ => {}
+        }
+        table.push('\n');
+    }
+    table
+}
+
+/// Runs `tournament`: plays a tournament between `players`, seeded in order, in `format`, over
+/// `rounds` rounds if it is a Swiss (or as many as it takes to tell the players apart), as
+/// `conditions` say. Openings come from the suite in the file `openings`, or else are random
+/// ones chosen by `seed` (or at random). Prints every game and the standings after every round,
+/// and writes the games to the PGN file `pgn` if given.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    mut players: Vec<Player>,
+    format: Format,
+    rounds: Option<u32>,
+    conditions: Conditions,
+    openings: Option<&str>,
+    seed: Option<u64>,
+    pgn: Option<&str>,
+) -> Result<(), Error> {
+    if players.len() < 2 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "A tournament needs at least two players.",
+        ));
+    }
+    // Players entered more than once are told apart by number.
+    let names: Vec<String> = players.iter().map(|p| p.name.clone()).collect();
+    for (i, player) in players.iter_mut().enumerate().skip(1) {
+        let entered = names[..i].iter().filter(|&name| *name == names[i]).count();
+        if entered > 0 {
+            player.name = format!("{} {}", names[i], entered + 1);
+        }
+    }
+    let openings = match openings {
+        Some(path) => read_openings(&std::fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+    let rounds = rounds.unwrap_or(players.len().next_power_of_two().trailing_zeros());
+    let rng = Rng::new(seed.unwrap_or_else(random_u64));
+    let mut output = pgn.map(File::create).transpose()?.map(BufWriter::new);
+    let ranking = play_tournament(
+        &players,
+        format,
+        rounds,
+        &conditions,
+        openings,
+        rng,
+        |game| {
+            println!("Game {}", describe(game));
+            match &mut output {
+                Some(output) => writeln!(output, "{}", game.to_pgn()),
+                None => Ok(()),
+            }
+        },
+        |round, report| {
+            println!("\nAfter round {}:\n{}", round, report);
+            Ok(())
+        },
+    )?;
+    if let Some(output) = &mut output {
+        output.flush()?;
+    }
+    println!("{} wins the tournament.", players[ranking[0]].name);
+    Ok(())
+}
+
+#[test]
+fn test_tournament() {
+    // Everyone meets everyone once, and with five players each sits out once.
+    for players in [2, 4, 5, 6] {
+        let rounds = round_robin(players);
+        assert_eq!(rounds.len(), players - 1 + players % 2);
+        let mut met = Vec::new();
+        for (pairings, bye) in &rounds {
+            assert_eq!(pairings.len(), players / 2);
+            assert_eq!(bye.is_some(), players % 2 == 1);
+            met.extend(pairings.iter().map(|&(a, b)| (a.min(b), a.max(b))));
+        }
+        met.sort();
+        met.dedup();
+        assert_eq!(met.len(), players * (players - 1) / 2);
+    }
+
+    // The leaders meet, avoiding a rematch; the last without a bye sits out.
+    let mut standings = vec![Standing::default(); 5];
+    for (player, points, opponents) in [(0, 2.0, [1]), (1, 0.0, [0]), (2, 2.0, [3]), (3, 0.0, [2])]
+    {
+        standings[player].points = points;
+        standings[player].opponents = opponents.to_vec();
+    }
+    standings[4] = Standing {
+        points: 2.0,
+        bye: true,
+        ..Standing::default()
+    };
+    assert_eq!(swiss(&standings), (vec![(0, 2), (4, 1)], Some(3)));
+    standings[0].opponents.push(2);
+    assert_eq!(swiss(&standings), (vec![(0, 4), (2, 1)], Some(3)));
+    assert_eq!(buchholz(&standings, 0), 2.0);
+    assert_eq!(ranking(&standings, Format::Swiss)[..3], [0, 2, 4]);
+
+    assert_eq!(bracket(8), [0, 7, 3, 4, 1, 6, 2, 5]);
+    assert_eq!(Format::by_name("swiss"), Some(Format::Swiss));
+    assert_eq!(
+        parse_player("uci:stockfish --flag").unwrap().engine,
+        EngineSpec::Uci {
+            command: "stockfish --flag".to_string(),
+            options: Vec::new()
+        }
+    );
+
+    // A knockout of three: the top seed has a bye, then meets the winner of the other two.
+    let players = ["a", "b", "c"]
+        .map(|name| Player {
+            name: name.to_string(),
+            engine: EngineSpec::Internal(SearchParams::default()),
+        })
+        .to_vec();
+    let conditions = Conditions {
+        games: 2,
+        nodes: 200,
+        concurrency: 1,
+        adjudication: Adjudication {
+            max_moves: 5,
+            ..Adjudication::default()
+        },
+        armageddon: false,
+    };
+    let (mut games, mut reports) = (Vec::new(), Vec::new());
+    let ranking = play_tournament(
+        &players,
+        Format::Knockout,
+        0,
+        &conditions,
+        Vec::new(),
+        Rng::new(3),
+        |game| {
+            games.push(game.to_pgn());
+            Ok(())
+        },
+        |round, report| {
+            reports.push((round, report.to_string()));
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(reports.len(), 2);
+    assert!(reports[0].1.contains("a has a bye."));
+    // Drawn games are tied, and the tiebreaks don't settle them either at this length.
+    assert!(reports[0].1.contains("b 3-3 c\nc is out."));
+    assert!(reports[1].1.contains("a 3-3 b\nb is out."));
+    assert_eq!(ranking[0], 0);
+    assert_eq!(games.len(), 12);
+    assert!(games[0].contains("[Event \"FreightChess tournament\"]\n"));
+    assert!(games[11].contains("[Round \"2\"]"));
+
+    // With Armageddon, the higher seed is white in one more game, and loses the pairing by
+    // drawing it.
+    let conditions = Conditions {
+        armageddon: true,
+        ..conditions
+    };
+    let (mut games, mut reports) = (Vec::new(), Vec::new());
+    let ranking = play_tournament(
+        &players,
+        Format::Knockout,
+        0,
+        &conditions,
+        Vec::new(),
+        Rng::new(3),
+        |game| {
+            games.push(game.to_pgn());
+            Ok(())
+        },
+        |round, report| {
+            reports.push((round, report.to_string()));
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert!(reports[0].1.contains("b 3-4 c\nb is out."));
+    assert!(reports[1].1.contains("a 3-4 c\na is out."));
+    assert_eq!(ranking[0], 2);
+    assert_eq!(games.len(), 14);
+    assert!(games[6].contains("[White \"b\"]\n[Black \"c\"]"));
+    assert!(games[6].contains("[Result \"0-1\"]"));
+}
@@ -0,0 +1,432 @@
+//! Games as trees of moves: the main line, with the variations that branch off it and what was
+//! written about each move, as read from and written to annotated PGN.
+
+use crate::annotation::Annotations;
+use crate::movegen::Move;
+use crate::ChessBoard;
+
+/// A move in a game tree and what was written about it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub mv: Move,
+    /// Numeric annotation glyphs, such as 1 for `!` and 2 for `?`.
+    pub nags: Vec<u8>,
+    /// A comment written before the move, such as one opening a variation.
+    pub comment_before: Option<String>,
+    /// The comment written after the move.
+    pub comment: Option<String>,
+    /// The arrows and squares drawn on the position after the move, written into its comment.
+    pub annotations: Annotations,
+    /// Lines played instead of this move, each starting from the position before it.
+    pub variations: Vec<Vec<Node>>,
+}
+
+impl Node {
+    pub fn new(mv: Move) -> Node {
+        Node {
+            mv,
+            nags: Vec::new(),
+            comment_before: None,
+            comment: None,
+            annotations: Annotations::default(),
+            variations: Vec::new(),
+        }
+    }
+}
+
+/// A position in a game tree: after the first `moves` moves of a line. The line is the main
+/// line, or the variation reached from it through `variations`: for each, the index (in the
+/// line so far) of the move the variation replaces, and which of that move's variations it is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cursor {
+    pub variations: Vec<(usize, usize)>,
+    pub moves: usize,
+}
+
+/// A game's moves from its starting position: the main line, and any variations.
+#[derive(Clone)]
+pub struct GameTree {
+    pub start: ChessBoard,
+    pub mainline: Vec<Node>,
+}
+
+impl GameTree {
+    pub fn new(start: ChessBoard) -> GameTree {
+        GameTree {
+            start,
+            mainline: Vec::new(),
+        }
+    }
+
+    /// The moves of the main line.
+    pub fn moves(&self) -> Vec<Move> {
+        self.mainline.iter().map(|node| node.mv).collect()
+    }
+
+    /// Plays `mv` at the end of the main line.
+    pub fn push(&mut self, mv: Move) {
+        self.mainline.push(Node::new(mv));
+    }
+
+    /// The position at the end of the main line.
+    pub fn board(&self) -> ChessBoard {
+        let mut board = self.start;
+        for node in &self.mainline {
+            board.apply_move(node.mv);
+        }
+        board
+    }
+
+    fn line(&self, variations: &[(usize, usize)]) -> Option<&Vec<Node>> {
+        let mut line = &self.mainline;
+        for &(index, variation) in variations {
+            line = line.get(index)?.variations.get(variation)?;
+        }
+        Some(line)
+    }
+
+    fn line_mut(&mut self, variations: &[(usize, usize)]) -> Option<&mut Vec<Node>> {
+        let mut line = &mut self.mainline;
+        for &(index, variation) in variations {
+            line = line.get_mut(index)?.variations.get_mut(variation)?;
+        }
+        Some(line)
+    }
+
+    /// The same position as `cursor`, but never at the start of a variation: that is the
+    /// position the variation branched off from.
+    fn canonical(cursor: &Cursor) -> Cursor {
+        let mut cursor = cursor.clone();
+        while cursor.moves == 0 {
+            match cursor.variations.pop() {
+                Some((index, _)) => cursor.moves = index,
+                None => break,
+            }
+        }
+        cursor
+    }
+
+    /// The moves played from the start to reach `cursor`, or `None` if it isn't in the tree.
+    pub fn moves_to(&self, cursor: &Cursor) -> Option<Vec<Move>> {
+        let mut moves = Vec::new();
+        let mut line = &self.mainline;
+        for &(index, variation) in &cursor.variations {
+            moves.extend(line.get(..index)?.iter().map(|node| node.mv));
+            line = line.get(index)?.variations.get(variation)?;
+        }
+        moves.extend(line.get(..cursor.moves)?.iter().map(|node| node.mv));
+        Some(moves)
+    }
+
+    /// The position at `cursor`.
+    pub fn board_at(&self, cursor: &Cursor) -> Option<ChessBoard> {
+        let mut board = self.start;
+        for mv in self.moves_to(cursor)? {
+            board.apply_move(mv);
+        }
+        Some(board)
+    }
+
+    /// The move played to reach `cursor`, unless it is the start of the game.
+    pub fn node(&self, cursor: &Cursor) -> Option<&Node> {
+        self.line(&cursor.variations)?
+            .get(cursor.moves.checked_sub(1)?)
+    }
+
+    pub fn node_mut(&mut self, cursor: &Cursor) -> Option<&mut Node> {
+        self.line_mut(&cursor.variations)?
+            .get_mut(cursor.moves.checked_sub(1)?)
+    }
+
+    /// The moves that can be played from `cursor` and where they lead: the line's next move
+    /// first, then the variations replacing it.
+    pub fn continuations(&self, cursor: &Cursor) -> Vec<(Move, Cursor)> {
+        let cursor = GameTree::canonical(cursor);
+        let next = match self
+            .line(&cursor.variations)
+            .and_then(|line| line.get(cursor.moves))
+        {
+            Some(next) => next,
+            None => return Vec::new(),
+        };
+        let mut continuations = vec![(
+            next.mv,
+            Cursor {
+                variations: cursor.variations.clone(),
+                moves: cursor.moves + 1,
+            },
+        )];
+        for (i, variation) in next.variations.iter().enumerate() {
+            let mut variations = cursor.variations.clone();
+            variations.push((cursor.moves, i));
+            continuations.push((
+                variation[0].mv,
+                Cursor {
+                    variations,
+                    moves: 1,
+                },
+            ));
+        }
+        continuations
+    }
+
+    /// One move further along the line `cursor` is in.
+    pub fn forward(&self, cursor: &Cursor) -> Option<Cursor> {
+        let mut forward = GameTree::canonical(cursor);
+        let line = self.line(&forward.variations)?;
+        (forward.moves < line.len()).then(|| {
+            forward.moves += 1;
+            forward
+        })
+    }
+
+    /// One move back, leaving a variation for the line it branched off from when at its start.
+    pub fn back(&self, cursor: &Cursor) -> Option<Cursor> {
+        let mut back = GameTree::canonical(cursor);
+        match back.moves {
+            0 => return None,
+            1 if !back.variations.is_empty() => {
+                let (index, _) = back.variations.pop()?;
+                back.moves = index;
+            }
+            _ => back.moves -= 1,
+        }
+        Some(back)
+    }
+
+    /// The end of the line `cursor` is in.
+    pub fn end_of_line(&self, cursor: &Cursor) -> Option<Cursor> {
+        let mut end = GameTree::canonical(cursor);
+        end.moves = self.line(&end.variations)?.len();
+        Some(end)
+    }
+
+    /// Plays `mv` from `cursor`, returning where it leads. A move already in the tree is
+    /// followed; a new move extends the line at its end, or starts a new variation.
+    pub fn add_move(&mut self, cursor: &Cursor, mv: Move) -> Option<Cursor> {
+        let cursor = GameTree::canonical(cursor);
+        if let Some((_, next)) = self
+            .continuations(&cursor)
+            .into_iter()
+            .find(|&(next, _)| next == mv)
+        {
+            return Some(next);
+        }
+        let line = self.line_mut(&cursor.variations)?;
+        let mut added = cursor.clone();
+        if cursor.moves == line.len() {
+            line.push(Node::new(mv));
+            added.moves += 1;
+        } else {
+            let next = line.get_mut(cursor.moves)?;
+            next.variations.push(vec![Node::new(mv)]);
+            added
+                .variations
+                .push((cursor.moves, next.variations.len() - 1));
+            added.moves = 1;
+        }
+        Some(added)
+    }
+
+    /// Plays `mv` from `cursor` on the main line, returning where it leads there. A move other
+    /// than the main line's next one takes its place, and the moves it replaces become its
+    /// first variation, so that going back and playing something else keeps the old line.
+    pub fn play(&mut self, cursor: &Cursor, mv: Move) -> Option<Cursor> {
+        let played = self.add_move(cursor, mv)?;
+        match played.variations.is_empty() {
+            true => Some(played),
+            false => self.promote(&played),
+        }
+    }
+
+    /// Where the main line reaches the position `moves` half-moves into the game, counted as
+    /// `ChessBoard::moves` counts them, if it does.
+    pub fn find_on_mainline(&self, moves: u16) -> Option<Cursor> {
+        let moves = usize::from(moves.checked_sub(self.start.moves)?);
+        (moves <= self.mainline.len()).then(|| Cursor {
+            variations: Vec::new(),
+            moves,
+        })
+    }
+
+    /// Swaps the variation `cursor` is in with the line it branches off from, so it becomes the
+    /// main continuation and the old one its first variation. Returns where `cursor`'s position
+    /// now is.
+    pub fn promote(&mut self, cursor: &Cursor) -> Option<Cursor> {
+        let cursor = GameTree::canonical(cursor);
+        let mut promoted = cursor.clone();
+        let (index, variation) = promoted.variations.pop()?;
+        let line = self.line_mut(&promoted.variations)?;
+        if line.get(index)?.variations.len() <= variation {
+            return None;
+        }
+        let mut old = line.split_off(index);
+        let mut others = std::mem::take(&mut old[0].variations);
+        let mut new = others.remove(variation);
+        let own = std::mem::take(&mut new[0].variations);
+        new[0].variations = std::iter::once(old).chain(others).chain(own).collect();
+        line.extend(new);
+        promoted.moves = index + cursor.moves;
+        Some(promoted)
+    }
+
+    /// Deletes the variation `cursor` is in, returning the position it branched off from. The
+    /// main line can't be deleted.
+    pub fn delete_variation(&mut self, cursor: &Cursor) -> Option<Cursor> {
+        let mut parent = GameTree::canonical(cursor);
+        let (index, variation) = parent.variations.pop()?;
+        let variations = &mut self
+            .line_mut(&parent.variations)?
+            .get_mut(index)?
+            .variations;
+        if variation >= variations.len() {
+            return None;
+        }
+        variations.remove(variation);
+        parent.moves = index;
+        Some(parent)
+    }
+
+    /// Deletes the moves after `cursor` in its line, with their variations.
+    pub fn truncate(&mut self, cursor: &Cursor) {
+        let cursor = GameTree::canonical(cursor);
+        if let Some(line) = self.line_mut(&cursor.variations) {
+            line.truncate(cursor.moves);
+        }
+    }
+
+    /// Every line of the tree followed from the start to its end: each variation, in the order
+    /// they end in PGN, then the main line.
+    pub fn lines(&self) -> Vec<Vec<Move>> {
+        fn collect(prefix: &[Move], line: &[Node], lines: &mut Vec<Vec<Move>>) {
+            let mut moves = prefix.to_vec();
+            for node in line {
+                for variation in &node.variations {
+                    collect(&moves, variation, lines);
+                }
+                moves.push(node.mv);
+            }
+            if !moves.is_empty() && !lines.contains(&moves) {
+                lines.push(moves);
+            }
+        }
+        let mut lines = Vec::new();
+        collect(&[], &self.mainline, &mut lines);
+        lines
+    }
+}
+
+#[test]
+fn test_tree() {
+    let game = crate::pgn::PgnGame::parse(
+        "1. e4 e5 (1... c5 2. Nf3 (2. c3) d6) 2. Nf3 {main} (2. Bc4) Nc6 *",
+    )
+    .unwrap();
+    let mut tree = game.tree;
+    let sans = |moves: &[Move]| ChessBoard::new().line_sans(moves).join(" ");
+    let lines = tree.lines();
+    let lines = lines.iter().map(|line| sans(line)).collect::<Vec<String>>();
+    assert_eq!(
+        lines,
+        ["e4 c5 c3", "e4 c5 Nf3 d6", "e4 e5 Bc4", "e4 e5 Nf3 Nc6"]
+    );
+
+    // Navigating into a variation and out again.
+    let start = Cursor::default();
+    assert_eq!(tree.back(&start), None);
+    let after_e4 = tree.forward(&start).unwrap();
+    let continuations = tree.continuations(&after_e4);
+    let replies = continuations
+        .iter()
+        .map(|(mv, _)| *mv)
+        .collect::<Vec<Move>>();
+    assert_eq!(sans(&[tree.mainline[0].mv, replies[1]]), "e4 c5");
+    let sicilian = continuations[1].1.clone();
+    let c3 = tree.continuations(&sicilian)[1].1.clone();
+    assert_eq!(sans(&tree.moves_to(&c3).unwrap()), "e4 c5 c3");
+    let back = tree.back(&c3).unwrap();
+    assert_eq!(sans(&tree.moves_to(&back).unwrap()), "e4 c5");
+    assert_eq!(tree.back(&sicilian), Some(after_e4.clone()));
+    let end = tree.end_of_line(&sicilian).unwrap();
+    assert_eq!(sans(&tree.moves_to(&end).unwrap()), "e4 c5 Nf3 d6");
+    let before_end = tree.board_at(&tree.back(&end).unwrap()).unwrap();
+    assert_eq!(
+        tree.node(&end).unwrap().mv,
+        before_end.parse_move("d6").unwrap()
+    );
+
+    // Moves already in the tree are followed; new ones start variations or extend lines.
+    let board = tree.board_at(&after_e4).unwrap();
+    let c5 = board.parse_move("c5").unwrap();
+    assert_eq!(tree.add_move(&after_e4, c5), Some(sicilian.clone()));
+    let e6 = tree
+        .add_move(&after_e4, board.parse_move("e6").unwrap())
+        .unwrap();
+    assert_eq!(e6.variations, [(1, 1)]);
+    let final_position = tree.end_of_line(&start).unwrap();
+    let bc4 = tree
+        .board_at(&final_position)
+        .unwrap()
+        .parse_move("Bc4")
+        .unwrap();
+    let extended = tree.add_move(&final_position, bc4).unwrap();
+    assert_eq!(extended.moves, 5);
+
+    // Promoting the Sicilian makes it the main line, with 1... e5 its first variation.
+    let d6 = tree.end_of_line(&sicilian).unwrap();
+    let promoted = tree.promote(&d6).unwrap();
+    assert_eq!(
+        promoted,
+        Cursor {
+            variations: Vec::new(),
+            moves: 4
+        }
+    );
+    assert_eq!(sans(&tree.moves()), "e4 c5 Nf3 d6");
+    let replies = tree.continuations(&after_e4);
+    assert_eq!(replies.len(), 3);
+    assert_eq!(
+        sans(
+            &tree
+                .moves_to(&tree.end_of_line(&replies[1].1).unwrap())
+                .unwrap()
+        ),
+        "e4 e5 Nf3 Nc6 Bc4"
+    );
+    assert_eq!(tree.promote(&promoted), None);
+
+    // Deleting a variation, and the end of a line.
+    let french = tree.continuations(&after_e4)[2].1.clone();
+    assert_eq!(tree.delete_variation(&french), Some(after_e4.clone()));
+    assert_eq!(tree.continuations(&after_e4).len(), 2);
+    assert_eq!(tree.delete_variation(&promoted), None);
+    tree.truncate(&tree.forward(&after_e4).unwrap());
+    assert_eq!(sans(&tree.moves()), "e4 c5");
+    assert!(tree.node_mut(&start).is_none());
+
+    // Going back along the main line and playing on keeps the old continuation as a variation.
+    let mut tree = GameTree::new(ChessBoard::new());
+    let mut cursor = start.clone();
+    for san in ["e4", "e5", "Nf3"] {
+        let mv = tree.board_at(&cursor).unwrap().parse_move(san).unwrap();
+        cursor = tree.play(&cursor, mv).unwrap();
+    }
+    let after_e4 = tree.find_on_mainline(1).unwrap();
+    assert_eq!(tree.find_on_mainline(3), Some(cursor));
+    assert_eq!(tree.find_on_mainline(4), None);
+    let e5 = tree.mainline[1].mv;
+    assert_eq!(tree.play(&after_e4, e5), tree.find_on_mainline(2));
+    let c5 = tree.board_at(&after_e4).unwrap().parse_move("c5").unwrap();
+    let sicilian = tree.play(&after_e4, c5).unwrap();
+    assert!(sicilian.variations.is_empty());
+    assert_eq!(sans(&tree.moves()), "e4 c5");
+    assert_eq!(sans(&tree.lines()[0]), "e4 e5 Nf3");
+    assert_eq!(tree.play(&after_e4, e5), tree.find_on_mainline(2));
+    assert_eq!(sans(&tree.moves()), "e4 e5 Nf3");
+    let later = ChessBoard::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 30").unwrap();
+    assert_eq!(
+        GameTree::new(later).find_on_mainline(59),
+        Some(Cursor::default())
+    );
+    assert_eq!(GameTree::new(later).find_on_mainline(58), None);
+}
@@ -0,0 +1,451 @@
+//! The transposition table: what the search found about each position it searched, by the
+//! position's Zobrist key, so a position reached again (by another move order, or in the next
+//! iteration) can reuse it instead of being searched again.
+//!
+//! Entries are packed into 16 bytes and kept four to a cluster, which fills one 64-byte cache
+//! line, so a probe reads memory once. A key picks a cluster, and its position may be in any of
+//! the four slots; a new position takes an empty slot or the one searched least deep. Probes
+//! mostly miss the processor's caches, so the search asks for a position's cluster as soon as it
+//! knows the key, with `prefetch`, and does other work while it arrives.
+//!
+//! Threads searching together share one table, so a slot may be written by one while another
+//! reads it. By default nothing is locked: a slot keeps its key XORed with its data, each in an
+//! atomic word, and a read that catches half of one write and half of another finds a key that
+//! doesn't match and so misses, as if the position weren't there. The `Locked` strategy instead
+//! locks the cluster around every probe and store, which is slower but easier to trust, so
+//! `bench` can compare the two.
+//!
+//! A table can be saved to a file and loaded again later, so a long analysis of a position can
+//! pick up where it left off. The file starts with `MAGIC`, then the number of entries, then the
+//! entries, 16 little-endian bytes each: the key, the score, the depth, the bound, and the best
+//! move packed into 16 bits.
+
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::movegen::Move;
+use crate::search::MATE;
+
+/// How big a table is, in megabytes, when nothing says otherwise.
+pub const DEFAULT_HASH_MB: usize = 16;
+
+/// What a saved table starts with.
+const MAGIC: &[u8; 8] = b"FCHASH01";
+
+/// How a stored score relates to the position's true score.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+    /// The score is the true score.
+    Exact,
+    /// The position caused a cutoff, so its true score is at least the score.
+    Lower,
+    /// No move beat alpha, so the true score is at most the score.
+    Upper,
+}
+
+/// What the search found about one position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Entry {
+    key: u64,
+    /// The score, with mates counted from the position rather than from the root.
+    score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+}
+
+impl Entry {
+    /// The stored score as seen `ply` plies from the root.
+    pub fn score(&self, ply: u32) -> i32 {
+        match self.score {
+            score if score > MATE - 1000 => score - ply as i32,
+            score if score < -MATE + 1000 => score + ply as i32,
+            score => score,
+        }
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        let bound = match self.bound {
+            Bound::Exact => 0u8,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        bytes.extend(self.key.to_le_bytes());
+        bytes.extend((self.score as i16).to_le_bytes());
+        bytes.extend([self.depth.min(255) as u8, bound]);
+        bytes.extend(pack_move(self.best_move).to_le_bytes());
+        bytes.extend([0; 2]);
+    }
+
+    fn read(bytes: &[u8]) -> Option<Entry> {
+        Some(Entry {
+            key: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            score: i16::from_le_bytes([bytes[8], bytes[9]]) as i32,
+            depth: bytes[10] as u32,
+            bound: match bytes[11] {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                2 => Bound::Upper,
+                _ => return None,
+            },
+            best_move: unpack_move(u16::from_le_bytes([bytes[12], bytes[13]])),
+        })
+    }
+
+    /// Packs everything but the key into a slot's data: the score in the low 16 bits, then the
+    /// depth, then the bound (never 0, which marks an empty slot), then the best move.
+    fn data(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 1u64,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+        (self.score as i16 as u16 as u64)
+            | (self.depth.min(255) as u64) << 16
+            | bound << 24
+            | (pack_move(self.best_move) as u64) << 32
+    }
+
+    /// The entry for `key` packed into `data`, if the slot isn't empty.
+    fn unpack(key: u64, data: u64) -> Option<Entry> {
+        let bound = match data >> 24 & 3 {
+            0 => return None,
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            _ => Bound::Upper,
+        };
+        Some(Entry {
+            key,
+            score: data as u16 as i16 as i32,
+            depth: (data >> 16 & 255) as u32,
+            bound,
+            best_move: unpack_move((data >> 32) as u16),
+        })
+    }
+}
+
+/// A move in 16 bits: the top bit set if there is one, then the promotion piece and the squares.
+fn pack_move(mv: Option<Move>) -> u16 {
+    mv.map_or(0, |mv| {
+        1 << 15 | (mv.promotion as u16 & 7) << 12 | (mv.from as u16) << 6 | mv.to as u16
+    })
+}
+
+fn unpack_move(packed: u16) -> Option<Move> {
+    match packed >> 15 {
+        0 => None,
+        _ => Some(Move {
+            from: (packed >> 6 & 63) as u8,
+            to: (packed & 63) as u8,
+            promotion: (packed >> 12 & 7) as u8,
+        }),
+    }
+}
+
+/// One entry, packed by `Entry::data`, with the key XORed with the data so a slot written by
+/// two threads at once reads as empty rather than as one position's key and another's data.
+#[derive(Default)]
+#[repr(C)]
+struct Slot {
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    /// The key and data in the slot, which are both 0 if it is empty.
+    fn read(&self) -> (u64, u64) {
+        let data = self.data.load(Ordering::Relaxed);
+        (self.check.load(Ordering::Relaxed) ^ data, data)
+    }
+
+    fn write(&self, key: u64, data: u64) {
+        self.check.store(key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+/// How many slots share a cluster.
+const CLUSTER_SLOTS: usize = 4;
+
+/// The slots a key may go in, filling a cache line.
+#[derive(Default)]
+#[repr(C, align(64))]
+struct Cluster([Slot; CLUSTER_SLOTS]);
+
+/// How threads sharing a table keep from reading a slot while another writes it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// Nothing is locked, and a torn slot fails its XOR check and reads as empty.
+    Lockless,
+    /// Each cluster has a lock, held while it is probed or stored to.
+    Locked,
+}
+
+impl Strategy {
+    /// The strategy called `name` on the command line.
+    pub fn by_name(name: &str) -> Option<Strategy> {
+        match name {
+            "lockless" => Some(Strategy::Lockless),
+            "locked" => Some(Strategy::Locked),
+            _ => None,
+        }
+    }
+}
+
+/// A fixed number of clusters of entries, each position going in the cluster its key picks. A
+/// new entry takes the place of the same position unless that was searched deeper, or else of
+/// an empty slot or the one searched least deep. Any number of threads can probe and store at
+/// once.
+pub struct TranspositionTable {
+    clusters: Vec<Cluster>,
+    /// A lock for each cluster with the `Locked` strategy, and none without.
+    locks: Vec<Mutex<()>>,
+}
+
+impl TranspositionTable {
+    /// An empty table taking up about `megabytes` megabytes, shared without locks.
+    pub fn new(megabytes: usize) -> TranspositionTable {
+        TranspositionTable::with_strategy(megabytes, Strategy::Lockless)
+    }
+
+    /// An empty table taking up about `megabytes` megabytes (and its locks, if any, besides),
+    /// shared by `strategy`.
+    pub fn with_strategy(megabytes: usize, strategy: Strategy) -> TranspositionTable {
+        let count = ((megabytes << 20) / std::mem::size_of::<Cluster>()).max(1);
+        let locks = match strategy {
+            Strategy::Lockless => 0,
+            Strategy::Locked => count,
+        };
+        TranspositionTable {
+            clusters: (0..count).map(|_| Cluster::default()).collect(),
+            locks: (0..locks).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    /// Forgets every position.
+    pub fn clear(&mut self) {
+        for slot in self.clusters.iter_mut().flat_map(|cluster| &mut cluster.0) {
+            *slot = Slot::default();
+        }
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.clusters.len() as u64) as usize
+    }
+
+    /// Locks the cluster at `index`, if the table has locks.
+    fn lock(&self, index: usize) -> Option<MutexGuard<'_, ()>> {
+        let lock = self.locks.get(index)?;
+        // Nothing a lock guards can be left half done, so one poisoned by a panic is still good.
+        Some(lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+
+    /// Asks the processor to start loading the cluster of the position with Zobrist key `key`
+    /// into its cache, so a `probe` soon after needn't wait for memory.
+    pub fn prefetch(&self, key: u64) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            let cluster = &self.clusters[self.index(key)] as *const Cluster as *const i8;
+            // Prefetching only hints at what to load, and every x86-64 processor has SSE.
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(cluster) };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = key;
+    }
+
+    /// What is known about the position with Zobrist key `key`.
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let index = self.index(key);
+        let _lock = self.lock(index);
+        let (_, data) = self.clusters[index]
+            .0
+            .iter()
+            .map(Slot::read)
+            .find(|&(slot, data)| slot == key && data != 0)?;
+        Entry::unpack(key, data)
+    }
+
+    /// Notes that the position with Zobrist key `key`, `ply` plies from the root, scored `score`
+    /// when searched to `depth`, with `best_move` the best move found.
+    pub fn store(
+        &self,
+        key: u64,
+        ply: u32,
+        depth: u32,
+        score: i32,
+        bound: Bound,
+        best_move: Option<Move>,
+    ) {
+        let score = match score {
+            score if score > MATE - 1000 => score + ply as i32,
+            score if score < -MATE + 1000 => score - ply as i32,
+            score => score,
+        };
+        self.insert(Entry {
+            key,
+            score,
+            depth,
+            bound,
+            best_move,
+        });
+    }
+
+    fn insert(&self, entry: Entry) {
+        let index = self.index(entry.key);
+        let _lock = self.lock(index);
+        let slots = self.clusters[index].0.each_ref().map(Slot::read);
+        let depth = |(key, data)| Entry::unpack(key, data).map_or(-1, |e| e.depth as i32);
+        let slot = match slots
+            .iter()
+            .position(|&(key, data)| key == entry.key && data != 0)
+        {
+            Some(same) if depth(slots[same]) > entry.depth as i32 => return,
+            Some(same) => same,
+            None => (0..CLUSTER_SLOTS).min_by_key(|&i| depth(slots[i])).unwrap(),
+        };
+        self.clusters[index].0[slot].write(entry.key, entry.data());
+    }
+
+    /// Every position the table holds.
+    fn entries(&self) -> impl Iterator<Item = Entry> + '_ {
+        self.clusters
+            .iter()
+            .flat_map(|cluster| &cluster.0)
+            .filter_map(|slot| {
+                let (key, data) = slot.read();
+                Entry::unpack(key, data)
+            })
+    }
+
+    /// How many positions the table holds.
+    pub fn positions(&self) -> usize {
+        self.entries().count()
+    }
+
+    /// Writes the table to the file at `path`.
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((self.positions() as u64).to_le_bytes());
+        for entry in self.entries() {
+            entry.write(&mut bytes);
+        }
+        std::fs::write(path, bytes)
+    }
+
+    /// Adds the positions of the table saved at `path`, returning how many there were. They are
+    /// stored as if they had just been searched, so a table of another size can load them.
+    pub fn load(&mut self, path: &str) -> Result<usize, Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+        let bytes = std::fs::read(path)?;
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            return Err(invalid("This isn't a saved hash table."));
+        };
+        if rest.len() < 8 {
+            return Err(invalid("The hash file is cut short."));
+        }
+        let (count, rest) = rest.split_at(8);
+        let count = u64::from_le_bytes(count.try_into().unwrap()) as usize;
+        if rest.len() != count.saturating_mul(16) {
+            return Err(invalid(
+                "The hash file doesn't hold as many entries as it says.",
+            ));
+        }
+        for bytes in rest.chunks_exact(16) {
+            let entry = Entry::read(bytes).ok_or_else(|| invalid("The hash file is corrupt."))?;
+            self.insert(entry);
+        }
+        Ok(count)
+    }
+}
+
+#[test]
+fn test_tt() {
+    let mut table = TranspositionTable::new(1);
+    let mv = Move {
+        from: 52,
+        to: 60,
+        promotion: crate::KNIGHT,
+    };
+    table.store(1, 3, 5, 42, Bound::Lower, Some(mv));
+    table.store(2, 4, 1, MATE - 9, Bound::Exact, None);
+    assert_eq!(table.probe(1).unwrap().best_move, Some(mv));
+    assert_eq!(table.probe(1).unwrap().score(0), 42);
+    assert_eq!(table.probe(3), None);
+    // Mates are stored from the position, so they are found just as far off from elsewhere.
+    assert_eq!(table.probe(2).unwrap().score(4), MATE - 9);
+    assert_eq!(table.probe(2).unwrap().score(2), MATE - 7);
+
+    // A shallower search doesn't replace a deeper one of the same position.
+    table.store(1, 3, 2, -7, Bound::Upper, None);
+    assert_eq!(table.probe(1).unwrap().depth, 5);
+
+    // Saved and loaded, into a table of another size.
+    let path = std::env::temp_dir().join(format!("freight_chess_tt_{}.hash", std::process::id()));
+    let path = path.to_str().unwrap();
+    table.save(path).unwrap();
+    let mut loaded = TranspositionTable::new(2);
+    assert_eq!(loaded.load(path).unwrap(), 2);
+    assert_eq!(loaded.probe(1), table.probe(1));
+    assert_eq!(loaded.probe(2), table.probe(2));
+    table.clear();
+    assert_eq!(table.positions(), 0);
+
+    // A cluster fills a cache line, and holds as many positions as there are slots, keeping the
+    // deepest searches.
+    assert_eq!(std::mem::size_of::<Cluster>(), 64);
+    assert_eq!(std::mem::align_of::<Cluster>(), 64);
+    let table = TranspositionTable::new(1);
+    let clusters = table.clusters.len() as u64;
+    for (i, depth) in [3, 1, 4, 2, 5].into_iter().enumerate() {
+        table.store(7 + i as u64 * clusters, 0, depth, 0, Bound::Exact, None);
+    }
+    assert_eq!(table.positions(), 4);
+    assert_eq!(table.probe(7 + clusters), None);
+    assert_eq!(table.probe(7 + 4 * clusters).unwrap().depth, 5);
+    table.prefetch(7);
+    let negative = Entry::unpack(
+        9,
+        Entry {
+            score: -MATE,
+            ..table.probe(7).unwrap()
+        }
+        .data(),
+    );
+    assert_eq!(negative.unwrap().score, -MATE);
+
+    std::fs::write(path, b"FCHASH01\x02\0\0\0\0\0\0\0").unwrap();
+    assert!(loaded.load(path).is_err());
+    std::fs::remove_file(path).unwrap();
+
+    // Threads storing to and probing the same few clusters at once only ever find what was
+    // stored for the key they probe: each stores its key's low bits as the score.
+    assert_eq!(Strategy::by_name("locked"), Some(Strategy::Locked));
+    for strategy in [Strategy::Lockless, Strategy::Locked] {
+        let table = TranspositionTable::with_strategy(1, strategy);
+        let clusters = table.clusters.len() as u64;
+        std::thread::scope(|scope| {
+            for thread in 0..4u64 {
+                let table = &table;
+                scope.spawn(move || {
+                    for i in 0..20_000u64 {
+                        let key = (i * 4 + thread) % 32 * clusters + i % 3;
+                        table.store(
+                            key,
+                            0,
+                            (i % 9) as u32,
+                            (key % 1000) as i32,
+                            Bound::Exact,
+                            None,
+                        );
+                        if let Some(entry) = table.probe(key ^ clusters) {
+                            assert_eq!(entry.score(0), ((key ^ clusters) % 1000) as i32);
+                        }
+                    }
+                });
+            }
+        });
+        assert!(table.positions() <= 3 * CLUSTER_SLOTS);
+    }
+}
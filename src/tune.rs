@@ -0,0 +1,181 @@
+//! Texel tuning: fits the evaluation's parameters to positions labeled with the results of the
+//! games they were taken from. The evaluation is turned into a predicted score with a logistic
+//! curve, and each parameter is nudged for as long as that brings the predictions closer to the
+//! actual results. The positions should be quiet, since they are evaluated without a search.
+
+use std::io::{BufRead, Error, ErrorKind, Write};
+
+use crate::config::params_section;
+use crate::eval::{self, Params, PARAM_LIMIT};
+use crate::{ChessBoard, BLACK};
+
+/// A position and the result of its game: 1 if white won, 0.5 for a draw, 0 if black won.
+struct Sample {
+    board: ChessBoard,
+    result: f64,
+}
+
+/// Reads a result written as `[1.0]`, `[0.5]` or `[0.0]`, or as `1-0`, `1/2-1/2` or `0-1`.
+fn parse_result(label: &str) -> Option<f64> {
+    match label.trim_matches(|c| c == '"' || c == '[' || c == ']') {
+        "1-0" => Some(1.0),
+        "1/2-1/2" => Some(0.5),
+        "0-1" => Some(0.0),
+        number => number
+            .parse::<f64>()
+            .ok()
+            .filter(|n| (0.0..=1.0).contains(n)),
+    }
+}
+
+/// Reads one line of a dataset: a position in FEN or EPD, then its result, possibly quoted
+/// after an EPD `c9` opcode, as in `... w - - c9 "1-0";`.
+fn parse_sample(line: &str) -> Result<Sample, String> {
+    let line = line.trim_end().trim_end_matches(';').trim_end();
+    let (position, label) = line
+        .rsplit_once(char::is_whitespace)
+        .ok_or("The line has no result.")?;
+    let result = parse_result(label).ok_or_else(|| format!("Unknown result {}.", label))?;
+    let position = position.trim_end();
+    let position = position.strip_suffix("c9").unwrap_or(position);
+    let board = ChessBoard::from_fen(position).map_err(|e| e.to_string())?;
+    Ok(Sample { board, result })
+}
+
+/// Reads a dataset with one labeled position per line. Blank lines and lines starting with `#`
+/// are skipped.
+fn read_dataset(input: impl BufRead) -> Result<Vec<Sample>, Error> {
+    let mut samples = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let sample = parse_sample(&line).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Line {}: {}", number + 1, e),
+            )
+        })?;
+        samples.push(sample);
+    }
+    Ok(samples)
+}
+
+/// The score the evaluation predicts for white, from 0 to 1, given white's advantage in
+/// centipawns and the scaling constant `k`.
+fn predict(score: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score as f64 / 400.0))
+}
+
+/// The mean squared difference between the predicted and actual results of `samples` with the
+/// evaluation using `params`.
+fn error(samples: &[Sample], params: Params, k: f64) -> f64 {
+    eval::set_params(params);
+    let total = samples
+        .iter()
+        .map(|sample| {
+            let score = eval::evaluate(&sample.board);
+            let score = match sample.board.side_to_move() {
+                BLACK => -score,
+                _ => score,
+            };
+            (sample.result - predict(score, k)).powi(2)
+        })
+        .sum::<f64>();
+    total / samples.len() as f64
+}
+
+/// Finds the scaling constant that makes `params` predict `samples` best, so that tuning
+/// improves the evaluation rather than just stretching it.
+fn fit_k(samples: &[Sample], params: Params) -> f64 {
+    let (mut low, mut high) = (0.05, 5.0);
+    for _ in 0..50 {
+        let third = (high - low) / 3.0;
+        if error(samples, params, low + third) < error(samples, params, high - third) {
+            high -= third;
+        } else {
+            low += third;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// Tunes `params` against `samples` with at most `passes` passes over the parameters, each
+/// moving every parameter a centipawn up or down if that lowers the error. Returns the tuned
+/// parameters, which evaluations on this thread are left using.
+fn tune(samples: &[Sample], mut params: Params, passes: u32) -> Params {
+    let k = fit_k(samples, params);
+    let mut best = error(samples, params, k);
+    eprintln!(
+        "Tuning against {} positions (K = {:.3}, error {:.6}).",
+        samples.len(),
+        k,
+        best
+    );
+    for pass in 1..=passes {
+        let mut improved = false;
+        for (name, value) in params.named() {
+            for step in [1, -1] {
+                let mut trial = params;
+                if (value + step).abs() > PARAM_LIMIT || !trial.set(name, value + step) {
+                    continue;
+                }
+                let trial_error = error(samples, trial, k);
+                if trial_error < best {
+                    (params, best, improved) = (trial, trial_error, true);
+                    break;
+                }
+            }
+        }
+        eprintln!("Pass {}: error {:.6}", pass, best);
+        if !improved {
+            break;
+        }
+    }
+    eval::set_params(params);
+    params
+}
+
+/// Runs `tune`: fits `params` to the dataset at `path` and writes the result to `output` (or
+/// stdout) as the `[eval]` section of a configuration file.
+pub fn run(path: &str, params: Params, passes: u32, output: Option<&str>) -> Result<(), Error> {
+    let samples = read_dataset(std::io::BufReader::new(std::fs::File::open(path)?))?;
+    if samples.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "The dataset has no positions.",
+        ));
+    }
+    let section = params_section("eval", tune(&samples, params, passes).named());
+    match output {
+        Some(path) => std::fs::write(path, section),
+        None => std::io::stdout().write_all(section.as_bytes()),
+    }
+}
+
+#[test]
+fn test_tune() {
+    let dataset = "# White wins with a rook on the seventh, black with one on the second.\n\
+        4k3/R7/8/8/8/8/8/4K3 w - - 0 1 [1.0]\n\
+        4k3/8/8/8/8/8/r7/4K3 w - - c9 \"0-1\";\n\
+        \n\
+        4k3/8/8/8/8/8/8/4K3 b - - 1/2-1/2\n";
+    let samples = read_dataset(dataset.as_bytes()).unwrap();
+    assert_eq!(samples.len(), 3);
+    assert_eq!(
+        samples.iter().map(|s| s.result).collect::<Vec<f64>>(),
+        [1.0, 0.0, 0.5]
+    );
+    let error = read_dataset("4k3/8/8/8/8/8/8/4K3 w - - 0 1 2-0\n".as_bytes())
+        .map(|samples| samples.len())
+        .unwrap_err();
+    assert_eq!(error.to_string(), "Line 1: Unknown result 2-0.");
+    assert!(read_dataset("4k3/8/8/8 w - - 0 1 [1.0]\n".as_bytes()).is_err());
+
+    let tuned = tune(&samples, Params::default(), 3);
+    assert!(tuned.rook_seventh > eval::DEFAULT_PARAMS.rook_seventh);
+    assert_eq!(tuned.knight_outpost, eval::DEFAULT_PARAMS.knight_outpost);
+    assert_eq!(eval::params(), tuned);
+    eval::set_params(eval::DEFAULT_PARAMS);
+}
@@ -0,0 +1,817 @@
+//! The Universal Chess Interface, so the engine can be used from chess GUIs and match runners.
+//! Commands are read one per line and unknown ones are ignored, as the protocol asks.
+//!
+//! A session can be recorded to a transcript, every line in and out with the seconds since it
+//! started, as in `    12.345 < go depth 8` and `    13.012 > bestmove e2e4`, and the commands
+//! of a transcript played back with `replay`, so a bug a GUI ran into can be reproduced.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufRead, Error, ErrorKind, Read, Write};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::crash;
+use crate::eval;
+use crate::mcts::{Policy, DEFAULT_PLAYOUTS};
+use crate::movegen::Move;
+use crate::nnue::{self, Network};
+use crate::search::{self, SearchParams, CONTEMPT_LIMIT, MATE, MAX_DEPTH};
+use crate::time_control;
+use crate::tt::{TranspositionTable, DEFAULT_HASH_MB};
+use crate::variant::{self, Variant};
+use crate::{ChessBoard, WHITE};
+
+/// How deep `go` searches when it isn't told.
+const DEFAULT_DEPTH: u32 = 4;
+
+/// The most threads the `Threads` option allows.
+const MAX_THREADS: usize = 256;
+
+/// The engine's state between commands.
+struct Engine {
+    board: ChessBoard,
+    /// The keys of the positions of the game before `board`, oldest first, for the search to
+    /// tell repetitions by.
+    history: Vec<u64>,
+    /// The rules games are played by, as the `UCI_Variant` option asks.
+    variant: &'static dyn Variant,
+    /// Whether castling is written as the king capturing its own rook, as the `UCI_Chess960`
+    /// option asks.
+    chess960: bool,
+    /// Whether to evaluate with the network, as the `Use NNUE` option asks.
+    use_nnue: bool,
+    /// The network loaded from the `EvalFile` option.
+    network: Option<Arc<Network>>,
+    /// The policy to search with by MCTS instead of alpha-beta, if any.
+    mcts: Option<Policy>,
+    /// What searches have found so far, as big as the `Hash` option asks.
+    table: TranspositionTable,
+    /// How many threads alpha-beta searches run on, as the `Threads` option asks.
+    threads: usize,
+    /// Where the table is loaded from at the start and saved to after every search, if anywhere.
+    hash_file: Option<String>,
+}
+
+impl Engine {
+    /// Loads the hash table from the hash file, if there is one yet.
+    fn load_hash(&mut self) {
+        let Some(path) = &self.hash_file else {
+            return;
+        };
+        if !std::path::Path::new(path).exists() {
+            return;
+        }
+        match self.table.load(path) {
+            Ok(count) => eprintln!("Loaded {} positions from {}.", count, path),
+            Err(e) => eprintln!("Couldn't load the hash table from {}: {}", path, e),
+        }
+    }
+
+    /// Switches the NNUE evaluation on or off to match the options.
+    fn set_evaluation(&self) {
+        if self.use_nnue && self.network.is_none() {
+            eprintln!("Evaluating classically until a network is loaded with EvalFile.");
+        }
+        nnue::set_network(self.network.clone().filter(|_| self.use_nnue));
+    }
+}
+
+/// Finds the legal move written as `text` in UCI notation.
+pub(crate) fn parse_move(board: &ChessBoard, text: &str) -> Option<Move> {
+    board
+        .legal_moves()
+        .into_iter()
+        .find(|mv| mv.to_string() == text)
+}
+
+/// Writes `score` (from the side to move's point of view) as UCI does: in centipawns, or in
+/// moves to mate.
+fn score(score: i32) -> String {
+    if score.abs() > MATE - 1000 {
+        let plies = MATE - score.abs();
+        format!("mate {}", score.signum() * (plies + 1) / 2)
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+/// Reads a score as `score` writes it, `kind` being `cp` or `mate` and `value` its number.
+#[cfg(feature = "cli")]
+pub(crate) fn read_score(kind: &str, value: &str) -> Option<i32> {
+    let value = value.parse::<i32>().ok()?;
+    match kind {
+        "cp" => Some(value),
+        "mate" if value > 0 => Some(MATE - (2 * value - 1)),
+        "mate" => Some(-(MATE + 2 * value)),
+        _ => None,
+    }
+}
+
+impl Engine {
+    /// The start position of the engine's variant.
+    fn start(&self) -> ChessBoard {
+        let mut board = ChessBoard::usual_start_of(self.variant);
+        board.chess960 |= self.chess960;
+        board
+    }
+
+    /// Handles `position [startpos | fen FEN] [moves MOVE...]`.
+    fn position(&mut self, arguments: &[&str]) {
+        let (setup, moves) = match arguments.iter().position(|&word| word == "moves") {
+            Some(index) => (&arguments[..index], &arguments[index + 1..]),
+            None => (arguments, &[][..]),
+        };
+        let board = match setup {
+            ["startpos"] => Ok(self.start()),
+            ["fen", fen @ ..] => ChessBoard::from_variant_fen(&fen.join(" "), self.variant),
+            _ => return,
+        };
+        let mut board = match board {
+            Ok(board) => board,
+            Err(e) => {
+                eprintln!("Ignoring the position: {}", e);
+                return;
+            }
+        };
+        board.chess960 |= self.chess960;
+        let mut played = Vec::new();
+        let mut history = Vec::new();
+        for text in moves {
+            match parse_move(&board, text) {
+                Some(mv) => {
+                    history.push(board.zobrist());
+                    board.apply_move(mv);
+                    played.push(text.to_string());
+                }
+                None => {
+                    eprintln!("Ignoring the moves from {}, which isn't legal.", text);
+                    break;
+                }
+            }
+        }
+        crash::note_game(&setup.join(" "), &played, &board);
+        self.board = board;
+        self.history = history;
+    }
+
+    /// Handles `go`, searching to `depth N` and through at most `nodes N` positions (or perft to
+    /// `perft N`), and answering `bestmove`.
+    fn go(&mut self, arguments: &[&str], output: &mut impl Write) -> Result<(), Error> {
+        let value = |name: &str| {
+            arguments
+                .iter()
+                .position(|&word| word == name)
+                .and_then(|index| arguments.get(index + 1)?.parse::<u32>().ok())
+        };
+        if let Some(depth) = value("perft") {
+            let moves = self.board.divide(depth);
+            for (mv, nodes) in &moves {
+                writeln!(output, "{}: {}", mv, nodes)?;
+            }
+            let nodes = moves.iter().map(|(_, nodes)| nodes).sum::<u64>();
+            return writeln!(output, "\nNodes searched: {}", nodes);
+        }
+        let result = match self.mcts {
+            Some(policy) => self
+                .board
+                .search_policy(value("nodes").unwrap_or(DEFAULT_PLAYOUTS), policy),
+            None => {
+                // On the clock, a move gets its share of the time left.
+                let clock = match self.board.side_to_move() {
+                    WHITE => value("wtime"),
+                    _ => value("btime"),
+                };
+                let time = clock.map(time_control::move_time);
+                // Searches limited by positions or time alone go as deep as they can.
+                let nodes = value("nodes").map(u64::from);
+                let depth = match nodes.is_some() || time.is_some() {
+                    true => value("depth").unwrap_or(MAX_DEPTH),
+                    false => value("depth").unwrap_or(DEFAULT_DEPTH),
+                };
+                self.board.search_threads(
+                    depth,
+                    nodes.unwrap_or(u64::MAX),
+                    time,
+                    self.threads,
+                    &self.table,
+                    &self.history,
+                )
+            }
+        };
+        if let Some(path) = &self.hash_file {
+            if let Err(e) = self.table.save(path) {
+                eprintln!("Couldn't save the hash table to {}: {}", path, e);
+            }
+        }
+        let pv = result
+            .pv
+            .iter()
+            .map(Move::to_string)
+            .collect::<Vec<String>>();
+        writeln!(
+            output,
+            "info depth {} score {} nodes {} pv {}",
+            result.depth,
+            score(result.score),
+            result.nodes,
+            pv.join(" ")
+        )?;
+        let best = result
+            .best_move
+            .map_or("0000".to_string(), |mv| mv.to_string());
+        tracing::debug!(target: "uci", ">> bestmove {}", best);
+        writeln!(output, "bestmove {}", best)
+    }
+}
+
+/// Speaks UCI over `input` and `output` until `quit`. `variant` sets the `UCI_Variant` option,
+/// and `UCI_Chess960` for Chess960, to begin with, and `params` and `search_params` the
+/// evaluation's and search's options. With `mcts`, `go` searches by MCTS with that policy,
+/// making as many playouts as `go nodes` asks. With `hash_file`, the hash table is loaded from
+/// that file, if there is one, and saved to it after every search, so analysis can go on from
+/// where an earlier session left it.
+pub fn run(
+    input: impl BufRead,
+    output: &mut impl Write,
+    variant: &'static dyn Variant,
+    params: eval::Params,
+    search_params: SearchParams,
+    mcts: Option<Policy>,
+    hash_file: Option<&str>,
+) -> Result<(), Error> {
+    let mut engine = Engine {
+        board: ChessBoard::new(),
+        history: Vec::new(),
+        variant,
+        chess960: variant.is_chess960(),
+        use_nnue: false,
+        network: None,
+        mcts,
+        table: TranspositionTable::new(DEFAULT_HASH_MB),
+        threads: 1,
+        hash_file: hash_file.map(str::to_string),
+    };
+    engine.board = engine.start();
+    engine.load_hash();
+    eval::set_params(params);
+    search::set_params(search_params);
+    engine.set_evaluation();
+    for line in input.lines() {
+        let line = line?;
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        let command = words.first().copied().unwrap_or_default();
+        let _span = tracing::info_span!(target: "uci", "uci", %command).entered();
+        tracing::debug!(target: "uci", "<< {}", line);
+        if let ["setoption", "name", option @ ..] = words.as_slice() {
+            let value = option.iter().position(|&word| word == "value");
+            let (name, value) = option.split_at(value.unwrap_or(option.len()));
+            crash::note_setting(&name.join(" "), &value.get(1..).unwrap_or(&[]).join(" "));
+        }
+        match words.as_slice() {
+            ["uci"] => {
+                writeln!(output, "id name FreightChess {}", env!("CARGO_PKG_VERSION"))?;
+                writeln!(output, "id author Arvin Kushwaha")?;
+                writeln!(
+                    output,
+                    "option name UCI_Chess960 type check default {}",
+                    variant.is_chess960()
+                )?;
+                let names = variant::names()
+                    .iter()
+                    .map(|name| format!(" var {}", name))
+                    .collect::<String>();
+                writeln!(
+                    output,
+                    "option name UCI_Variant type combo default {}{}",
+                    variant.name(),
+                    names
+                )?;
+                writeln!(output, "option name Use NNUE type check default false")?;
+                writeln!(output, "option name EvalFile type string default <empty>")?;
+                writeln!(
+                    output,
+                    "option name Hash type spin default {} min 1 max 4096",
+                    DEFAULT_HASH_MB
+                )?;
+                writeln!(
+                    output,
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                )?;
+                writeln!(
+                    output,
+                    "option name Contempt type spin default {} min {} max {}",
+                    search::contempt(),
+                    -CONTEMPT_LIMIT,
+                    CONTEMPT_LIMIT
+                )?;
+                for (name, value) in params.named() {
+                    writeln!(
+                        output,
+                        "option name {} type spin default {} min {} max {}",
+                        name,
+                        value,
+                        -eval::PARAM_LIMIT,
+                        eval::PARAM_LIMIT
+                    )?;
+                }
+                for (name, value) in search_params.named() {
+                    let (low, high) = SearchParams::range(name).unwrap();
+                    writeln!(
+                        output,
+                        "option name {} type spin default {} min {} max {}",
+                        name, value, low, high
+                    )?;
+                }
+                writeln!(output, "uciok")?;
+            }
+            ["isready"] => writeln!(output, "readyok")?,
+            ["ucinewgame"] => {
+                engine.board = engine.start();
+                engine.history.clear();
+                engine.table.clear();
+            }
+            ["setoption", "name", "UCI_Chess960", "value", value] => {
+                engine.chess960 = value.eq_ignore_ascii_case("true");
+            }
+            ["setoption", "name", "UCI_Variant", "value", value] => match variant::by_name(value) {
+                Some(variant) => engine.variant = variant,
+                None => eprintln!("Ignoring the variant {}, which isn't supported.", value),
+            },
+            ["setoption", "name", "Use", "NNUE", "value", value] => {
+                engine.use_nnue = value.eq_ignore_ascii_case("true");
+                engine.set_evaluation();
+            }
+            ["setoption", "name", "Hash", "value", value] => match value.parse::<usize>() {
+                Ok(megabytes @ 1..=4096) => {
+                    engine.table = TranspositionTable::new(megabytes);
+                    engine.load_hash();
+                }
+                _ => eprintln!("Ignoring the hash size {}.", value),
+            },
+            ["setoption", "name", "Threads", "value", value] => match value.parse::<usize>() {
+                Ok(threads @ 1..=MAX_THREADS) => engine.threads = threads,
+                _ => eprintln!("Ignoring the thread count {}.", value),
+            },
+            ["setoption", "name", "Contempt", "value", value] => match value.parse::<i32>() {
+                Ok(contempt) if contempt.abs() <= CONTEMPT_LIMIT => search::set_contempt(contempt),
+                _ => eprintln!("Ignoring the contempt {}.", value),
+            },
+            ["setoption", "name", "EvalFile", "value", path @ ..] => {
+                let path = path.join(" ");
+                match Network::load(&path) {
+                    Ok(network) => {
+                        eprintln!("Loaded the network {} ({}).", path, network.description());
+                        engine.network = Some(Arc::new(network));
+                    }
+                    Err(e) => eprintln!("Couldn't load the network {}: {}", path, e),
+                }
+                engine.set_evaluation();
+            }
+            ["setoption", "name", name, "value", value] => {
+                let mut params = eval::params();
+                let mut search_params = search::params();
+                match value.parse::<i32>() {
+                    Ok(value) if value.abs() <= eval::PARAM_LIMIT && params.set(name, value) => {
+                        eval::set_params(params)
+                    }
+                    Ok(value) if search_params.set(name, value) => {
+                        search::set_params(search_params)
+                    }
+                    _ => eprintln!("Ignoring the option {} = {}.", name, value),
+                }
+            }
+            ["position", arguments @ ..] => engine.position(arguments),
+            ["go", arguments @ ..] => engine.go(arguments, output)?,
+            ["quit"] => break,
+            _ => {}
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Where a session is being recorded, with the lines in each direction not yet finished.
+struct Transcript {
+    file: File,
+    started: Instant,
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl Transcript {
+    /// Notes `bytes` passing in the direction `marker` (`<` in, `>` out), writing every line
+    /// they finish.
+    fn note(&mut self, marker: char, bytes: &[u8]) -> Result<(), Error> {
+        let seconds = self.started.elapsed().as_secs_f64();
+        let pending = match marker {
+            '<' => &mut self.input,
+            _ => &mut self.output,
+        };
+        pending.extend_from_slice(bytes);
+        while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+            let line = pending.drain(..=end).collect::<Vec<u8>>();
+            let line = String::from_utf8_lossy(&line[..end]);
+            writeln!(
+                self.file,
+                "{:>10.3} {} {}",
+                seconds,
+                marker,
+                line.trim_end()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Input or output of a session, noted in its transcript as it passes, if there is one.
+pub struct Recorded<T> {
+    inner: T,
+    transcript: Option<Rc<RefCell<Transcript>>>,
+}
+
+/// Wraps `input` and `output` so that everything passing through them is recorded to a new
+/// transcript at `path`, or nothing is without one.
+pub fn recorded<R: BufRead, W: Write>(
+    input: R,
+    output: W,
+    path: Option<&str>,
+) -> Result<(Recorded<R>, Recorded<W>), Error> {
+    let transcript = match path {
+        Some(path) => Some(Rc::new(RefCell::new(Transcript {
+            file: File::create(path)?,
+            started: Instant::now(),
+            input: Vec::new(),
+            output: Vec::new(),
+        }))),
+        None => None,
+    };
+    Ok((
+        Recorded {
+            inner: input,
+            transcript: transcript.clone(),
+        },
+        Recorded {
+            inner: output,
+            transcript,
+        },
+    ))
+}
+
+impl<R: BufRead> Read for Recorded<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = self.inner.read(buf)?;
+        if let Some(transcript) = &self.transcript {
+            transcript.borrow_mut().note('<', &buf[..read])?;
+        }
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for Recorded<R> {
+    fn fill_buf(&mut self) -> Result<&[u8], Error> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if let Some(transcript) = &self.transcript {
+            // The bytes consumed are still buffered, so this doesn't read any more. A transcript
+            // that can't be written to shouldn't stop the engine, so errors are dropped here.
+            if let Ok(buffered) = self.inner.fill_buf() {
+                let _ = transcript.borrow_mut().note('<', &buffered[..amount]);
+            }
+        }
+        self.inner.consume(amount);
+    }
+}
+
+impl<W: Write> Write for Recorded<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let written = self.inner.write(buf)?;
+        if let Some(transcript) = &self.transcript {
+            transcript.borrow_mut().note('>', &buf[..written])?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if let Some(transcript) = &self.transcript {
+            transcript.borrow_mut().file.flush()?;
+        }
+        self.inner.flush()
+    }
+}
+
+/// The commands sent to the engine in the transcript `text`, and the lines it answered with.
+fn parse_transcript(text: &str) -> Result<(Vec<&str>, Vec<&str>), Error> {
+    let (mut commands, mut answers) = (Vec::new(), Vec::new());
+    for (number, line) in text.lines().enumerate() {
+        match line.trim_start().split_once(' ').map(|(_, rest)| rest) {
+            Some(rest) if rest.starts_with('<') => commands.push(rest[1..].trim_start()),
+            Some(rest) if rest.starts_with('>') => answers.push(rest[1..].trim_start()),
+            _ if line.trim().is_empty() => {}
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Line {} isn't part of a UCI transcript.", number + 1),
+                ))
+            }
+        }
+    }
+    Ok((commands, answers))
+}
+
+/// Plays the commands of the transcript at `path` to a new engine set up as `run` is, writing
+/// its answers to `output`. Returns the number of the first answer that differs from the
+/// recorded one (counting from 1), or `None` if they all match.
+pub fn replay(
+    path: &str,
+    output: &mut impl Write,
+    variant: &'static dyn Variant,
+    params: eval::Params,
+    search_params: SearchParams,
+    mcts: Option<Policy>,
+) -> Result<Option<usize>, Error> {
+    let text = std::fs::read_to_string(path)?;
+    let (commands, recorded) = parse_transcript(&text)?;
+    let mut input = commands.join("\n");
+    input.push('\n');
+    let mut answers = Vec::new();
+    run(
+        input.as_bytes(),
+        &mut answers,
+        variant,
+        params,
+        search_params,
+        mcts,
+        None,
+    )?;
+    output.write_all(&answers)?;
+    let answers = String::from_utf8_lossy(&answers);
+    let answers = answers.lines().collect::<Vec<&str>>();
+    let differs = (0..answers.len().max(recorded.len()))
+        .find(|&i| answers.get(i) != recorded.get(i))
+        .map(|i| i + 1);
+    Ok(differs)
+}
+
+#[test]
+fn test_uci() {
+    let session = |commands: &str, chess960: bool| {
+        let mut output = Vec::new();
+        let variant = variant::by_name(if chess960 { "chess960" } else { "standard" }).unwrap();
+        run(
+            commands.as_bytes(),
+            &mut output,
+            variant,
+            eval::DEFAULT_PARAMS,
+            SearchParams::default(),
+            None,
+            None,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    let output = session("uci\nisready\nquit\ngo depth 1\n", false);
+    assert!(output.contains("option name UCI_Chess960 type check default false\n"));
+    assert!(output.contains(
+        "option name UCI_Variant type combo default standard var standard var chess960 \
+        var crazyhouse var atomic var threecheck \
+        var antichess var racingkings var gardner var losalamos\n"
+    ));
+    assert!(output.contains("option name KnightOutpost type spin default 20 min -500 max 500\n"));
+    assert!(output.contains("option name LmrDivisor type spin default 225 min 100 max 600\n"));
+    assert!(output.contains("option name Use NNUE type check default false\n"));
+    assert!(output.contains("option name Contempt type spin default 0 min -1000 max 1000\n"));
+    assert!(output.contains("option name Threads type spin default 1 min 1 max 256\n"));
+    assert!(output.ends_with("readyok\n"));
+
+    // Evaluation parameters are set by name, and only within their range.
+    session(
+        "setoption name bishoppair value 75\nsetoption name RookSeventh value 9999\n",
+        false,
+    );
+    assert_eq!(eval::params().bishop_pair, 75);
+    assert_eq!(
+        eval::params().rook_seventh,
+        eval::DEFAULT_PARAMS.rook_seventh
+    );
+    eval::set_params(eval::DEFAULT_PARAMS);
+    session(
+        "setoption name FutilityMargin value 90\nsetoption name LmrDivisor value 50\n",
+        false,
+    );
+    assert_eq!(search::params().futility_margin, 90);
+    assert_eq!(search::params().lmr_divisor, 225);
+    search::set_params(SearchParams::default());
+    session("setoption name Contempt value -30\n", false);
+    assert_eq!(search::contempt(), -30);
+    session("setoption name Contempt value 2000\n", false);
+    assert_eq!(search::contempt(), -30);
+    search::set_contempt(0);
+
+    // With a network that thinks nothing of anything, every position is even.
+    let path = std::env::temp_dir().join(format!("freight_chess_{}.nnue", std::process::id()));
+    std::fs::write(&path, nnue::test_network(|_| {})).unwrap();
+    let output = session(
+        &format!(
+            "setoption name EvalFile value {}\nsetoption name Use NNUE value true\n\
+            position startpos\ngo depth 1\n",
+            path.display()
+        ),
+        false,
+    );
+    assert!(output.contains("score cp 0 "));
+    let output = session(
+        &format!(
+            "setoption name EvalFile value {}\nposition startpos\ngo depth 1\n",
+            path.display()
+        ),
+        false,
+    );
+    assert!(!output.contains("score cp 0 "));
+    std::fs::remove_file(path).unwrap();
+    nnue::set_network(None);
+
+    // Scholar's mate, found from moves played from the start.
+    let output = session(
+        "position startpos moves e2e4 e7e5 f1c4 b8c6 d1h5 g8f6\ngo depth 2\n",
+        false,
+    );
+    assert!(output.contains("score mate 1"));
+    assert!(output.ends_with("bestmove h5f7\n"));
+    // And by several threads sharing the table.
+    let output = session(
+        "setoption name Threads value 4\nposition startpos moves e2e4 e7e5 f1c4 b8c6 d1h5 g8f6\n\
+        go depth 4\n",
+        false,
+    );
+    assert!(output.ends_with("bestmove h5f7\n"));
+    // And on the clock, with black's time the one that counts.
+    let output = session(
+        "position startpos moves e2e4 e7e5 f1c4 b8c6 d1h5\ngo wtime 1 btime 60000\n",
+        false,
+    );
+    assert!(!output.contains("info depth 1 ") && !output.contains("bestmove 0000"));
+
+    // In Chess960, castling is the king taking its own rook.
+    let output = session(
+        "position fen 4k3/8/8/8/8/8/8/1R2K1R1 w GB - 0 1\ngo perft 1\n",
+        false,
+    );
+    assert!(output.contains("e1b1: 1\n"));
+    assert!(output.contains("e1g1: 1\n"));
+    let italian = "position startpos moves e2e4 e7e5 g1f3 b8c6 f1c4 g8f6\ngo perft 1\n";
+    assert!(session(italian, false).contains("e1g1: 1\n"));
+    let output = session(
+        &format!("setoption name UCI_Chess960 value true\n{}", italian),
+        false,
+    );
+    assert!(output.contains("e1h1: 1\n"));
+    assert!(!output.contains("e1g1"));
+    let output = session(
+        &format!("setoption name UCI_Variant value chess960\n{}", italian),
+        false,
+    );
+    assert!(output.contains("e1h1: 1\n"));
+    assert!(session(italian, true).contains("e1h1: 1\n"));
+
+    // Crazyhouse drops are written as in P@e4.
+    let output = session(
+        "setoption name UCI_Variant value crazyhouse\n\
+        position startpos moves e2e4 d7d5 e4d5 d8d5 b1c3 d5a5 P@e6\ngo perft 1\n",
+        false,
+    );
+    assert!(output.contains("f7e6: 1\n"));
+    assert!(output.contains("P@e3: 1\n"));
+    let output = session(
+        "setoption name UCI_Variant value crazyhouse\n\
+        position fen 4k3/8/8/8/8/8/8/r3K3[N] w - - 0 1\ngo perft 1\n",
+        false,
+    );
+    assert!(output.contains("N@d1: 1\n"));
+
+    // A saved hash table lets a later session search the same position with fewer nodes.
+    let path = std::env::temp_dir().join(format!("freight_chess_uci_{}.hash", std::process::id()));
+    let hash_session = || {
+        let mut output = Vec::new();
+        run(
+            "position startpos moves e2e4 e7e5\ngo depth 4\n".as_bytes(),
+            &mut output,
+            variant::by_name("standard").unwrap(),
+            eval::DEFAULT_PARAMS,
+            SearchParams::default(),
+            None,
+            path.to_str(),
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let nodes = output.split(" nodes ").nth(1).unwrap();
+        nodes.split(' ').next().unwrap().parse::<u64>().unwrap()
+    };
+    let first = hash_session();
+    assert!(path.exists());
+    assert!(hash_session() < first);
+    std::fs::remove_file(path).unwrap();
+
+    // By alpha-beta, `go nodes` stops the search at exactly that many positions, the same way
+    // every time.
+    let nodes = "position startpos moves e2e4 e7e5\ngo nodes 3000\n";
+    let output = session(nodes, false);
+    assert!(output.contains(" nodes 3000 "));
+    assert_eq!(session(nodes, false), output);
+
+    // By MCTS, `go nodes` sets the playouts.
+    let mut output = Vec::new();
+    run(
+        "position startpos moves e2e4 e7e5 f1c4 b8c6 d1h5 g8f6\ngo nodes 200\n".as_bytes(),
+        &mut output,
+        variant::by_name("standard").unwrap(),
+        eval::DEFAULT_PARAMS,
+        SearchParams::default(),
+        Some(Policy::Eval),
+        None,
+    )
+    .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains(" nodes 200 "));
+    assert!(output.ends_with("bestmove h5f7\n"));
+}
+
+#[test]
+fn test_transcript() {
+    let path = std::env::temp_dir().join(format!("freight_chess_{}.uci", std::process::id()));
+    let path = path.to_str().unwrap();
+    let commands = "uci\nposition startpos moves e2e4\ngo nodes 500\nisready\n";
+    let (input, mut output) = recorded(commands.as_bytes(), Vec::new(), Some(path)).unwrap();
+    let variant = variant::by_name("standard").unwrap();
+    let params = eval::DEFAULT_PARAMS;
+    run(
+        input,
+        &mut output,
+        variant,
+        params,
+        SearchParams::default(),
+        None,
+        None,
+    )
+    .unwrap();
+    drop(output);
+    let text = std::fs::read_to_string(path).unwrap();
+    let (sent, answered) = parse_transcript(&text).unwrap();
+    assert_eq!(
+        sent,
+        [
+            "uci",
+            "position startpos moves e2e4",
+            "go nodes 500",
+            "isready"
+        ]
+    );
+    assert_eq!(answered.last(), Some(&"readyok"));
+    assert!(text
+        .lines()
+        .nth(1)
+        .unwrap()
+        .contains(" > id name FreightChess "));
+
+    // Searches limited by positions answer the same when played back.
+    let mut replayed = Vec::new();
+    let differs = replay(
+        path,
+        &mut replayed,
+        variant,
+        params,
+        SearchParams::default(),
+        None,
+    );
+    assert_eq!(differs.unwrap(), None);
+    assert_eq!(
+        String::from_utf8(replayed).unwrap().lines().count(),
+        answered.len()
+    );
+    std::fs::write(path, text.replace("> readyok", "> readyno")).unwrap();
+    let differs = replay(
+        path,
+        &mut Vec::new(),
+        variant,
+        params,
+        SearchParams::default(),
+        None,
+    );
+    assert_eq!(differs.unwrap(), Some(answered.len()));
+    std::fs::write(path, "uci\n").unwrap();
+    assert!(replay(
+        path,
+        &mut Vec::new(),
+        variant,
+        params,
+        SearchParams::default(),
+        None
+    )
+    .is_err());
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,232 @@
+//! Chess variants. Each variant is a set of hooks into the standard rules: where games start,
+//! which moves are added or taken away, which positions are legal, what else happens when a move
+//! is played and when the game is over. The move generator and search call the hooks of the
+//! board's variant, so a new variant only implements the rules that differ.
+
+use crate::antichess::Antichess;
+use crate::atomic::Atomic;
+use crate::crazyhouse::Crazyhouse;
+use crate::eval;
+use crate::minichess::{Gardner, LosAlamos};
+use crate::movegen::{Move, MoveList, MoveRejection};
+use crate::racing_kings::RacingKings;
+use crate::three_check::ThreeCheck;
+use crate::{chess960, ChessBoard, WHITE};
+
+/// The rules of a chess variant. Every hook but the names and start position defaults to the
+/// standard rules.
+pub trait Variant: Sync {
+    /// The name the variant goes by on the command line and in UCI.
+    fn name(&self) -> &'static str;
+
+    /// The name of the variant in PGN's `Variant` tag.
+    fn pgn_name(&self) -> &'static str;
+
+    /// The position a new game starts from.
+    fn start(&self) -> ChessBoard;
+
+    /// Whether castling follows the Chess960 rules, with the king and rooks anywhere.
+    fn is_chess960(&self) -> bool {
+        false
+    }
+
+    /// Whether captured pieces go into hand to be dropped back on the board, so FEN and the board
+    /// show what each side holds.
+    fn has_pockets(&self) -> bool {
+        false
+    }
+
+    /// Whether each side has exactly one king, which can be checked. Positions without one are
+    /// only read from FEN in variants where kings are ordinary pieces.
+    fn has_royal_king(&self) -> bool {
+        true
+    }
+
+    /// How many files and ranks the board has. Smaller boards are the lower left corner of the
+    /// full-size one, so squares keep their names, and pawns only step twice on the full-size
+    /// board.
+    fn dimensions(&self) -> (u8, u8) {
+        (8, 8)
+    }
+
+    /// How many checks win the game, in variants that count them. FEN and the board then show
+    /// the checks each side has given.
+    fn check_limit(&self) -> Option<u8> {
+        None
+    }
+
+    /// Changes the moves the pieces could make, before the moves' legality is checked: adds
+    /// moves the standard pieces don't have, or takes away moves the variant forbids.
+    fn adjust_moves(&self, _board: &ChessBoard, _moves: &mut MoveList) {}
+
+    /// Whether `color`'s king is in check in `board`.
+    fn is_in_check(&self, board: &ChessBoard, color: u8) -> bool {
+        board.is_king_attacked(color)
+    }
+
+    /// Explains why the variant's own rules (those of `adjust_moves` or `is_legal`) don't allow
+    /// `mv`, if they are the reason. Otherwise the standard explanation is given.
+    fn rejection(&self, _board: &ChessBoard, _mv: Move) -> Option<MoveRejection> {
+        None
+    }
+
+    /// Scores the position in centipawns for the side to move, for the search.
+    fn evaluate(&self, board: &ChessBoard) -> i32 {
+        eval::evaluate_material(board)
+    }
+
+    /// Whether the move from `before` that led to `after` may be played. In standard chess, the
+    /// mover's king may not be left in check.
+    fn is_legal(&self, before: &ChessBoard, after: &ChessBoard) -> bool {
+        !after.is_in_check(before.side_to_move())
+    }
+
+    /// Makes the variant's own changes to the position once `mv`, played from `before`, has
+    /// moved the pieces in `after`.
+    fn after_move(&self, _before: &ChessBoard, _mv: Move, _after: &mut ChessBoard) {}
+
+    /// The result as written in PGN and the reason, if the game is over. `moves` are the legal
+    /// moves of `board`.
+    fn outcome(&self, board: &ChessBoard, moves: &[Move]) -> Option<(&'static str, &'static str)> {
+        if !moves.is_empty() {
+            return None;
+        }
+        let side = board.side_to_move();
+        Some(match (board.is_in_check(side), side) {
+            (true, WHITE) => ("0-1", "checkmate"),
+            (true, _) => ("1-0", "checkmate"),
+            (false, _) => ("1/2-1/2", "stalemate"),
+        })
+    }
+}
+
+/// Standard chess.
+pub struct Standard;
+
+impl Variant for Standard {
+    fn name(&self) -> &'static str {
+        "standard"
+    }
+
+    fn pgn_name(&self) -> &'static str {
+        "Standard"
+    }
+
+    fn start(&self) -> ChessBoard {
+        ChessBoard::new()
+    }
+}
+
+/// Chess960: the standard rules, from one of 960 start positions.
+pub struct Chess960;
+
+impl Variant for Chess960 {
+    fn name(&self) -> &'static str {
+        "chess960"
+    }
+
+    fn pgn_name(&self) -> &'static str {
+        "Chess960"
+    }
+
+    /// A start position picked at random.
+    fn start(&self) -> ChessBoard {
+        ChessBoard::chess960(chess960::random_number()).unwrap_or_default()
+    }
+
+    fn is_chess960(&self) -> bool {
+        true
+    }
+}
+
+pub static STANDARD: Standard = Standard;
+
+/// Every variant, in the order they are listed in help texts.
+pub static VARIANTS: [&dyn Variant; 9] = [
+    &STANDARD,
+    &Chess960,
+    &Crazyhouse,
+    &Atomic,
+    &ThreeCheck,
+    &Antichess,
+    &RacingKings,
+    &Gardner,
+    &LosAlamos,
+];
+
+/// The names of every variant, for `--variant`.
+pub fn names() -> Vec<&'static str> {
+    VARIANTS.iter().map(|variant| variant.name()).collect()
+}
+
+/// Finds a variant by its name or its name in PGN, ignoring case. PGN's names for standard chess
+/// (`Chess`, and `From Position` for games set up from a FEN) and `Fischerandom` are known too.
+pub fn by_name(name: &str) -> Option<&'static dyn Variant> {
+    let name = name.to_lowercase();
+    let name = match name.as_str() {
+        "chess" | "normal" | "from position" => "standard",
+        "fischerandom" => "chess960",
+        name => name,
+    };
+    VARIANTS
+        .iter()
+        .find(|variant| variant.name() == name || variant.pgn_name().to_lowercase() == name)
+        .copied()
+}
+
+impl ChessBoard {
+    /// The start position of `variant`.
+    pub fn start_of(variant: &'static dyn Variant) -> ChessBoard {
+        variant.start().with_variant(variant)
+    }
+
+    /// The start position of `variant` that needs no FEN in PGN or UCI. That is its only start
+    /// position, except in Chess960, where it is the standard one (number 518).
+    pub fn usual_start_of(variant: &'static dyn Variant) -> ChessBoard {
+        match variant.is_chess960() {
+            true => ChessBoard::new().with_variant(variant),
+            false => ChessBoard::start_of(variant),
+        }
+    }
+
+    /// Whether `square` is on the board of the position's variant.
+    pub fn is_on_board(&self, square: u8) -> bool {
+        let (files, ranks) = self.variant.dimensions();
+        square & 7 < files && square >> 3 < ranks
+    }
+
+    /// This position, played by the rules of `variant`.
+    pub fn with_variant(mut self, variant: &'static dyn Variant) -> ChessBoard {
+        self.variant = variant;
+        self.chess960 |= variant.is_chess960();
+        self
+    }
+}
+
+#[test]
+fn test_variant() {
+    assert_eq!(
+        names(),
+        [
+            "standard",
+            "chess960",
+            "crazyhouse",
+            "atomic",
+            "threecheck",
+            "antichess",
+            "racingkings",
+            "gardner",
+            "losalamos"
+        ]
+    );
+    assert_eq!(by_name("Standard").unwrap().name(), "standard");
+    assert_eq!(by_name("From Position").unwrap().name(), "standard");
+    assert_eq!(by_name("FischeRandom").unwrap().name(), "chess960");
+    assert!(by_name("shogi").is_none());
+
+    let board = ChessBoard::start_of(by_name("chess960").unwrap());
+    assert!(board.chess960);
+    assert_eq!(board.variant.name(), "chess960");
+    assert_eq!(board.legal_moves().len(), board.perft(1) as usize);
+    assert_eq!(ChessBoard::new().variant.name(), "standard");
+}
@@ -0,0 +1,110 @@
+//! Bindings for JavaScript, so the rules and the engine can run in a browser. Build them with
+//! `wasm-pack build -- --no-default-features --features wasm`, which leaves out the command line
+//! and everything else a browser can't do. From JavaScript:
+//!
+//! ```js
+//! import init, { Board } from "./pkg/freight_chess.js";
+//!
+//! await init();
+//! const board = new Board();
+//! board.play("e4");
+//! console.log(board.legalMoves(), board.bestMove(3));
+//! ```
+//!
+//! Moves are given and returned in SAN, but any move `ChessBoard::parse_move` reads is accepted.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{ChessBoard, WHITE};
+
+/// The strongest level `bestMove` plays at.
+pub const MAX_LEVEL: u32 = 10;
+
+/// How far, and through how many positions, the engine searches at `level` from 1 to
+/// `MAX_LEVEL`. Searches are limited by positions rather than time, since a browser's clock
+/// isn't available to WebAssembly outside JavaScript, and so each level plays the same way on
+/// any machine.
+fn limits(level: u32) -> (u32, u64) {
+    let level = level.clamp(1, MAX_LEVEL);
+    (level, 500u64 << level)
+}
+
+/// A game in progress, as JavaScript sees it.
+#[wasm_bindgen]
+pub struct Board {
+    board: ChessBoard,
+}
+
+fn error(e: impl std::fmt::Display) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+#[wasm_bindgen]
+impl Board {
+    /// The standard start position.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Board {
+        Board {
+            board: ChessBoard::new(),
+        }
+    }
+
+    /// The position `fen` describes, or an error saying what is wrong with it.
+    #[wasm_bindgen(js_name = fromFen)]
+    pub fn from_fen(fen: &str) -> Result<Board, JsValue> {
+        ChessBoard::from_fen(fen)
+            .map(|board| Board { board })
+            .map_err(error)
+    }
+
+    pub fn fen(&self) -> String {
+        self.board.fen()
+    }
+
+    /// `"white"` or `"black"`.
+    #[wasm_bindgen(js_name = sideToMove)]
+    pub fn side_to_move(&self) -> String {
+        match self.board.side_to_move() {
+            WHITE => "white".to_string(),
+            _ => "black".to_string(),
+        }
+    }
+
+    /// The SAN of every legal move.
+    #[wasm_bindgen(js_name = legalMoves)]
+    pub fn legal_moves(&self) -> Vec<String> {
+        self.board.legal_sans()
+    }
+
+    /// Plays `text`, in SAN or as in `e2->e4`, returning its SAN, or an error saying why it
+    /// can't be played.
+    pub fn play(&mut self, text: &str) -> Result<String, JsValue> {
+        let mv = self.board.parse_move(text).map_err(error)?;
+        let san = self.board.san(mv);
+        self.board.apply_move(mv);
+        Ok(san)
+    }
+
+    /// The result (`"1-0"`, `"0-1"` or `"1/2-1/2"`) and why, as in `"1-0 checkmate"`, or
+    /// `undefined` while the game goes on.
+    pub fn outcome(&self) -> Option<String> {
+        self.board
+            .outcome()
+            .map(|(result, reason)| format!("{} {}", result, reason))
+    }
+
+    /// The move the engine plays at `level`, from 1 (weakest) to 10, in SAN, or `undefined` if
+    /// the game is over.
+    #[wasm_bindgen(js_name = bestMove)]
+    pub fn best_move(&self, level: u32) -> Option<String> {
+        let (depth, nodes) = limits(level);
+        let result = self.board.search_nodes(depth, nodes);
+        result.best_move.map(|mv| self.board.san(mv))
+    }
+}
+
+impl Default for Board {
+    fn default() -> Board {
+        Board::new()
+    }
+}
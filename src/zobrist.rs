@@ -0,0 +1,185 @@
+use crate::movegen::NO_SQUARE;
+use crate::{ChessBoard, BLACK, EMPTY, PAWN, WHITE};
+
+/// A fixed stream of pseudo-random numbers (splitmix64), so hashes are the same in every run and
+/// can be stored.
+const fn splitmix(state: u64) -> u64 {
+    let mut z = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+const fn key(index: usize) -> u64 {
+    splitmix((index as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+/// One key per piece (indexed like the board's nibbles) per square.
+const PIECE_KEYS: [[u64; 64]; 16] = {
+    let mut keys = [[0; 64]; 16];
+    let mut piece = 0;
+    while piece < 16 {
+        let mut square = 0;
+        while square < 64 {
+            keys[piece][square] = key(piece * 64 + square);
+            square += 1;
+        }
+        piece += 1;
+    }
+    keys
+};
+
+/// Keys for each combination of castling rights.
+const CASTLING_KEYS: [u64; 16] = {
+    let mut keys = [0; 16];
+    let mut rights = 0;
+    while rights < 16 {
+        keys[rights] = key(1024 + rights);
+        rights += 1;
+    }
+    keys
+};
+
+/// Keys for the file of a pawn that can be captured en passant.
+const EN_PASSANT_KEYS: [u64; 8] = {
+    let mut keys = [0; 8];
+    let mut file = 0;
+    while file < 8 {
+        keys[file] = key(1040 + file);
+        file += 1;
+    }
+    keys
+};
+
+const BLACK_TO_MOVE_KEY: u64 = key(1048);
+
+/// Keys for each number of checks given (up to 3), by color.
+const CHECK_KEYS: [[u64; 4]; 2] = {
+    let mut keys = [[0; 4]; 2];
+    let mut color = 0;
+    while color < 2 {
+        let mut count = 0;
+        while count < 4 {
+            keys[color][count] = key(1241 + color * 4 + count);
+            count += 1;
+        }
+        color += 1;
+    }
+    keys
+};
+
+/// Keys for holding each number of each piece in hand (up to 15), by color and piece number.
+const POCKET_KEYS: [[[u64; 16]; 6]; 2] = {
+    let mut keys = [[[0; 16]; 6]; 2];
+    let mut color = 0;
+    while color < 2 {
+        let mut piece = 0;
+        while piece < 6 {
+            let mut count = 0;
+            while count < 16 {
+                keys[color][piece][count] = key(1049 + (color * 6 + piece) * 16 + count);
+                count += 1;
+            }
+            piece += 1;
+        }
+        color += 1;
+    }
+    keys
+};
+
+impl ChessBoard {
+    /// A 64-bit fingerprint of the position: the pieces (including those in hand), the checks
+    /// given in Three-check, the side to move, castling rights and en passant (only when a pawn
+    /// could actually capture, as in Polyglot). Move counters are left out, so transpositions hash
+    /// the same.
+    pub fn zobrist(&self) -> u64 {
+        let mut hash = 0;
+        for square in 0..64 {
+            let piece = self.get_piece(square);
+            if piece != EMPTY {
+                hash ^= PIECE_KEYS[piece as usize][square as usize];
+            }
+        }
+        hash ^= CASTLING_KEYS[self.castling as usize & 15];
+        for (color, &checks) in self.checks.iter().enumerate() {
+            if checks > 0 {
+                hash ^= CHECK_KEYS[color][checks.min(3) as usize];
+            }
+        }
+        for (color, pocket) in self.pockets.iter().enumerate() {
+            for (piece, &count) in pocket.iter().enumerate() {
+                if count > 0 {
+                    hash ^= POCKET_KEYS[color][piece][count.min(15) as usize];
+                }
+            }
+        }
+        let side = self.side_to_move();
+        if side == BLACK {
+            hash ^= BLACK_TO_MOVE_KEY;
+        }
+        if self.en_passant != NO_SQUARE {
+            // The capturing pawns stand beside the target square's pawn, one rank behind it.
+            let file = self.en_passant & 7;
+            let rank = if side == WHITE {
+                self.en_passant - 8
+            } else {
+                self.en_passant + 8
+            };
+            let capturer = PAWN | side;
+            let can_capture = (file > 0 && self.get_piece(rank - 1) == capturer)
+                || (file < 7 && self.get_piece(rank + 1) == capturer);
+            if can_capture {
+                hash ^= EN_PASSANT_KEYS[file as usize];
+            }
+        }
+        hash
+    }
+}
+
+#[test]
+fn test_zobrist() {
+    let start = ChessBoard::new();
+    assert_eq!(start.zobrist(), ChessBoard::new().zobrist());
+
+    // The same position reached by different move orders.
+    let play = |moves: &[&str]| {
+        let mut board = ChessBoard::new();
+        for san in moves {
+            let mv = board.parse_move(san).unwrap();
+            board.apply_move(mv);
+        }
+        board
+    };
+    let italian = play(&["e4", "e5", "Nf3", "Nc6", "Bc4"]);
+    let transposed = play(&["Nf3", "Nc6", "e4", "e5", "Bc4"]);
+    assert_eq!(italian.zobrist(), transposed.zobrist());
+    assert_ne!(italian.zobrist(), start.zobrist());
+
+    // A double pawn push only matters when it can be captured en passant.
+    assert_eq!(
+        play(&["e4"]).zobrist(),
+        ChessBoard::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+            .unwrap()
+            .zobrist()
+    );
+    let en_passant = play(&["e4", "a6", "e5", "d5"]);
+    let mut no_en_passant = en_passant;
+    no_en_passant.en_passant = NO_SQUARE;
+    assert_ne!(en_passant.zobrist(), no_en_passant.zobrist());
+
+    // Castling rights and the side to move count.
+    let mut no_castling = start;
+    no_castling.castling = 0;
+    assert_ne!(no_castling.zobrist(), start.zobrist());
+    let mut black = start;
+    black.moves += 1;
+    assert_ne!(black.zobrist(), start.zobrist());
+
+    // So do the pieces in hand and the checks given.
+    let mut checked = start;
+    checked.checks[1] = 2;
+    assert_ne!(checked.zobrist(), start.zobrist());
+    let mut pocket = start;
+    pocket.pockets[0][PAWN as usize] = 1;
+    assert_ne!(pocket.zobrist(), start.zobrist());
+}
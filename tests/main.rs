@@ -1,4 +1,8 @@
-use std::process::Command;
+// These tests drive the freight_chess binary, which is only built with the command line.
+#![cfg(feature = "cli")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
 use std::str;
 
 #[test]
@@ -60,3 +64,201 @@ fn test_version() {
     println!("{}", output);
     assert_eq!(output, "Chess Engine 0.1.0\n");
 }
+
+fn run_script(script: &str) -> (Option<i32>, String) {
+    run_script_with(script, &[])
+}
+
+fn run_script_with(script: &str, args: &[&str]) -> (Option<i32>, String) {
+    let binary = if cfg!(target_os = "windows") {
+        "target\\debug\\freight_chess.exe"
+    } else {
+        "./target/debug/freight_chess"
+    };
+    let mut child = Command::new(binary)
+        .args(["--script", "-"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute process");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .expect("Failed to write script");
+    let output = child.wait_with_output().expect("Failed to wait on process");
+
+    let stdout = match str::from_utf8(output.stdout.as_slice()) {
+        Ok(t) => t.to_string(),
+        Err(e) => panic!("Invalid utf-8 sequence: {}", e),
+    };
+    (output.status.code(), stdout)
+}
+
+#[test]
+fn test_script() {
+    let (code, output) = run_script("# opening\nmove e2->e4\n\nmove e7->e5\nquit\nmove d2->d4\n");
+    assert_eq!(code, Some(0));
+    assert_eq!(output, "ok move e2->e4\nok move e7->e5\n");
+
+    let (code, output) = run_script("move e2->e4\nmove e4->e5\n");
+    assert_eq!(code, Some(4));
+    assert_eq!(output, "ok move e2->e4\nerror 2: It's black's turn.\n");
+
+    let (code, output) = run_script("castle\n");
+    assert_eq!(code, Some(2));
+    assert!(output.starts_with("error 1: "));
+
+    // Several moves on a line, and several commands, stopping at the first that fails.
+    let (code, output) = run_script("e4 e5 Nf3; move Nc6 Bb5\nboard; a6 Ba4 Qh5 Nf6\n");
+    assert_eq!(code, Some(4));
+    assert!(output.starts_with("ok e4\nok e5\nok Nf3\nok move Nc6\nok move Bb5\n"));
+    assert!(output.contains("ok a6\nok Ba4\nerror 2: "));
+    assert!(!output.contains("Nf6"));
+
+    // A pasted PGN or FEN sets up its position, and play goes on from there.
+    let (code, output) =
+        run_script("1. e4 e5 2. Nf3 Nc6 3. Bb5\na6\n8/8/8/4k3/8/8/4P3/4K3 w - - 0 1\ne4\n");
+    assert_eq!(code, Some(0));
+    assert!(output.starts_with("ok 1. e4 e5 2. Nf3 Nc6 3. Bb5\nok a6\nok 8/8/8/4k3"));
+    assert!(output.ends_with("ok e4\n"));
+
+    // Going back and playing on from an earlier move.
+    let (code, output) =
+        run_script("e4 e5 Nf3 Nc6\ngoto 2; Bc4 Nf6; goto 2...\nboard\ngoto 3...\n");
+    assert_eq!(code, Some(2));
+    assert!(output.contains("ok goto 2\nok Bc4\nok Nf6\nok goto 2...\n"));
+    assert!(output.ends_with("error 4: The game never reached that move.\n"));
+    let (_, reached) = run_script("e4 e5 Bc4\nboard\n");
+    let board = |output: &str| {
+        let lines = output.lines().filter(|line| line.starts_with('|'));
+        lines.collect::<Vec<&str>>().join("\n")
+    };
+    assert_eq!(board(&output), board(&reached));
+}
+
+#[test]
+fn test_script_json() {
+    let (code, output) = run_script_with("move e2->e4\nmove e2->e3\n", &["--json"]);
+    assert_eq!(code, Some(4));
+
+    let lines = output.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("{\"command\":\"move e2->e4\",\"line\":1,\"ok\":true,"));
+    assert!(lines[0].contains("\"placement\":\"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR\""));
+    assert!(lines[0].contains("\"side_to_move\":\"black\""));
+    assert!(lines[0]
+        .contains("\"fen\":\"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1\""));
+    assert!(lines[0].contains("\"legal_moves\":[\"a6\",\"a5\","));
+    assert!(lines[0].contains("\"status\":\"ongoing\""));
+    assert!(lines[1].contains("\"kind\":\"bad_move\""));
+    assert!(lines[1].contains("\"reason\":\"no_piece\""));
+    assert!(lines[1].contains("\"ok\":false"));
+}
+
+#[test]
+fn test_script_start() {
+    // Scripts start from --fen, and play by the rules of --variant.
+    let fen = "8/8/8/4k3/8/8/4P3/4K3 w - - 0 1";
+    let (code, output) = run_script_with("e4\n", &["--json", "--fen", fen]);
+    assert_eq!(code, Some(0));
+    assert!(output.contains("\"placement\":\"8/8/8/4k3/4P3/8/8/4K3\""));
+    let drop = "e4 d5 exd5 Qxd5 P@e4\n";
+    assert_eq!(
+        run_script_with(drop, &["--variant", "crazyhouse"]).0,
+        Some(0)
+    );
+    assert_eq!(run_script(drop).0, Some(4));
+    assert_eq!(run_script_with("board\n", &["--fen", "x"]).0, Some(5));
+}
+
+#[test]
+fn test_perft_json() {
+    let binary = if cfg!(target_os = "windows") {
+        "target\\debug\\freight_chess.exe"
+    } else {
+        "./target/debug/freight_chess"
+    };
+    let output = Command::new(binary)
+        .args(["--json", "perft", "2", "--divide"])
+        .output()
+        .expect("Failed to execute process");
+    let output = str::from_utf8(output.stdout.as_slice()).unwrap();
+    assert_eq!(output.lines().count(), 1);
+    assert!(output.contains("\"nodes\":400"));
+    assert!(output.contains("\"moves\":{\"a2a3\":20,\"a2a4\":20,"));
+}
+
+#[test]
+fn test_ws_server() {
+    let binary = if cfg!(target_os = "windows") {
+        "target\\debug\\freight_chess.exe"
+    } else {
+        "./target/debug/freight_chess"
+    };
+    let mut server = Command::new(binary)
+        .args(["serve", "--ws", "--port", "48598"])
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to execute process");
+
+    let join = || {
+        for _ in 0..50 {
+            if let Ok((socket, _)) = tungstenite::connect("ws://127.0.0.1:48598") {
+                return socket;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        panic!("Could not connect to the server");
+    };
+    let mut white = join();
+    let mut black = join();
+
+    white.send(r#"{"type":"join"}"#.into()).unwrap();
+    assert!(white
+        .read()
+        .unwrap()
+        .to_text()
+        .unwrap()
+        .contains(r#""color":"white""#));
+    black.send(r#"{"type":"join"}"#.into()).unwrap();
+    assert!(black
+        .read()
+        .unwrap()
+        .to_text()
+        .unwrap()
+        .contains(r#""color":"black""#));
+    assert!(black
+        .read()
+        .unwrap()
+        .to_text()
+        .unwrap()
+        .contains(r#""type":"state""#));
+
+    white.send(r#"{"type":"move","move":"e4"}"#.into()).unwrap();
+    let state = black.read().unwrap();
+    assert!(state
+        .to_text()
+        .unwrap()
+        .contains(r#""fen":"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1""#));
+
+    // The engine answers a player who chose to play it.
+    let mut solo = join();
+    solo.send(
+        r#"{"type":"join","room":"solo","color":"black","opponent":"engine","nodes":1000}"#.into(),
+    )
+    .unwrap();
+    let answered = (0..3).any(|_| {
+        solo.read()
+            .unwrap()
+            .to_text()
+            .unwrap()
+            .contains(r#""side_to_move":"black""#)
+    });
+    assert!(answered);
+
+    server.kill().expect("Failed to stop the server");
+    server.wait().expect("Failed to wait on the server");
+}